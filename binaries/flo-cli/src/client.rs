@@ -1,6 +1,7 @@
 use structopt::StructOpt;
 
 use crate::env::ENV;
+use crate::grpc::get_grpc_client;
 use crate::Result;
 use std::time::Duration;
 
@@ -15,14 +16,30 @@ pub enum Command {
     port: u16,
   },
   StartTestGame,
+  /// Joins a game as a headless player: answers the start checks, connects to the node
+  /// and answers every game tick with a keep alive until the game ends, without actions
+  Play {
+    game_id: i32,
+    /// Lobby token of the player, generated with the local secret if not set
+    #[structopt(long)]
+    token: Option<String>,
+    #[structopt(long)]
+    password: Option<String>,
+    /// Reported in the version check, has to match the other players
+    #[structopt(long, default_value = flo_testlab::WAR3_VERSION)]
+    war3_version: String,
+    /// Leaves after this many ticks instead of waiting for the game to end
+    #[structopt(long)]
+    ticks: Option<usize>,
+  },
 }
 
 impl Command {
   #[tracing::instrument(skip(self))]
   pub async fn run(&self, player_id: i32) -> Result<()> {
-    let token = flo_controller::player::token::create_player_token(player_id)?;
+    let create_token = || flo_controller::player::token::create_player_token(player_id);
     match *self {
-      Command::Token => println!("{}", token),
+      Command::Token => println!("{}", create_token()?),
       Command::Connect { ws } => {
         let token = flo_controller::player::token::create_player_token(player_id)?;
         tracing::debug!("token generated: {}", token);
@@ -54,19 +71,155 @@ impl Command {
         };
       }
       Command::WsReconnect { port } => {
-        server_ws(format!("ws://127.0.0.1:{}", port), token).await?;
+        server_ws(format!("ws://127.0.0.1:{}", port), create_token()?).await?;
       }
       Command::StartTestGame => {
         let client = flo_client::start(Default::default()).await.unwrap();
         client.start_test_game().await.unwrap();
         client.serve().await;
       }
+      Command::Play {
+        game_id,
+        token: ref lobby_token,
+        ref password,
+        ref war3_version,
+        ticks,
+      } => {
+        let token = match lobby_token.clone() {
+          Some(token) => token,
+          None => create_token()?,
+        };
+        play(
+          player_id,
+          token,
+          game_id,
+          password.clone(),
+          war3_version,
+          ticks,
+        )
+        .await?;
+      }
     }
 
     Ok(())
   }
 }
 
+async fn play(
+  player_id: i32,
+  token: String,
+  game_id: i32,
+  password: Option<String>,
+  war3_version: &str,
+  ticks: Option<usize>,
+) -> Result<()> {
+  use flo_grpc::controller::JoinGameRequest;
+  use flo_net::packet::FloPacket;
+  use flo_net::proto::flo_connect::{
+    PacketGamePlayerToken, PacketGameStartPlayerClientInfoRequest, PacketGameStartReject,
+    PacketGameStarting, PacketReadyCheck, PacketReadyCheckResponse,
+  };
+  use flo_testlab::error::Error as LabError;
+  use flo_testlab::lobby::LobbyClient;
+  use flo_testlab::node::NodeClient;
+  use flo_types::node::NodeGameStatus;
+  use flo_w3gs::protocol::constants::LeaveReason;
+
+  let mut lobby = LobbyClient::connect_to(
+    &format!(
+      "{}:{}",
+      ENV.controller_host,
+      flo_constants::CONTROLLER_SOCKET_PORT
+    ),
+    player_id,
+    token,
+  )
+  .await?;
+  tracing::info!("lobby connected");
+
+  let mut grpc = get_grpc_client().await;
+  let mut req = tonic::Request::new(JoinGameRequest {
+    game_id,
+    player_id,
+    ..Default::default()
+  });
+  if let Some(password) = password {
+    req
+      .metadata_mut()
+      .insert("x-flo-game-password", password.parse()?);
+  }
+  let game = grpc.join_game(req).await?.into_inner().game;
+  let map_sha1 = game
+    .and_then(|game| game.map)
+    .map(|map| map.sha1)
+    .unwrap_or_default();
+  tracing::info!(game_id, "joined, waiting for the game to start");
+
+  // the host starts the game, the lobby times out while waiting
+  let player_token = loop {
+    let frame = match lobby.recv_frame().await {
+      Ok(frame) => frame,
+      Err(LabError::Timeout(_)) => continue,
+      Err(err) => return Err(err.into()),
+    };
+    match frame.type_id {
+      PacketReadyCheck::TYPE_ID => {
+        let check: PacketReadyCheck = frame.decode()?;
+        lobby
+          .send(PacketReadyCheckResponse {
+            game_id: check.game_id,
+            ready: true,
+          })
+          .await?;
+      }
+      PacketGameStarting::TYPE_ID => {
+        let starting: PacketGameStarting = frame.decode()?;
+        lobby
+          .send(PacketGameStartPlayerClientInfoRequest {
+            game_id: starting.game_id,
+            war3_version: war3_version.to_string(),
+            map_sha1: map_sha1.clone(),
+          })
+          .await?;
+      }
+      PacketGameStartReject::TYPE_ID => {
+        let reject: PacketGameStartReject = frame.decode()?;
+        anyhow::bail!("game start rejected: {}", reject.message);
+      }
+      PacketGamePlayerToken::TYPE_ID => {
+        break frame.decode::<PacketGamePlayerToken>()?;
+      }
+      other => tracing::debug!("skip: {:?}", other),
+    }
+  };
+
+  let nodes = grpc.list_nodes(()).await?.into_inner().nodes;
+  let node = nodes
+    .into_iter()
+    .find(|node| node.id == player_token.node_id)
+    .ok_or_else(|| anyhow::format_err!("node not found: {}", player_token.node_id))?;
+  let mut client =
+    NodeClient::connect_to(&node.ip_addr, player_id, player_token.player_token).await?;
+  tracing::info!(node_id = node.id, "node connected");
+
+  client.join_and_load().await?;
+  loop {
+    if matches!(ticks, Some(ticks) if client.ticks() >= ticks) {
+      client.leave(LeaveReason::LeaveLost).await?;
+      break;
+    }
+    if let Err(err) = client.tick(None).await {
+      // the node closes the connection once the game ends
+      if client.game_status() == NodeGameStatus::Ended {
+        break;
+      }
+      return Err(err.into());
+    }
+  }
+  tracing::info!(ticks = client.ticks(), "game finished");
+  Ok(())
+}
+
 async fn server_ws(url: String, token: String) -> Result<()> {
   use async_tungstenite::tokio::connect_async;
   use async_tungstenite::tungstenite::protocol::Message;
@@ -1,8 +1,9 @@
 use crate::Result;
 use flo_debug::player_emulator::PlayerEmulator;
-use flo_lan::search_lan_games;
+use flo_lan::{search_lan_games, GameData, GameInfo};
 use flo_w3storage::W3Storage;
 use futures::StreamExt;
+use std::path::PathBuf;
 use std::time::Duration;
 use structopt::StructOpt;
 
@@ -11,6 +12,10 @@ pub enum Command {
   List,
   Join { name: String },
   JoinMulti { name: String, player_ids: Vec<i32> },
+  /// Prints the entries and settings of a captured GameInfo protobuf
+  InspectGameInfo { path: PathBuf },
+  /// Prints the settings of a base64 `game_data` entry
+  InspectGameData { data: String },
 }
 
 impl Command {
@@ -89,6 +94,25 @@ impl Command {
           res.unwrap()
         }
       }
+      Command::InspectGameInfo { ref path } => {
+        let bytes = std::fs::read(path)?;
+        for (key, value) in GameInfo::decode_entries(&bytes)? {
+          println!("{} = {}", key, value);
+          if key == "game_data" {
+            match GameData::decode_base64(&value) {
+              Ok(data) => println!("{:#?}", data),
+              Err(err) => println!("invalid game_data: {}", err),
+            }
+          }
+        }
+        match GameInfo::decode_bytes(&bytes) {
+          Ok(info) => println!("{:#?}", info),
+          Err(err) => println!("invalid game info: {}", err),
+        }
+      }
+      Command::InspectGameData { ref data } => {
+        println!("{:#?}", GameData::decode_base64(data)?);
+      }
     }
 
     Ok(())
@@ -5,9 +5,11 @@ mod env;
 mod game;
 mod grpc;
 mod lan;
+mod map;
 mod server;
 mod observer;
 mod kinesis;
+mod trace;
 
 pub use anyhow::Result;
 
@@ -26,6 +28,10 @@ enum Opt {
     #[structopt(subcommand)]
     cmd: lan::Command,
   },
+  Map {
+    #[structopt(subcommand)]
+    cmd: map::Command,
+  },
   Observer {
     #[structopt(subcommand)]
     cmd: observer::Command,
@@ -33,7 +39,11 @@ enum Opt {
   Kinesis {
     #[structopt(subcommand)]
     cmd: kinesis::Command,
-  }
+  },
+  Trace {
+    #[structopt(subcommand)]
+    cmd: trace::Command,
+  },
 }
 
 #[tokio::main]
@@ -54,12 +64,18 @@ async fn main() -> Result<()> {
     Opt::Lan { cmd } => {
       cmd.run().await?;
     }
+    Opt::Map { cmd } => {
+      cmd.run().await?;
+    }
     Opt::Observer { cmd } => {
       cmd.run().await?;
     }
     Opt::Kinesis { cmd } => {
       cmd.run().await?;
     }
+    Opt::Trace { cmd } => {
+      cmd.run().await?;
+    }
   }
 
   Ok(())
@@ -0,0 +1,97 @@
+use flo_w3map::W3Map;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use crate::Result;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+  /// Prints the info and checksums of a map file
+  Inspect {
+    path: PathBuf,
+    /// Writes the preview image with the minimap icons as PNG
+    #[structopt(long)]
+    preview: Option<PathBuf>,
+    /// Writes the map script
+    #[structopt(long)]
+    script: Option<PathBuf>,
+  },
+  /// Checks the map script for cheat packs and banned natives
+  CheckScript { path: PathBuf },
+}
+
+impl Command {
+  pub async fn run(&self) -> Result<()> {
+    match *self {
+      Command::Inspect {
+        ref path,
+        ref preview,
+        ref script,
+      } => {
+        let (map, checksum) = W3Map::open_with_checksum(path)?;
+        let (width, height) = map.dimension();
+        println!("name: {}", map.name());
+        println!("author: {}", map.author());
+        println!("description: {}", map.description());
+        println!("suggested players: {}", map.suggested_players());
+        println!("dimension: {}x{}", width, height);
+        println!("flags: {:?}", map.flags());
+        println!("format version: {:?}", map.format_version());
+        println!("game data set: {}", map.game_data_set());
+        if let Some(version) = map.game_data_version() {
+          println!("game data version: {}", version);
+        }
+        println!("fixed teams: {}", map.has_fixed_teams());
+        println!("file size: {}", checksum.file_size);
+        println!("sha1: {}", hex::encode(checksum.sha1));
+        println!("crc32: {:08x}", checksum.crc32);
+        println!("xoro: {:08x}", checksum.xoro);
+
+        println!("players: {}", map.num_players());
+        for (idx, player) in map.get_players().iter().enumerate() {
+          println!(
+            "  #{} {}: id = {}, type = {}, race = {}, flags = {:#x}",
+            idx, player.name, player.id, player.r#type, player.race, player.flags
+          );
+        }
+        println!("forces: {}", map.num_forces());
+        for (idx, force) in map.get_forces().iter().enumerate() {
+          println!(
+            "  #{} {}: flags = {:?}, player set = {:#b}",
+            idx,
+            force.name,
+            force.force_flags(),
+            force.player_set
+          );
+        }
+
+        if let Some(ref preview) = preview {
+          let png = map.render_preview_png();
+          if png.is_empty() {
+            println!("no preview image");
+          } else {
+            std::fs::write(preview, png)?;
+            println!("preview written to {}", preview.display());
+          }
+        }
+
+        if let Some(ref script) = script {
+          let (script_path, bytes) = W3Map::read_script(path)?;
+          std::fs::write(script, bytes)?;
+          println!("{} written to {}", script_path, script.display());
+        }
+      }
+      Command::CheckScript { ref path } => {
+        let report = W3Map::analyze_script(path)?;
+        if report.is_clean() {
+          println!("clean");
+        }
+        for finding in &report.findings {
+          println!("line {}: {:?}", finding.line, finding.kind);
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+use flo_w3gs::trace::{decode_payload, Direction, TraceReader};
+
+use crate::Result;
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+  /// Decodes the packets of a W3GS trace recorded by the node
+  Print {
+    path: PathBuf,
+    #[structopt(long)]
+    player_id: Option<i32>,
+  },
+}
+
+impl Command {
+  pub async fn run(&self) -> Result<()> {
+    match *self {
+      Command::Print {
+        ref path,
+        player_id,
+      } => {
+        let reader = TraceReader::new(BufReader::new(File::open(path)?))?;
+        let mut packets = 0;
+        let mut errors = 0;
+        for record in reader {
+          let record = record?;
+          if matches!(player_id, Some(id) if id != record.player_id) {
+            continue;
+          }
+          packets += 1;
+          let direction = match record.direction {
+            Direction::Incoming => "<-",
+            Direction::Outgoing => "->",
+          };
+          let decoded = match decode_payload(&record.packet) {
+            Ok(Some(payload)) => format!("{:?}", payload),
+            Ok(None) => format!("{} bytes", record.packet.payload_len()),
+            Err(err) => {
+              errors += 1;
+              format!("decode error: {}", err)
+            }
+          };
+          println!(
+            "{:>10} {} {:>6} {:?}: {}",
+            record.elapsed_ms,
+            direction,
+            record.player_id,
+            record.packet.type_id(),
+            decoded
+          );
+        }
+        println!("{} packets, {} decode errors", packets, errors);
+      }
+    }
+
+    Ok(())
+  }
+}
@@ -32,6 +32,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
   }
 
+  #[cfg(feature = "http")]
+  tokio::spawn({
+    let state = state.clone();
+    async move {
+      if let Err(err) = flo_controller::serve_http(state).await {
+        tracing::error!("http server: {}", err);
+      }
+    }
+  });
+
   tokio::try_join!(serve_grpc(state.clone()), serve_socket(state.clone()))?;
 
   Ok(())
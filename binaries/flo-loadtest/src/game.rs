@@ -0,0 +1,168 @@
+use crate::grpc;
+use crate::stats::Stats;
+use crate::Opts;
+use anyhow::{bail, Result};
+use flo_grpc::controller::{CreateGameAsBotRequest, StartGameAsBotRequest};
+use flo_grpc::game::{CreateGameSlot, SlotSettings};
+use flo_testlab::lobby::LobbyClient;
+use flo_testlab::node::NodeClient;
+use flo_testlab::{test_map, TEST_MAP_SHA1, WAR3_VERSION};
+use flo_types::node::SlotClientStatus;
+use flo_w3gs::protocol::constants::LeaveReason;
+use s2_grpc_utils::S2ProtoPack;
+use std::time::{Duration, Instant};
+
+const ACTION: &[u8] = &[0x16, 0x01, 0x01, 0x00];
+
+/// Plays one game with `players`, the errors are counted in the stats
+pub async fn run(opts: Opts, stats: Stats, players: Vec<(i32, String)>) {
+  if let Err(err) = run_game(&opts, &stats, players).await {
+    tracing::error!("game: {}", err);
+    stats.error("game");
+  }
+}
+
+async fn run_game(opts: &Opts, stats: &Stats, players: Vec<(i32, String)>) -> Result<()> {
+  let mut lobby_clients = Vec::with_capacity(players.len());
+  for (player_id, token) in players {
+    let t = Instant::now();
+    lobby_clients.push(LobbyClient::connect_to(&crate::lobby_addr(opts), player_id, token).await?);
+    stats.record_since("lobby connect", t);
+  }
+
+  let mut client = grpc::connect(&opts.controller_host, &opts.controller_secret).await?;
+  let nodes = client.list_nodes(()).await?.into_inner().nodes;
+  let node = match opts.node_id {
+    Some(id) => nodes.into_iter().find(|node| node.id == id),
+    None => nodes.into_iter().next(),
+  };
+  let node = if let Some(node) = node {
+    node
+  } else {
+    bail!("node not found")
+  };
+
+  let t = Instant::now();
+  let game = client
+    .create_game_as_bot(CreateGameAsBotRequest {
+      name: format!("loadtest-{:x}", rand::random::<u32>()),
+      map: Some(test_map(lobby_clients.len()).pack()?),
+      node_id: node.id,
+      slots: lobby_clients
+        .iter()
+        .enumerate()
+        .map(|(idx, client)| CreateGameSlot {
+          player_id: Some(client.player_id()),
+          settings: Some(SlotSettings {
+            team: 0,
+            color: idx as i32,
+            handicap: 100,
+            status: 2,
+            race: 4,
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .collect(),
+      ..Default::default()
+    })
+    .await?
+    .into_inner()
+    .game;
+  stats.record_since("game create", t);
+  let game_id = if let Some(game) = game {
+    game.id
+  } else {
+    bail!("game not created")
+  };
+
+  // the start request returns once every player answered the version check
+  let t = Instant::now();
+  let acks: Vec<_> = lobby_clients
+    .into_iter()
+    .map(|mut client| {
+      tokio::spawn(async move {
+        client.ack_game_start(WAR3_VERSION, &TEST_MAP_SHA1).await?;
+        let token = client.recv_player_token().await?;
+        Ok::<_, flo_testlab::error::Error>((client, token))
+      })
+    })
+    .collect();
+  let res = client
+    .start_game_as_bot(StartGameAsBotRequest { game_id })
+    .await?
+    .into_inner();
+  if !res.succeed {
+    bail!("game start rejected: {}", res.error_message);
+  }
+  let mut tokens = Vec::with_capacity(acks.len());
+  for ack in acks {
+    tokens.push(ack.await??);
+  }
+  stats.record_since("game start", t);
+
+  let mut node_clients = Vec::with_capacity(tokens.len());
+  for (client, token) in tokens {
+    let t = Instant::now();
+    node_clients
+      .push(NodeClient::connect_to(&node.ip_addr, client.player_id(), token.player_token).await?);
+    stats.record_since("node connect", t);
+  }
+
+  // the game loads once everyone has joined
+  for client in node_clients.iter_mut() {
+    client.update_status(SlotClientStatus::Joined).await?;
+  }
+  for client in node_clients.iter_mut() {
+    client.update_status(SlotClientStatus::Loading).await?;
+    client.update_status(SlotClientStatus::Loaded).await?;
+  }
+
+  let handles: Vec<_> = node_clients
+    .into_iter()
+    .enumerate()
+    .map(|(idx, client)| {
+      let reason = if idx == 0 {
+        LeaveReason::LeaveLost
+      } else {
+        LeaveReason::LeaveWon
+      };
+      tokio::spawn(play(opts.clone(), stats.clone(), client, reason))
+    })
+    .collect();
+  for handle in handles {
+    handle.await??;
+  }
+
+  tracing::info!(game_id, "game finished");
+  Ok(())
+}
+
+/// Answers the game ticks, sending an action every `60 / apm` seconds
+async fn play(opts: Opts, stats: Stats, mut client: NodeClient, reason: LeaveReason) -> Result<()> {
+  let action_interval = if opts.apm > 0 {
+    Some(Duration::from_secs(60) / opts.apm)
+  } else {
+    None
+  };
+  let mut next_action_at = Instant::now();
+  let mut last_tick = None;
+  for _ in 0..opts.ticks {
+    let action = match action_interval {
+      Some(interval) if Instant::now() >= next_action_at => {
+        next_action_at += interval;
+        Some(ACTION)
+      }
+      _ => None,
+    };
+    client.tick(action).await?;
+    let now = Instant::now();
+    if let Some(last_tick) = last_tick.replace(now) {
+      stats.record("tick interval", now - last_tick);
+    }
+  }
+  let t = Instant::now();
+  client.leave(reason).await?;
+  stats.record_since("leave", t);
+  Ok(())
+}
@@ -0,0 +1,31 @@
+use anyhow::Result;
+pub use flo_grpc::controller::flo_controller_client::FloControllerClient;
+use flo_grpc::Channel;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+
+pub type Client = FloControllerClient<InterceptedService<Channel, WithSecret>>;
+
+pub async fn connect(host: &str, secret: &str) -> Result<Client> {
+  let channel = Channel::from_shared(format!(
+    "tcp://{}:{}",
+    host,
+    flo_constants::CONTROLLER_GRPC_PORT
+  ))?
+  .connect()
+  .await?;
+  Ok(FloControllerClient::with_interceptor(
+    channel,
+    WithSecret(secret.parse()?),
+  ))
+}
+
+#[derive(Clone)]
+pub struct WithSecret(AsciiMetadataValue);
+
+impl Interceptor for WithSecret {
+  fn call(&mut self, mut req: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+    req.metadata_mut().insert("x-flo-secret", self.0.clone());
+    Ok(req)
+  }
+}
@@ -0,0 +1,153 @@
+//! Drives a deployment with synthetic clients and reports the latencies they observed.
+//!
+//! Lobby clients connect to the lobby socket and stay connected for `--duration`.
+//! Game clients are grouped into games created through the controller API, then start,
+//! connect to the node and play `--ticks` ticks, sending actions at `--apm`.
+
+mod game;
+mod grpc;
+mod stats;
+
+use anyhow::Result;
+use clap::Parser;
+use flo_controller::player::PlayerSource;
+use flo_grpc::controller::UpdateAndGetPlayerRequest;
+use flo_testlab::lobby::LobbyClient;
+use stats::Stats;
+use std::time::{Duration, Instant};
+
+#[derive(Parser, Debug, Clone)]
+#[clap(version = "1.0")]
+pub struct Opts {
+  #[clap(long, default_value = "127.0.0.1")]
+  controller_host: String,
+  #[clap(long, default_value = "TEST")]
+  controller_secret: String,
+  /// Number of idle lobby clients
+  #[clap(short, long, default_value = "0")]
+  lobby_clients: usize,
+  /// Number of clients playing games, split into games of `--players-per-game`
+  #[clap(short, long, default_value = "2")]
+  game_clients: usize,
+  #[clap(long, default_value = "2")]
+  players_per_game: usize,
+  /// Actions per minute sent by each game client
+  #[clap(long, default_value = "120")]
+  apm: u32,
+  /// Game ticks played by each game client
+  #[clap(long, default_value = "600")]
+  ticks: usize,
+  /// Hosts the games on this node instead of the first one listed
+  #[clap(long)]
+  node_id: Option<i32>,
+  /// Seconds the lobby clients stay connected
+  #[clap(long, default_value = "60")]
+  duration: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+  flo_log_subscriber::init_env_override("flo_loadtest=info");
+
+  let opts: Opts = Opts::parse();
+  if opts.players_per_game == 0 || opts.game_clients % opts.players_per_game != 0 {
+    anyhow::bail!("--game-clients must be a multiple of --players-per-game");
+  }
+
+  let stats = Stats::default();
+  let run_id = format!("{:x}", rand::random::<u32>());
+  tracing::info!(
+    run_id = run_id.as_str(),
+    "{} lobby clients, {} game clients",
+    opts.lobby_clients,
+    opts.game_clients
+  );
+
+  let lobby_players = upsert_players(&opts, &run_id, "lobby", opts.lobby_clients).await?;
+  let game_players = upsert_players(&opts, &run_id, "game", opts.game_clients).await?;
+
+  let started_at = Instant::now();
+  let mut tasks = vec![];
+  for (player_id, token) in lobby_players {
+    tasks.push(tokio::spawn(run_lobby_client(
+      opts.clone(),
+      stats.clone(),
+      player_id,
+      token,
+    )));
+  }
+  for players in game_players.chunks(opts.players_per_game) {
+    tasks.push(tokio::spawn(game::run(
+      opts.clone(),
+      stats.clone(),
+      players.to_vec(),
+    )));
+  }
+  for task in tasks {
+    task.await?;
+  }
+
+  println!("finished in {:?}", started_at.elapsed());
+  println!("{}", stats.report());
+  Ok(())
+}
+
+/// Returns the player ids with their lobby tokens
+async fn upsert_players(
+  opts: &Opts,
+  run_id: &str,
+  kind: &str,
+  n: usize,
+) -> Result<Vec<(i32, String)>> {
+  let mut client = grpc::connect(&opts.controller_host, &opts.controller_secret).await?;
+  let mut players = Vec::with_capacity(n);
+  for idx in 0..n {
+    let name = format!("loadtest-{}-{}-{}", run_id, kind, idx + 1);
+    let res = client
+      .update_and_get_player(UpdateAndGetPlayerRequest {
+        source: PlayerSource::Api as i32,
+        name: name.clone(),
+        source_id: name,
+        ..Default::default()
+      })
+      .await?
+      .into_inner();
+    if let Some(player) = res.player {
+      players.push((player.id, res.token));
+    }
+  }
+  Ok(players)
+}
+
+fn lobby_addr(opts: &Opts) -> String {
+  format!(
+    "{}:{}",
+    opts.controller_host,
+    flo_constants::CONTROLLER_SOCKET_PORT
+  )
+}
+
+async fn run_lobby_client(opts: Opts, stats: Stats, player_id: i32, token: String) {
+  let t = Instant::now();
+  let mut client = match LobbyClient::connect_to(&lobby_addr(&opts), player_id, token).await {
+    Ok(client) => client,
+    Err(err) => {
+      tracing::error!(player_id, "lobby connect: {}", err);
+      stats.error("lobby connect");
+      return;
+    }
+  };
+  stats.record_since("lobby connect", t);
+
+  // pings are answered while waiting for frames, the idle clients get nothing else
+  let deadline = Instant::now() + Duration::from_secs(opts.duration);
+  while Instant::now() < deadline {
+    if let Err(err) = client.recv_frame().await {
+      if !matches!(err, flo_testlab::error::Error::Timeout(_)) {
+        tracing::error!(player_id, "lobby: {}", err);
+        stats.error("lobby");
+        return;
+      }
+    }
+  }
+}
@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Latency samples and error counts, shared by every synthetic client
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+  inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+  samples: BTreeMap<&'static str, Vec<Duration>>,
+  errors: BTreeMap<&'static str, usize>,
+}
+
+impl Stats {
+  pub fn record(&self, name: &'static str, value: Duration) {
+    let mut inner = self.inner.lock().unwrap();
+    inner.samples.entry(name).or_default().push(value);
+  }
+
+  /// Records the time elapsed since `t`
+  pub fn record_since(&self, name: &'static str, t: Instant) {
+    self.record(name, t.elapsed())
+  }
+
+  pub fn error(&self, name: &'static str) {
+    let mut inner = self.inner.lock().unwrap();
+    *inner.errors.entry(name).or_default() += 1;
+  }
+
+  pub fn report(&self) -> String {
+    let mut inner = self.inner.lock().unwrap();
+    let mut lines = vec![format!(
+      "{:<16} {:>8} {:>10} {:>10} {:>10} {:>10}",
+      "", "count", "p50", "p90", "p99", "max"
+    )];
+    for (name, samples) in inner.samples.iter_mut() {
+      samples.sort();
+      lines.push(format!(
+        "{:<16} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        name,
+        samples.len(),
+        format_ms(percentile(samples, 50)),
+        format_ms(percentile(samples, 90)),
+        format_ms(percentile(samples, 99)),
+        format_ms(samples.last().cloned()),
+      ));
+    }
+    for (name, count) in &inner.errors {
+      lines.push(format!("error: {}: {}", name, count));
+    }
+    lines.join("\n")
+  }
+}
+
+// nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], p: usize) -> Option<Duration> {
+  if sorted.is_empty() {
+    return None;
+  }
+  let rank = (sorted.len() * p + 99) / 100;
+  sorted.get(rank.max(1) - 1).cloned()
+}
+
+fn format_ms(value: Option<Duration>) -> String {
+  value
+    .map(|v| format!("{:.1}ms", v.as_secs_f64() * 1000.))
+    .unwrap_or_else(|| "-".to_string())
+}
@@ -24,6 +24,14 @@ struct Opt {
 
   #[structopt(long)]
   controller_host: Option<String>,
+
+  /// Warcraft III runs with classic graphics
+  #[structopt(long)]
+  classic_graphics: Option<bool>,
+
+  /// Port of the LAN game listener
+  #[structopt(long)]
+  lan_port: Option<u16>,
 }
 
 fn main() {
@@ -46,6 +54,8 @@ fn main() {
       installation_path: opt.installation_path,
       user_data_path: opt.user_data_path,
       controller_host: opt.controller_host.clone(),
+      classic_graphics: opt.classic_graphics,
+      lan_port: opt.lan_port,
       ..Default::default()
     }))?;
     let port = client.port();
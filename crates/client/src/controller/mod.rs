@@ -17,7 +17,7 @@ use crate::node::stream::NodeStreamEvent;
 use crate::node::{
   self, GetNode, NodeRegistry, SetActiveNode, UpdateAddressesAndGetNodePingMap, UpdateNodes,
 };
-use crate::platform::{GetClientConfig, Platform};
+use crate::platform::{GetClientConfig, GetClientPlatformInfo, Platform};
 use crate::StartConfig;
 use flo_config::ClientConfig;
 use flo_net::packet::FloPacket;
@@ -38,6 +38,8 @@ pub struct ControllerClient {
   current_session: Option<PlayerSession>,
   initial_token: Option<String>,
   mute_list: Vec<i32>,
+  // the running Warcraft III is checked once per joined game
+  war3_checked_game_id: Option<i32>,
 }
 
 impl ControllerClient {
@@ -60,6 +62,40 @@ impl ControllerClient {
     }
   }
 
+  /// Reports to the lobby if the running Warcraft III is not the version of the client,
+  /// before the game starts
+  async fn check_war3_version(&self, game_id: i32) {
+    let expected = match self.platform.send(GetClientPlatformInfo::default()).await {
+      Ok(Ok(info)) => info.version,
+      _ => return,
+    };
+    let running = match crate::platform::get_running_war3() {
+      Ok(Some(running)) => running.version,
+      Ok(None) => return,
+      Err(err) => {
+        tracing::debug!("detect running war3: {}", err);
+        return;
+      }
+    };
+    if expected != running {
+      tracing::warn!(
+        game_id,
+        "war3 version mismatch: expected {}, running {}",
+        expected,
+        running
+      );
+      self
+        .ws_send(OutgoingMessage::War3VersionMismatch(
+          message::War3VersionMismatch {
+            game_id,
+            expected,
+            running,
+          },
+        ))
+        .await;
+    }
+  }
+
   async fn replace_lan_game(&self, event: GameReceivedEvent) {
     let game_id = event.game_info.game_id;
     tracing::info!(game_id, "replace lan game");
@@ -154,6 +190,7 @@ impl Service<StartConfig> for ControllerClient {
       current_session: None,
       initial_token: registry.data().token.clone(),
       mute_list: vec![],
+      war3_checked_game_id: None,
     })
   }
 }
@@ -241,8 +278,13 @@ impl Handler<ControllerEvent> for ControllerClient {
           ControllerEventData::GameInfoUpdate(event) => match event.game_info {
             Some(game_info) => {
               tracing::debug!(game_id = game_info.game_id, "game info update");
+              if self.war3_checked_game_id != Some(game_info.game_id) {
+                self.war3_checked_game_id = Some(game_info.game_id);
+                self.check_war3_version(game_info.game_id).await;
+              }
             }
             None => {
+              self.war3_checked_game_id.take();
               self.lan.notify(KillLanGame).await.ok();
             }
           },
@@ -358,6 +400,7 @@ impl Handler<ReplaceSession> for ControllerClient {
         .send_or_discard(OutgoingMessage::Disconnect(message::Disconnect {
           reason: message::DisconnectReason::Multi,
           message: "Another browser window took up the connection.".to_string(),
+          localized: None,
         }))
         .await;
     }
@@ -5,10 +5,10 @@ use crate::message::message;
 use crate::message::message::OutgoingMessage;
 use crate::node::{AddNode, GetNodePingMap, NodeRegistry, RemoveNode, UpdateNodes};
 use crate::ping::PingUpdate;
-use crate::platform::{CalcMapChecksum, GetClientPlatformInfo, Platform};
+use crate::platform::{CalcMapChecksum, GetClientConfig, GetClientPlatformInfo, Platform};
 use flo_net::packet::*;
 use flo_net::proto::flo_connect as proto;
-use flo_net::stream::FloStream;
+use flo_net::stream::{FloStream, FloStreamWriter};
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message};
 use flo_types::game::*;
 use s2_grpc_utils::S2ProtoPack;
@@ -111,15 +111,29 @@ impl ControllerStream {
 
     let reply = stream.recv_frame().await?;
 
-    let (session, nodes): (PlayerSession, _) = flo_net::try_flo_packet! {
+    let (session, nodes, suggestion, flags): (PlayerSession, _, _, _) = flo_net::try_flo_packet! {
       reply => {
         p: proto::PacketClientConnectAccept => {
           (
             PlayerSession::unpack(p.session)?,
-            p.nodes
+            p.nodes,
+            message::NodeSuggestion {
+              node_id: p.suggested_node_id,
+              region: Some(p.suggested_region).filter(|v| !v.is_empty()),
+            },
+            message::FeatureFlags {
+              flags: p.feature_flags,
+            }
           )
         }
         p: proto::PacketClientConnectReject => {
+          // lets the user download the latest client
+          if let Some(advertisement) = p.version_advertisement.clone() {
+            SendWs::new(
+              id,
+              OutgoingMessage::ClientVersionAdvertisement(advertisement)
+            ).notify(&parent).await.ok();
+          }
           return Err(Error::ConnectionRequestRejected(S2ProtoEnum::unpack_enum(p.reason())))
         }
       }
@@ -150,40 +164,42 @@ impl ControllerStream {
         message::OutgoingMessage::PlayerSession(session),
       ))
       .await?;
+    parent
+      .notify(SendWs::new(
+        id,
+        message::OutgoingMessage::NodeSuggestion(suggestion),
+      ))
+      .await?;
+    parent
+      .notify(SendWs::new(
+        id,
+        message::OutgoingMessage::FeatureFlags(flags),
+      ))
+      .await?;
+
+    // frames are sent by a separate task, so a slow send doesn't hold the received frames
+    let (mut reader, writer) = stream.split();
+    let (reply_tx, reply_rx) = channel(5);
+    let mut send =
+      tokio::spawn(Self::send_frames(writer, frame_receiver, reply_rx).in_current_span());
 
     loop {
       tokio::select! {
-        next_send = frame_receiver.recv() => {
-          if let Some(frame) = next_send {
-            match stream.send_frame_timeout(frame).await {
-              Ok(_) => {},
-              Err(e) => {
-                tracing::debug!("exiting: send error: {}", e);
-                break;
-              }
-            }
-          } else {
-            tracing::debug!("exiting: sender dropped");
-            break;
-          }
+        _ = &mut send => {
+          break;
         }
-        recv = stream.recv_frame() => {
+        recv = reader.recv_frame() => {
           match recv {
-            Ok(mut frame) => {
+            Ok(frame) => {
               if frame.type_id == PacketTypeId::Ping {
-                frame.type_id = PacketTypeId::Pong;
-                match stream.send_frame_timeout(frame).await {
-                  Ok(_) => {
-                    continue;
-                  },
-                  Err(e) => {
-                    tracing::debug!("exiting: send error: {}", e);
-                    break;
-                  }
+                if reply_tx.send(flo_net::keepalive::pong(frame)).await.is_err() {
+                  tracing::debug!("exiting: send task gone");
+                  break;
                 }
+                continue;
               }
 
-              match Self::handle_frame(id, player_id, frame, &mut stream, &owner, &parent, &nodes_reg).await {
+              match Self::handle_frame(id, player_id, frame, &reply_tx, &owner, &parent, &nodes_reg).await {
                 Ok(_) => {},
                 Err(e) => {
                   tracing::error!("handle frame: {}", e);
@@ -198,6 +214,7 @@ impl ControllerStream {
         }
       }
     }
+    send.abort();
 
     parent
       .notify(SendWs::new(
@@ -205,6 +222,7 @@ impl ControllerStream {
         OutgoingMessage::Disconnect(message::Disconnect {
           reason: message::DisconnectReason::Unknown,
           message: "Server connection closed".to_string(),
+          localized: None,
         }),
       ))
       .await?;
@@ -218,12 +236,37 @@ impl ControllerStream {
     Ok(())
   }
 
+  /// Sends the outgoing frames and the replies to received frames until the stream fails
+  async fn send_frames(
+    mut writer: FloStreamWriter,
+    mut frame_receiver: Receiver<Frame>,
+    mut reply_receiver: Receiver<Frame>,
+  ) {
+    loop {
+      let frame = tokio::select! {
+        next_send = frame_receiver.recv() => {
+          if let Some(frame) = next_send {
+            frame
+          } else {
+            tracing::debug!("exiting: sender dropped");
+            break;
+          }
+        }
+        Some(frame) = reply_receiver.recv() => frame,
+      };
+      if let Err(e) = writer.send_frame_timeout(frame).await {
+        tracing::debug!("exiting: send error: {}", e);
+        break;
+      }
+    }
+  }
+
   // handle controller packets
   async fn handle_frame(
     id: u64,
     player_id: i32,
     frame: Frame,
-    stream: &mut FloStream,
+    replies: &Sender<Frame>,
     owner: &Addr<Self>,
     parent: &Addr<ControllerClient>,
     nodes: &Addr<NodeRegistry>,
@@ -233,7 +276,12 @@ impl ControllerStream {
         p: proto::PacketClientDisconnect => {
           SendWs::new(id, OutgoingMessage::Disconnect(message::Disconnect {
               reason: S2ProtoEnum::unpack_i32(p.reason)?,
-              message: format!("Server closed the connection: {:?}", p.reason)
+              message: if p.message.is_empty() {
+                format!("Server closed the connection: {:?}", p.reason)
+              } else {
+                p.message
+              },
+              localized: p.localized,
             })).notify(parent).await?;
         }
         p: proto::PacketGameInfo => {
@@ -334,6 +382,25 @@ impl ControllerStream {
             OutgoingMessage::GameSelectNode(p)
           ).notify(parent).await?;
         }
+        p: proto::PacketGameHostUpdate => {
+          owner.send(UpdateLocalGameInfo::new({
+            let host = p.host.clone();
+            move |info| -> Result<_> {
+              info.created_by = host.map(PlayerInfo::unpack).transpose()?;
+              Ok(())
+            }
+          })).await??;
+          SendWs::new(
+            id,
+            OutgoingMessage::GameHostUpdate(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameInvite => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameInvite(p)
+          ).notify(parent).await?;
+        }
         p: proto::PacketPlayerPingMapUpdate => {
           SendWs::new(
             id,
@@ -352,16 +419,69 @@ impl ControllerStream {
             OutgoingMessage::GameStartReject(p)
           ).notify(parent).await?;
         }
+        p: proto::PacketGameStartQueued => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameStartQueued(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketReadyCheck => {
+          SendWs::new(
+            id,
+            OutgoingMessage::ReadyCheck(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketReadyCheckReject => {
+          SendWs::new(
+            id,
+            OutgoingMessage::ReadyCheckReject(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameWaitlistUpdate => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameWaitlistUpdate(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameWaitlistOffer => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameWaitlistOffer(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameRemoteJoin => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameRemoteJoin(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameRemoteJoinReject => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameRemoteJoinReject(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketClientVersionAdvertisement => {
+          if p.update_required {
+            tracing::warn!("client update required: enforced at {}", p.enforce_at);
+          }
+          SendWs::new(
+            id,
+            OutgoingMessage::ClientVersionAdvertisement(p)
+          ).notify(parent).await?;
+        }
         p: proto::PacketGameStarting => {
           let info = owner.send(GetGameStartClientInfo {
             game_id: p.game_id
           }).await??;
           if let Some(info) = info {
-            stream.send(flo_net::proto::flo_connect::PacketGameStartPlayerClientInfoRequest {
+            let frame = flo_net::proto::flo_connect::PacketGameStartPlayerClientInfoRequest {
               game_id: p.game_id,
               war3_version: info.war3_version,
               map_sha1: info.map_sha1,
-            }).await?;
+              graphics_mode: info.graphics_mode.into(),
+            }.encode_as_frame()?;
+            replies.send(frame).await.map_err(|_| Error::TaskCancelled(anyhow::format_err!("controller stream worker gone")))?;
             SendWs::new(
               id,
               OutgoingMessage::GameStarting(p)
@@ -413,6 +533,36 @@ impl ControllerStream {
             .notify(RemoveNode { node_id: p.node_id })
             .await?;
         }
+        p: proto::PacketPlayerPreferences => {
+          SendWs::new(
+            id,
+            OutgoingMessage::PlayerPreferences(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketServerNotice => {
+          SendWs::new(
+            id,
+            OutgoingMessage::ServerNotice(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketMotd => {
+          SendWs::new(
+            id,
+            OutgoingMessage::Motd(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameScheduled => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameScheduled(p)
+          ).notify(parent).await?;
+        }
+        p: proto::PacketGameStatusResponse => {
+          SendWs::new(
+            id,
+            OutgoingMessage::GameStatusResponse(p)
+          ).notify(parent).await?;
+        }
       }
     };
     Ok(())
@@ -616,6 +766,7 @@ struct GetGameStartClientInfo {
 struct GameStartClientInfo {
   war3_version: String,
   map_sha1: Vec<u8>,
+  graphics_mode: proto::GraphicsMode,
 }
 
 impl Message for GetGameStartClientInfo {
@@ -645,9 +796,15 @@ impl Handler<GetGameStartClientInfo> for ControllerStream {
           .await??
           .sha1
           .to_vec();
+        let graphics_mode = match self.platform.send(GetClientConfig).await?.classic_graphics {
+          Some(true) => proto::GraphicsMode::Classic,
+          Some(false) => proto::GraphicsMode::Reforged,
+          None => proto::GraphicsMode::Unknown,
+        };
         return Ok(Some(GameStartClientInfo {
           war3_version,
           map_sha1,
+          graphics_mode,
         }));
       }
     }
@@ -7,6 +7,7 @@ pub struct LocalGameInfo {
   pub name: String,
   pub game_id: i32,
   pub random_seed: i32,
+  pub entry_key: u32,
   pub node_id: Option<i32>,
   pub player_id: i32,
   pub map_path: String,
@@ -15,6 +16,7 @@ pub struct LocalGameInfo {
   pub players: HashMap<i32, PlayerInfo>,
   pub slots: Vec<Slot>,
   pub host_player: Option<PlayerInfo>,
+  pub classic_graphics_only: bool,
 }
 
 impl LocalGameInfo {
@@ -23,6 +25,7 @@ impl LocalGameInfo {
       name: game.name.clone(),
       game_id: game.id,
       random_seed: game.random_seed,
+      entry_key: game.entry_key,
       node_id: game.node.as_ref().map(|v| v.id).clone(),
       player_id,
       map_path: game.map.path.clone(),
@@ -42,6 +45,7 @@ impl LocalGameInfo {
         .collect(),
       slots: game.slots.clone(),
       host_player: game.created_by.clone(),
+      classic_graphics_only: game.classic_graphics_only,
     })
   }
 }
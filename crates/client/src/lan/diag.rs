@@ -52,6 +52,9 @@ pub async fn run_test_lobby(
     is_live: false,
     random_seed: 0,
     created_by: None,
+    entry_key: 0,
+    slots_version: 0,
+    classic_graphics_only: false,
   };
 
   let info = LanGameInfo {
@@ -1,21 +1,19 @@
-use std::net::SocketAddr;
 use std::time::{Duration, Instant};
 use tokio::sync::watch::Receiver;
 use tokio::time::{interval_at, sleep};
 
-use flo_util::binary::SockAddr;
 use flo_w3gs::net::W3GSStream;
 use flo_w3gs::protocol::chat::{ChatFromHost, ChatToHost};
 use flo_w3gs::protocol::game::{CountDownEnd, CountDownStart};
-use flo_w3gs::protocol::join::{ReqJoin, SlotInfoJoin};
+use flo_w3gs::protocol::join::ReqJoin;
 use flo_w3gs::protocol::leave::{LeaveAck, LeaveReq};
 use flo_w3gs::protocol::map::{MapCheck, MapSize};
 use flo_w3gs::protocol::packet::*;
 use flo_w3gs::protocol::ping::{PingFromHost, PongToHost};
-use flo_w3gs::protocol::player::{PlayerInfo, PlayerProfileMessage, PlayerSkinsMessage};
+use flo_w3gs::protocol::player::{PlayerProfileMessage, PlayerSkinsMessage};
 
 use crate::error::*;
-use crate::lan::game::slot::index_to_player_id;
+use crate::lan::game::slot::{index_to_player_id, FLO_OB_NAME};
 use crate::lan::game::LanGameInfo;
 use crate::node::stream::NodeStreamSender;
 use flo_types::node::{NodeGameStatus, SlotClientStatus};
@@ -161,14 +159,9 @@ impl<'a> LobbyHandler<'a> {
         let mut replies = Vec::with_capacity(num_players * 3);
 
         // slot info
-        replies.push(Packet::simple(SlotInfoJoin {
-          slot_info: slot_info.slot_info.clone(),
-          player_id: slot_info.my_slot_player_id,
-          external_addr: SockAddr::from(match self.stream.local_addr() {
-            SocketAddr::V4(addr) => addr,
-            SocketAddr::V6(_) => return Err(flo_w3gs::error::Error::Ipv6NotSupported.into()),
-          }),
-        })?);
+        replies.push(Packet::simple(
+          slot_info.slot_info_join(self.stream.local_addr())?,
+        )?);
         tracing::debug!(
           "-> slot info: slots = {}, players = {}, random_seed = {}",
           slot_info.slot_info.slots().len(),
@@ -176,33 +169,25 @@ impl<'a> LobbyHandler<'a> {
           slot_info.slot_info.random_seed
         );
 
-        let mut player_info_packets = Vec::with_capacity(num_players);
-        let mut player_skin_packets = Vec::with_capacity(num_players);
-        let mut player_profile_packets = Vec::with_capacity(num_players);
+        let peers = slot_info.peer_player_infos();
+        let mut player_info_packets = Vec::with_capacity(peers.len());
+        let mut player_skin_packets = Vec::with_capacity(peers.len());
+        let mut player_profile_packets = Vec::with_capacity(num_players + 1);
 
-        for info in &slot_info.player_infos {
-          if info.slot_player_id != slot_info.my_slot_player_id {
-            tracing::debug!(
-              "-> PlayerInfo: player: id = {}, name = {}",
-              info.slot_player_id,
-              info.name
-            );
-            player_info_packets.push(Packet::simple(PlayerInfo::new(
-              info.slot_player_id,
-              &info.name,
-            ))?);
-
-            tracing::debug!(
-              "-> PlayerSkinsMessage: player: id = {}, name = {}",
-              info.slot_player_id,
-              info.name
-            );
-            player_skin_packets.push(Packet::simple(ProtoBufPayload::new(PlayerSkinsMessage {
-              player_id: info.slot_player_id as u32,
-              ..Default::default()
-            }))?);
-          }
+        for info in peers {
+          tracing::debug!(
+            "-> PlayerInfo: player: id = {}, name = {:?}",
+            info.player_id,
+            info.player_name
+          );
+          player_skin_packets.push(Packet::simple(ProtoBufPayload::new(PlayerSkinsMessage {
+            player_id: info.player_id as u32,
+            ..Default::default()
+          }))?);
+          player_info_packets.push(Packet::simple(info)?);
+        }
 
+        for info in &slot_info.player_infos {
           tracing::debug!(
             "-> PlayerProfileMessage: player: id = {}, name = {}",
             info.slot_player_id,
@@ -215,18 +200,9 @@ impl<'a> LobbyHandler<'a> {
 
         if let Some(ob_slot) = self.info.slot_info.stream_ob_slot.clone() {
           let ob_player_id = index_to_player_id(ob_slot);
-          tracing::debug!("-> PlayerInfo: stream ob: {}", ob_player_id);
-          player_info_packets.push(Packet::simple(PlayerInfo::new(ob_player_id, "FLO"))?);
-
-          tracing::debug!("-> PlayerSkinsMessage: stream ob: {}", ob_player_id);
-          player_skin_packets.push(Packet::simple(ProtoBufPayload::new(PlayerSkinsMessage {
-            player_id: ob_player_id as u32,
-            ..Default::default()
-          }))?);
-
           tracing::debug!("-> PlayerProfileMessage: obs: {}", ob_player_id);
           player_profile_packets.push(Packet::simple(ProtoBufPayload::new(
-            PlayerProfileMessage::new(ob_player_id, "FLO"),
+            PlayerProfileMessage::new(ob_player_id, FLO_OB_NAME),
           ))?);
         }
 
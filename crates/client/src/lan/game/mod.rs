@@ -27,6 +27,8 @@ use tokio::sync::Notify;
 use tokio::time::sleep;
 use tracing_futures::Instrument;
 
+const WAR3_DETECT_INTERVAL: Duration = Duration::from_secs(2);
+
 pub struct LanGame {
   _scope: SpawnScope,
   state: Arc<State>,
@@ -49,6 +51,7 @@ impl LanGame {
     player_token: Vec<u8>,
     game: Arc<LocalGameInfo>,
     map_checksum: MapChecksum,
+    port: Option<u16>,
     client: Addr<ControllerClient>,
   ) -> Result<Self> {
     let mdns_shutdown_notify = Arc::new(Notify::new());
@@ -57,7 +60,11 @@ impl LanGame {
     #[cfg(not(feature = "worker"))]
     let game_name = get_lan_game_name(game.game_id, my_player_id);
     #[cfg(feature = "worker")]
-    let game_name = format!("{}-{}", game.name, my_player_id);
+    let game_name = flo_util::name::truncate_with_suffix(
+      &game.name,
+      &format!("-{}", my_player_id),
+      flo_util::name::MAX_GAME_NAME_LEN,
+    );
     let mut game_info = GameInfo::new(
       game.game_id,
       &game_name,
@@ -65,6 +72,10 @@ impl LanGame {
       game.map_sha1,
       game.map_checksum,
     )?;
+    game_info.secret = game.entry_key;
+    game_info
+      .data
+      .set_classic_graphics_only(game.classic_graphics_only);
     let token = NodeConnectToken::from_vec(player_token).ok_or_else(|| Error::InvalidNodeToken)?;
 
     let proxy = LanProxy::start(
@@ -80,6 +91,7 @@ impl LanGame {
       },
       node,
       token,
+      port,
       client.clone(),
     )
     .await?;
@@ -93,9 +105,21 @@ impl LanGame {
       {
         let mut scope = scope.handle();
         let mdns_shutdown_notify = mdns_shutdown_notify.clone();
-        let publisher = MdnsPublisher::start(game_info).await?;
         async move {
-          let _publisher = publisher;
+          // the game is advertised once Warcraft III is up
+          tokio::select! {
+            _ = scope.left() => return,
+            _ = mdns_shutdown_notify.notified() => return,
+            _ = wait_for_war3() => {}
+          }
+
+          let _publisher = match MdnsPublisher::start(game_info).await {
+            Ok(publisher) => publisher,
+            Err(err) => {
+              tracing::error!("start mdns publisher: {}", err);
+              return;
+            }
+          };
           tokio::select! {
             _ = scope.left() => {}
             _ = mdns_shutdown_notify.notified() => {}
@@ -156,6 +180,21 @@ impl LanGame {
   }
 }
 
+/// Resolves once Warcraft III is running, or immediately if it can't be detected on this platform.
+async fn wait_for_war3() {
+  loop {
+    match crate::platform::get_running_war3() {
+      Ok(Some(_)) => return,
+      Ok(None) => {}
+      Err(err) => {
+        tracing::debug!("detect running war3: {}", err);
+        return;
+      }
+    }
+    sleep(WAR3_DETECT_INTERVAL).await;
+  }
+}
+
 struct State {
   game_id: i32,
   my_player_id: i32,
@@ -18,17 +18,22 @@ use flo_w3gs::protocol::leave::{LeaveAck, LeaveReq};
 use flo_w3gs::protocol::packet::Packet;
 use flo_w3gs::protocol::packet::*;
 use flo_w3gs::protocol::ping::{PingFromHost, PongToHost};
+use lazy_static::lazy_static;
 use parking_lot::Mutex;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::{oneshot, watch};
 use tokio::time::interval;
 use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
 use tracing_futures::Instrument;
 
 const LOAD_SCREEN_PING_INTERVAL: Duration = Duration::from_secs(15);
+const BIND_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+const BIND_RETRIES: usize = 10;
 
 #[derive(Debug, Clone)]
 pub enum GameEndReason {
@@ -49,10 +54,11 @@ impl LanProxy {
     info: LanGameInfo,
     node: Arc<NodeInfo>,
     token: NodeConnectToken,
+    port: Option<u16>,
     client: Addr<ControllerClient>,
   ) -> Result<Self> {
     let scope = SpawnScope::new();
-    let listener = W3GSListener::bind().await?;
+    let (listener, port_guard) = bind_listener(port).await?;
     let port = listener.port();
     let (status_tx, status_rx) = watch::channel(None);
     let (event_tx, event_rx) = channel(10);
@@ -90,6 +96,7 @@ impl LanProxy {
         let res = state
           .serve(
             listener,
+            port_guard,
             event_rx,
             w3gs_tx,
             w3gs_rx,
@@ -139,6 +146,88 @@ impl LanProxy {
   }
 }
 
+/// Binds the preferred port if there is one.
+/// The listener of the last lan game is released asynchronously after it shut down,
+/// so an address in use is retried for a while before falling back to a random port.
+/// A proxy of this process still holding the port is stopped,
+/// ports held by other processes can only be waited for.
+async fn bind_listener(port: Option<u16>) -> Result<(W3GSListener, Option<PortGuard>)> {
+  let port = match port {
+    Some(port) if port != 0 => port,
+    _ => return Ok((W3GSListener::bind().await?, None)),
+  };
+
+  let mut taken_over = false;
+  for _ in 0..BIND_RETRIES {
+    match W3GSListener::bind_port(port).await {
+      Ok(listener) => return Ok((listener, Some(PortGuard::register(port)))),
+      Err(flo_w3gs::error::Error::Io(err)) if err.kind() == std::io::ErrorKind::AddrInUse => {
+        if !taken_over && take_over_port(port) {
+          tracing::info!(port, "port held by a stale proxy, stopping it");
+          taken_over = true;
+        }
+        tracing::debug!(port, "port in use, retrying");
+        tokio::time::sleep(BIND_RETRY_INTERVAL).await;
+      }
+      Err(err) => return Err(err.into()),
+    }
+  }
+
+  tracing::warn!(port, "port in use, using a random port");
+  Ok((W3GSListener::bind().await?, None))
+}
+
+lazy_static! {
+  /// Preferred ports held by the proxies of this process
+  static ref PORT_OWNERS: Mutex<HashMap<u16, PortOwner>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_PORT_OWNER_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+struct PortOwner {
+  id: u64,
+  ct: CancellationToken,
+}
+
+/// Held with the listener of a preferred port, cancelled when a new proxy takes the port over
+#[derive(Debug)]
+struct PortGuard {
+  port: u16,
+  owner: PortOwner,
+}
+
+impl PortGuard {
+  fn register(port: u16) -> Self {
+    let owner = PortOwner {
+      id: NEXT_PORT_OWNER_ID.fetch_add(1, Ordering::Relaxed),
+      ct: CancellationToken::new(),
+    };
+    PORT_OWNERS.lock().insert(port, owner.clone());
+    PortGuard { port, owner }
+  }
+}
+
+impl Drop for PortGuard {
+  fn drop(&mut self) {
+    let mut owners = PORT_OWNERS.lock();
+    if owners.get(&self.port).map(|owner| owner.id) == Some(self.owner.id) {
+      owners.remove(&self.port);
+    }
+  }
+}
+
+/// Stops the proxy of this process holding `port`, `false` if there is none
+fn take_over_port(port: u16) -> bool {
+  match PORT_OWNERS.lock().get(&port) {
+    Some(owner) => {
+      owner.ct.cancel();
+      true
+    }
+    None => false,
+  }
+}
+
 #[derive(Debug)]
 struct State {
   info: LanGameInfo,
@@ -150,6 +239,7 @@ impl State {
   async fn serve(
     self: Arc<Self>,
     mut listener: W3GSListener,
+    port_guard: Option<PortGuard>,
     event_rx: Receiver<PlayerEvent>,
     mut w3gs_tx: Sender<Packet>,
     mut w3gs_rx: Receiver<Packet>,
@@ -162,8 +252,20 @@ impl State {
     let mut status_rx = self.game_status_rx.clone();
     let (stop_collect_player_events_tx, stop_rx) = oneshot::channel();
 
+    // the listener is released when this returns
+    let taken_over = port_guard
+      .as_ref()
+      .map(|guard| guard.owner.ct.clone())
+      .unwrap_or_else(CancellationToken::new);
     tokio::pin! {
-      let dropped = scope.left();
+      let dropped = async {
+        tokio::select! {
+          _ = scope.left() => {}
+          _ = taken_over.cancelled() => {
+            tracing::info!("port taken over by a new proxy, exiting");
+          }
+        }
+      };
       let collect_player_events = self.collect_player_events(event_rx, stop_rx, &self.info);
     }
 
@@ -551,3 +653,18 @@ pub enum PlayerEvent {
     status: SlotClientStatus,
   },
 }
+
+#[test]
+fn test_port_take_over() {
+  let stale = PortGuard::register(6112);
+  assert!(take_over_port(6112));
+  assert!(stale.owner.ct.is_cancelled());
+
+  let next = PortGuard::register(6112);
+  // the stale listener released after the new one bound
+  drop(stale);
+  assert!(take_over_port(6112));
+  assert!(next.owner.ct.is_cancelled());
+  drop(next);
+  assert!(!take_over_port(6112));
+}
@@ -1,7 +1,14 @@
-use flo_w3gs::slot::{RacePref, SlotData, SlotInfo};
+use flo_w3gs::slot::{RacePref, SlotData, SlotInfo, SlotLayout};
 
 use crate::error::*;
 use flo_types::game::{LanGameSlot, SlotStatus};
+use flo_util::binary::SockAddr;
+use flo_w3gs::protocol::join::SlotInfoJoin;
+use flo_w3gs::protocol::player::PlayerInfo;
+use std::net::SocketAddr;
+
+/// Name of the stream observer
+pub(crate) const FLO_OB_NAME: &str = "FLO";
 
 #[derive(Debug)]
 pub struct LanSlotInfo {
@@ -20,6 +27,38 @@ pub struct LanSlotPlayerInfo {
   pub name: String,
 }
 
+impl LanSlotInfo {
+  /// `SlotInfoJoin` reply to the `ReqJoin` of the local game client
+  pub fn slot_info_join(&self, local_addr: SocketAddr) -> Result<SlotInfoJoin> {
+    let external_addr = match local_addr {
+      SocketAddr::V4(addr) => SockAddr::from(addr),
+      SocketAddr::V6(_) => return Err(flo_w3gs::error::Error::Ipv6NotSupported.into()),
+    };
+    Ok(SlotInfoJoin {
+      slot_info: self.slot_info.clone(),
+      player_id: self.my_slot_player_id,
+      external_addr,
+    })
+  }
+
+  /// `PlayerInfo` of the players other than the local player, including the stream observer
+  pub fn peer_player_infos(&self) -> Vec<PlayerInfo> {
+    let mut infos: Vec<_> = self
+      .player_infos
+      .iter()
+      .filter(|info| info.slot_player_id != self.my_slot_player_id)
+      .map(|info| PlayerInfo::new(info.slot_player_id, flo_util::name::normalize(&info.name)))
+      .collect();
+    if let Some(ob_slot) = self.stream_ob_slot {
+      let ob_player_id = index_to_player_id(ob_slot);
+      if ob_player_id != self.my_slot_player_id {
+        infos.push(PlayerInfo::new(ob_player_id, FLO_OB_NAME));
+      }
+    }
+    infos
+  }
+}
+
 pub enum SelfPlayer {
   Player(i32),
   StreamObserver,
@@ -77,6 +116,7 @@ where
   let mut slot_info = {
     let mut b = SlotInfo::build();
     b.random_seed(random_seed)
+      .slot_layout(SlotLayout::Melee)
       .num_slots(24)
       .num_players(
         occupied_slots
@@ -168,3 +208,117 @@ where
 pub fn index_to_player_id(index: usize) -> u8 {
   return (index + 1) as u8;
 }
+
+#[test]
+fn test_lan_slot_info_packets() {
+  use flo_types::game::{PlayerInfo as LobbyPlayerInfo, PlayerSource, Slot, SlotSettings};
+
+  let slot = |id: i32, team: i32, color: i32| Slot {
+    player: Some(LobbyPlayerInfo {
+      id,
+      name: format!("player{}", id),
+      source: PlayerSource::Test,
+    }),
+    settings: SlotSettings {
+      team,
+      color,
+      handicap: 80,
+      status: SlotStatus::Occupied,
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+  let slots = vec![slot(1, 0, 0), slot(2, 1, 1)];
+
+  let info = build_player_slot_info(2, 0x12345678, &slots).unwrap();
+  assert_eq!(info.my_slot_player_id, 2);
+  assert_eq!(info.stream_ob_slot, Some(23));
+
+  let join = info
+    .slot_info_join("127.0.0.1:6112".parse().unwrap())
+    .unwrap();
+  assert_eq!(join.player_id, 2);
+  assert_eq!(join.external_addr, SockAddr::new_ipv4([127, 0, 0, 1], 6112));
+  assert_eq!(join.slot_info.random_seed, 0x12345678);
+  assert_eq!(join.slot_info.slot_layout, SlotLayout::Melee);
+  assert_eq!(join.slot_info.num_players, 2);
+  assert_eq!(join.slot_info.slots().len(), 24);
+  assert_eq!(join.slot_info.slots()[0].handicap, 80);
+  assert_eq!(join.slot_info.slots()[1].team, 1);
+  assert_eq!(join.slot_info.slots()[23].team, 24);
+  assert!(info.slot_info_join("[::1]:6112".parse().unwrap()).is_err());
+
+  let peers: Vec<_> = info
+    .peer_player_infos()
+    .into_iter()
+    .map(|p| {
+      (
+        p.player_id,
+        p.join_counter,
+        p.player_name.into_string().unwrap(),
+      )
+    })
+    .collect();
+  assert_eq!(
+    peers,
+    vec![
+      (1, 1, "player1".to_string()),
+      (24, 1, FLO_OB_NAME.to_string())
+    ]
+  );
+}
+
+#[test]
+fn test_lan_slot_info_packets_captured() {
+  use bytes::BytesMut;
+  use flo_w3gs::packet::Packet;
+
+  let captured = |filename: &str| {
+    let mut bytes = BytesMut::from(flo_util::sample_bytes!("packet", filename).as_slice());
+    let header = Packet::decode_header(&mut bytes).unwrap();
+    Packet::decode(header, &mut bytes).unwrap()
+  };
+
+  // the captured reply to the second player of a 2 slots lobby
+  let slot_info_join = captured("slot_info_join.bin");
+  let join: SlotInfoJoin = slot_info_join.decode_simple().unwrap();
+  let info = LanSlotInfo {
+    my_slot_player_id: 2,
+    my_slot: join.slot_info.slots()[1].clone(),
+    slot_info: join.slot_info,
+    player_infos: vec![
+      LanSlotPlayerInfo {
+        slot_player_id: 1,
+        slot_index: 0,
+        player_id: 1,
+        name: "fluxxu#1815".to_string(),
+      },
+      LanSlotPlayerInfo {
+        slot_player_id: 2,
+        slot_index: 1,
+        player_id: 2,
+        name: "PLAYER".to_string(),
+      },
+    ],
+    stream_ob_slot: None,
+  };
+  assert_eq!(
+    Packet::simple(
+      info
+        .slot_info_join("192.168.1.6:7379".parse().unwrap())
+        .unwrap()
+    )
+    .unwrap()
+    .payload,
+    slot_info_join.payload
+  );
+
+  let peers = info.peer_player_infos();
+  assert_eq!(peers.len(), 1);
+  assert_eq!(
+    Packet::simple(peers.into_iter().next().unwrap())
+      .unwrap()
+      .payload,
+    captured("player_info.bin").payload
+  );
+}
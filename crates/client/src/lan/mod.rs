@@ -12,7 +12,7 @@ use crate::error::*;
 use crate::game::LocalGameInfo;
 use crate::node::stream::NodeStreamEvent;
 use crate::node::NodeInfo;
-use crate::platform::{CalcMapChecksum, Platform};
+use crate::platform::{CalcMapChecksum, GetClientConfig, Platform};
 use crate::StartConfig;
 use flo_state::{
   async_trait, Actor, Addr, Context, Deferred, Handler, Message, RegistryRef, Service,
@@ -23,6 +23,7 @@ pub struct Lan {
   platform: Addr<Platform>,
   client: Deferred<ControllerClient, StartConfig>,
   active_game: Option<LanGame>,
+  lan_port: Option<u16>,
 }
 
 impl Actor for Lan {}
@@ -32,11 +33,13 @@ impl Service<StartConfig> for Lan {
   type Error = Error;
 
   async fn create(registry: &mut RegistryRef<StartConfig>) -> Result<Self, Self::Error> {
-    let platform = registry.resolve().await?;
+    let platform: Addr<Platform> = registry.resolve().await?;
+    let lan_port = platform.send(GetClientConfig).await?.lan_port;
     Ok(Lan {
       platform,
       client: registry.deferred(),
       active_game: None,
+      lan_port,
     })
   }
 }
@@ -93,6 +96,7 @@ impl Handler<ReplaceLanGame> for Lan {
         player_token,
         game,
         checksum,
+        self.lan_port,
         self.client.resolve().await?,
       )
       .await?;
@@ -22,6 +22,10 @@ pub struct StartConfig {
   pub user_data_path: Option<PathBuf>,
   pub controller_host: Option<String>,
   pub stats_host: Option<String>,
+  /// Port of the LAN game listener, a random port is used if unset or unavailable
+  pub lan_port: Option<u16>,
+  /// Warcraft III runs with classic graphics, unknown if unset
+  pub classic_graphics: Option<bool>,
 }
 
 pub struct FloClient {
@@ -5,9 +5,17 @@ use std::borrow::Cow;
 use std::str::FromStr;
 
 use flo_net::proto::flo_connect::{
+  LocalizedMessage, PacketClientVersionAdvertisement, PacketGameBalanceTeamsRequest,
+  PacketGameHostUpdate, PacketGameInvite, PacketGameInviteAcceptRequest, PacketGameInviteRequest,
   PacketGamePlayerLeave, PacketGamePlayerPingMapSnapshot, PacketGamePlayerPingMapSnapshotRequest,
-  PacketGameSelectNode, PacketGameSelectNodeRequest, PacketGameStartReject, PacketGameStartRequest,
-  PacketGameStarting, PacketPlayerPingMapUpdate,
+  PacketGameRemoteJoin, PacketGameRemoteJoinReject, PacketGameRemoteJoinRequest,
+  PacketGameScheduled, PacketGameSelectNode, PacketGameSelectNodeRequest, PacketGameStartQueued,
+  PacketGameStartReject, PacketGameStartRequest, PacketGameStarting, PacketGameStatusRequest,
+  PacketGameStatusResponse, PacketGameTransferHostRequest, PacketGameWaitlistJoinRequest,
+  PacketGameWaitlistLeaveRequest, PacketGameWaitlistOffer, PacketGameWaitlistOfferResponse,
+  PacketGameWaitlistUpdate, PacketMotd, PacketPlayerPingMapUpdate, PacketPlayerPreferences,
+  PacketPlayerPreferencesUpdateRequest, PacketReadyCheck, PacketReadyCheckReject,
+  PacketReadyCheckResponse, PacketServerNotice,
 };
 
 use crate::error::{Error, Result};
@@ -29,9 +37,20 @@ pub enum IncomingMessage {
   GetMapDetail(MapPath),
   GameSlotUpdateRequest(GameSlotUpdateRequest),
   GameSelectNodeRequest(PacketGameSelectNodeRequest),
+  GameBalanceTeamsRequest(PacketGameBalanceTeamsRequest),
+  GameTransferHostRequest(PacketGameTransferHostRequest),
+  GameInviteRequest(PacketGameInviteRequest),
+  GameInviteAcceptRequest(PacketGameInviteAcceptRequest),
+  PlayerPreferencesUpdateRequest(PacketPlayerPreferencesUpdateRequest),
   GamePlayerPingMapSnapshotRequest(PacketGamePlayerPingMapSnapshotRequest),
+  GameStatusRequest(PacketGameStatusRequest),
   ListNodesRequest,
   GameStartRequest(PacketGameStartRequest),
+  ReadyCheckResponse(PacketReadyCheckResponse),
+  GameWaitlistJoinRequest(PacketGameWaitlistJoinRequest),
+  GameWaitlistLeaveRequest(PacketGameWaitlistLeaveRequest),
+  GameWaitlistOfferResponse(PacketGameWaitlistOfferResponse),
+  GameRemoteJoinRequest(PacketGameRemoteJoinRequest),
   StartTestGame(StartTestGame),
   KillTestGame,
   SetNodeAddrOverrides(SetNodeAddrOverrides),
@@ -57,11 +76,23 @@ pub enum OutgoingMessage {
   GameSlotUpdate(GameSlotUpdate),
   PlayerSessionUpdate(PlayerSessionUpdate),
   ListNodes(NodeList),
+  NodeSuggestion(NodeSuggestion),
+  FeatureFlags(FeatureFlags),
   PingUpdate(PingUpdate),
   GameSelectNode(PacketGameSelectNode),
+  GameHostUpdate(PacketGameHostUpdate),
+  GameInvite(PacketGameInvite),
   PlayerPingMapUpdate(PacketPlayerPingMapUpdate),
   GamePlayerPingMapSnapshot(PacketGamePlayerPingMapSnapshot),
   GameStartReject(PacketGameStartReject),
+  GameStartQueued(PacketGameStartQueued),
+  ReadyCheck(PacketReadyCheck),
+  ReadyCheckReject(PacketReadyCheckReject),
+  GameWaitlistUpdate(PacketGameWaitlistUpdate),
+  GameWaitlistOffer(PacketGameWaitlistOffer),
+  GameRemoteJoin(PacketGameRemoteJoin),
+  GameRemoteJoinReject(PacketGameRemoteJoinReject),
+  ClientVersionAdvertisement(PacketClientVersionAdvertisement),
   GameStarting(PacketGameStarting),
   GameStarted(GameStarted),
   GameStartError(ErrorMessage),
@@ -69,6 +100,12 @@ pub enum OutgoingMessage {
   GameStatusUpdate(GameStatusUpdate),
   GameDisconnect,
   SetNodeAddrOverridesError(ErrorMessage),
+  ServerNotice(PacketServerNotice),
+  Motd(PacketMotd),
+  PlayerPreferences(PacketPlayerPreferences),
+  GameScheduled(PacketGameScheduled),
+  GameStatusResponse(PacketGameStatusResponse),
+  War3VersionMismatch(War3VersionMismatch),
 }
 
 impl FromStr for IncomingMessage {
@@ -120,6 +157,7 @@ pub struct Connect {
 pub struct Disconnect {
   pub reason: DisconnectReason,
   pub message: String,
+  pub localized: Option<LocalizedMessage>,
 }
 
 #[derive(Debug, Serialize)]
@@ -137,6 +175,19 @@ pub struct NodeList {
   pub nodes: Vec<Node>,
 }
 
+/// Closest node by GeoIP, sent on connect
+#[derive(Debug, Serialize)]
+pub struct NodeSuggestion {
+  pub node_id: Option<i32>,
+  pub region: Option<String>,
+}
+
+/// Features enabled by the lobby for the player
+#[derive(Debug, Serialize)]
+pub struct FeatureFlags {
+  pub flags: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct Node {
   pub id: i32,
@@ -152,12 +203,23 @@ pub struct GameStarted {
   pub lan_game_name: String,
 }
 
+/// The running Warcraft III is not the version of the client, sent when joining a game
+#[derive(Debug, Serialize)]
+pub struct War3VersionMismatch {
+  pub game_id: i32,
+  pub expected: String,
+  pub running: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, S2ProtoPack)]
 #[s2_grpc(message_type(flo_net::proto::flo_connect::PacketGameSlotUpdateRequest))]
 pub struct GameSlotUpdateRequest {
   pub game_id: i32,
   pub slot_index: i32,
   pub slot_settings: SlotSettings,
+  /// `slots_version` of the last update seen, omitted or 0 skips the check
+  #[serde(default)]
+  pub slots_version: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize, S2ProtoUnpack)]
@@ -167,6 +229,7 @@ pub struct GameSlotUpdate {
   pub slot_index: i32,
   pub slot_settings: SlotSettings,
   pub player: Option<PlayerInfo>,
+  pub slots_version: i32,
 }
 
 use crate::controller::SetNodeAddrOverrides;
@@ -179,4 +242,5 @@ pub struct GamePlayerEnter {
   pub game_id: i32,
   pub slot_index: i32,
   pub slot: Slot,
+  pub slots_version: i32,
 }
@@ -159,6 +159,24 @@ impl Worker {
       IncomingMessage::GameSelectNodeRequest(req) => {
         self.send_frame(req).await?;
       }
+      IncomingMessage::GameBalanceTeamsRequest(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameStatusRequest(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameTransferHostRequest(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameInviteRequest(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameInviteAcceptRequest(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::PlayerPreferencesUpdateRequest(req) => {
+        self.send_frame(req).await?;
+      }
       IncomingMessage::GamePlayerPingMapSnapshotRequest(req) => {
         self
           .send_frame::<PacketGamePlayerPingMapSnapshotRequest>(req)
@@ -167,6 +185,21 @@ impl Worker {
       IncomingMessage::GameStartRequest(req) => {
         self.send_frame::<PacketGameStartRequest>(req).await?;
       }
+      IncomingMessage::ReadyCheckResponse(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameWaitlistJoinRequest(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameWaitlistLeaveRequest(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameWaitlistOfferResponse(req) => {
+        self.send_frame(req).await?;
+      }
+      IncomingMessage::GameRemoteJoinRequest(req) => {
+        self.send_frame(req).await?;
+      }
       IncomingMessage::StartTestGame(msg) => {
         self.platform.send(msg).await??;
       }
@@ -1,8 +1,9 @@
 use crate::error::*;
 use crate::ping::{
-  AddAddress, GetPingMap, PingActor, RemoveAddress, SetActiveAddress, UpdateAddresses,
+  AddAddress, GetPingMap, PingActor, PingTarget, RemoveAddress, SetActiveAddress, UpdateAddresses,
 };
 use crate::StartConfig;
+use flo_net::echo::PingToken;
 use flo_net::proto::flo_connect::Node;
 use flo_state::{async_trait, Actor, Context, Handler, Message, Owner, RegistryRef, Service};
 use flo_types::ping::PingStats;
@@ -111,22 +112,24 @@ impl Handler<UpdateNodes> for NodeRegistry {
           location: node.location.to_string(),
           country_id: node.country_id.to_string(),
           socket_addr,
+          ping_token: PingToken::from_slice(&node.ping_token),
         },
       );
     }
 
-    let addresses: Vec<_> = self
+    let targets: Vec<_> = self
       .map
       .values()
-      .map(|v| {
-        self
+      .filter_map(|v| {
+        let address = self
           .addr_overrides
           .get(&v.id)
           .cloned()
-          .unwrap_or_else(|| v.socket_addr)
+          .unwrap_or_else(|| v.socket_addr);
+        v.ping_target(address)
       })
       .collect();
-    self.ping.send(UpdateAddresses { addresses }).await?;
+    self.ping.send(UpdateAddresses { targets }).await?;
 
     Ok(())
   }
@@ -276,6 +279,7 @@ impl Handler<AddNode> for NodeRegistry {
       }
     };
     let name = node.name;
+    let ping_token = PingToken::from_slice(&node.ping_token);
 
     self.map.insert(
       node.id,
@@ -285,16 +289,22 @@ impl Handler<AddNode> for NodeRegistry {
         location: node.location.to_string(),
         country_id: node.country_id.to_string(),
         socket_addr,
+        ping_token,
       },
     );
 
-    self
-      .ping
-      .notify(AddAddress {
-        address: socket_addr,
-      })
-      .await
-      .ok();
+    if let Some(token) = ping_token {
+      self
+        .ping
+        .notify(AddAddress {
+          address: socket_addr,
+          token,
+        })
+        .await
+        .ok();
+    } else {
+      tracing::warn!(node_id = node.id, "node has no ping token");
+    }
     tracing::debug!(node_id = node.id, "add node: {}", socket_addr);
   }
 }
@@ -344,15 +354,21 @@ impl Handler<SetNodeAddrOverrides> for NodeRegistry {
     _: &mut Context<Self>,
     SetNodeAddrOverrides { overrides }: SetNodeAddrOverrides,
   ) -> <SetNodeAddrOverrides as Message>::Result {
-    let mut addresses: Vec<_> = self.map.values().map(|v| v.socket_addr).collect();
+    let mut targets: Vec<_> = self
+      .map
+      .values()
+      .filter_map(|v| v.ping_target(v.socket_addr))
+      .collect();
     for (id, addr) in overrides.iter() {
-      if !addresses.contains(addr) {
+      if !targets.iter().any(|t| t.address == *addr) {
         tracing::debug!(node_id = *id, "addr override: {}", addr);
-        addresses.push(*addr);
+        if let Some(target) = self.map.get(id).and_then(|v| v.ping_target(*addr)) {
+          targets.push(target);
+        }
       }
     }
     self.addr_overrides = overrides;
-    self.ping.notify(UpdateAddresses { addresses }).await?;
+    self.ping.notify(UpdateAddresses { targets }).await?;
     Ok(())
   }
 }
@@ -372,8 +388,12 @@ impl Handler<ClearNodeAddrOverrides> for NodeRegistry {
   ) -> <SetNodeAddrOverrides as Message>::Result {
     self.addr_overrides.clear();
 
-    let addresses: Vec<_> = self.map.values().map(|v| v.socket_addr).collect();
-    self.ping.notify(UpdateAddresses { addresses }).await?;
+    let targets: Vec<_> = self
+      .map
+      .values()
+      .filter_map(|v| v.ping_target(v.socket_addr))
+      .collect();
+    self.ping.notify(UpdateAddresses { targets }).await?;
 
     Ok(())
   }
@@ -392,6 +412,7 @@ pub struct NodeInfo {
   pub location: String,
   pub country_id: String,
   socket_addr: SocketAddr,
+  ping_token: Option<PingToken>,
 }
 
 impl NodeInfo {
@@ -399,6 +420,10 @@ impl NodeInfo {
     self.socket_addr_offset(flo_constants::NODE_CLIENT_PORT_OFFSET)
   }
 
+  fn ping_target(&self, address: SocketAddr) -> Option<PingTarget> {
+    self.ping_token.map(|token| PingTarget { address, token })
+  }
+
   fn socket_addr_offset(&self, offset: u16) -> SocketAddr {
     let mut addr = self.socket_addr;
     addr.set_port(addr.port() + offset);
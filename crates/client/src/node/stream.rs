@@ -302,6 +302,7 @@ impl Session {
       .send(proto::PacketClientConnect {
         version: Some(crate::version::FLO_VERSION.into()),
         token: self.token.to_vec(),
+        trace_context: flo_net::trace::current_context(),
         ..Default::default()
       })
       .await?;
@@ -380,6 +381,7 @@ impl Session {
         token: self.token.to_vec(),
         retry_shutdown: true,
         leave_reason,
+        trace_context: flo_net::trace::current_context(),
       })
       .await?;
 
@@ -522,13 +524,12 @@ impl Connection {
         // packet from node
         next = stream.recv_frame() => {
           match next {
-            Ok(mut frame) => {
+            Ok(frame) => {
               match frame.type_id {
                 PacketTypeId::Ping => {
                   Self::reset_timeout(ping_timeout.as_mut());
 
-                  frame.type_id = PacketTypeId::Pong;
-                  if let Err(err) = stream.send_frame(frame).await {
+                  if let Err(err) = stream.send_frame(flo_net::keepalive::pong(frame)).await {
                     tracing::error!("send pong to node: {}", err);
                     break ConnectionRunResult::NodeDisconnected;
                   }
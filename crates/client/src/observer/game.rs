@@ -6,7 +6,6 @@ use flo_lan::MdnsPublisher;
 use flo_observer::record::GameRecordData;
 use flo_state::Addr;
 use flo_types::observer::GameInfo;
-use flo_util::binary::SockAddr;
 use flo_w3gs::action::IncomingAction;
 use flo_w3gs::chat::ChatFromHost;
 use flo_w3gs::constants::{PacketTypeId, ProtoBufMessageTypeId};
@@ -16,15 +15,14 @@ use flo_w3gs::net::{W3GSListener, W3GSStream};
 use flo_w3gs::packet::Packet;
 use flo_w3gs::protocol::action::OutgoingKeepAlive;
 use flo_w3gs::protocol::game::{CountDownEnd, CountDownStart, PlayerLoaded};
-use flo_w3gs::protocol::join::{ReqJoin, SlotInfoJoin};
+use flo_w3gs::protocol::join::ReqJoin;
 use flo_w3gs::protocol::leave::LeaveAck;
 use flo_w3gs::protocol::map::{MapCheck, MapSize};
 use flo_w3gs::protocol::packet::ProtoBufPayload;
-use flo_w3gs::protocol::player::{PlayerInfo, PlayerProfileMessage, PlayerSkinsMessage};
+use flo_w3gs::protocol::player::{PlayerProfileMessage, PlayerSkinsMessage};
 use flo_w3map::MapChecksum;
 use futures::Stream;
 use std::collections::VecDeque;
-use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{atomic::AtomicU64, Arc};
 use std::time::{Duration, Instant, SystemTime};
@@ -546,14 +544,9 @@ where
     let mut replies = Vec::with_capacity((num_players - 1) * 3);
 
     // slot info
-    replies.push(Packet::simple(SlotInfoJoin {
-      slot_info: slot_info.slot_info.clone(),
-      player_id: slot_info.my_slot_player_id,
-      external_addr: SockAddr::from(match stream.local_addr() {
-        SocketAddr::V4(addr) => addr,
-        SocketAddr::V6(_) => return Err(flo_w3gs::error::Error::Ipv6NotSupported.into()),
-      }),
-    })?);
+    replies.push(Packet::simple(
+      slot_info.slot_info_join(stream.local_addr())?,
+    )?);
 
     tracing::debug!(
       "-> slot info: slots = {}, players = {}, random_seed = {}",
@@ -566,27 +559,20 @@ where
     let mut player_skin_packets = Vec::with_capacity(num_players);
     let mut player_profile_packets = Vec::with_capacity(num_players);
 
-    for info in &slot_info.player_infos {
-      tracing::debug!(
-        "-> PlayerInfo: player: id = {}, name = {}",
-        info.slot_player_id,
-        info.name
-      );
-      player_info_packets.push(Packet::simple(PlayerInfo::new(
-        info.slot_player_id,
-        &info.name,
-      ))?);
-
+    for info in slot_info.peer_player_infos() {
       tracing::debug!(
-        "-> PlayerSkinsMessage: player: id = {}, name = {}",
-        info.slot_player_id,
-        info.name
+        "-> PlayerInfo: player: id = {}, name = {:?}",
+        info.player_id,
+        info.player_name
       );
       player_skin_packets.push(Packet::simple(ProtoBufPayload::new(PlayerSkinsMessage {
-        player_id: info.slot_player_id as u32,
+        player_id: info.player_id as u32,
         ..Default::default()
       }))?);
+      player_info_packets.push(Packet::simple(info)?);
+    }
 
+    for info in &slot_info.player_infos {
       tracing::debug!(
         "-> PlayerProfileMessage: player: id = {}, name = {}",
         info.slot_player_id,
@@ -1,5 +1,6 @@
 use super::{PingError, SendPing};
 use crate::error::*;
+use flo_net::echo::{encode_datagram, PingToken, ECHO_PAYLOAD_LEN};
 use flo_net::time::StopWatch;
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message};
 use flo_types::ping::PingStats;
@@ -25,15 +26,17 @@ pub struct PingCollectActor {
   stop_watch: StopWatch,
   base_time: u32,
   sender: Sender<SendPing>,
+  token: PingToken,
   abort_timeout: Option<AbortHandle>,
   stats: PingStats,
   active: bool,
 }
 
 impl PingCollectActor {
-  pub fn new(sender: Sender<SendPing>, sock_addr: SocketAddr) -> Self {
+  pub fn new(sender: Sender<SendPing>, sock_addr: SocketAddr, token: PingToken) -> Self {
     Self {
       sender,
+      token,
       sock_addr_string: format!("{}", sock_addr),
       sock_addr,
       batch_id: rand::random(),
@@ -60,9 +63,10 @@ impl PingCollectActor {
     stop_watch: StopWatch,
     sender: Sender<SendPing>,
     sock_addr: SocketAddr,
+    token: PingToken,
     batch_id: u8,
   ) -> Result<(), PingError> {
-    let mut buf = [0_u8; 4];
+    let mut buf = [0_u8; ECHO_PAYLOAD_LEN];
     let base_time = stop_watch.elapsed_ms();
     addr.send(SetBaseTime { base_time }).await.ok();
     for seq in 0..(PACKETS as u16) {
@@ -78,7 +82,7 @@ impl PingCollectActor {
         .send_timeout(
           SendPing {
             to: sock_addr,
-            data: buf,
+            data: encode_datagram(&token, buf),
           },
           Duration::from_millis(50),
         )
@@ -145,6 +149,7 @@ impl PingCollectActor {
         self.stop_watch.clone(),
         self.sender.clone(),
         self.sock_addr.clone().into(),
+        self.token,
         self.batch_id,
       );
       let address_string = self.sock_addr_string.clone();
@@ -337,6 +342,25 @@ impl Handler<GetPingStats> for PingCollectActor {
   }
 }
 
+pub struct SetToken {
+  pub token: PingToken,
+}
+
+impl Message for SetToken {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<SetToken> for PingCollectActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    SetToken { token }: SetToken,
+  ) -> <SetToken as Message>::Result {
+    self.token = token;
+  }
+}
+
 pub struct SetActive {
   pub active: bool,
 }
@@ -372,7 +396,7 @@ async fn test_ping_collect() {
   let sock_addr = SocketAddr::from((Ipv4Addr::new(127, 0, 0, 1), 3552));
 
   let actor = {
-    let mut a = PingCollectActor::new(tx, sock_addr);
+    let mut a = PingCollectActor::new(tx, sock_addr, PingToken::issue(""));
     a.active = true;
     a
   }
@@ -1,5 +1,6 @@
 use crate::error::Result;
-use crate::ping::collect::{GetPingStats, PingCollectActor, PingReply, SetActive};
+use crate::ping::collect::{GetPingStats, PingCollectActor, PingReply, SetActive, SetToken};
+use flo_net::echo::{PingToken, ECHO_DATAGRAM_LEN, ECHO_PAYLOAD_LEN};
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message, Owner};
 use flo_types::ping::PingStats;
 use flo_util::binary::Ipv4Addr;
@@ -33,14 +34,14 @@ impl PingActor {
 
   async fn worker(addr: Addr<Self>, rx: &mut mpsc::Receiver<SendPing>) -> Result<(), PingError> {
     let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
-    let mut buf = [0_u8; 4];
+    let mut buf = [0_u8; ECHO_PAYLOAD_LEN];
     loop {
       tokio::select! {
         Some(SendPing { to, data }) = rx.recv() => {
           socket.send_to(&data, to).await?;
         }
         Ok((size, from)) = socket.recv_from(&mut buf) => {
-          if size == 4 {
+          if size == ECHO_PAYLOAD_LEN {
             addr.send(RecvPong {
               from,
               data: buf
@@ -77,12 +78,19 @@ impl Actor for PingActor {
 
 pub struct SendPing {
   to: SocketAddr,
-  data: [u8; 4],
+  data: [u8; ECHO_DATAGRAM_LEN],
 }
 
 struct RecvPong {
   from: SocketAddr,
-  data: [u8; 4],
+  data: [u8; ECHO_PAYLOAD_LEN],
+}
+
+/// An echo service address and the token issued by the controller for it
+#[derive(Debug, Clone)]
+pub struct PingTarget {
+  pub address: SocketAddr,
+  pub token: PingToken,
 }
 
 impl Message for RecvPong {
@@ -103,7 +111,7 @@ impl Handler<RecvPong> for PingActor {
 }
 
 pub struct UpdateAddresses {
-  pub addresses: Vec<SocketAddr>,
+  pub targets: Vec<PingTarget>,
 }
 
 impl Message for UpdateAddresses {
@@ -115,24 +123,24 @@ impl Handler<UpdateAddresses> for PingActor {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    UpdateAddresses { addresses }: UpdateAddresses,
+    UpdateAddresses { targets }: UpdateAddresses,
   ) -> <UpdateAddresses as Message>::Result {
-    let add_keys: Vec<_> = addresses
-      .iter()
-      .filter(|addr| !self.map.contains_key(addr))
-      .cloned()
-      .collect();
     let remove_keys: Vec<_> = self
       .map
       .keys()
-      .filter(|addr| !addresses.contains(addr))
+      .filter(|addr| !targets.iter().any(|t| t.address == **addr))
       .cloned()
       .collect();
-    for addr in add_keys {
-      tracing::debug!("add addr: {}", addr);
-      self
-        .map
-        .insert(addr, PingCollectActor::new(self.tx.clone(), addr).start());
+    for PingTarget { address, token } in targets {
+      if let Some(v) = self.map.get_mut(&address) {
+        v.notify(SetToken { token }).await.ok();
+      } else {
+        tracing::debug!("add addr: {}", address);
+        self.map.insert(
+          address,
+          PingCollectActor::new(self.tx.clone(), address, token).start(),
+        );
+      }
     }
 
     for addr in remove_keys {
@@ -144,6 +152,7 @@ impl Handler<UpdateAddresses> for PingActor {
 
 pub struct AddAddress {
   pub address: SocketAddr,
+  pub token: PingToken,
 }
 
 impl Message for AddAddress {
@@ -155,12 +164,12 @@ impl Handler<AddAddress> for PingActor {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    AddAddress { address }: AddAddress,
+    AddAddress { address, token }: AddAddress,
   ) -> <AddAddress as Message>::Result {
     tracing::debug!("add addr: {}", address);
     self.map.insert(
       address,
-      PingCollectActor::new(self.tx.clone(), address).start(),
+      PingCollectActor::new(self.tx.clone(), address, token).start(),
     );
   }
 }
@@ -289,7 +298,7 @@ pub struct PingUpdate {
 async fn test_ping() {
   use tokio::time::sleep;
 
-  let addresses: Vec<SocketAddr> = (&[
+  let targets: Vec<PingTarget> = (&[
     "127.0.0.1",
     "139.162.28.191",
     "45.79.236.67",
@@ -299,11 +308,14 @@ async fn test_ping() {
     "96.126.100.210",
   ])
     .into_iter()
-    .map(|v| format!("{}:3552", v).parse::<SocketAddr>().unwrap())
+    .map(|v| PingTarget {
+      address: format!("{}:3552", v).parse::<SocketAddr>().unwrap(),
+      token: PingToken::issue(""),
+    })
     .collect();
 
   let actor = PingActor::new().start();
-  actor.send(UpdateAddresses { addresses }).await.unwrap();
+  actor.send(UpdateAddresses { targets }).await.unwrap();
 
   for i in 0..10 {
     sleep(std::time::Duration::from_secs(2)).await;
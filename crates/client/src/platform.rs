@@ -2,10 +2,10 @@ use crate::error::{Error, Result};
 use crate::StartConfig;
 use flo_config::ClientConfig;
 use flo_platform::error::Error as PlatformError;
-use flo_platform::ClientPlatformInfo;
+use flo_platform::{ClientPlatformInfo, RunningWar3};
 use flo_state::{async_trait, Actor, Context, Handler, Message, RegistryRef, Service};
-use flo_types::game::{MapDetail, MapForceOwned, MapPlayerOwned};
-use flo_w3map::{MapChecksum, W3Map};
+use flo_types::game::{MapDetail, MapForceOwned, MapPlayerOwned, MapScriptFinding};
+use flo_w3map::{MapChecksum, MapProtected, OpenedMap, W3Map};
 use flo_w3storage::W3Storage;
 use futures::future::{abortable, AbortHandle};
 use futures::FutureExt;
@@ -216,7 +216,18 @@ impl Handler<GetMapDetail> for Platform {
     use flo_w3map::W3Map;
     self
       .with_storage(move |storage| {
-        let (map, checksum) = W3Map::open_storage_with_checksum(storage, &path)?;
+        let (map, checksum) = W3Map::open_storage_with_checksum_recover(storage, &path)?;
+        let script_findings = map_script_findings(storage, &path);
+        let map = match map {
+          OpenedMap::Full(map) => map,
+          OpenedMap::Protected(map) => {
+            tracing::warn!("map `{}` is protected: {}", path, map.error);
+            return Ok(MapDetail {
+              script_findings,
+              ..protected_map_detail(path, &checksum, &map)
+            });
+          }
+        };
         let (width, height) = map.dimension();
         Ok(MapDetail {
           path,
@@ -249,12 +260,89 @@ impl Handler<GetMapDetail> for Platform {
               player_set: f.player_set,
             })
             .collect(),
+          fixed_teams: map.has_fixed_teams(),
+          protected: false,
+          script_findings,
         })
       })
       .await
   }
 }
 
+/// Map info player type of human players
+const MAP_PLAYER_TYPE_HUMAN: u32 = 1;
+
+/// The players and forces are unknown, only the player count of the header is.
+/// The map is treated as a free for all of that many human players with selectable races
+/// so games can be created with it.
+fn protected_map_detail(path: String, checksum: &MapChecksum, map: &MapProtected) -> MapDetail {
+  let num_players = map.num_players();
+  let players = (0..num_players)
+    .map(|id| MapPlayerOwned {
+      name: format!("Player {}", id + 1),
+      r#type: MAP_PLAYER_TYPE_HUMAN,
+      race: 0,
+      flags: 0,
+    })
+    .collect();
+  let forces = if num_players > 0 {
+    vec![MapForceOwned {
+      name: "Force 1".to_string(),
+      flags: 0,
+      player_set: ((1u64 << num_players.min(32)) - 1) as u32,
+    }]
+  } else {
+    vec![]
+  };
+  MapDetail {
+    path,
+    sha1: checksum.get_sha1_hex_string(),
+    crc32: checksum.crc32,
+    name: map.name().to_string(),
+    author: String::new(),
+    description: String::new(),
+    width: 0,
+    height: 0,
+    preview_jpeg_base64: String::new(),
+    suggested_players: String::new(),
+    num_players,
+    players,
+    forces,
+    fixed_teams: false,
+    protected: true,
+    script_findings: vec![],
+  }
+}
+
+/// Empty if the script can't be read
+fn map_script_findings(storage: &W3Storage, path: &str) -> Vec<MapScriptFinding> {
+  use flo_w3map::ScriptFindingKind;
+  let report = match W3Map::analyze_storage_script(storage, path) {
+    Ok(report) => report,
+    Err(err) => {
+      tracing::warn!("analyze map script `{}`: {}", path, err);
+      return vec![];
+    }
+  };
+  report
+    .findings
+    .iter()
+    .map(|finding| {
+      let (kind, name) = match finding.kind {
+        ScriptFindingKind::CheatPack { signature } => ("cheat_pack", signature),
+        ScriptFindingKind::BannedNative { name } => ("banned_native", name),
+        ScriptFindingKind::FileNative { name } => ("file_native", name),
+      };
+      MapScriptFinding {
+        kind,
+        name,
+        line: finding.line,
+        warning: finding.is_warning(),
+      }
+    })
+    .collect()
+}
+
 #[derive(Debug, Deserialize)]
 pub struct StartTestGame {
   pub name: String,
@@ -372,6 +460,8 @@ async fn load(
         .stats_host
         .clone()
         .unwrap_or_else(|| flo_constants::STATS_HOST.to_string()),
+      classic_graphics: start_config.classic_graphics,
+      lan_port: start_config.lan_port,
       ..Default::default()
     };
 
@@ -391,3 +481,39 @@ async fn load(
     (config, info)
   })
 }
+
+/// Returns `None` if Warcraft III is not running
+pub fn get_running_war3() -> Result<Option<RunningWar3>> {
+  tokio::task::block_in_place(flo_platform::get_running_war3).map_err(Into::into)
+}
+
+#[test]
+fn test_protected_map_detail() {
+  use flo_w3map::{MapFlags, MapHeader};
+
+  let checksum = MapChecksum {
+    xoro: 0,
+    crc32: 0,
+    sha1: [0; 20],
+    file_size: 0,
+  };
+  let map = MapProtected {
+    header: Some(MapHeader {
+      name: "Protected".to_string(),
+      flags: MapFlags::empty(),
+      num_players: 4,
+    }),
+    error: flo_w3map::error::Error::MapScriptNotFound,
+  };
+  let detail = protected_map_detail("maps/protected.w3x".to_string(), &checksum, &map);
+
+  // the controller rejects maps without players in `create`
+  assert!(detail.protected);
+  assert_eq!(detail.players.len(), 4);
+  assert!(detail
+    .players
+    .iter()
+    .all(|p| p.r#type == MAP_PLAYER_TYPE_HUMAN));
+  assert_eq!(detail.forces.len(), 1);
+  assert_eq!(detail.forces[0].player_set, 0b1111);
+}
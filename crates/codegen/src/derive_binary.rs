@@ -10,7 +10,14 @@ use syn::spanned::Spanned;
 #[derive(Debug, FromDeriveInput)]
 #[darling(
   attributes(bin),
-  supports(struct_named, struct_unit, struct_newtype, enum_unit, enum_newtype)
+  supports(
+    struct_named,
+    struct_unit,
+    struct_newtype,
+    enum_unit,
+    enum_newtype,
+    enum_named
+  )
 )]
 pub struct DecodeInputReceiver {
   ident: syn::Ident,
@@ -28,11 +35,18 @@ impl DecodeInputReceiver {
     mod_path: &syn::Path,
     ty: &syn::Type,
     repeat: Option<&MetaExpr>,
+    len_prefix: Option<&MetaPrefix>,
   ) -> TokenStream {
     if get_option_type_arg(ty).is_some() {
       return quote! { 0 };
     }
 
+    if let Some(MetaPrefix { ty: ref prefix_ty }) = len_prefix {
+      return quote! {
+        <#prefix_ty as #mod_path::BinDecode>::MIN_SIZE
+      };
+    }
+
     match *ty {
       syn::Type::Array(syn::TypeArray {
         ref elem, ref len, ..
@@ -58,10 +72,11 @@ impl DecodeInputReceiver {
       ref ty,
       ref repeat,
       ref bitflags,
+      ref len_prefix,
       ..
     }: &FieldReceiver,
   ) -> TokenStream {
-    if get_option_type_arg(ty).is_some() {
+    if get_option_type_arg(ty).is_some() || len_prefix.is_some() {
       return quote! { false };
     }
 
@@ -92,8 +107,11 @@ impl DecodeInputReceiver {
     ty: &syn::Type,
   ) -> TokenStream {
     let FieldReceiver {
+      ref ident,
       ref repeat,
       bitflags,
+      ref len_prefix,
+      ref max,
       ..
     } = field;
 
@@ -137,7 +155,39 @@ impl DecodeInputReceiver {
         }
       }
       _ => {
-        if let Some(ref repeat) = repeat.as_ref() {
+        if let Some(MetaPrefix { ty: ref prefix_ty }) = len_prefix.as_ref() {
+          let check_limit = if let Some(Eq { ref value }) = max {
+            let ident_str = ident.as_ref().map(|v| v.to_string()).unwrap_or_default();
+            quote! {
+              if len > (#value as usize) {
+                return Err(#mod_path::BinDecodeError::limit_exceeded(#value as usize).context(#ident_str));
+              }
+            }
+          } else {
+            quote! {}
+          };
+          let decode_items = if is_type_string(ty) {
+            quote! {
+              #mod_path::BinBufExt::check_size(buf, len)?;
+              let mut bytes = vec![0; len];
+              buf.copy_to_slice(&mut bytes);
+              String::from_utf8(bytes).map_err(|e| {
+                #mod_path::BinDecodeError::failure(format!("can not parse bytes as utf8 string: {}", e))
+              })?
+            }
+          } else {
+            quote! {
+              #mod_path::BinBufExt::get_repeated(buf, len)?
+            }
+          };
+          quote! {
+            {
+              let len = <#prefix_ty as #mod_path::BinDecode>::decode(buf)? as usize;
+              #check_limit
+              #decode_items
+            }
+          }
+        } else if let Some(ref repeat) = repeat.as_ref() {
           let len = &repeat.expr;
           quote! {
             #mod_path::BinBufExt::get_repeated(buf, (#len) as usize)?
@@ -175,12 +225,12 @@ impl DecodeInputReceiver {
     let FieldReceiver {
       ref ident,
       ref ty,
-      ref condition,
       ref repeat,
       ..
     } = field;
+    let condition = field.condition();
     if let Some(opt_ty) = get_option_type_arg(ty) {
-      let expr = if let Some(MetaExpr { ref expr }) = condition {
+      let expr = if let Some(MetaExpr { expr }) = condition {
         quote! { #expr }
       } else {
         return quote::quote_spanned! {
@@ -188,11 +238,11 @@ impl DecodeInputReceiver {
         };
       };
       let decode = self.gen_decode_as_ty(mod_path, field, &opt_ty);
-      let opt_min_size = self.gen_min_size(mod_path, &opt_ty, repeat.as_ref());
+      let opt_min_size = self.gen_min_size(mod_path, &opt_ty, repeat.as_ref(), None);
       return quote! {
         if #expr {
           if #opt_min_size > 0 && buf.remaining()  < #opt_min_size {
-            return Err(#mod_path::BinDecodeError::incomplete());
+            return Err(#mod_path::BinDecodeError::incomplete_needed(#opt_min_size - buf.remaining()));
           }
           Some(#decode)
         } else {
@@ -265,7 +315,7 @@ impl DecodeInputReceiver {
         } else {
           &f.ty
         };
-        self.gen_min_size(&mod_path, ty, f.repeat.as_ref())
+        self.gen_min_size(&mod_path, ty, f.repeat.as_ref(), f.len_prefix.as_ref())
       })
       .collect();
 
@@ -280,7 +330,7 @@ impl DecodeInputReceiver {
         quote! {
           if #items > 0 && !#last_ty_fixed_size {
             if buf.remaining() < #items {
-              return Err(#mod_path::BinDecodeError::incomplete());
+              return Err(#mod_path::BinDecodeError::incomplete_needed(#items - buf.remaining()));
             }
           }
         }
@@ -303,6 +353,19 @@ impl DecodeInputReceiver {
       } else {
         quote! {}
       };
+      let check_limit = if field.len_prefix.is_some() {
+        // checked against the decoded prefix instead
+        quote! {}
+      } else if let Some(Eq { ref value }) = &field.max {
+        let ident_str = ident.as_ref().unwrap().to_string();
+        quote! {
+          if (#ident as usize) > (#value as usize) {
+            return Err(#mod_path::BinDecodeError::limit_exceeded(#value as usize).context(#ident_str));
+          }
+        }
+      } else {
+        quote! {}
+      };
       let decode = self.gen_decode(&mod_path, &field);
       decode_field_items.push(quote! {
         let #ident = {
@@ -311,6 +374,7 @@ impl DecodeInputReceiver {
         };
 
         #check_value
+        #check_limit
       });
     }
 
@@ -327,7 +391,7 @@ impl DecodeInputReceiver {
         const FIXED_SIZE: bool = #fixed_size_and_list;
         fn decode<T: #mod_path::Buf>(buf: &mut T) -> std::result::Result<Self, #mod_path::BinDecodeError> {
           if buf.remaining() < Self::MIN_SIZE {
-            return Err(#mod_path::BinDecodeError::incomplete());
+            return Err(#mod_path::BinDecodeError::incomplete_needed(Self::MIN_SIZE - buf.remaining()));
           }
 
           #(#decode_field_items)*
@@ -358,6 +422,10 @@ impl DecodeInputReceiver {
       syn::parse_quote! { u32 }
     });
 
+    if is_tagged_enum(&variants) {
+      return self.to_tagged_enum_tokens(tokens, &mod_path, &repr, &variants);
+    }
+
     // (value => Enum::Variant),*
     let known_items: Punctuated<TokenStream, syn::Token![,]> = variants
       .iter()
@@ -379,7 +447,7 @@ impl DecodeInputReceiver {
         const FIXED_SIZE: bool = <#repr as #mod_path::BinDecode>::FIXED_SIZE;
         fn decode<T: #mod_path::Buf>(buf: &mut T) -> std::result::Result<Self, #mod_path::BinDecodeError> {
           if buf.remaining() < Self::MIN_SIZE {
-            return Err(#mod_path::BinDecodeError::incomplete());
+            return Err(#mod_path::BinDecodeError::incomplete_needed(Self::MIN_SIZE - buf.remaining()));
           }
 
           Ok(match <#repr as #mod_path::BinDecode>::decode(buf)? {
@@ -401,6 +469,106 @@ impl DecodeInputReceiver {
   }
 }
 
+impl DecodeInputReceiver {
+  // the tag is followed by the variant's fields
+  fn to_tagged_enum_tokens(
+    &self,
+    tokens: &mut TokenStream,
+    mod_path: &syn::Path,
+    repr: &syn::Type,
+    variants: &[&VariantReceiver],
+  ) {
+    let ident = &self.ident;
+    let (imp, ty, wher) = self.generics.split_for_impl();
+
+    let mut unknown_item = None;
+    let mut items: Punctuated<TokenStream, syn::Token![,]> = Punctuated::new();
+    for v in variants {
+      let vident = &v.ident;
+      if vident == "UnknownValue" {
+        unknown_item.replace(quote! {
+          v => #ident::UnknownValue(v)
+        });
+        continue;
+      }
+
+      let value = if let Some(value) = v.value.as_ref() {
+        value
+      } else {
+        items.push(quote::quote_spanned! {
+          vident.span() => compile_error!("#[bin(value = lit)] not specified")
+        });
+        continue;
+      };
+
+      let decode_fields: Vec<TokenStream> = v
+        .fields
+        .iter()
+        .map(|f| {
+          let ty = &f.ty;
+          quote! {
+            {
+              if buf.remaining() < <#ty as #mod_path::BinDecode>::MIN_SIZE {
+                return Err(#mod_path::BinDecodeError::incomplete_needed(
+                  <#ty as #mod_path::BinDecode>::MIN_SIZE - buf.remaining()
+                ));
+              }
+              <#ty as #mod_path::BinDecode>::decode(buf)?
+            }
+          }
+        })
+        .collect();
+
+      let expr = match v.fields.style {
+        ast::Style::Unit => quote! { #ident::#vident },
+        ast::Style::Tuple => quote! { #ident::#vident(#(#decode_fields),*) },
+        ast::Style::Struct => {
+          let fields = v
+            .fields
+            .iter()
+            .zip(decode_fields.iter())
+            .map(|(f, decode)| {
+              let fident = &f.ident;
+              quote! { #fident: #decode }
+            });
+          quote! { #ident::#vident { #(#fields),* } }
+        }
+      };
+
+      items.push(quote::quote_spanned! {
+        vident.span() => #value => #expr
+      });
+    }
+
+    let unknown_item = unknown_item.unwrap_or_else(|| {
+      quote! {
+        v => return Err(#mod_path::BinDecodeError::failure(format!(
+          "unknown `{}` tag: {:?}",
+          stringify!(#ident),
+          v
+        )))
+      }
+    });
+
+    tokens.extend(quote! {
+      impl #imp #mod_path::BinDecode for #ident #ty #wher {
+        const MIN_SIZE: usize = <#repr as #mod_path::BinDecode>::MIN_SIZE;
+        const FIXED_SIZE: bool = false;
+        fn decode<T: #mod_path::Buf>(buf: &mut T) -> std::result::Result<Self, #mod_path::BinDecodeError> {
+          if buf.remaining() < Self::MIN_SIZE {
+            return Err(#mod_path::BinDecodeError::incomplete_needed(Self::MIN_SIZE - buf.remaining()));
+          }
+
+          Ok(match <#repr as #mod_path::BinDecode>::decode(buf)? {
+            #items,
+            #unknown_item,
+          })
+        }
+      }
+    })
+  }
+}
+
 impl ToTokens for DecodeInputReceiver {
   fn to_tokens(&self, tokens: &mut TokenStream) {
     match self.data {
@@ -438,7 +606,14 @@ impl ToTokens for DecodeInputReceiver {
 #[derive(Debug, FromDeriveInput)]
 #[darling(
   attributes(bin),
-  supports(struct_named, struct_unit, struct_newtype, enum_unit, enum_newtype)
+  supports(
+    struct_named,
+    struct_unit,
+    struct_newtype,
+    enum_unit,
+    enum_newtype,
+    enum_named
+  )
 )]
 pub struct EncodeInputReceiver {
   ident: syn::Ident,
@@ -505,12 +680,35 @@ impl EncodeInputReceiver {
     let FieldReceiver {
       ref ident,
       ref ty,
-      ref condition,
       bitflags,
+      ref len_prefix,
       ..
     } = field;
+    let condition = field.condition();
+    if let Some(MetaPrefix { ty: ref prefix_ty }) = len_prefix {
+      // longer values are cut at the prefix maximum, `check_limits` reports them
+      let encode_items = if is_type_string(ty) {
+        quote! {
+          buf.put_slice(&self.#ident.as_bytes()[..(len as usize)]);
+        }
+      } else {
+        quote! {
+          for item in self.#ident.iter().take(len as usize) {
+            #mod_path::BinEncode::encode(item, buf);
+          }
+        }
+      };
+      return quote! {
+        {
+          let len = <#prefix_ty as std::convert::TryFrom<usize>>::try_from(self.#ident.len())
+            .unwrap_or(#prefix_ty::MAX);
+          <#prefix_ty as #mod_path::BinEncode>::encode(&len, buf);
+          #encode_items
+        }
+      };
+    }
     if let Some(opt_ty) = get_option_type_arg(ty) {
-      let expr = if let Some(MetaExpr { ref expr }) = condition {
+      let expr = if let Some(MetaExpr { expr }) = condition {
         let expr = add_self_to_idents(expr);
         quote! { #expr }
       } else {
@@ -587,23 +785,107 @@ impl ToTokens for EncodeInputReceiver {
           })
         } else {
           let mut field_items = Vec::with_capacity(fields.len());
+          let mut check_items = vec![];
 
           for field in fields.iter() {
             let encode = self.gen_encode(&mod_path, &field);
             field_items.push(quote! {
               #encode
             });
+            if let Some(MetaPrefix { ty: ref prefix_ty }) = field.len_prefix {
+              let field_ident = &field.ident;
+              let ident_str = field_ident
+                .as_ref()
+                .map(|v| v.to_string())
+                .unwrap_or_default();
+              check_items.push(quote! {
+                if <#prefix_ty as std::convert::TryFrom<usize>>::try_from(self.#field_ident.len()).is_err() {
+                  return Err(#mod_path::BinDecodeError::limit_exceeded(#prefix_ty::MAX as usize).context(#ident_str));
+                }
+              });
+            }
           }
 
+          let check_limits = if check_items.is_empty() {
+            quote! {}
+          } else {
+            quote! {
+              fn check_limits(&self) -> Result<(), #mod_path::BinDecodeError> {
+                #(#check_items)*
+                Ok(())
+              }
+            }
+          };
+
           tokens.extend(quote! {
             impl #imp #mod_path::BinEncode for #ident #ty #wher {
               fn encode<T: #mod_path::BufMut>(&self, buf: &mut T) {
                 #(#field_items)*
               }
+              #check_limits
             }
           })
         }
       }
+      ast::Data::Enum(variants) if is_tagged_enum(&variants.iter().collect::<Vec<_>>()) => {
+        let repr = enum_repr.as_ref().map(|v| v.ty.clone()).unwrap_or_else(|| {
+          syn::parse_quote! { u32 }
+        });
+
+        let items: Punctuated<TokenStream, syn::Token![,]> = variants
+          .iter()
+          .map(|v| {
+            let vident = &v.ident;
+            if vident == "UnknownValue" {
+              return quote! {
+                #ident::UnknownValue(ref v) => <#repr as #mod_path::BinEncode>::encode(v, buf)
+              };
+            }
+            let value = if let Some(value) = v.value.as_ref() {
+              value
+            } else {
+              return quote::quote_spanned! {
+                vident.span() => compile_error!("#[bin(value = lit)] not specified")
+              };
+            };
+            let bindings: Vec<syn::Ident> = v
+              .fields
+              .iter()
+              .enumerate()
+              .map(|(i, f)| {
+                f.ident
+                  .clone()
+                  .unwrap_or_else(|| quote::format_ident!("_{}", i))
+              })
+              .collect();
+            let encode_fields = v.fields.iter().zip(bindings.iter()).map(|(f, binding)| {
+              let ty = &f.ty;
+              quote! { <#ty as #mod_path::BinEncode>::encode(#binding, buf); }
+            });
+            let pat = match v.fields.style {
+              ast::Style::Unit => quote! { #ident::#vident },
+              ast::Style::Tuple => quote! { #ident::#vident(#(ref #bindings),*) },
+              ast::Style::Struct => quote! { #ident::#vident { #(ref #bindings),* } },
+            };
+            quote! {
+              #pat => {
+                <#repr as #mod_path::BinEncode>::encode(&(#value as #repr), buf);
+                #(#encode_fields)*
+              }
+            }
+          })
+          .collect();
+
+        tokens.extend(quote! {
+          impl #imp #mod_path::BinEncode for #ident #ty #wher {
+            fn encode<T: #mod_path::BufMut>(&self, buf: &mut T) {
+              match *self {
+                #items
+              }
+            }
+          }
+        })
+      }
       ast::Data::Enum(variants) => {
         let repr = enum_repr.as_ref().map(|v| v.ty.clone()).unwrap_or_else(|| {
           syn::parse_quote! { u32 }
@@ -614,7 +896,7 @@ impl ToTokens for EncodeInputReceiver {
           .iter()
           .map(|v| {
             let vident = &v.ident;
-            if vident.to_string() == "UnknownValue" {
+            if vident == "UnknownValue" {
               quote! { #ident::UnknownValue(ref v) => v.clone() }
             } else {
               if let Some(value) = v.value.as_ref() {
@@ -656,21 +938,51 @@ struct FieldReceiver {
   eq: Option<Eq>,
   #[darling(default)]
   condition: Option<MetaExpr>,
+  // if = "flags & 0x4 != 0", alias of `condition`
+  #[darling(default, rename = "if")]
+  if_: Option<MetaExpr>,
   #[darling(default)]
   repeat: Option<MetaExpr>,
   #[darling(default)]
   bitflags: Option<MetaType>,
+  // max = 24, usually put on the length field of a repeated field
+  #[darling(default)]
+  max: Option<Eq>,
+  // len_prefix = "u16", for `Vec<T>` (number of items) and `String` (number of bytes)
+  #[darling(default)]
+  len_prefix: Option<MetaPrefix>,
+}
+
+impl FieldReceiver {
+  fn condition(&self) -> Option<&MetaExpr> {
+    self.condition.as_ref().or(self.if_.as_ref())
+  }
 }
 
 #[derive(Debug, FromVariant)]
 #[darling(attributes(bin))]
 struct VariantReceiver {
   ident: syn::Ident,
+  fields: ast::Fields<VariantFieldReceiver>,
   #[darling(default)]
   value: Option<syn::Lit>,
 }
 
-// eq(0x0)
+#[derive(Debug, FromField)]
+struct VariantFieldReceiver {
+  ident: Option<syn::Ident>,
+  ty: syn::Type,
+}
+
+// enums with any variant other than `UnknownValue` carrying data are
+// decoded as a tag followed by the variant's fields
+fn is_tagged_enum(variants: &[&VariantReceiver]) -> bool {
+  variants
+    .iter()
+    .any(|v| v.ident.to_string() != "UnknownValue" && !v.fields.is_unit())
+}
+
+// eq = 0x0 / max = 24
 #[derive(Debug)]
 struct Eq {
   value: syn::Lit,
@@ -707,6 +1019,18 @@ impl FromMeta for MetaType {
   }
 }
 
+#[derive(Debug)]
+struct MetaPrefix {
+  ty: syn::Type,
+}
+
+impl FromMeta for MetaPrefix {
+  fn from_string(value: &str) -> Result<Self, Error> {
+    let ty = syn::parse_str(value).map_err(|e| Error::custom(format!("invalid type: {}", e)))?;
+    Ok(MetaPrefix { ty })
+  }
+}
+
 #[derive(Debug)]
 struct MetaExpr {
   expr: syn::Expr,
@@ -724,6 +1048,10 @@ fn is_type_likely_u8(ty: &syn::Type) -> bool {
   ty == &syn::parse_quote! { u8 }
 }
 
+fn is_type_string(ty: &syn::Type) -> bool {
+  ty == &syn::parse_quote! { String }
+}
+
 fn get_option_type_arg(ty: &syn::Type) -> Option<syn::Type> {
   if let syn::Type::Path(syn::TypePath {
     qself: None,
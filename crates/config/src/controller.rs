@@ -0,0 +1,430 @@
+use crate::error::*;
+use crate::service::{check, ServiceConfig};
+use flo_constants::version::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerConfig {
+  pub socket_port: u16,
+  pub grpc_port: u16,
+  pub database_url: String,
+  /// Read-only replica for heavy read queries, the primary is used if not set
+  pub database_read_url: Option<String>,
+  /// Connections of the async pool used by the queries ported off the blocking executor,
+  /// 0 (the default) runs them on the blocking executor. The async pool doesn't use TLS.
+  pub database_async_pool_size: usize,
+  pub jwt_secret_base64: String,
+  /// Ping of new lobby connections, can change at runtime
+  pub client_ping_interval_ms: u64,
+  pub client_ping_timeout_ms: u64,
+  /// Clients older than this are warned until `client_version_enforce_at`,
+  /// then rejected, can change at runtime
+  pub client_min_version: Option<String>,
+  /// Unix timestamp in seconds, clients are rejected right away if not set
+  pub client_version_enforce_at: Option<u64>,
+  /// Latest client downloads keyed by platform: `windows`, `macos` or `linux`
+  pub client_download_urls: BTreeMap<String, String>,
+  /// Client features rolled out from the lobby, sent to clients at connect,
+  /// can change at runtime
+  pub feature_flags: BTreeMap<String, FeatureFlag>,
+  /// The Discord bot is disabled if not set
+  pub discord: Option<DiscordConfig>,
+  /// Other lobbies sharing their public games with this one, can change at runtime
+  pub federation: Option<FederationConfig>,
+  /// Name of the games created without one, with the `{map}`, `{host}` and `{seq}`
+  /// placeholders, can change at runtime
+  pub game_name_template: String,
+  /// Checks the text set by players, can change at runtime
+  pub text_filter: TextFilterConfig,
+  /// Connections kept to each node, requests are spread across them.
+  /// Must not exceed `max_controller_connections` of the nodes.
+  /// Nodes older than the connection pool keep only the latest connection,
+  /// set it to 1 until every node is updated.
+  pub node_connections: usize,
+  /// Shares the sessions of the connected players with the other lobby instances
+  /// using this Redis server
+  pub redis_url: Option<String>,
+}
+
+/// Channels can change at runtime, the bot token requires a restart
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscordConfig {
+  pub bot_token: String,
+  /// Channels where public games are announced when created
+  pub announce_channel_ids: Vec<String>,
+  /// Channels where results are posted when games end
+  pub result_channel_ids: Vec<String>,
+  /// Channels where the bot answers commands
+  pub command_channel_ids: Vec<String>,
+  pub command_prefix: String,
+}
+
+impl Default for DiscordConfig {
+  fn default() -> Self {
+    DiscordConfig {
+      bot_token: String::new(),
+      announce_channel_ids: vec![],
+      result_channel_ids: vec![],
+      command_channel_ids: vec![],
+      command_prefix: "!flo".to_string(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextFilterConfig {
+  /// Case insensitive whole words, in addition to the built-in list
+  pub blocked_words: Vec<String>,
+  /// Regular expressions matched against the whole text, invalid ones are ignored
+  pub blocked_patterns: Vec<String>,
+  /// An external filter implementing the `flo_text_filter.TextFilter` service,
+  /// asked after the text passed the word list
+  pub grpc_url: Option<String>,
+  /// The text is allowed if the external filter doesn't reply in time
+  pub grpc_timeout_ms: u64,
+}
+
+impl Default for TextFilterConfig {
+  fn default() -> Self {
+    TextFilterConfig {
+      blocked_words: vec![],
+      blocked_patterns: vec![],
+      grpc_url: None,
+      grpc_timeout_ms: 500,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FederationConfig {
+  /// Identifies this lobby to the peers
+  pub lobby_id: String,
+  pub peers: Vec<FederationPeer>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FederationPeer {
+  /// The `federation.lobby_id` of the peer
+  pub lobby_id: String,
+  /// Base URL of the peer's HTTP API
+  pub url: String,
+  /// Lobby host of the peer that players connect to when they join its games
+  pub host: String,
+  /// Shared with the peer, signs the requests in both directions
+  pub secret: String,
+  /// Api client that owns the players of the peer who join local games
+  pub api_client_id: i32,
+}
+
+impl FederationConfig {
+  pub fn peer(&self, lobby_id: &str) -> Option<&FederationPeer> {
+    self.peers.iter().find(|peer| peer.lobby_id == lobby_id)
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeatureFlag {
+  /// Share of the players with the flag enabled, between 0 and 100
+  pub rollout_percent: u8,
+  /// Players with the flag enabled regardless of the rollout
+  pub players: Vec<i32>,
+}
+
+impl FeatureFlag {
+  /// A player stays in or out of the rollout as long as the percentage doesn't decrease
+  pub fn is_enabled(&self, name: &str, player_id: i32) -> bool {
+    self.players.contains(&player_id) || rollout_bucket(name, player_id) < self.rollout_percent
+  }
+}
+
+// FNV-1a, stable across builds unlike the std hasher
+fn rollout_bucket(name: &str, player_id: i32) -> u8 {
+  let hash = name
+    .bytes()
+    .chain(player_id.to_le_bytes().iter().cloned())
+    .fold(0xcbf29ce484222325_u64, |hash, byte| {
+      (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+  (hash % 100) as u8
+}
+
+impl Default for ControllerConfig {
+  fn default() -> Self {
+    ControllerConfig {
+      socket_port: flo_constants::CONTROLLER_SOCKET_PORT,
+      grpc_port: flo_constants::CONTROLLER_GRPC_PORT,
+      database_url: String::new(),
+      database_read_url: None,
+      database_async_pool_size: 0,
+      jwt_secret_base64: String::new(),
+      client_ping_interval_ms: 30000,
+      client_ping_timeout_ms: 5000,
+      client_min_version: None,
+      client_version_enforce_at: None,
+      client_download_urls: BTreeMap::new(),
+      feature_flags: BTreeMap::new(),
+      discord: None,
+      federation: None,
+      game_name_template: "{map} #{seq}".to_string(),
+      text_filter: TextFilterConfig::default(),
+      node_connections: 4,
+      redis_url: None,
+    }
+  }
+}
+
+impl ControllerConfig {
+  pub fn client_ping_interval(&self) -> Duration {
+    Duration::from_millis(self.client_ping_interval_ms)
+  }
+
+  pub fn client_ping_timeout(&self) -> Duration {
+    Duration::from_millis(self.client_ping_timeout_ms)
+  }
+
+  /// Never lower than `flo_constants::MIN_FLO_VERSION`
+  pub fn client_min_version(&self) -> Version {
+    self
+      .client_min_version
+      .as_deref()
+      .and_then(Version::try_parse)
+      .filter(|v| *v > flo_constants::MIN_FLO_VERSION)
+      .unwrap_or(flo_constants::MIN_FLO_VERSION)
+  }
+
+  /// Names of the feature flags enabled for the player
+  pub fn feature_flags_for(&self, player_id: i32) -> Vec<String> {
+    self
+      .feature_flags
+      .iter()
+      .filter(|(name, flag)| flag.is_enabled(name, player_id))
+      .map(|(name, _)| name.clone())
+      .collect()
+  }
+}
+
+impl ServiceConfig for ControllerConfig {
+  const PATH_ENV: &'static str = "FLO_CONTROLLER_CONFIG";
+  const DEFAULT_PATH: &'static str = "flo-controller.toml";
+
+  fn apply_env(&mut self) {
+    if let Ok(value) = std::env::var("DATABASE_URL") {
+      self.database_url = value;
+    }
+
+    if let Ok(value) = std::env::var("DATABASE_READ_URL") {
+      self.database_read_url = Some(value).filter(|v| !v.is_empty());
+    }
+
+    if let Ok(value) = std::env::var("JWT_SECRET_BASE64") {
+      self.jwt_secret_base64 = value;
+    }
+
+    if let Ok(value) = std::env::var("REDIS_URL") {
+      self.redis_url = Some(value).filter(|v| !v.is_empty());
+    }
+
+    if let Ok(value) = std::env::var("FLO_DISCORD_BOT_TOKEN") {
+      self.discord.get_or_insert_with(Default::default).bot_token = value;
+    }
+  }
+
+  fn validate(&self) -> Result<()> {
+    check(
+      !self.database_url.is_empty(),
+      "`database_url` or env `DATABASE_URL` is required",
+    )?;
+    check(
+      !self.jwt_secret_base64.is_empty(),
+      "`jwt_secret_base64` or env `JWT_SECRET_BASE64` is required",
+    )?;
+    check(
+      self.client_ping_interval_ms > 0,
+      "`client_ping_interval_ms` must be positive",
+    )?;
+    check(
+      self.client_ping_timeout_ms > 0,
+      "`client_ping_timeout_ms` must be positive",
+    )?;
+    check(
+      self
+        .client_min_version
+        .as_deref()
+        .map(|v| Version::try_parse(v).is_some())
+        .unwrap_or(true),
+      "`client_min_version` must be in the `major.minor.patch` format",
+    )?;
+    check(
+      self.node_connections > 0,
+      "`node_connections` must be positive",
+    )?;
+    check(
+      !self.game_name_template.trim().is_empty(),
+      "`game_name_template` must not be empty",
+    )?;
+    check(
+      self
+        .text_filter
+        .grpc_url
+        .as_ref()
+        .map(|url| !url.is_empty() && self.text_filter.grpc_timeout_ms > 0)
+        .unwrap_or(true),
+      "`text_filter.grpc_url` must not be empty and `text_filter.grpc_timeout_ms` must be positive",
+    )?;
+    check(
+      self
+        .feature_flags
+        .values()
+        .all(|flag| flag.rollout_percent <= 100),
+      "`feature_flags.*.rollout_percent` must be between 0 and 100",
+    )?;
+    check(
+      self
+        .discord
+        .as_ref()
+        .map(|discord| !discord.bot_token.is_empty() && !discord.command_prefix.is_empty())
+        .unwrap_or(true),
+      "`discord.bot_token` or env `FLO_DISCORD_BOT_TOKEN`, and `discord.command_prefix` are required",
+    )?;
+    check(
+      self
+        .federation
+        .as_ref()
+        .map(|federation| {
+          !federation.lobby_id.is_empty()
+            && federation.peers.iter().enumerate().all(|(i, peer)| {
+              !peer.lobby_id.is_empty()
+                && peer.lobby_id != federation.lobby_id
+                && !peer.url.is_empty()
+                && !peer.secret.is_empty()
+                && federation.peers[..i]
+                  .iter()
+                  .all(|other| other.lobby_id != peer.lobby_id)
+            })
+        })
+        .unwrap_or(true),
+      "`federation.lobby_id` is required, `federation.peers` need a unique `lobby_id`, a `url` and a `secret`",
+    )
+  }
+
+  fn apply_reload(&mut self, next: Self) -> Vec<&'static str> {
+    let mut restart_required = vec![];
+    if next.socket_port != self.socket_port {
+      restart_required.push("socket_port");
+    }
+    if next.grpc_port != self.grpc_port {
+      restart_required.push("grpc_port");
+    }
+    if next.database_url != self.database_url {
+      restart_required.push("database_url");
+    }
+    if next.database_read_url != self.database_read_url {
+      restart_required.push("database_read_url");
+    }
+    if next.database_async_pool_size != self.database_async_pool_size {
+      restart_required.push("database_async_pool_size");
+    }
+    if next.jwt_secret_base64 != self.jwt_secret_base64 {
+      restart_required.push("jwt_secret_base64");
+    }
+    if next.node_connections != self.node_connections {
+      restart_required.push("node_connections");
+    }
+    if next.redis_url != self.redis_url {
+      restart_required.push("redis_url");
+    }
+    self.client_ping_interval_ms = next.client_ping_interval_ms;
+    self.client_ping_timeout_ms = next.client_ping_timeout_ms;
+    self.client_min_version = next.client_min_version;
+    self.client_version_enforce_at = next.client_version_enforce_at;
+    self.client_download_urls = next.client_download_urls;
+    self.feature_flags = next.feature_flags;
+    self.federation = next.federation;
+    let bot_token = |config: &Self| {
+      config
+        .discord
+        .as_ref()
+        .map(|discord| discord.bot_token.clone())
+    };
+    if bot_token(&next) != bot_token(self) {
+      restart_required.push("discord.bot_token");
+    } else {
+      self.discord = next.discord;
+    }
+    restart_required
+  }
+}
+
+#[test]
+fn test_federation_config() {
+  let config: ControllerConfig = toml::from_str(
+    r#"
+    database_url = "postgres://localhost/flo"
+    jwt_secret_base64 = "c2VjcmV0"
+
+    [federation]
+    lobby_id = "eu"
+
+    [[federation.peers]]
+    lobby_id = "na"
+    url = "https://na.example.com"
+    host = "na.example.com"
+    secret = "shared"
+    api_client_id = 2
+    "#,
+  )
+  .unwrap();
+  assert!(config.validate().is_ok());
+  let federation = config.federation.as_ref().unwrap();
+  assert_eq!(federation.peer("na").unwrap().api_client_id, 2);
+  assert!(federation.peer("eu").is_none());
+
+  let mut duplicated = config.clone();
+  let peers = &mut duplicated.federation.as_mut().unwrap().peers;
+  peers.push(peers[0].clone());
+  assert!(duplicated.validate().is_err());
+}
+
+#[test]
+fn test_feature_flags() {
+  let config: ControllerConfig = toml::from_str(
+    r#"
+    [feature_flags.everyone]
+    rollout_percent = 100
+
+    [feature_flags.nobody]
+
+    [feature_flags.testers]
+    players = [1, 2]
+
+    [feature_flags.half]
+    rollout_percent = 50
+    "#,
+  )
+  .unwrap();
+  assert_eq!(
+    config.feature_flags_for(1),
+    vec!["everyone", "half", "testers"]
+  );
+  assert_eq!(config.feature_flags_for(3), vec!["everyone"]);
+
+  let half = &config.feature_flags["half"];
+  let enabled = (0..1000).filter(|id| half.is_enabled("half", *id)).count();
+  assert!(enabled > 400 && enabled < 600);
+  // raising the percentage keeps the players already in the rollout
+  let more = FeatureFlag {
+    rollout_percent: 60,
+    ..half.clone()
+  };
+  assert!((0..1000)
+    .filter(|id| half.is_enabled("half", *id))
+    .all(|id| more.is_enabled("half", id)));
+}
@@ -13,6 +13,9 @@ pub enum Error {
 
   #[error("toml deserialize: {0}")]
   TomlDe(#[from] toml::de::Error),
+
+  #[error("invalid config: {0}")]
+  Invalid(String),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
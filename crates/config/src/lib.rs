@@ -2,9 +2,16 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
+mod controller;
 pub mod error;
+mod node;
+pub mod service;
 
+pub use controller::{
+  ControllerConfig, DiscordConfig, FederationConfig, FederationPeer, TextFilterConfig,
+};
 use error::*;
+pub use node::NodeConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
@@ -13,6 +20,11 @@ pub struct ClientConfig {
   pub installation_path: Option<PathBuf>,
   pub controller_host: String,
   pub stats_host: String,
+  /// Warcraft III runs with classic graphics, reported to the lobby when a game starts.
+  /// Reported as unknown if not set
+  pub classic_graphics: Option<bool>,
+  /// Port of the LAN game listener, a random port is used if unset or unavailable
+  pub lan_port: Option<u16>,
 }
 
 impl Default for ClientConfig {
@@ -23,6 +35,8 @@ impl Default for ClientConfig {
       installation_path: None,
       controller_host: flo_constants::CONTROLLER_HOST.to_string(),
       stats_host: flo_constants::STATS_HOST.to_string(),
+      classic_graphics: None,
+      lan_port: None,
     }
   }
 }
@@ -44,6 +58,8 @@ impl ClientConfig {
       pub installation_path: Option<PathBuf>,
       pub controller_host: Option<String>,
       pub stats_host: Option<String>,
+      pub classic_graphics: Option<bool>,
+      pub lan_port: Option<u16>,
     }
 
     let config: TomlConfig = toml::from_str(&fs::read_to_string("flo.toml")?)?;
@@ -57,6 +73,8 @@ impl ClientConfig {
       stats_host: config
         .stats_host
         .unwrap_or_else(|| flo_constants::STATS_HOST.to_string()),
+      classic_graphics: config.classic_graphics,
+      lan_port: config.lan_port,
     };
 
     config.apply_env();
@@ -94,5 +112,17 @@ impl ClientConfig {
     if let Ok(domain) = env::var("FLO_STATS_HOST") {
       self.stats_host = domain;
     }
+
+    if let Ok(Some(value)) = env::var("FLO_CLASSIC_GRAPHICS")
+      .ok()
+      .map(|v| v.parse())
+      .transpose()
+    {
+      self.classic_graphics = Some(value);
+    }
+
+    if let Ok(Some(port)) = env::var("FLO_LAN_PORT").ok().map(|v| v.parse()).transpose() {
+      self.lan_port = Some(port);
+    }
   }
 }
@@ -0,0 +1,136 @@
+use crate::error::*;
+use crate::service::{check, env_parse, ServiceConfig};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+  pub client_port: u16,
+  pub controller_port: u16,
+  /// Shared with the lobby, empty if not set
+  pub secret_key: String,
+  /// Games hosted at the same time, can change at runtime
+  pub max_games: Option<usize>,
+  /// Step of new games, can change at runtime
+  pub game_step_ms: u16,
+  /// Ping of new player connections, can change at runtime
+  pub game_ping_interval_ms: u64,
+  pub game_ping_timeout_ms: u64,
+  /// Connections accepted from the lobby, the oldest is closed when a new one exceeds it,
+  /// can change at runtime
+  pub max_controller_connections: usize,
+  /// Echoes datagrams of the legacy format without a ping token, can change at runtime
+  pub echo_legacy_datagrams: bool,
+}
+
+impl Default for NodeConfig {
+  fn default() -> Self {
+    NodeConfig {
+      client_port: flo_constants::NODE_CLIENT_PORT,
+      controller_port: flo_constants::NODE_CONTROLLER_PORT,
+      secret_key: String::new(),
+      max_games: None,
+      game_step_ms: 30,
+      game_ping_interval_ms: 1000,
+      game_ping_timeout_ms: 5000,
+      max_controller_connections: 8,
+      echo_legacy_datagrams: false,
+    }
+  }
+}
+
+impl NodeConfig {
+  pub fn game_ping_interval(&self) -> Duration {
+    Duration::from_millis(self.game_ping_interval_ms)
+  }
+
+  pub fn game_ping_timeout(&self) -> Duration {
+    Duration::from_millis(self.game_ping_timeout_ms)
+  }
+}
+
+impl ServiceConfig for NodeConfig {
+  const PATH_ENV: &'static str = "FLO_NODE_CONFIG";
+  const DEFAULT_PATH: &'static str = "flo-node.toml";
+
+  fn apply_env(&mut self) {
+    if let Ok(value) = std::env::var("FLO_NODE_SECRET") {
+      self.secret_key = value;
+    }
+
+    if let Some(value) = env_parse("FLO_NODE_MAX_GAMES") {
+      self.max_games = Some(value);
+    }
+
+    if let Some(value) = env_parse("FLO_GAME_STEP_MS") {
+      self.game_step_ms = value;
+    }
+  }
+
+  fn validate(&self) -> Result<()> {
+    check(self.max_games != Some(0), "`max_games` must be positive")?;
+    check(self.game_step_ms > 0, "`game_step_ms` must be positive")?;
+    check(
+      self.max_controller_connections > 0,
+      "`max_controller_connections` must be positive",
+    )?;
+    check(
+      self.game_ping_interval_ms > 0,
+      "`game_ping_interval_ms` must be positive",
+    )?;
+    check(
+      self.game_ping_timeout_ms > self.game_ping_interval_ms,
+      "`game_ping_timeout_ms` must be greater than `game_ping_interval_ms`",
+    )
+  }
+
+  fn apply_reload(&mut self, next: Self) -> Vec<&'static str> {
+    let mut restart_required = vec![];
+    if next.client_port != self.client_port {
+      restart_required.push("client_port");
+    }
+    if next.controller_port != self.controller_port {
+      restart_required.push("controller_port");
+    }
+    if next.secret_key != self.secret_key {
+      restart_required.push("secret_key");
+    }
+    self.max_games = next.max_games;
+    self.game_step_ms = next.game_step_ms;
+    self.game_ping_interval_ms = next.game_ping_interval_ms;
+    self.game_ping_timeout_ms = next.game_ping_timeout_ms;
+    self.max_controller_connections = next.max_controller_connections;
+    self.echo_legacy_datagrams = next.echo_legacy_datagrams;
+    restart_required
+  }
+}
+
+#[test]
+fn test_node_config() {
+  let mut config: NodeConfig = toml::from_str(
+    r#"
+    secret_key = "secret"
+    max_games = 10
+    "#,
+  )
+  .unwrap();
+  config.validate().unwrap();
+  assert_eq!(config.client_port, flo_constants::NODE_CLIENT_PORT);
+  assert_eq!(config.max_games, Some(10));
+
+  let restart_required = config.apply_reload(NodeConfig {
+    secret_key: "changed".to_string(),
+    max_games: Some(20),
+    ..NodeConfig::default()
+  });
+  assert_eq!(restart_required, vec!["secret_key"]);
+  assert_eq!(config.secret_key, "secret");
+  assert_eq!(config.max_games, Some(20));
+
+  config.game_ping_timeout_ms = config.game_ping_interval_ms;
+  assert!(config.validate().is_err());
+
+  // deployments without `FLO_NODE_SECRET`
+  assert!(NodeConfig::default().validate().is_ok());
+}
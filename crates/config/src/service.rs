@@ -0,0 +1,149 @@
+//! Config files of the lobby and node services.
+//!
+//! A file is loaded from the path in `ServiceConfig::PATH_ENV`, then overridden by env variables.
+//! `watch` reloads it on SIGHUP or when the file changes, values that can't change at runtime
+//! are kept until the next restart.
+
+use crate::error::*;
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type ConfigReceiver<T> = watch::Receiver<Arc<T>>;
+
+pub trait ServiceConfig: Default + DeserializeOwned + Clone + Send + Sync + 'static {
+  /// Env variable of the config file path
+  const PATH_ENV: &'static str;
+  const DEFAULT_PATH: &'static str;
+
+  fn apply_env(&mut self);
+
+  fn validate(&self) -> Result<()>;
+
+  /// Applies the values of `next` that can change at runtime,
+  /// returns the names of the changed values that require a restart
+  fn apply_reload(&mut self, next: Self) -> Vec<&'static str>;
+
+  fn path() -> PathBuf {
+    std::env::var(Self::PATH_ENV)
+      .map(PathBuf::from)
+      .unwrap_or_else(|_| PathBuf::from(Self::DEFAULT_PATH))
+  }
+
+  /// A missing file is the same as an empty one
+  fn load_from(path: &Path) -> Result<Self> {
+    let mut config: Self = match fs::read_to_string(path) {
+      Ok(content) => toml::from_str(&content)?,
+      Err(err) if err.kind() == ErrorKind::NotFound => Self::default(),
+      Err(err) => return Err(err.into()),
+    };
+    config.apply_env();
+    config.validate()?;
+    Ok(config)
+  }
+
+  fn load() -> Result<Self> {
+    Self::load_from(&Self::path())
+  }
+}
+
+/// Loads the config and keeps reloading it until every receiver is dropped
+pub fn watch<T: ServiceConfig>() -> Result<ConfigReceiver<T>> {
+  let path = T::path();
+  let (tx, rx) = watch::channel(Arc::new(T::load_from(&path)?));
+
+  tokio::spawn(async move {
+    let mut modified = modified_time(&path);
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    let mut hangup = Hangup::new();
+    loop {
+      tokio::select! {
+        _ = tx.closed() => break,
+        _ = interval.tick() => {
+          let value = modified_time(&path);
+          if value == modified {
+            continue;
+          }
+          modified = value;
+        }
+        _ = hangup.recv() => {}
+      }
+      reload(&path, &tx);
+    }
+  });
+
+  Ok(rx)
+}
+
+fn reload<T: ServiceConfig>(path: &Path, tx: &watch::Sender<Arc<T>>) {
+  let next = match T::load_from(path) {
+    Ok(next) => next,
+    Err(err) => {
+      tracing::error!("reload config `{}`: {}", path.display(), err);
+      return;
+    }
+  };
+  let mut config = T::clone(&tx.borrow());
+  let restart_required = config.apply_reload(next);
+  if !restart_required.is_empty() {
+    tracing::warn!(
+      "config changes require a restart: {}",
+      restart_required.join(", ")
+    );
+  }
+  tracing::info!("config reloaded: {}", path.display());
+  tx.send(Arc::new(config)).ok();
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+struct Hangup {
+  #[cfg(unix)]
+  signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangup {
+  fn new() -> Self {
+    Hangup {
+      #[cfg(unix)]
+      signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .map_err(|err| tracing::warn!("listen SIGHUP: {}", err))
+        .ok(),
+    }
+  }
+
+  #[cfg(unix)]
+  async fn recv(&mut self) {
+    match self.signal.as_mut() {
+      Some(signal) => {
+        signal.recv().await;
+      }
+      None => std::future::pending().await,
+    }
+  }
+
+  #[cfg(not(unix))]
+  async fn recv(&mut self) {
+    std::future::pending().await
+  }
+}
+
+pub(crate) fn check(valid: bool, message: &str) -> Result<()> {
+  if valid {
+    Ok(())
+  } else {
+    Err(Error::Invalid(message.to_string()))
+  }
+}
+
+pub(crate) fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+  std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
@@ -9,13 +9,22 @@ pub struct Version {
 
 impl Version {
   pub fn parse(v: &'static str) -> Self {
-    let parts: Vec<i32> = v.split('.').map(|v| v.parse::<i32>().unwrap()).collect();
-    assert_eq!(parts.len(), 3);
-    Version {
+    Self::try_parse(v).unwrap()
+  }
+
+  pub fn try_parse(v: &str) -> Option<Self> {
+    let parts: Vec<i32> = v
+      .split('.')
+      .map(|v| v.parse::<i32>().ok())
+      .collect::<Option<_>>()?;
+    if parts.len() != 3 {
+      return None;
+    }
+    Some(Version {
       major: parts[0],
       minor: parts[1],
       patch: parts[2],
-    }
+    })
   }
 }
 
@@ -24,3 +33,18 @@ impl fmt::Display for Version {
     write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
   }
 }
+
+#[test]
+fn test_parse_version() {
+  assert_eq!(
+    Version::try_parse("0.10.2"),
+    Some(Version {
+      major: 0,
+      minor: 10,
+      patch: 2
+    })
+  );
+  assert_eq!(Version::try_parse("0.10"), None);
+  assert_eq!(Version::try_parse("0.10.x"), None);
+  assert!(Version::parse("0.9.2") < Version::parse("0.10.0"));
+}
@@ -1,5 +1,6 @@
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 
 fn main() {
   let out_dir = std::env::var_os("OUT_DIR").unwrap();
@@ -22,5 +23,61 @@ fn main() {
       version_str = pkg_version
     ),
   )
-    .unwrap()
+    .unwrap();
+
+  // the descriptor set is served by gRPC reflection
+  tonic_build::configure()
+    .file_descriptor_set_path(Path::new(&out_dir).join("flo_controller_descriptor.bin"))
+    .compile(
+      &[
+        "src/proto/admin.proto",
+        "src/proto/template.proto",
+        "src/proto/replay.proto",
+        "src/proto/game_list.proto",
+        "src/proto/map.proto",
+        "src/proto/text_filter.proto",
+        "src/proto/lobby_bus.proto",
+      ],
+      &["src/proto"],
+    )
+    .unwrap();
+
+  // `flo_grpc` doesn't export its descriptors, so they are compiled again only for reflection
+  let flo_grpc_dir = Path::new("../../deps/flo-grpc");
+  println!("cargo:rerun-if-changed={}", flo_grpc_dir.display());
+  let mut protos = vec![];
+  if let Err(err) = find_protos(flo_grpc_dir, &mut protos) {
+    panic!(
+      "read protos in {}, is the submodule checked out? {}",
+      flo_grpc_dir.display(),
+      err
+    );
+  }
+  let mut includes: Vec<_> = protos
+    .iter()
+    .filter_map(|path| path.parent().map(Path::to_path_buf))
+    .collect();
+  includes.sort();
+  includes.dedup();
+  let flo_grpc_out_dir = Path::new(&out_dir).join("flo_grpc");
+  fs::create_dir_all(&flo_grpc_out_dir).unwrap();
+  tonic_build::configure()
+    .build_client(false)
+    .build_server(false)
+    .out_dir(&flo_grpc_out_dir)
+    .file_descriptor_set_path(Path::new(&out_dir).join("flo_grpc_descriptor.bin"))
+    .compile(&protos, &includes)
+    .unwrap();
+}
+
+fn find_protos(dir: &Path, protos: &mut Vec<PathBuf>) -> io::Result<()> {
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      find_protos(&path, protos)?;
+    } else if path.extension().map(|ext| ext == "proto").unwrap_or(false) {
+      protos.push(path);
+    }
+  }
+  Ok(())
 }
@@ -0,0 +1,707 @@
+use crate::audit::db::QueryAuditEventParams;
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
+use crate::catalogue;
+use crate::error::Error;
+use crate::game::state::cancel::ForceCancelGame;
+use crate::game::state::leave::PlayerLeave;
+use crate::game::state::registry::{GetPlayerGames, Remove, RemoveGamePlayer};
+use crate::maintenance::{CancelMaintenance, GetMaintenance, ScheduleMaintenance};
+use crate::motd::{ListMotdItems, MotdItemKind, MotdItemUpdate, RemoveMotdItem, SetMotdItem};
+use crate::node::db::AddNode;
+use crate::node::messages::SetPacketCapture;
+use crate::player::state::conn::Kick;
+use crate::state::{ActorMapExt, ControllerStateRef, Reload};
+use chrono::{NaiveTime, TimeZone, Utc};
+use flo_net::packet::FloPacket;
+use once_cell::sync::Lazy;
+use s2_grpc_utils::S2ProtoEnum;
+use std::env;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+  tonic::include_proto!("flo_admin");
+}
+
+use proto::flo_admin_server::{FloAdmin, FloAdminServer};
+use proto::*;
+
+pub const REQUEST_META_ADMIN_SECRET: &str = "x-flo-admin-secret";
+
+static ADMIN_SECRET: Lazy<Option<String>> = Lazy::new(|| env::var("FLO_ADMIN_SECRET").ok());
+
+pub fn server(
+  state: ControllerStateRef,
+) -> InterceptedService<FloAdminServer<FloAdminService>, AdminInterceptor> {
+  FloAdminServer::with_interceptor(FloAdminService { state }, AdminInterceptor)
+}
+
+/// Rejects every request unless `FLO_ADMIN_SECRET` is set and matches `x-flo-admin-secret`.
+#[derive(Clone)]
+pub struct AdminInterceptor;
+
+impl Interceptor for AdminInterceptor {
+  fn call(&mut self, req: Request<()>) -> Result<Request<()>, Status> {
+    let secret = req
+      .metadata()
+      .get(REQUEST_META_ADMIN_SECRET)
+      .map(|v| v.as_bytes());
+    verify_admin_secret(secret).map_err(Status::unauthenticated)?;
+    Ok(req)
+  }
+}
+
+/// Also used by the admin routes of the http api, with `x-flo-admin-secret` as a header
+pub(crate) fn verify_admin_secret(secret: Option<&[u8]>) -> Result<(), &'static str> {
+  let expected = match ADMIN_SECRET.as_ref() {
+    Some(v) => v,
+    None => return Err("admin api disabled"),
+  };
+  match secret {
+    Some(secret) if constant_time_eq(secret, expected.as_bytes()) => Ok(()),
+    Some(_) => Err("invalid admin secret"),
+    None => Err("`x-flo-admin-secret` metadata was not found"),
+  }
+}
+
+/// Takes the same time for every secret of the expected length
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  if a.len() != b.len() {
+    return false;
+  }
+  a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub struct FloAdminService {
+  state: ControllerStateRef,
+}
+
+#[tonic::async_trait]
+impl FloAdmin for FloAdminService {
+  async fn cancel_game(&self, request: Request<CancelGameRequest>) -> Result<Response<()>, Status> {
+    let game_id = request.into_inner().game_id;
+
+    self.state.games.send_to(game_id, ForceCancelGame).await?;
+
+    tracing::info!(game_id, "shutting down: reason: admin ForceCancelGame");
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::game(AuditEventKind::AdminCancelGame, AuditActor::Admin, game_id),
+    );
+    self
+      .state
+      .games
+      .send(Remove { game_id })
+      .await
+      .map_err(Error::from)?;
+
+    Ok(Response::new(()))
+  }
+
+  async fn disconnect_player(
+    &self,
+    request: Request<DisconnectPlayerRequest>,
+  ) -> Result<Response<()>, Status> {
+    let player_id = request.into_inner().player_id;
+
+    let game_ids = self
+      .state
+      .games
+      .send(GetPlayerGames { player_id })
+      .await
+      .map_err(Error::from)?;
+    for game_id in game_ids {
+      let res = self
+        .state
+        .games
+        .send_to(game_id, PlayerLeave { player_id })
+        .await?;
+      if res.game_ended {
+        tracing::debug!(game_id, "shutting down: reason: admin DisconnectPlayer");
+        self
+          .state
+          .games
+          .send(Remove { game_id })
+          .await
+          .map_err(Error::from)?;
+      } else {
+        self
+          .state
+          .games
+          .send(RemoveGamePlayer { game_id, player_id })
+          .await
+          .map_err(Error::from)?;
+      }
+    }
+
+    self
+      .state
+      .players
+      .send(Kick { player_id })
+      .await
+      .map_err(Error::from)??;
+
+    tracing::info!(player_id, "player disconnected by admin");
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminKickPlayer, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "player_id": player_id })),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn broadcast_notice(
+    &self,
+    request: Request<BroadcastNoticeRequest>,
+  ) -> Result<Response<()>, Status> {
+    let message = request.into_inner().message;
+    if message.is_empty() {
+      return Err(Status::invalid_argument("message is empty"));
+    }
+
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminBroadcastNotice, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "message": message })),
+    );
+
+    let frame = catalogue::NOTICE
+      .with("message", message)
+      .notice()
+      .encode_as_frame()?;
+    self
+      .state
+      .player_packet_sender
+      .broadcast_to_all(frame)
+      .await?;
+
+    Ok(Response::new(()))
+  }
+
+  async fn add_node(
+    &self,
+    request: Request<AddNodeRequest>,
+  ) -> Result<Response<AddNodeReply>, Status> {
+    let req = request.into_inner();
+    if req.name.is_empty() || req.secret.is_empty() {
+      return Err(Status::invalid_argument("name and secret are required"));
+    }
+    if req.ip_addr.parse::<Ipv4Addr>().is_err() && req.ip_addr.parse::<SocketAddrV4>().is_err() {
+      return Err(Error::InvalidNodeAddress(req.ip_addr).into());
+    }
+    if let Some(max_games) = req.max_games {
+      if max_games <= 0 {
+        return Err(Status::invalid_argument("max_games must be positive"));
+      }
+    }
+
+    let data = AddNode {
+      name: req.name,
+      location: req.location,
+      secret: req.secret,
+      ip_addr: req.ip_addr,
+      country_id: req.country_id,
+      region: req.region,
+      max_games: req.max_games,
+    };
+    let node = self
+      .state
+      .db
+      .exec(move |conn| crate::node::db::add_node(conn, &data))
+      .await
+      .map_err(Error::from)?;
+
+    tracing::info!(node_id = node.id, "node added by admin");
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminAddNode, AuditActor::Admin).with_payload(
+        serde_json::json!({
+          "node_id": node.id,
+          "name": node.name,
+          "ip_addr": node.ip_addr,
+          "region": node.region,
+          "max_games": node.max_games,
+        }),
+      ),
+    );
+    self.state.nodes.send(Reload).await.map_err(Error::from)??;
+
+    Ok(Response::new(AddNodeReply { node_id: node.id }))
+  }
+
+  async fn set_node_disabled(
+    &self,
+    request: Request<SetNodeDisabledRequest>,
+  ) -> Result<Response<()>, Status> {
+    let SetNodeDisabledRequest { node_id, disabled } = request.into_inner();
+
+    self
+      .state
+      .db
+      .exec(move |conn| crate::node::db::set_node_disabled(conn, node_id, disabled))
+      .await
+      .map_err(Error::from)?;
+
+    tracing::info!(node_id, disabled, "node disabled flag updated by admin");
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminSetNodeDisabled, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "node_id": node_id, "disabled": disabled })),
+    );
+    self.state.nodes.send(Reload).await.map_err(Error::from)??;
+
+    Ok(Response::new(()))
+  }
+
+  async fn schedule_maintenance(
+    &self,
+    request: Request<ScheduleMaintenanceRequest>,
+  ) -> Result<Response<()>, Status> {
+    let window = request
+      .into_inner()
+      .window
+      .ok_or_else(|| Status::invalid_argument("window is required"))?;
+    let timestamp = |secs: i64| {
+      Utc
+        .timestamp_opt(secs, 0)
+        .single()
+        .ok_or_else(|| Status::invalid_argument("invalid timestamp"))
+    };
+    let window = crate::maintenance::MaintenanceWindow {
+      starts_at: timestamp(window.starts_at)?,
+      ends_at: timestamp(window.ends_at)?,
+      message: window.message,
+      drain_nodes: window.drain_nodes,
+    };
+
+    let payload = serde_json::to_value(&window).map_err(Error::from)?;
+    self
+      .state
+      .maintenance
+      .send(ScheduleMaintenance { window })
+      .await
+      .map_err(Error::from)??;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminScheduleMaintenance, AuditActor::Admin)
+        .with_payload(payload),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn cancel_maintenance(&self, _: Request<()>) -> Result<Response<()>, Status> {
+    self
+      .state
+      .maintenance
+      .send(CancelMaintenance)
+      .await
+      .map_err(Error::from)??;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminCancelMaintenance, AuditActor::Admin),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn get_maintenance(&self, _: Request<()>) -> Result<Response<GetMaintenanceReply>, Status> {
+    let window = self
+      .state
+      .maintenance
+      .send(GetMaintenance)
+      .await
+      .map_err(Error::from)?;
+
+    Ok(Response::new(GetMaintenanceReply {
+      window: window.map(|window| MaintenanceWindow {
+        starts_at: window.starts_at.timestamp(),
+        ends_at: window.ends_at.timestamp(),
+        message: window.message,
+        drain_nodes: window.drain_nodes,
+      }),
+    }))
+  }
+
+  async fn list_audit_events(
+    &self,
+    request: Request<ListAuditEventsRequest>,
+  ) -> Result<Response<ListAuditEventsReply>, Status> {
+    let req = request.into_inner();
+    let params = QueryAuditEventParams {
+      game_id: req.game_id,
+      actor_kind: req.actor_kind,
+      actor_id: req.actor_id,
+      kind: req.kind,
+      next_id: req.next_id,
+      take: req.take,
+    };
+
+    let r = self
+      .state
+      .db
+      .exec(move |conn| crate::audit::db::query(conn, &params))
+      .await
+      .map_err(Error::from)?;
+
+    Ok(Response::new(ListAuditEventsReply {
+      events: r
+        .events
+        .into_iter()
+        .map(|row| {
+          Ok(proto::AuditEvent {
+            id: row.id,
+            kind: row.kind as i32,
+            actor_kind: row.actor_kind as i32,
+            actor_id: row.actor_id,
+            game_id: row.game_id,
+            payload_json: serde_json::to_string(&row.payload).map_err(Error::from)?,
+            created_at: row.created_at.timestamp(),
+          })
+        })
+        .collect::<Result<Vec<_>, Error>>()?,
+      next_id: r.next_id,
+    }))
+  }
+
+  async fn set_packet_capture(
+    &self,
+    request: Request<SetPacketCaptureRequest>,
+  ) -> Result<Response<SetPacketCaptureReply>, Status> {
+    let enabled = request.into_inner().enabled;
+
+    flo_net::capture::set_enabled(enabled);
+    let nodes = self
+      .state
+      .nodes
+      .send(SetPacketCapture { enabled })
+      .await
+      .map_err(Error::from)?;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminSetPacketCapture, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "enabled": enabled, "nodes": nodes })),
+    );
+
+    Ok(Response::new(SetPacketCaptureReply {
+      nodes: nodes as i32,
+    }))
+  }
+
+  async fn set_game_rules(
+    &self,
+    request: Request<SetGameRulesRequest>,
+  ) -> Result<Response<()>, Status> {
+    let SetGameRulesRequest { game_id, rules } = request.into_inner();
+    let rules = rules
+      .map(|rules| crate::game::GameRules {
+        disable_pause: rules.disable_pause,
+        disable_save: rules.disable_save,
+        max_pause_count: rules.max_pause_count,
+        afk_kick_ticks: rules.afk_kick_ticks,
+        disable_shared_control: rules.disable_shared_control,
+        shared_control_pairs: rules
+          .shared_control_pairs
+          .into_iter()
+          .map(|pair| crate::game::SharedControlPair {
+            slot_id: pair.slot_id,
+            with_slot_id: pair.with_slot_id,
+          })
+          .collect(),
+        classic_graphics_only: rules.classic_graphics_only,
+      })
+      .unwrap_or_default();
+
+    let payload = serde_json::to_value(&rules).map_err(Error::from)?;
+    self
+      .state
+      .db
+      .exec(move |conn| crate::game::db::update_rules(conn, game_id, rules))
+      .await
+      .map_err(Error::from)?;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::game(
+        AuditEventKind::AdminSetGameRules,
+        AuditActor::Admin,
+        game_id,
+      )
+      .with_payload(payload),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn set_player_rating(
+    &self,
+    request: Request<SetPlayerRatingRequest>,
+  ) -> Result<Response<()>, Status> {
+    let SetPlayerRatingRequest { player_id, rating } = request.into_inner();
+
+    self
+      .state
+      .db
+      .exec(move |conn| crate::player::db::update_rating(conn, player_id, rating))
+      .await
+      .map_err(Error::from)?;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminSetPlayerRating, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "player_id": player_id, "rating": rating })),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn add_game_schedule(
+    &self,
+    request: Request<AddGameScheduleRequest>,
+  ) -> Result<Response<GameSchedule>, Status> {
+    let req = request.into_inner();
+    let insert = crate::game_schedule::db::AddGameSchedule {
+      template_id: req.template_id,
+      node_id: req.node_id,
+      start_time: NaiveTime::parse_from_str(&req.start_time, "%H:%M")
+        .map_err(|_| Error::GameScheduleInvalid)?,
+      open_minutes: req.open_minutes,
+      min_players: req.min_players,
+      host_player_id: req.host_player_id,
+    };
+
+    let schedule = self
+      .state
+      .db
+      .exec(move |conn| crate::game_schedule::db::add(conn, &insert))
+      .await
+      .map_err(Error::from)?;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminAddGameSchedule, AuditActor::Admin)
+        .with_payload(serde_json::to_value(&schedule).map_err(Error::from)?),
+    );
+
+    Ok(Response::new(pack_game_schedule(schedule)))
+  }
+
+  async fn remove_game_schedule(
+    &self,
+    request: Request<RemoveGameScheduleRequest>,
+  ) -> Result<Response<()>, Status> {
+    let schedule_id = request.into_inner().schedule_id;
+
+    self
+      .state
+      .db
+      .exec(move |conn| crate::game_schedule::db::remove(conn, schedule_id))
+      .await
+      .map_err(Error::from)?;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminRemoveGameSchedule, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "schedule_id": schedule_id })),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn list_game_schedules(
+    &self,
+    _: Request<()>,
+  ) -> Result<Response<ListGameSchedulesReply>, Status> {
+    let schedules = self
+      .state
+      .db
+      .exec(|conn| crate::game_schedule::db::list(conn))
+      .await
+      .map_err(Error::from)?;
+
+    Ok(Response::new(ListGameSchedulesReply {
+      schedules: schedules.into_iter().map(pack_game_schedule).collect(),
+    }))
+  }
+
+  async fn add_webhook(
+    &self,
+    request: Request<AddWebhookRequest>,
+  ) -> Result<Response<Webhook>, Status> {
+    let req = request.into_inner();
+
+    let webhook = self
+      .state
+      .db
+      .exec(move |conn| crate::webhook::db::add(conn, &req.url, &req.secret))
+      .await
+      .map_err(Error::from)?;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminAddWebhook, AuditActor::Admin)
+        .with_payload(serde_json::to_value(&webhook).map_err(Error::from)?),
+    );
+
+    Ok(Response::new(pack_webhook(webhook)))
+  }
+
+  async fn remove_webhook(
+    &self,
+    request: Request<RemoveWebhookRequest>,
+  ) -> Result<Response<()>, Status> {
+    let webhook_id = request.into_inner().webhook_id;
+
+    self
+      .state
+      .db
+      .exec(move |conn| crate::webhook::db::remove(conn, webhook_id))
+      .await
+      .map_err(Error::from)?;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminRemoveWebhook, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "webhook_id": webhook_id })),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn list_webhooks(&self, _: Request<()>) -> Result<Response<ListWebhooksReply>, Status> {
+    let webhooks = self
+      .state
+      .db
+      .exec(|conn| crate::webhook::db::list(conn))
+      .await
+      .map_err(Error::from)?;
+
+    Ok(Response::new(ListWebhooksReply {
+      webhooks: webhooks.into_iter().map(pack_webhook).collect(),
+    }))
+  }
+
+  async fn list_webhook_deliveries(
+    &self,
+    request: Request<ListWebhookDeliveriesRequest>,
+  ) -> Result<Response<ListWebhookDeliveriesReply>, Status> {
+    let req = request.into_inner();
+
+    let deliveries = self
+      .state
+      .db
+      .exec(move |conn| crate::webhook::db::list_deliveries(conn, req.webhook_id, req.take))
+      .await
+      .map_err(Error::from)?;
+
+    Ok(Response::new(ListWebhookDeliveriesReply {
+      deliveries: deliveries
+        .into_iter()
+        .map(|row| {
+          Ok(WebhookDelivery {
+            id: row.id,
+            event: row.event,
+            payload_json: serde_json::to_string(&row.payload).map_err(Error::from)?,
+            attempts: row.attempts,
+            status_code: row.status_code,
+            error: row.error,
+            delivered_at: row.delivered_at.map(|t| t.timestamp()),
+            created_at: row.created_at.timestamp(),
+          })
+        })
+        .collect::<Result<Vec<_>, Error>>()?,
+    }))
+  }
+
+  async fn set_motd_item(
+    &self,
+    request: Request<SetMotdItemRequest>,
+  ) -> Result<Response<MotdItem>, Status> {
+    let req = request.into_inner();
+    let kind = flo_net::proto::flo_connect::MotdItemKind::from_i32(req.kind)
+      .map(MotdItemKind::unpack_enum)
+      .ok_or_else(|| Error::MotdItemInvalid)?;
+    let item = MotdItemUpdate {
+      id: req.id,
+      kind,
+      title: req.title,
+      body: req.body,
+    };
+
+    let item = self
+      .state
+      .motd
+      .send(SetMotdItem { item })
+      .await
+      .map_err(Error::from)??;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminSetMotdItem, AuditActor::Admin)
+        .with_payload(serde_json::to_value(&item).map_err(Error::from)?),
+    );
+
+    Ok(Response::new(pack_motd_item(item)))
+  }
+
+  async fn remove_motd_item(
+    &self,
+    request: Request<RemoveMotdItemRequest>,
+  ) -> Result<Response<()>, Status> {
+    let id = request.into_inner().id;
+
+    self
+      .state
+      .motd
+      .send(RemoveMotdItem { id })
+      .await
+      .map_err(Error::from)??;
+    crate::audit::record(
+      &self.state.db,
+      AuditEvent::new(AuditEventKind::AdminRemoveMotdItem, AuditActor::Admin)
+        .with_payload(serde_json::json!({ "motd_item_id": id })),
+    );
+
+    Ok(Response::new(()))
+  }
+
+  async fn list_motd_items(&self, _: Request<()>) -> Result<Response<ListMotdItemsReply>, Status> {
+    let items = self
+      .state
+      .motd
+      .send(ListMotdItems)
+      .await
+      .map_err(Error::from)?;
+
+    Ok(Response::new(ListMotdItemsReply {
+      items: items.into_iter().map(pack_motd_item).collect(),
+    }))
+  }
+}
+
+fn pack_motd_item(item: crate::motd::MotdItem) -> MotdItem {
+  MotdItem {
+    id: item.id,
+    kind: item.kind as i32,
+    title: item.title,
+    body: item.body,
+    updated_at: item.updated_at.timestamp(),
+  }
+}
+
+fn pack_webhook(webhook: crate::webhook::Webhook) -> Webhook {
+  Webhook {
+    id: webhook.id,
+    url: webhook.url,
+    enabled: webhook.enabled,
+    created_at: webhook.created_at.timestamp(),
+  }
+}
+
+fn pack_game_schedule(schedule: crate::game_schedule::GameSchedule) -> GameSchedule {
+  GameSchedule {
+    id: schedule.id,
+    template_id: schedule.template_id,
+    node_id: schedule.node_id,
+    start_time: schedule.start_time.format("%H:%M").to_string(),
+    open_minutes: schedule.open_minutes,
+    min_players: schedule.min_players,
+    game_id: schedule.game_id,
+    host_player_id: schedule.host_player_id,
+  }
+}
@@ -0,0 +1,89 @@
+use diesel::prelude::*;
+
+use crate::audit::{AuditActorKind, AuditEvent, AuditEventKind, AuditEventRow};
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schema::audit_event;
+
+pub fn insert(conn: &DbConn, event: AuditEvent) -> Result<()> {
+  #[derive(Insertable)]
+  #[table_name = "audit_event"]
+  struct Insert {
+    kind: AuditEventKind,
+    actor_kind: AuditActorKind,
+    actor_id: Option<i32>,
+    game_id: Option<i32>,
+    payload: serde_json::Value,
+  }
+
+  let (actor_kind, actor_id) = event.actor.into_columns();
+  diesel::insert_into(audit_event::table)
+    .values(&Insert {
+      kind: event.kind,
+      actor_kind,
+      actor_id,
+      game_id: event.game_id,
+      payload: event.payload,
+    })
+    .execute(conn)?;
+  Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct QueryAuditEventParams {
+  pub game_id: Option<i32>,
+  pub actor_kind: Option<i32>,
+  pub actor_id: Option<i32>,
+  pub kind: Option<i32>,
+  pub next_id: Option<i64>,
+  pub take: Option<i64>,
+}
+
+pub struct QueryAuditEvent {
+  pub events: Vec<AuditEventRow>,
+  pub next_id: Option<i64>,
+}
+
+/// Lists events newest first
+pub fn query(conn: &DbConn, params: &QueryAuditEventParams) -> Result<QueryAuditEvent> {
+  use audit_event::dsl;
+  const MAX_TAKE: i64 = 100;
+
+  let take = params.take.unwrap_or(MAX_TAKE).max(1).min(MAX_TAKE);
+
+  let mut q = audit_event::table
+    .order(dsl::id.desc())
+    .limit(take + 1)
+    .into_boxed();
+
+  if let Some(id) = params.game_id {
+    q = q.filter(dsl::game_id.eq(id));
+  }
+
+  if let Some(kind) = params.actor_kind {
+    q = q.filter(dsl::actor_kind.eq(kind));
+  }
+
+  if let Some(id) = params.actor_id {
+    q = q.filter(dsl::actor_id.eq(id));
+  }
+
+  if let Some(kind) = params.kind {
+    q = q.filter(dsl::kind.eq(kind));
+  }
+
+  if let Some(id) = params.next_id {
+    q = q.filter(dsl::id.le(id));
+  }
+
+  let mut events = q.load::<AuditEventRow>(conn)?;
+  let next_id = if events.len() > take as usize {
+    let id = events.last().map(|row| row.id);
+    events.truncate(take as usize);
+    id
+  } else {
+    None
+  };
+
+  Ok(QueryAuditEvent { events, next_id })
+}
@@ -0,0 +1,131 @@
+pub mod db;
+
+use crate::db::ExecutorRef;
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Tracing target of the audit event sink
+pub const TRACING_TARGET: &str = "flo_audit";
+
+#[derive(Debug, Serialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum AuditEventKind {
+  GameCreate = 0,
+  GameJoin = 1,
+  GameLeave = 2,
+  GameStart = 3,
+  GameCancel = 4,
+  AdminCancelGame = 5,
+  AdminKickPlayer = 6,
+  AdminBroadcastNotice = 7,
+  AdminAddNode = 8,
+  AdminSetNodeDisabled = 9,
+  AdminScheduleMaintenance = 10,
+  AdminCancelMaintenance = 11,
+  AdminSetPacketCapture = 12,
+  AdminSetGameRules = 13,
+  AdminSetPlayerRating = 14,
+  GameTransferHost = 15,
+  GameInvite = 16,
+  AdminAddGameSchedule = 17,
+  AdminRemoveGameSchedule = 18,
+  /// Flagged for moderator review
+  GameSharedIp = 19,
+  GameAnomaly = 20,
+  AdminAddWebhook = 21,
+  AdminRemoveWebhook = 22,
+  AdminSetMotdItem = 23,
+  AdminRemoveMotdItem = 24,
+}
+
+#[derive(Debug, Serialize, Copy, Clone, PartialEq, BSDieselEnum)]
+#[repr(i32)]
+pub enum AuditActorKind {
+  System = 0,
+  Player = 1,
+  ApiClient = 2,
+  Admin = 3,
+}
+
+#[derive(Debug, Serialize, Copy, Clone, PartialEq)]
+pub enum AuditActor {
+  System,
+  Player(i32),
+  ApiClient(i32),
+  Admin,
+}
+
+impl AuditActor {
+  fn into_columns(self) -> (AuditActorKind, Option<i32>) {
+    match self {
+      AuditActor::System => (AuditActorKind::System, None),
+      AuditActor::Player(id) => (AuditActorKind::Player, Some(id)),
+      AuditActor::ApiClient(id) => (AuditActorKind::ApiClient, Some(id)),
+      AuditActor::Admin => (AuditActorKind::Admin, None),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct AuditEvent {
+  pub kind: AuditEventKind,
+  pub actor: AuditActor,
+  pub game_id: Option<i32>,
+  pub payload: Value,
+}
+
+impl AuditEvent {
+  pub fn new(kind: AuditEventKind, actor: AuditActor) -> Self {
+    Self {
+      kind,
+      actor,
+      game_id: None,
+      payload: Value::Object(Default::default()),
+    }
+  }
+
+  pub fn game(kind: AuditEventKind, actor: AuditActor, game_id: i32) -> Self {
+    Self {
+      game_id: Some(game_id),
+      ..Self::new(kind, actor)
+    }
+  }
+
+  pub fn with_payload(self, payload: Value) -> Self {
+    Self { payload, ..self }
+  }
+}
+
+#[derive(Debug, Serialize, Queryable)]
+pub struct AuditEventRow {
+  pub id: i64,
+  pub kind: AuditEventKind,
+  pub actor_kind: AuditActorKind,
+  pub actor_id: Option<i32>,
+  pub game_id: Option<i32>,
+  pub payload: Value,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Appends an event to the audit log.
+/// The write happens in the background, failures are logged and never affect the caller.
+pub fn record(db: &ExecutorRef, event: AuditEvent) {
+  tracing::info!(
+    target: TRACING_TARGET,
+    kind = ?event.kind,
+    actor = ?event.actor,
+    game_id = ?event.game_id,
+    payload = %event.payload,
+    "audit"
+  );
+
+  let db = db.clone();
+  tokio::spawn(async move {
+    let kind = event.kind;
+    if let Err(err) = db.exec(move |conn| db::insert(conn, event)).await {
+      tracing::error!("audit: insert {:?}: {}", kind, err);
+    }
+  });
+}
@@ -0,0 +1,149 @@
+//! In-memory cache of records fetched on every connect and session update.
+//!
+//! The db functions writing a game invalidate its entry with `invalidate_game_after_commit`,
+//! the entry is dropped once the transaction is committed. A read that started before the
+//! commit can still put the old record back: entries expire after `TTL` to bound how long
+//! that lasts. Reads inside a transaction should not go through the cache.
+
+use crate::game::Game;
+use crate::player::PlayerRef;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+const TTL: Duration = Duration::from_secs(30);
+const CAPACITY: usize = 10000;
+
+/// `game::db::get_full` by game id
+pub static GAMES: Lazy<Cache<i32, Game>> = Lazy::new(|| Cache::new(TTL, CAPACITY));
+/// `player::db::get_ref` by player id
+pub static PLAYERS: Lazy<Cache<i32, PlayerRef>> = Lazy::new(|| Cache::new(TTL, CAPACITY));
+
+thread_local! {
+  /// Games written by the db closure running on this thread
+  static PENDING_GAME_INVALIDATIONS: RefCell<Vec<i32>> = RefCell::new(vec![]);
+}
+
+/// Invalidates the game when the `db::ExecutorRef::exec` closure running the write returns,
+/// its transactions are committed or rolled back by then
+pub fn invalidate_game_after_commit(game_id: i32) {
+  PENDING_GAME_INVALIDATIONS.with(|pending| pending.borrow_mut().push(game_id));
+}
+
+/// Called by `db::ExecutorRef::exec` after the closure returns
+pub(crate) fn flush_pending_invalidations() {
+  let game_ids =
+    PENDING_GAME_INVALIDATIONS.with(|pending| std::mem::take(&mut *pending.borrow_mut()));
+  for game_id in game_ids {
+    GAMES.invalidate(game_id);
+  }
+}
+
+pub struct Cache<K, V> {
+  ttl: Duration,
+  capacity: usize,
+  entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K, V> Cache<K, V>
+where
+  K: Eq + Hash + Copy,
+  V: Clone,
+{
+  pub fn new(ttl: Duration, capacity: usize) -> Self {
+    Self {
+      ttl,
+      capacity,
+      entries: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub fn get(&self, key: K) -> Option<V> {
+    self.get_at(key, Instant::now())
+  }
+
+  pub fn insert(&self, key: K, value: V) {
+    self.insert_at(key, value, Instant::now())
+  }
+
+  pub fn invalidate(&self, key: K) {
+    self.entries.lock().remove(&key);
+  }
+
+  pub fn clear(&self) {
+    self.entries.lock().clear();
+  }
+
+  /// Returns the cached value or loads and caches it, errors are not cached
+  pub fn get_or_load<E, F>(&self, key: K, load: F) -> Result<V, E>
+  where
+    F: FnOnce() -> Result<V, E>,
+  {
+    if let Some(value) = self.get(key) {
+      return Ok(value);
+    }
+    let value = load()?;
+    self.insert(key, value.clone());
+    Ok(value)
+  }
+
+  fn get_at(&self, key: K, now: Instant) -> Option<V> {
+    let mut entries = self.entries.lock();
+    match entries.get(&key) {
+      Some((expires_at, value)) if now < *expires_at => Some(value.clone()),
+      Some(_) => {
+        entries.remove(&key);
+        None
+      }
+      None => None,
+    }
+  }
+
+  fn insert_at(&self, key: K, value: V, now: Instant) {
+    let mut entries = self.entries.lock();
+    if entries.len() >= self.capacity && !entries.contains_key(&key) {
+      entries.retain(|_, (expires_at, _)| now < *expires_at);
+      // still full, drop the entry closest to expiring
+      if entries.len() >= self.capacity {
+        let oldest = entries
+          .iter()
+          .min_by_key(|(_, (expires_at, _))| *expires_at)
+          .map(|(key, _)| *key);
+        if let Some(oldest) = oldest {
+          entries.remove(&oldest);
+        }
+      }
+    }
+    entries.insert(key, (now + self.ttl, value));
+  }
+}
+
+#[test]
+fn test_cache() {
+  let cache = Cache::new(Duration::from_secs(10), 2);
+  let now = Instant::now();
+  cache.insert_at(1, "a", now);
+  assert_eq!(cache.get_at(1, now), Some("a"));
+  assert_eq!(cache.get_at(1, now + Duration::from_secs(10)), None);
+
+  cache.insert_at(1, "a", now);
+  cache.insert_at(2, "b", now + Duration::from_secs(1));
+  // full, the oldest entry is dropped
+  cache.insert_at(3, "c", now + Duration::from_secs(2));
+  assert_eq!(cache.get_at(1, now), None);
+  assert_eq!(cache.get_at(2, now), Some("b"));
+  assert_eq!(cache.get_at(3, now), Some("c"));
+
+  cache.invalidate(2);
+  assert_eq!(cache.get_at(2, now), None);
+
+  let loaded: Result<_, ()> = cache.get_or_load(4, || Ok("d"));
+  assert_eq!(loaded, Ok("d"));
+  let loaded: Result<_, ()> = cache.get_or_load(4, || Err(()));
+  assert_eq!(loaded, Ok("d"));
+  cache.clear();
+  assert_eq!(cache.get(4), None);
+}
@@ -0,0 +1,213 @@
+//! Catalogue of the messages the lobby sends to players.
+//!
+//! A message goes out as its key and params so clients can localize it,
+//! next to the English rendering for clients that don't know the key.
+
+use flo_net::proto::flo_common::ErrorCode;
+use flo_net::proto::flo_connect::{
+  ClientConnectRejectReason, ClientDisconnectReason, LocalizedMessage, PacketClientConnectReject,
+  PacketClientDisconnect, PacketGameStartReject, PacketServerNotice,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+  pub key: &'static str,
+  /// `{name}` is replaced by the param `name`
+  pub en: &'static str,
+}
+
+macro_rules! catalogue {
+  ($($(#[$meta:meta])* $name:ident = $key:literal => $en:literal,)*) => {
+    $(
+      $(#[$meta])*
+      pub const $name: Entry = Entry { key: $key, en: $en };
+    )*
+
+    #[cfg(test)]
+    const ENTRIES: &[Entry] = &[$($name),*];
+  };
+}
+
+catalogue! {
+  INTERNAL_ERROR = "error.internal" => "Internal error.",
+
+  CONNECT_VERSION_TOO_OLD = "connect.version_too_old"
+    => "Your client is too old, please update to {min_version} or later.",
+  CONNECT_TOO_MANY_SESSIONS = "connect.too_many_sessions"
+    => "Your account is already connected from too many places.",
+  CONNECT_ALREADY_CONNECTED = "connect.already_connected"
+    => "You are already logged in elsewhere.",
+
+  DISCONNECT_UNKNOWN = "disconnect.unknown" => "Server closed the connection.",
+  DISCONNECT_MULTI = "disconnect.multi" => "You have logged in elsewhere.",
+  DISCONNECT_MAINTENANCE = "disconnect.maintenance" => "Server maintenance.",
+  DISCONNECT_KICKED = "disconnect.kicked" => "You have been kicked from the server.",
+  DISCONNECT_SESSION_ENDED = "disconnect.session_ended"
+    => "Your other session has ended.",
+
+  GAME_START_VERSION_MISMATCH = "game_start.version_mismatch"
+    => "Unable to start the game because the game and map version check failed.",
+  GAME_START_CLASSIC_GRAPHICS_REQUIRED = "game_start.classic_graphics_required"
+    => "Unable to start the game because it requires all players to run classic graphics.",
+  GAME_START_TIMEOUT = "game_start.timeout" => "Create game timeout.",
+  GAME_START_REJECTED = "game_start.rejected" => "Create game request rejected.",
+  GAME_START_ALREADY_STARTED = "game_start.already_started" => "Game already started.",
+  GAME_START_PLAYER_BUSY = "game_start.player_busy"
+    => "Create game request rejected: Player busy.",
+  GAME_START_MAINTENANCE = "game_start.maintenance"
+    => "Create game request rejected: Server Maintenance.",
+  GAME_START_NODE_FULL = "game_start.node_full" => "Create game request rejected: Node is full.",
+  GAME_START_NO_NODE = "game_start.no_node" => "No server available, please try again later.",
+  GAME_START_PLAYERS_TIMEOUT = "game_start.players_timeout"
+    => "Some of the players didn't response in time.",
+  GAME_START_NODE_DOWN = "game_start.node_down"
+    => "The server went down before the game started, please start the game again.",
+
+  MAINTENANCE_SCHEDULED = "maintenance.scheduled"
+    => "Server maintenance scheduled at {starts_at} (UTC): {message}",
+  MAINTENANCE_CANCELLED = "maintenance.cancelled"
+    => "Scheduled server maintenance has been cancelled.",
+  MAINTENANCE_STARTED = "maintenance.started" => "Server maintenance started: {message}",
+  MAINTENANCE_COUNTDOWN = "maintenance.countdown"
+    => "Server maintenance starts in {minutes} minute(s): {message}",
+
+  /// Written by admins, can't be localized
+  NOTICE = "notice" => "{message}",
+}
+
+impl Entry {
+  pub fn message(self) -> ServerMessage {
+    ServerMessage {
+      entry: self,
+      params: vec![],
+    }
+  }
+
+  pub fn with<T: ToString>(self, name: &'static str, value: T) -> ServerMessage {
+    self.message().with(name, value)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerMessage {
+  entry: Entry,
+  params: Vec<(&'static str, String)>,
+}
+
+impl ServerMessage {
+  pub fn with<T: ToString>(mut self, name: &'static str, value: T) -> Self {
+    self.params.push((name, value.to_string()));
+    self
+  }
+
+  /// The English fallback, placeholders without a param are kept as is
+  pub fn render(&self) -> String {
+    let mut text = String::with_capacity(self.entry.en.len());
+    let mut rest = self.entry.en;
+    while let Some(start) = rest.find('{') {
+      text.push_str(&rest[..start]);
+      rest = &rest[start..];
+      let value = rest.find('}').and_then(|end| {
+        let name = &rest[1..end];
+        self
+          .params
+          .iter()
+          .find(|(n, _)| *n == name)
+          .map(|(_, value)| (end, value))
+      });
+      if let Some((end, value)) = value {
+        text.push_str(value);
+        rest = &rest[(end + 1)..];
+      } else {
+        text.push('{');
+        rest = &rest[1..];
+      }
+    }
+    text.push_str(rest);
+    text
+  }
+
+  pub fn pack(&self) -> LocalizedMessage {
+    LocalizedMessage {
+      key: self.entry.key.to_string(),
+      params: self
+        .params
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect(),
+    }
+  }
+
+  pub fn notice(&self) -> PacketServerNotice {
+    PacketServerNotice {
+      message: self.render(),
+      localized: Some(self.pack()),
+    }
+  }
+
+  pub fn connect_reject(&self, reason: ClientConnectRejectReason) -> PacketClientConnectReject {
+    PacketClientConnectReject {
+      lobby_version: Some(From::from(crate::version::FLO_LOBBY_VERSION)),
+      reason: reason.into(),
+      message: self.render(),
+      localized: Some(self.pack()),
+      error_code: match reason {
+        ClientConnectRejectReason::Unknown => ErrorCode::Unknown,
+        ClientConnectRejectReason::ClientVersionTooOld => ErrorCode::ClientVersionTooOld,
+        ClientConnectRejectReason::InvalidToken => ErrorCode::InvalidToken,
+        ClientConnectRejectReason::TooManySessions => ErrorCode::TooManySessions,
+        ClientConnectRejectReason::AlreadyConnected => ErrorCode::AlreadyConnected,
+      }
+      .into(),
+      version_advertisement: None,
+    }
+  }
+
+  pub fn disconnect(&self, reason: ClientDisconnectReason) -> PacketClientDisconnect {
+    PacketClientDisconnect {
+      reason: reason.into(),
+      message: self.render(),
+      localized: Some(self.pack()),
+      error_code: match reason {
+        ClientDisconnectReason::Unknown | ClientDisconnectReason::SessionEnded => {
+          ErrorCode::Unknown
+        }
+        ClientDisconnectReason::Multi => ErrorCode::AlreadyConnected,
+        ClientDisconnectReason::Maintenance => ErrorCode::Maintenance,
+        ClientDisconnectReason::Kicked => ErrorCode::PermissionDenied,
+      }
+      .into(),
+    }
+  }
+
+  pub fn game_start_reject(&self, game_id: i32, error_code: ErrorCode) -> PacketGameStartReject {
+    PacketGameStartReject {
+      game_id,
+      message: self.render(),
+      localized: Some(self.pack()),
+      error_code: error_code.into(),
+      ..Default::default()
+    }
+  }
+}
+
+#[test]
+fn test_catalogue() {
+  // params are not expanded again
+  let msg = MAINTENANCE_COUNTDOWN
+    .with("message", "{minutes}")
+    .with("minutes", 5);
+  assert_eq!(
+    msg.render(),
+    "Server maintenance starts in 5 minute(s): {minutes}"
+  );
+  let packed = msg.pack();
+  assert_eq!(packed.key, "maintenance.countdown");
+  assert_eq!(packed.params["minutes"], "5");
+  assert_eq!(INTERNAL_ERROR.message().render(), "Internal error.");
+
+  let mut keys: Vec<_> = ENTRIES.iter().map(|entry| entry.key).collect();
+  keys.sort();
+  keys.dedup();
+  assert_eq!(keys.len(), ENTRIES.len());
+}
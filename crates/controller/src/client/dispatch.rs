@@ -0,0 +1,226 @@
+//! Handlers of the frames sent by clients after the initial state, by packet type.
+
+use flo_net::dispatch::Dispatcher;
+use flo_net::packet::{FloPacket, Frame};
+use flo_net::proto::flo_connect::*;
+use once_cell::sync::Lazy;
+
+use super::*;
+
+/// The state and the id of the player who sent the frame
+pub(super) type Session = (ControllerStateRef, i32);
+
+pub(super) static DISPATCHER: Lazy<Dispatcher<Session, Error>> = Lazy::new(|| {
+  Dispatcher::new("client")
+    .on(
+      |(state, player_id): Session, packet: PacketGameSlotUpdateRequest| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_slot_update_request(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, _packet: PacketListNodesRequest| async move {
+        handle_list_nodes_request(state, player_id).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketPlayerPingMapUpdateRequest| async move {
+        handle_player_ping_map_update_request(state, player_id, packet).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGamePlayerPingMapSnapshotRequest| async move {
+        handle_game_player_ping_map_snapshot_request(state, player_id, packet.game_id)
+          .await
+          .with_context(ErrorContext::game(packet.game_id))
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameSelectNodeRequest| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_select_node_request(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameBalanceTeamsRequest| async move {
+        handle_game_balance_teams_request(state, player_id, packet.game_id)
+          .await
+          .with_context(ErrorContext::game(packet.game_id))
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameTransferHostRequest| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_transfer_host_request(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameInviteRequest| async move {
+        handle_game_invite_request(state, player_id, packet).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameInviteAcceptRequest| async move {
+        handle_game_invite_accept_request(state, player_id, packet.token).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameStatusRequest| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_status_request(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameStartRequest| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_start_request(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameStartPlayerClientInfoRequest| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_start_player_client_info_request(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketReadyCheckResponse| async move {
+        handle_ready_check_response(state, player_id, packet).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameWaitlistJoinRequest| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_waitlist_join_request(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameWaitlistLeaveRequest| async move {
+        state
+          .games
+          .send_to(packet.game_id, LeaveWaitlist { player_id })
+          .await
+          .with_context(ErrorContext::game(packet.game_id))
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameWaitlistOfferResponse| async move {
+        let context = ErrorContext::game(packet.game_id);
+        handle_game_waitlist_offer_response(state, player_id, packet)
+          .await
+          .with_context(context)
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketGameRemoteJoinRequest| async move {
+        handle_game_remote_join_request(state, player_id, packet);
+        Ok(())
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketPlayerPreferencesUpdateRequest| async move {
+        handle_player_preferences_update_request(state, player_id, packet).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketPlayerMuteAddRequest| async move {
+        handle_player_mute_list_update_request(state, player_id, packet.into()).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketPlayerMuteRemoveRequest| async move {
+        handle_player_mute_list_update_request(state, player_id, packet.into()).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, _packet: PacketPlayerMuteListRequest| async move {
+        handle_player_mute_list_update_request(state, player_id, PlayerMuteListUpdate::None).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketSubscribeRequest| async move {
+        handle_subscribe_request(state, player_id, packet.topics).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, packet: PacketUnsubscribeRequest| async move {
+        handle_unsubscribe_request(state, player_id, packet.topics).await
+      },
+    )
+    .on(
+      |(state, player_id): Session, _packet: PacketGameListResyncRequest| async move {
+        state.player_packet_sender.resync_game_list(player_id).await
+      },
+    )
+});
+
+/// The game of a request handled by the `GameRegistry`, to route it to the instance
+/// owning the game
+pub(super) fn request_game_id(frame: &Frame) -> Option<i32> {
+  macro_rules! game_id {
+    ($($packet:ty),*) => {
+      $(
+        if frame.type_id == <$packet>::TYPE_ID {
+          return frame.clone().decode::<$packet>().ok().map(|packet| packet.game_id);
+        }
+      )*
+    };
+  }
+  game_id!(
+    PacketGameSlotUpdateRequest,
+    PacketGamePlayerPingMapSnapshotRequest,
+    PacketGameSelectNodeRequest,
+    PacketGameBalanceTeamsRequest,
+    PacketGameTransferHostRequest,
+    PacketGameInviteRequest,
+    PacketGameStatusRequest,
+    PacketGameStartRequest,
+    PacketGameStartPlayerClientInfoRequest,
+    PacketReadyCheckResponse,
+    PacketGameWaitlistJoinRequest,
+    PacketGameWaitlistLeaveRequest,
+    PacketGameWaitlistOfferResponse
+  );
+  None
+}
+
+#[test]
+fn test_request_game_id() {
+  let frame = PacketGameStartRequest {
+    game_id: 1,
+    ..Default::default()
+  }
+  .encode_as_frame()
+  .unwrap();
+  assert_eq!(request_game_id(&frame), Some(1));
+  let frame = PacketListNodesRequest {}.encode_as_frame().unwrap();
+  assert_eq!(request_game_id(&frame), None);
+}
+
+/// Every request a read-only session accepts has a handler
+#[test]
+fn test_read_only_requests() {
+  for type_id in [
+    PacketTypeId::ListNodesRequest,
+    PacketTypeId::GamePlayerPingMapSnapshotRequest,
+    PacketTypeId::GameStatusRequest,
+    PacketTypeId::PlayerMuteListRequest,
+    PacketTypeId::GameListResyncRequest,
+  ] {
+    assert!(is_read_only_request(type_id));
+    assert!(DISPATCHER.handles(type_id));
+  }
+}
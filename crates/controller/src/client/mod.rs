@@ -2,33 +2,58 @@ use flo_net::connect;
 use flo_net::listener::FloListener;
 use flo_net::packet::FloPacket;
 use flo_net::packet::OptionalFieldExt;
+use flo_net::packet::PacketTypeId;
 use flo_net::proto;
+use flo_net::proto::flo_common::ErrorCode;
 use flo_net::stream::FloStream;
 use s2_grpc_utils::{S2ProtoPack, S2ProtoUnpack};
 use std::collections::HashMap;
-use std::time::Duration;
 
+use crate::catalogue;
 use crate::error::*;
 use crate::state::{ActorMapExt, ControllerStateRef};
 
+mod dispatch;
 mod handshake;
 mod sender;
-use crate::game::messages::{ResolveGamePlayerPingBroadcastTargets, UpdateSlot};
-use crate::game::state::node::SelectNode;
+mod update;
+use crate::game::messages::{PlayerJoin, ResolveGamePlayerPingBroadcastTargets, UpdateSlot};
+use crate::game::state::host::{HostDisconnected, TransferHost};
+use crate::game::state::invite::InvitePlayer;
+use crate::game::state::node::{GetGameNode, SelectNode};
 use crate::game::state::player::GetGamePlayers;
-use crate::game::state::registry::UpdateGameNodeCache;
+use crate::game::state::ready::ReadyCheckResponse;
+use crate::game::state::registry::{
+  AddGamePlayer, GetPlayerGames, ResolvePlayerGame, UpdateGameNodeCache,
+};
+use crate::game::state::slot::BalanceTeams;
 use crate::game::state::start::{StartGameCheck, StartGamePlayerAck};
+use crate::game::state::waitlist::{JoinWaitlist, LeaveWaitlist, WaitlistOfferResponse};
 use crate::game::SlotSettings;
-use crate::node::messages::ListNode;
-use crate::player::state::conn::{Connect, Disconnect};
+use crate::motd::GetMotd;
+use crate::node::messages::{ListNode, NodeQueryGameStatus};
+use crate::node::Node;
+use crate::player::state::bus::GameRequest;
+use crate::player::state::conn::{Connect, Disconnect, GetOnlinePlayers};
 use crate::player::state::ping::{GetPlayersPingSnapshot, UpdatePing};
-use flo_net::ping::{PingMsg, PingStream};
+use crate::player::state::subscription::{Subscribe, Topic, Unsubscribe};
+use crate::player_preferences::PlayerPreferences;
+use crate::policy::{Policy, SessionMode};
+use flo_constants::version::Version;
+use flo_net::keepalive::{Incoming, KeepAlive, KeepAliveConfig, KeepAliveEvent};
 use flo_types::ping::PingStats;
-use futures::{StreamExt, TryStreamExt};
+use futures::TryStreamExt;
+use once_cell::sync::Lazy;
 pub use sender::{PlayerReceiver, PlayerSender, PlayerSenderMessage};
+use std::sync::Arc;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::Semaphore;
+use tracing_futures::Instrument;
 
-const PING_INTERVAL: Duration = Duration::from_secs(30);
-const PING_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_PENDING_REMOTE_JOINS: usize = 64;
+
+static REMOTE_JOIN_PERMITS: Lazy<Arc<Semaphore>> =
+  Lazy::new(|| Arc::new(Semaphore::new(MAX_PENDING_REMOTE_JOINS)));
 
 pub async fn serve(state: ControllerStateRef) -> Result<()> {
   state
@@ -36,7 +61,11 @@ pub async fn serve(state: ControllerStateRef) -> Result<()> {
     .exec(|conn| crate::game::db::reset_instance_state(conn))
     .await?;
 
-  let mut listener = FloListener::bind_v4(flo_constants::CONTROLLER_SOCKET_PORT).await?;
+  if let Some(requests) = state.bus.as_ref().and_then(|bus| bus.take_game_requests()) {
+    tokio::spawn(serve_routed_requests(state.clone(), requests));
+  }
+
+  let mut listener = FloListener::bind_v4(crate::config::service_config().socket_port).await?;
   tracing::info!("listening on port {}", listener.port());
 
   while let Some(mut stream) = listener.incoming().try_next().await? {
@@ -55,22 +84,64 @@ pub async fn serve(state: ControllerStateRef) -> Result<()> {
       let player_id = accepted.player_id;
       tracing::debug!("accepted: player_id = {}", player_id);
 
-      if accepted.client_version < flo_constants::MIN_FLO_VERSION {
-        stream
-          .send(proto::flo_connect::PacketClientConnectReject {
-            lobby_version: Some(From::from(crate::version::FLO_LOBBY_VERSION)),
-            reason: proto::flo_connect::ClientConnectRejectReason::ClientVersionTooOld.into(),
-          })
-          .await?;
+      let client_version = accepted.client_version;
+      if update::check_client_version(&crate::config::service_config(), client_version)
+        == update::ClientVersionCheck::Rejected
+      {
+        tracing::debug!(
+          player_id,
+          "rejected: client version too old: {}",
+          client_version
+        );
+        let config = crate::config::service_config();
+        let mut reject = catalogue::CONNECT_VERSION_TOO_OLD
+          .with("min_version", config.client_min_version())
+          .connect_reject(proto::flo_connect::ClientConnectRejectReason::ClientVersionTooOld);
+        reject.version_advertisement = Some(update::advertisement(&config, client_version));
+        stream.send(reject).await?;
         stream.shutdown().await?;
         return Ok(());
       }
 
-      if let Err(err) = handle_stream(state.clone(), player_id, stream).await {
-        tracing::debug!("stream error: {}", err);
+      match check_sessions(&state, player_id).await {
+        Ok(()) => {}
+        Err(Error::TooManySessions) => {
+          tracing::debug!(player_id, "rejected: too many sessions");
+          stream
+            .send(
+              catalogue::CONNECT_TOO_MANY_SESSIONS
+                .message()
+                .connect_reject(proto::flo_connect::ClientConnectRejectReason::TooManySessions),
+            )
+            .await?;
+          stream.shutdown().await?;
+          return Ok(());
+        }
+        Err(err) => return Err(err),
+      }
+
+      let (sender, receiver) = PlayerSender::new(player_id);
+      let session_id = sender.session_id();
+      if let Err(err) = handle_stream(state.clone(), sender, receiver, client_version, stream).await
+      {
+        tracing::debug!(
+          "stream error: {}",
+          err.with_context(ErrorContext::player(player_id))
+        );
       }
 
-      state.players.send(Disconnect { player_id }).await?;
+      let offline = state
+        .players
+        .send(Disconnect {
+          player_id,
+          session_id,
+        })
+        .await?;
+      if offline {
+        if let Err(err) = handle_player_disconnect(state.clone(), player_id).await {
+          tracing::error!(player_id, "handle player disconnect: {}", err);
+        }
+      }
       tracing::debug!("exiting: player_id = {}", player_id);
       Ok::<_, crate::error::Error>(())
     });
@@ -81,27 +152,49 @@ pub async fn serve(state: ControllerStateRef) -> Result<()> {
   Ok(())
 }
 
-#[tracing::instrument(target = "player_stream", skip(state, stream))]
+/// Handles the game requests routed from other instances, in order
+async fn serve_routed_requests(state: ControllerStateRef, mut requests: Receiver<GameRequest>) {
+  while let Some((player_id, frame)) = requests.recv().await {
+    let type_id = frame.type_id;
+    if let Err(err) = dispatch::DISPATCHER
+      .dispatch((state.clone(), player_id), frame)
+      .await
+    {
+      tracing::warn!(player_id, "routed request {:?}: {}", type_id, err);
+    }
+  }
+}
+
+#[tracing::instrument(
+  target = "player_stream",
+  skip(state, sender, receiver, client_version, stream),
+  fields(player_id = sender.player_id())
+)]
 async fn handle_stream(
   state: ControllerStateRef,
-  player_id: i32,
+  sender: PlayerSender,
+  mut receiver: PlayerReceiver,
+  client_version: Version,
   mut stream: FloStream,
 ) -> Result<()> {
-  let (sender, mut receiver) = PlayerSender::new(player_id);
-
-  send_initial_state(state.clone(), &mut stream, sender).await?;
+  let player_id = sender.player_id();
+  let mode = send_initial_state(state.clone(), &mut stream, sender, client_version).await?;
+  let read_only = mode == SessionMode::ReadOnly;
 
-  let mut ping = PingStream::interval(PING_INTERVAL, PING_TIMEOUT);
-  ping.start();
+  let config = crate::config::service_config();
+  let mut keepalive = KeepAlive::new(KeepAliveConfig::new(
+    config.client_ping_interval(),
+    config.client_ping_timeout(),
+  ));
 
   loop {
     tokio::select! {
-      Some(msg) = ping.next() => {
-        match msg {
-          PingMsg::Ping(frame) => {
+      event = keepalive.next() => {
+        match event {
+          KeepAliveEvent::Ping(frame) => {
             stream.send_frame(frame).await?;
           },
-          PingMsg::Timeout => {
+          KeepAliveEvent::Timeout => {
             tracing::debug!("heartbeat timeout");
             break;
           },
@@ -117,10 +210,15 @@ async fn handle_stream(
               }
             }
             PlayerSenderMessage::Disconnect(reason) => {
-              use flo_net::proto::flo_connect::PacketClientDisconnect;
-              if let Err(e) = stream.send(PacketClientDisconnect {
-                reason: reason.into()
-              }).await {
+              use flo_net::proto::flo_connect::ClientDisconnectReason;
+              let message = match reason {
+                ClientDisconnectReason::Unknown => catalogue::DISCONNECT_UNKNOWN,
+                ClientDisconnectReason::Multi => catalogue::DISCONNECT_MULTI,
+                ClientDisconnectReason::Maintenance => catalogue::DISCONNECT_MAINTENANCE,
+                ClientDisconnectReason::Kicked => catalogue::DISCONNECT_KICKED,
+                ClientDisconnectReason::SessionEnded => catalogue::DISCONNECT_SESSION_ENDED,
+              };
+              if let Err(e) = stream.send(message.message().disconnect(reason)).await {
                 tracing::debug!("send error: {}", e);
               }
               break;
@@ -132,43 +230,33 @@ async fn handle_stream(
         }
       }
       incoming = stream.recv_frame() => {
-        let frame = incoming?;
-        if frame.type_id == PingStream::PONG_TYPE_ID {
-          ping.capture_pong(frame);
+        let frame = match keepalive.recv(incoming?) {
+          Incoming::Frame(frame) => frame,
+          Incoming::KeepAlive(reply) => {
+            if let Some(frame) = reply {
+              stream.send_frame(frame).await?;
+            }
+            continue;
+          }
+        };
+
+        if read_only && !is_read_only_request(frame.type_id) {
+          tracing::debug!("read-only session: ignored {:?}", frame.type_id);
           continue;
         }
 
-        flo_net::try_flo_packet! {
-          frame => {
-            packet: proto::flo_connect::PacketGameSlotUpdateRequest => {
-              handle_game_slot_update_request(state.clone(), player_id, packet).await?;
-            }
-            _packet: proto::flo_connect::PacketListNodesRequest => {
-              handle_list_nodes_request(state.clone(), player_id).await?;
-            }
-            packet: proto::flo_connect::PacketPlayerPingMapUpdateRequest => {
-              handle_player_ping_map_update_request(state.clone(), player_id, packet).await?;
-            }
-            packet: proto::flo_connect::PacketGamePlayerPingMapSnapshotRequest => {
-              handle_game_player_ping_map_snapshot_request(state.clone(), player_id, packet.game_id).await?;
-            }
-            packet: proto::flo_connect::PacketGameSelectNodeRequest => {
-              handle_game_select_node_request(state.clone(), player_id, packet).await?;
-            }
-            packet: flo_net::proto::flo_connect::PacketGameStartRequest => {
-              handle_game_start_request(state.clone(), player_id, packet).await?;
-            }
-            packet: flo_net::proto::flo_connect::PacketGameStartPlayerClientInfoRequest => {
-              handle_game_start_player_client_info_request(state.clone(), player_id, packet).await?;
-            }
-            packet: proto::flo_connect::PacketPlayerMuteAddRequest => {
-              handle_player_mute_list_update_request(state.clone(), player_id, packet.into()).await?;
-            }
-            packet: proto::flo_connect::PacketPlayerMuteRemoveRequest => {
-              handle_player_mute_list_update_request(state.clone(), player_id, packet.into()).await?;
+        let frame = match (state.bus.as_ref(), dispatch::request_game_id(&frame)) {
+          (Some(bus), Some(game_id)) => {
+            if let Some(frame) = bus.route_game_request(player_id, game_id, frame).await {
+              frame
+            } else {
+              continue;
             }
           }
-        }
+          _ => frame,
+        };
+
+        dispatch::DISPATCHER.dispatch((state.clone(), player_id), frame).await?;
       }
     }
   }
@@ -176,32 +264,107 @@ async fn handle_stream(
   Ok(())
 }
 
+/// Limits concurrent sessions of players linked to the same external account
+async fn check_sessions(state: &ControllerStateRef, player_id: i32) -> Result<()> {
+  let policy = Policy::get();
+  if policy.max_sessions_per_account.is_none() {
+    return Ok(());
+  }
+  let linked = state
+    .db
+    .exec(move |conn| crate::player::db::get_linked_player_ids(conn, player_id))
+    .await?;
+  let online = state
+    .players
+    .send(GetOnlinePlayers { players: linked })
+    .await?;
+  policy.check_sessions(online.len())
+}
+
+/// Requests that only read state, and are answered to every session of the player
+fn is_read_only_request(type_id: PacketTypeId) -> bool {
+  matches!(
+    type_id,
+    PacketTypeId::ListNodesRequest
+      | PacketTypeId::GamePlayerPingMapSnapshotRequest
+      | PacketTypeId::GameStatusRequest
+      | PacketTypeId::PlayerMuteListRequest
+      | PacketTypeId::GameListResyncRequest
+  )
+}
+
 async fn send_initial_state(
   state: ControllerStateRef,
   stream: &mut FloStream,
   sender: PlayerSender,
-) -> Result<()> {
+  client_version: Version,
+) -> Result<SessionMode> {
   let player_id = sender.player_id();
 
-  let (player, active_slots) = state
+  let (player, game_ids, preferences) = state
     .db
-    .exec(move |conn| -> Result<_> {
-      Ok((
-        crate::player::db::get_ref(conn, player_id)?,
-        crate::game::db::get_player_active_slots(conn, player_id)?,
-      ))
+    .exec_async(
+      move |conn| async move {
+        Ok::<_, Error>((
+          crate::player::db::get_ref_cached_async(&conn, player_id).await?,
+          crate::game::db::get_player_active_game_ids_async(&conn, player_id).await?,
+          crate::player_preferences::db::get_async(&conn, player_id).await?,
+        ))
+      },
+      move |conn| -> Result<_> {
+        Ok((
+          crate::player::db::get_ref_cached(conn, player_id)?,
+          crate::game::db::get_player_active_slots(conn, player_id)?
+            .into_iter()
+            .map(|s| s.game_id)
+            .collect(),
+          crate::player_preferences::db::get(conn, player_id)?,
+        ))
+      },
+    )
+    .await?;
+
+  let game_id = state
+    .games
+    .send(ResolvePlayerGame {
+      player_id,
+      game_ids,
     })
     .await?;
 
-  let game_id = active_slots.last().map(|s| s.game_id);
+  let ip = stream.peer_addr().ok().map(|addr| addr.ip());
+  let nodes = state.nodes.send(ListNode).await?;
+  let suggested_node = ip
+    .and_then(crate::geoip::lookup)
+    .and_then(|location| crate::geoip::suggest_node(&location, &nodes))
+    .cloned();
 
-  state
+  let res = state
     .players
-    .notify(Connect {
+    .send(Connect {
       game_id: game_id.clone(),
       sender,
+      suggested_region: suggested_node.as_ref().map(|node| node.region.clone()),
+      ip,
+      policy: Policy::get().session,
     })
     .await?;
+  let mode = match res {
+    Ok(mode) => mode,
+    Err(Error::AlreadyConnected) => {
+      tracing::debug!(player_id, "rejected: already connected");
+      stream
+        .send(
+          catalogue::CONNECT_ALREADY_CONNECTED
+            .message()
+            .connect_reject(proto::flo_connect::ClientConnectRejectReason::AlreadyConnected),
+        )
+        .await?;
+      stream.shutdown().await?;
+      return Err(Error::AlreadyConnected);
+    }
+    Err(err) => return Err(err),
+  };
 
   let frame_accept = connect::PacketClientConnectAccept {
     lobby_version: Some(From::from(crate::version::FLO_LOBBY_VERSION)),
@@ -217,11 +380,24 @@ async fn send_initial_state(
         game_id: game_id.clone(),
       }
     }),
-    nodes: state.nodes.send(ListNode).await?.pack()?,
+    nodes: Node::pack_list_for_client(nodes)?,
+    suggested_node_id: suggested_node.as_ref().map(|node| node.id),
+    suggested_region: suggested_node.map(|node| node.region).unwrap_or_default(),
+    feature_flags: crate::config::service_config().feature_flags_for(player_id),
   }
   .encode_as_frame()?;
 
-  let mut frames = vec![frame_accept];
+  let frame_preferences = connect::PacketPlayerPreferences {
+    preferences: Some(preferences.into_proto()),
+  }
+  .encode_as_frame()?;
+
+  let frame_version =
+    update::advertisement(&crate::config::service_config(), client_version).encode_as_frame()?;
+
+  let frame_motd = state.motd.send(GetMotd).await?.encode_as_frame()?;
+
+  let mut frames = vec![frame_accept, frame_version, frame_preferences, frame_motd];
 
   if let Some(game_id) = game_id {
     let (mut game, node_player_token) = state
@@ -257,7 +433,7 @@ async fn send_initial_state(
   }
 
   stream.send_frames(frames).await?;
-  Ok(())
+  Ok(mode)
 }
 
 async fn handle_game_slot_update_request(
@@ -265,24 +441,43 @@ async fn handle_game_slot_update_request(
   player_id: i32,
   packet: proto::flo_connect::PacketGameSlotUpdateRequest,
 ) -> Result<()> {
-  state
+  let game_id = packet.game_id;
+  let res = state
     .games
     .send_to(
-      packet.game_id,
+      game_id,
       UpdateSlot {
         player_id,
         slot_index: packet.slot_index,
         settings: SlotSettings::unpack(packet.slot_settings.extract()?)?,
+        slots_version: Some(packet.slots_version).filter(|v| *v != 0),
       },
     )
-    .await?;
-  Ok(())
+    .await;
+  match res {
+    Ok(_) => Ok(()),
+    // the player missed an update, resend the slots so the client can retry
+    Err(Error::GameSlotsChanged) => {
+      let mut game = state
+        .db
+        .exec(move |conn| crate::game::db::get_full(conn, game_id))
+        .await?;
+      crate::game::state::create::mask_player_names(&mut game);
+      let frame = proto::flo_connect::PacketGameInfo {
+        game: Some(game.pack()?),
+      }
+      .encode_as_frame()?;
+      state.player_packet_sender.send(player_id, frame).await?;
+      Ok(())
+    }
+    Err(err) => Err(err),
+  }
 }
 
 async fn handle_list_nodes_request(state: ControllerStateRef, player_id: i32) -> Result<()> {
   let nodes = state.nodes.send(ListNode).await?;
   let packet = proto::flo_connect::PacketListNodes {
-    nodes: nodes.pack()?,
+    nodes: Node::pack_list_for_client(nodes)?,
   };
   state
     .player_packet_sender
@@ -379,6 +574,64 @@ async fn handle_game_player_ping_map_snapshot_request(
   Ok(())
 }
 
+async fn handle_game_status_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameStatusRequest,
+) -> Result<()> {
+  use flo_net::proto::flo_connect::PacketGameStatusResponse;
+
+  let request_id = packet.request_id;
+  let game_id = packet.game_id;
+  let node_id = state
+    .games
+    .send_to(game_id, GetGameNode { player_id })
+    .await?;
+
+  // the node can take up to the request timeout to respond
+  tokio::spawn(
+    async move {
+      let status = if let Some(node_id) = node_id {
+        let res = match state
+          .nodes
+          .send_to(node_id, NodeQueryGameStatus { game_id })
+          .await
+        {
+          Ok(deferred) => deferred.await.or_cancelled(),
+          Err(err) => Err(err),
+        };
+        match res {
+          Ok(status) => status,
+          Err(err) => {
+            tracing::error!(game_id, node_id, "query game status: {}", err);
+            None
+          }
+        }
+      } else {
+        None
+      };
+
+      let res: Result<()> = async {
+        let frame = PacketGameStatusResponse {
+          request_id,
+          game_id,
+          status,
+        }
+        .encode_as_frame()?;
+        state.player_packet_sender.send(player_id, frame).await?;
+        Ok(())
+      }
+      .await;
+      if let Err(err) = res {
+        tracing::debug!(player_id, game_id, "send game status: {}", err);
+      }
+    }
+    .in_current_span(),
+  );
+
+  Ok(())
+}
+
 async fn handle_game_select_node_request(
   state: ControllerStateRef,
   player_id: i32,
@@ -404,6 +657,94 @@ async fn handle_game_select_node_request(
   Ok(())
 }
 
+async fn handle_game_balance_teams_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  game_id: i32,
+) -> Result<()> {
+  state
+    .games
+    .send_to(game_id, BalanceTeams { player_id })
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_transfer_host_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameTransferHostRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      TransferHost {
+        player_id,
+        new_host_player_id: packet.player_id,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_invite_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameInviteRequest,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      InvitePlayer {
+        player_id,
+        target_player_id: packet.player_id,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_invite_accept_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  token: String,
+) -> Result<()> {
+  let join_token = crate::game::token::validate_join_token(&token)?;
+  join_token.check_player(player_id)?;
+
+  state
+    .games
+    .send_to(
+      join_token.game_id,
+      PlayerJoin {
+        player_id,
+        password: None,
+        by_token: true,
+      },
+    )
+    .await?;
+  state
+    .games
+    .send(AddGamePlayer {
+      game_id: join_token.game_id,
+      player_id,
+    })
+    .await?;
+  Ok(())
+}
+
+async fn handle_player_disconnect(state: ControllerStateRef, player_id: i32) -> Result<()> {
+  let game_ids = state.games.send(GetPlayerGames { player_id }).await?;
+  for game_id in game_ids {
+    state
+      .games
+      .send_to(game_id, HostDisconnected { player_id })
+      .await?;
+  }
+  Ok(())
+}
+
 async fn handle_game_start_request(
   state: ControllerStateRef,
   player_id: i32,
@@ -411,7 +752,13 @@ async fn handle_game_start_request(
 ) -> Result<()> {
   state
     .games
-    .send_to(packet.game_id, StartGameCheck { player_id })
+    .send_to(
+      packet.game_id,
+      StartGameCheck {
+        player_id,
+        ready_check: packet.ready_check,
+      },
+    )
     .await?;
   Ok(())
 }
@@ -428,9 +775,161 @@ async fn handle_game_start_player_client_info_request(
   Ok(())
 }
 
+async fn handle_ready_check_response(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketReadyCheckResponse,
+) -> Result<()> {
+  state
+    .games
+    .send_to(
+      packet.game_id,
+      ReadyCheckResponse {
+        player_id,
+        ready: packet.ready,
+      },
+    )
+    .await?;
+  Ok(())
+}
+
+async fn handle_game_waitlist_join_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameWaitlistJoinRequest,
+) -> Result<()> {
+  let game_id = packet.game_id;
+  let res = state
+    .games
+    .send_to(
+      game_id,
+      JoinWaitlist {
+        player_id,
+        password: Some(packet.password).filter(|v| !v.is_empty()),
+      },
+    )
+    .await;
+  match res {
+    Ok(()) => Ok(()),
+    Err(
+      err @ Error::ActorNotFound
+      | err @ Error::GameStarted
+      | err @ Error::GameWaitlistFull
+      | err @ Error::GamePasswordIncorrect
+      | err @ Error::PlayerAlreadyInGame,
+    ) => {
+      tracing::debug!(game_id, player_id, "waitlist join rejected: {}", err);
+      let frame = proto::flo_connect::PacketGameWaitlistUpdate {
+        game_id,
+        position: 0,
+      }
+      .encode_as_frame()?;
+      state.player_packet_sender.send(player_id, frame).await?;
+      Ok(())
+    }
+    Err(err) => Err(err),
+  }
+}
+
+async fn handle_game_waitlist_offer_response(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameWaitlistOfferResponse,
+) -> Result<()> {
+  let game_id = packet.game_id;
+  let joined = state
+    .games
+    .send_to(
+      game_id,
+      WaitlistOfferResponse {
+        player_id,
+        accept: packet.accept,
+      },
+    )
+    .await?;
+  if joined.is_some() {
+    state
+      .games
+      .send(AddGamePlayer { game_id, player_id })
+      .await?;
+  }
+  Ok(())
+}
+
+/// Answered from a task, the peer can take up to the request timeout.
+/// At most `MAX_PENDING_REMOTE_JOINS` requests are waiting for peers at once.
+fn handle_game_remote_join_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketGameRemoteJoinRequest,
+) {
+  let permit = REMOTE_JOIN_PERMITS.clone().try_acquire_owned();
+  tokio::spawn(async move {
+    let proto::flo_connect::PacketGameRemoteJoinRequest { lobby_id, game_id } = packet;
+    let res = match permit {
+      Ok(_permit) => request_remote_join(&state, player_id, &lobby_id, game_id).await,
+      Err(_) => Err(Error::FederationBusy),
+    };
+    let frame = match res {
+      Ok((host, res)) => proto::flo_connect::PacketGameRemoteJoin {
+        lobby_id,
+        game_id,
+        host,
+        player_token: res.player_token,
+        join_token: res.join_token,
+      }
+      .encode_as_frame(),
+      Err(err) => {
+        tracing::debug!(game_id, player_id, lobby_id = %lobby_id, "remote join rejected: {}", err);
+        proto::flo_connect::PacketGameRemoteJoinReject {
+          lobby_id,
+          game_id,
+          message: if err.code() == ErrorCode::Internal {
+            catalogue::INTERNAL_ERROR.en.to_string()
+          } else {
+            err.client_message()
+          },
+          error_code: err.code().into(),
+        }
+        .encode_as_frame()
+      }
+    };
+    let res = match frame {
+      Ok(frame) => state.player_packet_sender.send(player_id, frame).await,
+      Err(err) => Err(err.into()),
+    };
+    if let Err(err) = res {
+      tracing::error!(game_id, player_id, "remote join: {}", err);
+    }
+  });
+}
+
+/// Returns the host of the peer and the tokens to join with
+async fn request_remote_join(
+  state: &ControllerStateRef,
+  player_id: i32,
+  lobby_id: &str,
+  game_id: i32,
+) -> Result<(String, crate::federation::JoinResponse)> {
+  let peer = crate::config::service_config()
+    .federation
+    .as_ref()
+    .and_then(|federation| federation.peer(lobby_id))
+    .cloned()
+    .ok_or_else(|| Error::FederationPeerNotFound)?;
+  let player = state
+    .db
+    .exec(move |conn| crate::player::db::get_ref_cached(conn, player_id))
+    .await?;
+  let res = crate::federation::request_join(&peer, game_id, player).await?;
+  Ok((peer.host, res))
+}
+
 enum PlayerMuteListUpdate {
   Add(proto::flo_connect::PacketPlayerMuteAddRequest),
   Remove(proto::flo_connect::PacketPlayerMuteRemoveRequest),
+  /// Only sends the list
+  None,
 }
 
 impl From<proto::flo_connect::PacketPlayerMuteAddRequest> for PlayerMuteListUpdate {
@@ -450,14 +949,72 @@ async fn handle_player_mute_list_update_request(
   player_id: i32,
   update: PlayerMuteListUpdate,
 ) -> Result<()> {
-  state
+  let mute_list = state
     .db
-    .exec(move |conn| match update {
-      PlayerMuteListUpdate::Add(req) => crate::player::db::add_mute(conn, player_id, req.player_id),
-      PlayerMuteListUpdate::Remove(req) => {
-        crate::player::db::remove_mute(conn, player_id, req.player_id)
+    .exec(move |conn| {
+      match update {
+        PlayerMuteListUpdate::Add(req) if req.player_id == player_id => {}
+        PlayerMuteListUpdate::Add(req) => {
+          crate::player::db::add_mute(conn, player_id, req.player_id)?
+        }
+        PlayerMuteListUpdate::Remove(req) => {
+          crate::player::db::remove_mute(conn, player_id, req.player_id)?
+        }
+        PlayerMuteListUpdate::None => {}
       }
+      crate::player::db::get_mute_list(conn, player_id)
     })
     .await?;
+  let packet = proto::flo_connect::PacketPlayerMuteListUpdate { mute_list };
+  state
+    .player_packet_sender
+    .send(player_id, packet.encode_as_frame()?)
+    .await?;
+  Ok(())
+}
+
+async fn handle_subscribe_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  topics: Vec<proto::flo_connect::SubscriptionTopic>,
+) -> Result<()> {
+  let topics = topics.iter().map(Topic::from_proto).collect();
+  state.players.send(Subscribe { player_id, topics }).await?;
+  Ok(())
+}
+
+async fn handle_unsubscribe_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  topics: Vec<proto::flo_connect::SubscriptionTopic>,
+) -> Result<()> {
+  let topics = topics.iter().map(Topic::from_proto).collect();
+  state
+    .players
+    .send(Unsubscribe { player_id, topics })
+    .await?;
+  Ok(())
+}
+
+async fn handle_player_preferences_update_request(
+  state: ControllerStateRef,
+  player_id: i32,
+  packet: proto::flo_connect::PacketPlayerPreferencesUpdateRequest,
+) -> Result<()> {
+  let preferences = PlayerPreferences::from_proto(packet.preferences.extract()?)?;
+  let preferences = state
+    .db
+    .exec(move |conn| {
+      crate::player_preferences::db::upsert(conn, player_id, &preferences)?;
+      Ok::<_, Error>(preferences)
+    })
+    .await?;
+  let packet = proto::flo_connect::PacketPlayerPreferences {
+    preferences: Some(preferences.into_proto()),
+  };
+  state
+    .player_packet_sender
+    .send(player_id, packet.encode_as_frame()?)
+    .await?;
   Ok(())
 }
@@ -1,36 +1,113 @@
 use flo_net::packet::*;
 use flo_net::proto::flo_connect::*;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
 use crate::error::*;
 
-pub type PlayerReceiver = Receiver<PlayerSenderMessage>;
+const LANE_SIZE: usize = 8;
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
 pub enum PlayerSenderMessage {
   Frame(Frame),
   Disconnect(ClientDisconnectReason),
 }
 
+/// Outgoing frames are queued in one lane per priority
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+  Control,
+  /// Large lists that shouldn't hold back game signals.
+  /// Frames other frames depend on, e.g. the game info, stay on the control lane.
+  Bulk,
+}
+
+impl Priority {
+  pub fn of(type_id: PacketTypeId) -> Self {
+    match type_id {
+      PacketTypeId::ListNodes
+      | PacketTypeId::GamePlayerPingMapSnapshot
+      | PacketTypeId::PlayerPingMapUpdate => Priority::Bulk,
+      _ => Priority::Control,
+    }
+  }
+}
+
+pub struct PlayerReceiver {
+  control: Receiver<PlayerSenderMessage>,
+  bulk: Receiver<PlayerSenderMessage>,
+}
+
+impl PlayerReceiver {
+  /// Queued control messages are received before any bulk frame, cancel safe
+  pub async fn recv(&mut self) -> Option<PlayerSenderMessage> {
+    tokio::select! {
+      biased;
+      Some(msg) = self.control.recv() => Some(msg),
+      Some(msg) = self.bulk.recv() => Some(msg),
+      else => None,
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct PlayerSender {
   player_id: i32,
+  /// Tells the sessions of the same player apart
+  session_id: u64,
   sender: Sender<PlayerSenderMessage>,
+  bulk_sender: Sender<PlayerSenderMessage>,
 }
 
 impl PlayerSender {
   pub fn new(player_id: i32) -> (Self, PlayerReceiver) {
-    let (sender, receiver) = channel(8);
-    (PlayerSender { player_id, sender }, receiver)
+    let (sender, control) = channel(LANE_SIZE);
+    let (bulk_sender, bulk) = channel(LANE_SIZE);
+    (
+      PlayerSender {
+        player_id,
+        session_id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+        sender,
+        bulk_sender,
+      },
+      PlayerReceiver { control, bulk },
+    )
+  }
+
+  fn lane(&self, frame: &Frame) -> &Sender<PlayerSenderMessage> {
+    match Priority::of(frame.type_id) {
+      Priority::Control => &self.sender,
+      Priority::Bulk => &self.bulk_sender,
+    }
   }
 
   pub fn player_id(&self) -> i32 {
     self.player_id
   }
 
+  pub fn session_id(&self) -> u64 {
+    self.session_id
+  }
+
+  /// Number of messages waiting in both lanes
+  pub fn queue_len(&self) -> usize {
+    (LANE_SIZE - self.sender.capacity()) + (LANE_SIZE - self.bulk_sender.capacity())
+  }
+
   pub async fn disconnect_multi(&mut self) {
     self.disconnect(ClientDisconnectReason::Multi).await;
   }
 
+  pub async fn disconnect_kicked(&mut self) {
+    self.disconnect(ClientDisconnectReason::Kicked).await;
+  }
+
+  pub async fn disconnect_session_ended(&mut self) {
+    self.disconnect(ClientDisconnectReason::SessionEnded).await;
+  }
+
   #[tracing::instrument]
   async fn disconnect(&mut self, reason: ClientDisconnectReason) {
     self
@@ -45,14 +122,14 @@ impl PlayerSender {
 
   pub fn try_send(&mut self, frame: Frame) -> bool {
     self
-      .sender
+      .lane(&frame)
       .try_send(PlayerSenderMessage::Frame(frame))
       .is_ok()
   }
 
   pub async fn send_frame(&mut self, frame: Frame) -> Result<()> {
     self
-      .sender
+      .lane(&frame)
       .send(PlayerSenderMessage::Frame(frame))
       .await
       .map_err(|_| Error::PlayerStreamClosed)?;
@@ -64,3 +141,38 @@ impl PlayerSender {
     Ok(())
   }
 }
+
+#[test]
+fn test_priority() {
+  futures::executor::block_on(async {
+    let (mut sender, mut receiver) = PlayerSender::new(1);
+    sender
+      .send_frame(Frame::new_empty(PacketTypeId::ListNodes))
+      .await
+      .unwrap();
+    sender
+      .send_frame(Frame::new_empty(PacketTypeId::GameInfo))
+      .await
+      .unwrap();
+    sender
+      .send_frame(Frame::new_empty(PacketTypeId::GameStarting))
+      .await
+      .unwrap();
+    drop(sender);
+
+    let mut type_ids = vec![];
+    while let Some(msg) = receiver.recv().await {
+      if let PlayerSenderMessage::Frame(frame) = msg {
+        type_ids.push(frame.type_id);
+      }
+    }
+    assert_eq!(
+      type_ids,
+      vec![
+        PacketTypeId::GameInfo,
+        PacketTypeId::GameStarting,
+        PacketTypeId::ListNodes
+      ]
+    );
+  });
+}
@@ -0,0 +1,82 @@
+//! Client auto-update channel.
+//!
+//! Clients older than the configured minimum version are accepted with a warning,
+//! carried by the version advertisement, until the enforcement time is reached.
+//! After that they are rejected in the handshake.
+
+use chrono::Utc;
+use flo_config::ControllerConfig;
+use flo_constants::version::Version;
+use flo_net::proto::flo_connect::PacketClientVersionAdvertisement;
+
+#[derive(Debug, PartialEq)]
+pub enum ClientVersionCheck {
+  Supported,
+  /// Accepted until the enforcement time
+  Outdated,
+  Rejected,
+}
+
+pub fn check_client_version(config: &ControllerConfig, version: Version) -> ClientVersionCheck {
+  check(config, version, Utc::now().timestamp() as u64)
+}
+
+fn check(config: &ControllerConfig, version: Version, now: u64) -> ClientVersionCheck {
+  if version < flo_constants::MIN_FLO_VERSION {
+    return ClientVersionCheck::Rejected;
+  }
+  if version >= config.client_min_version() {
+    return ClientVersionCheck::Supported;
+  }
+  match config.client_version_enforce_at {
+    Some(enforce_at) if now < enforce_at => ClientVersionCheck::Outdated,
+    _ => ClientVersionCheck::Rejected,
+  }
+}
+
+pub fn advertisement(
+  config: &ControllerConfig,
+  version: Version,
+) -> PacketClientVersionAdvertisement {
+  let min_version = config.client_min_version();
+  PacketClientVersionAdvertisement {
+    min_version: Some(min_version.into()),
+    enforce_at: config.client_version_enforce_at.unwrap_or_default(),
+    download_urls: config
+      .client_download_urls
+      .iter()
+      .map(|(platform, url)| (platform.clone(), url.clone()))
+      .collect(),
+    update_required: version < min_version,
+  }
+}
+
+#[test]
+fn test_check_client_version() {
+  let v = |v: &'static str| Version::parse(v);
+  let mut config = ControllerConfig::default();
+  assert_eq!(
+    check(&config, flo_constants::MIN_FLO_VERSION, 0),
+    ClientVersionCheck::Supported
+  );
+  assert_eq!(check(&config, v("0.1.0"), 0), ClientVersionCheck::Rejected);
+
+  config.client_min_version = Some("0.12.0".to_string());
+  assert_eq!(
+    check(&config, v("0.12.0"), 0),
+    ClientVersionCheck::Supported
+  );
+  assert_eq!(check(&config, v("0.11.9"), 0), ClientVersionCheck::Rejected);
+
+  config.client_version_enforce_at = Some(100);
+  assert_eq!(
+    check(&config, v("0.11.9"), 99),
+    ClientVersionCheck::Outdated
+  );
+  assert_eq!(
+    check(&config, v("0.11.9"), 100),
+    ClientVersionCheck::Rejected
+  );
+  // the warn window doesn't apply below the hard minimum
+  assert_eq!(check(&config, v("0.1.0"), 99), ClientVersionCheck::Rejected);
+}
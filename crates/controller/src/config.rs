@@ -1,10 +1,11 @@
+use crate::db::{DbConn, ExecutorRef};
 use arc_swap::ArcSwap;
-use bs_diesel_utils::{DbConn, ExecutorRef};
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use once_cell::sync::Lazy;
+use flo_config::service::{ConfigReceiver, ServiceConfig};
+use flo_config::ControllerConfig;
+use once_cell::sync::{Lazy, OnceCell};
 use std::collections::BTreeMap;
-use std::env;
 use std::sync::Arc;
 use tonic::{metadata::MetadataValue, service::Interceptor, Request, Status};
 
@@ -16,7 +17,27 @@ use crate::state::{Data, Reload};
 use flo_state::{async_trait, Actor, Context, Handler, Message, RegistryRef, Service};
 
 pub static JWT_SECRET_BASE64: Lazy<String> =
-  Lazy::new(|| env::var("JWT_SECRET_BASE64").expect("env `JWT_SECRET_BASE64`"));
+  Lazy::new(|| service_config().jwt_secret_base64.clone());
+
+static SERVICE_CONFIG: OnceCell<ConfigReceiver<ControllerConfig>> = OnceCell::new();
+
+/// Loads the service config and reloads it on SIGHUP or when the file changes
+pub fn init_service_config() -> Result<()> {
+  SERVICE_CONFIG.set(flo_config::service::watch()?).ok();
+  Ok(())
+}
+
+/// The current service config, loaded once without reloading if `init_service_config` wasn't called
+pub fn service_config() -> Arc<ControllerConfig> {
+  SERVICE_CONFIG
+    .get_or_init(|| {
+      let config =
+        ControllerConfig::load().unwrap_or_else(|err| panic!("controller config: {}", err));
+      tokio::sync::watch::channel(Arc::new(config)).1
+    })
+    .borrow()
+    .clone()
+}
 
 #[derive(Debug, Queryable)]
 pub struct ApiClient {
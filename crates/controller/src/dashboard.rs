@@ -0,0 +1,186 @@
+//! Data for the ops dashboard, collected from the lobby's in-memory state.
+//!
+//! Served to admins by the http api, see `GET /dashboard`.
+
+use crate::db::DbStatus;
+use crate::error::*;
+use crate::game::state::summary::{list_game_summaries, GameSummary};
+use crate::game::{GameStatus, SlotClientStatus};
+use crate::node::messages::ListNodeStatus;
+use crate::node::NodeStatus;
+use crate::player::state::conn::SenderQueueStats;
+use crate::state::ControllerStateRef;
+use chrono::{DateTime, Utc};
+use flo_types::ping::PingStats;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+
+const MAX_RECENT_ERRORS: usize = 100;
+
+static RECENT_ERRORS: Lazy<Mutex<RecentErrors>> = Lazy::new(|| Mutex::new(RecentErrors::new()));
+
+struct RecentErrors(VecDeque<RecentError>);
+
+impl RecentErrors {
+  fn new() -> Self {
+    Self(VecDeque::with_capacity(MAX_RECENT_ERRORS))
+  }
+
+  fn push(&mut self, error: RecentError) {
+    if self.0.len() == MAX_RECENT_ERRORS {
+      self.0.pop_front();
+    }
+    self.0.push_back(error);
+  }
+
+  fn latest_first(&self) -> Vec<RecentError> {
+    self.0.iter().rev().cloned().collect()
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+  pub time: DateTime<Utc>,
+  pub source: &'static str,
+  pub message: String,
+}
+
+/// Keeps the error for the dashboard, only the latest `MAX_RECENT_ERRORS` are kept
+pub fn record_error(source: &'static str, message: String) {
+  RECENT_ERRORS.lock().push(RecentError {
+    time: Utc::now(),
+    source,
+    message,
+  });
+}
+
+#[derive(Debug, Serialize)]
+pub struct Dashboard {
+  pub nodes: Vec<DashboardNode>,
+  /// Games created on a node
+  pub games: Vec<DashboardGame>,
+  /// Latest first
+  pub recent_errors: Vec<RecentError>,
+  pub queues: DashboardQueues,
+  pub database: DbStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardNode {
+  #[serde(flatten)]
+  pub status: NodeStatus,
+  pub active_games: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardGame {
+  pub game_id: i32,
+  pub status: GameStatus,
+  pub node_id: Option<i32>,
+  pub players: Vec<DashboardPlayer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardPlayer {
+  pub player_id: i32,
+  pub client_status: Option<SlotClientStatus>,
+  /// Ping to the node of the game, `None` if the player is offline
+  pub ping: Option<PingStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DashboardQueues {
+  pub player_senders: SenderQueueStats,
+  /// Games waiting for a node with free capacity
+  pub queued_game_starts: usize,
+}
+
+pub async fn get(state: &ControllerStateRef) -> Result<Dashboard> {
+  let nodes = state.nodes.send(ListNodeStatus).await?;
+  let ids: Vec<i32> = nodes.iter().map(|status| status.node.id).collect();
+  let counts = state
+    .db
+    .exec(move |conn| {
+      ids
+        .into_iter()
+        .map(|id| Ok((id, crate::node::db::count_active_games(conn, id)?)))
+        .collect::<Result<BTreeMap<i32, i64>>>()
+    })
+    .await?;
+  let nodes = nodes
+    .into_iter()
+    .map(|status| DashboardNode {
+      active_games: counts.get(&status.node.id).cloned().unwrap_or_default(),
+      status,
+    })
+    .collect();
+
+  let summaries = list_game_summaries(&state.games).await?;
+  let queued_game_starts = summaries.iter().filter(|s| s.start_queued).count();
+  let summaries: Vec<GameSummary> = summaries
+    .into_iter()
+    .filter(|s| s.node_id.is_some() && s.status != GameStatus::Preparing)
+    .collect();
+  let ping_map = state
+    .player_packet_sender
+    .get_players_ping_map(
+      summaries
+        .iter()
+        .flat_map(|s| s.players.iter().cloned())
+        .collect(),
+    )
+    .await?;
+  let games = summaries
+    .into_iter()
+    .map(|summary| DashboardGame {
+      game_id: summary.game_id,
+      status: summary.status,
+      node_id: summary.node_id,
+      players: summary
+        .players
+        .iter()
+        .map(|player_id| DashboardPlayer {
+          player_id: *player_id,
+          client_status: summary.player_client_status_map.get(player_id).cloned(),
+          ping: summary.node_id.and_then(|node_id| {
+            ping_map
+              .get(player_id)
+              .and_then(|map| map.get(&node_id))
+              .cloned()
+          }),
+        })
+        .collect(),
+    })
+    .collect();
+
+  let recent_errors = RECENT_ERRORS.lock().latest_first();
+
+  Ok(Dashboard {
+    nodes,
+    games,
+    recent_errors,
+    queues: DashboardQueues {
+      player_senders: state.player_packet_sender.get_sender_queue_stats().await?,
+      queued_game_starts,
+    },
+    database: state.db.status(),
+  })
+}
+
+#[test]
+fn test_recent_errors() {
+  let mut errors = RecentErrors::new();
+  for i in 0..(MAX_RECENT_ERRORS + 1) {
+    errors.push(RecentError {
+      time: Utc::now(),
+      source: "test",
+      message: i.to_string(),
+    });
+  }
+  let list = errors.latest_first();
+  assert_eq!(list.len(), MAX_RECENT_ERRORS);
+  assert_eq!(list.first().unwrap().message, MAX_RECENT_ERRORS.to_string());
+  assert_eq!(list.last().unwrap().message, "1");
+}
@@ -1 +1,578 @@
-pub use bs_diesel_utils::{lock::transaction_with_advisory_lock, DbConn, Executor, ExecutorRef};
+//! Database executor of the lobby.
+//!
+//! Wraps the `bs_diesel_utils` executor with pool metrics, a per-query timeout and a
+//! circuit breaker: after consecutive connection failures or timeouts, queries fail
+//! right away with `Error::DbUnavailable` instead of piling up, until a trial query succeeds.
+//!
+//! Heavy read queries like game lists and stats go through `ExecutorRef::read`, which uses
+//! the read-only replica if one is configured. The replica can lag behind the primary, so
+//! reads that must see a write just made stay on the primary. Reads fall back to the primary
+//! while the replica's circuit is open or no connection to it can be made.
+//!
+//! Queries ported to `tokio_postgres` go through `ExecutorRef::exec_async`, which runs them
+//! on the async pool without the hop to the blocking thread pool. Each takes its diesel
+//! version as well, used while the async pool is disabled, so hot paths move over one
+//! query at a time and the rest keep using `exec`.
+
+pub use bs_diesel_utils::{lock::transaction_with_advisory_lock, DbConn, Executor};
+
+use bs_diesel_utils::result::DbError;
+use deadpool_postgres::{PoolConfig, PoolError, Runtime};
+use diesel::RunQueryDsl;
+use futures::Future;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use prometheus::{
+  register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Histogram,
+  HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The caller stops waiting and the database cancels the statement,
+/// queries still queued by then are dropped without taking a connection
+const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+/// `QUERY_TIMEOUT` in milliseconds
+const SET_STATEMENT_TIMEOUT: &str = "SET statement_timeout = 30000";
+const BREAKER_FAILURE_THRESHOLD: u32 = 5;
+const BREAKER_OPEN_DURATION: Duration = Duration::from_secs(10);
+
+static WAIT_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+  register_histogram_vec!(
+    "flocontroller_db_wait_seconds",
+    "Time queries waited for a database connection",
+    &["pool"]
+  )
+  .unwrap()
+});
+static QUERY_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+  register_histogram_vec!(
+    "flocontroller_db_query_seconds",
+    "Time queries held a database connection",
+    &["pool"]
+  )
+  .unwrap()
+});
+static ACTIVE_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+  register_int_gauge_vec!(
+    "flocontroller_db_active_connections",
+    "Database connections running a query",
+    &["pool"]
+  )
+  .unwrap()
+});
+static PENDING_QUERIES: Lazy<IntGaugeVec> = Lazy::new(|| {
+  register_int_gauge_vec!(
+    "flocontroller_db_pending_queries",
+    "Queries waiting for a database connection",
+    &["pool"]
+  )
+  .unwrap()
+});
+static TIMEOUTS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec!(
+    "flocontroller_db_timeouts_total",
+    "Queries that didn't complete in time",
+    &["pool"]
+  )
+  .unwrap()
+});
+static REJECTED: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec!(
+    "flocontroller_db_rejected_total",
+    "Queries rejected by the open circuit breaker",
+    &["pool"]
+  )
+  .unwrap()
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExecutorError<E> {
+  #[error("{0}")]
+  Task(E),
+  #[error("{0}")]
+  Executor(DbError),
+  #[error("database query timeout")]
+  Timeout,
+  #[error("database unavailable")]
+  Unavailable,
+  #[error("{0}")]
+  Pool(PoolError),
+}
+
+/// Connection of the async pool
+pub type AsyncConn = deadpool_postgres::Object;
+
+/// The query failed on the unique index or constraint named `constraint`
+pub fn is_unique_violation(err: &diesel::result::Error, constraint: &str) -> bool {
+  use diesel::result::{DatabaseErrorKind, Error};
+  match err {
+    Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+      info.constraint_name() == Some(constraint)
+    }
+    _ => false,
+  }
+}
+
+/// Escapes `\`, `%` and `_` so `value` matches literally in a LIKE pattern
+pub fn escape_like(value: &str) -> String {
+  let mut escaped = String::with_capacity(value.len());
+  for c in value.chars() {
+    if c == '\\' || c == '%' || c == '_' {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// Metrics of a pool, labeled by its name
+struct PoolMetrics {
+  wait_time: Histogram,
+  query_time: Histogram,
+  active_connections: IntGauge,
+  pending_queries: IntGauge,
+  timeouts: IntCounter,
+  rejected: IntCounter,
+}
+
+impl PoolMetrics {
+  fn new(name: &str) -> Self {
+    PoolMetrics {
+      wait_time: WAIT_TIME.with_label_values(&[name]),
+      query_time: QUERY_TIME.with_label_values(&[name]),
+      active_connections: ACTIVE_CONNECTIONS.with_label_values(&[name]),
+      pending_queries: PENDING_QUERIES.with_label_values(&[name]),
+      timeouts: TIMEOUTS.with_label_values(&[name]),
+      rejected: REJECTED.with_label_values(&[name]),
+    }
+  }
+}
+
+struct Pool {
+  executor: bs_diesel_utils::ExecutorRef,
+  breaker: CircuitBreaker,
+  metrics: Arc<PoolMetrics>,
+}
+
+impl Pool {
+  fn new(executor: bs_diesel_utils::ExecutorRef, name: &str) -> Self {
+    Pool {
+      executor,
+      breaker: CircuitBreaker::default(),
+      metrics: Arc::new(PoolMetrics::new(name)),
+    }
+  }
+
+  fn status(&self) -> PoolStatus {
+    PoolStatus {
+      circuit: self.breaker.state(Instant::now()),
+      active_connections: self.metrics.active_connections.get(),
+      pending_queries: self.metrics.pending_queries.get(),
+    }
+  }
+}
+
+/// Decrements the gauge when dropped, the query is dropped on timeout
+struct GaugeGuard<'a>(&'a IntGauge);
+
+impl<'a> GaugeGuard<'a> {
+  fn inc(gauge: &'a IntGauge) -> Self {
+    gauge.inc();
+    GaugeGuard(gauge)
+  }
+}
+
+impl<'a> Drop for GaugeGuard<'a> {
+  fn drop(&mut self) {
+    self.0.dec();
+  }
+}
+
+struct AsyncPool {
+  pool: deadpool_postgres::Pool,
+  breaker: CircuitBreaker,
+  metrics: Arc<PoolMetrics>,
+}
+
+impl AsyncPool {
+  fn status(&self) -> PoolStatus {
+    PoolStatus {
+      circuit: self.breaker.state(Instant::now()),
+      active_connections: self.metrics.active_connections.get(),
+      pending_queries: self.metrics.pending_queries.get(),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct ExecutorRef {
+  pool: Arc<Pool>,
+  replica: Option<Arc<Pool>>,
+  // used if the query can't be started on `pool`
+  fallback: Option<Arc<Pool>>,
+  // connects to the primary
+  async_pool: Option<Arc<AsyncPool>>,
+}
+
+impl ExecutorRef {
+  pub fn new(primary: bs_diesel_utils::ExecutorRef) -> Self {
+    Self {
+      pool: Arc::new(Pool::new(primary, "primary")),
+      replica: None,
+      fallback: None,
+      async_pool: None,
+    }
+  }
+
+  /// Runs `exec_async` queries on a `tokio_postgres` pool of the primary.
+  /// The pool connects without TLS, URLs with `sslmode=require` are rejected.
+  pub fn with_async_pool(self, url: &str, max_size: usize) -> crate::error::Result<Self> {
+    let pg_config: tokio_postgres::Config = url.parse()?;
+    if pg_config.get_ssl_mode() == tokio_postgres::config::SslMode::Require {
+      return Err(crate::error::Error::DbPoolTlsUnsupported);
+    }
+    let config = deadpool_postgres::Config {
+      url: Some(url.to_string()),
+      pool: Some(PoolConfig::new(max_size)),
+      ..Default::default()
+    };
+    let pool = config.create_pool(Some(Runtime::Tokio1), tokio_postgres::NoTls)?;
+    Ok(Self {
+      async_pool: Some(Arc::new(AsyncPool {
+        pool,
+        breaker: CircuitBreaker::default(),
+        metrics: Arc::new(PoolMetrics::new("async")),
+      })),
+      ..self
+    })
+  }
+
+  /// Routes `read` queries to the replica
+  pub fn with_replica(self, replica: bs_diesel_utils::ExecutorRef) -> Self {
+    Self {
+      replica: Some(Arc::new(Pool::new(replica, "replica"))),
+      ..self
+    }
+  }
+
+  /// Executor for heavy read-only queries, the primary if no replica is configured
+  pub fn read(&self) -> ExecutorRef {
+    match self.replica {
+      // the async pool doesn't connect to the replica
+      Some(ref replica) => Self {
+        pool: replica.clone(),
+        replica: None,
+        fallback: Some(self.pool.clone()),
+        async_pool: None,
+      },
+      None => Self {
+        pool: self.pool.clone(),
+        replica: None,
+        fallback: None,
+        async_pool: self.async_pool.clone(),
+      },
+    }
+  }
+
+  pub async fn exec<F, T, E>(&self, f: F) -> Result<T, ExecutorError<E>>
+  where
+    F: FnOnce(&DbConn) -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+  {
+    // taken by the task once it has a connection
+    let f = Arc::new(Mutex::new(Some(f)));
+    let r = Self::exec_on(&self.pool, f.clone()).await;
+    let fallback = match self.fallback {
+      Some(ref fallback) => fallback,
+      None => return r,
+    };
+    match r {
+      Err(ExecutorError::Unavailable) | Err(ExecutorError::Executor(_)) if f.lock().is_some() => {
+        tracing::warn!("replica unavailable, falling back to the primary");
+        Self::exec_on(fallback, f).await
+      }
+      r => r,
+    }
+  }
+
+  async fn exec_on<F, T, E>(pool: &Pool, f: Arc<Mutex<Option<F>>>) -> Result<T, ExecutorError<E>>
+  where
+    F: FnOnce(&DbConn) -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+  {
+    let metrics = pool.metrics.clone();
+    if !pool.breaker.acquire(Instant::now()) {
+      metrics.rejected.inc();
+      return Err(ExecutorError::Unavailable);
+    }
+
+    metrics.pending_queries.inc();
+    let queued_at = Instant::now();
+    // set by whichever of the task or the caller stops the query from pending first
+    let dequeued = Arc::new(AtomicBool::new(false));
+    let task = {
+      let dequeued = dequeued.clone();
+      let metrics = metrics.clone();
+      move |conn: &DbConn| {
+        if dequeued.swap(true, Ordering::SeqCst) {
+          // the caller has timed out
+          return Ok(None);
+        }
+        metrics.pending_queries.dec();
+        metrics.wait_time.observe(queued_at.elapsed().as_secs_f64());
+        if let Err(err) = diesel::sql_query(SET_STATEMENT_TIMEOUT).execute(conn) {
+          tracing::warn!("set statement timeout: {}", err);
+        }
+        metrics.active_connections.inc();
+        let timer = metrics.query_time.start_timer();
+        let f = f.lock().take().expect("query runs once");
+        let r = f(conn);
+        crate::cache::flush_pending_invalidations();
+        timer.observe_duration();
+        metrics.active_connections.dec();
+        r.map(Some)
+      }
+    };
+
+    let r = tokio::time::timeout(QUERY_TIMEOUT, pool.executor.exec(task)).await;
+    if !dequeued.swap(true, Ordering::SeqCst) {
+      metrics.pending_queries.dec();
+    }
+
+    match r {
+      Ok(Ok(Some(value))) => {
+        pool.breaker.on_success();
+        Ok(value)
+      }
+      // the database has answered
+      Ok(Err(bs_diesel_utils::executor::ExecutorError::Task(err))) => {
+        pool.breaker.on_success();
+        Err(ExecutorError::Task(err))
+      }
+      Ok(Err(bs_diesel_utils::executor::ExecutorError::Executor(err))) => {
+        pool.breaker.on_failure(Instant::now());
+        Err(ExecutorError::Executor(err))
+      }
+      Ok(Ok(None)) | Err(_) => {
+        metrics.timeouts.inc();
+        pool.breaker.on_failure(Instant::now());
+        Err(ExecutorError::Timeout)
+      }
+    }
+  }
+
+  /// Runs `f` on the async pool, or `blocking` on the executor if the async pool is disabled
+  pub async fn exec_async<A, Fut, F, T, E>(&self, f: A, blocking: F) -> Result<T, ExecutorError<E>>
+  where
+    A: FnOnce(AsyncConn) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    F: FnOnce(&DbConn) -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+  {
+    let pool = match self.async_pool {
+      Some(ref pool) => pool,
+      None => return self.exec(blocking).await,
+    };
+    let metrics = pool.metrics.clone();
+    if !pool.breaker.acquire(Instant::now()) {
+      metrics.rejected.inc();
+      return Err(ExecutorError::Unavailable);
+    }
+
+    let mut cancel_token = None;
+    let r = tokio::time::timeout(QUERY_TIMEOUT, async {
+      let pending = GaugeGuard::inc(&metrics.pending_queries);
+      let queued_at = Instant::now();
+      let conn = pool.pool.get().await.map_err(ExecutorError::Pool)?;
+      drop(pending);
+      cancel_token = Some(conn.cancel_token());
+      metrics.wait_time.observe(queued_at.elapsed().as_secs_f64());
+
+      let _active = GaugeGuard::inc(&metrics.active_connections);
+      let timer = metrics.query_time.start_timer();
+      let r = f(conn).await;
+      crate::cache::flush_pending_invalidations();
+      timer.observe_duration();
+      r.map_err(ExecutorError::Task)
+    })
+    .await;
+
+    match r {
+      Ok(r @ Ok(_)) | Ok(r @ Err(ExecutorError::Task(_))) => {
+        pool.breaker.on_success();
+        r
+      }
+      Ok(r) => {
+        pool.breaker.on_failure(Instant::now());
+        r
+      }
+      Err(_) => {
+        metrics.timeouts.inc();
+        pool.breaker.on_failure(Instant::now());
+        // the connection went back to the pool with the query still running
+        if let Some(token) = cancel_token {
+          tokio::spawn(async move {
+            if let Err(err) = token.cancel_query(tokio_postgres::NoTls).await {
+              tracing::warn!("cancel timed out query: {}", err);
+            }
+          });
+        }
+        Err(ExecutorError::Timeout)
+      }
+    }
+  }
+
+  pub fn status(&self) -> DbStatus {
+    DbStatus {
+      primary: self.pool.status(),
+      replica: self.replica.as_ref().map(|pool| pool.status()),
+      async_pool: self.async_pool.as_ref().map(|pool| pool.status()),
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DbStatus {
+  pub primary: PoolStatus,
+  pub replica: Option<PoolStatus>,
+  pub async_pool: Option<PoolStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolStatus {
+  pub circuit: CircuitState,
+  pub active_connections: i64,
+  pub pending_queries: i64,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+  Closed,
+  /// Queries are rejected
+  Open,
+  /// A trial query is allowed
+  HalfOpen,
+}
+
+#[derive(Debug)]
+enum BreakerState {
+  Closed {
+    failures: u32,
+  },
+  Open {
+    until: Instant,
+  },
+  /// The trial query is running, another one is allowed after `QUERY_TIMEOUT`
+  /// in case it was cancelled
+  HalfOpen {
+    since: Instant,
+  },
+}
+
+#[derive(Debug)]
+struct CircuitBreaker {
+  state: Mutex<BreakerState>,
+}
+
+impl Default for CircuitBreaker {
+  fn default() -> Self {
+    Self {
+      state: Mutex::new(BreakerState::Closed { failures: 0 }),
+    }
+  }
+}
+
+impl CircuitBreaker {
+  /// Returns `false` if the query should be rejected
+  fn acquire(&self, now: Instant) -> bool {
+    let mut state = self.state.lock();
+    match *state {
+      BreakerState::Closed { .. } => true,
+      BreakerState::Open { until } if now >= until => {
+        *state = BreakerState::HalfOpen { since: now };
+        true
+      }
+      BreakerState::HalfOpen { since } if now >= since + QUERY_TIMEOUT => {
+        *state = BreakerState::HalfOpen { since: now };
+        true
+      }
+      BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => false,
+    }
+  }
+
+  fn on_success(&self) {
+    *self.state.lock() = BreakerState::Closed { failures: 0 };
+  }
+
+  fn on_failure(&self, now: Instant) {
+    let mut state = self.state.lock();
+    let failures = match *state {
+      BreakerState::Closed { failures } => failures + 1,
+      BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => BREAKER_FAILURE_THRESHOLD,
+    };
+    *state = if failures >= BREAKER_FAILURE_THRESHOLD {
+      BreakerState::Open {
+        until: now + BREAKER_OPEN_DURATION,
+      }
+    } else {
+      BreakerState::Closed { failures }
+    };
+  }
+
+  fn state(&self, now: Instant) -> CircuitState {
+    match *self.state.lock() {
+      BreakerState::Closed { .. } => CircuitState::Closed,
+      BreakerState::Open { until } if now >= until => CircuitState::HalfOpen,
+      BreakerState::Open { .. } => CircuitState::Open,
+      BreakerState::HalfOpen { .. } => CircuitState::HalfOpen,
+    }
+  }
+}
+
+#[test]
+fn test_circuit_breaker() {
+  let breaker = CircuitBreaker::default();
+  let now = Instant::now();
+  for _ in 0..(BREAKER_FAILURE_THRESHOLD - 1) {
+    assert!(breaker.acquire(now));
+    breaker.on_failure(now);
+  }
+  assert_eq!(breaker.state(now), CircuitState::Closed);
+  // a success resets the count
+  breaker.on_success();
+  for _ in 0..BREAKER_FAILURE_THRESHOLD {
+    assert!(breaker.acquire(now));
+    breaker.on_failure(now);
+  }
+  assert_eq!(breaker.state(now), CircuitState::Open);
+  assert!(!breaker.acquire(now));
+
+  // a single trial query after the open duration
+  let later = now + BREAKER_OPEN_DURATION;
+  assert!(breaker.acquire(later));
+  assert!(!breaker.acquire(later));
+  breaker.on_failure(later);
+  assert!(!breaker.acquire(later));
+
+  // a cancelled trial doesn't keep the circuit open
+  let later = later + BREAKER_OPEN_DURATION;
+  assert!(breaker.acquire(later));
+  assert!(!breaker.acquire(later));
+  let later = later + QUERY_TIMEOUT;
+  assert!(breaker.acquire(later));
+  breaker.on_success();
+  assert_eq!(breaker.state(later), CircuitState::Closed);
+  assert!(breaker.acquire(later));
+}
+
+#[test]
+fn test_escape_like() {
+  assert_eq!(escape_like("100%_\\x"), "100\\%\\_\\\\x");
+  assert_eq!(escape_like("map"), "map");
+}
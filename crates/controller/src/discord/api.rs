@@ -0,0 +1,130 @@
+//! The few Discord REST endpoints used by the bot.
+
+use crate::error::*;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+const BASE_URL: &str = "https://discord.com/api/v9";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Discord rejects longer messages
+pub const MAX_MESSAGE_LEN: usize = 2000;
+
+static CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> =
+  Lazy::new(|| Client::builder().build(HttpsConnector::new()));
+
+#[derive(Debug, Deserialize)]
+pub struct Message {
+  /// Snowflake, later messages have larger ids
+  pub id: String,
+  pub content: String,
+  pub author: User,
+}
+
+impl Message {
+  pub fn snowflake(&self) -> u64 {
+    self.id.parse().unwrap_or_default()
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+  pub id: String,
+  #[serde(default)]
+  pub bot: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscordApi {
+  bot_token: String,
+}
+
+impl DiscordApi {
+  pub fn new(bot_token: &str) -> Self {
+    Self {
+      bot_token: bot_token.to_string(),
+    }
+  }
+
+  /// Text longer than `MAX_MESSAGE_LEN` is truncated
+  pub async fn create_message(&self, channel_id: &str, content: &str) -> Result<()> {
+    let content = truncate(content, MAX_MESSAGE_LEN);
+    self
+      .request::<serde_json::Value>(
+        Method::POST,
+        &format!("/channels/{}/messages", channel_id),
+        Some(json!({
+          "content": content,
+          // player and game names are never pings
+          "allowed_mentions": { "parse": [] },
+        })),
+      )
+      .await?;
+    Ok(())
+  }
+
+  /// Messages sent after `after_id`, or the latest message, newest first
+  pub async fn get_messages(
+    &self,
+    channel_id: &str,
+    after_id: Option<&str>,
+  ) -> Result<Vec<Message>> {
+    let path = match after_id {
+      Some(id) => format!("/channels/{}/messages?after={}&limit=50", channel_id, id),
+      None => format!("/channels/{}/messages?limit=1", channel_id),
+    };
+    self.request(Method::GET, &path, None).await
+  }
+
+  async fn request<T: DeserializeOwned>(
+    &self,
+    method: Method,
+    path: &str,
+    body: Option<serde_json::Value>,
+  ) -> Result<T> {
+    let req = Request::builder()
+      .method(method)
+      .uri(format!("{}{}", BASE_URL, path))
+      .header(
+        hyper::header::AUTHORIZATION,
+        format!("Bot {}", self.bot_token),
+      )
+      .header(
+        hyper::header::USER_AGENT,
+        concat!(
+          "DiscordBot (https://github.com/w3champions/flo, ",
+          env!("CARGO_PKG_VERSION"),
+          ")"
+        ),
+      )
+      .header(hyper::header::CONTENT_TYPE, "application/json")
+      .body(match body {
+        Some(body) => Body::from(body.to_string()),
+        None => Body::empty(),
+      })?;
+    let res = tokio::time::timeout(REQUEST_TIMEOUT, CLIENT.request(req))
+      .await
+      .map_err(|_| Error::Timeout(anyhow::format_err!("discord request timeout")))??;
+    if !res.status().is_success() {
+      return Err(Error::DiscordApi(res.status()));
+    }
+    let bytes = hyper::body::to_bytes(res.into_body()).await?;
+    Ok(serde_json::from_slice(&bytes)?)
+  }
+}
+
+fn truncate(content: &str, max_len: usize) -> &str {
+  if content.len() <= max_len {
+    return content;
+  }
+  let mut end = max_len;
+  while !content.is_char_boundary(end) {
+    end -= 1;
+  }
+  &content[..end]
+}
@@ -0,0 +1,433 @@
+//! Optional Discord bot, enabled by the `discord` section of the config.
+//!
+//! Public games are announced when created and their results are posted when they end.
+//! Commands are read by polling the command channels, so no gateway connection is needed:
+//! `<prefix> games` lists open games and `<prefix> stats <name or #id>` shows player stats.
+
+mod api;
+
+use crate::config::service_config;
+use crate::db::ExecutorRef;
+use crate::error::*;
+use crate::game::db::{GameStatusFilter, ListGameParams};
+use crate::game::{Game, GameEntry};
+use crate::player::db::PlayerStats;
+use crate::player::PlayerRef;
+use crate::state::Data;
+use api::{DiscordApi, Message};
+use flo_state::{
+  async_trait, Actor, Context, Handler, Message as ActorMessage, RegistryRef, Service,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_LISTED_GAMES: i64 = 10;
+/// Commands sent by the same user within the cooldown are ignored
+const COMMAND_COOLDOWN: Duration = Duration::from_secs(10);
+/// Answered by channel and poll, the remaining commands are ignored
+const MAX_COMMANDS_PER_POLL: usize = 5;
+
+/// Posts the game to the announce channels, private games are never announced
+pub fn announce_game_created(game: &Game) {
+  if game.is_private {
+    return;
+  }
+  let config = service_config();
+  if let Some(ref discord) = config.discord {
+    post(
+      DiscordApi::new(&discord.bot_token),
+      discord.announce_channel_ids.clone(),
+      format_game_created(game),
+    );
+  }
+}
+
+/// Posts the result to the result channels, private games are never posted
+pub fn announce_game_ended(db: &ExecutorRef, game_id: i32) {
+  let config = service_config();
+  let discord = match config.discord {
+    Some(ref discord) if !discord.result_channel_ids.is_empty() => discord.clone(),
+    _ => return,
+  };
+  let db = db.clone();
+  tokio::spawn(async move {
+    let game = match db
+      .exec(move |conn| crate::game::db::get_full(conn, game_id))
+      .await
+    {
+      Ok(game) => game,
+      Err(err) => {
+        tracing::error!(game_id, "discord: load ended game: {}", err);
+        return;
+      }
+    };
+    if !game.is_private {
+      post(
+        DiscordApi::new(&discord.bot_token),
+        discord.result_channel_ids,
+        format_game_ended(&game),
+      );
+    }
+  });
+}
+
+fn post(api: DiscordApi, channel_ids: Vec<String>, content: String) {
+  if channel_ids.is_empty() {
+    return;
+  }
+  tokio::spawn(async move {
+    for channel_id in channel_ids {
+      if let Err(err) = api.create_message(&channel_id, &content).await {
+        tracing::error!(channel_id = %channel_id, "discord: post message: {}", err);
+      }
+    }
+  });
+}
+
+/// Answers commands, only polls if the bot is enabled at startup
+pub struct DiscordBot {
+  db: ExecutorRef,
+  api: Option<DiscordApi>,
+  /// Id of the last seen message by channel
+  cursors: BTreeMap<String, String>,
+  /// Time of the last answered command by user
+  last_commands: BTreeMap<String, Instant>,
+}
+
+#[async_trait]
+impl Actor for DiscordBot {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    if self.api.is_some() {
+      self.handle(ctx, Poll).await;
+    }
+  }
+}
+
+#[async_trait]
+impl Service<Data> for DiscordBot {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    Ok(Self {
+      db: registry.data().db.clone(),
+      api: service_config()
+        .discord
+        .as_ref()
+        .map(|discord| DiscordApi::new(&discord.bot_token)),
+      cursors: BTreeMap::new(),
+      last_commands: BTreeMap::new(),
+    })
+  }
+}
+
+impl DiscordBot {
+  async fn poll(&mut self, api: &DiscordApi) {
+    let discord = match service_config().discord.clone() {
+      Some(discord) => discord,
+      None => return,
+    };
+    for channel_id in &discord.command_channel_ids {
+      if let Err(err) = self
+        .poll_channel(api, &discord.command_prefix, channel_id)
+        .await
+      {
+        tracing::warn!(channel_id = %channel_id, "discord: poll commands: {}", err);
+      }
+    }
+  }
+
+  async fn poll_channel(&mut self, api: &DiscordApi, prefix: &str, channel_id: &str) -> Result<()> {
+    let after_id = self.cursors.get(channel_id).cloned();
+    let mut messages = api.get_messages(channel_id, after_id.as_deref()).await?;
+    messages.sort_by_key(Message::snowflake);
+    let last_id = messages
+      .last()
+      .map(|message| message.id.clone())
+      .or_else(|| after_id.clone())
+      .unwrap_or_else(|| "0".to_string());
+    self.cursors.insert(channel_id.to_string(), last_id);
+
+    // messages sent before the bot started are not answered
+    if after_id.is_none() {
+      return Ok(());
+    }
+
+    let now = Instant::now();
+    self
+      .last_commands
+      .retain(|_, at| now.duration_since(*at) < COMMAND_COOLDOWN);
+
+    let mut answered = 0;
+    for message in messages {
+      if answered == MAX_COMMANDS_PER_POLL {
+        break;
+      }
+      if message.author.bot {
+        continue;
+      }
+      if let Some(command) = parse_command(prefix, &message.content) {
+        if self.last_commands.contains_key(&message.author.id) {
+          continue;
+        }
+        self.last_commands.insert(message.author.id.clone(), now);
+        answered += 1;
+        let reply = match self.reply(command).await {
+          Ok(reply) => reply,
+          Err(err) => {
+            tracing::error!("discord: command `{}`: {}", message.content, err);
+            "Something went wrong, please try again later.".to_string()
+          }
+        };
+        api.create_message(channel_id, &reply).await?;
+      }
+    }
+    Ok(())
+  }
+
+  async fn reply(&self, command: Command) -> Result<String> {
+    match command {
+      Command::Games => {
+        let params = ListGameParams {
+          status: GameStatusFilter::Open,
+          has_open_slots: Some(true),
+          take: Some(MAX_LISTED_GAMES),
+          ..Default::default()
+        };
+        let r = self
+          .db
+          .read()
+          .exec(move |conn| crate::game::db::list(conn, &params))
+          .await?;
+        Ok(format_game_list(&r.games))
+      }
+      Command::Stats(name) => {
+        let r = self
+          .db
+          .read()
+          .exec(move |conn| -> Result<_> {
+            let player = match name.strip_prefix('#').and_then(|id| id.parse().ok()) {
+              Some(id) => Some(crate::player::db::get_ref(conn, id)?),
+              None => crate::player::db::find_refs_by_name(conn, &name, 1)?
+                .into_iter()
+                .next(),
+            };
+            player
+              .map(|player| {
+                let stats = crate::player::db::get_stats(conn, player.id)?;
+                Ok((player, stats))
+              })
+              .transpose()
+          })
+          .await;
+        match r {
+          Ok(Some((player, stats))) => Ok(format_stats(&player, &stats)),
+          Ok(None) | Err(Error::PlayerNotFound) => Ok("Player not found.".to_string()),
+          Err(err) => Err(err),
+        }
+      }
+      Command::Help => Ok(HELP.to_string()),
+    }
+  }
+}
+
+struct Poll;
+
+impl ActorMessage for Poll {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Poll> for DiscordBot {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: Poll) {
+    if let Some(api) = self.api.clone() {
+      self.poll(&api).await;
+    }
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      tokio::time::sleep(POLL_INTERVAL).await;
+      addr.notify(Poll).await.ok();
+    });
+  }
+}
+
+const HELP: &str = "Commands: `games` lists open games, `stats <name or #id>` shows player stats.";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+  Games,
+  Stats(String),
+  Help,
+}
+
+fn parse_command(prefix: &str, content: &str) -> Option<Command> {
+  let rest = content.trim().strip_prefix(prefix)?;
+  // `!flogames` is not a command
+  if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+    return None;
+  }
+  let mut parts = rest.split_whitespace();
+  let command = match parts.next() {
+    Some("games") => Command::Games,
+    Some("stats") => {
+      let name = parts.collect::<Vec<_>>().join(" ");
+      if name.is_empty() {
+        Command::Help
+      } else {
+        Command::Stats(name)
+      }
+    }
+    _ => Command::Help,
+  };
+  Some(command)
+}
+
+/// Player and game names are not formatted as markdown
+fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    if matches!(
+      c,
+      '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '#' | '[' | ']'
+    ) {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+fn player_names(game: &Game) -> Vec<(i32, String)> {
+  game
+    .slots
+    .iter()
+    .enumerate()
+    .filter_map(|(idx, slot)| {
+      let player = slot.player.as_ref()?;
+      let name = if game.mask_player_names {
+        format!("Player {}", idx + 1)
+      } else {
+        escape(&player.name)
+      };
+      Some((slot.settings.team, name))
+    })
+    .collect()
+}
+
+fn format_game_created(game: &Game) -> String {
+  let names: Vec<_> = player_names(game)
+    .into_iter()
+    .map(|(_, name)| name)
+    .collect();
+  format!(
+    "New game **{}** (#{}) on {} by {}, {}/{} players: {}",
+    escape(&game.name),
+    game.id,
+    escape(&game.map.name),
+    if game.mask_player_names {
+      "Player 1".to_string()
+    } else {
+      escape(&game.created_by.name)
+    },
+    names.len(),
+    game.max_players,
+    names.join(", ")
+  )
+}
+
+fn format_game_ended(game: &Game) -> String {
+  let mut teams: BTreeMap<i32, Vec<String>> = BTreeMap::new();
+  for (team, name) in player_names(game) {
+    teams.entry(team).or_default().push(name);
+  }
+  let mut text = format!(
+    "Game **{}** (#{}) on {} ended",
+    escape(&game.name),
+    game.id,
+    escape(&game.map.name)
+  );
+  if let (Some(started_at), Some(ended_at)) = (game.started_at, game.ended_at) {
+    write!(text, " after {} min", (ended_at - started_at).num_minutes()).ok();
+  }
+  text.push('.');
+  for (team, names) in teams {
+    write!(text, "\nTeam {}: {}", team + 1, names.join(", ")).ok();
+  }
+  text
+}
+
+fn format_game_list(games: &[GameEntry]) -> String {
+  if games.is_empty() {
+    return "No open games.".to_string();
+  }
+  let mut text = String::from("Open games:");
+  for game in games {
+    write!(
+      text,
+      "\n#{} **{}** on {}, {}/{} players",
+      game.id,
+      escape(&game.name),
+      escape(&game.map_name),
+      game.num_players,
+      game.max_players
+    )
+    .ok();
+    if let Some(ref node) = game.node {
+      write!(text, ", {}", escape(&node.name)).ok();
+    }
+  }
+  text
+}
+
+fn format_stats(player: &PlayerRef, stats: &PlayerStats) -> String {
+  let mut text = format!(
+    "**{}** (#{}): {} games played",
+    escape(&player.name),
+    player.id,
+    stats.games
+  );
+  if let Some(rating) = stats.rating {
+    write!(text, ", rating {}", rating).ok();
+  }
+  if let Some(apm) = stats.average_apm {
+    write!(text, ", average APM {:.0}", apm).ok();
+  }
+  text
+}
+
+#[test]
+fn test_discord_commands() {
+  assert_eq!(parse_command("!flo", "!flo games"), Some(Command::Games));
+  assert_eq!(
+    parse_command("!flo", "  !flo   games  "),
+    Some(Command::Games)
+  );
+  assert_eq!(
+    parse_command("!flo", "!flo stats Some Player"),
+    Some(Command::Stats("Some Player".to_string()))
+  );
+  assert_eq!(parse_command("!flo", "!flo stats"), Some(Command::Help));
+  assert_eq!(parse_command("!flo", "!flo"), Some(Command::Help));
+  assert_eq!(parse_command("!flo", "!flogames"), None);
+  assert_eq!(parse_command("!flo", "games"), None);
+
+  assert_eq!(escape("*bold*_x_"), r"\*bold\*\_x\_");
+  assert_eq!(
+    format_stats(
+      &PlayerRef {
+        id: 1,
+        name: "a_b".to_string(),
+        source: crate::player::PlayerSource::Test,
+        realm: None,
+      },
+      &PlayerStats {
+        player_id: 1,
+        rating: Some(1500),
+        games: 12,
+        average_apm: Some(151.6),
+      }
+    ),
+    r"**a\_b** (#1): 12 games played, rating 1500, average APM 152"
+  );
+}
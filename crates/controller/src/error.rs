@@ -1,10 +1,14 @@
-use bs_diesel_utils::executor::ExecutorError;
+use crate::db::ExecutorError;
+use flo_net::proto::flo_common::ErrorCode;
 use flo_state::RegistryError;
+pub use flo_util::error::ErrorContext;
 use thiserror::Error;
 use tonic::Status;
 
 #[derive(Error, Debug)]
 pub enum Error {
+  #[error("Config: {0}")]
+  Config(#[from] flo_config::error::Error),
   #[error("Task cancelled")]
   TaskCancelled,
   #[error("Node not found")]
@@ -24,6 +28,16 @@ pub enum Error {
   NodeRequestTimeout,
   #[error("Node request cancelled")]
   NodeRequestCancelled,
+  #[error("Node has reached its game capacity")]
+  NodeFull,
+  #[error("No node available")]
+  NoNodeAvailable,
+  #[error("Server maintenance is scheduled at {0}, new games can not be created")]
+  Maintenance(chrono::DateTime<chrono::Utc>),
+  #[error("Invalid maintenance window")]
+  MaintenanceWindowInvalid,
+  #[error("No maintenance scheduled")]
+  MaintenanceNotScheduled,
   #[error("Invalid node address: {0}")]
   InvalidNodeAddress(String),
   #[error("Player stream closed")]
@@ -32,12 +46,46 @@ pub enum Error {
   PlayerTokenExpired,
   #[error("Join link expired")]
   JoinTokenExpired,
+  #[error("This invite is for another player")]
+  InviteNotForPlayer,
+  #[error("Incorrect game password")]
+  GamePasswordIncorrect,
+  #[error("Your account must be at least {0} days old to join public games")]
+  AccountTooNew(i64),
+  #[error("Too many sessions for this account")]
+  TooManySessions,
+  #[error("Already connected from another place")]
+  AlreadyConnected,
   #[error("You are not the host player")]
   PlayerNotHost,
   #[error("Player not found")]
   PlayerNotFound,
   #[error("Game not found")]
   GameNotFound,
+  #[error("Game template not found")]
+  GameTemplateNotFound,
+  #[error("Game template name contains a blocked word")]
+  GameTemplateNameBlocked,
+  #[error("Game schedule not found")]
+  GameScheduleNotFound,
+  #[error("Map not found")]
+  MapNotFound,
+  #[error("Map file not stored")]
+  MapNotStored,
+  #[error("Map is registered with a different file")]
+  MapFileConflict,
+  #[error("Invalid game schedule")]
+  GameScheduleInvalid,
+  #[error("Webhook not found")]
+  WebhookNotFound,
+  #[error("Webhook URL must be an absolute http or https URL")]
+  WebhookUrlInvalid,
+  #[error("Webhook secret must not be empty")]
+  WebhookSecretEmpty,
+  #[error("MOTD item not found")]
+  MotdItemNotFound,
+  #[error("Invalid MOTD item: empty title, or title or body too long")]
+  MotdItemInvalid,
   #[error("Only games with `Preparing` or `Created` status are cancellable")]
   GameNotCancellable,
   #[error("Invalid game data, please re-create")]
@@ -52,12 +100,24 @@ pub enum Error {
   GameLeaveRejected(flo_net::proto::flo_node::UpdateSlotClientStatusRejectReason),
   #[error("Game node not selected")]
   GameNodeNotSelected,
+  #[error("Game was moved to another node")]
+  GameNodeStale,
   #[error("Slot update denied")]
   GameSlotUpdateDenied,
+  #[error("Game slots changed, please retry")]
+  GameSlotsChanged,
+  #[error("Game waitlist is full")]
+  GameWaitlistFull,
   #[error("Game already started")]
   GameStarted,
   #[error("Game not in starting state")]
   GameNotStarting,
+  #[error("{0}")]
+  GameTransitionInvalid(#[from] flo_types::lifecycle::InvalidTransition),
+  #[error("Replays are only available after the game has ended")]
+  GameNotEnded,
+  #[error("Replays are only available to the players of the game")]
+  ReplayAccessDenied,
   #[error("This map has no player slot")]
   MapHasNoPlayer,
   #[error("Player not in game")]
@@ -72,8 +132,12 @@ pub enum Error {
   PlayerChannelClosed,
   #[error("Player source id is invalid")]
   PlayerSourceIdInvalid,
+  #[error("Player name is taken by another player")]
+  PlayerNameTaken,
   #[error("Invalid player source state")]
   InvalidPlayerSourceState,
+  #[error("Invalid player preferences")]
+  PlayerPreferencesInvalid,
   #[error("Actor not found")]
   ActorNotFound,
   #[error("Too many players")]
@@ -84,28 +148,263 @@ pub enum Error {
   PlayerColorConflict,
   #[error("Invalid player team value")]
   PlayerTeamInvalid,
+  #[error("Handicap must be a multiple of 10 between 50 and 100")]
+  HandicapInvalid,
+  #[error("Shared control pair must reference two different player slots")]
+  SharedControlPairInvalid,
+  #[error("Game metadata is too large or has an empty key")]
+  GameMetadataInvalid,
+  #[error("Request id must be 1 to 64 visible ASCII characters")]
+  GameRequestIdInvalid,
+  /// Rolls back a game created for a request id that an earlier request already used
+  #[error("Game already created by request: {0}")]
+  GameRequestDuplicated(i32),
+  #[error("Classic graphics value must be `true` or `false`")]
+  GameClassicGraphicsInvalid,
+  #[error("Game name must be 1 to 31 bytes without control characters")]
+  GameNameInvalid,
+  #[error("Game name contains a blocked word")]
+  GameNameBlocked,
+  #[error("No game name available, please enter a name")]
+  GameNameUnavailable,
+  #[error("No unique game identity available")]
+  GameIdentityUnavailable,
   #[error("Player not belongs to the current API client")]
   PlayerOwnerCheckFailed,
   #[error("Operation timeout: {0}")]
   Timeout(anyhow::Error),
+  #[error("Database unavailable")]
+  DbUnavailable,
   #[error("net: {0}")]
   Net(#[from] flo_net::error::Error),
   #[error("db error: {0}")]
   Db(#[from] bs_diesel_utils::result::DbError),
+  #[error("postgres: {0}")]
+  Postgres(#[from] tokio_postgres::Error),
+  #[error("db pool: {0}")]
+  DbPool(#[from] deadpool_postgres::PoolError),
+  #[error("create db pool: {0}")]
+  DbPoolCreate(#[from] deadpool_postgres::CreatePoolError),
+  #[error("the async database pool doesn't support TLS, set `database_async_pool_size` to 0")]
+  DbPoolTlsUnsupported,
+  #[error("invalid db value: {0}")]
+  DbValueInvalid(&'static str),
   #[error("db migration: {0}")]
   DbMigration(#[from] diesel_migrations::RunMigrationsError),
   #[error("json: {0}")]
   Json(#[from] serde_json::Error),
   #[error("json web token: {0}")]
   JsonWebToken(#[from] jsonwebtoken::errors::Error),
+  #[error("bcrypt: {0}")]
+  Bcrypt(#[from] bcrypt::BcryptError),
   #[error("proto: {0}")]
   Proto(#[from] s2_grpc_utils::result::Error),
   #[error("gRPC transport: {0}")]
   GrpcTransport(#[from] tonic::transport::Error),
+  #[error("Text filter: {0}")]
+  TextFilter(tonic::Status),
+  #[error("Invalid text filter url: {0}")]
+  TextFilterUrlInvalid(String),
+  #[error("gRPC reflection: {0}")]
+  GrpcReflection(#[from] tonic_reflection::server::Error),
+  #[error("http: {0}")]
+  Http(#[from] hyper::Error),
+  #[error("http request: {0}")]
+  HttpRequest(#[from] hyper::http::Error),
+  #[error("redis: {0}")]
+  Redis(#[from] redis::RedisError),
+  #[error("Discord API request failed: {0}")]
+  DiscordApi(hyper::StatusCode),
+  #[error("Unknown federation lobby")]
+  FederationPeerNotFound,
+  #[error("Invalid federation request signature")]
+  FederationSignatureInvalid,
+  #[error("Federation request failed: {0}")]
+  FederationRequest(hyper::StatusCode),
+  #[error("Too many remote join requests, please try again later")]
+  FederationBusy,
+  #[error("{source} ({context})")]
+  WithContext {
+    source: Box<Error>,
+    context: ErrorContext,
+  },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// gRPC metadata with the `ErrorCode` of failed requests, as a decimal number
+pub const STATUS_META_ERROR_CODE: &str = "x-flo-error-code";
+
+impl Error {
+  /// Adds the game, player or node ids to the error, see `ErrorContext`
+  pub fn with_context(self, context: ErrorContext) -> Self {
+    match self {
+      Error::WithContext {
+        source,
+        context: inner,
+      } => Error::WithContext {
+        source,
+        context: inner.merge(context),
+      },
+      source if context.is_empty() => source,
+      source => Error::WithContext {
+        source: Box::new(source),
+        context,
+      },
+    }
+  }
+
+  pub fn context(&self) -> ErrorContext {
+    match self {
+      Error::WithContext { context, .. } => *context,
+      _ => ErrorContext::default(),
+    }
+  }
+
+  /// The error without context, match on this instead of the error itself
+  pub fn root(&self) -> &Error {
+    match self {
+      Error::WithContext { source, .. } => source,
+      err => err,
+    }
+  }
+
+  pub fn into_root(self) -> Error {
+    match self {
+      Error::WithContext { source, .. } => *source,
+      err => err,
+    }
+  }
+
+  /// The message with the ids players are allowed to see
+  pub fn client_message(&self) -> String {
+    let context = self.context().client_safe();
+    if context.is_empty() {
+      self.root().to_string()
+    } else {
+      format!("{} ({})", self.root(), context)
+    }
+  }
+
+  /// The stable code sent to clients next to the message.
+  /// No wildcard arm so new variants have to pick one.
+  pub fn code(&self) -> ErrorCode {
+    use flo_net::proto::flo_node::ControllerCreateGameRejectReason;
+    match self {
+      Error::NodeNotFound => ErrorCode::NodeNotFound,
+      Error::NodeNotReady => ErrorCode::NodeNotReady,
+      Error::NodeConnectionRejected { .. } | Error::GameLeaveRejected(_) => ErrorCode::NodeRejected,
+      Error::NodeRequestTimeout | Error::PlayerChannelSendTimeout | Error::Timeout(_) => {
+        ErrorCode::Timeout
+      }
+      Error::NodeFull => ErrorCode::NodeFull,
+      Error::NoNodeAvailable => ErrorCode::NoNodeAvailable,
+      Error::Maintenance(_) => ErrorCode::Maintenance,
+      Error::NodeRequestProcessing
+      | Error::GameIdentityUnavailable
+      | Error::DbUnavailable
+      | Error::FederationBusy => ErrorCode::Unavailable,
+      Error::MaintenanceWindowInvalid
+      | Error::MaintenanceNotScheduled
+      | Error::InvalidNodeAddress(_)
+      | Error::GameScheduleInvalid
+      | Error::WebhookUrlInvalid
+      | Error::WebhookSecretEmpty
+      | Error::MotdItemInvalid
+      | Error::GameTemplateNameBlocked
+      | Error::PlayerSourceIdInvalid => ErrorCode::InvalidRequest,
+      Error::GameTemplateNotFound
+      | Error::GameScheduleNotFound
+      | Error::WebhookNotFound
+      | Error::MotdItemNotFound
+      | Error::FederationPeerNotFound => ErrorCode::NotFound,
+      Error::PlayerTokenExpired => ErrorCode::TokenExpired,
+      Error::JsonWebToken(_) => ErrorCode::InvalidToken,
+      Error::TooManySessions => ErrorCode::TooManySessions,
+      Error::AlreadyConnected => ErrorCode::AlreadyConnected,
+      Error::AccountTooNew(_) => ErrorCode::AccountTooNew,
+      Error::PlayerOwnerCheckFailed | Error::FederationSignatureInvalid => {
+        ErrorCode::PermissionDenied
+      }
+      Error::PlayerNotFound => ErrorCode::PlayerNotFound,
+      Error::PlayerNotHost => ErrorCode::PlayerNotHost,
+      Error::PlayerNotInGame | Error::PlayerSlotNotFound => ErrorCode::PlayerNotInGame,
+      Error::PlayerAlreadyInGame => ErrorCode::PlayerAlreadyInGame,
+      Error::PlayerPreferencesInvalid => ErrorCode::PlayerPreferencesInvalid,
+      Error::PlayerNameTaken => ErrorCode::PlayerNameTaken,
+      Error::GameNotFound => ErrorCode::GameNotFound,
+      Error::GameFull => ErrorCode::GameFull,
+      Error::GamePasswordIncorrect => ErrorCode::GamePasswordIncorrect,
+      Error::GameStarted => ErrorCode::GameStarted,
+      Error::GameNotStarting => ErrorCode::GameNotStarting,
+      Error::GameNotCancellable => ErrorCode::GameNotCancellable,
+      Error::GameNotEnded => ErrorCode::GameNotEnded,
+      Error::ReplayAccessDenied => ErrorCode::PermissionDenied,
+      Error::GameSlotsChanged => ErrorCode::GameSlotsChanged,
+      Error::GameSlotUpdateDenied => ErrorCode::GameSlotUpdateDenied,
+      Error::GameWaitlistFull => ErrorCode::GameWaitlistFull,
+      Error::GameCreating => ErrorCode::GameCreating,
+      Error::GameCreateReject(reason) => match reason {
+        ControllerCreateGameRejectReason::Unknown => ErrorCode::NodeRejected,
+        ControllerCreateGameRejectReason::GameExists => ErrorCode::GameStarted,
+        ControllerCreateGameRejectReason::PlayerBusy => ErrorCode::PlayerAlreadyInGame,
+        ControllerCreateGameRejectReason::Maintenance => ErrorCode::Maintenance,
+        ControllerCreateGameRejectReason::NodeFull => ErrorCode::NodeFull,
+      },
+      Error::GameNodeNotSelected | Error::GameNodeStale => ErrorCode::GameNodeNotSelected,
+      Error::MapHasNoPlayer
+      | Error::TooManyPlayers
+      | Error::GameHasNoPlayer
+      | Error::PlayerColorConflict
+      | Error::PlayerTeamInvalid
+      | Error::HandicapInvalid
+      | Error::SharedControlPairInvalid
+      | Error::GameMetadataInvalid
+      | Error::GameRequestIdInvalid
+      | Error::GameClassicGraphicsInvalid => ErrorCode::GameSettingsInvalid,
+      Error::GameNameInvalid => ErrorCode::GameNameInvalid,
+      Error::GameNameBlocked => ErrorCode::GameNameBlocked,
+      Error::GameNameUnavailable => ErrorCode::GameNameUnavailable,
+      Error::GameDataInvalid => ErrorCode::GameDataInvalid,
+      Error::JoinTokenExpired => ErrorCode::JoinTokenExpired,
+      Error::InviteNotForPlayer => ErrorCode::InviteNotForPlayer,
+      Error::MapNotFound | Error::MapNotStored => ErrorCode::MapNotFound,
+      Error::MapFileConflict => ErrorCode::MapFileConflict,
+      Error::Config(_)
+      | Error::TaskCancelled
+      | Error::NodeResponseUnexpected
+      | Error::NodeRequestCancelled
+      | Error::PlayerStreamClosed
+      | Error::PlayerChannelClosed
+      | Error::InvalidPlayerSourceState
+      | Error::GameTransitionInvalid(_)
+      | Error::GameRequestDuplicated(_)
+      | Error::ActorNotFound
+      | Error::Net(_)
+      | Error::Db(_)
+      | Error::Postgres(_)
+      | Error::DbPool(_)
+      | Error::DbPoolCreate(_)
+      | Error::DbPoolTlsUnsupported
+      | Error::DbValueInvalid(_)
+      | Error::DbMigration(_)
+      | Error::Json(_)
+      | Error::Bcrypt(_)
+      | Error::Proto(_)
+      | Error::GrpcTransport(_)
+      | Error::TextFilter(_)
+      | Error::TextFilterUrlInvalid(_)
+      | Error::GrpcReflection(_)
+      | Error::Http(_)
+      | Error::HttpRequest(_)
+      | Error::Redis(_)
+      | Error::DiscordApi(_)
+      | Error::FederationRequest(_) => ErrorCode::Internal,
+      Error::WithContext { source, .. } => source.code(),
+    }
+  }
+}
+
 impl From<Error> for String {
   fn from(e: Error) -> Self {
     format!("{}", e)
@@ -120,17 +419,67 @@ impl From<diesel::result::Error> for Error {
 
 impl From<Error> for Status {
   fn from(e: Error) -> Status {
-    match e {
+    let code = e.code();
+    let context = e.context().client_safe();
+    let mut status = match e.into_root() {
       e @ Error::GameNotFound
+      | e @ Error::GameTemplateNotFound
+      | e @ Error::GameScheduleNotFound
+      | e @ Error::MapNotFound
+      | e @ Error::MapNotStored
+      | e @ Error::GameScheduleInvalid
       | e @ Error::PlayerNotFound
+      | e @ Error::PlayerNameTaken
       | e @ Error::MapHasNoPlayer
       | e @ Error::GameFull
+      | e @ Error::GameWaitlistFull
       | e @ Error::GameNotCancellable
-      | e @ Error::JoinTokenExpired => Status::invalid_argument(e.to_string()),
+      | e @ Error::GameNotEnded
+      | e @ Error::JoinTokenExpired
+      | e @ Error::InviteNotForPlayer
+      | e @ Error::HandicapInvalid
+      | e @ Error::SharedControlPairInvalid
+      | e @ Error::GameMetadataInvalid
+      | e @ Error::GameRequestIdInvalid
+      | e @ Error::GameClassicGraphicsInvalid
+      | e @ Error::GameNameInvalid
+      | e @ Error::GameNameBlocked
+      | e @ Error::GameTemplateNameBlocked
+      | e @ Error::GameNameUnavailable
+      | e @ Error::WebhookNotFound
+      | e @ Error::WebhookUrlInvalid
+      | e @ Error::WebhookSecretEmpty
+      | e @ Error::MotdItemNotFound
+      | e @ Error::MotdItemInvalid => Status::invalid_argument(e.to_string()),
+      e @ Error::NodeFull | e @ Error::NoNodeAvailable => Status::resource_exhausted(e.to_string()),
+      e @ Error::GameSlotsChanged => Status::aborted(e.to_string()),
+      e @ Error::MapFileConflict => Status::already_exists(e.to_string()),
+      e @ Error::Maintenance(_) | e @ Error::DbUnavailable | e @ Error::FederationBusy => {
+        Status::unavailable(e.to_string())
+      }
+      e @ Error::MaintenanceWindowInvalid | e @ Error::MaintenanceNotScheduled => {
+        Status::invalid_argument(e.to_string())
+      }
       e @ Error::PlayerTokenExpired => Status::unauthenticated(e.to_string()),
+      e @ Error::GamePasswordIncorrect
+      | e @ Error::AccountTooNew(_)
+      | e @ Error::TooManySessions
+      | e @ Error::AlreadyConnected
+      | e @ Error::ReplayAccessDenied => Status::permission_denied(e.to_string()),
       Error::JsonWebToken(e) => Status::unauthenticated(e.to_string()),
-      e => Status::internal(e.to_string()),
+      e => {
+        let message = e.to_string();
+        crate::dashboard::record_error("grpc", message.clone());
+        Status::internal(message)
+      }
+    };
+    if !context.is_empty() {
+      status = Status::new(status.code(), format!("{} ({})", status.message(), context));
     }
+    if let Ok(value) = (code as i32).to_string().parse() {
+      status.metadata_mut().insert(STATUS_META_ERROR_CODE, value);
+    }
+    status
   }
 }
 
@@ -139,6 +488,9 @@ impl From<ExecutorError<diesel::result::Error>> for Error {
     match e {
       ExecutorError::Task(e) => Error::Db(e.into()),
       ExecutorError::Executor(e) => e.into(),
+      ExecutorError::Timeout => Error::Timeout(anyhow::format_err!("database query")),
+      ExecutorError::Unavailable => Error::DbUnavailable,
+      ExecutorError::Pool(e) => Error::DbPool(e),
     }
   }
 }
@@ -148,6 +500,9 @@ impl From<ExecutorError<Error>> for Error {
     match e {
       ExecutorError::Task(e) => e,
       ExecutorError::Executor(e) => Error::Db(e),
+      ExecutorError::Timeout => Error::Timeout(anyhow::format_err!("database query")),
+      ExecutorError::Unavailable => Error::DbUnavailable,
+      ExecutorError::Pool(e) => Error::DbPool(e),
     }
   }
 }
@@ -169,6 +524,20 @@ impl From<flo_state::RegistryError> for Error {
   }
 }
 
+/// Helper trait to add an `ErrorContext` to the error of a `Result`
+pub trait ErrorContextExt<T> {
+  fn with_context(self, context: ErrorContext) -> Result<T>;
+}
+
+impl<T, E> ErrorContextExt<T> for Result<T, E>
+where
+  E: Into<Error>,
+{
+  fn with_context(self, context: ErrorContext) -> Result<T> {
+    self.map_err(|err| err.into().with_context(context))
+  }
+}
+
 /// Helper trait to convert Option<Result<T>> to Result<T>
 pub trait TaskCancelledExt<T> {
   fn or_cancelled(self) -> Result<T>;
@@ -186,3 +555,45 @@ where
     }
   }
 }
+
+#[test]
+fn test_error_code_status() {
+  assert_eq!(Error::GameFull.code(), ErrorCode::GameFull);
+  assert_eq!(
+    Error::Maintenance(chrono::Utc::now()).code(),
+    ErrorCode::Maintenance
+  );
+  assert_eq!(Error::ActorNotFound.code(), ErrorCode::Internal);
+
+  let status = Status::from(Error::GamePasswordIncorrect);
+  assert_eq!(status.code(), tonic::Code::PermissionDenied);
+  let value = status.metadata().get(STATUS_META_ERROR_CODE).unwrap();
+  assert_eq!(
+    value.to_str().unwrap(),
+    (ErrorCode::GamePasswordIncorrect as i32).to_string()
+  );
+}
+
+#[test]
+fn test_error_context() {
+  let err = Error::GameFull
+    .with_context(ErrorContext::game(1).with_node(3))
+    .with_context(ErrorContext::player(2).with_game(4));
+  assert!(matches!(err.root(), Error::GameFull));
+  assert_eq!(err.code(), ErrorCode::GameFull);
+  assert_eq!(
+    err.to_string(),
+    "The game you are trying to join is full (game_id = 1, player_id = 2, node_id = 3)"
+  );
+  assert_eq!(
+    err.client_message(),
+    "The game you are trying to join is full (game_id = 1, player_id = 2)"
+  );
+
+  let status = Status::from(err);
+  assert_eq!(status.code(), tonic::Code::InvalidArgument);
+  assert_eq!(
+    status.message(),
+    "The game you are trying to join is full (game_id = 1, player_id = 2)"
+  );
+}
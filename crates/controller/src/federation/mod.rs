@@ -0,0 +1,263 @@
+//! Federation with other flo lobbies, enabled by the `federation` section of the config.
+//!
+//! Peers poll each other's `GET /federation/games` for their public open games, which are
+//! listed by `GET /remote-games` and in the `remote_games` of the game list. Players join a
+//! remote game through their own lobby: it asserts the player's identity to the peer with
+//! `POST /federation/join`, the peer creates a player for them and answers with a player
+//! token and an invite token to connect with. Only the games of the peer itself are shared,
+//! games it got from its own peers are not.
+//!
+//! Requests are signed with the secret shared by both lobbies, the caller sends its lobby id
+//! in `x-flo-lobby`, the unix time in `x-flo-timestamp` and
+//! `x-flo-signature: sha256=<hex HMAC-SHA256 of "<timestamp>\n<path>\n<body>">`.
+
+#[cfg(feature = "http")]
+pub mod server;
+
+use crate::config::service_config;
+use crate::error::*;
+use crate::game::GameEntry;
+use crate::player::state::game_list::{status_to_proto, GameListChange};
+use crate::player::state::sender::PlayerRegistryHandle;
+use crate::player::state::PlayerRegistry;
+use crate::player::PlayerRef;
+use crate::state::Data;
+use chrono::Utc;
+use flo_config::FederationPeer;
+use flo_net::proto::flo_connect::GameListEntry;
+use flo_state::{async_trait, Actor, Context, Handler, Message, RegistryRef, Service};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+pub const HEADER_LOBBY: &str = "x-flo-lobby";
+pub const HEADER_TIMESTAMP: &str = "x-flo-timestamp";
+pub const HEADER_SIGNATURE: &str = "x-flo-signature";
+
+pub const PATH_GAMES: &str = "/federation/games";
+pub const PATH_JOIN: &str = "/federation/join";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Games of a peer that can't be reached are dropped after this long
+const REMOTE_GAMES_TTL: Duration = Duration::from_secs(3 * 60);
+
+static CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> =
+  Lazy::new(|| Client::builder().build(HttpsConnector::new()));
+
+/// A public game of a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteGame {
+  pub lobby_id: String,
+  #[serde(flatten)]
+  pub game: GameEntry,
+}
+
+/// The identity of a player of the calling lobby, trusted as the request is signed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemotePlayer {
+  pub id: i32,
+  pub name: String,
+  pub realm: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JoinRequest {
+  pub game_id: i32,
+  pub player: RemotePlayer,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JoinResponse {
+  /// The player of the peer that represents the remote player
+  pub player_id: i32,
+  pub player_token: String,
+  /// Invite token for the game, accepted once connected with the player token
+  pub join_token: String,
+}
+
+fn signing_message(timestamp: i64, path: &str, body: &[u8]) -> Vec<u8> {
+  let mut message = format!("{}\n{}\n", timestamp, path).into_bytes();
+  message.extend_from_slice(body);
+  message
+}
+
+async fn request<T: DeserializeOwned>(
+  peer: &FederationPeer,
+  method: Method,
+  path: &str,
+  body: Option<Vec<u8>>,
+) -> Result<T> {
+  let lobby_id = service_config()
+    .federation
+    .as_ref()
+    .map(|federation| federation.lobby_id.clone())
+    .ok_or_else(|| Error::FederationPeerNotFound)?;
+  let body = body.unwrap_or_default();
+  let timestamp = Utc::now().timestamp();
+  let signature = crate::webhook::sign(&peer.secret, &signing_message(timestamp, path, &body));
+  let req = Request::builder()
+    .method(method)
+    .uri(format!("{}{}", peer.url.trim_end_matches('/'), path))
+    .header(hyper::header::CONTENT_TYPE, "application/json")
+    .header(HEADER_LOBBY, lobby_id)
+    .header(HEADER_TIMESTAMP, timestamp.to_string())
+    .header(HEADER_SIGNATURE, signature)
+    .body(Body::from(body))?;
+  let res = tokio::time::timeout(REQUEST_TIMEOUT, CLIENT.request(req))
+    .await
+    .map_err(|_| Error::Timeout(anyhow::format_err!("federation request timeout")))??;
+  if !res.status().is_success() {
+    return Err(Error::FederationRequest(res.status()));
+  }
+  let bytes = hyper::body::to_bytes(res.into_body()).await?;
+  Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Asks the peer to let the local player join its game
+pub async fn request_join(
+  peer: &FederationPeer,
+  game_id: i32,
+  player: PlayerRef,
+) -> Result<JoinResponse> {
+  let body = serde_json::to_vec(&JoinRequest {
+    game_id,
+    player: RemotePlayer {
+      id: player.id,
+      name: player.name,
+      realm: player.realm,
+    },
+  })?;
+  request(peer, Method::POST, PATH_JOIN, Some(body)).await
+}
+
+/// Polls the peers for their public games
+pub struct FederationSync {
+  players: PlayerRegistryHandle,
+  remote_games: BTreeMap<String, PeerGames>,
+}
+
+struct PeerGames {
+  fetched_at: Instant,
+  games: Vec<GameEntry>,
+}
+
+impl FederationSync {
+  async fn poll(&mut self) {
+    let peers = match service_config().federation {
+      Some(ref federation) => federation.peers.clone(),
+      None => vec![],
+    };
+    self
+      .remote_games
+      .retain(|lobby_id, _| peers.iter().any(|peer| &peer.lobby_id == lobby_id));
+    for peer in peers {
+      match request::<Vec<GameEntry>>(&peer, Method::GET, PATH_GAMES, None).await {
+        Ok(games) => {
+          self.remote_games.insert(
+            peer.lobby_id,
+            PeerGames {
+              fetched_at: Instant::now(),
+              games,
+            },
+          );
+        }
+        Err(err) => {
+          tracing::warn!(lobby_id = %peer.lobby_id, "federation: list games: {}", err);
+        }
+      }
+    }
+
+    let entries = self
+      .remote_games()
+      .into_iter()
+      .map(|remote| GameListEntry {
+        id: remote.game.id,
+        name: remote.game.name,
+        map_name: remote.game.map_name,
+        players: remote.game.num_players,
+        max_players: remote.game.max_players,
+        status: status_to_proto(remote.game.status),
+        lobby_id: remote.lobby_id,
+      })
+      .collect();
+    if let Err(err) = self
+      .players
+      .update_game_list(GameListChange::Remote(entries))
+      .await
+    {
+      tracing::error!("federation: update game list: {}", err);
+    }
+  }
+
+  fn remote_games(&self) -> Vec<RemoteGame> {
+    let now = Instant::now();
+    self
+      .remote_games
+      .iter()
+      .filter(|(_, peer_games)| now.duration_since(peer_games.fetched_at) < REMOTE_GAMES_TTL)
+      .flat_map(|(lobby_id, peer_games)| {
+        peer_games.games.iter().map(move |game| RemoteGame {
+          lobby_id: lobby_id.clone(),
+          game: game.clone(),
+        })
+      })
+      .collect()
+  }
+}
+
+#[async_trait]
+impl Actor for FederationSync {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    self.handle(ctx, Poll).await;
+  }
+}
+
+#[async_trait]
+impl Service<Data> for FederationSync {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    let players = registry.resolve::<PlayerRegistry>().await?;
+    Ok(Self {
+      players: players.into(),
+      remote_games: BTreeMap::new(),
+    })
+  }
+}
+
+struct Poll;
+
+impl Message for Poll {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Poll> for FederationSync {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: Poll) {
+    self.poll().await;
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      tokio::time::sleep(POLL_INTERVAL).await;
+      addr.notify(Poll).await.ok();
+    });
+  }
+}
+
+pub struct GetRemoteGames;
+
+impl Message for GetRemoteGames {
+  type Result = Vec<RemoteGame>;
+}
+
+#[async_trait]
+impl Handler<GetRemoteGames> for FederationSync {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetRemoteGames) -> Vec<RemoteGame> {
+    self.remote_games()
+  }
+}
@@ -0,0 +1,194 @@
+//! Endpoints called by the peers, served by the http module.
+
+use super::*;
+use crate::db::DbConn;
+use crate::game::db::{GameStatusFilter, ListGameParams};
+use crate::game::GameStatus;
+use crate::player::db::UpsertPlayer;
+use crate::player::PlayerSource;
+use hmac::{Hmac, Mac, NewMac};
+use hyper::header::HeaderMap;
+use parking_lot::Mutex;
+use sha2::Sha256;
+use std::collections::HashMap;
+
+/// Also bounds the clock difference between the lobbies
+const MAX_REQUEST_AGE_SECS: i64 = 5 * 60;
+const MAX_SHARED_GAMES: i64 = 100;
+
+/// Signatures accepted within `MAX_REQUEST_AGE_SECS`, a request is only accepted once
+static SEEN_SIGNATURES: Lazy<Mutex<SeenSignatures>> =
+  Lazy::new(|| Mutex::new(SeenSignatures::default()));
+
+/// Returns the peer that signed the request
+pub fn verify_request(headers: &HeaderMap, path: &str, body: &[u8]) -> Result<FederationPeer> {
+  let peer = header(headers, HEADER_LOBBY)
+    .and_then(|lobby_id| {
+      service_config()
+        .federation
+        .as_ref()
+        .and_then(|federation| federation.peer(lobby_id))
+        .cloned()
+    })
+    .ok_or_else(|| Error::FederationPeerNotFound)?;
+  let now = Utc::now().timestamp();
+  let (timestamp, signature) = check_signature(&peer.secret, headers, path, body, now)?;
+  if !SEEN_SIGNATURES.lock().insert(signature, timestamp, now) {
+    tracing::warn!(lobby_id = %peer.lobby_id, path, "federation: replayed request");
+    return Err(Error::FederationSignatureInvalid);
+  }
+  Ok(peer)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+  headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Returns the timestamp and the signature of the request
+fn check_signature(
+  secret: &str,
+  headers: &HeaderMap,
+  path: &str,
+  body: &[u8],
+  now: i64,
+) -> Result<(i64, Vec<u8>)> {
+  let timestamp: i64 = header(headers, HEADER_TIMESTAMP)
+    .and_then(|v| v.parse().ok())
+    .ok_or_else(|| Error::FederationSignatureInvalid)?;
+  if (now - timestamp).abs() > MAX_REQUEST_AGE_SECS {
+    return Err(Error::FederationSignatureInvalid);
+  }
+  let signature = header(headers, HEADER_SIGNATURE)
+    .and_then(|v| v.strip_prefix("sha256="))
+    .and_then(decode_hex)
+    .ok_or_else(|| Error::FederationSignatureInvalid)?;
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+  mac.update(&signing_message(timestamp, path, body));
+  // constant time
+  mac
+    .verify(&signature)
+    .map_err(|_| Error::FederationSignatureInvalid)?;
+  Ok((timestamp, signature))
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+  if value.len() % 2 != 0 {
+    return None;
+  }
+  (0..value.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(value.get(i..(i + 2))?, 16).ok())
+    .collect()
+}
+
+#[derive(Debug, Default)]
+struct SeenSignatures {
+  map: HashMap<Vec<u8>, i64>,
+}
+
+impl SeenSignatures {
+  /// Returns `false` if the signature was already seen.
+  /// Signatures too old to pass the age check are forgotten.
+  fn insert(&mut self, signature: Vec<u8>, timestamp: i64, now: i64) -> bool {
+    self
+      .map
+      .retain(|_, timestamp| now - *timestamp <= MAX_REQUEST_AGE_SECS);
+    if self.map.contains_key(&signature) {
+      return false;
+    }
+    self.map.insert(signature, timestamp);
+    true
+  }
+}
+
+/// Public games shared with the peers
+pub fn list_shared_games(conn: &DbConn) -> Result<Vec<GameEntry>> {
+  let params = ListGameParams {
+    status: GameStatusFilter::Open,
+    is_private: Some(false),
+    take: Some(MAX_SHARED_GAMES),
+    ..Default::default()
+  };
+  Ok(crate::game::db::list(conn, &params)?.games)
+}
+
+/// Creates or updates the player that represents the remote player, and issues the tokens
+pub fn accept_join(conn: &DbConn, peer: &FederationPeer, req: JoinRequest) -> Result<JoinResponse> {
+  let game = crate::game::db::get(conn, req.game_id)?;
+  if game.is_private {
+    return Err(Error::GameNotFound);
+  }
+  if game.status != GameStatus::Preparing {
+    return Err(Error::GameStarted);
+  }
+
+  let player = crate::player::db::upsert(
+    conn,
+    &UpsertPlayer {
+      api_client_id: peer.api_client_id,
+      name: req.player.name,
+      source: PlayerSource::Api,
+      source_id: remote_source_id(&peer.lobby_id, req.player.id),
+      source_state: None,
+      realm: req.player.realm,
+    },
+  )?;
+  Ok(JoinResponse {
+    player_id: player.id,
+    player_token: crate::player::token::create_player_token(player.id)?,
+    join_token: crate::game::token::create_invite_token(req.game_id, player.id)?,
+  })
+}
+
+fn remote_source_id(lobby_id: &str, player_id: i32) -> String {
+  format!("federation:{}:{}", lobby_id, player_id)
+}
+
+#[test]
+fn test_federation_signature() {
+  use hyper::header::HeaderValue;
+
+  let now = 1_627_776_000;
+  let body = br#"{"game_id":1}"#;
+  let mut headers = HeaderMap::new();
+  headers.insert(HEADER_TIMESTAMP, HeaderValue::from(now));
+  headers.insert(
+    HEADER_SIGNATURE,
+    HeaderValue::from_str(&crate::webhook::sign(
+      "secret",
+      &signing_message(now, PATH_JOIN, body),
+    ))
+    .unwrap(),
+  );
+  let (timestamp, signature) = check_signature("secret", &headers, PATH_JOIN, body, now).unwrap();
+  assert_eq!(timestamp, now);
+
+  let mut seen = SeenSignatures::default();
+  assert!(seen.insert(signature.clone(), timestamp, now));
+  assert!(!seen.insert(signature.clone(), timestamp, now + MAX_REQUEST_AGE_SECS));
+  assert!(seen.insert(signature, timestamp, now + MAX_REQUEST_AGE_SECS + 1));
+
+  assert!(check_signature(
+    "secret",
+    &headers,
+    PATH_JOIN,
+    body,
+    now + MAX_REQUEST_AGE_SECS
+  )
+  .is_ok());
+  assert!(check_signature("other", &headers, PATH_JOIN, body, now).is_err());
+  assert!(check_signature("secret", &headers, PATH_GAMES, body, now).is_err());
+  assert!(check_signature("secret", &headers, PATH_JOIN, b"{}", now).is_err());
+  // replayed later
+  assert!(check_signature(
+    "secret",
+    &headers,
+    PATH_JOIN,
+    body,
+    now + MAX_REQUEST_AGE_SECS + 1
+  )
+  .is_err());
+  headers.remove(HEADER_TIMESTAMP);
+  assert!(check_signature("secret", &headers, PATH_JOIN, body, now).is_err());
+}
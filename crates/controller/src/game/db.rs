@@ -6,13 +6,16 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
-use crate::db::DbConn;
+use crate::db::{AsyncConn, DbConn};
 use crate::error::*;
+use crate::game::metadata::GameMetadata;
+use crate::game::name::NameVars;
+use crate::game::request::CreateRequestId;
 use crate::game::slots::{UsedSlot, UsedSlotInfo};
 use crate::game::state::GameStatusUpdate;
 use crate::game::{
-  Computer, CreateGameSlot, Game, GameEntry, GameStatus, Race, Slot, SlotClientStatus,
-  SlotSettings, SlotStatus, Slots,
+  Computer, CreateGameSlot, Game, GameEntry, GameRules, GameStatus, Race, SharedControlPair, Slot,
+  SlotClientStatus, SlotSettings, SlotStatus, Slots,
 };
 use crate::map::Map;
 use crate::node::{NodeRef, NodeRefColumns, PlayerToken};
@@ -20,6 +23,8 @@ use crate::player::{PlayerRef, PlayerRefColumns};
 use crate::schema::{game, game_used_slot, node, player};
 use diesel::pg::expression::dsl::{all, any};
 
+const PASSWORD_HASH_COST: u32 = 10;
+
 pub fn get(conn: &DbConn, id: i32) -> Result<GameRowWithRelated> {
   let row = game::table
     .find(id)
@@ -34,6 +39,7 @@ pub fn get(conn: &DbConn, id: i32) -> Result<GameRowWithRelated> {
 
 #[derive(Debug, Deserialize, Default, S2ProtoUnpack)]
 #[s2_grpc(message_type = "flo_grpc::controller::ListGamesRequest")]
+#[serde(default)]
 pub struct QueryGameParams {
   pub keyword: Option<String>,
   pub status: GameStatusFilter,
@@ -43,7 +49,7 @@ pub struct QueryGameParams {
   pub since_id: Option<i32>,
 }
 
-#[derive(Debug, S2ProtoPack)]
+#[derive(Debug, Serialize, S2ProtoPack)]
 #[s2_grpc(message_type = "flo_grpc::controller::ListGamesReply")]
 pub struct QueryGame {
   pub games: Vec<GameEntry>,
@@ -66,6 +72,131 @@ impl Default for GameStatusFilter {
   }
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ListGameParams {
+  pub keyword: Option<String>,
+  pub map_name: Option<String>,
+  pub region: Option<String>,
+  pub created_after: Option<DateTime<Utc>>,
+  pub has_open_slots: Option<bool>,
+  pub status: GameStatusFilter,
+  pub sort: GameListSort,
+  pub take: Option<i64>,
+  /// Id of the last game of the previous page
+  pub cursor: Option<i32>,
+  /// Only games with all of these metadata entries
+  pub metadata: GameMetadata,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GameListSort {
+  CreatedDesc,
+  CreatedAsc,
+  MapName,
+}
+
+impl Default for GameListSort {
+  fn default() -> Self {
+    Self::CreatedDesc
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListGame {
+  pub games: Vec<GameEntry>,
+  pub next_cursor: Option<i32>,
+}
+
+/// Lists public games, pages are keyed by the sort columns of the cursor game
+/// so later pages don't scan the skipped rows
+pub fn list(conn: &DbConn, params: &ListGameParams) -> Result<ListGame> {
+  use diesel::sql_types::{Bool, Integer, Jsonb};
+  use game::dsl;
+
+  let take = params.take.unwrap_or(30).max(1).min(100);
+
+  let mut q = game::table
+    .left_outer_join(node::table)
+    .left_outer_join(player::table)
+    .select(GameEntry::columns_with_num_players())
+    .filter(dsl::is_private.eq(false))
+    .limit(take + 1)
+    .into_boxed();
+
+  q = match params.sort {
+    GameListSort::CreatedDesc => q.order((dsl::created_at.desc(), dsl::id.desc())),
+    GameListSort::CreatedAsc => q.order((dsl::created_at.asc(), dsl::id.asc())),
+    GameListSort::MapName => q.order((dsl::map_name.asc(), dsl::id.asc())),
+  };
+
+  if let Some(cursor) = params.cursor.clone() {
+    let cond = match params.sort {
+      GameListSort::CreatedDesc => {
+        "(game.created_at, game.id) < (select c.created_at, c.id from game c where c.id = "
+      }
+      GameListSort::CreatedAsc => {
+        "(game.created_at, game.id) > (select c.created_at, c.id from game c where c.id = "
+      }
+      GameListSort::MapName => {
+        "(game.map_name, game.id) > (select c.map_name, c.id from game c where c.id = "
+      }
+    };
+    q = q.filter(sql::<Bool>(cond).bind::<Integer, _>(cursor).sql(")"));
+  }
+
+  if let Some(ref keyword) = params.keyword {
+    let like = format!("%{}%", keyword.trim());
+    q = q.filter(dsl::name.ilike(like.clone()).or(dsl::map_name.ilike(like)));
+  }
+
+  if let Some(ref map_name) = params.map_name {
+    q = q.filter(dsl::map_name.ilike(format!("%{}%", map_name.trim())));
+  }
+
+  if let Some(region) = params.region.clone() {
+    q = q.filter(node::dsl::region.nullable().eq(region));
+  }
+
+  if let Some(created_after) = params.created_after.clone() {
+    q = q.filter(dsl::created_at.gt(created_after));
+  }
+
+  if !params.metadata.is_empty() {
+    q = q.filter(
+      sql::<Bool>(crate::game::metadata::CONTAINS_SQL)
+        .bind::<Jsonb, _>(serde_json::to_value(&params.metadata)?),
+    );
+  }
+
+  if let Some(has_open_slots) = params.has_open_slots.clone() {
+    let op = if has_open_slots { "<" } else { ">=" };
+    q = q.filter(sql::<Bool>(&format!(
+      "{} {} game.max_players",
+      crate::game::NUM_PLAYERS_SQL,
+      op
+    )));
+  }
+
+  match params.status {
+    GameStatusFilter::All => q = q.filter(dsl::status.ne(GameStatus::Ended)),
+    GameStatusFilter::Open => q = q.filter(dsl::status.eq(GameStatus::Preparing)),
+    GameStatusFilter::Live => q = q.filter(dsl::status.eq(GameStatus::Running)),
+    GameStatusFilter::Ended => q = q.filter(dsl::status.eq(GameStatus::Ended)),
+  }
+
+  let mut games: Vec<GameEntry> = q.load(conn)?;
+
+  let next_cursor = if games.len() > take as usize {
+    games.truncate(take as usize);
+    games.last().map(|game| game.id)
+  } else {
+    None
+  };
+
+  Ok(ListGame { games, next_cursor })
+}
+
 pub fn get_entry(conn: &DbConn, id: i32) -> Result<GameEntry> {
   let q = game::table
     .find(id)
@@ -138,6 +269,7 @@ pub fn query(conn: &DbConn, params: &QueryGameParams) -> Result<QueryGame> {
 
 pub fn cancel(conn: &DbConn, game_id: i32, created_by: Option<i32>) -> Result<()> {
   use game::dsl;
+  crate::cache::invalidate_game_after_commit(game_id);
 
   let mut q = game::table.find(game_id).into_boxed();
   if let Some(created_by) = created_by {
@@ -167,46 +299,107 @@ pub struct CreateGameParams {
   pub is_live: bool,
 }
 
+/// Game settings that are not part of `CreateGameParams`
+#[derive(Debug, Default)]
+pub struct CreateGameOptions {
+  /// Players have to provide it to join, unless they join with a join token
+  pub password: Option<String>,
+  pub rules: GameRules,
+  /// Layout of the slots not taken by the creator, e.g. from a game template
+  pub slots: Option<Vec<SlotSettings>>,
+  pub metadata: GameMetadata,
+  /// Recorded with the game so retries return it, see `crate::game::request`
+  pub request_id: Option<CreateRequestId>,
+}
+
 /// Creates a game, make the creator as the first player
-pub fn create(conn: &DbConn, params: CreateGameParams) -> Result<Game> {
+pub fn create(conn: &DbConn, params: CreateGameParams, options: CreateGameOptions) -> Result<Game> {
   let max_players = params.map.players.len();
 
   if max_players == 0 {
     return Err(Error::MapHasNoPlayer);
   }
 
+  // the slots are taken later, the pairs of empty slots are ignored by the node
+  check_shared_control_pairs(&options.rules.shared_control_pairs, |slot_id| {
+    (slot_id as usize) < max_players
+  })?;
+  crate::game::metadata::validate(&options.metadata)?;
+  let layout = options
+    .slots
+    .as_ref()
+    .map(|layout| {
+      layout
+        .iter()
+        .map(|settings| {
+          Ok(SlotSettings {
+            handicap: normalize_handicap(settings.handicap)?,
+            ..settings.clone()
+          })
+        })
+        .collect::<Result<Vec<_>>>()
+    })
+    .transpose()?;
+
   let player = crate::player::db::get_ref(conn, params.player_id)?;
-  let mut slots = Slots::new(max_players);
+  let name = crate::game::name::resolve(
+    conn,
+    &params.name,
+    NameVars {
+      map: &params.map.name,
+      host: &player.name,
+    },
+  )?;
+  let preferences = crate::player_preferences::db::get(conn, params.player_id)?;
+  let force_teams = crate::map::db::get_force_teams(conn, &params.map)?;
+  let mut slots = Slots::new(max_players).with_fixed_teams(force_teams.clone());
   slots.join(&player);
+  if let Some(layout) = layout.as_ref() {
+    slots.apply_layout(layout);
+  }
+  slots.apply_player_preferences(player.id, preferences.race, preferences.color);
 
+  let request_id = options.request_id;
   let meta = Meta {
     map: params.map,
     created_by: player.into(),
+    rules: options.rules,
+    password_hash: options
+      .password
+      .map(|password| bcrypt::hash(password, PASSWORD_HASH_COST))
+      .transpose()?,
+    metadata: options.metadata,
+    force_teams,
   };
 
   let meta_value = serde_json::to_value(&meta)?;
 
+  let name_folded = flo_util::name::fold(&name);
   let insert = GameInsert {
-    name: &params.name,
+    name: &name,
+    name_folded: &name_folded,
     map_name: &meta.map.name,
     is_private: params.is_private,
     is_live: params.is_live,
     max_players: max_players as i32,
     created_by: Some(params.player_id),
     meta: meta_value,
-    random_seed: rand::random(),
+    // replaced by `identity::allocate`
+    random_seed: 0,
     locked: false,
     node_id: None,
     mask_player_names: false,
   };
 
   let row = conn.transaction(|| -> Result<_> {
-    let id: i32 = diesel::insert_into(game::table)
-      .values(&insert)
-      .returning(game::dsl::id)
-      .get_result(conn)?;
+    let id = insert_game(conn, &insert)?;
+    crate::game::identity::allocate(conn, id)?;
+    if let Some(ref request_id) = request_id {
+      crate::game::request::record(conn, request_id, id)?;
+    }
     let row = get(conn, id)?;
     upsert_used_slots(conn, row.id, slots.as_used())?;
+    crate::map::db::record_hosted(conn, &meta.map)?;
     Ok(row)
   })?;
   Ok(row.into_game(meta, slots.into_inner())?)
@@ -230,9 +423,13 @@ pub fn create_as_bot(
   api_client_id: i32,
   api_player_id: i32,
   params: CreateGameAsBotParams,
+  rules: GameRules,
+  metadata: GameMetadata,
+  request_id: Option<CreateRequestId>,
 ) -> Result<Game> {
   use std::collections::{BTreeMap, BTreeSet};
   let max_players = params.map.players.len();
+  let force_teams = crate::map::db::get_force_teams(conn, &params.map)?;
 
   if max_players == 0 {
     return Err(Error::MapHasNoPlayer);
@@ -253,6 +450,11 @@ pub fn create_as_bot(
     return Err(Error::TooManyPlayers);
   }
 
+  check_shared_control_pairs(&rules.shared_control_pairs, |slot_id| {
+    player_slots.iter().any(|(i, _)| *i == slot_id as usize)
+  })?;
+  crate::game::metadata::validate(&metadata)?;
+
   let mut player_ids: Vec<i32> = params
     .slots
     .iter()
@@ -286,13 +488,24 @@ pub fn create_as_bot(
       return Err(Error::PlayerTeamInvalid);
     }
 
+    if let Some(ref teams) = force_teams {
+      if teams.get(*i) != Some(&slot.settings.team) {
+        return Err(Error::PlayerTeamInvalid);
+      }
+    }
+
+    let handicap = normalize_handicap(slot.settings.handicap)?;
+
     let player = slot.player_id.clone().and_then(|id| players.remove(&id));
     if slot.player_id.is_some() && player.is_none() {
       return Err(Error::PlayerNotFound);
     }
     slots.push(UsedSlot {
       slot_index: *i as i32,
-      settings: slot.settings.clone(),
+      settings: SlotSettings {
+        handicap,
+        ..slot.settings.clone()
+      },
       client_status: SlotClientStatus::Pending,
       player,
     });
@@ -316,45 +529,121 @@ pub fn create_as_bot(
 
   let slots = Slots::from_used(max_players, slots);
 
+  let created_by = players
+    .remove(&api_player_id)
+    .ok_or_else(|| Error::PlayerNotFound)?;
+  let name = crate::game::name::resolve(
+    conn,
+    &params.name,
+    NameVars {
+      map: &params.map.name,
+      host: &created_by.name,
+    },
+  )?;
+
   let meta = Meta {
     map: params.map,
-    created_by: players
-      .remove(&api_player_id)
-      .ok_or_else(|| Error::PlayerNotFound)?
-      .into(),
+    created_by: created_by.into(),
+    rules,
+    password_hash: None,
+    metadata,
+    force_teams,
   };
 
   let meta_value = serde_json::to_value(&meta)?;
 
+  let name_folded = flo_util::name::fold(&name);
   let insert = GameInsert {
-    name: &params.name,
+    name: &name,
+    name_folded: &name_folded,
     map_name: &meta.map.name,
     is_private: params.is_private,
     is_live: params.is_live,
     max_players: max_players as i32,
     created_by: Some(api_player_id),
     meta: meta_value,
-    random_seed: rand::random(),
+    // replaced by `identity::allocate`
+    random_seed: 0,
     locked: true,
     node_id: Some(params.node_id),
     mask_player_names: params.mask_player_names.unwrap_or_default(),
   };
 
   let row = conn.transaction(|| -> Result<_> {
-    let id: i32 = diesel::insert_into(game::table)
-      .values(&insert)
-      .returning(game::dsl::id)
-      .get_result(conn)?;
+    crate::node::db::check_capacity(conn, params.node_id, None)?;
+    let id = insert_game(conn, &insert)?;
+    crate::game::identity::allocate(conn, id)?;
+    if let Some(ref request_id) = request_id {
+      crate::game::request::record(conn, request_id, id)?;
+    }
     let row = get(conn, id)?;
     upsert_used_slots(conn, row.id, slots.as_used())?;
+    crate::map::db::record_hosted(conn, &meta.map)?;
     Ok(row)
   })?;
 
   Ok(row.into_game(meta, slots.into_inner())?)
 }
 
-/// Adds a player into a game
-pub fn add_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Vec<Slot>> {
+/// Fails with `Error::GameNameUnavailable` if an active game took the name in the meantime
+fn insert_game(conn: &DbConn, insert: &GameInsert) -> Result<i32> {
+  diesel::insert_into(game::table)
+    .values(insert)
+    .returning(game::dsl::id)
+    .get_result(conn)
+    .map_err(|err| {
+      if crate::db::is_unique_violation(&err, "game_name_folded_active") {
+        Error::GameNameUnavailable
+      } else {
+        err.into()
+      }
+    })
+}
+
+// 0 is the unset value and means no handicap
+fn normalize_handicap(handicap: i32) -> Result<i32> {
+  match handicap {
+    0 => Ok(100),
+    50..=100 if handicap % 10 == 0 => Ok(handicap),
+    _ => Err(Error::HandicapInvalid),
+  }
+}
+
+fn check_shared_control_pairs<F>(pairs: &[SharedControlPair], is_player_slot: F) -> Result<()>
+where
+  F: Fn(u32) -> bool,
+{
+  for pair in pairs {
+    if pair.slot_id == pair.with_slot_id
+      || !is_player_slot(pair.slot_id)
+      || !is_player_slot(pair.with_slot_id)
+    {
+      return Err(Error::SharedControlPairInvalid);
+    }
+  }
+  Ok(())
+}
+
+/// Verifies the password of a game that has one, the hash is slow so it runs outside of
+/// transactions
+pub fn check_password(conn: &DbConn, game_id: i32, password: Option<&str>) -> Result<()> {
+  if let Some(hash) = get_meta(conn, game_id)?.password_hash {
+    match password {
+      Some(password) if bcrypt::verify(password, &hash)? => {}
+      _ => return Err(Error::GamePasswordIncorrect),
+    }
+  }
+  Ok(())
+}
+
+/// Adds a player into a game.
+/// `reserved_slots` open slots are held for other players and can't be taken
+pub fn add_player(
+  conn: &DbConn,
+  game_id: i32,
+  player_id: i32,
+  reserved_slots: usize,
+) -> Result<Vec<Slot>> {
   let InspectId { status, locked } = inspect_id(conn, game_id)?;
 
   if locked {
@@ -365,30 +654,42 @@ pub fn add_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Vec<Slo
     return Err(Error::GameStarted);
   }
 
-  let mut slots = get_slots(conn, game_id)?.slots;
+  let GetSlots {
+    mut slots,
+    slots_version,
+    ..
+  } = get_slots(conn, game_id)?;
 
   if slots.find_player_slot(player_id).is_some() {
     return Err(Error::PlayerAlreadyInGame);
   }
 
-  if slots.is_full() {
+  if slots.open_slots() <= reserved_slots {
     return Err(Error::GameFull);
   }
 
   let player = crate::player::db::get_ref(conn, player_id)?;
+  let preferences = crate::player_preferences::db::get(conn, player_id)?;
 
   slots.join(&player);
+  slots.apply_player_preferences(player_id, preferences.race, preferences.color);
 
+  bump_slots_version(conn, game_id, slots_version)?;
   upsert_used_slots(conn, game_id, slots.as_used())?;
 
   Ok(slots.into_inner())
 }
 
+pub fn get_open_slots(conn: &DbConn, game_id: i32) -> Result<usize> {
+  Ok(get_slots(conn, game_id)?.slots.open_slots())
+}
+
 #[derive(Debug)]
 pub struct LeaveGame {
   pub game_ended: bool,
   pub removed_players: Vec<i32>,
   pub slots: Vec<Slot>,
+  pub slots_version: i32,
 }
 
 pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<LeaveGame> {
@@ -405,23 +706,27 @@ pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Leav
   let GetSlots {
     mut slots,
     host_player_id,
+    mut slots_version,
   } = get_slots(conn, game_id)?;
 
   // host left, kick all players
   if player_id == host_player_id {
     let removed = slots.release_all_player_slots();
+    slots_version = bump_slots_version(conn, game_id, slots_version)?;
     upsert_used_slots(conn, game_id, slots.as_used())?;
     end_game(conn, game_id, GameStatus::Ended)?;
     Ok(LeaveGame {
       game_ended: true,
       removed_players: removed,
       slots: slots.into_inner(),
+      slots_version,
     })
   } else {
     let mut ended = false;
     let mut removed_players = Vec::with_capacity(1);
     if slots.release_player_slot(player_id) {
       removed_players.push(player_id);
+      slots_version = bump_slots_version(conn, game_id, slots_version)?;
       upsert_used_slots(conn, game_id, slots.as_used())?;
       if slots.is_empty() {
         ended = true;
@@ -432,10 +737,59 @@ pub fn remove_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<Leav
       game_ended: ended,
       removed_players,
       slots: slots.into_inner(),
+      slots_version,
     })
   }
 }
 
+pub struct TransferHost {
+  pub host: PlayerRef,
+  pub slots: Vec<Slot>,
+}
+
+pub fn transfer_host(
+  conn: &DbConn,
+  game_id: i32,
+  player_id: i32,
+  new_host_player_id: i32,
+) -> Result<TransferHost> {
+  use game::dsl;
+
+  let InspectId { status, locked } = inspect_id(conn, game_id)?;
+
+  if locked {
+    return Err(Error::GameSlotUpdateDenied);
+  }
+
+  if status != GameStatus::Preparing {
+    return Err(Error::GameStarted);
+  }
+
+  let GetSlots {
+    slots,
+    host_player_id,
+  } = get_slots(conn, game_id)?;
+
+  if host_player_id != player_id {
+    return Err(Error::PlayerNotHost);
+  }
+
+  let host = slots
+    .find_player_slot(new_host_player_id)
+    .and_then(|slot| slot.player.clone())
+    .ok_or_else(|| Error::PlayerNotInGame)?;
+
+  crate::cache::invalidate_game_after_commit(game_id);
+  diesel::update(game::table.find(game_id))
+    .set(dsl::created_by.eq(new_host_player_id))
+    .execute(conn)?;
+
+  Ok(TransferHost {
+    host,
+    slots: slots.into_inner(),
+  })
+}
+
 #[derive(Queryable)]
 struct InspectId {
   status: GameStatus,
@@ -455,6 +809,7 @@ fn inspect_id(conn: &DbConn, game_id: i32) -> Result<InspectId> {
 
 pub fn leave_node(conn: &DbConn, game_id: i32, player_id: i32) -> Result<()> {
   use game_used_slot::dsl;
+  crate::cache::invalidate_game_after_commit(game_id);
   diesel::update(
     game_used_slot::table.filter(dsl::game_id.eq(game_id).and(dsl::player_id.eq(player_id))),
   )
@@ -478,6 +833,17 @@ pub fn get_node_active_player_ids(conn: &DbConn, game_id: i32) -> Result<Vec<i32
     .map_err(Into::into)
 }
 
+/// The player had a slot in the game, including players that left
+pub fn has_player(conn: &DbConn, game_id: i32, player_id: i32) -> Result<bool> {
+  use diesel::dsl::{exists, select};
+  use game_used_slot::dsl;
+  select(exists(game_used_slot::table.filter(
+    dsl::game_id.eq(game_id).and(dsl::player_id.eq(player_id)),
+  )))
+  .get_result(conn)
+  .map_err(Into::into)
+}
+
 #[derive(Debug, Queryable)]
 pub struct SlotOwnerInfo {
   pub host_player_id: i32,
@@ -515,13 +881,17 @@ pub fn get_slot_owner_info(conn: &DbConn, game_id: i32, slot_index: i32) -> Resu
 pub struct UpdateSlotSettings {
   pub slots: Vec<Slot>,
   pub updated_indexes: Vec<i32>,
+  pub slots_version: i32,
 }
 
+/// Fails with `Error::GameSlotsChanged` if `expected_version` is set and the slot layout
+/// changed since, e.g. the player was looking at a slot someone else just took.
 pub fn update_slot_settings(
   conn: &DbConn,
   game_id: i32,
   slot_index: i32,
   settings: SlotSettings,
+  expected_version: Option<i32>,
 ) -> Result<UpdateSlotSettings> {
   let InspectId { status, locked } = inspect_id(conn, game_id)?;
 
@@ -533,10 +903,19 @@ pub fn update_slot_settings(
     return Err(Error::GameStarted);
   }
 
-  let mut slots = get_slots(conn, game_id)?.slots;
+  let GetSlots {
+    mut slots,
+    mut slots_version,
+    ..
+  } = get_slots(conn, game_id)?;
+  if matches!(expected_version, Some(v) if v != slots_version) {
+    return Err(Error::GameSlotsChanged);
+  }
+
   let mut updated_indexes = vec![];
-  if let Some(slots) = slots.update_slot_at(slot_index, &settings) {
-    for (index, slot) in slots {
+  if let Some(updated) = slots.update_slot_at(slot_index, &settings) {
+    slots_version = bump_slots_version(conn, game_id, slots_version)?;
+    for (index, slot) in updated {
       sync_slot_at(conn, game_id, index as i32, &slot)?;
       updated_indexes.push(index);
     }
@@ -544,11 +923,49 @@ pub fn update_slot_settings(
   Ok(UpdateSlotSettings {
     slots: slots.into_inner(),
     updated_indexes,
+    slots_version,
+  })
+}
+
+/// Reassign player teams by rating, only the host can balance teams
+pub fn balance_teams(conn: &DbConn, game_id: i32, player_id: i32) -> Result<UpdateSlotSettings> {
+  let InspectId { status, locked } = inspect_id(conn, game_id)?;
+
+  if locked {
+    return Err(Error::GameSlotUpdateDenied);
+  }
+
+  if status != GameStatus::Preparing {
+    return Err(Error::GameStarted);
+  }
+
+  let GetSlots {
+    host_player_id,
+    mut slots,
+    mut slots_version,
+  } = get_slots(conn, game_id)?;
+  if host_player_id != player_id {
+    return Err(Error::PlayerNotHost);
+  }
+
+  let ratings = crate::player::db::get_rating_map(conn, &slots.get_player_ids())?;
+  let updated_indexes = slots.balance_teams(&ratings);
+  if !updated_indexes.is_empty() {
+    slots_version = bump_slots_version(conn, game_id, slots_version)?;
+  }
+  for index in &updated_indexes {
+    sync_slot_at(conn, game_id, *index, &slots[*index as usize])?;
+  }
+  Ok(UpdateSlotSettings {
+    slots: slots.into_inner(),
+    updated_indexes,
+    slots_version,
   })
 }
 
 fn sync_slot_at(conn: &DbConn, game_id: i32, slot_index: i32, slot: &Slot) -> Result<()> {
   use game_used_slot::dsl;
+  crate::cache::invalidate_game_after_commit(game_id);
 
   if slot.is_used() {
     diesel::insert_into(game_used_slot::table)
@@ -577,6 +994,7 @@ pub fn update_slot_client_status(
   status: SlotClientStatus,
 ) -> Result<()> {
   use game_used_slot::dsl;
+  crate::cache::invalidate_game_after_commit(game_id);
 
   diesel::update(
     game_used_slot::table.filter(
@@ -594,6 +1012,7 @@ pub fn update_slot_client_status(
 pub fn update_status(conn: &DbConn, update: &GameStatusUpdate) -> Result<()> {
   let game_id = update.game_id;
   let game_status = GameStatus::from(update.status);
+  crate::cache::invalidate_game_after_commit(game_id);
   conn.transaction(|| {
     diesel::update(game::table.find(update.game_id))
       .set(game::dsl::status.eq(game_status))
@@ -628,6 +1047,24 @@ pub fn update_status(conn: &DbConn, update: &GameStatusUpdate) -> Result<()> {
       .set(game_used_slot::client_status.eq(*status))
       .execute(conn)?;
     }
+
+    for stats in &update.player_stats {
+      diesel::update(
+        game_used_slot::table.filter(
+          game_used_slot::dsl::game_id
+            .eq(game_id)
+            .and(game_used_slot::player_id.eq(stats.player_id)),
+        ),
+      )
+      .set(game_used_slot::action_stats.eq(serde_json::to_value(stats)?))
+      .execute(conn)?;
+    }
+
+    if let Some(ref traffic) = update.traffic {
+      diesel::update(game::table.find(game_id))
+        .set(game::dsl::traffic_stats.eq(serde_json::to_value(traffic)?))
+        .execute(conn)?;
+    }
     Ok(())
   })
 }
@@ -640,6 +1077,7 @@ fn upsert_used_slots(conn: &DbConn, game_id: i32, used_slots: Vec<UsedSlot>) ->
     .into_iter()
     .map(|slot| UsedSlotInsert::from_used_slot(game_id, slot))
     .collect();
+  crate::cache::invalidate_game_after_commit(game_id);
   conn.transaction(|| {
     diesel::delete(
       game_used_slot::table.filter(
@@ -671,20 +1109,27 @@ fn upsert_used_slots(conn: &DbConn, game_id: i32, used_slots: Vec<UsedSlot>) ->
 struct GetSlots {
   host_player_id: i32,
   slots: Slots,
+  slots_version: i32,
 }
 
 fn get_slots(conn: &DbConn, game_id: i32) -> Result<GetSlots> {
   use game_used_slot::dsl;
 
-  let (host_player_id, max_players): (i32, i32) = {
+  let (host_player_id, max_players, slots_version, meta): (i32, i32, i32, Value) = {
     use game::dsl;
     game::table
       .find(game_id)
-      .select((dsl::created_by, dsl::max_players))
+      .select((
+        dsl::created_by,
+        dsl::max_players,
+        dsl::slots_version,
+        dsl::meta,
+      ))
       .first(conn)
       .optional()?
       .ok_or_else(|| Error::GameNotFound)?
   };
+  let meta: Meta = serde_json::from_value(meta)?;
 
   let used_slots: Vec<UsedSlot> = game_used_slot::table
     .left_outer_join(player::table)
@@ -692,13 +1137,32 @@ fn get_slots(conn: &DbConn, game_id: i32) -> Result<GetSlots> {
     .filter(dsl::game_id.eq(game_id))
     .load(conn)?;
 
-  let slots = Slots::from_used(max_players as usize, used_slots);
+  let slots =
+    Slots::from_used(max_players as usize, used_slots).with_fixed_teams(meta.force_teams.clone());
   Ok(GetSlots {
     host_player_id,
     slots,
+    slots_version,
   })
 }
 
+/// Bumps the slot layout version read with the slots, must be called in the transaction
+/// writing the slots: fails if another update changed the layout since.
+fn bump_slots_version(conn: &DbConn, game_id: i32, slots_version: i32) -> Result<i32> {
+  use game::dsl;
+  let n = diesel::update(
+    game::table
+      .find(game_id)
+      .filter(dsl::slots_version.eq(slots_version)),
+  )
+  .set(dsl::slots_version.eq(slots_version + 1))
+  .execute(conn)?;
+  if n == 0 {
+    return Err(Error::GameSlotsChanged);
+  }
+  Ok(slots_version + 1)
+}
+
 fn get_used_slots(conn: &DbConn, game_id: i32) -> Result<Vec<UsedSlot>> {
   use game_used_slot::dsl;
   game_used_slot::table
@@ -732,6 +1196,38 @@ pub fn get_player_active_slots(conn: &DbConn, player_id: i32) -> Result<Vec<Play
   Ok(rows)
 }
 
+/// Game ids of `get_player_active_slots` on the async pool
+pub async fn get_player_active_game_ids_async(
+  conn: &AsyncConn,
+  player_id: i32,
+) -> Result<Vec<i32>> {
+  let active_status: Vec<i32> = GameStatus::active_variants()
+    .iter()
+    .map(|status| *status as i32)
+    .collect();
+  let gone_client_status = vec![
+    SlotClientStatus::Disconnected as i32,
+    SlotClientStatus::Left as i32,
+  ];
+  let stmt = conn
+    .prepare_cached(
+      r#"
+SELECT s.game_id FROM game_used_slot s
+INNER JOIN game g ON g.id = s.game_id
+WHERE g.status = ANY($1) AND s.player_id = $2 AND s.client_status <> ALL($3)
+ORDER BY s.created_at
+"#,
+    )
+    .await?;
+  let rows = conn
+    .query(&stmt, &[&active_status, &player_id, &gone_client_status])
+    .await?;
+  rows
+    .iter()
+    .map(|row| row.try_get(0).map_err(Into::into))
+    .collect()
+}
+
 pub fn get_full(conn: &DbConn, id: i32) -> Result<Game> {
   let row: GameRowWithRelated = game::table
     .find(id)
@@ -747,35 +1243,65 @@ pub fn get_full(conn: &DbConn, id: i32) -> Result<Game> {
   Ok(row.into_game(meta, slots)?)
 }
 
+/// `get_full` through the cache, not for reads inside a transaction
+pub fn get_full_cached(conn: &DbConn, id: i32) -> Result<Game> {
+  crate::cache::GAMES.get_or_load(id, || get_full(conn, id))
+}
+
+fn get_meta(conn: &DbConn, id: i32) -> Result<Meta> {
+  let meta: Value = game::table
+    .find(id)
+    .select(game::dsl::meta)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameNotFound)?;
+  Ok(serde_json::from_value(meta)?)
+}
+
+pub fn get_rules(conn: &DbConn, id: i32) -> Result<GameRules> {
+  Ok(get_meta(conn, id)?.rules)
+}
+
+/// Replaces the rules of a game that has not been started yet
+pub fn update_rules(conn: &DbConn, id: i32, rules: GameRules) -> Result<()> {
+  use game::dsl;
+  crate::cache::invalidate_game_after_commit(id);
+  conn.transaction(|| {
+    let (status, meta): (GameStatus, Value) = game::table
+      .find(id)
+      .select((dsl::status, dsl::meta))
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::GameNotFound)?;
+    if status != GameStatus::Preparing {
+      return Err(Error::GameStarted);
+    }
+    let mut meta: Meta = serde_json::from_value(meta)?;
+    meta.rules = rules;
+    diesel::update(game::table.find(id))
+      .set(dsl::meta.eq(serde_json::to_value(&meta)?))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+/// The game comes from the cache, not for reads inside a transaction
 pub fn get_full_and_node_token(
   conn: &DbConn,
   game_id: i32,
   player_id: i32,
 ) -> Result<(Game, Option<PlayerToken>)> {
   use game_used_slot::dsl as gus;
-  let row: GameRowWithRelated = game::table
-    .find(game_id)
-    .left_outer_join(node::table)
-    .left_outer_join(player::table)
-    .select(GameRowWithRelated::columns())
-    .first(conn)
-    .optional()?
-    .ok_or_else(|| Error::GameNotFound)?;
-  let meta: Meta = serde_json::from_value(row.meta.clone())?;
-  let used_slots = get_used_slots(conn, game_id)?;
+  let game = get_full_cached(conn, game_id)?;
   let player_token: Option<Vec<u8>> = game_used_slot::table
     .select(gus::node_token)
     .filter(gus::game_id.eq(game_id).and(gus::player_id.eq(player_id)))
     .first(conn)
     .optional()?
     .ok_or_else(|| Error::PlayerNotInGame)?;
-  let max_players = row.max_players;
 
   Ok((
-    row.into_game(
-      meta,
-      Slots::from_used(max_players as usize, used_slots).into_inner(),
-    )?,
+    game,
     player_token.and_then(|bytes| PlayerToken::from_vec(player_id, bytes)),
   ))
 }
@@ -783,8 +1309,12 @@ pub fn get_full_and_node_token(
 #[derive(Debug)]
 pub struct GameStateFromDb {
   pub id: i32,
+  pub name: String,
+  pub map_name: String,
+  pub is_private: bool,
+  pub max_players: i32,
   pub status: GameStatus,
-  pub players: Vec<(i32, Option<Vec<u8>>)>,
+  pub players: Vec<(i32, Option<Vec<u8>>, SlotClientStatus)>,
   pub node_id: Option<i32>,
   pub created_by: i32,
 }
@@ -794,22 +1324,32 @@ pub struct GameStateFromDb {
 pub fn get_all_active_game_state(conn: &DbConn) -> Result<Vec<GameStateFromDb>> {
   use game::dsl;
 
-  let rows: Vec<(i32, GameStatus, Option<i32>, i32)> = game::table
+  let rows: Vec<(i32, String, String, bool, i32, GameStatus, Option<i32>, i32)> = game::table
     .left_outer_join(node::table)
-    .filter(dsl::status.eq_any(&[
-      GameStatus::Preparing,
-      GameStatus::Created,
-      GameStatus::Running,
-    ]))
+    .filter(dsl::status.eq_any(GameStatus::active_variants()))
     .order(dsl::created_at)
-    .select((dsl::id, dsl::status, dsl::node_id, dsl::created_by))
+    .select((
+      dsl::id,
+      dsl::name,
+      dsl::map_name,
+      dsl::is_private,
+      dsl::max_players,
+      dsl::status,
+      dsl::node_id,
+      dsl::created_by,
+    ))
     .load(conn)?;
 
-  let game_ids: Vec<_> = rows.iter().map(|(id, _, _, _)| *id).collect();
-  let mut game_players_map: HashMap<i32, Vec<(i32, Option<Vec<u8>>)>> = {
+  let game_ids: Vec<_> = rows.iter().map(|row| row.0).collect();
+  let mut game_players_map: HashMap<i32, Vec<(i32, Option<Vec<u8>>, SlotClientStatus)>> = {
     use game_used_slot::dsl;
-    let rows: Vec<(i32, Option<i32>, Option<Vec<u8>>)> = game_used_slot::table
-      .select((dsl::game_id, dsl::player_id, dsl::node_token))
+    let rows: Vec<(i32, Option<i32>, Option<Vec<u8>>, SlotClientStatus)> = game_used_slot::table
+      .select((
+        dsl::game_id,
+        dsl::player_id,
+        dsl::node_token,
+        dsl::client_status,
+      ))
       .filter(
         dsl::game_id
           .eq(any(game_ids))
@@ -820,22 +1360,26 @@ pub fn get_all_active_game_state(conn: &DbConn) -> Result<Vec<GameStateFromDb>>
       )
       .load(conn)?;
     let mut map = HashMap::new();
-    for (game_id, player_id, node_token) in rows {
+    for (game_id, player_id, node_token, client_status) in rows {
       if let Some(player_id) = player_id {
         map
           .entry(game_id)
           .or_insert_with(|| vec![])
-          .push((player_id, node_token))
+          .push((player_id, node_token, client_status))
       }
     }
     map
   };
 
   let mut games = Vec::with_capacity(rows.len());
-  for (id, status, node_id, created_by) in rows {
+  for (id, name, map_name, is_private, max_players, status, node_id, created_by) in rows {
     let players = game_players_map.remove(&id).unwrap_or_default();
     games.push(GameStateFromDb {
       id,
+      name,
+      map_name,
+      is_private,
+      max_players,
       status,
       players,
       node_id,
@@ -868,6 +1412,7 @@ pub fn select_node(conn: &DbConn, id: i32, player_id: i32, node_id: Option<i32>)
     return Err(Error::GameStarted);
   }
 
+  crate::cache::invalidate_game_after_commit(id);
   let n: usize = diesel::update(game::table.find(id))
     .filter(
       dsl::status
@@ -886,6 +1431,7 @@ pub fn select_node(conn: &DbConn, id: i32, player_id: i32, node_id: Option<i32>)
 
 fn end_game(conn: &DbConn, id: i32, status: GameStatus) -> Result<()> {
   use game::dsl;
+  crate::cache::invalidate_game_after_commit(id);
   conn.transaction(|| -> Result<_> {
     diesel::update(game::table.find(id))
       .filter(dsl::status.ne(status))
@@ -908,6 +1454,7 @@ pub fn update_created(
   player_tokens: HashMap<i32, PlayerToken>,
 ) -> Result<()> {
   use game::dsl;
+  crate::cache::invalidate_game_after_commit(id);
   conn.transaction(|| {
     diesel::update(game::table.find(id))
       .filter(dsl::status.eq(GameStatus::Preparing))
@@ -932,6 +1479,7 @@ pub fn update_created(
 pub fn update_reset_created(conn: &DbConn, id: i32) -> Result<()> {
   use game::dsl;
   use game_used_slot::dsl as gus;
+  crate::cache::invalidate_game_after_commit(id);
   conn.transaction(|| {
     diesel::update(game::table.find(id))
       .filter(dsl::status.eq(GameStatus::Created))
@@ -949,6 +1497,7 @@ pub fn update_reset_created(conn: &DbConn, id: i32) -> Result<()> {
 pub fn reset_instance_state(conn: &DbConn) -> Result<()> {
   use game::dsl as g;
   use game_used_slot::dsl as gus;
+  crate::cache::GAMES.clear();
   // invalidate active games' slot client status
   conn.transaction(|| {
     let active_game_id = game::table
@@ -980,6 +1529,16 @@ pub fn get_node_active_game_ids(conn: &DbConn, node_id: i32) -> Result<Vec<i32>>
 pub struct Meta {
   pub map: Map,
   pub created_by: Option<PlayerRef>,
+  #[serde(default)]
+  pub rules: GameRules,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub password_hash: Option<String>,
+  #[serde(default, skip_serializing_if = "GameMetadata::is_empty")]
+  pub metadata: GameMetadata,
+  /// Team of each slot if the registered map file has fixed teams,
+  /// see `Slots::with_fixed_teams`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub force_teams: Option<Vec<i32>>,
 }
 
 #[derive(Debug, Queryable)]
@@ -1002,6 +1561,7 @@ pub struct GameRowWithRelated {
   pub random_seed: i32,
   pub mask_player_names: bool,
   pub game_version: Option<String>,
+  pub slots_version: i32,
 }
 
 pub(crate) type GameRowWithRelatedColumns = (
@@ -1023,6 +1583,7 @@ pub(crate) type GameRowWithRelatedColumns = (
   game::dsl::random_seed,
   game::dsl::mask_player_names,
   game::dsl::game_version,
+  game::dsl::slots_version,
 );
 
 impl GameRowWithRelated {
@@ -1046,6 +1607,7 @@ impl GameRowWithRelated {
       game::dsl::random_seed,
       game::dsl::mask_player_names,
       game::dsl::game_version,
+      game::dsl::slots_version,
     )
   }
 
@@ -1071,6 +1633,8 @@ impl GameRowWithRelated {
       random_seed: self.random_seed,
       mask_player_names: self.mask_player_names,
       game_version: self.game_version,
+      slots_version: self.slots_version,
+      classic_graphics_only: meta.rules.classic_graphics_only,
     })
   }
 }
@@ -1079,6 +1643,7 @@ impl GameRowWithRelated {
 #[table_name = "game"]
 pub struct GameInsert<'a> {
   pub name: &'a str,
+  pub name_folded: &'a str,
   pub map_name: &'a str,
   pub is_private: bool,
   pub is_live: bool,
@@ -1151,3 +1716,20 @@ impl UsedSlotUpdate {
     }
   }
 }
+
+#[test]
+fn test_create_game_slot_validation() {
+  assert_eq!(normalize_handicap(0).unwrap(), 100);
+  assert_eq!(normalize_handicap(50).unwrap(), 50);
+  assert!(normalize_handicap(55).is_err());
+  assert!(normalize_handicap(110).is_err());
+
+  let pair = |slot_id, with_slot_id| SharedControlPair {
+    slot_id,
+    with_slot_id,
+  };
+  let is_player_slot = |slot_id| slot_id < 4;
+  assert!(check_shared_control_pairs(&[pair(0, 1), pair(2, 3)], is_player_slot).is_ok());
+  assert!(check_shared_control_pairs(&[pair(1, 1)], is_player_slot).is_err());
+  assert!(check_shared_control_pairs(&[pair(0, 4)], is_player_slot).is_err());
+}
@@ -0,0 +1,108 @@
+use crate::config::FloGrpcInterceptor;
+use crate::error::Error;
+use crate::game::db::{GameListSort, GameStatusFilter, ListGameParams};
+use crate::game::metadata::GameMetadata;
+use crate::game::GameEntry;
+use crate::state::ControllerStateRef;
+use chrono::{TimeZone, Utc};
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+  tonic::include_proto!("flo_game_list");
+}
+
+use proto::flo_game_list_server::{FloGameList, FloGameListServer};
+use proto::*;
+
+pub fn server(
+  state: ControllerStateRef,
+  interceptor: FloGrpcInterceptor,
+) -> InterceptedService<FloGameListServer<FloGameListService>, FloGrpcInterceptor> {
+  FloGameListServer::with_interceptor(FloGameListService { state }, interceptor)
+}
+
+pub struct FloGameListService {
+  state: ControllerStateRef,
+}
+
+#[tonic::async_trait]
+impl FloGameList for FloGameListService {
+  async fn list_games(
+    &self,
+    request: Request<ListGamesRequest>,
+  ) -> Result<Response<ListGamesReply>, Status> {
+    let req = request.into_inner();
+    let created_after = match req.created_after {
+      Some(secs) => Some(
+        Utc
+          .timestamp_opt(secs, 0)
+          .single()
+          .ok_or_else(|| Status::invalid_argument("invalid created_after"))?,
+      ),
+      None => None,
+    };
+    let params = ListGameParams {
+      status: match req.status() {
+        proto::GameListStatusFilter::All => GameStatusFilter::All,
+        proto::GameListStatusFilter::Open => GameStatusFilter::Open,
+        proto::GameListStatusFilter::Live => GameStatusFilter::Live,
+        proto::GameListStatusFilter::Ended => GameStatusFilter::Ended,
+      },
+      sort: match req.sort() {
+        proto::GameListSort::CreatedDesc => GameListSort::CreatedDesc,
+        proto::GameListSort::CreatedAsc => GameListSort::CreatedAsc,
+        proto::GameListSort::MapName => GameListSort::MapName,
+      },
+      keyword: req.keyword,
+      map_name: req.map_name,
+      region: req.region,
+      created_after,
+      has_open_slots: req.has_open_slots,
+      take: req.take,
+      cursor: req.cursor,
+      metadata: req.metadata.into_iter().collect(),
+    };
+    let (r, mut metadata_map) = self
+      .state
+      .db
+      .read()
+      .exec(move |conn| -> Result<_, Error> {
+        let r = crate::game::db::list(conn, &params)?;
+        let ids: Vec<i32> = r.games.iter().map(|game| game.id).collect();
+        let metadata_map = crate::game::metadata::get_map(conn, &ids)?;
+        Ok((r, metadata_map))
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ListGamesReply {
+      games: r
+        .games
+        .into_iter()
+        .map(|game| {
+          let metadata = metadata_map.remove(&game.id).unwrap_or_default();
+          pack_item(game, metadata)
+        })
+        .collect(),
+      next_cursor: r.next_cursor,
+    }))
+  }
+}
+
+fn pack_item(game: GameEntry, metadata: GameMetadata) -> GameListItem {
+  GameListItem {
+    id: game.id,
+    name: game.name,
+    map_name: game.map_name,
+    status: game.status as i32,
+    is_live: game.is_live,
+    num_players: game.num_players,
+    max_players: game.max_players,
+    created_at: game.created_at.timestamp(),
+    node_id: game.node.as_ref().map(|node| node.id),
+    node_name: game.node.map(|node| node.name),
+    created_by_id: game.created_by.as_ref().map(|player| player.id),
+    created_by_name: game.created_by.map(|player| player.name),
+    metadata: metadata.into_iter().collect(),
+  }
+}
@@ -0,0 +1,96 @@
+//! W3GS identity of the games.
+//!
+//! The random seed and the LAN entry key are generated once per game and saved with it,
+//! so the same game can be announced again to reconnecting clients and matched to its replay.
+//! They are unique among the active games. The LAN host counter is the game id.
+
+use diesel::prelude::*;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::GameStatus;
+
+const MAX_ATTEMPTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameIdentity {
+  pub random_seed: i32,
+  pub host_counter: u32,
+  /// Saved to the `secret` column
+  pub entry_key: u32,
+}
+
+/// Saves the identity if no other active game has the same random seed or entry key,
+/// the check and the write are a single statement
+const ALLOCATE_SQL: &str = r#"
+  update game set random_seed = $2, secret = $3
+  where id = $1 and not exists (
+    select 1 from game other
+    where other.id <> $1
+      and other.status = any($4)
+      and (other.random_seed = $2 or other.secret = $3)
+  )
+"#;
+
+/// Generates the identity of a newly inserted game
+pub fn allocate(conn: &DbConn, game_id: i32) -> Result<GameIdentity> {
+  use diesel::sql_types::{Array, Integer};
+
+  let (random_seed, entry_key) = generate(
+    || (rand::random::<i32>(), rand::random::<i32>()),
+    |&(random_seed, entry_key)| {
+      // 0 is the entry key of games without one
+      if entry_key == 0 {
+        return Ok(false);
+      }
+      let updated = diesel::sql_query(ALLOCATE_SQL)
+        .bind::<Integer, _>(game_id)
+        .bind::<Integer, _>(random_seed)
+        .bind::<Integer, _>(entry_key)
+        .bind::<Array<Integer>, _>(GameStatus::active_variants())
+        .execute(conn)?;
+      Ok(updated == 1)
+    },
+  )?;
+
+  Ok(GameIdentity {
+    random_seed,
+    host_counter: game_id as u32,
+    entry_key: entry_key as u32,
+  })
+}
+
+/// Returns the first generated value accepted by `try_take`
+fn generate<T, G, F>(mut gen: G, mut try_take: F) -> Result<T>
+where
+  G: FnMut() -> T,
+  F: FnMut(&T) -> Result<bool>,
+{
+  for _ in 0..MAX_ATTEMPTS {
+    let value = gen();
+    if try_take(&value)? {
+      return Ok(value);
+    }
+  }
+  Err(Error::GameIdentityUnavailable)
+}
+
+#[test]
+fn test_generate_game_identity() {
+  let mut values = vec![3, 2, 1].into_iter();
+  assert_eq!(
+    generate(|| values.next().unwrap(), |v| Ok(*v <= 1)).unwrap(),
+    1
+  );
+
+  let mut value = 0;
+  let res = generate(
+    || {
+      value += 1;
+      value
+    },
+    |_| Ok(false),
+  );
+  assert!(res.is_err());
+  assert_eq!(value, MAX_ATTEMPTS);
+}
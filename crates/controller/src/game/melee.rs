@@ -0,0 +1,371 @@
+//! Slot layouts of standard ladder games on melee maps.
+
+use crate::error::*;
+use crate::game::{CreateGameSlot, Race, SlotSettings, SlotStatus};
+use crate::map::{Map, MapFileInfo};
+
+const MAX_SLOTS: usize = 24;
+const REFEREE_TEAM: i32 = 24;
+/// w3i player type of the start locations taken by users, others are computer or neutral
+const MAP_PLAYER_TYPE_HUMAN: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct MeleePlayer {
+  pub player_id: i32,
+  /// Random if not set
+  pub race: Option<Race>,
+}
+
+impl From<i32> for MeleePlayer {
+  fn from(player_id: i32) -> Self {
+    Self {
+      player_id,
+      race: None,
+    }
+  }
+}
+
+/// One slot per start location of the map, the remaining start locations are closed.
+/// Observers are appended after the start locations.
+///
+/// If the map has several forces, each team takes the start locations of the force with
+/// the same index, falling back to the slot order unless the map has fixed teams.
+/// Computer and neutral start locations are never taken, and the race of the map player
+/// is kept if the map has fixed player settings.
+///
+/// `file_info` is trusted over the players and forces sent by the game clients.
+pub fn melee_slots(
+  map: &Map,
+  file_info: Option<&MapFileInfo>,
+  teams: &[Vec<MeleePlayer>],
+  observers: &[i32],
+) -> Result<Vec<CreateGameSlot>> {
+  let locations = start_locations(map, file_info);
+  if locations.is_empty() {
+    return Err(Error::MapHasNoPlayer);
+  }
+
+  let num_players: usize = teams.iter().map(|team| team.len()).sum();
+  if num_players == 0 {
+    return Err(Error::GameHasNoPlayer);
+  }
+  let playable = locations
+    .iter()
+    .filter(|location| location.playable)
+    .count();
+  if num_players > playable || locations.len() + observers.len() > MAX_SLOTS {
+    return Err(Error::TooManyPlayers);
+  }
+
+  let fixed_teams = file_info.map(|info| info.fixed_teams).unwrap_or_default();
+  let num_forces = file_info
+    .map(|info| info.forces.len())
+    .unwrap_or_else(|| map.forces.len());
+  let by_force = fixed_teams || num_forces > 1;
+  let placed = match place(&locations, teams, by_force) {
+    Some(placed) => placed,
+    None if fixed_teams => return Err(Error::PlayerTeamInvalid),
+    None => place(&locations, teams, false).ok_or_else(|| Error::TooManyPlayers)?,
+  };
+
+  let mut slots = Vec::with_capacity(locations.len() + observers.len());
+  for (idx, (placed, location)) in placed.into_iter().zip(&locations).enumerate() {
+    slots.push(match placed {
+      Some((team, player)) => CreateGameSlot {
+        player_id: Some(player.player_id),
+        settings: SlotSettings {
+          team: team as i32,
+          color: idx as i32,
+          status: SlotStatus::Occupied,
+          race: location.race.or(player.race).unwrap_or(Race::Random),
+          ..Default::default()
+        },
+      },
+      None => CreateGameSlot {
+        player_id: None,
+        settings: SlotSettings {
+          status: SlotStatus::Closed,
+          ..Default::default()
+        },
+      },
+    });
+  }
+
+  for player_id in observers {
+    slots.push(CreateGameSlot {
+      player_id: Some(*player_id),
+      settings: SlotSettings {
+        team: REFEREE_TEAM,
+        status: SlotStatus::Occupied,
+        race: Race::Random,
+        ..Default::default()
+      },
+    });
+  }
+
+  Ok(slots)
+}
+
+#[derive(Debug)]
+struct StartLocation {
+  /// Index of the map force containing the player, 0 if none does, like `force_teams`
+  force: usize,
+  /// Set if the map has fixed player settings and the race isn't selectable
+  race: Option<Race>,
+  playable: bool,
+}
+
+fn start_locations(map: &Map, file_info: Option<&MapFileInfo>) -> Vec<StartLocation> {
+  match file_info.filter(|info| info.players.len() == map.players.len()) {
+    // the forces of the file info refer to the w3i player ids
+    Some(info) => info
+      .players
+      .iter()
+      .map(|player| StartLocation {
+        force: find_force(info.forces.iter().map(|force| force.player_set), player.id),
+        race: if info.fixed_teams {
+          fixed_race(player.race)
+        } else {
+          None
+        },
+        playable: player.r#type == MAP_PLAYER_TYPE_HUMAN,
+      })
+      .collect(),
+    // the players sent by the game clients are numbered in slot order
+    None => map
+      .players
+      .iter()
+      .enumerate()
+      .map(|(idx, player)| StartLocation {
+        force: find_force(map.forces.iter().map(|force| force.player_set), idx as u32),
+        race: None,
+        playable: player.r#type == MAP_PLAYER_TYPE_HUMAN,
+      })
+      .collect(),
+  }
+}
+
+fn find_force(mut player_sets: impl Iterator<Item = u32>, player_id: u32) -> usize {
+  player_sets
+    .position(|player_set| player_id < 32 && player_set & (1 << player_id) != 0)
+    .unwrap_or(0)
+}
+
+/// w3i race of the map player, 0 is selectable
+fn fixed_race(race: u32) -> Option<Race> {
+  match race {
+    1 => Some(Race::Human),
+    2 => Some(Race::Orc),
+    3 => Some(Race::Undead),
+    4 => Some(Race::NightElf),
+    _ => None,
+  }
+}
+
+/// Team and player of each start location, team by team in slot order
+fn place<'a>(
+  locations: &[StartLocation],
+  teams: &'a [Vec<MeleePlayer>],
+  by_force: bool,
+) -> Option<Vec<Option<(usize, &'a MeleePlayer)>>> {
+  let mut placed = vec![None; locations.len()];
+  for (team, players) in teams.iter().enumerate() {
+    for player in players {
+      let idx = (0..locations.len()).find(|idx| {
+        let location = &locations[*idx];
+        location.playable && placed[*idx].is_none() && (!by_force || location.force == team)
+      })?;
+      placed[idx] = Some((team, player));
+    }
+  }
+  Some(placed)
+}
+
+#[cfg(test)]
+fn test_map(players: Vec<crate::map::MapPlayer>, forces: Vec<crate::map::MapForce>) -> Map {
+  use crate::map::MapSha1;
+
+  Map {
+    sha1: MapSha1([0; 20]),
+    checksum: 0,
+    name: "(4)TurtleRock".to_string(),
+    description: String::new(),
+    author: String::new(),
+    path: "maps\\(4)TurtleRock.w3x".to_string(),
+    width: 0,
+    height: 0,
+    players,
+    forces,
+  }
+}
+
+#[cfg(test)]
+fn test_map_player(idx: usize, r#type: u32) -> crate::map::MapPlayer {
+  crate::map::MapPlayer {
+    name: format!("Player {}", idx + 1),
+    r#type,
+    race: 0,
+    flags: 0,
+  }
+}
+
+#[test]
+fn test_melee_slots() {
+  let map = test_map((0..4).map(|idx| test_map_player(idx, 1)).collect(), vec![]);
+
+  let teams = vec![
+    vec![MeleePlayer::from(1)],
+    vec![MeleePlayer {
+      player_id: 2,
+      race: Some(Race::Orc),
+    }],
+  ];
+  let slots = melee_slots(&map, None, &teams, &[3]).unwrap();
+  assert_eq!(slots.len(), 5);
+  assert_eq!(slots[0].player_id, Some(1));
+  assert_eq!(slots[0].settings.team, 0);
+  assert_eq!(slots[0].settings.race, Race::Random);
+  assert_eq!(slots[1].player_id, Some(2));
+  assert_eq!(slots[1].settings.team, 1);
+  assert_eq!(slots[1].settings.color, 1);
+  assert_eq!(slots[1].settings.race, Race::Orc);
+  assert_eq!(slots[2].settings.status, SlotStatus::Closed);
+  assert_eq!(slots[3].settings.status, SlotStatus::Closed);
+  assert_eq!(slots[4].player_id, Some(3));
+  assert_eq!(slots[4].settings.team, REFEREE_TEAM);
+
+  let ffa: Vec<_> = (1..=5).map(|id| vec![MeleePlayer::from(id)]).collect();
+  assert!(matches!(
+    melee_slots(&map, None, &ffa, &[]),
+    Err(Error::TooManyPlayers)
+  ));
+  assert!(matches!(
+    melee_slots(&map, None, &[], &[3]),
+    Err(Error::GameHasNoPlayer)
+  ));
+}
+
+#[test]
+fn test_melee_slots_map_settings() {
+  use crate::map::{MapFileForce, MapFilePlayer, MapForce};
+
+  // 2v2 map with the allies at start locations 0, 2 and 1, 3, location 4 is a computer
+  let force = |player_set| MapForce {
+    name: String::new(),
+    flags: 0,
+    player_set,
+  };
+  let map = test_map(
+    vec![
+      test_map_player(0, 1),
+      test_map_player(1, 1),
+      test_map_player(2, 1),
+      test_map_player(3, 1),
+      test_map_player(4, 2),
+    ],
+    vec![force(0b00101), force(0b11010)],
+  );
+  let teams = vec![
+    vec![MeleePlayer::from(1), MeleePlayer::from(2)],
+    vec![MeleePlayer::from(3), MeleePlayer::from(4)],
+  ];
+  let slots = melee_slots(&map, None, &teams, &[]).unwrap();
+  let layout: Vec<_> = slots
+    .iter()
+    .map(|slot| (slot.player_id, slot.settings.team))
+    .collect();
+  assert_eq!(
+    layout,
+    vec![
+      (Some(1), 0),
+      (Some(3), 1),
+      (Some(2), 0),
+      (Some(4), 1),
+      (None, 0)
+    ]
+  );
+  assert_eq!(slots[4].settings.status, SlotStatus::Closed);
+
+  // a team doesn't fit in its force, the map doesn't fix the teams
+  let teams = vec![(1..=3).map(MeleePlayer::from).collect::<Vec<_>>()];
+  let slots = melee_slots(&map, None, &teams, &[]).unwrap();
+  assert_eq!(slots[0].player_id, Some(1));
+  assert_eq!(slots[1].player_id, Some(2));
+  assert_eq!(slots[2].player_id, Some(3));
+  assert_eq!(slots[4].player_id, None);
+
+  // the computer start location is never taken
+  let ffa: Vec<_> = (1..=5).map(|id| vec![MeleePlayer::from(id)]).collect();
+  assert!(matches!(
+    melee_slots(&map, None, &ffa, &[]),
+    Err(Error::TooManyPlayers)
+  ));
+
+  // fixed player settings, the w3i ids don't follow the slot order
+  let player = |id, r#type, race| MapFilePlayer {
+    id,
+    r#type,
+    race,
+    flags: 0,
+  };
+  let info = MapFileInfo {
+    players: vec![
+      player(3, 1, 2),
+      player(0, 1, 0),
+      player(1, 1, 0),
+      player(2, 1, 1),
+      player(4, 2, 0),
+    ],
+    forces: vec![
+      MapFileForce {
+        flags: 0,
+        player_set: 1 << 0 | 1 << 1,
+      },
+      MapFileForce {
+        flags: 0,
+        player_set: 1 << 2 | 1 << 3 | 1 << 4,
+      },
+    ],
+    fixed_teams: true,
+    ..Default::default()
+  };
+  let teams = vec![
+    vec![MeleePlayer::from(1), MeleePlayer::from(2)],
+    vec![
+      MeleePlayer::from(3),
+      MeleePlayer {
+        player_id: 4,
+        race: Some(Race::Undead),
+      },
+    ],
+  ];
+  let slots = melee_slots(&map, Some(&info), &teams, &[]).unwrap();
+  let layout: Vec<_> = slots
+    .iter()
+    .map(|slot| (slot.player_id, slot.settings.team, slot.settings.race))
+    .collect();
+  assert_eq!(
+    layout,
+    vec![
+      (Some(3), 1, Race::Orc),
+      (Some(1), 0, Race::Random),
+      (Some(2), 0, Race::Random),
+      (Some(4), 1, Race::Human),
+      (None, 0, Race::Human),
+    ]
+  );
+  assert_eq!(
+    slots
+      .iter()
+      .map(|slot| slot.settings.team)
+      .take(4)
+      .collect::<Vec<_>>(),
+    info.force_teams().unwrap()[..4].to_vec()
+  );
+
+  let teams = vec![(1..=3).map(MeleePlayer::from).collect::<Vec<_>>()];
+  assert!(matches!(
+    melee_slots(&map, Some(&info), &teams, &[]),
+    Err(Error::PlayerTeamInvalid)
+  ));
+}
@@ -0,0 +1,82 @@
+//! Free-form key/value pairs attached to games at creation.
+//!
+//! Lets tournament organizers and bots link games to their own records, e.g. a match id or
+//! a stream URL. Saved in the `meta` column and size-limited.
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{Jsonb, Nullable};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schema::game;
+
+pub type GameMetadata = BTreeMap<String, String>;
+
+const MAX_ENTRIES: usize = 16;
+const MAX_KEY_LEN: usize = 64;
+const MAX_VALUE_LEN: usize = 512;
+
+/// SQL condition of games with all entries of a bound `Jsonb` object
+pub(crate) const CONTAINS_SQL: &str = "(game.meta -> 'metadata') @> ";
+
+pub fn validate(metadata: &GameMetadata) -> Result<()> {
+  let valid = metadata.len() <= MAX_ENTRIES
+    && metadata.iter().all(|(key, value)| {
+      !key.is_empty() && key.len() <= MAX_KEY_LEN && value.len() <= MAX_VALUE_LEN
+    });
+  if valid {
+    Ok(())
+  } else {
+    Err(Error::GameMetadataInvalid)
+  }
+}
+
+pub fn get(conn: &DbConn, game_id: i32) -> Result<GameMetadata> {
+  Ok(
+    get_map(conn, &[game_id])?
+      .remove(&game_id)
+      .unwrap_or_default(),
+  )
+}
+
+/// Games without metadata are not included
+pub fn get_map(conn: &DbConn, game_ids: &[i32]) -> Result<BTreeMap<i32, GameMetadata>> {
+  let rows: Vec<(i32, Option<Value>)> = game::table
+    .filter(game::id.eq_any(game_ids))
+    .select((game::id, sql::<Nullable<Jsonb>>("game.meta -> 'metadata'")))
+    .load(conn)?;
+  let mut map = BTreeMap::new();
+  for (id, value) in rows {
+    if let Some(value) = value {
+      map.insert(id, serde_json::from_value(value)?);
+    }
+  }
+  Ok(map)
+}
+
+#[test]
+fn test_validate_game_metadata() {
+  let mut metadata = GameMetadata::new();
+  metadata.insert("challonge_match_id".to_string(), "123456".to_string());
+  metadata.insert(
+    "stream_url".to_string(),
+    "https://www.twitch.tv/w3champions".to_string(),
+  );
+  assert!(validate(&metadata).is_ok());
+
+  let mut empty_key = metadata.clone();
+  empty_key.insert(String::new(), "value".to_string());
+  assert!(validate(&empty_key).is_err());
+
+  let mut long_value = metadata.clone();
+  long_value.insert("notes".to_string(), "x".repeat(MAX_VALUE_LEN + 1));
+  assert!(validate(&long_value).is_err());
+
+  let too_many: GameMetadata = (0..(MAX_ENTRIES + 1))
+    .map(|i| (i.to_string(), String::new()))
+    .collect();
+  assert!(validate(&too_many).is_err());
+}
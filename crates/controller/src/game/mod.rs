@@ -1,4 +1,10 @@
 pub mod db;
+pub(crate) mod grpc;
+pub mod identity;
+pub mod melee;
+pub mod metadata;
+pub mod name;
+pub mod request;
 mod slots;
 pub(crate) mod state;
 pub mod token;
@@ -6,7 +12,8 @@ mod types;
 
 pub mod messages {
   pub use super::state::cancel::CancelGame;
-  pub use super::state::create::CreateGame;
+  pub use super::state::create::{CreateGame, NameFiltered};
+  pub use super::state::host::TransferHost;
   pub use super::state::join::PlayerJoin;
   pub use super::state::leave::PlayerLeave;
   pub use super::state::node::SelectNode;
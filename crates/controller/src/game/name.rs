@@ -0,0 +1,162 @@
+//! Game names.
+//!
+//! Names are shown in the clients and in the game lists. The LAN game info only carries
+//! them for the worker clients, other clients name the LAN game with `get_lan_game_name`
+//! of the client. The game cuts names longer than `MAX_NAME_LEN` bytes, so they are rejected.
+//! Active games have unique names, compared in their folded form.
+//!
+//! Names are checked when a game is created, and generated from `game_name_template` of
+//! the service config if empty. For example with `{map} #{seq}` the games on Echo Isles
+//! are named `(2)EchoIsles #1`, `(2)EchoIsles #2`, ...
+
+use diesel::prelude::*;
+use flo_util::name::{fold, normalize, truncate};
+use std::collections::BTreeSet;
+
+use crate::config::service_config;
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::GameStatus;
+use crate::schema::game;
+use crate::text_filter::WordList;
+
+pub use flo_util::name::MAX_GAME_NAME_LEN as MAX_NAME_LEN;
+
+/// Sequence numbers tried before giving up
+const MAX_SEQ: usize = 100;
+
+/// Values of the template placeholders
+#[derive(Debug, Clone, Copy)]
+pub struct NameVars<'a> {
+  /// `{map}`
+  pub map: &'a str,
+  /// `{host}`
+  pub host: &'a str,
+}
+
+/// The normalized name of a new game, generated if `name` is empty.
+/// Generated names don't fold to the name of another active game, see `flo_util::name::fold`.
+/// Both kinds are checked again by the unique index when the game is inserted.
+/// The names set by players should have passed `crate::text_filter` before.
+pub fn resolve(conn: &DbConn, name: &str, vars: NameVars) -> Result<String> {
+  let word_list = crate::text_filter::word_list();
+  let name = normalize(name);
+  if !name.is_empty() {
+    validate(&name, &word_list)?;
+    return Ok(name);
+  }
+
+  let template = service_config().game_name_template.clone();
+  let candidates: Vec<String> = (1..=MAX_SEQ)
+    .map(|seq| render(&template, vars, seq, &word_list))
+    .collect();
+  validate(&candidates[0], &word_list)?;
+
+  let folded: Vec<String> = candidates.iter().map(|name| fold(name)).collect();
+  let taken: BTreeSet<String> = game::table
+    .filter(game::status.eq_any(GameStatus::active_variants()))
+    .filter(game::name_folded.eq_any(&folded))
+    .select(game::name_folded)
+    .distinct()
+    .load::<Option<String>>(conn)?
+    .into_iter()
+    .flatten()
+    .collect();
+  candidates
+    .into_iter()
+    .find(|name| !taken.contains(&fold(name)))
+    .ok_or_else(|| Error::GameNameUnavailable)
+}
+
+/// Length and charset limits of the game, and the word list of the text filter
+pub fn validate(name: &str, word_list: &WordList) -> Result<()> {
+  if name.trim().is_empty() || name.len() > MAX_NAME_LEN || name.chars().any(char::is_control) {
+    return Err(Error::GameNameInvalid);
+  }
+  if word_list.is_blocked(name) {
+    return Err(Error::GameNameBlocked);
+  }
+  Ok(())
+}
+
+/// Blocked words of the placeholder values are masked, `{map}` is shortened to fit the
+/// name in `MAX_NAME_LEN`. Without `{seq}` in the template, the sequence number is
+/// appended from the second game on.
+fn render(template: &str, vars: NameVars, seq: usize, word_list: &WordList) -> String {
+  let template = if template.contains("{seq}") || seq == 1 {
+    template.to_string()
+  } else {
+    format!("{} #{{seq}}", template)
+  };
+  let without_map = template
+    .replace("{host}", &word_list.mask(&normalize(vars.host)))
+    .replace("{seq}", &seq.to_string());
+  let placeholders = without_map.matches("{map}").count();
+  let fixed_len = without_map.len() - placeholders * "{map}".len();
+  let map_len = MAX_NAME_LEN.saturating_sub(fixed_len) / placeholders.max(1);
+  let map = word_list.mask(&normalize(vars.map));
+  let name = without_map.replace("{map}", truncate(&map, map_len).trim_end());
+  truncate(name.trim(), MAX_NAME_LEN).to_string()
+}
+
+#[test]
+fn test_render_game_name() {
+  use flo_config::TextFilterConfig;
+
+  let word_list = WordList::new(&TextFilterConfig::default());
+  let vars = NameVars {
+    map: "(2)EchoIsles",
+    host: "Shit Happens",
+  };
+  assert_eq!(
+    render("{map} #{seq}", vars, 1, &word_list),
+    "(2)EchoIsles #1"
+  );
+  assert_eq!(render("{map}", vars, 1, &word_list), "(2)EchoIsles");
+  assert_eq!(render("{map}", vars, 2, &word_list), "(2)EchoIsles #2");
+  assert_eq!(
+    render("{host}'s game", vars, 1, &word_list),
+    "**** Happens's game"
+  );
+  let custom = WordList::new(&TextFilterConfig {
+    blocked_words: vec!["happens".to_string()],
+    ..Default::default()
+  });
+  assert_eq!(
+    render("{host} {map}", vars, 1, &custom),
+    "**** ******* (2)EchoIsles"
+  );
+
+  let long = NameVars {
+    map: "(12)Very Long Map Name With Many Words",
+    host: "",
+  };
+  let name = render("{map} #{seq}", long, 42, &word_list);
+  assert_eq!(name, "(12)Very Long Map Name With #42");
+  assert_eq!(name.len(), MAX_NAME_LEN);
+}
+
+#[test]
+fn test_validate_game_name() {
+  use flo_config::TextFilterConfig;
+
+  let word_list = WordList::new(&TextFilterConfig::default());
+  assert!(validate("(2)EchoIsles #1", &word_list).is_ok());
+  assert!(matches!(
+    validate("", &word_list),
+    Err(Error::GameNameInvalid)
+  ));
+  assert!(matches!(
+    validate("a\0b", &word_list),
+    Err(Error::GameNameInvalid)
+  ));
+  assert!(matches!(
+    validate(&"x".repeat(MAX_NAME_LEN + 1), &word_list),
+    Err(Error::GameNameInvalid)
+  ));
+  assert!(matches!(
+    validate("SHIT game", &word_list),
+    Err(Error::GameNameBlocked)
+  ));
+  assert!(validate("shiitake", &word_list).is_ok());
+}
@@ -0,0 +1,101 @@
+//! Client-supplied request ids of create game requests.
+//!
+//! A retry with the same id, e.g. after a timeout, returns the game created by the first
+//! request instead of creating another one. Ids are scoped to the api client and
+//! remembered for `RETENTION_HOURS`. The id is recorded in the transaction creating the
+//! game, a retry arriving while the first request is still running waits for it there.
+
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game::Game;
+use crate::schema::game_create_request;
+
+const MAX_LEN: usize = 64;
+const RETENTION_HOURS: i64 = 24;
+
+#[derive(Debug, Clone)]
+pub struct CreateRequestId {
+  pub api_client_id: i32,
+  pub request_id: String,
+}
+
+impl CreateRequestId {
+  pub fn new(api_client_id: i32, request_id: String) -> Result<Self> {
+    validate(&request_id)?;
+    Ok(Self {
+      api_client_id,
+      request_id,
+    })
+  }
+}
+
+pub fn validate(request_id: &str) -> Result<()> {
+  let valid = !request_id.is_empty()
+    && request_id.len() <= MAX_LEN
+    && request_id.bytes().all(|b| b.is_ascii_graphic());
+  if valid {
+    Ok(())
+  } else {
+    Err(Error::GameRequestIdInvalid)
+  }
+}
+
+/// The game created by an earlier request with the same id
+pub fn find_game(conn: &DbConn, id: &CreateRequestId) -> Result<Option<Game>> {
+  use game_create_request::dsl;
+  let game_id: Option<i32> = game_create_request::table
+    .find((id.api_client_id, &id.request_id))
+    .filter(dsl::created_at.gt(Utc::now() - Duration::hours(RETENTION_HOURS)))
+    .select(dsl::game_id)
+    .first(conn)
+    .optional()?;
+  game_id
+    .map(|game_id| crate::game::db::get_full(conn, game_id))
+    .transpose()
+}
+
+/// Called in the transaction creating the game, replaces an expired entry.
+/// Fails with `Error::GameRequestDuplicated` if an earlier request created a game with the
+/// id, the transaction must be rolled back then.
+pub fn record(conn: &DbConn, id: &CreateRequestId, game_id: i32) -> Result<()> {
+  use game_create_request::dsl;
+  // blocks until a running transaction with the same id completes
+  let inserted = diesel::insert_into(game_create_request::table)
+    .values((
+      dsl::api_client_id.eq(id.api_client_id),
+      dsl::request_id.eq(&id.request_id),
+      dsl::game_id.eq(game_id),
+    ))
+    .on_conflict_do_nothing()
+    .execute(conn)?;
+  if inserted > 0 {
+    return Ok(());
+  }
+
+  let row = || game_create_request::table.find((id.api_client_id, &id.request_id));
+  let expired = dsl::created_at.le(Utc::now() - Duration::hours(RETENTION_HOURS));
+  let replaced = diesel::update(row().filter(expired))
+    .set((
+      dsl::game_id.eq(game_id),
+      dsl::created_at.eq(diesel::dsl::now),
+    ))
+    .execute(conn)?;
+  if replaced > 0 {
+    return Ok(());
+  }
+
+  let existing: i32 = row().select(dsl::game_id).first(conn)?;
+  Err(Error::GameRequestDuplicated(existing))
+}
+
+#[test]
+fn test_validate_request_id() {
+  assert!(validate("3f2a9c1e-retry").is_ok());
+  assert!(validate("").is_err());
+  assert!(validate("has space").is_err());
+  assert!(validate(&"a".repeat(MAX_LEN)).is_ok());
+  assert!(validate(&"a".repeat(MAX_LEN + 1)).is_err());
+}
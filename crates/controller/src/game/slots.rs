@@ -1,17 +1,24 @@
 use diesel::helper_types::Nullable;
 use diesel::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::game::{
-  Computer, Slot, SlotClientStatus, SlotSettings, SlotSettingsColumns, SlotStatus,
+  Computer, Race, Slot, SlotClientStatus, SlotSettings, SlotSettingsColumns, SlotStatus,
 };
 use crate::player::{PlayerRef, PlayerRefColumns};
 use crate::schema::game_used_slot;
 
+/// Rating used for players without a rating when balancing teams
+const DEFAULT_RATING: i32 = 1500;
+/// Teams are split exhaustively up to this number of players
+const EXACT_SPLIT_MAX_PLAYERS: usize = 16;
+
 #[derive(Debug)]
 pub struct Slots {
   inner: Vec<Slot>,
   map_players: usize,
+  // team of each map player slot, if the map doesn't let players leave their force
+  fixed_teams: Option<Vec<i32>>,
 }
 
 impl Slots {
@@ -22,7 +29,11 @@ impl Slots {
       .map(|(idx, _)| Self::make_unused_slot(map_players, idx))
       .collect();
 
-    Self { inner, map_players }
+    Self {
+      inner,
+      map_players,
+      fixed_teams: None,
+    }
   }
 
   pub fn from_used(map_players: usize, slots: Vec<UsedSlot>) -> Self {
@@ -45,7 +56,28 @@ impl Slots {
         }
       })
       .collect();
-    Slots { map_players, inner }
+    Slots {
+      map_players,
+      inner,
+      fixed_teams: None,
+    }
+  }
+
+  /// Keeps the player slots in the teams of `teams`, see `MapFileInfo::force_teams`
+  pub fn with_fixed_teams(mut self, teams: Option<Vec<i32>>) -> Self {
+    self.fixed_teams = teams;
+    self.apply_fixed_teams();
+    self
+  }
+
+  fn apply_fixed_teams(&mut self) {
+    if let Some(ref teams) = self.fixed_teams {
+      for (slot, team) in self.inner.iter_mut().zip(teams) {
+        if slot.settings.team != 24 {
+          slot.settings.team = *team;
+        }
+      }
+    }
   }
 
   pub fn as_used(&self) -> Vec<UsedSlot> {
@@ -106,6 +138,14 @@ impl Slots {
       .any(|s| s.settings.status == SlotStatus::Open)
   }
 
+  pub fn open_slots(&self) -> usize {
+    self
+      .inner
+      .iter()
+      .filter(|s| s.settings.status == SlotStatus::Open)
+      .count()
+  }
+
   pub fn is_empty(&self) -> bool {
     !self.inner.iter().any(|s| s.player.is_some())
   }
@@ -169,7 +209,8 @@ impl Slots {
       };
       slot.settings.status = SlotStatus::Occupied;
       slot.settings.computer = Computer::Easy;
-      Some(slot)
+      self.apply_fixed_teams();
+      Some(&mut self.inner[idx])
     } else {
       None
     }
@@ -186,6 +227,7 @@ impl Slots {
     match slot {
       Some(slot) => {
         *slot = Default::default();
+        self.apply_fixed_teams();
         true
       }
       None => false,
@@ -201,6 +243,7 @@ impl Slots {
         *slot = Default::default();
       }
     }
+    self.apply_fixed_teams();
     player_ids
   }
 
@@ -234,13 +277,16 @@ impl Slots {
           // reset color
           let next_color = color_set.iter().position(|v| !*v).map(|v| v as i32);
 
-          // find an open player slot
-          if let Some((index, _player_slot)) = self
-            .inner
-            .iter_mut()
-            .enumerate()
-            .find(|(_index, s)| s.settings.team != 24 && s.settings.status == SlotStatus::Open)
-          {
+          // find an open player slot, in the team if teams are fixed
+          let fixed_teams = self.fixed_teams.as_ref();
+          if let Some((index, _player_slot)) = self.inner.iter().enumerate().find(|(index, s)| {
+            s.settings.team != 24
+              && s.settings.status == SlotStatus::Open
+              && fixed_teams
+                .and_then(|teams| teams.get(*index))
+                .map(|team| *team == new_team)
+                .unwrap_or(true)
+          }) {
             target_index = index as i32;
             self.inner[index].player = self.inner[slot_index as usize].player.clone();
             self.inner[index].settings = SlotSettings {
@@ -281,6 +327,9 @@ impl Slots {
             return None;
           }
         } else {
+          if self.fixed_teams.is_some() {
+            return None;
+          }
           self.inner[slot_index as usize].settings.team = new_team;
         }
       }
@@ -331,6 +380,7 @@ impl Slots {
 
       slot.settings.race = settings.race;
     }
+    self.apply_fixed_teams();
 
     updated_slots.push((slot_index, &self.inner[slot_index as usize]));
     if target_index != slot_index {
@@ -340,6 +390,85 @@ impl Slots {
     Some(updated_slots)
   }
 
+  /// Apply a saved slot layout to the slots that are not taken by players
+  pub fn apply_layout(&mut self, layout: &[SlotSettings]) {
+    for (slot, settings) in self.inner.iter_mut().zip(layout) {
+      if slot.player.is_none() {
+        slot.settings = settings.clone();
+      }
+    }
+    self.apply_fixed_teams();
+  }
+
+  /// Apply the preferred race and color to the slot of a player,
+  /// the color is kept if the preferred one is taken
+  pub fn apply_player_preferences(
+    &mut self,
+    player_id: i32,
+    race: Option<Race>,
+    color: Option<i32>,
+  ) {
+    let color_set = self.get_color_set();
+    let slot = self
+      .inner
+      .iter_mut()
+      .find(|s| s.player.as_ref().map(|p| p.id) == Some(player_id));
+    let slot = match slot {
+      Some(slot) if slot.settings.team != 24 => slot,
+      _ => return,
+    };
+
+    if let Some(race) = race {
+      slot.settings.race = race;
+    }
+
+    if let Some(color) = color {
+      if color >= 0 && color < 24 && !color_set[color as usize] {
+        slot.settings.color = color;
+      }
+    }
+  }
+
+  /// Reassign teams of player slots to minimize the rating difference between teams,
+  /// team sizes are kept, return indexes of updated slots
+  pub fn balance_teams(&mut self, ratings: &BTreeMap<i32, i32>) -> Vec<i32> {
+    if self.fixed_teams.is_some() {
+      return vec![];
+    }
+    let mut slot_indexes = vec![];
+    let mut teams = vec![];
+    let mut player_ratings = vec![];
+    for (index, slot) in self.inner.iter().enumerate() {
+      if slot.settings.team == 24 {
+        continue;
+      }
+      if let Some(player) = slot.player.as_ref() {
+        slot_indexes.push(index);
+        teams.push(slot.settings.team);
+        player_ratings.push(ratings.get(&player.id).cloned().unwrap_or(DEFAULT_RATING));
+      }
+    }
+
+    let mut team_ids = teams.clone();
+    team_ids.sort();
+    team_ids.dedup();
+    let team_sizes: Vec<usize> = team_ids
+      .iter()
+      .map(|id| teams.iter().filter(|team| *team == id).count())
+      .collect();
+
+    let mut updated_indexes = vec![];
+    let assignment = split_teams(&player_ratings, &team_sizes);
+    for ((index, current), team) in slot_indexes.into_iter().zip(teams).zip(assignment) {
+      let team = team_ids[team];
+      if team != current {
+        self.inner[index].settings.team = team;
+        updated_indexes.push(index as i32);
+      }
+    }
+    updated_indexes
+  }
+
   fn get_color_set(&self) -> [bool; 24] {
     let mut set = [false; 24];
     for slot in &self.inner {
@@ -353,6 +482,53 @@ impl Slots {
   }
 }
 
+/// Split players into teams of `team_sizes` with the closest rating sums,
+/// return the team index of each player
+fn split_teams(ratings: &[i32], team_sizes: &[usize]) -> Vec<usize> {
+  let n = ratings.len();
+
+  if team_sizes.len() == 2 && n <= EXACT_SPLIT_MAX_PLAYERS {
+    let total: i64 = ratings.iter().map(|r| *r as i64).sum();
+    let mut best: Option<(i64, u32)> = None;
+    for mask in 0u32..(1 << n) {
+      if mask.count_ones() as usize != team_sizes[0] {
+        continue;
+      }
+      let sum: i64 = (0..n)
+        .filter(|i| mask & (1 << i) != 0)
+        .map(|i| ratings[i] as i64)
+        .sum();
+      let diff = (total - 2 * sum).abs();
+      if best.map(|(best_diff, _)| diff < best_diff).unwrap_or(true) {
+        best = Some((diff, mask));
+      }
+    }
+    if let Some((_, mask)) = best {
+      return (0..n)
+        .map(|i| if mask & (1 << i) != 0 { 0 } else { 1 })
+        .collect();
+    }
+  }
+
+  // strongest players first, each joins the weakest team that has room
+  let mut order: Vec<usize> = (0..n).collect();
+  order.sort_by_key(|i| std::cmp::Reverse(ratings[*i]));
+  let mut sums = vec![0_i64; team_sizes.len()];
+  let mut counts = vec![0_usize; team_sizes.len()];
+  let mut assignment = vec![0; n];
+  for i in order {
+    let team = (0..team_sizes.len())
+      .filter(|team| counts[*team] < team_sizes[*team])
+      .min_by_key(|team| sums[*team]);
+    if let Some(team) = team {
+      sums[team] += ratings[i] as i64;
+      counts[team] += 1;
+      assignment[i] = team;
+    }
+  }
+  assignment
+}
+
 #[derive(Debug, Queryable)]
 pub struct UsedSlot {
   pub slot_index: i32,
@@ -412,3 +588,39 @@ impl UsedSlotInfo {
     )
   }
 }
+
+#[test]
+fn test_split_teams() {
+  assert_eq!(
+    split_teams(&[2000, 1500, 1000, 1500], &[2, 2]),
+    vec![0, 1, 0, 1]
+  );
+
+  let ratings = [1800, 1700, 1600, 1500, 1400, 1300];
+  let assignment = split_teams(&ratings, &[2, 2, 2]);
+  let mut sums = [0; 3];
+  for (rating, team) in ratings.iter().zip(assignment) {
+    sums[team] += rating;
+  }
+  assert_eq!(sums, [3100, 3100, 3100]);
+}
+
+#[test]
+fn test_fixed_teams() {
+  let mut slots = Slots::new(4).with_fixed_teams(Some(vec![0, 0, 1, 1]));
+  let teams: Vec<_> = slots.iter().take(4).map(|s| s.settings.team).collect();
+  assert_eq!(teams, vec![0, 0, 1, 1]);
+
+  let mut settings = slots[2].settings.clone();
+  settings.team = 0;
+  assert!(slots.update_slot_at(2, &settings).is_none());
+  assert_eq!(slots[2].settings.team, 1);
+
+  // other settings can still be changed
+  settings.team = 1;
+  settings.race = Race::Orc;
+  assert!(slots.update_slot_at(2, &settings).is_some());
+  assert_eq!(slots[2].settings.race, Race::Orc);
+
+  assert!(slots.balance_teams(&BTreeMap::new()).is_empty());
+}
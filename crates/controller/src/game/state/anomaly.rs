@@ -0,0 +1,76 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
+use crate::error::Result;
+use crate::game::state::GameActor;
+use flo_net::proto::flo_node::{GameAnomalyKind, PacketNodeGameAnomaly};
+use flo_state::{async_trait, Context, Handler, Message};
+use std::fmt::Write;
+
+/// Impossible player behavior reported by the node
+#[derive(Debug)]
+pub struct GameAnomaly {
+  pub game_id: i32,
+  pub player_id: i32,
+  pub kind: GameAnomalyKind,
+  pub tick: u32,
+  pub detail: String,
+  pub evidence: Vec<Vec<u8>>,
+}
+
+impl From<PacketNodeGameAnomaly> for GameAnomaly {
+  fn from(packet: PacketNodeGameAnomaly) -> Self {
+    Self {
+      game_id: packet.game_id,
+      player_id: packet.player_id,
+      kind: packet.kind(),
+      tick: packet.tick,
+      detail: packet.detail,
+      evidence: packet.evidence,
+    }
+  }
+}
+
+impl Message for GameAnomaly {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<GameAnomaly> for GameActor {
+  async fn handle(&mut self, _: &mut Context<Self>, message: GameAnomaly) -> Result<()> {
+    if !self.players.contains(&message.player_id) {
+      return Ok(());
+    }
+
+    tracing::warn!(
+      game_id = self.game_id,
+      player_id = message.player_id,
+      "anomaly reported: {:?}: {}",
+      message.kind,
+      message.detail
+    );
+    let evidence: Vec<String> = message.evidence.iter().map(|v| to_hex(v)).collect();
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameAnomaly,
+        AuditActor::System,
+        self.game_id,
+      )
+      .with_payload(serde_json::json!({
+        "player_id": message.player_id,
+        "kind": format!("{:?}", message.kind),
+        "tick": message.tick,
+        "detail": message.detail,
+        "evidence": evidence,
+      })),
+    );
+    Ok(())
+  }
+}
+
+fn to_hex(data: &[u8]) -> String {
+  let mut s = String::with_capacity(data.len() * 2);
+  for b in data {
+    write!(s, "{:02x}", b).ok();
+  }
+  s
+}
@@ -1,11 +1,14 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
 use crate::error::*;
 use crate::game::state::GameActor;
-
+use crate::node::{messages as node_messages, PlayerLeaveResponse};
 use crate::player::state::sender::PlayerFrames;
+use crate::state::ActorMapExt;
 
 use flo_net::packet::FloPacket;
 
 use flo_state::{async_trait, Context, Handler, Message};
+use flo_types::lifecycle::GamePhase;
 
 pub struct CancelGame {
   pub player_id: Option<i32>,
@@ -30,31 +33,138 @@ impl Handler<CancelGame> for GameActor {
       .await
       .map_err(Error::from)?;
 
-    self
-      .player_reg
-      .players_leave_game(self.players.clone(), game_id)
-      .await?;
-
-    let packet_iter = self
-      .players
-      .iter()
-      .cloned()
-      .map(|player_id| {
-        use flo_net::proto::flo_connect::*;
-        let frame_left = PacketGamePlayerLeave {
-          game_id,
-          player_id,
-          reason: PlayerLeaveReason::GameCancelled.into(),
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameCancel,
+        player_id
+          .map(AuditActor::Player)
+          .unwrap_or(AuditActor::System),
+        game_id,
+      ),
+    );
+    if self.lifecycle.phase().is_terminal() {
+      self
+        .player_reg
+        .players_leave_game(self.players.clone(), game_id)
+        .await?;
+    } else {
+      self.transition(GamePhase::Aborted).await?;
+    }
+
+    notify_cancelled(self).await
+  }
+}
+
+/// Cancels the game regardless of its status.
+/// If the game has been created on a node, all players are removed from the node first.
+pub struct ForceCancelGame;
+
+impl Message for ForceCancelGame {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<ForceCancelGame> for GameActor {
+  async fn handle(&mut self, _: &mut Context<Self>, _: ForceCancelGame) -> Result<()> {
+    let game_id = self.game_id;
+
+    match self.lifecycle.phase() {
+      GamePhase::Created | GamePhase::Lobby => {
+        self
+          .db
+          .exec(move |conn| crate::game::db::cancel(conn, game_id, None))
+          .await
+          .map_err(Error::from)?;
+        self.transition(GamePhase::Aborted).await?;
+      }
+      GamePhase::Loading | GamePhase::Running => {
+        if let Some(node_id) = self.selected_node_id.clone() {
+          for player_id in self.players.clone() {
+            force_leave_node(self, game_id, player_id, node_id).await;
+          }
         }
-        .encode_as_frame()?;
+        self
+          .db
+          .exec(move |conn| crate::game::db::terminate_game(conn, game_id))
+          .await
+          .map_err(Error::from)?;
+        self.transition(GamePhase::Aborted).await?;
+      }
+      // released by the transition, unless it failed before
+      GamePhase::Ended | GamePhase::Aborted => {
+        self
+          .player_reg
+          .players_leave_game(self.players.clone(), game_id)
+          .await?;
+      }
+    }
+
+    notify_cancelled(self).await
+  }
+}
 
-        Ok((player_id, PlayerFrames::from(frame_left)))
-      })
-      .collect::<Result<Vec<_>>>()?
-      .into_iter();
+async fn force_leave_node(state: &mut GameActor, game_id: i32, player_id: i32, node_id: i32) {
+  let res = state
+    .nodes
+    .send_to(
+      node_id,
+      node_messages::NodePlayerLeave { game_id, player_id },
+    )
+    .await;
 
-    self.player_reg.broadcast_map(packet_iter).await?;
+  let res = match res {
+    Ok(deferred) => deferred.await.or_cancelled(),
+    Err(err) => Err(err),
+  };
 
-    Ok(())
+  match res {
+    Ok(PlayerLeaveResponse::Accepted(_)) => {}
+    Ok(PlayerLeaveResponse::Rejected(reason)) => {
+      tracing::error!(
+        game_id,
+        node_id,
+        player_id,
+        "force cancel: leave node rejected: {:?}",
+        reason
+      );
+    }
+    Err(err) => {
+      tracing::error!(
+        game_id,
+        node_id,
+        player_id,
+        "force cancel: leave node error: {}",
+        err
+      );
+    }
   }
 }
+
+async fn notify_cancelled(state: &mut GameActor) -> Result<()> {
+  let game_id = state.game_id;
+
+  let packet_iter = state
+    .players
+    .iter()
+    .cloned()
+    .map(|player_id| {
+      use flo_net::proto::flo_connect::*;
+      let frame_left = PacketGamePlayerLeave {
+        game_id,
+        player_id,
+        reason: PlayerLeaveReason::GameCancelled.into(),
+        ..Default::default()
+      }
+      .encode_as_frame()?;
+
+      Ok((player_id, PlayerFrames::from(frame_left)))
+    })
+    .collect::<Result<Vec<_>>>()?
+    .into_iter();
+
+  state.player_reg.broadcast_map(packet_iter).await?;
+  state.clear_waitlist().await?;
+
+  Ok(())
+}
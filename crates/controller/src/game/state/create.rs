@@ -1,30 +1,74 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
+use crate::db::ExecutorRef;
 use crate::error::{Error, Result};
-use crate::game::db::{CreateGameAsBotParams, CreateGameParams};
+use crate::game::db::{CreateGameAsBotParams, CreateGameOptions, CreateGameParams};
+use crate::game::metadata::GameMetadata;
+use crate::game::request::CreateRequestId;
 use crate::game::state::registry::Register;
 use crate::game::state::GameRegistry;
-use crate::game::{Game, GameStatus};
+use crate::game::{Game, GameRules, GameStatus};
+use crate::player::state::game_list::{entry_from_game, GameListChange};
+use crate::text_filter::{self, TextKind};
+use crate::webhook::WebhookEventKind;
 use flo_state::{async_trait, Context, Handler, Message};
 
 pub struct CreateGame {
   pub params: CreateGameParams,
+  pub options: CreateGameOptions,
 }
 
-impl Message for CreateGame {
+/// A create game message whose name passed the text filter, the only one the registry
+/// accepts. The filter runs before the message is sent, the registry shouldn't wait for
+/// the filter service.
+pub struct NameFiltered<M>(M);
+
+impl Message for NameFiltered<CreateGame> {
   type Result = Result<Game>;
 }
 
+impl CreateGame {
+  pub async fn filter_name(mut self) -> Result<NameFiltered<Self>> {
+    self.params.name = text_filter::apply(
+      TextKind::GameName,
+      self.params.name,
+      Some(self.params.player_id),
+      Error::GameNameBlocked,
+    )
+    .await?;
+    Ok(NameFiltered(self))
+  }
+}
+
 #[async_trait]
-impl Handler<CreateGame> for GameRegistry {
+impl Handler<NameFiltered<CreateGame>> for GameRegistry {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    CreateGame { params }: CreateGame,
-  ) -> <CreateGame as Message>::Result {
+    NameFiltered(CreateGame { params, options }): NameFiltered<CreateGame>,
+  ) -> <NameFiltered<CreateGame> as Message>::Result {
+    self.maintenance.check_game_creation()?;
+
+    if let Some(request_id) = options.request_id.clone() {
+      let game = self
+        .db
+        .exec(move |conn| crate::game::request::find_game(conn, &request_id))
+        .await?;
+      if let Some(game) = game {
+        tracing::info!(game_id = game.id, "create game: duplicated request");
+        return Ok(game);
+      }
+    }
+
     let player_id = params.player_id;
-    let game = self
+    let has_password = options.password.is_some();
+    let game = match self
       .db
-      .exec(move |conn| crate::game::db::create(conn, params))
-      .await?;
+      .exec(move |conn| crate::game::db::create(conn, params, options))
+      .await
+    {
+      Ok(game) => game,
+      Err(err) => return self.existing_game(err).await,
+    };
 
     self.register(Register {
       id: game.id,
@@ -39,49 +83,130 @@ impl Handler<CreateGame> for GameRegistry {
       .player_replace_game(player_id, game.clone(), vec![])
       .await?;
 
+    if !game.is_private {
+      self
+        .players
+        .update_game_list(GameListChange::Upsert(entry_from_game(&game)))
+        .await?;
+    }
+
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameCreate,
+        AuditActor::Player(player_id),
+        game.id,
+      )
+      .with_payload(serde_json::json!({
+        "name": game.name,
+        "map": game.map.name,
+        "is_private": game.is_private,
+        "has_password": has_password,
+      })),
+    );
+    emit_created(&self.db, &game);
+    crate::discord::announce_game_created(&game);
+
     Ok(game)
   }
 }
 
+impl GameRegistry {
+  // a concurrent request with the same request id created the game first
+  async fn existing_game(&self, err: Error) -> Result<Game> {
+    if let Error::GameRequestDuplicated(game_id) = *err.root() {
+      tracing::info!(game_id, "create game: duplicated request");
+      self
+        .db
+        .exec(move |conn| crate::game::db::get_full(conn, game_id))
+        .await
+    } else {
+      Err(err)
+    }
+  }
+}
+
 pub struct CreateGameAsBot {
   pub api_client_id: i32,
   pub api_player_id: i32,
   pub params: CreateGameAsBotParams,
+  pub rules: GameRules,
+  pub metadata: GameMetadata,
+  pub request_id: Option<CreateRequestId>,
 }
 
-impl Message for CreateGameAsBot {
+impl Message for NameFiltered<CreateGameAsBot> {
   type Result = Result<Game>;
 }
 
+impl CreateGameAsBot {
+  pub async fn filter_name(mut self) -> Result<NameFiltered<Self>> {
+    self.params.name = text_filter::apply(
+      TextKind::GameName,
+      self.params.name,
+      None,
+      Error::GameNameBlocked,
+    )
+    .await?;
+    Ok(NameFiltered(self))
+  }
+}
+
 #[async_trait]
-impl Handler<CreateGameAsBot> for GameRegistry {
+impl Handler<NameFiltered<CreateGameAsBot>> for GameRegistry {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    CreateGameAsBot {
+    NameFiltered(CreateGameAsBot {
       api_client_id,
       api_player_id,
       params,
-    }: CreateGameAsBot,
-  ) -> <CreateGameAsBot as Message>::Result {
-    let (mut game, player_ids, mute_list_map) = self
+      rules,
+      metadata,
+      request_id,
+    }): NameFiltered<CreateGameAsBot>,
+  ) -> <NameFiltered<CreateGameAsBot> as Message>::Result {
+    self.maintenance.check_game_creation()?;
+
+    if let Some(request_id) = request_id.clone() {
+      let game = self
+        .db
+        .exec(move |conn| crate::game::request::find_game(conn, &request_id))
+        .await?;
+      if let Some(mut game) = game {
+        tracing::info!(game_id = game.id, "create game as bot: duplicated request");
+        mask_player_names(&mut game);
+        return Ok(game);
+      }
+    }
+
+    let res = self
       .db
       .exec(move |conn| {
-        let game = crate::game::db::create_as_bot(conn, api_client_id, api_player_id, params)?;
+        let game = crate::game::db::create_as_bot(
+          conn,
+          api_client_id,
+          api_player_id,
+          params,
+          rules,
+          metadata,
+          request_id,
+        )?;
         let player_ids = game.get_player_ids();
         let mute_list_map = crate::player::db::get_mute_list_map(conn, &player_ids)?;
         Ok::<_, Error>((game, player_ids, mute_list_map))
       })
-      .await?;
-
-    if game.mask_player_names {
-      for (idx, slot) in game.slots.iter_mut().enumerate() {
-        slot
-          .player
-          .as_mut()
-          .map(|v| v.name = format!("Player {}", idx + 1));
+      .await;
+    let (mut game, player_ids, mute_list_map) = match res {
+      Ok(v) => v,
+      Err(err) => {
+        let mut game = self.existing_game(err).await?;
+        mask_player_names(&mut game);
+        return Ok(game);
       }
-    }
+    };
+
+    mask_player_names(&mut game);
 
     self.register(Register {
       id: game.id,
@@ -93,9 +218,59 @@ impl Handler<CreateGameAsBot> for GameRegistry {
 
     self
       .players
-      .players_replace_game(player_ids, game.clone(), mute_list_map)
+      .players_replace_game(player_ids.clone(), game.clone(), mute_list_map)
       .await?;
 
+    if !game.is_private {
+      self
+        .players
+        .update_game_list(GameListChange::Upsert(entry_from_game(&game)))
+        .await?;
+    }
+
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameCreate,
+        AuditActor::ApiClient(api_client_id),
+        game.id,
+      )
+      .with_payload(serde_json::json!({
+        "name": game.name,
+        "map": game.map.name,
+        "is_private": game.is_private,
+        "players": player_ids,
+      })),
+    );
+    emit_created(&self.db, &game);
+    crate::discord::announce_game_created(&game);
+
     Ok(game)
   }
 }
+
+/// Replaces the player names with their slot numbers if the game hides them
+pub(crate) fn mask_player_names(game: &mut Game) {
+  if game.mask_player_names {
+    for (idx, slot) in game.slots.iter_mut().enumerate() {
+      slot
+        .player
+        .as_mut()
+        .map(|v| v.name = format!("Player {}", idx + 1));
+    }
+  }
+}
+
+fn emit_created(db: &ExecutorRef, game: &Game) {
+  crate::webhook::emit(
+    db,
+    WebhookEventKind::GameCreated,
+    serde_json::json!({
+      "game_id": game.id,
+      "name": game.name,
+      "map": game.map.name,
+      "is_private": game.is_private,
+      "created_by": game.created_by.id,
+    }),
+  );
+}
@@ -0,0 +1,267 @@
+use crate::catalogue;
+use crate::error::*;
+use crate::game::state::registry::UpdateGameNodeCache;
+use crate::game::state::start::CreateOnNodeResult;
+use crate::game::state::{GameActor, GameRegistry};
+use crate::node::messages::{NodePlayerLeave, SelectNodeForGame};
+use crate::node::PlayerLeaveResponse;
+use crate::state::ActorMapExt;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_common::ErrorCode;
+use flo_state::{async_trait, Context, Handler, Message};
+use flo_types::lifecycle::GamePhase;
+
+/// Moves the games on a node that went down to other nodes,
+/// games already running on the node are not affected.
+pub struct FailoverNodeGames {
+  pub node_id: i32,
+}
+
+impl Message for FailoverNodeGames {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<FailoverNodeGames> for GameRegistry {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    FailoverNodeGames { node_id }: FailoverNodeGames,
+  ) {
+    let games: Vec<_> = self
+      .game_node_map
+      .iter()
+      .filter(|(_, id)| **id == node_id)
+      .filter_map(|(game_id, _)| Some((*game_id, self.map.get(game_id)?.addr())))
+      .collect();
+
+    if games.is_empty() {
+      return;
+    }
+
+    tracing::warn!(node_id, "failover: {} games", games.len());
+
+    let registry = ctx.addr();
+    ctx.spawn(async move {
+      for (game_id, addr) in games {
+        match addr.send(MigrateGameNode { node_id }).await {
+          Ok(Ok(Some(next_node_id))) => {
+            tracing::info!(game_id, node_id, next_node_id, "game migrated");
+            registry
+              .notify(UpdateGameNodeCache {
+                game_id,
+                node_id: Some(next_node_id),
+              })
+              .await
+              .ok();
+          }
+          Ok(Ok(None)) => {}
+          Ok(Err(err)) => tracing::error!(game_id, node_id, "migrate game: {}", err),
+          Err(err) => tracing::error!(game_id, node_id, "migrate game: {}", err),
+        }
+      }
+    });
+  }
+}
+
+/// Returns the node the game has been moved to
+pub struct MigrateGameNode {
+  pub node_id: i32,
+}
+
+impl Message for MigrateGameNode {
+  type Result = Result<Option<i32>>;
+}
+
+#[async_trait]
+impl Handler<MigrateGameNode> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    MigrateGameNode { node_id }: MigrateGameNode,
+  ) -> Result<Option<i32>> {
+    if self.selected_node_id != Some(node_id) {
+      return Ok(None);
+    }
+
+    match self.lifecycle.phase() {
+      GamePhase::Created | GamePhase::Lobby => self.migrate_selected_node(node_id).await,
+      GamePhase::Loading => self.migrate_created_game(node_id).await,
+      _ => Ok(None),
+    }
+  }
+}
+
+impl GameActor {
+  /// Selects another node for a game that has not been created on the node yet
+  async fn migrate_selected_node(&mut self, node_id: i32) -> Result<Option<i32>> {
+    let host_player = self.host_player;
+    let host_preferences = self
+      .db
+      .exec(move |conn| crate::player_preferences::db::get(conn, host_player))
+      .await?;
+    let ping_map = self.player_reg.get_ping_map(self.host_player).await?;
+    let fallback_region = self
+      .player_reg
+      .get_suggested_region(self.host_player)
+      .await?;
+    let next_node_id = match self
+      .nodes
+      .send(SelectNodeForGame {
+        ping_map,
+        region: host_preferences.node_region,
+        fallback_region,
+        exclude: vec![node_id],
+      })
+      .await?
+    {
+      Ok(node) => Some(node.id),
+      // a node will be picked once the game starts
+      Err(Error::NoNodeAvailable) => None,
+      Err(err) => return Err(err),
+    };
+    self.select_node(self.host_player, next_node_id).await?;
+    Ok(next_node_id)
+  }
+
+  /// Re-creates the game on another node and sends the new tokens to the players.
+  /// Resets the game to the lobby if no node can host it.
+  async fn migrate_created_game(&mut self, node_id: i32) -> Result<Option<i32>> {
+    let game_id = self.game_id;
+    let host_player = self.host_player;
+    let (mut game, ban_list_map, mute_list_map, rules, host_preferences) = self
+      .db
+      .exec(move |conn| {
+        let game = crate::game::db::get_full(conn, game_id)?;
+        let players = game.get_player_ids();
+        let ban_list_map = crate::player::db::get_ban_list_map(conn, &players)?;
+        let mute_list_map = crate::player::db::get_mute_list_map(conn, &players)?;
+        let rules = crate::game::db::get_rules(conn, game_id)?;
+        let host_preferences = crate::player_preferences::db::get(conn, host_player)?;
+        crate::game::db::update_reset_created(conn, game_id)?;
+        Ok::<_, Error>((game, ban_list_map, mute_list_map, rules, host_preferences))
+      })
+      .await?;
+    self.transition(GamePhase::Lobby).await?;
+    self.player_tokens.clear();
+
+    // the players already agreed on the version when the game was created
+    let agreed_version = game.game_version.clone();
+    game.node = None;
+    let res = self
+      .create_on_node(
+        &mut game,
+        &ban_list_map,
+        &mute_list_map,
+        &rules,
+        host_preferences.node_region,
+        vec![node_id],
+      )
+      .await?;
+
+    match res {
+      CreateOnNodeResult::Created {
+        node_id: next_node_id,
+        created,
+      } => {
+        self
+          .issue_player_tokens(next_node_id, created, agreed_version)
+          .await?;
+        self.transition(GamePhase::Loading).await?;
+        Ok(Some(next_node_id))
+      }
+      CreateOnNodeResult::NoCapacity | CreateOnNodeResult::Rejected(_) => {
+        tracing::warn!(game_id, node_id, "failover: no node can host the game");
+        self.player_client_status_map.clear();
+        self.select_node(self.host_player, None).await?;
+
+        let frame = catalogue::GAME_START_NODE_DOWN
+          .message()
+          .game_start_reject(game_id, ErrorCode::NodeNotReady)
+          .encode_as_frame()?;
+        self
+          .player_reg
+          .broadcast(self.players.clone(), frame)
+          .await?;
+        Ok(None)
+      }
+    }
+  }
+}
+
+/// An event reported by a node about one of its games.
+/// A game moved away from a node during a partition may still be held by it,
+/// events from a node other than the selected one are rejected and the node is told to drop the game.
+pub struct NodeGameEvent<M> {
+  pub node_id: i32,
+  pub event: M,
+}
+
+impl<M, R> Message for NodeGameEvent<M>
+where
+  M: Message<Result = Result<R>>,
+  R: Send + 'static,
+{
+  type Result = Result<R>;
+}
+
+#[async_trait]
+impl<M, R> Handler<NodeGameEvent<M>> for GameActor
+where
+  GameActor: Handler<M>,
+  M: Message<Result = Result<R>>,
+  R: Send + 'static,
+{
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    NodeGameEvent { node_id, event }: NodeGameEvent<M>,
+  ) -> Result<R> {
+    if self.selected_node_id != Some(node_id) {
+      self.release_stale_node(ctx, node_id);
+      return Err(Error::GameNodeStale);
+    }
+    Handler::<M>::handle(self, ctx, event).await
+  }
+}
+
+impl GameActor {
+  /// Removes all players from the game on a node it has been moved away from,
+  /// which ends the game there.
+  fn release_stale_node(&mut self, ctx: &mut Context<Self>, node_id: i32) {
+    if !self.released_node_ids.insert(node_id) {
+      return;
+    }
+
+    let game_id = self.game_id;
+    let players = self.players.clone();
+    let nodes = self.nodes.clone();
+    tracing::warn!(game_id, node_id, "releasing game on stale node");
+    ctx.spawn(async move {
+      for player_id in players {
+        let res = match nodes
+          .send_to(node_id, NodePlayerLeave { game_id, player_id })
+          .await
+        {
+          Ok(deferred) => deferred.await.or_cancelled(),
+          Err(err) => Err(err),
+        };
+        match res {
+          Ok(PlayerLeaveResponse::Accepted(_)) => {}
+          Ok(PlayerLeaveResponse::Rejected(reason)) => {
+            tracing::debug!(
+              game_id,
+              node_id,
+              player_id,
+              "release rejected: {:?}",
+              reason
+            );
+          }
+          Err(err) => {
+            tracing::error!(game_id, node_id, player_id, "release: {}", err);
+          }
+        }
+      }
+    });
+  }
+}
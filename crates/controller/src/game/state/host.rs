@@ -0,0 +1,168 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
+use crate::error::*;
+use crate::game::db::TransferHost as TransferHostResult;
+use crate::game::state::GameActor;
+use diesel::prelude::*;
+use flo_net::packet::FloPacket;
+use flo_net::proto;
+use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Host rights are given to another player if the host does not reconnect in time
+const HOST_RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct TransferHost {
+  pub player_id: i32,
+  pub new_host_player_id: i32,
+}
+
+impl Message for TransferHost {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<TransferHost> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    TransferHost {
+      player_id,
+      new_host_player_id,
+    }: TransferHost,
+  ) -> Result<()> {
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    self
+      .transfer_host(AuditActor::Player(player_id), new_host_player_id)
+      .await
+  }
+}
+
+pub struct HostDisconnected {
+  pub player_id: i32,
+}
+
+impl Message for HostDisconnected {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<HostDisconnected> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    HostDisconnected { player_id }: HostDisconnected,
+  ) -> Result<()> {
+    if self.host_player != player_id || !self.in_lobby() {
+      return Ok(());
+    }
+
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(HOST_RECONNECT_TIMEOUT).await;
+      addr.notify(HostReconnectTimeout { player_id }).await.ok();
+    });
+
+    Ok(())
+  }
+}
+
+struct HostReconnectTimeout {
+  player_id: i32,
+}
+
+impl Message for HostReconnectTimeout {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<HostReconnectTimeout> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    HostReconnectTimeout { player_id }: HostReconnectTimeout,
+  ) {
+    let game_id = self.game_id;
+
+    if self.host_player != player_id || !self.in_lobby() || self.started() {
+      return;
+    }
+
+    let res: Result<()> = async {
+      let online = self
+        .player_reg
+        .get_online_players(self.players.clone())
+        .await?;
+      if online.contains(&player_id) {
+        return Ok(());
+      }
+
+      // the first connected player in slot order, players who joined later come last
+      let new_host_player_id = if let Some(id) = online.into_iter().find(|id| *id != player_id) {
+        id
+      } else {
+        return Ok(());
+      };
+
+      self
+        .transfer_host(AuditActor::System, new_host_player_id)
+        .await
+    }
+    .await;
+
+    if let Err(err) = res {
+      tracing::error!(game_id, player_id, "transfer host: {}", err);
+    }
+  }
+}
+
+impl GameActor {
+  async fn transfer_host(&mut self, actor: AuditActor, new_host_player_id: i32) -> Result<()> {
+    let game_id = self.game_id;
+    let player_id = self.host_player;
+
+    let TransferHostResult { host, slots } = self
+      .db
+      .exec(move |conn| {
+        conn.transaction(|| {
+          crate::game::db::transfer_host(conn, game_id, player_id, new_host_player_id)
+        })
+      })
+      .await?;
+
+    self.host_player = new_host_player_id;
+
+    tracing::info!(game_id, player_id, new_host_player_id, "host transferred");
+
+    let frame = proto::flo_connect::PacketGameHostUpdate {
+      game_id,
+      host: Some(host.pack()?),
+    }
+    .encode_as_frame()?;
+    let players = slots
+      .iter()
+      .filter_map(|s| s.player.as_ref().map(|p| p.id))
+      .collect();
+    self.player_reg.broadcast(players, frame).await?;
+
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(AuditEventKind::GameTransferHost, actor, game_id).with_payload(
+        serde_json::json!({
+          "from": player_id,
+          "to": new_host_player_id,
+        }),
+      ),
+    );
+
+    Ok(())
+  }
+}
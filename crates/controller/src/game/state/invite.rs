@@ -0,0 +1,78 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
+use crate::error::*;
+use crate::game::state::GameActor;
+use flo_net::packet::FloPacket;
+use flo_net::proto;
+use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
+
+pub struct InvitePlayer {
+  pub player_id: i32,
+  pub target_player_id: i32,
+}
+
+impl Message for InvitePlayer {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<InvitePlayer> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    InvitePlayer {
+      player_id,
+      target_player_id,
+    }: InvitePlayer,
+  ) -> Result<()> {
+    let game_id = self.game_id;
+
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    if !self.in_lobby() || self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    if self.players.contains(&target_player_id) {
+      return Err(Error::PlayerAlreadyInGame);
+    }
+
+    let game = self
+      .db
+      .exec(move |conn| crate::game::db::get(conn, game_id))
+      .await?;
+
+    let online = self
+      .player_reg
+      .get_online_players(vec![target_player_id])
+      .await?;
+    if online.is_empty() {
+      tracing::debug!(game_id, target_player_id, "invite: player offline");
+      return Ok(());
+    }
+
+    let token = crate::game::token::create_invite_token(game_id, target_player_id)?;
+    let frame = proto::flo_connect::PacketGameInvite {
+      game_id,
+      game_name: game.name,
+      host: game.created_by.map(|p| p.pack()).transpose()?,
+      token,
+    }
+    .encode_as_frame()?;
+    self.player_reg.send(target_player_id, frame).await?;
+
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameInvite,
+        AuditActor::Player(player_id),
+        game_id,
+      )
+      .with_payload(serde_json::json!({ "player_id": target_player_id })),
+    );
+
+    Ok(())
+  }
+}
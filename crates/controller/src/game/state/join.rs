@@ -1,6 +1,9 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
 use crate::error::*;
 use crate::game::state::GameActor;
 use crate::game::Game;
+use crate::policy::Policy;
+use chrono::Utc;
 use diesel::prelude::*;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
@@ -10,6 +13,9 @@ use s2_grpc_utils::S2ProtoPack;
 
 pub struct PlayerJoin {
   pub player_id: i32,
+  pub password: Option<String>,
+  /// Joining with a join token skips the password check
+  pub by_token: bool,
 }
 
 impl Message for PlayerJoin {
@@ -21,15 +27,39 @@ impl Handler<PlayerJoin> for GameActor {
   async fn handle(
     &mut self,
     _: &mut Context<Self>,
-    PlayerJoin { player_id }: PlayerJoin,
+    PlayerJoin {
+      player_id,
+      password,
+      by_token,
+    }: PlayerJoin,
+  ) -> Result<Game> {
+    self.join(player_id, password, by_token).await
+  }
+}
+
+impl GameActor {
+  pub(super) async fn join(
+    &mut self,
+    player_id: i32,
+    password: Option<String>,
+    by_token: bool,
   ) -> Result<Game> {
     let game_id = self.game_id;
+    let reserved_slots = self.waitlist.reserved_slots(player_id);
     let (game, mute_list) = self
       .db
       .exec(move |conn| {
+        if !by_token {
+          crate::game::db::check_password(conn, game_id, password.as_deref())?;
+        }
         conn.transaction(|| {
-          crate::game::db::add_player(conn, game_id, player_id)?;
+          crate::game::db::add_player(conn, game_id, player_id, reserved_slots)?;
           let game = crate::game::db::get_full(conn, game_id)?;
+          // there are no ranked queues, public games are where new accounts meet strangers
+          if !by_token && !game.is_private && Policy::get().min_account_age_days.is_some() {
+            let player = crate::player::db::get(conn, player_id)?;
+            Policy::get().check_account_age(player.created_at, Utc::now())?;
+          }
           let mut mute_list_map =
             crate::player::db::get_mute_list_map(conn, &game.get_player_ids())?;
           Ok::<_, Error>((game, mute_list_map.remove(&player_id).unwrap_or_default()))
@@ -38,6 +68,9 @@ impl Handler<PlayerJoin> for GameActor {
       .await?;
 
     self.players.push(player_id);
+    if self.waitlist.remove(player_id) {
+      self.send_waitlist_positions().await?;
+    }
 
     // send game info to joined player
     self
@@ -65,12 +98,70 @@ impl Handler<PlayerJoin> for GameActor {
             ..Default::default()
           }
           .into(),
+          slots_version: game.slots_version,
         }
       }
       .encode_as_frame()?;
       self.player_reg.broadcast(players, frame).await?;
     }
 
+    if let Err(err) = self.ready_check_player_joined(player_id).await {
+      tracing::error!(game_id, player_id, "ready check: {}", err);
+    }
+
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameJoin,
+        AuditActor::Player(player_id),
+        game_id,
+      ),
+    );
+
+    if Policy::get().flag_shared_ip {
+      if let Err(err) = self.flag_shared_ip(&game, player_id).await {
+        tracing::error!(game_id, player_id, "flag shared ip: {}", err);
+      }
+    }
+
     Ok(game)
   }
+
+  /// Records an audit event for moderators if the joined player has the same IP as other players
+  async fn flag_shared_ip(&self, game: &Game, player_id: i32) -> Result<()> {
+    let ips = self
+      .player_reg
+      .get_player_ips(game.get_player_ids())
+      .await?;
+    let ip = if let Some(ip) = ips.get(&player_id) {
+      ip
+    } else {
+      return Ok(());
+    };
+    let shared_with: Vec<i32> = ips
+      .iter()
+      .filter(|(id, v)| **id != player_id && *v == ip)
+      .map(|(id, _)| *id)
+      .collect();
+    if shared_with.is_empty() {
+      return Ok(());
+    }
+
+    tracing::warn!(
+      game_id = game.id,
+      player_id,
+      "shared ip with players: {:?}",
+      shared_with
+    );
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(AuditEventKind::GameSharedIp, AuditActor::System, game.id).with_payload(
+        serde_json::json!({
+          "player_id": player_id,
+          "shared_with": shared_with,
+        }),
+      ),
+    );
+    Ok(())
+  }
 }
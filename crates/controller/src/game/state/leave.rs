@@ -1,6 +1,7 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
 use crate::error::*;
 use crate::game::state::GameActor;
-use crate::game::{GameStatus, SlotClientStatus};
+use crate::game::SlotClientStatus;
 use crate::node::{messages as node_messages, PlayerLeaveResponse};
 use crate::player::state::sender::PlayerFrames;
 use crate::state::ActorMapExt;
@@ -8,6 +9,7 @@ use diesel::prelude::*;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
 use flo_state::{async_trait, Context, Handler, Message};
+use flo_types::lifecycle::GamePhase;
 use s2_grpc_utils::S2ProtoEnum;
 use std::collections::BTreeMap;
 
@@ -28,13 +30,28 @@ pub struct PlayerLeaveResult {
 impl Handler<PlayerLeave> for GameActor {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     PlayerLeave { player_id }: PlayerLeave,
   ) -> Result<PlayerLeaveResult> {
     let game_id = self.game_id;
-    let result = match self.status {
-      GameStatus::Preparing => leave_game_lobby(self, game_id, player_id).await?,
-      GameStatus::Created | GameStatus::Running | GameStatus::Paused => {
+    let result = match self.lifecycle.phase() {
+      GamePhase::Created | GamePhase::Lobby => {
+        let result = leave_game_lobby(self, game_id, player_id).await?;
+        let res = if result.game_ended {
+          self.ready_check.take();
+          self.clear_waitlist().await
+        } else {
+          if let Err(err) = self.ready_check_player_left(ctx, player_id).await {
+            tracing::error!(game_id, "ready check: {}", err);
+          }
+          self.promote_waitlist(ctx).await
+        };
+        if let Err(err) = res {
+          tracing::error!(game_id, "update waitlist: {}", err);
+        }
+        result
+      }
+      GamePhase::Loading | GamePhase::Running => {
         if let Some(node_id) = self.selected_node_id.clone() {
           leave_game_abort(self, game_id, player_id, node_id).await?
         } else {
@@ -42,11 +59,11 @@ impl Handler<PlayerLeave> for GameActor {
           PlayerLeaveResult::default()
         }
       }
-      GameStatus::Ended => {
+      GamePhase::Ended => {
         tracing::error!(game_id, player_id, "player requested to leave a Ended game");
         PlayerLeaveResult::default()
       }
-      GameStatus::Terminated => {
+      GamePhase::Aborted => {
         tracing::error!(
           game_id,
           player_id,
@@ -61,6 +78,19 @@ impl Handler<PlayerLeave> for GameActor {
       .player_leave_game(player_id, self.game_id)
       .await?;
 
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameLeave,
+        AuditActor::Player(player_id),
+        game_id,
+      )
+      .with_payload(serde_json::json!({
+        "status": self.status(),
+        "game_ended": result.game_ended,
+      })),
+    );
+
     Ok(result)
   }
 }
@@ -73,7 +103,7 @@ async fn leave_game_lobby(
 ) -> Result<PlayerLeaveResult> {
   let leave = state
     .db
-    .exec(move |conn| crate::game::db::remove_player(conn, game_id, player_id))
+    .exec(move |conn| conn.transaction(|| crate::game::db::remove_player(conn, game_id, player_id)))
     .await?;
 
   let recipient_player_ids: Vec<i32> = leave
@@ -89,6 +119,7 @@ async fn leave_game_lobby(
     leave.game_ended,
     &leave.removed_players,
     &recipient_player_ids,
+    leave.slots_version,
   )
   .await?;
 
@@ -181,6 +212,7 @@ async fn leave_game_abort(
     false, // only change game status by node packet
     &[player_id],
     &active_player_ids,
+    0, // slots are not released after the game was created
   )
   .await?;
 
@@ -194,6 +226,7 @@ async fn broadcast(
   ended: bool,
   left_players: &[i32],
   recipient_players: &[i32],
+  slots_version: i32,
 ) -> Result<()> {
   if ended {
     state
@@ -212,6 +245,7 @@ async fn broadcast(
       game_id,
       player_id,
       reason: proto::flo_connect::PlayerLeaveReason::Left.into(),
+      slots_version,
     }
     .encode_as_frame()?;
 
@@ -1,33 +1,50 @@
+pub mod anomaly;
 pub mod cancel;
 pub mod create;
+pub mod failover;
+pub mod host;
+pub mod invite;
 pub mod join;
 pub mod leave;
 pub mod node;
 pub mod player;
+pub mod ready;
 pub mod registry;
 pub mod slot;
 pub mod start;
 pub mod status;
+pub mod summary;
+pub mod waitlist;
 
 pub use status::{GameSlotClientStatusUpdate, GameStatusUpdate};
 
 use crate::error::*;
 use crate::game::db::{get_all_active_game_state, get_expired_games};
 use crate::game::{GameStatus, SlotClientStatus};
+use crate::maintenance::{GetMaintenanceGate, Maintenance, MaintenanceGate};
 use crate::node::{NodeRegistry, PlayerToken};
+use crate::player::state::game_list::{status_to_proto, GameListChange};
 use crate::player::state::sender::PlayerRegistryHandle;
 
 use crate::game::state::cancel::CancelGame;
 use crate::game::state::registry::Remove;
+use crate::player::state::bus::{GetLobbyBus, LobbyBus};
 use crate::player::state::PlayerRegistry;
 use crate::state::{Data, GetActorEntry};
-use bs_diesel_utils::ExecutorRef;
+use crate::webhook::WebhookEventKind;
+use crate::db::ExecutorRef;
+use flo_net::proto::flo_connect::GameListEntry;
 use flo_state::*;
+use flo_types::lifecycle::{GameLifecycle, GamePhase, GameTransition};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use ready::ReadyCheck;
 use start::StartGameState;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::collections::HashMap;
 use std::time::Duration;
 use tokio::time::sleep;
+use waitlist::Waitlist;
 
 const GAME_INACTIVE_CHECK_INTERVAL: Duration = Duration::from_secs(3600 * 30);
 
@@ -35,6 +52,9 @@ pub struct GameRegistry {
   db: ExecutorRef,
   players: PlayerRegistryHandle,
   nodes: Addr<NodeRegistry>,
+  maintenance: MaintenanceGate,
+  /// Records the games created by this instance as owned by it
+  bus: Option<LobbyBus>,
   map: BTreeMap<i32, Owner<GameActor>>,
   player_games_map: BTreeMap<i32, Vec<i32>>,
   game_players_map: BTreeMap<i32, Vec<i32>>,
@@ -46,6 +66,8 @@ impl GameRegistry {
     db: ExecutorRef,
     player_packet_sender: PlayerRegistryHandle,
     nodes: Addr<NodeRegistry>,
+    maintenance: MaintenanceGate,
+    bus: Option<LobbyBus>,
   ) -> Result<GameRegistry> {
     let games = db.exec(|conn| get_all_active_game_state(conn)).await?;
     let mut map = BTreeMap::new();
@@ -54,12 +76,28 @@ impl GameRegistry {
     let mut game_node_map = BTreeMap::new();
 
     for game in games {
+      if !game.is_private {
+        player_packet_sender
+          .update_game_list(GameListChange::Upsert(GameListEntry {
+            id: game.id,
+            name: game.name.clone(),
+            map_name: game.map_name.clone(),
+            players: game.players.len() as i32,
+            max_players: game.max_players,
+            status: status_to_proto(game.status),
+            lobby_id: String::new(),
+          }))
+          .await?;
+      }
+
       let mut players = Vec::with_capacity(game.players.len());
       let mut player_tokens = HashMap::new();
+      let mut player_client_status_map = HashMap::new();
 
       game_players_map.insert(game.id, game.players.iter().map(|t| t.0).collect());
-      for (id, token) in game.players {
+      for (id, token, client_status) in game.players {
         players.push(id);
+        player_client_status_map.insert(id, client_status);
         if let Some(token) = token.and_then(|v| PlayerToken::from_vec(id, v)) {
           player_tokens.insert(id, token.bytes);
         }
@@ -80,13 +118,18 @@ impl GameRegistry {
           db: db.clone(),
           player_reg: player_packet_sender.clone(),
           nodes: nodes.clone(),
-          status: game.status,
+          lifecycle: GameActor::lifecycle(game.status),
+          start_announced: game.status != GameStatus::Preparing,
           host_player: game.created_by,
           players,
           selected_node_id: game.node_id,
           start_state: None,
+          start_queued: false,
+          ready_check: None,
+          waitlist: Waitlist::default(),
           player_tokens,
-          player_client_status_map: Default::default(),
+          player_client_status_map,
+          released_node_ids: BTreeSet::new(),
         }),
       );
     }
@@ -95,6 +138,8 @@ impl GameRegistry {
       db: db.clone(),
       players: player_packet_sender.clone(),
       nodes: nodes.clone(),
+      maintenance,
+      bus,
       map,
       player_games_map,
       game_players_map,
@@ -146,8 +191,21 @@ impl Service<Data> for GameRegistry {
 
   async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
     let players = registry.resolve::<PlayerRegistry>().await?;
+    let bus = players.send(GetLobbyBus).await?;
     let nodes = registry.resolve::<NodeRegistry>().await?;
-    Self::init(registry.data().db.clone(), players.into(), nodes).await
+    let maintenance = registry
+      .resolve::<Maintenance>()
+      .await?
+      .send(GetMaintenanceGate)
+      .await?;
+    Self::init(
+      registry.data().db.clone(),
+      players.into(),
+      nodes,
+      maintenance,
+      bus,
+    )
+    .await
   }
 }
 
@@ -191,13 +249,22 @@ pub struct GameActor {
   pub db: ExecutorRef,
   pub player_reg: PlayerRegistryHandle,
   pub nodes: Addr<NodeRegistry>,
-  pub status: GameStatus,
+  pub lifecycle: GameLifecycle,
+  /// The `GameStarted` webhook has been sent, a game moved to another node
+  /// while loading is not announced again
+  pub start_announced: bool,
   pub host_player: i32,
   pub players: Vec<i32>,
   pub selected_node_id: Option<i32>,
   pub start_state: Option<Owner<StartGameState>>,
+  /// Waiting for a node with free capacity
+  pub start_queued: bool,
+  pub ready_check: Option<ReadyCheck>,
+  pub waitlist: Waitlist,
   pub player_tokens: HashMap<i32, [u8; 16]>,
   pub player_client_status_map: HashMap<i32, SlotClientStatus>,
+  /// Nodes the game was moved away from that have been told to drop it
+  pub released_node_ids: BTreeSet<i32>,
 }
 
 impl Actor for GameActor {}
@@ -206,4 +273,94 @@ impl GameActor {
   fn started(&self) -> bool {
     self.start_state.is_some() || !self.player_tokens.is_empty()
   }
+
+  pub fn lifecycle(status: GameStatus) -> GameLifecycle {
+    let mut lifecycle = GameLifecycle::new(status.phase());
+    lifecycle.on_transition(|transition| {
+      GAME_TRANSITIONS
+        .with_label_values(&[transition.to.as_str()])
+        .inc()
+    });
+    lifecycle
+  }
+
+  pub fn status(&self) -> GameStatus {
+    self.lifecycle.phase().into()
+  }
+
+  /// Players can still join, leave and change slots
+  pub fn in_lobby(&self) -> bool {
+    self.lifecycle.phase() == GamePhase::Lobby
+  }
+
+  /// Returns `false` if the game is already in the phase
+  async fn transition(&mut self, phase: GamePhase) -> Result<bool> {
+    let from = self.lifecycle.phase();
+    if !self.lifecycle.transition(phase)? {
+      return Ok(false);
+    }
+    self
+      .on_transition(GameTransition { from, to: phase })
+      .await?;
+    Ok(true)
+  }
+
+  /// Announces a phase change to the game list, the webhooks and the players.
+  async fn on_transition(&mut self, transition: GameTransition) -> Result<()> {
+    let game_id = self.game_id;
+    self
+      .player_reg
+      .update_game_list(GameListChange::Status {
+        game_id,
+        status: self.status(),
+      })
+      .await?;
+
+    match transition.to {
+      GamePhase::Loading if !self.start_announced => {
+        self.start_announced = true;
+        crate::webhook::emit(
+          &self.db,
+          WebhookEventKind::GameStarted,
+          serde_json::json!({
+            "game_id": game_id,
+            "node_id": self.selected_node_id,
+            "players": self.players,
+          }),
+        );
+      }
+      GamePhase::Ended | GamePhase::Aborted => {
+        let kind = if transition.to == GamePhase::Ended {
+          crate::discord::announce_game_ended(&self.db, game_id);
+          WebhookEventKind::GameFinished
+        } else {
+          WebhookEventKind::GameAborted
+        };
+        crate::webhook::emit(
+          &self.db,
+          kind,
+          serde_json::json!({
+            "game_id": game_id,
+            "status": self.status(),
+            "players": self.players,
+          }),
+        );
+        self
+          .player_reg
+          .players_leave_game(self.players.clone(), game_id)
+          .await?;
+      }
+      GamePhase::Created | GamePhase::Lobby | GamePhase::Loading | GamePhase::Running => {}
+    }
+    Ok(())
+  }
 }
+
+static GAME_TRANSITIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec!(
+    "flocontroller_game_transitions_total",
+    "Game phase transitions, by the phase entered",
+    &["phase"]
+  )
+  .unwrap()
+});
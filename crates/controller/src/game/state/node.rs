@@ -1,6 +1,7 @@
 use crate::error::*;
 use crate::game::state::GameActor;
 
+use diesel::prelude::*;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
 use flo_state::{async_trait, Context, Handler, Message};
@@ -21,15 +22,56 @@ impl Handler<SelectNode> for GameActor {
     _: &mut Context<Self>,
     SelectNode { node_id, player_id }: SelectNode,
   ) -> Result<()> {
-    let game_id = self.game_id;
-
     if self.started() {
       return Err(Error::GameStarted);
     }
 
+    self.select_node(player_id, node_id).await
+  }
+}
+
+/// Node the game has been created on, only players in the game can query it
+pub struct GetGameNode {
+  pub player_id: i32,
+}
+
+impl Message for GetGameNode {
+  type Result = Result<Option<i32>>;
+}
+
+#[async_trait]
+impl Handler<GetGameNode> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetGameNode { player_id }: GetGameNode,
+  ) -> Result<Option<i32>> {
+    if !self.players.contains(&player_id) {
+      return Err(Error::PlayerNotInGame);
+    }
+
+    if self.player_tokens.is_empty() {
+      return Ok(None);
+    }
+
+    Ok(self.selected_node_id)
+  }
+}
+
+impl GameActor {
+  pub(crate) async fn select_node(&mut self, player_id: i32, node_id: Option<i32>) -> Result<()> {
+    let game_id = self.game_id;
+
     self
       .db
-      .exec(move |conn| crate::game::db::select_node(conn, game_id, player_id, node_id))
+      .exec(move |conn| {
+        conn.transaction(|| {
+          if let Some(node_id) = node_id {
+            crate::node::db::check_capacity(conn, node_id, Some(game_id))?;
+          }
+          crate::game::db::select_node(conn, game_id, player_id, node_id)
+        })
+      })
       .await?;
 
     self.selected_node_id = node_id;
@@ -0,0 +1,272 @@
+use crate::error::*;
+use crate::game::state::GameActor;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::{PacketReadyCheck, PacketReadyCheckReject, PlayerInfo};
+use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoPack;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+const READY_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Requested by the host with the start, players other than the host confirm they are ready
+/// before the start check begins
+#[derive(Debug)]
+pub struct ReadyCheck {
+  started_at: Instant,
+  pending: BTreeSet<i32>,
+  declined: BTreeSet<i32>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ReadyCheckState {
+  Pending,
+  Accepted,
+  /// Players who declined or didn't respond in time
+  Rejected(Vec<i32>),
+}
+
+impl ReadyCheck {
+  fn new<I>(player_ids: I) -> Self
+  where
+    I: IntoIterator<Item = i32>,
+  {
+    ReadyCheck {
+      started_at: Instant::now(),
+      pending: player_ids.into_iter().collect(),
+      declined: BTreeSet::new(),
+    }
+  }
+
+  /// Players who join during the check have to confirm too
+  fn add(&mut self, player_id: i32) {
+    self.declined.remove(&player_id);
+    self.pending.insert(player_id);
+  }
+
+  fn remove(&mut self, player_id: i32) {
+    self.pending.remove(&player_id);
+    self.declined.remove(&player_id);
+  }
+
+  fn respond(&mut self, player_id: i32, ready: bool) {
+    if self.pending.remove(&player_id) && !ready {
+      self.declined.insert(player_id);
+    }
+  }
+
+  // players who left the game are ignored
+  fn state(&self, players: &[i32], timed_out: bool) -> ReadyCheckState {
+    let in_game = |id: &&i32| players.contains(id);
+    let pending: Vec<i32> = self.pending.iter().filter(in_game).cloned().collect();
+    let declined: Vec<i32> = self.declined.iter().filter(in_game).cloned().collect();
+    if !declined.is_empty() || (timed_out && !pending.is_empty()) {
+      let mut holdouts = declined;
+      holdouts.extend(pending);
+      ReadyCheckState::Rejected(holdouts)
+    } else if pending.is_empty() {
+      ReadyCheckState::Accepted
+    } else {
+      ReadyCheckState::Pending
+    }
+  }
+}
+
+impl GameActor {
+  pub(super) async fn begin_ready_check(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    let host_player = self.host_player;
+    let check = ReadyCheck::new(self.players.iter().cloned().filter(|id| *id != host_player));
+    if check.pending.is_empty() {
+      return self.begin_start_check(ctx).await;
+    }
+
+    let started_at = check.started_at;
+    self.ready_check = Some(check);
+
+    let frame = PacketReadyCheck {
+      game_id: self.game_id,
+      timeout_ms: READY_CHECK_TIMEOUT.as_millis() as u32,
+    }
+    .encode_as_frame()?;
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(READY_CHECK_TIMEOUT).await;
+      addr.notify(ReadyCheckTimeout { started_at }).await.ok();
+    });
+
+    Ok(())
+  }
+
+  /// Asks a player who joined the lobby during the check, the check ends at the same time
+  pub(super) async fn ready_check_player_joined(&mut self, player_id: i32) -> Result<()> {
+    let started_at = if let Some(check) = self.ready_check.as_mut() {
+      check.add(player_id);
+      check.started_at
+    } else {
+      return Ok(());
+    };
+    let frame = PacketReadyCheck {
+      game_id: self.game_id,
+      timeout_ms: READY_CHECK_TIMEOUT
+        .saturating_sub(started_at.elapsed())
+        .as_millis() as u32,
+    }
+    .encode_as_frame()?;
+    self.player_reg.send(player_id, frame).await?;
+    Ok(())
+  }
+
+  /// The check no longer waits for a player who left the lobby
+  pub(super) async fn ready_check_player_left(
+    &mut self,
+    ctx: &mut Context<Self>,
+    player_id: i32,
+  ) -> Result<()> {
+    if let Some(check) = self.ready_check.as_mut() {
+      check.remove(player_id);
+    } else {
+      return Ok(());
+    }
+    self.resolve_ready_check(ctx, false).await
+  }
+
+  async fn resolve_ready_check(&mut self, ctx: &mut Context<Self>, timed_out: bool) -> Result<()> {
+    let state = if let Some(check) = self.ready_check.as_ref() {
+      check.state(&self.players, timed_out)
+    } else {
+      return Ok(());
+    };
+
+    match state {
+      ReadyCheckState::Pending => Ok(()),
+      ReadyCheckState::Accepted => {
+        self.ready_check.take();
+        self.begin_start_check(ctx).await
+      }
+      ReadyCheckState::Rejected(holdouts) => {
+        self.ready_check.take();
+        let game_id = self.game_id;
+        tracing::debug!(game_id, "ready check rejected: {:?}", holdouts);
+        let holdouts = self
+          .db
+          .exec(move |conn| crate::player::db::get_refs_by_ids(conn, &holdouts))
+          .await?;
+        let holdouts: Vec<PlayerInfo> = holdouts.pack()?;
+        let frame = PacketReadyCheckReject { game_id, holdouts }.encode_as_frame()?;
+        self
+          .player_reg
+          .broadcast(self.players.clone(), frame)
+          .await?;
+        Ok(())
+      }
+    }
+  }
+}
+
+pub struct ReadyCheckResponse {
+  pub player_id: i32,
+  pub ready: bool,
+}
+
+impl Message for ReadyCheckResponse {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<ReadyCheckResponse> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    ReadyCheckResponse { player_id, ready }: ReadyCheckResponse,
+  ) -> Result<()> {
+    if let Some(check) = self.ready_check.as_mut() {
+      check.respond(player_id, ready);
+    } else {
+      tracing::debug!(
+        game_id = self.game_id,
+        player_id,
+        "ready check response discarded: no ready check"
+      );
+      return Ok(());
+    }
+    self.resolve_ready_check(ctx, false).await
+  }
+}
+
+struct ReadyCheckTimeout {
+  started_at: Instant,
+}
+
+impl Message for ReadyCheckTimeout {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<ReadyCheckTimeout> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    ReadyCheckTimeout { started_at }: ReadyCheckTimeout,
+  ) {
+    // a later ready check has its own timer
+    if self.ready_check.as_ref().map(|c| c.started_at) != Some(started_at) {
+      return;
+    }
+    if let Err(err) = self.resolve_ready_check(ctx, true).await {
+      tracing::error!(game_id = self.game_id, "ready check timeout: {}", err);
+    }
+  }
+}
+
+#[test]
+fn test_ready_check_state() {
+  let players = vec![1, 2, 3, 4];
+
+  let mut check = ReadyCheck::new(vec![2, 3, 4]);
+  assert_eq!(check.state(&players, false), ReadyCheckState::Pending);
+  check.respond(2, true);
+  check.respond(3, true);
+  assert_eq!(check.state(&players, false), ReadyCheckState::Pending);
+  assert_eq!(
+    check.state(&players, true),
+    ReadyCheckState::Rejected(vec![4])
+  );
+  // player 4 left the game
+  assert_eq!(check.state(&[1, 2, 3], false), ReadyCheckState::Accepted);
+  check.respond(4, true);
+  assert_eq!(check.state(&players, false), ReadyCheckState::Accepted);
+
+  let mut check = ReadyCheck::new(vec![2, 3, 4]);
+  check.respond(3, false);
+  // responses after a decline don't change the result
+  check.respond(3, true);
+  assert_eq!(
+    check.state(&players, false),
+    ReadyCheckState::Rejected(vec![3, 2, 4])
+  );
+  // players who left are neither waited for nor holdouts
+  check.remove(3);
+  assert_eq!(check.state(&players, false), ReadyCheckState::Pending);
+  check.remove(2);
+  check.remove(4);
+  assert_eq!(check.state(&players, false), ReadyCheckState::Accepted);
+
+  // player 5 joined during the check
+  let players = vec![1, 2, 5];
+  let mut check = ReadyCheck::new(vec![2]);
+  check.respond(2, true);
+  check.add(5);
+  assert_eq!(check.state(&players, false), ReadyCheckState::Pending);
+  assert_eq!(
+    check.state(&players, true),
+    ReadyCheckState::Rejected(vec![5])
+  );
+  check.respond(5, true);
+  assert_eq!(check.state(&players, false), ReadyCheckState::Accepted);
+}
@@ -1,6 +1,7 @@
 use crate::error::*;
 use crate::game::state::{GameActor, GameRegistry};
 use crate::game::GameStatus;
+use crate::player::state::game_list::GameListChange;
 use flo_state::{async_trait, Context, Handler, Message, Owner};
 use std::collections::btree_map::Entry;
 
@@ -35,6 +36,9 @@ impl GameRegistry {
       node_id,
     }: Register,
   ) {
+    if let Some(bus) = self.bus.as_ref() {
+      bus.claim_game(id);
+    }
     for player in &players {
       self.add_game_player(id, *player);
     }
@@ -45,13 +49,18 @@ impl GameRegistry {
         db: self.db.clone(),
         player_reg: self.players.clone(),
         nodes: self.nodes.clone(),
-        status,
+        lifecycle: GameActor::lifecycle(status),
+        start_announced: status != GameStatus::Preparing,
         host_player,
         players,
         selected_node_id: node_id,
         start_state: None,
+        start_queued: false,
+        ready_check: None,
+        waitlist: Default::default(),
         player_tokens: Default::default(),
         player_client_status_map: Default::default(),
+        released_node_ids: Default::default(),
       }),
     );
   }
@@ -72,9 +81,19 @@ impl Handler<Remove> for GameRegistry {
     if let Some(owner) = self.map.remove(&id) {
       self.game_players_map.remove(&id);
       self.game_node_map.remove(&id);
+      if let Some(bus) = self.bus.as_ref() {
+        bus.release_game(id);
+      }
 
       let addr = ctx.addr();
+      let players = self.players.clone();
       ctx.spawn(async move {
+        if let Err(err) = players
+          .update_game_list(GameListChange::Remove { game_id: id })
+          .await
+        {
+          tracing::warn!(game_id = id, "Remove: update game list: {}", err);
+        }
         match tokio::time::timeout(std::time::Duration::from_secs(3), owner.shutdown()).await {
           Ok(Ok(state)) => {
             let players = state.players;
@@ -117,7 +136,8 @@ impl Handler<AddGamePlayer> for GameRegistry {
     _: &mut Context<Self>,
     AddGamePlayer { game_id, player_id }: AddGamePlayer,
   ) {
-    self.add_game_player(game_id, player_id)
+    self.add_game_player(game_id, player_id);
+    self.update_game_list_players(game_id).await
   }
 }
 
@@ -137,7 +157,8 @@ impl Handler<RemoveGamePlayer> for GameRegistry {
     _: &mut Context<Self>,
     RemoveGamePlayer { game_id, player_id }: RemoveGamePlayer,
   ) {
-    self.remove_game_player(game_id, player_id)
+    self.remove_game_player(game_id, player_id);
+    self.update_game_list_players(game_id).await
   }
 }
 
@@ -165,6 +186,72 @@ impl Handler<UpdateGameNodeCache> for GameRegistry {
   }
 }
 
+/// Reconciles the active games of a connecting player loaded from the database
+/// with the restored in-memory state, returns the game the session should be attached to.
+pub struct ResolvePlayerGame {
+  pub player_id: i32,
+  pub game_ids: Vec<i32>,
+}
+
+impl Message for ResolvePlayerGame {
+  type Result = Option<i32>;
+}
+
+#[async_trait]
+impl Handler<ResolvePlayerGame> for GameRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    ResolvePlayerGame {
+      player_id,
+      game_ids,
+    }: ResolvePlayerGame,
+  ) -> Option<i32> {
+    let tracked = self
+      .player_games_map
+      .get(&player_id)
+      .cloned()
+      .unwrap_or_default();
+    let mut resolved = None;
+    for game_id in game_ids {
+      let registered = self.map.contains_key(&game_id);
+      if registered && !tracked.contains(&game_id) {
+        tracing::warn!(game_id, player_id, "player game membership restored");
+        self.add_game_player(game_id, player_id);
+      }
+      if registered {
+        resolved = Some(game_id);
+      } else {
+        tracing::warn!(game_id, player_id, "active slot of a game not loaded");
+      }
+    }
+    resolved
+  }
+}
+
+pub struct GetPlayerGames {
+  pub player_id: i32,
+}
+
+impl Message for GetPlayerGames {
+  type Result = Vec<i32>;
+}
+
+#[async_trait]
+impl Handler<GetPlayerGames> for GameRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetPlayerGames { player_id }: GetPlayerGames,
+  ) -> Vec<i32> {
+    self
+      .player_games_map
+      .get(&player_id)
+      .cloned()
+      .unwrap_or_default()
+  }
+}
+
 pub struct ResolveGamePlayerPingBroadcastTargets {
   pub player_id: i32,
   pub node_ids: Vec<i32>,
@@ -210,6 +297,21 @@ impl Handler<ResolveGamePlayerPingBroadcastTargets> for GameRegistry {
 }
 
 impl GameRegistry {
+  async fn update_game_list_players(&self, game_id: i32) {
+    let players = self
+      .game_players_map
+      .get(&game_id)
+      .map(|v| v.len() as i32)
+      .unwrap_or_default();
+    if let Err(err) = self
+      .players
+      .update_game_list(GameListChange::Players { game_id, players })
+      .await
+    {
+      tracing::warn!(game_id, "update game list: {}", err);
+    }
+  }
+
   fn add_game_player(&mut self, game_id: i32, player_id: i32) {
     self
       .player_games_map
@@ -2,6 +2,7 @@ use crate::error::*;
 use crate::game::db::UpdateSlotSettings;
 use crate::game::state::GameActor;
 use crate::game::{Slot, SlotSettings};
+use crate::player::state::subscription::Topic;
 use diesel::prelude::*;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
@@ -12,6 +13,8 @@ pub struct UpdateSlot {
   pub player_id: i32,
   pub slot_index: i32,
   pub settings: SlotSettings,
+  /// The slot layout version the player is looking at, `None` skips the check
+  pub slots_version: Option<i32>,
 }
 
 impl Message for UpdateSlot {
@@ -22,11 +25,12 @@ impl Message for UpdateSlot {
 impl Handler<UpdateSlot> for GameActor {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     UpdateSlot {
       player_id,
       slot_index,
       settings,
+      slots_version: expected_version,
     }: UpdateSlot,
   ) -> Result<Vec<Slot>> {
     let game_id = self.game_id;
@@ -34,6 +38,7 @@ impl Handler<UpdateSlot> for GameActor {
     let UpdateSlotSettings {
       slots,
       updated_indexes,
+      slots_version,
     } = self
       .db
       .exec(move |conn| {
@@ -42,11 +47,78 @@ impl Handler<UpdateSlot> for GameActor {
           if !info.is_slot_owner(player_id) {
             return Err(Error::GameSlotUpdateDenied);
           }
-          crate::game::db::update_slot_settings(conn, game_id, slot_index, settings)
+          crate::game::db::update_slot_settings(
+            conn,
+            game_id,
+            slot_index,
+            settings,
+            expected_version,
+          )
         })
       })
       .await?;
 
+    self
+      .broadcast_slot_updates(&slots, updated_indexes, slots_version)
+      .await?;
+
+    // the host might have opened a slot
+    if let Err(err) = self.promote_waitlist(ctx).await {
+      tracing::error!(game_id, "update waitlist: {}", err);
+    }
+
+    Ok(slots)
+  }
+}
+
+pub struct BalanceTeams {
+  pub player_id: i32,
+}
+
+impl Message for BalanceTeams {
+  type Result = Result<Vec<Slot>>;
+}
+
+#[async_trait]
+impl Handler<BalanceTeams> for GameActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    BalanceTeams { player_id }: BalanceTeams,
+  ) -> Result<Vec<Slot>> {
+    let game_id = self.game_id;
+
+    if self.host_player != player_id {
+      return Err(Error::PlayerNotHost);
+    }
+
+    let UpdateSlotSettings {
+      slots,
+      updated_indexes,
+      slots_version,
+    } = self
+      .db
+      .exec(move |conn| {
+        conn.transaction(|| crate::game::db::balance_teams(conn, game_id, player_id))
+      })
+      .await?;
+
+    self
+      .broadcast_slot_updates(&slots, updated_indexes, slots_version)
+      .await?;
+
+    Ok(slots)
+  }
+}
+
+impl GameActor {
+  async fn broadcast_slot_updates(
+    &self,
+    slots: &[Slot],
+    updated_indexes: Vec<i32>,
+    slots_version: i32,
+  ) -> Result<()> {
+    let game_id = self.game_id;
     let mut frames_slot_update = Vec::with_capacity(updated_indexes.len());
 
     for index in updated_indexes {
@@ -57,20 +129,24 @@ impl Handler<UpdateSlot> for GameActor {
         slot_index: index,
         slot_settings: settings.into(),
         player: slot.player.clone().map(|p| p.pack()).transpose()?,
+        slots_version,
       }
       .encode_as_frame()?;
       frames_slot_update.push(frame);
     }
 
-    let players = slots
+    let players: Vec<i32> = slots
       .iter()
       .filter_map(|s| s.player.as_ref().map(|p| p.id))
       .collect();
     self
       .player_reg
-      .broadcast(players, frames_slot_update)
+      .broadcast(players.clone(), frames_slot_update.clone())
       .await?;
-
-    Ok(slots)
+    self
+      .player_reg
+      .publish(Topic::Game(game_id), players, frames_slot_update)
+      .await?;
+    Ok(())
   }
 }
@@ -1,13 +1,19 @@
+use crate::audit::{AuditActor, AuditEvent, AuditEventKind};
+use crate::catalogue;
+use crate::db::ExecutorError;
 use crate::error::*;
 use crate::game::state::GameActor;
-use crate::game::{GameStatus, SlotClientStatus};
-use crate::node::messages::NodeCreateGame;
+use crate::game::{Game, GameRules, SlotClientStatus};
+use crate::node::messages::{CreatedGameInfo, NodeCreateGame, SelectNodeForGame};
 use crate::player::state::sender::PlayerFrames;
+use crate::player::PlayerBanType;
 use crate::state::ActorMapExt;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
+use flo_net::proto::flo_common::ErrorCode;
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message};
-use std::collections::HashMap;
+use flo_types::lifecycle::GamePhase;
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 use tokio::sync::oneshot;
 
@@ -17,6 +23,8 @@ const TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct StartGameCheck {
   pub player_id: i32,
+  /// Requested by the host, see `ReadyCheck`
+  pub ready_check: bool,
 }
 
 impl Message for StartGameCheck {
@@ -28,22 +36,32 @@ impl Handler<StartGameCheck> for GameActor {
   async fn handle(
     &mut self,
     ctx: &mut Context<Self>,
-    StartGameCheck { player_id }: StartGameCheck,
+    StartGameCheck {
+      player_id,
+      ready_check,
+    }: StartGameCheck,
   ) -> Result<()> {
-    let game_id = self.game_id;
-
     if self.host_player != player_id {
       return Err(Error::PlayerNotHost);
     }
 
-    if self.selected_node_id.is_none() {
-      return Err(Error::GameNodeNotSelected);
+    if self.start_state.is_some() || self.start_queued || self.ready_check.is_some() {
+      return Err(Error::GameStarted);
     }
 
-    let players = self.players.clone();
-    if self.start_state.is_some() {
-      return Err(Error::GameStarted);
+    if ready_check {
+      self.begin_ready_check(ctx).await
+    } else {
+      self.begin_start_check(ctx).await
     }
+  }
+}
+
+impl GameActor {
+  /// Asks the players for their client info, the game is created once everyone responded
+  pub(super) async fn begin_start_check(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    let game_id = self.game_id;
+    let players = self.players.clone();
 
     self.start_state = StartGameState::new(game_id, ctx.addr(), players, None)
       .start()
@@ -63,11 +81,18 @@ pub struct StartGameCheckProceed {
   pub map: HashMap<i32, proto::flo_connect::PacketGameStartPlayerClientInfoRequest>,
 }
 
+enum StartGameProceedResult {
+  Created,
+  /// No node has free capacity
+  NoCapacity,
+  Rejected(proto::flo_connect::PacketGameStartReject),
+}
+
 impl GameActor {
   async fn start_game_proceed(
     &mut self,
     StartGameCheckProceed { map }: StartGameCheckProceed,
-  ) -> Result<Result<(), proto::flo_connect::PacketGameStartReject>> {
+  ) -> Result<StartGameProceedResult> {
     let game_id = self.game_id;
 
     tracing::debug!(game_id, "start game check proceed.");
@@ -92,10 +117,10 @@ impl GameActor {
 
     if !pass {
       let pkt = proto::flo_connect::PacketGameStartReject {
-        game_id,
-        message: "Unable to start the game because the game and map version check failed."
-          .to_string(),
         player_client_info_map: map.clone(),
+        ..catalogue::GAME_START_VERSION_MISMATCH
+          .message()
+          .game_start_reject(game_id, ErrorCode::GameVersionMismatch)
       };
       let frame = pkt.encode_as_frame()?;
       self
@@ -108,76 +133,269 @@ impl GameActor {
         "start game failed: version check failed"
       );
 
-      return Ok(Err(pkt));
+      return Ok(StartGameProceedResult::Rejected(pkt));
     }
 
-    let (game, ban_list_map) = self
+    let host_player = self.host_player;
+    let (mut game, ban_list_map, mute_list_map, rules, host_preferences) = self
       .db
       .exec(move |conn| {
         let game = crate::game::db::get_full(conn, game_id)?;
         let players = game.get_player_ids();
-        Ok::<_, Error>((game, crate::player::db::get_ban_list_map(conn, &players)?))
+        let ban_list_map = crate::player::db::get_ban_list_map(conn, &players)?;
+        let mute_list_map = crate::player::db::get_mute_list_map(conn, &players)?;
+        let rules = crate::game::db::get_rules(conn, game_id)?;
+        let host_preferences = crate::player_preferences::db::get(conn, host_player)?;
+        Ok::<_, Error>((game, ban_list_map, mute_list_map, rules, host_preferences))
       })
       .await?;
 
-    let node_id = if let Some(id) = game.node.as_ref().map(|node| node.id) {
-      id
-    } else {
-      return Err(Error::GameNodeNotSelected);
-    };
+    // the LAN game flags keep Reforged clients out, `Unknown` is not rejected
+    if rules.classic_graphics_only
+      && map
+        .values()
+        .any(|req| req.graphics_mode() == proto::flo_connect::GraphicsMode::Reforged)
+    {
+      let pkt = proto::flo_connect::PacketGameStartReject {
+        player_client_info_map: map.clone(),
+        ..catalogue::GAME_START_CLASSIC_GRAPHICS_REQUIRED
+          .message()
+          .game_start_reject(game_id, ErrorCode::GameClassicGraphicsRequired)
+      };
+      let frame = pkt.encode_as_frame()?;
+      self
+        .player_reg
+        .broadcast(self.players.clone(), frame)
+        .await?;
+
+      tracing::error!(
+        game_id = self.game_id,
+        "start game failed: classic graphics check failed"
+      );
 
-    let created = self
-      .nodes
-      .send_to(node_id, NodeCreateGame { game, ban_list_map })
+      return Ok(StartGameProceedResult::Rejected(pkt));
+    }
+
+    let (node_id, created) = match self
+      .create_on_node(
+        &mut game,
+        &ban_list_map,
+        &mute_list_map,
+        &rules,
+        host_preferences.node_region,
+        vec![],
+      )
       .await?
-      .await
-      .or_cancelled();
+    {
+      CreateOnNodeResult::Created { node_id, created } => (node_id, created),
+      CreateOnNodeResult::NoCapacity => return Ok(StartGameProceedResult::NoCapacity),
+      CreateOnNodeResult::Rejected(pkt) => return Ok(StartGameProceedResult::Rejected(pkt)),
+    };
 
-    let created = match created {
-      Ok(created) => created,
-      // failed, reply host player
-      Err(err) => {
-        let pkt = match err {
-          Error::NodeRequestTimeout => proto::flo_connect::PacketGameStartReject {
+    self
+      .issue_player_tokens(node_id, created, agreed_version)
+      .await?;
+    self.transition(GamePhase::Loading).await?;
+    if let Err(err) = self.clear_waitlist().await {
+      tracing::error!(game_id, "clear waitlist: {}", err);
+    }
+
+    crate::audit::record(
+      &self.db,
+      AuditEvent::game(
+        AuditEventKind::GameStart,
+        AuditActor::Player(self.host_player),
+        game_id,
+      )
+      .with_payload(serde_json::json!({
+        "node_id": node_id,
+        "players": self.players,
+      })),
+    );
+
+    Ok(StartGameProceedResult::Created)
+  }
+}
+
+pub(super) enum CreateOnNodeResult {
+  Created {
+    node_id: i32,
+    created: CreatedGameInfo,
+  },
+  /// No node has free capacity
+  NoCapacity,
+  Rejected(proto::flo_connect::PacketGameStartReject),
+}
+
+impl GameActor {
+  /// Creates the game on the node selected by the host.
+  /// If the host didn't select a node, picks the best node with free capacity and falls back
+  /// to the next best one if it is full.
+  pub(super) async fn create_on_node(
+    &mut self,
+    game: &mut Game,
+    ban_list_map: &BTreeMap<i32, Vec<PlayerBanType>>,
+    mute_list_map: &BTreeMap<i32, Vec<i32>>,
+    rules: &GameRules,
+    node_region: Option<String>,
+    mut excluded_node_ids: Vec<i32>,
+  ) -> Result<CreateOnNodeResult> {
+    let game_id = self.game_id;
+    // the same on every node, requests are only deduplicated by the node that received them
+    let request_id = format!("{}-{:016x}", game_id, rand::random::<u64>());
+    // a node the host picked is never replaced
+    let node_selected = game.node.is_some();
+    loop {
+      let node_id = if let Some(id) = game.node.as_ref().map(|node| node.id) {
+        id
+      } else {
+        // no node selected by the host, pick one with free capacity
+        let ping_map = self.player_reg.get_ping_map(self.host_player).await?;
+        let fallback_region = self
+          .player_reg
+          .get_suggested_region(self.host_player)
+          .await?;
+        let node = match self
+          .nodes
+          .send(SelectNodeForGame {
+            ping_map,
+            region: node_region.clone(),
+            fallback_region,
+            exclude: excluded_node_ids.clone(),
+          })
+          .await?
+        {
+          Ok(node) => node,
+          Err(Error::NoNodeAvailable) => {
+            tracing::warn!(game_id, "start game: no node available");
+            return Ok(CreateOnNodeResult::NoCapacity);
+          }
+          Err(err) => return Err(err),
+        };
+        let node_id = node.id;
+        match self.select_node(self.host_player, Some(node_id)).await {
+          Ok(_) => {}
+          Err(Error::NodeFull) => {
+            excluded_node_ids.push(node_id);
+            continue;
+          }
+          Err(err) => return Err(err),
+        }
+        game.node = Some(node.into());
+        node_id
+      };
+
+      match self
+        .db
+        .exec(move |conn| crate::node::db::assign_game(conn, node_id, game_id))
+        .await
+      {
+        Ok(_) => {}
+        Err(ExecutorError::Task(Error::NodeFull)) if node_selected => {
+          tracing::warn!(game_id, node_id, "start game: selected node full");
+          return Ok(CreateOnNodeResult::Rejected(
+            catalogue::GAME_START_NODE_FULL
+              .message()
+              .game_start_reject(game_id, ErrorCode::NodeFull),
+          ));
+        }
+        Err(ExecutorError::Task(Error::NodeFull)) => {
+          tracing::warn!(
             game_id,
-            message: format!("Create game timeout."),
-            ..Default::default()
+            node_id,
+            "start game: node full, trying the next node"
+          );
+          excluded_node_ids.push(node_id);
+          // a queued retry must not take the picked node as selected by the host
+          self.select_node(self.host_player, None).await?;
+          game.node = None;
+          continue;
+        }
+        Err(err) => return Err(err.into()),
+      }
+
+      let created = self
+        .nodes
+        .send_to(
+          node_id,
+          NodeCreateGame {
+            game: game.clone(),
+            ban_list_map: ban_list_map.clone(),
+            mute_list_map: mute_list_map.clone(),
+            rules: rules.clone(),
+            request_id: request_id.clone(),
           },
-          Error::GameCreateReject(reason) => {
-            use proto::flo_node::ControllerCreateGameRejectReason;
-            proto::flo_connect::PacketGameStartReject {
-              game_id,
-              message: match reason {
-                ControllerCreateGameRejectReason::Unknown => {
-                  format!("Create game request rejected.")
-                }
-                ControllerCreateGameRejectReason::GameExists => format!("Game already started."),
-                ControllerCreateGameRejectReason::PlayerBusy => {
-                  format!("Create game request rejected: Player busy.")
-                }
-                ControllerCreateGameRejectReason::Maintenance => {
-                  format!("Create game request rejected: Server Maintenance.")
+        )
+        .await?
+        .await
+        .or_cancelled();
+
+      if created.is_err() {
+        if let Err(err) = self
+          .db
+          .exec(move |conn| crate::node::db::release_game(conn, game_id))
+          .await
+        {
+          tracing::error!(game_id, node_id, "release node capacity: {}", err);
+        }
+      }
+
+      match created {
+        Ok(created) => return Ok(CreateOnNodeResult::Created { node_id, created }),
+        Err(Error::GameCreateReject(
+          proto::flo_node::ControllerCreateGameRejectReason::NodeFull,
+        )) if !node_selected => {
+          tracing::warn!(
+            game_id,
+            node_id,
+            "start game: node rejected as full, trying the next node"
+          );
+          excluded_node_ids.push(node_id);
+          self.select_node(self.host_player, None).await?;
+          game.node = None;
+        }
+        // failed, reply host player
+        Err(err) => {
+          let err = err.with_context(ErrorContext::game(game_id).with_node(node_id));
+          let code = err.code();
+          let pkt = match err.root() {
+            Error::NodeRequestTimeout => catalogue::GAME_START_TIMEOUT,
+            Error::GameCreateReject(reason) => {
+              use proto::flo_node::ControllerCreateGameRejectReason;
+              match reason {
+                ControllerCreateGameRejectReason::Unknown => catalogue::GAME_START_REJECTED,
+                ControllerCreateGameRejectReason::GameExists => {
+                  catalogue::GAME_START_ALREADY_STARTED
                 }
-              },
-              ..Default::default()
+                ControllerCreateGameRejectReason::PlayerBusy => catalogue::GAME_START_PLAYER_BUSY,
+                ControllerCreateGameRejectReason::Maintenance => catalogue::GAME_START_MAINTENANCE,
+                ControllerCreateGameRejectReason::NodeFull => catalogue::GAME_START_NODE_FULL,
+              }
             }
-          }
-          err => {
-            tracing::error!("node create game: {}", err);
-            proto::flo_connect::PacketGameStartReject {
-              game_id,
-              message: format!("Internal error."),
-              ..Default::default()
+            _ => {
+              tracing::error!("node create game: {}", err);
+              catalogue::INTERNAL_ERROR
             }
           }
-        };
+          .message()
+          .game_start_reject(game_id, code);
 
-        tracing::error!(game_id = self.game_id, "start game failed: {}", pkt.message);
+          tracing::error!(game_id, node_id, "start game failed: {}", err);
 
-        return Ok(Err(pkt));
+          return Ok(CreateOnNodeResult::Rejected(pkt));
+        }
       }
-    };
+    }
+  }
 
+  /// Sends the node tokens to the players
+  pub(super) async fn issue_player_tokens(
+    &mut self,
+    node_id: i32,
+    created: CreatedGameInfo,
+    agreed_version: Option<String>,
+  ) -> Result<()> {
+    let game_id = self.game_id;
     self.player_client_status_map = self
       .players
       .iter()
@@ -220,9 +438,7 @@ impl GameActor {
       .db
       .exec(move |conn| crate::game::db::update_created(conn, game_id, agreed_version, token_map))
       .await?;
-    self.status = GameStatus::Created;
-
-    Ok(Ok(()))
+    Ok(())
   }
 }
 
@@ -262,9 +478,10 @@ impl GameActor {
     let start_state = start_state.shutdown().await?;
 
     let pkt = proto::flo_connect::PacketGameStartReject {
-      game_id,
-      message: "Some of the players didn't response in time.".to_string(),
       player_client_info_map: map,
+      ..catalogue::GAME_START_PLAYERS_TIMEOUT
+        .message()
+        .game_start_reject(game_id, ErrorCode::Timeout)
     };
     let frame = pkt.encode_as_frame()?;
 
@@ -463,7 +680,7 @@ impl Message for StartGamePlayerAck {
 impl Handler<StartGamePlayerAck> for GameActor {
   async fn handle(
     &mut self,
-    _: &mut Context<Self>,
+    ctx: &mut Context<Self>,
     StartGamePlayerAck(message): StartGamePlayerAck,
   ) -> <StartGamePlayerAck as Message>::Result {
     let res = self
@@ -479,15 +696,25 @@ impl Handler<StartGamePlayerAck> for GameActor {
         .take()
         .ok_or_else(|| Error::GameNotStarting)?;
       let start_state = start_state.shutdown().await?;
+      let map = proceed.map.clone();
 
       match self.start_game_proceed(proceed).await {
-        Ok(Ok(_)) => {
+        Ok(StartGameProceedResult::Created) => {
           if start_state.by_api() {
             let map = start_state.get_map();
             start_state.reply_api(StartGameCheckAsBotResult::Started(map));
           }
         }
-        Ok(Err(pkt)) => {
+        Ok(StartGameProceedResult::NoCapacity) => {
+          if start_state.by_api() {
+            start_state.reply_api(StartGameCheckAsBotResult::Rejected(no_node_reject(
+              self.game_id,
+            )));
+          } else {
+            self.queue_start(ctx, map, 0).await?;
+          }
+        }
+        Ok(StartGameProceedResult::Rejected(pkt)) => {
           if start_state.by_api() {
             start_state.reply_api(StartGameCheckAsBotResult::Rejected(pkt));
           } else {
@@ -498,11 +725,10 @@ impl Handler<StartGamePlayerAck> for GameActor {
           }
         }
         Err(err) => {
-          let pkt = proto::flo_connect::PacketGameStartReject {
-            game_id: self.game_id,
-            message: format!("Internal error: {}", err),
-            ..Default::default()
-          };
+          tracing::error!(game_id = self.game_id, "start game: {}", err);
+          let pkt = catalogue::INTERNAL_ERROR
+            .message()
+            .game_start_reject(self.game_id, err.code());
           self
             .player_reg
             .send(self.host_player, pkt.encode_as_frame()?)
@@ -518,6 +744,121 @@ impl Handler<StartGamePlayerAck> for GameActor {
   }
 }
 
+const START_QUEUE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+const START_QUEUE_MAX_ATTEMPTS: u32 = 6;
+
+fn no_node_reject(game_id: i32) -> proto::flo_connect::PacketGameStartReject {
+  catalogue::GAME_START_NO_NODE
+    .message()
+    .game_start_reject(game_id, ErrorCode::NoNodeAvailable)
+}
+
+impl GameActor {
+  /// Retries to create the game later, until a node has free capacity or no attempts remain
+  async fn queue_start(
+    &mut self,
+    ctx: &mut Context<Self>,
+    map: HashMap<i32, proto::flo_connect::PacketGameStartPlayerClientInfoRequest>,
+    attempt: u32,
+  ) -> Result<()> {
+    let game_id = self.game_id;
+    if attempt >= START_QUEUE_MAX_ATTEMPTS {
+      self.start_queued = false;
+      tracing::error!(game_id, "start game failed: no node available");
+      return self.send_host_start_reject(no_node_reject(game_id)).await;
+    }
+
+    self.start_queued = true;
+    let frame = proto::flo_connect::PacketGameStartQueued {
+      game_id,
+      retry_in_ms: START_QUEUE_RETRY_INTERVAL.as_millis() as u32,
+      remaining_attempts: START_QUEUE_MAX_ATTEMPTS - attempt,
+    }
+    .encode_as_frame()?;
+    self
+      .player_reg
+      .broadcast(self.players.clone(), frame)
+      .await?;
+
+    ctx.spawn({
+      let addr = ctx.addr();
+      async move {
+        sleep(START_QUEUE_RETRY_INTERVAL).await;
+        addr
+          .notify(RetryQueuedStart {
+            map,
+            attempt: attempt + 1,
+          })
+          .await
+          .ok();
+      }
+    });
+    Ok(())
+  }
+
+  async fn send_host_start_reject(
+    &self,
+    pkt: proto::flo_connect::PacketGameStartReject,
+  ) -> Result<()> {
+    self
+      .player_reg
+      .send(self.host_player, pkt.encode_as_frame()?)
+      .await
+  }
+}
+
+struct RetryQueuedStart {
+  map: HashMap<i32, proto::flo_connect::PacketGameStartPlayerClientInfoRequest>,
+  attempt: u32,
+}
+
+impl Message for RetryQueuedStart {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<RetryQueuedStart> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    RetryQueuedStart { map, attempt }: RetryQueuedStart,
+  ) {
+    let game_id = self.game_id;
+    if !self.start_queued || !self.in_lobby() {
+      self.start_queued = false;
+      return;
+    }
+
+    tracing::info!(game_id, attempt, "retry queued game start");
+    let proceed = StartGameCheckProceed { map: map.clone() };
+    let res = match self.start_game_proceed(proceed).await {
+      Ok(StartGameProceedResult::Created) => {
+        self.start_queued = false;
+        Ok(())
+      }
+      Ok(StartGameProceedResult::NoCapacity) => self.queue_start(ctx, map, attempt).await,
+      Ok(StartGameProceedResult::Rejected(pkt)) => {
+        self.start_queued = false;
+        self.send_host_start_reject(pkt).await
+      }
+      Err(err) => {
+        self.start_queued = false;
+        tracing::error!(game_id, "retry queued game start: {}", err);
+        self
+          .send_host_start_reject(
+            catalogue::INTERNAL_ERROR
+              .message()
+              .game_start_reject(game_id, err.code()),
+          )
+          .await
+      }
+    };
+    if let Err(err) = res {
+      tracing::error!(game_id, "retry queued game start: {}", err);
+    }
+  }
+}
+
 struct AckTimeout;
 impl Message for AckTimeout {
   type Result = Result<()>;
@@ -569,7 +910,7 @@ impl Handler<StartGameCheckAsBot> for GameActor {
     }
 
     let players = self.players.clone();
-    if self.start_state.is_some() {
+    if self.start_state.is_some() || self.start_queued || self.ready_check.is_some() {
       return Err(Error::GameStarted);
     }
 
@@ -1,11 +1,13 @@
 use crate::error::*;
 use crate::game::state::GameActor;
-use crate::game::{db, GameStatus, NodeGameStatus, SlotClientStatus};
+use crate::game::{
+  db, GameStatus, GameTrafficStats, NodeGameStatus, PlayerActionStats, SlotClientStatus,
+};
 use crate::player::state::sender::PlayerFrames;
 use flo_net::packet::FloPacket;
 use flo_net::proto;
 use flo_state::{async_trait, Context, Handler, Message};
-use s2_grpc_utils::{S2ProtoEnum, S2ProtoUnpack};
+use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack, S2ProtoUnpack};
 use std::collections::HashMap;
 
 #[derive(Debug, S2ProtoUnpack)]
@@ -67,6 +69,8 @@ pub struct GameStatusUpdate {
   pub game_id: i32,
   pub status: NodeGameStatus,
   pub updated_player_game_client_status_map: HashMap<i32, SlotClientStatus>,
+  pub player_stats: Vec<PlayerActionStats>,
+  pub traffic: Option<GameTrafficStats>,
 }
 
 impl Message for GameStatusUpdate {
@@ -80,6 +84,9 @@ impl Handler<GameStatusUpdate> for GameActor {
     _ctx: &mut Context<Self>,
     message: GameStatusUpdate,
   ) -> Result<GameStatus> {
+    let phase = GameStatus::from(message.status).phase();
+    self.lifecycle.check(phase)?;
+
     self
       .db
       .exec({
@@ -92,13 +99,6 @@ impl Handler<GameStatusUpdate> for GameActor {
       .await?;
 
     let frame_game_status = message.to_packet().encode_as_frame()?;
-    self.status = GameStatus::from(message.status);
-
-    let ended = match self.status {
-      GameStatus::Ended | GameStatus::Terminated => true,
-      _ => false,
-    };
-
     let frame_iter = self
       .players
       .iter()
@@ -110,15 +110,9 @@ impl Handler<GameStatusUpdate> for GameActor {
       .extend(message.updated_player_game_client_status_map);
 
     self.player_reg.broadcast_map(frame_iter).await?;
+    self.transition(phase).await?;
 
-    if ended {
-      self
-        .player_reg
-        .players_leave_game(self.players.clone(), self.game_id)
-        .await?;
-    }
-
-    Ok(self.status)
+    Ok(self.status())
   }
 }
 
@@ -132,6 +126,13 @@ impl GameStatusUpdate {
     for (id, status) in &self.updated_player_game_client_status_map {
       pkt.insert_updated_player_game_client_status_map(*id, status.into_proto_enum());
     }
+    pkt.player_stats = self
+      .player_stats
+      .iter()
+      .cloned()
+      .filter_map(|v| v.pack().ok())
+      .collect();
+    pkt.traffic = self.traffic.clone().and_then(|v| v.pack().ok());
     pkt
   }
 }
@@ -153,6 +154,12 @@ impl From<flo_net::proto::flo_node::PacketNodeGameStatusUpdate> for GameStatusUp
           )
         })
         .collect(),
+      player_stats: pkt
+        .player_stats
+        .into_iter()
+        .filter_map(|v| PlayerActionStats::unpack(v).ok())
+        .collect(),
+      traffic: pkt.traffic.and_then(|v| GameTrafficStats::unpack(v).ok()),
     }
   }
 }
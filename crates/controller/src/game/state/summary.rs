@@ -0,0 +1,89 @@
+use crate::error::Result;
+use crate::game::state::{GameActor, GameRegistry};
+use crate::game::{GameStatus, SlotClientStatus};
+use flo_state::{async_trait, Addr, Context, Handler, Message};
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// In-memory state of a game in the lobby
+#[derive(Debug, Clone, Serialize)]
+pub struct GameSummary {
+  pub game_id: i32,
+  pub status: GameStatus,
+  pub host_player: i32,
+  pub players: Vec<i32>,
+  pub node_id: Option<i32>,
+  /// Waiting for a node with free capacity
+  pub start_queued: bool,
+  pub player_client_status_map: HashMap<i32, SlotClientStatus>,
+}
+
+struct GetGameSummary;
+
+impl Message for GetGameSummary {
+  type Result = GameSummary;
+}
+
+#[async_trait]
+impl Handler<GetGameSummary> for GameActor {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetGameSummary) -> GameSummary {
+    GameSummary {
+      game_id: self.game_id,
+      status: self.status(),
+      host_player: self.host_player,
+      players: self.players.clone(),
+      node_id: self.selected_node_id,
+      start_queued: self.start_queued,
+      player_client_status_map: self.player_client_status_map.clone(),
+    }
+  }
+}
+
+struct ListGameActors;
+
+impl Message for ListGameActors {
+  type Result = Vec<(i32, Addr<GameActor>)>;
+}
+
+#[async_trait]
+impl Handler<ListGameActors> for GameRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    _: ListGameActors,
+  ) -> Vec<(i32, Addr<GameActor>)> {
+    self
+      .map
+      .iter()
+      .map(|(game_id, owner)| (*game_id, owner.addr()))
+      .collect()
+  }
+}
+
+const GET_GAME_SUMMARY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Summaries of every game registered in the lobby.
+///
+/// The game actors are queried concurrently and outside of the registry,
+/// a busy game is skipped after `GET_GAME_SUMMARY_TIMEOUT`.
+pub async fn list_game_summaries(registry: &Addr<GameRegistry>) -> Result<Vec<GameSummary>> {
+  let actors = registry.send(ListGameActors).await?;
+  let list = join_all(actors.into_iter().map(|(game_id, addr)| async move {
+    match timeout(GET_GAME_SUMMARY_TIMEOUT, addr.send(GetGameSummary)).await {
+      Ok(Ok(summary)) => Some(summary),
+      Ok(Err(err)) => {
+        tracing::warn!(game_id, "get game summary: {}", err);
+        None
+      }
+      Err(_) => {
+        tracing::warn!(game_id, "get game summary: timeout");
+        None
+      }
+    }
+  }))
+  .await;
+  Ok(list.into_iter().flatten().collect())
+}
@@ -0,0 +1,377 @@
+use crate::error::*;
+use crate::game::state::GameActor;
+use crate::game::Game;
+use crate::player::state::sender::PlayerFrames;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::{PacketGameWaitlistOffer, PacketGameWaitlistUpdate};
+use flo_state::{async_trait, Context, Handler, Message};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+const MAX_WAITLIST_LEN: usize = 24;
+const OFFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Players waiting for a slot of a full game.
+/// A freed slot is offered to the first waiter and held for them until they accept, decline
+/// or the offer times out, then it goes to the next one.
+#[derive(Debug, Default)]
+pub struct Waitlist {
+  queue: VecDeque<Waiter>,
+  offers: BTreeMap<i32, Offer>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+  player_id: i32,
+  /// Checked again when the player takes the slot
+  password: Option<String>,
+}
+
+#[derive(Debug)]
+struct Offer {
+  offered_at: Instant,
+  password: Option<String>,
+}
+
+impl Waitlist {
+  fn contains(&self, player_id: i32) -> bool {
+    self.offers.contains_key(&player_id) || self.queue.iter().any(|w| w.player_id == player_id)
+  }
+
+  fn push(&mut self, player_id: i32, password: Option<String>) -> Result<()> {
+    if self.contains(player_id) {
+      return Ok(());
+    }
+    if self.queue.len() >= MAX_WAITLIST_LEN {
+      return Err(Error::GameWaitlistFull);
+    }
+    self.queue.push_back(Waiter {
+      player_id,
+      password,
+    });
+    Ok(())
+  }
+
+  /// Returns `false` if the player wasn't on the waitlist
+  pub(super) fn remove(&mut self, player_id: i32) -> bool {
+    let len = self.queue.len();
+    self.queue.retain(|w| w.player_id != player_id);
+    self.offers.remove(&player_id).is_some() || self.queue.len() != len
+  }
+
+  /// Open slots held for the offered players other than this one
+  pub(super) fn reserved_slots(&self, player_id: i32) -> usize {
+    self.offers.len() - self.offers.contains_key(&player_id) as usize
+  }
+
+  /// Offers the open slots that are not held yet, returns the offered players
+  fn offer(&mut self, open_slots: usize, now: Instant) -> Vec<i32> {
+    let mut offered = vec![];
+    while self.offers.len() < open_slots {
+      let waiter = match self.queue.pop_front() {
+        Some(waiter) => waiter,
+        None => break,
+      };
+      offered.push(waiter.player_id);
+      self.offers.insert(
+        waiter.player_id,
+        Offer {
+          offered_at: now,
+          password: waiter.password,
+        },
+      );
+    }
+    offered
+  }
+
+  /// Returns the password the player queued with
+  fn take_offer(&mut self, player_id: i32) -> Option<Option<String>> {
+    self.offers.remove(&player_id).map(|offer| offer.password)
+  }
+
+  /// Returns `false` if the offer was responded to or replaced by a later one
+  fn expire_offer(&mut self, player_id: i32, offered_at: Instant) -> bool {
+    if self.offers.get(&player_id).map(|o| o.offered_at) == Some(offered_at) {
+      self.offers.remove(&player_id);
+      true
+    } else {
+      false
+    }
+  }
+
+  fn clear(&mut self) -> Vec<i32> {
+    let mut player_ids: Vec<i32> = self.offers.keys().cloned().collect();
+    player_ids.extend(self.queue.drain(..).map(|w| w.player_id));
+    self.offers.clear();
+    player_ids
+  }
+}
+
+impl GameActor {
+  pub(super) async fn send_waitlist_positions(&self) -> Result<()> {
+    let game_id = self.game_id;
+    let mut frames = Vec::with_capacity(self.waitlist.queue.len());
+    for (idx, waiter) in self.waitlist.queue.iter().enumerate() {
+      let frame = PacketGameWaitlistUpdate {
+        game_id,
+        position: idx as u32 + 1,
+      }
+      .encode_as_frame()?;
+      frames.push((waiter.player_id, PlayerFrames::from(frame)));
+    }
+    self.player_reg.broadcast_map(frames).await
+  }
+
+  async fn send_waitlist_removed(&self, player_ids: Vec<i32>) -> Result<()> {
+    let frame = PacketGameWaitlistUpdate {
+      game_id: self.game_id,
+      position: 0,
+    }
+    .encode_as_frame()?;
+    self.player_reg.broadcast(player_ids, frame).await
+  }
+
+  /// Offers freed slots to the waiters, called after slots may have been released
+  pub(super) async fn promote_waitlist(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+    if self.waitlist.queue.is_empty() || !self.in_lobby() || self.started() {
+      return Ok(());
+    }
+
+    let game_id = self.game_id;
+    let open_slots = self
+      .db
+      .exec(move |conn| crate::game::db::get_open_slots(conn, game_id))
+      .await?;
+    let now = Instant::now();
+    let offered = self.waitlist.offer(open_slots, now);
+    if offered.is_empty() {
+      return Ok(());
+    }
+
+    tracing::debug!(game_id, "waitlist offers: {:?}", offered);
+    let frame = PacketGameWaitlistOffer {
+      game_id,
+      timeout_ms: OFFER_TIMEOUT.as_millis() as u32,
+    }
+    .encode_as_frame()?;
+    self.player_reg.broadcast(offered.clone(), frame).await?;
+
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(OFFER_TIMEOUT).await;
+      for player_id in offered {
+        addr
+          .notify(WaitlistOfferTimeout {
+            player_id,
+            offered_at: now,
+          })
+          .await
+          .ok();
+      }
+    });
+
+    self.send_waitlist_positions().await
+  }
+
+  /// Called when the game leaves the lobby
+  pub(super) async fn clear_waitlist(&mut self) -> Result<()> {
+    let player_ids = self.waitlist.clear();
+    if player_ids.is_empty() {
+      return Ok(());
+    }
+    self.send_waitlist_removed(player_ids).await
+  }
+}
+
+pub struct JoinWaitlist {
+  pub player_id: i32,
+  pub password: Option<String>,
+}
+
+impl Message for JoinWaitlist {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<JoinWaitlist> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    JoinWaitlist {
+      player_id,
+      password,
+    }: JoinWaitlist,
+  ) -> Result<()> {
+    let game_id = self.game_id;
+
+    if !self.in_lobby() || self.started() {
+      return Err(Error::GameStarted);
+    }
+
+    if self.players.contains(&player_id) {
+      return Err(Error::PlayerAlreadyInGame);
+    }
+
+    {
+      let password = password.clone();
+      self
+        .db
+        .exec(move |conn| crate::game::db::check_password(conn, game_id, password.as_deref()))
+        .await?;
+    }
+
+    self.waitlist.push(player_id, password)?;
+    self.send_waitlist_positions().await?;
+    // a slot might be open already
+    self.promote_waitlist(ctx).await
+  }
+}
+
+pub struct LeaveWaitlist {
+  pub player_id: i32,
+}
+
+impl Message for LeaveWaitlist {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<LeaveWaitlist> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    LeaveWaitlist { player_id }: LeaveWaitlist,
+  ) -> Result<()> {
+    if !self.waitlist.remove(player_id) {
+      return Ok(());
+    }
+    self.send_waitlist_removed(vec![player_id]).await?;
+    self.send_waitlist_positions().await?;
+    self.promote_waitlist(ctx).await
+  }
+}
+
+pub struct WaitlistOfferResponse {
+  pub player_id: i32,
+  pub accept: bool,
+}
+
+impl Message for WaitlistOfferResponse {
+  /// The joined game if the offer was accepted in time
+  type Result = Result<Option<Game>>;
+}
+
+#[async_trait]
+impl Handler<WaitlistOfferResponse> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    WaitlistOfferResponse { player_id, accept }: WaitlistOfferResponse,
+  ) -> Result<Option<Game>> {
+    let game_id = self.game_id;
+
+    let password = match self.waitlist.take_offer(player_id) {
+      Some(password) => password,
+      None => {
+        tracing::debug!(
+          game_id,
+          player_id,
+          "waitlist offer response discarded: no offer"
+        );
+        self.send_waitlist_removed(vec![player_id]).await?;
+        return Ok(None);
+      }
+    };
+
+    let game = if accept {
+      match self.join(player_id, password, false).await {
+        Ok(game) => Some(game),
+        Err(err) => {
+          tracing::debug!(game_id, player_id, "waitlist join: {}", err);
+          self.send_waitlist_removed(vec![player_id]).await?;
+          None
+        }
+      }
+    } else {
+      self.send_waitlist_removed(vec![player_id]).await?;
+      None
+    };
+
+    // the slot goes to the next waiter if the offer was declined
+    self.promote_waitlist(ctx).await?;
+
+    Ok(game)
+  }
+}
+
+struct WaitlistOfferTimeout {
+  player_id: i32,
+  offered_at: Instant,
+}
+
+impl Message for WaitlistOfferTimeout {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<WaitlistOfferTimeout> for GameActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    WaitlistOfferTimeout {
+      player_id,
+      offered_at,
+    }: WaitlistOfferTimeout,
+  ) {
+    if !self.waitlist.expire_offer(player_id, offered_at) {
+      return;
+    }
+    let game_id = self.game_id;
+    tracing::debug!(game_id, player_id, "waitlist offer timeout");
+    if let Err(err) = self.send_waitlist_removed(vec![player_id]).await {
+      tracing::error!(game_id, "waitlist offer timeout: {}", err);
+    }
+    if let Err(err) = self.promote_waitlist(ctx).await {
+      tracing::error!(game_id, "waitlist offer timeout: {}", err);
+    }
+  }
+}
+
+#[test]
+fn test_waitlist() {
+  let now = Instant::now();
+  let mut waitlist = Waitlist::default();
+  waitlist.push(1, None).unwrap();
+  waitlist.push(2, Some("pw".to_string())).unwrap();
+  waitlist.push(3, None).unwrap();
+  // already queued
+  waitlist.push(1, None).unwrap();
+  assert_eq!(waitlist.queue.len(), 3);
+
+  assert_eq!(waitlist.offer(0, now), Vec::<i32>::new());
+  assert_eq!(waitlist.offer(1, now), vec![1]);
+  // the offered slot is still open until player 1 takes it
+  assert_eq!(waitlist.offer(1, now), Vec::<i32>::new());
+  assert_eq!(waitlist.reserved_slots(1), 0);
+  assert_eq!(waitlist.reserved_slots(4), 1);
+
+  // a later offer isn't expired by the timer of an earlier one
+  let later = now + OFFER_TIMEOUT;
+  assert!(waitlist.expire_offer(1, now));
+  assert_eq!(waitlist.offer(1, later), vec![2]);
+  assert!(!waitlist.expire_offer(2, now));
+  assert_eq!(waitlist.take_offer(2), Some(Some("pw".to_string())));
+  assert_eq!(waitlist.take_offer(2), None);
+
+  assert!(waitlist.remove(3));
+  assert!(!waitlist.remove(3));
+  assert_eq!(waitlist.offer(2, later), Vec::<i32>::new());
+
+  for id in 0..(MAX_WAITLIST_LEN as i32) {
+    waitlist.push(id, None).unwrap();
+  }
+  assert!(waitlist.push(-1, None).is_err());
+  assert_eq!(waitlist.offer(1, later), vec![0]);
+  assert_eq!(waitlist.clear().len(), MAX_WAITLIST_LEN);
+}
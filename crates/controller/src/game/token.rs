@@ -13,10 +13,30 @@ const TOKEN_SUB: &str = "flo";
 pub struct JoinToken {
   pub sub: String,
   pub game_id: i32,
+  /// Only this player can join with an invite token
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub player_id: Option<i32>,
   pub exp: usize,
 }
 
+impl JoinToken {
+  pub fn check_player(&self, player_id: i32) -> Result<()> {
+    match self.player_id {
+      Some(id) if id != player_id => Err(Error::InviteNotForPlayer),
+      _ => Ok(()),
+    }
+  }
+}
+
 pub fn create_join_token(game_id: i32) -> Result<String> {
+  encode_join_token(game_id, None)
+}
+
+pub fn create_invite_token(game_id: i32, player_id: i32) -> Result<String> {
+  encode_join_token(game_id, Some(player_id))
+}
+
+fn encode_join_token(game_id: i32, player_id: Option<i32>) -> Result<String> {
   static ENCODING_KEY: Lazy<EncodingKey> = Lazy::new(|| {
     EncodingKey::from_base64_secret(&crate::config::JWT_SECRET_BASE64)
       .expect("DecodingKey::from_base64_secret")
@@ -26,6 +46,7 @@ pub fn create_join_token(game_id: i32) -> Result<String> {
   let claims = JoinToken {
     sub: TOKEN_SUB.to_string(),
     game_id,
+    player_id,
     exp: exp as usize,
   };
   encode(&Header::default(), &claims, &ENCODING_KEY).map_err(Into::into)
@@ -47,4 +68,9 @@ fn test_join_token() {
   let token = create_join_token(100).unwrap();
   let token = validate_join_token(&token).unwrap();
   dbg!(token);
+
+  let token = create_invite_token(100, 1).unwrap();
+  let token = validate_join_token(&token).unwrap();
+  assert!(token.check_player(1).is_ok());
+  assert!(token.check_player(2).is_err());
 }
@@ -5,10 +5,11 @@ use crate::schema::{game, game_used_slot};
 use bs_diesel_utils::BSDieselEnum;
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use flo_types::lifecycle::GamePhase;
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack, S2ProtoUnpack};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone)]
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, Clone)]
 #[s2_grpc(message_type(flo_grpc::game::Game))]
 pub struct Game {
   pub id: i32,
@@ -31,12 +32,21 @@ pub struct Game {
   pub updated_at: DateTime<Utc>,
   pub mask_player_names: bool,
   pub game_version: Option<String>,
+  /// Bumped by every slot layout change
+  #[serde(default)]
+  #[s2_grpc(skip_pack)]
+  pub slots_version: i32,
+  /// From `GameRules`, set on the LAN game by the clients
+  #[serde(default)]
+  #[s2_grpc(skip_pack)]
+  pub classic_graphics_only: bool,
 }
 
 impl S2ProtoPack<flo_net::proto::flo_connect::GameInfo> for Game {
   fn pack(self) -> Result<flo_net::proto::flo_connect::GameInfo, s2_grpc_utils::result::Error> {
     use flo_net::proto::flo_connect::*;
     let status: flo_net::proto::flo_connect::GameStatus = self.status.into_proto_enum();
+    let download = crate::map::storage::download_link(&self.map.sha1.0);
     Ok(GameInfo {
       id: self.id,
       name: self.name,
@@ -45,6 +55,8 @@ impl S2ProtoPack<flo_net::proto::flo_connect::GameInfo> for Game {
         sha1: self.map.sha1.to_vec(),
         checksum: self.map.checksum,
         path: self.map.path,
+        download_url: download.as_ref().map(|v| v.url.clone()).unwrap_or_default(),
+        download_expires_at: download.map(|v| v.expires_at).unwrap_or_default(),
       }),
       slots: self.slots.pack()?,
       node: self.node.pack()?,
@@ -52,6 +64,9 @@ impl S2ProtoPack<flo_net::proto::flo_connect::GameInfo> for Game {
       is_live: self.is_live,
       random_seed: self.random_seed,
       created_by: self.created_by.pack()?,
+      entry_key: self.secret.unwrap_or_default() as u32,
+      slots_version: self.slots_version,
+      classic_graphics_only: self.classic_graphics_only,
     })
   }
 }
@@ -95,7 +110,7 @@ pub struct PlayerSlotInfo<'a> {
   pub player: &'a PlayerRef,
 }
 
-#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Queryable)]
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Queryable, Clone)]
 #[s2_grpc(message_type(flo_grpc::game::GameEntry))]
 pub struct GameEntry {
   pub id: i32,
@@ -115,6 +130,10 @@ pub struct GameEntry {
   pub created_by: Option<PlayerRef>,
 }
 
+/// Number of players in the used slots of the selected game row
+pub(crate) const NUM_PLAYERS_SQL: &str =
+  "(select count(*) from game_used_slot s where s.game_id = game.id and s.player_id is not null)::int4";
+
 pub(crate) type GameEntryColumns = (
   game::dsl::id,
   game::dsl::name,
@@ -151,6 +170,26 @@ impl GameEntry {
       PlayerRef::COLUMNS.nullable(),
     )
   }
+
+  /// Same as `columns` with `num_players` counted from the used slots
+  pub(crate) fn columns_with_num_players() -> GameEntryColumns {
+    (
+      game::dsl::id,
+      game::dsl::name,
+      game::dsl::map_name,
+      game::dsl::status,
+      game::dsl::is_private,
+      game::dsl::is_live,
+      diesel::dsl::sql(NUM_PLAYERS_SQL),
+      game::dsl::max_players,
+      game::dsl::started_at,
+      game::dsl::ended_at,
+      game::dsl::created_at,
+      game::dsl::updated_at,
+      NodeRef::COLUMNS.nullable(),
+      PlayerRef::COLUMNS.nullable(),
+    )
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
@@ -166,12 +205,23 @@ pub enum GameStatus {
 }
 
 impl GameStatus {
+  /// `Paused` games are `Running`
+  pub fn phase(&self) -> GamePhase {
+    match self {
+      GameStatus::Preparing => GamePhase::Lobby,
+      GameStatus::Created => GamePhase::Loading,
+      GameStatus::Running | GameStatus::Paused => GamePhase::Running,
+      GameStatus::Ended => GamePhase::Ended,
+      GameStatus::Terminated => GamePhase::Aborted,
+    }
+  }
+
   pub fn is_active(&self) -> bool {
     Self::active_variants().contains(self)
   }
 
   pub fn active_variants() -> &'static [GameStatus] {
-    &[Self::Preparing, Self::Created, Self::Running]
+    &[Self::Preparing, Self::Created, Self::Running, Self::Paused]
   }
 }
 
@@ -186,6 +236,18 @@ pub enum NodeGameStatus {
   Ended = 4,
 }
 
+impl From<GamePhase> for GameStatus {
+  fn from(phase: GamePhase) -> Self {
+    match phase {
+      GamePhase::Created | GamePhase::Lobby => GameStatus::Preparing,
+      GamePhase::Loading => GameStatus::Created,
+      GamePhase::Running => GameStatus::Running,
+      GamePhase::Ended => GameStatus::Ended,
+      GamePhase::Aborted => GameStatus::Terminated,
+    }
+  }
+}
+
 impl From<NodeGameStatus> for GameStatus {
   fn from(status: NodeGameStatus) -> Self {
     match status {
@@ -229,6 +291,36 @@ impl Default for Slot {
   }
 }
 
+/// Host behaviors enforced by the node, e.g. for tournament games.
+/// The default value keeps the normal game behavior.
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone, Default, PartialEq)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::GameRules))]
+#[serde(default)]
+pub struct GameRules {
+  pub disable_pause: bool,
+  pub disable_save: bool,
+  /// Pauses allowed per player, 0 means unlimited
+  pub max_pause_count: u32,
+  /// Kick players that sent no action for this many ticks, 0 means disabled
+  pub afk_kick_ticks: u32,
+  pub disable_shared_control: bool,
+  /// Slots allowed to share unit control with each other, empty means no restriction
+  pub shared_control_pairs: Vec<SharedControlPair>,
+  /// Only Reforged clients running classic graphics can join, the game does not start
+  /// if a player reports running Reforged graphics
+  pub classic_graphics_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone, Copy, PartialEq)]
+#[s2_grpc(message_type(
+  flo_grpc::game::SharedControlPair,
+  flo_net::proto::flo_node::SharedControlPair
+))]
+pub struct SharedControlPair {
+  pub slot_id: u32,
+  pub with_slot_id: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone, Queryable)]
 #[s2_grpc(message_type(
   flo_grpc::game::SlotSettings,
@@ -334,3 +426,39 @@ impl SlotClientStatus {
     }
   }
 }
+
+/// Actions of a player aggregated by the node, reported when the game ends
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::PlayerActionStats))]
+pub struct PlayerActionStats {
+  pub player_id: i32,
+  pub apm: u32,
+  pub epm: u32,
+  pub peak_apm: u32,
+  pub selection_actions: u32,
+  pub hotkey_actions: u32,
+  pub ability_actions: u32,
+  pub item_actions: u32,
+  pub other_actions: u32,
+  pub time_ms: u32,
+  /// Action packets the node fell behind on, not counted in the stats
+  #[serde(default)]
+  pub dropped_samples: u32,
+}
+
+/// Bytes relayed by the node, reported when the game ends
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::GameTrafficStats))]
+pub struct GameTrafficStats {
+  pub bytes_in: u64,
+  pub bytes_out: u64,
+  pub players: Vec<PlayerTrafficStats>,
+}
+
+#[derive(Debug, Serialize, Deserialize, S2ProtoPack, S2ProtoUnpack, Clone)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::PlayerTrafficStats))]
+pub struct PlayerTrafficStats {
+  pub player_id: i32,
+  pub bytes_in: u64,
+  pub bytes_out: u64,
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use diesel::prelude::*;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game_schedule::GameSchedule;
+use crate::schema::game_schedule;
+
+pub fn list(conn: &DbConn) -> Result<Vec<GameSchedule>> {
+  use game_schedule::dsl;
+  Ok(
+    game_schedule::table
+      .order(dsl::id)
+      .select(GameSchedule::COLUMNS)
+      .load(conn)?,
+  )
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "game_schedule"]
+pub struct AddGameSchedule {
+  pub template_id: i32,
+  pub node_id: i32,
+  pub start_time: NaiveTime,
+  pub open_minutes: i32,
+  pub min_players: i32,
+  pub host_player_id: Option<i32>,
+}
+
+pub fn add(conn: &DbConn, insert: &AddGameSchedule) -> Result<GameSchedule> {
+  if insert.open_minutes < 1 || insert.open_minutes > 24 * 60 || insert.min_players < 1 {
+    return Err(Error::GameScheduleInvalid);
+  }
+  match insert.host_player_id {
+    Some(player_id) => crate::game_template::db::get(conn, player_id, insert.template_id)?,
+    None => crate::game_template::db::get_by_id(conn, insert.template_id)?,
+  };
+  Ok(
+    diesel::insert_into(game_schedule::table)
+      .values(insert)
+      .returning(GameSchedule::COLUMNS)
+      .get_result(conn)?,
+  )
+}
+
+pub fn remove(conn: &DbConn, id: i32) -> Result<()> {
+  let n = diesel::delete(game_schedule::table.find(id)).execute(conn)?;
+  if n == 0 {
+    return Err(Error::GameScheduleNotFound);
+  }
+  Ok(())
+}
+
+/// Marks the occurrence starting at `start_at` as handled,
+/// `game_id` is `None` if the game could not be created
+pub fn set_game(
+  conn: &DbConn,
+  id: i32,
+  game_id: Option<i32>,
+  start_at: DateTime<Utc>,
+) -> Result<()> {
+  use diesel::dsl::now;
+  use game_schedule::dsl;
+  diesel::update(game_schedule::table.find(id))
+    .set((
+      dsl::game_id.eq(game_id),
+      dsl::game_start_at.eq(start_at),
+      dsl::updated_at.eq(now),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+pub fn clear_game(conn: &DbConn, id: i32) -> Result<()> {
+  use diesel::dsl::now;
+  use game_schedule::dsl;
+  diesel::update(game_schedule::table.find(id))
+    .set((
+      dsl::game_id.eq(Option::<i32>::None),
+      dsl::updated_at.eq(now),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
@@ -0,0 +1,274 @@
+pub mod db;
+
+use crate::db::ExecutorRef;
+use crate::error::*;
+use crate::game::state::cancel::CancelGame;
+use crate::game::state::node::SelectNode;
+use crate::game::state::player::GetGamePlayers;
+use crate::game::state::registry::Remove;
+use crate::game::state::GameRegistry;
+use crate::player::state::sender::PlayerRegistryHandle;
+use crate::schema::game_schedule;
+use crate::state::{ActorMapExt, Data};
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::PacketGameScheduled;
+use flo_state::{async_trait, Actor, Addr, Context, Handler, Message, RegistryRef, Service};
+use serde::Serialize;
+use tokio::time::sleep;
+
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Creates a game from a template every day at `start_time`
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct GameSchedule {
+  pub id: i32,
+  pub template_id: i32,
+  /// Reserved for the game when it is created
+  pub node_id: i32,
+  /// UTC
+  pub start_time: NaiveTime,
+  /// The game is created and announced this many minutes before it starts
+  pub open_minutes: i32,
+  /// The game is cancelled if fewer players have joined when it starts
+  pub min_players: i32,
+  pub game_id: Option<i32>,
+  pub game_start_at: Option<DateTime<Utc>>,
+  /// Hosts the created games instead of the template owner
+  pub host_player_id: Option<i32>,
+}
+
+pub(crate) type GameScheduleColumns = (
+  game_schedule::id,
+  game_schedule::template_id,
+  game_schedule::node_id,
+  game_schedule::start_time,
+  game_schedule::open_minutes,
+  game_schedule::min_players,
+  game_schedule::game_id,
+  game_schedule::game_start_at,
+  game_schedule::host_player_id,
+);
+
+impl GameSchedule {
+  pub(crate) const COLUMNS: GameScheduleColumns = (
+    game_schedule::id,
+    game_schedule::template_id,
+    game_schedule::node_id,
+    game_schedule::start_time,
+    game_schedule::open_minutes,
+    game_schedule::min_players,
+    game_schedule::game_id,
+    game_schedule::game_start_at,
+    game_schedule::host_player_id,
+  );
+
+  /// Start time of the first occurrence after `now`
+  pub fn next_start_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = Utc.from_utc_datetime(&now.date().naive_utc().and_time(self.start_time));
+    if today > now {
+      today
+    } else {
+      today + Duration::days(1)
+    }
+  }
+
+  pub fn open_at(&self, start_at: DateTime<Utc>) -> DateTime<Utc> {
+    start_at - Duration::minutes(self.open_minutes as i64)
+  }
+}
+
+pub struct GameScheduler {
+  db: ExecutorRef,
+  players: PlayerRegistryHandle,
+  games: Addr<GameRegistry>,
+}
+
+#[async_trait]
+impl Actor for GameScheduler {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    self.handle(ctx, CheckSchedules).await;
+  }
+}
+
+#[async_trait]
+impl Service<Data> for GameScheduler {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    let players = registry.resolve().await?;
+    let games = registry.resolve().await?;
+    Ok(Self {
+      db: registry.data().db.clone(),
+      players: PlayerRegistryHandle::from(players),
+      games,
+    })
+  }
+}
+
+impl GameScheduler {
+  async fn check_schedules(&self) -> Result<()> {
+    let now = Utc::now();
+    let schedules = self.db.exec(|conn| db::list(conn)).await?;
+
+    for schedule in schedules {
+      let id = schedule.id;
+
+      if let (Some(game_id), Some(start_at)) = (schedule.game_id, schedule.game_start_at) {
+        if start_at <= now {
+          if let Err(err) = self.check_min_players(&schedule, game_id).await {
+            tracing::error!(schedule_id = id, game_id, "check scheduled game: {}", err);
+          }
+          self.db.exec(move |conn| db::clear_game(conn, id)).await?;
+        }
+        continue;
+      }
+
+      let start_at = schedule.next_start_at(now);
+      if schedule.game_start_at == Some(start_at) || now < schedule.open_at(start_at) {
+        continue;
+      }
+
+      let game_id = match self.open_game(&schedule, start_at).await {
+        Ok(game_id) => Some(game_id),
+        Err(err) => {
+          tracing::error!(schedule_id = id, "create scheduled game: {}", err);
+          None
+        }
+      };
+      self
+        .db
+        .exec(move |conn| db::set_game(conn, id, game_id, start_at))
+        .await?;
+    }
+
+    Ok(())
+  }
+
+  async fn open_game(&self, schedule: &GameSchedule, start_at: DateTime<Utc>) -> Result<i32> {
+    let template_id = schedule.template_id;
+    let template = self
+      .db
+      .exec(move |conn| crate::game_template::db::get_by_id(conn, template_id))
+      .await?;
+    let host_player_id = schedule.host_player_id.unwrap_or(template.player_id);
+
+    let message = template
+      .into_create_game(host_player_id, String::new())
+      .filter_name()
+      .await?;
+    let game = self.games.send(message).await??;
+
+    // the host can still select another node if the reserved one is full
+    if let Err(err) = self
+      .games
+      .send_to(
+        game.id,
+        SelectNode {
+          node_id: Some(schedule.node_id),
+          player_id: host_player_id,
+        },
+      )
+      .await
+    {
+      tracing::error!(
+        game_id = game.id,
+        "reserve node for scheduled game: {}",
+        err
+      );
+    }
+
+    tracing::info!(
+      schedule_id = schedule.id,
+      game_id = game.id,
+      "scheduled game created"
+    );
+
+    let frame = PacketGameScheduled {
+      game_id: game.id,
+      game_name: game.name,
+      map_name: game.map.name,
+      start_at: start_at.timestamp(),
+    }
+    .encode_as_frame()?;
+    self.players.broadcast_to_all(frame).await?;
+
+    Ok(game.id)
+  }
+
+  async fn check_min_players(&self, schedule: &GameSchedule, game_id: i32) -> Result<()> {
+    let players = match self.games.send_to(game_id, GetGamePlayers).await {
+      Ok(players) => players,
+      // the game has already ended
+      Err(Error::ActorNotFound) => return Ok(()),
+      Err(err) => return Err(err),
+    };
+
+    if players.len() >= schedule.min_players as usize {
+      return Ok(());
+    }
+
+    tracing::info!(
+      schedule_id = schedule.id,
+      game_id,
+      "scheduled game cancelled: {} of {} players joined",
+      players.len(),
+      schedule.min_players
+    );
+    self
+      .games
+      .send_to(game_id, CancelGame { player_id: None })
+      .await?;
+    self.games.send(Remove { game_id }).await?;
+    Ok(())
+  }
+}
+
+struct CheckSchedules;
+
+impl Message for CheckSchedules {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<CheckSchedules> for GameScheduler {
+  async fn handle(&mut self, ctx: &mut Context<Self>, _: CheckSchedules) {
+    if let Err(err) = self.check_schedules().await {
+      tracing::error!("check game schedules: {}", err);
+    }
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(CHECK_INTERVAL).await;
+      addr.notify(CheckSchedules).await.ok();
+    });
+  }
+}
+
+#[test]
+fn test_next_start_at() {
+  let schedule = GameSchedule {
+    id: 1,
+    template_id: 1,
+    node_id: 1,
+    start_time: NaiveTime::from_hms(20, 0, 0),
+    open_minutes: 30,
+    min_players: 2,
+    game_id: None,
+    game_start_at: None,
+    host_player_id: None,
+  };
+
+  let now = Utc.ymd(2021, 7, 15).and_hms(19, 45, 0);
+  let start_at = schedule.next_start_at(now);
+  assert_eq!(start_at, Utc.ymd(2021, 7, 15).and_hms(20, 0, 0));
+  assert_eq!(
+    schedule.open_at(start_at),
+    Utc.ymd(2021, 7, 15).and_hms(19, 30, 0)
+  );
+
+  let now = Utc.ymd(2021, 7, 15).and_hms(20, 0, 0);
+  assert_eq!(
+    schedule.next_start_at(now),
+    Utc.ymd(2021, 7, 16).and_hms(20, 0, 0)
+  );
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::game_template::{GameTemplate, GameTemplateConfig};
+use crate::schema::game_template;
+
+/// Saves the config of a game hosted by the player as a new template
+pub fn save_from_game(
+  conn: &DbConn,
+  player_id: i32,
+  game_id: i32,
+  name: &str,
+  is_public: bool,
+) -> Result<GameTemplate> {
+  #[derive(Insertable)]
+  #[table_name = "game_template"]
+  struct Insert<'a> {
+    player_id: i32,
+    name: &'a str,
+    is_public: bool,
+    config: Value,
+  }
+
+  let game = crate::game::db::get_full(conn, game_id)?;
+  if game.created_by.id != player_id {
+    return Err(Error::PlayerNotHost);
+  }
+  let rules = crate::game::db::get_rules(conn, game_id)?;
+  let config = serde_json::to_value(&GameTemplateConfig::from_game(game, rules))?;
+
+  let row: Row = diesel::insert_into(game_template::table)
+    .values(&Insert {
+      player_id,
+      name,
+      is_public,
+      config,
+    })
+    .get_result(conn)?;
+  row.into_template()
+}
+
+/// Lists templates of the player, newest first
+pub fn list(conn: &DbConn, player_id: i32) -> Result<Vec<GameTemplate>> {
+  use game_template::dsl;
+  game_template::table
+    .filter(dsl::player_id.eq(player_id))
+    .order(dsl::id.desc())
+    .load::<Row>(conn)?
+    .into_iter()
+    .map(Row::into_template)
+    .collect()
+}
+
+/// Returns a template owned by the player or shared publicly
+pub fn get(conn: &DbConn, player_id: i32, id: i32) -> Result<GameTemplate> {
+  use game_template::dsl;
+  game_template::table
+    .find(id)
+    .filter(dsl::player_id.eq(player_id).or(dsl::is_public.eq(true)))
+    .first::<Row>(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameTemplateNotFound)?
+    .into_template()
+}
+
+/// Returns a template regardless of its visibility
+pub fn get_by_id(conn: &DbConn, id: i32) -> Result<GameTemplate> {
+  game_template::table
+    .find(id)
+    .first::<Row>(conn)
+    .optional()?
+    .ok_or_else(|| Error::GameTemplateNotFound)?
+    .into_template()
+}
+
+pub fn delete(conn: &DbConn, player_id: i32, id: i32) -> Result<()> {
+  use game_template::dsl;
+  let n = diesel::delete(
+    game_template::table
+      .find(id)
+      .filter(dsl::player_id.eq(player_id)),
+  )
+  .execute(conn)?;
+  if n == 0 {
+    return Err(Error::GameTemplateNotFound);
+  }
+  Ok(())
+}
+
+#[derive(Debug, Queryable)]
+struct Row {
+  id: i32,
+  player_id: i32,
+  name: String,
+  is_public: bool,
+  config: Value,
+  created_at: DateTime<Utc>,
+  updated_at: DateTime<Utc>,
+}
+
+impl Row {
+  fn into_template(self) -> Result<GameTemplate> {
+    Ok(GameTemplate {
+      id: self.id,
+      player_id: self.player_id,
+      name: self.name,
+      is_public: self.is_public,
+      config: serde_json::from_value(self.config)?,
+      created_at: self.created_at,
+      updated_at: self.updated_at,
+    })
+  }
+}
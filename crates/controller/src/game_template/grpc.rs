@@ -0,0 +1,147 @@
+use crate::config::FloGrpcInterceptor;
+use crate::error::Error;
+use crate::game_template::GameTemplate;
+use crate::state::ControllerStateRef;
+use crate::text_filter::{self, TextKind};
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+  tonic::include_proto!("flo_template");
+}
+
+use proto::flo_template_server::{FloTemplate, FloTemplateServer};
+use proto::*;
+
+pub fn server(
+  state: ControllerStateRef,
+  interceptor: FloGrpcInterceptor,
+) -> InterceptedService<FloTemplateServer<FloTemplateService>, FloGrpcInterceptor> {
+  FloTemplateServer::with_interceptor(FloTemplateService { state }, interceptor)
+}
+
+pub struct FloTemplateService {
+  state: ControllerStateRef,
+}
+
+#[tonic::async_trait]
+impl FloTemplate for FloTemplateService {
+  async fn save_game_template(
+    &self,
+    request: Request<SaveGameTemplateRequest>,
+  ) -> Result<Response<proto::GameTemplate>, Status> {
+    let mut req = request.into_inner();
+    if req.name.is_empty() {
+      return Err(Status::invalid_argument("name is empty"));
+    }
+    // public templates are listed to other players
+    req.name = text_filter::apply(
+      TextKind::Description,
+      req.name,
+      Some(req.player_id),
+      Error::GameTemplateNameBlocked,
+    )
+    .await?;
+    let template = self
+      .state
+      .db
+      .exec(move |conn| {
+        crate::game_template::db::save_from_game(
+          conn,
+          req.player_id,
+          req.game_id,
+          &req.name,
+          req.is_public,
+        )
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(pack_template(template)))
+  }
+
+  async fn list_game_templates(
+    &self,
+    request: Request<ListGameTemplatesRequest>,
+  ) -> Result<Response<ListGameTemplatesReply>, Status> {
+    let player_id = request.into_inner().player_id;
+    let templates = self
+      .state
+      .db
+      .exec(move |conn| crate::game_template::db::list(conn, player_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(ListGameTemplatesReply {
+      templates: templates.into_iter().map(pack_template).collect(),
+    }))
+  }
+
+  async fn get_game_template(
+    &self,
+    request: Request<GetGameTemplateRequest>,
+  ) -> Result<Response<proto::GameTemplate>, Status> {
+    let req = request.into_inner();
+    let template = self
+      .state
+      .db
+      .exec(move |conn| crate::game_template::db::get(conn, req.player_id, req.template_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(pack_template(template)))
+  }
+
+  async fn delete_game_template(
+    &self,
+    request: Request<DeleteGameTemplateRequest>,
+  ) -> Result<Response<()>, Status> {
+    let req = request.into_inner();
+    self
+      .state
+      .db
+      .exec(move |conn| crate::game_template::db::delete(conn, req.player_id, req.template_id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(()))
+  }
+
+  async fn create_game_from_template(
+    &self,
+    request: Request<CreateGameFromTemplateRequest>,
+  ) -> Result<Response<CreateGameFromTemplateReply>, Status> {
+    let req = request.into_inner();
+    let player_id = req.player_id;
+    let template_id = req.template_id;
+    let template = self
+      .state
+      .db
+      .exec(move |conn| crate::game_template::db::get(conn, player_id, template_id))
+      .await
+      .map_err(Error::from)?;
+
+    let message = template
+      .into_create_game(player_id, req.name)
+      .filter_name()
+      .await?;
+    let game = self
+      .state
+      .games
+      .send(message)
+      .await
+      .map_err(Error::from)??;
+
+    Ok(Response::new(CreateGameFromTemplateReply {
+      game_id: game.id,
+    }))
+  }
+}
+
+fn pack_template(template: GameTemplate) -> proto::GameTemplate {
+  proto::GameTemplate {
+    id: template.id,
+    player_id: template.player_id,
+    name: template.name,
+    is_public: template.is_public,
+    map_name: template.config.map.name,
+    created_at: template.created_at.timestamp(),
+    updated_at: template.updated_at.timestamp(),
+  }
+}
@@ -0,0 +1,80 @@
+pub mod db;
+pub(crate) mod grpc;
+
+use crate::game::db::{CreateGameOptions, CreateGameParams};
+use crate::game::messages::CreateGame;
+use crate::game::{Game, GameRules, SlotSettings, SlotStatus};
+use crate::map::Map;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug)]
+pub struct GameTemplate {
+  pub id: i32,
+  pub player_id: i32,
+  pub name: String,
+  pub is_public: bool,
+  pub config: GameTemplateConfig,
+  pub created_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+}
+
+impl GameTemplate {
+  /// Creates a game hosted by `player_id`, named after the template if `name` is empty
+  pub fn into_create_game(self, player_id: i32, name: String) -> CreateGame {
+    let config = self.config;
+    CreateGame {
+      params: CreateGameParams {
+        player_id,
+        name: if name.is_empty() { self.name } else { name },
+        map: config.map,
+        is_private: config.is_private,
+        is_live: config.is_live,
+      },
+      options: CreateGameOptions {
+        rules: config.rules,
+        slots: Some(config.slots),
+        ..Default::default()
+      },
+    }
+  }
+}
+
+/// Everything needed to re-create a game, stored as json
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameTemplateConfig {
+  pub map: Map,
+  pub is_private: bool,
+  pub is_live: bool,
+  pub slots: Vec<SlotSettings>,
+  #[serde(default)]
+  pub rules: GameRules,
+}
+
+impl GameTemplateConfig {
+  /// Slots taken by players are saved as open slots
+  pub fn from_game(game: Game, rules: GameRules) -> Self {
+    let slots = game
+      .slots
+      .into_iter()
+      .map(|slot| {
+        if slot.player.is_some() {
+          SlotSettings {
+            team: slot.settings.team,
+            status: SlotStatus::Open,
+            ..Default::default()
+          }
+        } else {
+          slot.settings
+        }
+      })
+      .collect();
+    Self {
+      map: game.map,
+      is_private: game.is_private,
+      is_live: game.is_live,
+      slots,
+      rules,
+    }
+  }
+}
@@ -0,0 +1,121 @@
+use crate::node::Node;
+use maxminddb::{geoip2, Reader};
+use once_cell::sync::Lazy;
+use std::env;
+use std::net::IpAddr;
+
+/// MaxMind country database, lookups always fail if `FLO_GEOIP_DB_PATH` is not set
+static READER: Lazy<Option<Reader<Vec<u8>>>> = Lazy::new(|| {
+  let path = env::var("FLO_GEOIP_DB_PATH").ok()?;
+  match Reader::open_readfile(&path) {
+    Ok(reader) => Some(reader),
+    Err(err) => {
+      tracing::error!("open geoip database `{}`: {}", path, err);
+      None
+    }
+  }
+});
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GeoLocation {
+  /// ISO 3166-1 alpha-2 code
+  pub country: Option<String>,
+  /// Two-letter continent code
+  pub continent: Option<String>,
+}
+
+pub fn lookup(ip: IpAddr) -> Option<GeoLocation> {
+  let reader = READER.as_ref()?;
+  let country: geoip2::Country = reader.lookup(ip).ok()?;
+  Some(GeoLocation {
+    country: country
+      .country
+      .and_then(|v| v.iso_code)
+      .map(ToString::to_string),
+    continent: country
+      .continent
+      .and_then(|v| v.code)
+      .map(ToString::to_string),
+  })
+}
+
+/// Picks a node in the same country as `location`, then one on the same continent
+pub fn suggest_node<'a>(location: &GeoLocation, nodes: &'a [Node]) -> Option<&'a Node> {
+  let candidates: Vec<_> = nodes.iter().map(|node| (node, lookup_node(node))).collect();
+  closest(location, &candidates)
+}
+
+fn lookup_node(node: &Node) -> GeoLocation {
+  // `ip_addr` can contain a port
+  let mut location = node
+    .ip_addr
+    .split(':')
+    .next()
+    .and_then(|ip| ip.parse().ok())
+    .and_then(lookup)
+    .unwrap_or_default();
+  if !node.country_id.is_empty() {
+    location.country = Some(node.country_id.clone());
+  }
+  location
+}
+
+fn closest<'a>(location: &GeoLocation, candidates: &[(&'a Node, GeoLocation)]) -> Option<&'a Node> {
+  fn same(a: &Option<String>, b: &Option<String>) -> bool {
+    match (a, b) {
+      (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+      _ => false,
+    }
+  }
+  candidates
+    .iter()
+    .find(|(_, v)| same(&location.country, &v.country))
+    .or_else(|| {
+      candidates
+        .iter()
+        .find(|(_, v)| same(&location.continent, &v.continent))
+    })
+    .map(|(node, _)| *node)
+}
+
+#[test]
+fn test_closest() {
+  use chrono::Utc;
+
+  let node = |id: i32| Node {
+    id,
+    name: format!("node {}", id),
+    location: String::new(),
+    secret: String::new(),
+    ip_addr: String::new(),
+    created_at: Utc::now(),
+    updated_at: Utc::now(),
+    country_id: String::new(),
+    disabled: false,
+    region: String::new(),
+    max_games: None,
+  };
+  let location = |country: &str, continent: &str| GeoLocation {
+    country: Some(country.to_string()),
+    continent: Some(continent.to_string()),
+  };
+
+  let nodes = vec![node(1), node(2)];
+  let candidates = vec![
+    (&nodes[0], location("US", "NA")),
+    (&nodes[1], location("DE", "EU")),
+  ];
+
+  assert_eq!(
+    closest(&location("de", "EU"), &candidates).map(|v| v.id),
+    Some(2)
+  );
+  assert_eq!(
+    closest(&location("CA", "NA"), &candidates).map(|v| v.id),
+    Some(1)
+  );
+  assert_eq!(
+    closest(&location("JP", "AS"), &candidates).map(|v| v.id),
+    None
+  );
+}
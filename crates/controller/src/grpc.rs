@@ -1,36 +1,174 @@
 use crate::config::{ApiRequestExt, GetInterceptor};
+use crate::db::ExecutorError;
 use crate::error::{Error, Result};
-use crate::game::db::{CreateGameAsBotParams, CreateGameParams};
+use crate::game::db::{CreateGameAsBotParams, CreateGameOptions, CreateGameParams};
 use crate::game::messages::{CreateGame, PlayerJoin, PlayerLeave};
+use crate::game::metadata::GameMetadata;
+use crate::game::request::CreateRequestId;
 use crate::game::state::cancel::CancelGame;
 use crate::game::state::create::CreateGameAsBot;
 use crate::game::state::node::SelectNode;
 use crate::game::state::registry::{AddGamePlayer, Remove, RemoveGamePlayer, UpdateGameNodeCache};
 use crate::game::state::start::{StartGameCheckAsBot, StartGameCheckAsBotResult};
+use crate::game::{GameRules, SharedControlPair};
+use crate::map::Map;
 use crate::node::messages::ListNode;
 use crate::player::state::ping::GetPlayersPingSnapshot;
 use crate::player::{PlayerBanType, PlayerSource, SourceState};
 use crate::state::{ActorMapExt, ControllerStateRef};
-use bs_diesel_utils::executor::ExecutorError;
+use crate::webhook::WebhookEventKind;
 use chrono::{DateTime, Utc};
+use diesel::RunQueryDsl;
 use flo_grpc::controller::flo_controller_server::*;
 use flo_grpc::controller::*;
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoPack, S2ProtoUnpack};
 use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
 
 pub async fn serve(state: ControllerStateRef) -> Result<()> {
-  let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, flo_constants::CONTROLLER_GRPC_PORT);
+  let addr = SocketAddrV4::new(
+    Ipv4Addr::UNSPECIFIED,
+    crate::config::service_config().grpc_port,
+  );
   let server_impl = FloControllerService::new(state.clone());
 
   let interceptor = state.config.send(GetInterceptor).await?;
-  let server = FloControllerServer::with_interceptor(server_impl, interceptor);
-  let server = Server::builder().add_service(server);
+  let server = FloControllerServer::with_interceptor(server_impl, interceptor.clone());
+
+  let (health_reporter, health_server) = tonic_health::server::health_reporter();
+  tokio::spawn(report_health(state.clone(), health_reporter));
+  let reflection_server = tonic_reflection::server::Builder::configure()
+    .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+    .register_encoded_file_descriptor_set(FLO_GRPC_FILE_DESCRIPTOR_SET)
+    .build()?;
+
+  let server = Server::builder()
+    .add_service(health_server)
+    .add_service(reflection_server)
+    .add_service(server)
+    .add_service(crate::admin::server(state.clone()))
+    .add_service(crate::game_template::grpc::server(
+      state.clone(),
+      interceptor.clone(),
+    ))
+    .add_service(crate::game::grpc::server(
+      state.clone(),
+      interceptor.clone(),
+    ))
+    .add_service(crate::map::grpc::server(state.clone(), interceptor.clone()))
+    .add_service(crate::replay::grpc::server(state.clone(), interceptor));
   server.serve(addr.into()).await?;
   Ok(())
 }
 
+/// Services compiled in this crate
+const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("flo_controller_descriptor");
+/// `FloController` and the messages it uses
+const FLO_GRPC_FILE_DESCRIPTOR_SET: &[u8] =
+  tonic::include_file_descriptor_set!("flo_grpc_descriptor");
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reports `FloController` and the server as a whole as not serving while the database doesn't answer
+async fn report_health(state: ControllerStateRef, mut reporter: HealthReporter) {
+  let mut serving = None;
+  let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+  loop {
+    interval.tick().await;
+    let check = state
+      .db
+      .exec(|conn| diesel::sql_query("SELECT 1").execute(conn));
+    let ok = matches!(
+      tokio::time::timeout(HEALTH_CHECK_INTERVAL, check).await,
+      Ok(Ok(_))
+    );
+    if serving == Some(ok) {
+      continue;
+    }
+    if ok {
+      reporter
+        .set_serving::<FloControllerServer<FloControllerService>>()
+        .await;
+      reporter.set_service_status("", ServingStatus::Serving).await;
+    } else {
+      tracing::warn!("health: database unavailable");
+      reporter
+        .set_not_serving::<FloControllerServer<FloControllerService>>()
+        .await;
+      reporter
+        .set_service_status("", ServingStatus::NotServing)
+        .await;
+    }
+    serving = Some(ok);
+  }
+}
+
+/// Game password of `CreateGame` and `JoinGame` requests
+pub const REQUEST_META_GAME_PASSWORD: &str = "x-flo-game-password";
+
+fn get_game_password<T>(request: &Request<T>) -> Option<String> {
+  request
+    .metadata()
+    .get(REQUEST_META_GAME_PASSWORD)
+    .and_then(|value| value.to_str().ok())
+    .filter(|value| !value.is_empty())
+    .map(ToString::to_string)
+}
+
+/// Set to `true` on `CreateGame` and `CreateGameAsBot` requests to restrict the game to
+/// Reforged clients running classic graphics
+pub const REQUEST_META_GAME_CLASSIC_GRAPHICS: &str = "x-flo-game-classic-graphics";
+
+fn get_classic_graphics_only<T>(request: &Request<T>) -> Result<bool, Status> {
+  match request.metadata().get(REQUEST_META_GAME_CLASSIC_GRAPHICS) {
+    Some(value) => value
+      .to_str()
+      .ok()
+      .and_then(|value| value.parse().ok())
+      .ok_or_else(|| Status::invalid_argument(Error::GameClassicGraphicsInvalid.to_string())),
+    None => Ok(false),
+  }
+}
+
+/// Client-supplied id of `CreateGame` and `CreateGameAsBot` requests,
+/// a retry with the same id returns the game created by the first request
+pub const REQUEST_META_REQUEST_ID: &str = "x-flo-request-id";
+
+fn get_create_request_id<T>(request: &Request<T>) -> Result<Option<CreateRequestId>, Status> {
+  let value = if let Some(value) = request.metadata().get(REQUEST_META_REQUEST_ID) {
+    value
+  } else {
+    return Ok(None);
+  };
+  let value = value
+    .to_str()
+    .map_err(|_| Status::invalid_argument(Error::GameRequestIdInvalid.to_string()))?;
+  Ok(Some(CreateRequestId::new(
+    request.get_api_client_id(),
+    value.to_string(),
+  )?))
+}
+
+/// Catalogue id of the latest version of the game map if the map has been superseded,
+/// the `map_newer_version_id` of `CreateGame` and `CreateGameAsBot` replies
+async fn get_map_newer_version_id(state: &ControllerStateRef, map: Map) -> Option<i32> {
+  let newer = state
+    .db
+    .exec(move |conn| crate::map::db::get_newer_version(conn, &map))
+    .await;
+  match newer {
+    Ok(newer) => newer.map(|newer| newer.id),
+    Err(err) => {
+      tracing::warn!("get map newer version: {}", err);
+      None
+    }
+  }
+}
+
 pub struct FloControllerService {
   state: ControllerStateRef,
 }
@@ -125,9 +263,15 @@ impl FloController for FloControllerService {
   ) -> Result<Response<ListGamesReply>, Status> {
     let params =
       crate::game::db::QueryGameParams::unpack(request.into_inner()).map_err(Status::internal)?;
+    let params = crate::game::db::QueryGameParams {
+      // unlisted games can only be joined by id
+      is_private: None,
+      ..params
+    };
     let r = self
       .state
       .db
+      .read()
       .exec(move |conn| crate::game::db::query(conn, &params))
       .await
       .map_err(|e| Status::internal(e.to_string()))?;
@@ -140,17 +284,23 @@ impl FloController for FloControllerService {
     request: Request<GetGameRequest>,
   ) -> Result<Response<GetGameReply>, Status> {
     let game_id = request.into_inner().game_id;
-    let game = self
+    let (game, metadata) = self
       .state
       .db
-      .exec(move |conn| crate::game::db::get_full(conn, game_id))
+      .exec(move |conn| -> Result<_> {
+        Ok((
+          crate::game::db::get_full_cached(conn, game_id)?,
+          crate::game::metadata::get(conn, game_id)?,
+        ))
+      })
       .await
       .map_err(|e| match e {
         ExecutorError::Task(Error::GameNotFound) => Status::invalid_argument(e.to_string()),
-        other => Status::internal(other.to_string()),
+        other => Error::from(other).into(),
       })?;
     Ok(Response::new(GetGameReply {
       game: game.pack().map_err(Error::from)?,
+      metadata: metadata.into_iter().collect(),
     }))
   }
 
@@ -158,17 +308,40 @@ impl FloController for FloControllerService {
     &self,
     request: Request<CreateGameRequest>,
   ) -> Result<Response<CreateGameReply>, Status> {
+    let password = get_game_password(&request);
+    let shared_control_pairs =
+      Vec::<SharedControlPair>::unpack(request.get_ref().shared_control_pairs.clone())
+        .map_err(Error::from)?;
+    let classic_graphics_only = get_classic_graphics_only(&request)?;
+    let metadata: GameMetadata = request.get_ref().metadata.clone().into_iter().collect();
+    let request_id = get_create_request_id(&request)?;
+    let message = CreateGame {
+      params: CreateGameParams::unpack(request.into_inner()).map_err(Error::from)?,
+      options: CreateGameOptions {
+        password,
+        rules: GameRules {
+          shared_control_pairs,
+          classic_graphics_only,
+          ..Default::default()
+        },
+        metadata,
+        request_id,
+        ..Default::default()
+      },
+    }
+    .filter_name()
+    .await?;
     let game = self
       .state
       .games
-      .send(CreateGame {
-        params: CreateGameParams::unpack(request.into_inner()).map_err(Error::from)?,
-      })
+      .send(message)
       .await
       .map_err(Error::from)??;
 
+    let map_newer_version_id = get_map_newer_version_id(&self.state, game.map.clone()).await;
     Ok(Response::new(CreateGameReply {
       game: game.pack().map_err(Status::internal)?,
+      map_newer_version_id,
     }))
   }
 
@@ -176,6 +349,7 @@ impl FloController for FloControllerService {
     &self,
     request: Request<JoinGameRequest>,
   ) -> Result<Response<JoinGameReply>, Status> {
+    let password = get_game_password(&request);
     let params = request.into_inner();
 
     let game = self
@@ -185,6 +359,8 @@ impl FloController for FloControllerService {
         params.game_id,
         PlayerJoin {
           player_id: params.player_id,
+          password,
+          by_token: false,
         },
       )
       .await?;
@@ -233,6 +409,7 @@ impl FloController for FloControllerService {
   ) -> Result<Response<JoinGameReply>, Status> {
     let params = request.into_inner();
     let join_token = crate::game::token::validate_join_token(&params.token)?;
+    join_token.check_player(params.player_id)?;
 
     let game = self
       .state
@@ -241,6 +418,8 @@ impl FloController for FloControllerService {
         join_token.game_id,
         PlayerJoin {
           player_id: params.player_id,
+          password: None,
+          by_token: true,
         },
       )
       .await?;
@@ -449,19 +628,37 @@ impl FloController for FloControllerService {
     &self,
     request: Request<CreateGameAsBotRequest>,
   ) -> Result<Response<CreateGameAsBotReply>, Status> {
+    let shared_control_pairs =
+      Vec::<SharedControlPair>::unpack(request.get_ref().shared_control_pairs.clone())
+        .map_err(Error::from)?;
+    let classic_graphics_only = get_classic_graphics_only(&request)?;
+    let metadata: GameMetadata = request.get_ref().metadata.clone().into_iter().collect();
+    let request_id = get_create_request_id(&request)?;
+    let message = CreateGameAsBot {
+      api_client_id: request.get_api_client_id(),
+      api_player_id: request.get_api_player_id(),
+      params: CreateGameAsBotParams::unpack(request.into_inner()).map_err(Error::from)?,
+      rules: GameRules {
+        shared_control_pairs,
+        classic_graphics_only,
+        ..Default::default()
+      },
+      metadata,
+      request_id,
+    }
+    .filter_name()
+    .await?;
     let game = self
       .state
       .games
-      .send(CreateGameAsBot {
-        api_client_id: request.get_api_client_id(),
-        api_player_id: request.get_api_player_id(),
-        params: CreateGameAsBotParams::unpack(request.into_inner()).map_err(Error::from)?,
-      })
+      .send(message)
       .await
       .map_err(Error::from)??;
 
+    let map_newer_version_id = get_map_newer_version_id(&self.state, game.map.clone()).await;
     Ok(Response::new(CreateGameAsBotReply {
       game: game.pack().map_err(Status::internal)?,
+      map_newer_version_id,
     }))
   }
 
@@ -565,20 +762,26 @@ impl FloController for FloControllerService {
       .map(|t| DateTime::<Utc>::unpack(t))
       .transpose()
       .map_err(Status::internal)?;
+    let player_id = params.player_id;
+    let ban_type = PlayerBanType::unpack_enum(params.ban_type());
     self
       .state
       .db
       .exec(move |conn| {
-        crate::player::db::check_player_api_client_id(conn, api_client_id, params.player_id)?;
-        crate::player::db::create_ban(
-          conn,
-          params.player_id,
-          PlayerBanType::unpack_enum(params.ban_type()),
-          ban_expires_at,
-        )
+        crate::player::db::check_player_api_client_id(conn, api_client_id, player_id)?;
+        crate::player::db::create_ban(conn, player_id, ban_type, ban_expires_at)
       })
       .await
       .map_err(Error::from)?;
+    crate::webhook::emit(
+      &self.state.db,
+      WebhookEventKind::PlayerBanned,
+      serde_json::json!({
+        "player_id": player_id,
+        "ban_type": ban_type,
+        "ban_expires_at": ban_expires_at,
+      }),
+    );
     Ok(Response::new(()))
   }
 
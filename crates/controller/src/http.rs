@@ -0,0 +1,251 @@
+use crate::dashboard::Dashboard;
+use crate::error::{Error, Result};
+use crate::federation::{JoinRequest, JoinResponse, RemoteGame};
+use crate::game::db::{QueryGame, QueryGameParams};
+use crate::game::metadata::GameMetadata;
+use crate::game::{Game, GameEntry};
+use crate::node::messages::ListNodeStatus;
+use crate::node::NodeStatus;
+use crate::player::PlayerRef;
+use crate::state::ControllerStateRef;
+use axum::body::Bytes;
+use axum::extract::{Extension, Path, Query, RawQuery};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::{AddExtensionLayer, Json, Router, Server};
+use prometheus::{Encoder, TextEncoder};
+use serde::Serialize;
+use serde_json::json;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+pub async fn serve(state: ControllerStateRef) -> Result<()> {
+  let app = Router::new()
+    .route("/games", get(list_games))
+    .route("/games/:id", get(get_game))
+    .route("/players/:id", get(get_player))
+    .route("/nodes", get(list_nodes))
+    .route("/maps/:sha1", get(download_map))
+    .route("/dashboard", get(get_dashboard))
+    .route("/metrics", get(get_metrics))
+    .route("/remote-games", get(list_remote_games))
+    .route(crate::federation::PATH_GAMES, get(federation_list_games))
+    .route(crate::federation::PATH_JOIN, post(federation_join))
+    .layer(AddExtensionLayer::new(state));
+
+  let addr = SocketAddr::from(SocketAddrV4::new(
+    Ipv4Addr::UNSPECIFIED,
+    flo_constants::CONTROLLER_HTTP_PORT,
+  ));
+  tracing::info!("http listening on port {}", addr.port());
+
+  Server::bind(&addr).serve(app.into_make_service()).await?;
+  Ok(())
+}
+
+async fn list_games(
+  Extension(state): Extension<ControllerStateRef>,
+  Query(params): Query<QueryGameParams>,
+) -> Result<Json<QueryGame>> {
+  let params = QueryGameParams {
+    // private games are never listed publicly
+    is_private: None,
+    ..params
+  };
+  let r = state
+    .db
+    .read()
+    .exec(move |conn| crate::game::db::query(conn, &params))
+    .await?;
+  Ok(Json(r))
+}
+
+#[derive(Debug, Serialize)]
+struct GameDetail {
+  #[serde(flatten)]
+  game: Game,
+  metadata: GameMetadata,
+}
+
+async fn get_game(
+  Extension(state): Extension<ControllerStateRef>,
+  Path(game_id): Path<i32>,
+) -> Result<Json<GameDetail>> {
+  let (mut game, metadata) = state
+    .db
+    .exec(move |conn| -> Result<_> {
+      Ok((
+        crate::game::db::get_full_cached(conn, game_id)?,
+        crate::game::metadata::get(conn, game_id)?,
+      ))
+    })
+    .await?;
+
+  if game.is_private {
+    return Err(Error::GameNotFound);
+  }
+
+  game.secret = None;
+  crate::game::state::create::mask_player_names(&mut game);
+
+  Ok(Json(GameDetail { game, metadata }))
+}
+
+async fn get_player(
+  Extension(state): Extension<ControllerStateRef>,
+  Path(player_id): Path<i32>,
+) -> Result<Json<PlayerRef>> {
+  let player = state
+    .db
+    .exec(move |conn| crate::player::db::get_ref_cached(conn, player_id))
+    .await?;
+  Ok(Json(player))
+}
+
+/// Requires the admin secret in the `x-flo-admin-secret` header
+async fn list_nodes(
+  Extension(state): Extension<ControllerStateRef>,
+  headers: HeaderMap,
+) -> Result<Json<Vec<NodeStatus>>, Response> {
+  verify_admin_secret(&headers)?;
+  let nodes = state
+    .nodes
+    .send(ListNodeStatus)
+    .await
+    .map_err(|err| Error::from(err).into_response())?;
+  Ok(Json(nodes))
+}
+
+/// Requires the admin secret in the `x-flo-admin-secret` header
+async fn get_dashboard(
+  Extension(state): Extension<ControllerStateRef>,
+  headers: HeaderMap,
+) -> Result<Json<Dashboard>, Response> {
+  verify_admin_secret(&headers)?;
+  let dashboard = crate::dashboard::get(&state)
+    .await
+    .map_err(IntoResponse::into_response)?;
+  Ok(Json(dashboard))
+}
+
+/// Prometheus metrics of the lobby
+async fn get_metrics() -> (HeaderMap, Vec<u8>) {
+  let encoder = TextEncoder::new();
+  let mut buffer = vec![];
+  encoder.encode(&prometheus::gather(), &mut buffer).unwrap();
+  let mut headers = HeaderMap::new();
+  headers.insert(
+    CONTENT_TYPE,
+    HeaderValue::from_static(prometheus::TEXT_FORMAT),
+  );
+  (headers, buffer)
+}
+
+/// Redirects a signed map download link to the stored map file
+async fn download_map(
+  Extension(state): Extension<ControllerStateRef>,
+  Path(sha1): Path<String>,
+  RawQuery(query): RawQuery,
+) -> Result<Redirect> {
+  let path = format!("{}{}", crate::map::storage::MAP_HTTP_PATH_PREFIX, sha1);
+  let sha1 = crate::map::storage::verify_download_path(
+    &path,
+    query.as_deref().unwrap_or_default(),
+    flo_net::echo::unix_now(),
+  )
+  .ok_or_else(|| Error::MapNotFound)?;
+  let sha256 = state
+    .db
+    .exec(move |conn| crate::map::db::get_file_sha256(conn, &sha1))
+    .await?;
+  let url = crate::map::storage::object_url(&sha256)?;
+  let uri: Uri = url.url.parse().map_err(|_| Error::MapNotStored)?;
+  Ok(Redirect::temporary(uri))
+}
+
+/// Public games of the federated lobbies
+async fn list_remote_games(
+  Extension(state): Extension<ControllerStateRef>,
+) -> Result<Json<Vec<RemoteGame>>> {
+  let games = state
+    .federation
+    .send(crate::federation::GetRemoteGames)
+    .await?;
+  Ok(Json(games))
+}
+
+/// Polled by the federated lobbies, signed
+async fn federation_list_games(
+  Extension(state): Extension<ControllerStateRef>,
+  headers: HeaderMap,
+) -> Result<Json<Vec<GameEntry>>> {
+  crate::federation::server::verify_request(&headers, crate::federation::PATH_GAMES, &[])?;
+  let games = state
+    .db
+    .read()
+    .exec(|conn| crate::federation::server::list_shared_games(conn))
+    .await?;
+  Ok(Json(games))
+}
+
+/// A player of a federated lobby joins a local game, signed
+async fn federation_join(
+  Extension(state): Extension<ControllerStateRef>,
+  headers: HeaderMap,
+  body: Bytes,
+) -> Result<Json<JoinResponse>> {
+  let peer =
+    crate::federation::server::verify_request(&headers, crate::federation::PATH_JOIN, &body)?;
+  let req: JoinRequest = serde_json::from_slice(&body)?;
+  tracing::info!(
+    lobby_id = %peer.lobby_id,
+    game_id = req.game_id,
+    remote_player_id = req.player.id,
+    "federation join"
+  );
+  let res = state
+    .db
+    .exec(move |conn| crate::federation::server::accept_join(conn, &peer, req))
+    .await?;
+  Ok(Json(res))
+}
+
+fn verify_admin_secret(headers: &HeaderMap) -> Result<(), Response> {
+  let secret = headers
+    .get(crate::admin::REQUEST_META_ADMIN_SECRET)
+    .map(|v| v.as_bytes());
+  crate::admin::verify_admin_secret(secret).map_err(|message| {
+    (
+      StatusCode::UNAUTHORIZED,
+      Json(json!({ "message": message })),
+    )
+      .into_response()
+  })
+}
+
+impl IntoResponse for Error {
+  fn into_response(self) -> Response {
+    let status = match self.root() {
+      Error::GameNotFound
+      | Error::PlayerNotFound
+      | Error::NodeNotFound
+      | Error::MapNotFound
+      | Error::MapNotStored => StatusCode::NOT_FOUND,
+      Error::FederationPeerNotFound | Error::FederationSignatureInvalid => StatusCode::UNAUTHORIZED,
+      Error::GameStarted | Error::GameFull | Error::MapFileConflict => StatusCode::CONFLICT,
+      Error::Json(_) => StatusCode::BAD_REQUEST,
+      Error::DbUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+      _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let message = if status == StatusCode::INTERNAL_SERVER_ERROR {
+      tracing::error!("http: {}", self);
+      crate::dashboard::record_error("http", self.to_string());
+      "Internal error".to_string()
+    } else {
+      self.client_message()
+    };
+    let code = self.code() as i32;
+    (status, Json(json!({ "code": code, "message": message }))).into_response()
+  }
+}
@@ -14,17 +14,38 @@ extern crate diesel;
 mod db;
 mod schema;
 
+mod admin;
+pub mod audit;
+mod cache;
+mod catalogue;
 mod client;
 mod config;
+pub mod dashboard;
+mod discord;
 pub mod error;
+mod federation;
 pub mod game;
+pub mod game_schedule;
+pub mod game_template;
+mod geoip;
 mod grpc;
 pub mod host;
+mod maintenance;
+#[cfg(feature = "http")]
+mod http;
 pub mod map;
+mod motd;
 pub mod node;
 pub mod player;
+pub mod player_preferences;
+mod policy;
+mod replay;
 mod state;
+pub mod text_filter;
+pub mod webhook;
 
 pub use client::serve as serve_socket;
 pub use grpc::serve as serve_grpc;
-pub use state::{ControllerState, ControllerStateRef};
+#[cfg(feature = "http")]
+pub use http::serve as serve_http;
+pub use state::{ActorMapExt, ControllerState, ControllerStateRef};
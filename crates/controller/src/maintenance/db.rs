@@ -0,0 +1,46 @@
+use diesel::prelude::*;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::maintenance::MaintenanceWindow;
+use crate::schema::maintenance_window;
+
+pub fn get(conn: &DbConn) -> Result<Option<MaintenanceWindow>> {
+  use maintenance_window::dsl;
+  Ok(
+    maintenance_window::table
+      .select((dsl::starts_at, dsl::ends_at, dsl::message, dsl::drain_nodes))
+      .first(conn)
+      .optional()?,
+  )
+}
+
+/// Replaces the scheduled window, if any
+pub fn set(conn: &DbConn, window: &MaintenanceWindow) -> Result<()> {
+  use diesel::dsl::now;
+  use maintenance_window::dsl;
+  diesel::insert_into(maintenance_window::table)
+    .values((
+      dsl::id.eq(1),
+      dsl::starts_at.eq(window.starts_at),
+      dsl::ends_at.eq(window.ends_at),
+      dsl::message.eq(&window.message),
+      dsl::drain_nodes.eq(window.drain_nodes),
+    ))
+    .on_conflict(dsl::id)
+    .do_update()
+    .set((
+      dsl::starts_at.eq(window.starts_at),
+      dsl::ends_at.eq(window.ends_at),
+      dsl::message.eq(&window.message),
+      dsl::drain_nodes.eq(window.drain_nodes),
+      dsl::created_at.eq(now),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+pub fn remove(conn: &DbConn) -> Result<()> {
+  diesel::delete(maintenance_window::table).execute(conn)?;
+  Ok(())
+}
@@ -0,0 +1,348 @@
+pub mod db;
+
+use crate::catalogue::{self, ServerMessage};
+use crate::db::ExecutorRef;
+use crate::error::*;
+use crate::game::state::cancel::ForceCancelGame;
+use crate::game::state::registry::Remove;
+use crate::game::state::GameRegistry;
+use crate::player::state::sender::PlayerRegistryHandle;
+use crate::state::{ActorMapExt, Data};
+use chrono::{DateTime, Duration, Utc};
+use flo_net::packet::FloPacket;
+use flo_state::{
+  async_trait, Actor, Addr, Context, Deferred, Handler, Message, RegistryRef, Service,
+};
+use serde::Serialize;
+use tokio::sync::watch;
+
+/// Game creation is blocked this long before a window starts
+const CREATE_GAME_LOCK_MINUTES: i64 = 15;
+/// Countdown notices are sent when the remaining time reaches these values
+const NOTICE_MINUTES: &[i64] = &[60, 30, 15, 5, 1];
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct MaintenanceWindow {
+  pub starts_at: DateTime<Utc>,
+  pub ends_at: DateTime<Utc>,
+  pub message: String,
+  /// Cancel all active games when the window begins, so nodes can be shut down
+  /// without waiting for games to finish
+  pub drain_nodes: bool,
+}
+
+impl MaintenanceWindow {
+  pub fn blocks_game_creation(&self, now: DateTime<Utc>) -> bool {
+    now >= self.starts_at - Duration::minutes(CREATE_GAME_LOCK_MINUTES) && now < self.ends_at
+  }
+}
+
+/// Read side of the scheduled window.
+///
+/// `GameRegistry` checks it directly instead of sending to `Maintenance`,
+/// which awaits `GameRegistry` while draining.
+#[derive(Debug, Clone)]
+pub struct MaintenanceGate(watch::Receiver<Option<MaintenanceWindow>>);
+
+impl MaintenanceGate {
+  /// Returns `Error::Maintenance` if new games are not allowed at the moment
+  pub fn check_game_creation(&self) -> Result<()> {
+    match self.0.borrow().as_ref() {
+      Some(window) if window.blocks_game_creation(Utc::now()) => {
+        Err(Error::Maintenance(window.starts_at))
+      }
+      _ => Ok(()),
+    }
+  }
+}
+
+pub struct Maintenance {
+  db: ExecutorRef,
+  players: PlayerRegistryHandle,
+  games: Deferred<GameRegistry, Data>,
+  window: Option<MaintenanceWindow>,
+  window_tx: watch::Sender<Option<MaintenanceWindow>>,
+  gate: MaintenanceGate,
+  // invalidates timers of cancelled or rescheduled windows
+  generation: u64,
+}
+
+#[async_trait]
+impl Actor for Maintenance {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    self.schedule_next_tick(ctx);
+  }
+}
+
+#[async_trait]
+impl Service<Data> for Maintenance {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    let players = registry.resolve().await?;
+    let db = registry.data().db.clone();
+    let window = db
+      .exec(|conn| db::get(conn))
+      .await?
+      .filter(|window| window.ends_at > Utc::now());
+    if let Some(window) = window.as_ref() {
+      tracing::info!(
+        starts_at = %window.starts_at,
+        ends_at = %window.ends_at,
+        "maintenance window restored"
+      );
+    }
+    let (window_tx, window_rx) = watch::channel(window.clone());
+    Ok(Self {
+      db,
+      players: PlayerRegistryHandle::from(players),
+      games: registry.deferred::<GameRegistry>(),
+      window,
+      window_tx,
+      gate: MaintenanceGate(window_rx),
+      generation: 0,
+    })
+  }
+}
+
+impl Maintenance {
+  fn publish(&self) {
+    self.window_tx.send(self.window.clone()).ok();
+  }
+
+  fn schedule_next_tick(&self, ctx: &mut Context<Self>) {
+    let window = if let Some(window) = self.window.as_ref() {
+      window
+    } else {
+      return;
+    };
+
+    let now = Utc::now();
+    let next = NOTICE_MINUTES
+      .iter()
+      .map(|m| window.starts_at - Duration::minutes(*m))
+      .chain(std::iter::once(window.starts_at))
+      .chain(std::iter::once(window.ends_at))
+      .filter(|t| *t > now)
+      .min();
+
+    if let Some(next) = next {
+      let generation = self.generation;
+      let delay = (next - now).to_std().unwrap_or_default();
+      let addr = ctx.addr();
+      ctx.spawn(async move {
+        tokio::time::sleep(delay).await;
+        addr.notify(Tick { generation }).await.ok();
+      });
+    }
+  }
+
+  async fn notify_players(&self, message: ServerMessage) -> Result<()> {
+    let frame = message.notice().encode_as_frame()?;
+    self.players.broadcast_to_all(frame).await
+  }
+
+  /// Runs outside of the actor, `GameRegistry` handlers can not be awaited here
+  async fn drain(db: ExecutorRef, games: Addr<GameRegistry>) -> Result<()> {
+    let game_ids: Vec<i32> = db
+      .exec(|conn| crate::game::db::get_all_active_game_state(conn))
+      .await?
+      .into_iter()
+      .map(|game| game.id)
+      .collect();
+
+    tracing::info!(games = game_ids.len(), "maintenance drain");
+
+    for game_id in game_ids {
+      if let Err(err) = games.send_to(game_id, ForceCancelGame).await {
+        tracing::error!(game_id, "maintenance drain: cancel game: {}", err);
+        continue;
+      }
+      if let Err(err) = games.send(Remove { game_id }).await {
+        tracing::error!(game_id, "maintenance drain: remove game: {}", err);
+      }
+    }
+    Ok(())
+  }
+}
+
+pub struct ScheduleMaintenance {
+  pub window: MaintenanceWindow,
+}
+
+impl Message for ScheduleMaintenance {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<ScheduleMaintenance> for Maintenance {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    ScheduleMaintenance { window }: ScheduleMaintenance,
+  ) -> Result<()> {
+    if window.ends_at <= window.starts_at || window.ends_at <= Utc::now() {
+      return Err(Error::MaintenanceWindowInvalid);
+    }
+
+    tracing::info!(
+      starts_at = %window.starts_at,
+      ends_at = %window.ends_at,
+      "maintenance scheduled"
+    );
+
+    let message = catalogue::MAINTENANCE_SCHEDULED
+      .with("starts_at", window.starts_at.format("%Y-%m-%d %H:%M"))
+      .with("message", &window.message);
+    let persisted = window.clone();
+    self.db.exec(move |conn| db::set(conn, &persisted)).await?;
+    self.generation += 1;
+    self.window = Some(window);
+    self.publish();
+    self.schedule_next_tick(ctx);
+    self.notify_players(message).await
+  }
+}
+
+pub struct CancelMaintenance;
+
+impl Message for CancelMaintenance {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<CancelMaintenance> for Maintenance {
+  async fn handle(&mut self, _: &mut Context<Self>, _: CancelMaintenance) -> Result<()> {
+    if self.window.is_none() {
+      return Err(Error::MaintenanceNotScheduled);
+    }
+    self.db.exec(|conn| db::remove(conn)).await?;
+    self.window.take();
+    self.generation += 1;
+    self.publish();
+    tracing::info!("maintenance cancelled");
+    self
+      .notify_players(catalogue::MAINTENANCE_CANCELLED.message())
+      .await
+  }
+}
+
+pub struct GetMaintenance;
+
+impl Message for GetMaintenance {
+  type Result = Option<MaintenanceWindow>;
+}
+
+#[async_trait]
+impl Handler<GetMaintenance> for Maintenance {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    _: GetMaintenance,
+  ) -> Option<MaintenanceWindow> {
+    self.window.clone()
+  }
+}
+
+pub struct GetMaintenanceGate;
+
+impl Message for GetMaintenanceGate {
+  type Result = MaintenanceGate;
+}
+
+#[async_trait]
+impl Handler<GetMaintenanceGate> for Maintenance {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetMaintenanceGate) -> MaintenanceGate {
+    self.gate.clone()
+  }
+}
+
+struct Tick {
+  generation: u64,
+}
+
+impl Message for Tick {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Tick> for Maintenance {
+  async fn handle(&mut self, ctx: &mut Context<Self>, Tick { generation }: Tick) {
+    if generation != self.generation {
+      return;
+    }
+
+    let window = if let Some(window) = self.window.clone() {
+      window
+    } else {
+      return;
+    };
+
+    let now = Utc::now();
+    let res = if now >= window.ends_at {
+      tracing::info!("maintenance ended");
+      self.window.take();
+      self.publish();
+      self.db.exec(|conn| db::remove(conn)).await
+    } else if now >= window.starts_at {
+      tracing::info!("maintenance started");
+      let res = self
+        .notify_players(catalogue::MAINTENANCE_STARTED.with("message", &window.message))
+        .await;
+      if window.drain_nodes {
+        match self.games.resolve().await {
+          Ok(games) => {
+            let db = self.db.clone();
+            ctx.spawn(async move {
+              if let Err(err) = Self::drain(db, games).await {
+                tracing::error!("maintenance drain: {}", err);
+              }
+            });
+          }
+          Err(err) => {
+            tracing::error!("maintenance drain: resolve game registry: {}", err);
+          }
+        }
+      }
+      res
+    } else {
+      let minutes = (window.starts_at - now).num_seconds().max(0) as f64 / 60.;
+      self
+        .notify_players(
+          catalogue::MAINTENANCE_COUNTDOWN
+            .with("minutes", minutes.round() as i64)
+            .with("message", &window.message),
+        )
+        .await
+    };
+
+    if let Err(err) = res {
+      tracing::error!("maintenance notice: {}", err);
+    }
+
+    self.schedule_next_tick(ctx);
+  }
+}
+
+#[test]
+fn test_maintenance_gate() {
+  let now = Utc::now();
+  let (tx, rx) = watch::channel(None);
+  let gate = MaintenanceGate(rx);
+  assert!(gate.check_game_creation().is_ok());
+
+  tx.send(Some(MaintenanceWindow {
+    starts_at: now + Duration::minutes(CREATE_GAME_LOCK_MINUTES - 1),
+    ends_at: now + Duration::hours(1),
+    message: String::new(),
+    drain_nodes: true,
+  }))
+  .unwrap();
+  assert!(matches!(
+    gate.check_game_creation(),
+    Err(Error::Maintenance(_))
+  ));
+
+  tx.send(None).unwrap();
+  assert!(gate.check_game_creation().is_ok());
+}
@@ -4,7 +4,8 @@ use serde::Deserialize;
 
 use crate::db::DbConn;
 use crate::error::*;
-use crate::schema::map_checksum;
+use crate::map::{Map, MapFileInfo};
+use crate::schema::{map_catalogue, map_checksum};
 
 pub fn search_checksum(conn: &DbConn, sha1: String) -> Result<Option<u32>> {
   use map_checksum::dsl;
@@ -62,3 +63,257 @@ struct Insert<'a> {
   sha1: &'a str,
   checksum: Vec<u8>,
 }
+
+/// Adds the map of a created game to the catalogue or bumps its hosted count.
+/// The map comes from the client, it is only searchable once `register_file`
+/// replaced its name, author and description by the registered file info.
+pub fn record_hosted(conn: &DbConn, map: &Map) -> Result<()> {
+  use map_catalogue::dsl;
+
+  let insert = CatalogueInsert {
+    sha1: map.sha1.to_hex(),
+    name: &map.name,
+    author: &map.author,
+    description: &map.description,
+    path: &map.path,
+    map: serde_json::to_value(map)?,
+  };
+
+  diesel::insert_into(map_catalogue::table)
+    .values(&insert)
+    .on_conflict(dsl::sha1)
+    .do_update()
+    .set((
+      dsl::hosted_count.eq(dsl::hosted_count + 1),
+      dsl::updated_at.eq(diesel::dsl::now),
+    ))
+    .execute(conn)?;
+  Ok(())
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "map_catalogue"]
+struct CatalogueInsert<'a> {
+  sha1: String,
+  name: &'a str,
+  author: &'a str,
+  description: &'a str,
+  path: &'a str,
+  map: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub struct CatalogueMap {
+  pub id: i32,
+  pub hosted_count: i32,
+  pub previous_version_id: Option<i32>,
+  /// Set if a newer version has been catalogued
+  pub latest_version_id: Option<i32>,
+  pub map: Map,
+  /// Set once the file is registered, trusted over `map`
+  pub file_info: Option<MapFileInfo>,
+}
+
+type CatalogueRow = (
+  i32,
+  i32,
+  Option<i32>,
+  Option<i32>,
+  serde_json::Value,
+  Option<serde_json::Value>,
+);
+
+impl CatalogueMap {
+  const COLUMNS: (
+    map_catalogue::dsl::id,
+    map_catalogue::dsl::hosted_count,
+    map_catalogue::dsl::previous_version_id,
+    map_catalogue::dsl::latest_version_id,
+    map_catalogue::dsl::map,
+    map_catalogue::dsl::file_info,
+  ) = (
+    map_catalogue::dsl::id,
+    map_catalogue::dsl::hosted_count,
+    map_catalogue::dsl::previous_version_id,
+    map_catalogue::dsl::latest_version_id,
+    map_catalogue::dsl::map,
+    map_catalogue::dsl::file_info,
+  );
+
+  fn from_row(
+    (id, hosted_count, previous_version_id, latest_version_id, map, file_info): CatalogueRow,
+  ) -> Result<Self> {
+    Ok(CatalogueMap {
+      id,
+      hosted_count,
+      previous_version_id,
+      latest_version_id,
+      map: serde_json::from_value(map)?,
+      file_info: file_info.map(serde_json::from_value).transpose()?,
+    })
+  }
+}
+
+pub fn get_catalogue_map(conn: &DbConn, id: i32) -> Result<CatalogueMap> {
+  let row: CatalogueRow = map_catalogue::table
+    .find(id)
+    .select(CatalogueMap::COLUMNS)
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::MapNotFound)?;
+  CatalogueMap::from_row(row)
+}
+
+/// Returns the latest version of the map if it has been superseded
+pub fn get_newer_version(conn: &DbConn, map: &Map) -> Result<Option<CatalogueMap>> {
+  use map_catalogue::dsl;
+  let latest_version_id: Option<i32> = map_catalogue::table
+    .filter(dsl::sha1.eq(map.sha1.to_hex()))
+    .select(dsl::latest_version_id)
+    .first::<Option<i32>>(conn)
+    .optional()?
+    .flatten();
+  latest_version_id
+    .map(|id| get_catalogue_map(conn, id))
+    .transpose()
+}
+
+/// Searches the registered maps by name, author and description,
+/// best name matches and most hosted maps first
+pub fn search_catalogue(conn: &DbConn, query: &str, take: i64) -> Result<Vec<CatalogueMap>> {
+  use diesel::dsl::sql;
+  use diesel::sql_types::{Float, Text};
+  use map_catalogue::dsl;
+
+  let take = std::cmp::max(1, std::cmp::min(100, take));
+  let query = query.trim();
+
+  let mut q = map_catalogue::table
+    .filter(dsl::sha256.is_not_null())
+    .select(CatalogueMap::COLUMNS)
+    .limit(take)
+    .into_boxed();
+
+  if query.is_empty() {
+    q = q.order(dsl::hosted_count.desc());
+  } else {
+    let like = format!("%{}%", crate::db::escape_like(query));
+    q = q
+      .filter(
+        dsl::name
+          .ilike(like.clone())
+          .or(dsl::author.ilike(like.clone()))
+          .or(dsl::description.ilike(like)),
+      )
+      .order(
+        sql::<Float>("similarity(map_catalogue.name, ")
+          .bind::<Text, _>(query.to_string())
+          .sql(") desc"),
+      )
+      .then_order_by(dsl::hosted_count.desc());
+  }
+
+  let rows: Vec<CatalogueRow> = q.load(conn)?;
+  rows.into_iter().map(CatalogueMap::from_row).collect()
+}
+
+/// Records that the file of a catalogued map has been stored under its sha256.
+/// A registered file is never replaced, registering another file for the map fails
+/// with `MapFileConflict`.
+///
+/// Registered by an api client, so the name, author and description of the file info
+/// replace the ones sent by the clients, and the map is trusted to be a version of the
+/// registered maps with its name and author: it is linked to the latest of them and
+/// becomes the latest version of all of them.
+pub fn register_file(
+  conn: &DbConn,
+  sha1: &str,
+  sha256: &str,
+  file_info: &MapFileInfo,
+) -> Result<()> {
+  use map_catalogue::dsl;
+  let name = &file_info.name;
+  let author = &file_info.author;
+  let info = serde_json::to_value(file_info)?;
+  conn.transaction(|| {
+    let (id, registered): (i32, Option<String>) = map_catalogue::table
+      .filter(dsl::sha1.eq(sha1))
+      .select((dsl::id, dsl::sha256))
+      .for_update()
+      .first(conn)
+      .optional()?
+      .ok_or_else(|| Error::MapNotFound)?;
+    if let Some(registered) = registered {
+      return if registered == sha256 {
+        Ok(())
+      } else {
+        Err(Error::MapFileConflict)
+      };
+    }
+    diesel::update(map_catalogue::table.find(id))
+      .set((
+        dsl::sha256.eq(sha256),
+        dsl::file_info.eq(&info),
+        dsl::name.eq(name),
+        dsl::author.eq(author),
+        dsl::description.eq(&file_info.description),
+      ))
+      .execute(conn)?;
+
+    let versions = map_catalogue::table.filter(
+      dsl::name
+        .eq(name)
+        .and(dsl::author.eq(author))
+        .and(dsl::sha256.is_not_null())
+        .and(dsl::id.ne(id)),
+    );
+    let previous_version_id: Option<i32> = versions
+      .order(dsl::created_at.desc())
+      .select(dsl::id)
+      .first(conn)
+      .optional()?;
+    if previous_version_id.is_none() {
+      return Ok(());
+    }
+
+    diesel::update(map_catalogue::table.find(id))
+      .set(dsl::previous_version_id.eq(previous_version_id))
+      .execute(conn)?;
+    diesel::update(versions)
+      .set(dsl::latest_version_id.eq(id))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+/// The map info registered with the file of the map, if any
+pub fn get_file_info(conn: &DbConn, sha1: &str) -> Result<Option<MapFileInfo>> {
+  use map_catalogue::dsl;
+  let value: Option<serde_json::Value> = map_catalogue::table
+    .filter(dsl::sha1.eq(sha1))
+    .select(dsl::file_info)
+    .first::<Option<serde_json::Value>>(conn)
+    .optional()?
+    .flatten();
+  Ok(value.map(serde_json::from_value).transpose()?)
+}
+
+/// Team of each slot if the registered file of the map has fixed teams
+pub fn get_force_teams(conn: &DbConn, map: &Map) -> Result<Option<Vec<i32>>> {
+  Ok(
+    get_file_info(conn, &map.sha1.to_hex())?
+      .and_then(|info| info.force_teams())
+      .filter(|teams| teams.len() == map.players.len()),
+  )
+}
+
+pub fn get_file_sha256(conn: &DbConn, sha1: &str) -> Result<String> {
+  use map_catalogue::dsl;
+  map_catalogue::table
+    .filter(dsl::sha1.eq(sha1))
+    .select(dsl::sha256)
+    .first::<Option<String>>(conn)
+    .optional()?
+    .ok_or_else(|| Error::MapNotFound)?
+    .ok_or_else(|| Error::MapNotStored)
+}
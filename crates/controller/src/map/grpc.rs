@@ -0,0 +1,181 @@
+use crate::config::{ApiRequestExt, FloGrpcInterceptor};
+use crate::error::Error;
+use crate::game::db::CreateGameAsBotParams;
+use crate::game::melee;
+use crate::game::state::create::CreateGameAsBot;
+use crate::game::{GameRules, Race};
+use crate::map::db::CatalogueMap;
+use crate::map::{MapFileInfo, MapSha1};
+use crate::state::ControllerStateRef;
+use s2_grpc_utils::S2ProtoUnpack;
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+  tonic::include_proto!("flo_map");
+}
+
+use proto::flo_map_server::{FloMap, FloMapServer};
+use proto::*;
+
+pub fn server(
+  state: ControllerStateRef,
+  interceptor: FloGrpcInterceptor,
+) -> InterceptedService<FloMapServer<FloMapService>, FloGrpcInterceptor> {
+  FloMapServer::with_interceptor(FloMapService { state }, interceptor)
+}
+
+pub struct FloMapService {
+  state: ControllerStateRef,
+}
+
+#[tonic::async_trait]
+impl FloMap for FloMapService {
+  async fn search_maps(
+    &self,
+    request: Request<SearchMapsRequest>,
+  ) -> Result<Response<SearchMapsReply>, Status> {
+    let req = request.into_inner();
+    let take = if req.take > 0 { req.take } else { 30 };
+    let maps = self
+      .state
+      .db
+      .exec(move |conn| crate::map::db::search_catalogue(conn, &req.query, take))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(SearchMapsReply {
+      maps: maps.into_iter().map(pack_map).collect(),
+    }))
+  }
+
+  async fn get_map(
+    &self,
+    request: Request<GetMapRequest>,
+  ) -> Result<Response<proto::CatalogueMap>, Status> {
+    let id = request.into_inner().id;
+    let map = self
+      .state
+      .db
+      .exec(move |conn| crate::map::db::get_catalogue_map(conn, id))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(pack_map(map)))
+  }
+
+  async fn register_map_file(
+    &self,
+    request: Request<RegisterMapFileRequest>,
+  ) -> Result<Response<RegisterMapFileReply>, Status> {
+    let req = request.into_inner();
+    let sha256 = req.sha256.to_lowercase();
+    if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+      return Err(Status::invalid_argument("invalid sha256"));
+    }
+    let sha1 = MapSha1::unpack(req.sha1).map_err(Error::from)?.to_hex();
+    let file_info = MapFileInfo::from(
+      req
+        .info
+        .ok_or_else(|| Status::invalid_argument("map file info required"))?,
+    );
+    let content_url = crate::map::storage::content_url(&sha256).unwrap_or_default();
+    self
+      .state
+      .db
+      .exec(move |conn| crate::map::db::register_file(conn, &sha1, &sha256, &file_info))
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(RegisterMapFileReply { content_url }))
+  }
+
+  async fn create_melee_game(
+    &self,
+    request: Request<CreateMeleeGameRequest>,
+  ) -> Result<Response<CreateMeleeGameReply>, Status> {
+    let api_client_id = request.get_api_client_id();
+    let api_player_id = request.get_api_player_id();
+    let req = request.into_inner();
+    let teams = req
+      .teams
+      .into_iter()
+      .map(|team| team.players.into_iter().map(unpack_melee_player).collect())
+      .collect::<Result<Vec<Vec<_>>, Status>>()?;
+    let map_id = req.map_id;
+    let observer_ids = req.observer_ids;
+    let (map, slots) = self
+      .state
+      .db
+      .exec(move |conn| -> Result<_, Error> {
+        let item = crate::map::db::get_catalogue_map(conn, map_id)?;
+        let slots = melee::melee_slots(&item.map, item.file_info.as_ref(), &teams, &observer_ids)?;
+        Ok((item.map, slots))
+      })
+      .await
+      .map_err(Error::from)?;
+
+    let message = CreateGameAsBot {
+      api_client_id,
+      api_player_id,
+      params: CreateGameAsBotParams {
+        name: req.name,
+        map,
+        is_private: req.is_private,
+        is_live: req.is_live,
+        node_id: req.node_id,
+        slots,
+        mask_player_names: None,
+      },
+      rules: GameRules::default(),
+      metadata: Default::default(),
+      request_id: None,
+    }
+    .filter_name()
+    .await?;
+    let game = self
+      .state
+      .games
+      .send(message)
+      .await
+      .map_err(Error::from)??;
+    Ok(Response::new(CreateMeleeGameReply { game_id: game.id }))
+  }
+}
+
+fn unpack_melee_player(player: MeleePlayer) -> Result<melee::MeleePlayer, Status> {
+  let race = match player.race {
+    None => None,
+    Some(0) => Some(Race::Human),
+    Some(1) => Some(Race::Orc),
+    Some(2) => Some(Race::NightElf),
+    Some(3) => Some(Race::Undead),
+    Some(4) => Some(Race::Random),
+    Some(_) => return Err(Status::invalid_argument("invalid race")),
+  };
+  Ok(melee::MeleePlayer {
+    player_id: player.player_id,
+    race,
+  })
+}
+
+fn pack_map(item: CatalogueMap) -> proto::CatalogueMap {
+  let map = item.map;
+  // the registered file info over the one sent by the clients
+  let (name, author, description) = match item.file_info {
+    Some(info) => (info.name, info.author, info.description),
+    None => (map.name, map.author, map.description),
+  };
+  proto::CatalogueMap {
+    id: item.id,
+    sha1: map.sha1.to_vec(),
+    checksum: map.checksum,
+    name,
+    author,
+    description,
+    path: map.path,
+    width: map.width,
+    height: map.height,
+    num_players: map.players.len() as u32,
+    hosted_count: item.hosted_count,
+    previous_version_id: item.previous_version_id,
+    latest_version_id: item.latest_version_id,
+  }
+}
@@ -1,4 +1,6 @@
 pub mod db;
+pub(crate) mod grpc;
+pub mod storage;
 
 use s2_grpc_utils::result::Error as ProtoError;
 use s2_grpc_utils::{S2ProtoPack, S2ProtoUnpack};
@@ -27,6 +29,10 @@ impl MapSha1 {
   pub fn to_vec(&self) -> Vec<u8> {
     self.0.to_vec()
   }
+
+  pub fn to_hex(&self) -> String {
+    self.0.iter().map(|b| format!("{:02x}", b)).collect()
+  }
 }
 
 impl S2ProtoUnpack<Vec<u8>> for MapSha1 {
@@ -63,3 +69,110 @@ pub struct MapForce {
   pub flags: u32,
   pub player_set: u32,
 }
+
+/// Read from the map file by the api client registering it, unlike `Map` which is sent by
+/// the game clients
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MapFileInfo {
+  pub name: String,
+  pub author: String,
+  pub description: String,
+  pub players: Vec<MapFilePlayer>,
+  pub forces: Vec<MapFileForce>,
+  /// Players can't leave the force the map puts them in
+  pub fixed_teams: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MapFilePlayer {
+  /// Player id of the w3i, bit of the player in `MapFileForce::player_set`
+  pub id: u32,
+  pub r#type: u32,
+  pub race: u32,
+  pub flags: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MapFileForce {
+  pub flags: u32,
+  pub player_set: u32,
+}
+
+impl MapFileInfo {
+  /// Team of each player slot if the map has fixed teams: the index of the force containing
+  /// the w3i player id of the map player, the map players are numbered in slot order
+  pub fn force_teams(&self) -> Option<Vec<i32>> {
+    if !self.fixed_teams {
+      return None;
+    }
+    let teams = self
+      .players
+      .iter()
+      .map(|player| {
+        self
+          .forces
+          .iter()
+          .position(|force| player.id < 32 && force.player_set & (1 << player.id) != 0)
+          .unwrap_or(0) as i32
+      })
+      .collect();
+    Some(teams)
+  }
+}
+
+impl From<grpc::proto::MapFileInfo> for MapFileInfo {
+  fn from(info: grpc::proto::MapFileInfo) -> Self {
+    MapFileInfo {
+      name: info.name,
+      author: info.author,
+      description: info.description,
+      players: info
+        .players
+        .into_iter()
+        .map(|player| MapFilePlayer {
+          id: player.id,
+          r#type: player.r#type,
+          race: player.race,
+          flags: player.flags,
+        })
+        .collect(),
+      forces: info
+        .forces
+        .into_iter()
+        .map(|force| MapFileForce {
+          flags: force.flags,
+          player_set: force.player_set,
+        })
+        .collect(),
+      fixed_teams: info.fixed_teams,
+    }
+  }
+}
+
+#[test]
+fn test_map_file_force_teams() {
+  let player = |id| MapFilePlayer {
+    id,
+    r#type: 1,
+    race: 0,
+    flags: 0,
+  };
+  let mut info = MapFileInfo {
+    // the w3i ids don't follow the slot order
+    players: vec![player(2), player(0), player(5)],
+    forces: vec![
+      MapFileForce {
+        flags: 0,
+        player_set: 1 << 0,
+      },
+      MapFileForce {
+        flags: 0,
+        player_set: 1 << 2 | 1 << 5,
+      },
+    ],
+    ..Default::default()
+  };
+  assert_eq!(info.force_teams(), None);
+  info.fixed_teams = true;
+  assert_eq!(info.force_teams(), Some(vec![1, 0, 1]));
+}
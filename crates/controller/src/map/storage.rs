@@ -0,0 +1,173 @@
+//! Content addressed map files.
+//!
+//! Map files are stored as `maps/<sha256>.w3x` in the map bucket, optionally served by a CDN.
+//! Game info carries a short-lived link to the controller HTTP server keyed by the map sha1,
+//! which redirects to the stored file if it has been registered in the map catalogue.
+//! The links are signed with `FLO_MAP_DOWNLOAD_SECRET`, they are not generated if it's unset.
+
+use crate::error::*;
+use once_cell::sync::Lazy;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::Region;
+use rusoto_s3::util::{PreSignedRequest, PreSignedRequestOption};
+use rusoto_s3::GetObjectRequest;
+use std::env;
+use std::fmt::Write;
+use std::time::Duration;
+
+pub const MAP_URL_TTL: Duration = Duration::from_secs(600);
+pub const MAP_HTTP_PATH_PREFIX: &str = "/maps/";
+
+#[derive(Debug)]
+struct MapStorage {
+  /// Public base URL of the controller HTTP server, download links are not generated if unset
+  download_base_url: Option<String>,
+  /// Key of the download link signatures, download links are not generated if unset
+  download_secret: Option<String>,
+  /// Serves the objects of the bucket
+  cdn_base_url: Option<String>,
+  s3: Option<S3Bucket>,
+}
+
+#[derive(Debug)]
+struct S3Bucket {
+  bucket: String,
+  region: Region,
+  credentials: AwsCredentials,
+}
+
+static STORAGE: Lazy<MapStorage> = Lazy::new(|| {
+  let s3 = env::var("FLO_MAP_S3_BUCKET").ok().map(|bucket| {
+    let region_name = env::var("AWS_S3_REGION").unwrap_or_default();
+    let region = if let Ok(endpoint) = env::var("FLO_MAP_S3_ENDPOINT") {
+      Region::Custom {
+        name: region_name,
+        endpoint,
+      }
+    } else {
+      region_name.parse().unwrap_or_default()
+    };
+    S3Bucket {
+      bucket,
+      region,
+      credentials: AwsCredentials::new(
+        env::var("AWS_ACCESS_KEY_ID").unwrap_or_default(),
+        env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default(),
+        None,
+        None,
+      ),
+    }
+  });
+  MapStorage {
+    download_base_url: env::var("FLO_MAP_DOWNLOAD_URL")
+      .ok()
+      .map(|v| v.trim_end_matches('/').to_string()),
+    download_secret: env::var("FLO_MAP_DOWNLOAD_SECRET")
+      .ok()
+      .filter(|v| !v.is_empty()),
+    cdn_base_url: env::var("FLO_MAP_CDN_URL")
+      .ok()
+      .map(|v| v.trim_end_matches('/').to_string()),
+    s3,
+  }
+});
+
+#[derive(Debug)]
+pub struct MapUrl {
+  pub url: String,
+  /// unix timestamp in seconds
+  pub expires_at: u32,
+}
+
+/// Object key of a map file in the map bucket
+pub fn object_key(sha256: &str) -> String {
+  format!("maps/{}.w3x", sha256)
+}
+
+/// Stable URL of a map file, if the bucket is served by a CDN
+pub fn content_url(sha256: &str) -> Option<String> {
+  STORAGE
+    .cdn_base_url
+    .as_ref()
+    .map(|base| format!("{}/{}", base, object_key(sha256)))
+}
+
+/// Short-lived link of a stored map file
+pub fn object_url(sha256: &str) -> Result<MapUrl> {
+  let expires_at = flo_net::echo::unix_now().saturating_add(MAP_URL_TTL.as_secs() as u32);
+  if let Some(S3Bucket {
+    ref bucket,
+    ref region,
+    ref credentials,
+  }) = STORAGE.s3
+  {
+    let url = GetObjectRequest {
+      bucket: bucket.clone(),
+      key: object_key(sha256),
+      ..Default::default()
+    }
+    .get_presigned_url(
+      region,
+      credentials,
+      &PreSignedRequestOption {
+        expires_in: MAP_URL_TTL,
+      },
+    );
+    return Ok(MapUrl { url, expires_at });
+  }
+  content_url(sha256)
+    .map(|url| MapUrl { url, expires_at })
+    .ok_or_else(|| Error::MapNotStored)
+}
+
+/// Signed link to the controller HTTP server for the map with `sha1`
+pub fn download_link(sha1: &[u8]) -> Option<MapUrl> {
+  let base = STORAGE.download_base_url.as_ref()?;
+  let secret = STORAGE.download_secret.as_ref()?;
+  let expires_at = flo_net::echo::unix_now().saturating_add(MAP_URL_TTL.as_secs() as u32);
+  Some(MapUrl {
+    url: format!("{}{}", base, signed_path(secret, sha1, expires_at)),
+    expires_at,
+  })
+}
+
+/// Returns the sha1 hex if the path and query of a download link are valid
+pub fn verify_download_path(path: &str, query: &str, now: u32) -> Option<String> {
+  let secret = STORAGE.download_secret.as_ref()?;
+  verify_path(secret, path, query, now)
+}
+
+fn signed_path(secret: &str, sha1: &[u8], expires_at: u32) -> String {
+  flo_net::signed_url::signed_path(
+    secret.as_bytes(),
+    MAP_HTTP_PATH_PREFIX,
+    &to_hex(sha1),
+    expires_at,
+  )
+}
+
+fn verify_path(secret: &str, path: &str, query: &str, now: u32) -> Option<String> {
+  flo_net::signed_url::verify_path(secret.as_bytes(), MAP_HTTP_PATH_PREFIX, path, query, now)
+    .map(ToString::to_string)
+}
+
+fn to_hex(data: &[u8]) -> String {
+  let mut s = String::with_capacity(data.len() * 2);
+  for b in data {
+    write!(s, "{:02x}", b).ok();
+  }
+  s
+}
+
+#[test]
+fn test_map_signed_path() {
+  let sha1 = [0xAB_u8; 20];
+  let path = signed_path("secret", &sha1, 1000);
+  let (path, query) = path.split_at(path.find('?').unwrap());
+  let query = &query[1..];
+  assert_eq!(verify_path("secret", path, query, 999), Some(to_hex(&sha1)));
+  assert_eq!(verify_path("secret", path, query, 1001), None);
+  assert_eq!(verify_path("other", path, query, 999), None);
+  let tampered = query.replace("expires=1000", "expires=2000");
+  assert_eq!(verify_path("secret", path, &tampered, 999), None);
+}
@@ -0,0 +1,62 @@
+use diesel::prelude::*;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::motd::{MotdItem, MotdItemKind};
+use crate::schema::motd_item;
+
+/// Newest first
+pub fn list(conn: &DbConn) -> Result<Vec<MotdItem>> {
+  Ok(
+    motd_item::table
+      .order(motd_item::id.desc())
+      .select(MotdItem::COLUMNS)
+      .load(conn)?,
+  )
+}
+
+#[derive(Debug)]
+pub struct MotdItemUpdate {
+  /// Adds a new item if not set
+  pub id: Option<i32>,
+  pub kind: MotdItemKind,
+  pub title: String,
+  pub body: String,
+}
+
+pub fn set(conn: &DbConn, item: &MotdItemUpdate) -> Result<MotdItem> {
+  use diesel::dsl::now;
+  use motd_item::dsl;
+  if let Some(id) = item.id {
+    diesel::update(motd_item::table.find(id))
+      .set((
+        dsl::kind.eq(item.kind),
+        dsl::title.eq(&item.title),
+        dsl::body.eq(&item.body),
+        dsl::updated_at.eq(now),
+      ))
+      .returning(MotdItem::COLUMNS)
+      .get_result(conn)
+      .optional()?
+      .ok_or_else(|| Error::MotdItemNotFound)
+  } else {
+    Ok(
+      diesel::insert_into(motd_item::table)
+        .values((
+          dsl::kind.eq(item.kind),
+          dsl::title.eq(&item.title),
+          dsl::body.eq(&item.body),
+        ))
+        .returning(MotdItem::COLUMNS)
+        .get_result(conn)?,
+    )
+  }
+}
+
+pub fn remove(conn: &DbConn, id: i32) -> Result<()> {
+  let n = diesel::delete(motd_item::table.find(id)).execute(conn)?;
+  if n == 0 {
+    return Err(Error::MotdItemNotFound);
+  }
+  Ok(())
+}
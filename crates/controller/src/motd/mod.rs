@@ -0,0 +1,319 @@
+//! Announcements, patch notes and event banners shown by the client.
+//!
+//! Admins write the body in a small markdown subset, one block per line:
+//! `# heading`, `- list item` or a paragraph line, with `**bold**`, `*italic*` and
+//! `[text](https://...)` inside paragraphs and list items. It is parsed into segments so
+//! clients never have to render markup. The items are sent to every player at connect,
+//! and again to everyone online when an admin changes them.
+
+pub mod db;
+
+use crate::db::ExecutorRef;
+use crate::error::*;
+use crate::player::state::sender::PlayerRegistryHandle;
+use crate::schema::motd_item;
+use crate::state::Data;
+use bs_diesel_utils::BSDieselEnum;
+use chrono::{DateTime, Utc};
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::{self, MotdSegment, MotdSegmentKind, PacketMotd};
+use flo_state::{async_trait, Actor, Context, Handler, Message, RegistryRef, Service};
+use s2_grpc_utils::S2ProtoEnum;
+use serde::Serialize;
+
+pub use db::MotdItemUpdate;
+
+const MAX_TITLE_LEN: usize = 100;
+const MAX_BODY_LEN: usize = 4000;
+
+#[derive(Debug, Serialize, Copy, Clone, PartialEq, BSDieselEnum, S2ProtoEnum)]
+#[repr(i32)]
+#[s2_grpc(proto_enum_type(flo_connect::MotdItemKind))]
+pub enum MotdItemKind {
+  Announcement = 0,
+  PatchNotes = 1,
+  Event = 2,
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct MotdItem {
+  pub id: i32,
+  pub kind: MotdItemKind,
+  pub title: String,
+  /// Markdown subset, see the module doc
+  pub body: String,
+  pub updated_at: DateTime<Utc>,
+}
+
+pub(crate) type MotdItemColumns = (
+  motd_item::id,
+  motd_item::kind,
+  motd_item::title,
+  motd_item::body,
+  motd_item::updated_at,
+);
+
+impl MotdItem {
+  pub(crate) const COLUMNS: MotdItemColumns = (
+    motd_item::id,
+    motd_item::kind,
+    motd_item::title,
+    motd_item::body,
+    motd_item::updated_at,
+  );
+
+  pub fn into_proto(self) -> flo_connect::MotdItem {
+    flo_connect::MotdItem {
+      id: self.id,
+      kind: self.kind.into_proto_enum() as i32,
+      title: self.title,
+      segments: parse_body(&self.body),
+      updated_at: self.updated_at.timestamp(),
+    }
+  }
+}
+
+pub struct Motd {
+  db: ExecutorRef,
+  players: PlayerRegistryHandle,
+  // newest first
+  items: Vec<MotdItem>,
+}
+
+impl Actor for Motd {}
+
+#[async_trait]
+impl Service<Data> for Motd {
+  type Error = Error;
+
+  async fn create(registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
+    let db = registry.data().db.clone();
+    let players = registry.resolve().await?;
+    let items = db.exec(|conn| db::list(conn)).await?;
+    Ok(Self {
+      db,
+      players: PlayerRegistryHandle::from(players),
+      items,
+    })
+  }
+}
+
+impl Motd {
+  fn packet(&self) -> PacketMotd {
+    PacketMotd {
+      items: self
+        .items
+        .iter()
+        .cloned()
+        .map(MotdItem::into_proto)
+        .collect(),
+    }
+  }
+
+  async fn notify_players(&self) -> Result<()> {
+    let frame = self.packet().encode_as_frame()?;
+    self.players.broadcast_to_all(frame).await
+  }
+}
+
+pub struct SetMotdItem {
+  pub item: MotdItemUpdate,
+}
+
+impl Message for SetMotdItem {
+  type Result = Result<MotdItem>;
+}
+
+#[async_trait]
+impl Handler<SetMotdItem> for Motd {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    SetMotdItem { mut item }: SetMotdItem,
+  ) -> Result<MotdItem> {
+    item.title = item.title.trim().to_string();
+    if item.title.is_empty() || item.title.len() > MAX_TITLE_LEN || item.body.len() > MAX_BODY_LEN {
+      return Err(Error::MotdItemInvalid);
+    }
+
+    let saved = self.db.exec(move |conn| db::set(conn, &item)).await?;
+    self.items.retain(|v| v.id != saved.id);
+    self.items.push(saved.clone());
+    self.items.sort_by(|a, b| b.id.cmp(&a.id));
+
+    if let Err(err) = self.notify_players().await {
+      tracing::error!("motd: notify players: {}", err);
+    }
+    Ok(saved)
+  }
+}
+
+pub struct RemoveMotdItem {
+  pub id: i32,
+}
+
+impl Message for RemoveMotdItem {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<RemoveMotdItem> for Motd {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    RemoveMotdItem { id }: RemoveMotdItem,
+  ) -> Result<()> {
+    self.db.exec(move |conn| db::remove(conn, id)).await?;
+    self.items.retain(|v| v.id != id);
+
+    if let Err(err) = self.notify_players().await {
+      tracing::error!("motd: notify players: {}", err);
+    }
+    Ok(())
+  }
+}
+
+pub struct ListMotdItems;
+
+impl Message for ListMotdItems {
+  type Result = Vec<MotdItem>;
+}
+
+#[async_trait]
+impl Handler<ListMotdItems> for Motd {
+  async fn handle(&mut self, _: &mut Context<Self>, _: ListMotdItems) -> Vec<MotdItem> {
+    self.items.clone()
+  }
+}
+
+/// The packet sent at connect
+pub struct GetMotd;
+
+impl Message for GetMotd {
+  type Result = PacketMotd;
+}
+
+#[async_trait]
+impl Handler<GetMotd> for Motd {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetMotd) -> PacketMotd {
+    self.packet()
+  }
+}
+
+/// Lines are separated by `MotdSegmentKindLineBreak`, unclosed markers are kept as text
+pub fn parse_body(body: &str) -> Vec<MotdSegment> {
+  let mut segments = vec![];
+  for (idx, line) in body.lines().enumerate() {
+    if idx > 0 {
+      segments.push(segment(MotdSegmentKind::LineBreak, ""));
+    }
+    let line = line.trim_end();
+    let heading = line.trim_start_matches('#');
+    if heading.len() < line.len() && heading.starts_with(' ') {
+      segments.push(segment(MotdSegmentKind::Heading, heading.trim()));
+    } else if let Some(item) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+      segments.push(segment(MotdSegmentKind::ListItem, ""));
+      parse_inline(item.trim_start(), &mut segments);
+    } else {
+      parse_inline(line, &mut segments);
+    }
+  }
+  segments
+}
+
+fn parse_inline(line: &str, segments: &mut Vec<MotdSegment>) {
+  let mut text = String::new();
+  let mut rest = line;
+  while let Some(c) = rest.chars().next() {
+    let parsed = if let Some(after) = rest.strip_prefix("**") {
+      enclosed(after, "**").map(|(inner, rest)| (segment(MotdSegmentKind::Bold, inner), rest))
+    } else if let Some(after) = rest.strip_prefix('*') {
+      enclosed(after, "*").map(|(inner, rest)| (segment(MotdSegmentKind::Italic, inner), rest))
+    } else if let Some(after) = rest.strip_prefix('[') {
+      link(after)
+    } else {
+      None
+    };
+
+    if let Some((parsed, after)) = parsed {
+      if !text.is_empty() {
+        segments.push(segment(MotdSegmentKind::Text, &text));
+        text.clear();
+      }
+      segments.push(parsed);
+      rest = after;
+    } else {
+      text.push(c);
+      rest = &rest[c.len_utf8()..];
+    }
+  }
+  if !text.is_empty() {
+    segments.push(segment(MotdSegmentKind::Text, &text));
+  }
+}
+
+// the non-empty text before `end`, and the rest after it
+fn enclosed<'a>(text: &'a str, end: &str) -> Option<(&'a str, &'a str)> {
+  let idx = text.find(end)?;
+  if idx == 0 {
+    return None;
+  }
+  Some((&text[..idx], &text[(idx + end.len())..]))
+}
+
+// `text](url)`, links to other schemes are shown as text
+fn link(text: &str) -> Option<(MotdSegment, &str)> {
+  let (label, rest) = enclosed(text, "](")?;
+  let (url, rest) = enclosed(rest, ")")?;
+  if label.contains('[') {
+    return None;
+  }
+  let segment = if url.starts_with("https://") || url.starts_with("http://") {
+    MotdSegment {
+      kind: MotdSegmentKind::Link.into(),
+      text: label.to_string(),
+      url: url.to_string(),
+    }
+  } else {
+    segment(MotdSegmentKind::Text, label)
+  };
+  Some((segment, rest))
+}
+
+fn segment(kind: MotdSegmentKind, text: &str) -> MotdSegment {
+  MotdSegment {
+    kind: kind.into(),
+    text: text.to_string(),
+    url: String::new(),
+  }
+}
+
+#[test]
+fn test_parse_body() {
+  let segments: Vec<_> = parse_body(
+    "# Patch 1.2\n- **New**: map [pool](https://example.com/pool)\nSee *notes*, 2 * 3 = 6 #1\n[x](javascript:alert)",
+  )
+  .into_iter()
+  .map(|s| (s.kind(), s.text, s.url))
+  .collect();
+
+  use MotdSegmentKind::*;
+  let expected: Vec<_> = vec![
+    (Heading, "Patch 1.2", ""),
+    (LineBreak, "", ""),
+    (ListItem, "", ""),
+    (Bold, "New", ""),
+    (Text, ": map ", ""),
+    (Link, "pool", "https://example.com/pool"),
+    (LineBreak, "", ""),
+    (Text, "See ", ""),
+    (Italic, "notes", ""),
+    (Text, ", 2 * 3 = 6 #1", ""),
+    (LineBreak, "", ""),
+    (Text, "x", ""),
+  ]
+  .into_iter()
+  .map(|(kind, text, url)| (kind, text.to_string(), url.to_string()))
+  .collect();
+  assert_eq!(segments, expected);
+}
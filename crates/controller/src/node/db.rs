@@ -2,8 +2,9 @@ use diesel::prelude::*;
 
 use crate::db::DbConn;
 use crate::error::*;
+use crate::game::GameStatus;
 use crate::node::types::Node;
-use crate::schema::node;
+use crate::schema::{game, node};
 
 pub fn get_all_nodes(conn: &DbConn) -> Result<Vec<Node>> {
   use node::dsl;
@@ -22,3 +23,119 @@ pub fn get_node(conn: &DbConn, node_id: i32) -> Result<Node> {
     .ok_or_else(|| Error::NodeNotFound)
     .map_err(Into::into)
 }
+
+#[derive(Debug, Insertable)]
+#[table_name = "node"]
+pub struct AddNode {
+  pub name: String,
+  pub location: String,
+  pub secret: String,
+  pub ip_addr: String,
+  pub country_id: String,
+  pub region: String,
+  pub max_games: Option<i32>,
+}
+
+pub fn add_node(conn: &DbConn, data: &AddNode) -> Result<Node> {
+  diesel::insert_into(node::table)
+    .values(data)
+    .get_result(conn)
+    .map_err(Into::into)
+}
+
+pub fn set_node_disabled(conn: &DbConn, node_id: i32, disabled: bool) -> Result<()> {
+  use node::dsl;
+  let updated = diesel::update(node::table.find(node_id))
+    .set(dsl::disabled.eq(disabled))
+    .execute(conn)?;
+  if updated == 0 {
+    return Err(Error::NodeNotFound);
+  }
+  Ok(())
+}
+
+/// A game assigned by `assign_game` counts against the capacity of the node for this long
+/// if it is not created on the node
+const NODE_RESERVATION_TIMEOUT_SECS: i64 = 60;
+
+/// Games created on the node that have not ended yet, or being created on it.
+/// Lobbies that only selected the node are not counted.
+pub fn count_active_games(conn: &DbConn, node_id: i32) -> Result<i64> {
+  count_hosted_games(conn, node_id, None)
+}
+
+fn count_hosted_games(conn: &DbConn, node_id: i32, exclude_game_id: Option<i32>) -> Result<i64> {
+  use diesel::dsl::count_star;
+  use game::dsl as g;
+
+  let reserved_since =
+    chrono::Utc::now() - chrono::Duration::seconds(NODE_RESERVATION_TIMEOUT_SECS);
+  let hosted = g::status
+    .eq_any(&[GameStatus::Created, GameStatus::Running, GameStatus::Paused] as &[_])
+    .or(
+      g::status
+        .eq(GameStatus::Preparing)
+        .and(g::node_reserved_at.gt(reserved_since)),
+    )
+    .and(g::node_id.eq(node_id));
+  if let Some(game_id) = exclude_game_id {
+    game::table
+      .filter(hosted.and(g::id.ne(game_id)))
+      .select(count_star())
+      .first(conn)
+      .map_err(Into::into)
+  } else {
+    game::table
+      .filter(hosted)
+      .select(count_star())
+      .first(conn)
+      .map_err(Into::into)
+  }
+}
+
+/// Returns `Error::NodeFull` if the node can not host another game.
+/// The node row stays locked until the transaction commits, call it in the transaction
+/// that assigns the node to the game.
+pub fn check_capacity(conn: &DbConn, node_id: i32, game_id: Option<i32>) -> Result<()> {
+  let max_games: Option<i32> = node::table
+    .find(node_id)
+    .select(node::dsl::max_games)
+    .for_update()
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::NodeNotFound)?;
+  if let Some(max_games) = max_games {
+    if count_hosted_games(conn, node_id, game_id)? >= max_games as i64 {
+      return Err(Error::NodeFull);
+    }
+  }
+  Ok(())
+}
+
+/// Checks the capacity of the node and assigns it to a game that is about to start.
+/// The game counts against the capacity while it is created on the node, concurrent
+/// starts can't both take the last slot.
+pub fn assign_game(conn: &DbConn, node_id: i32, game_id: i32) -> Result<()> {
+  use game::dsl as g;
+  conn.transaction(|| {
+    check_capacity(conn, node_id, Some(game_id))?;
+    crate::cache::invalidate_game_after_commit(game_id);
+    diesel::update(game::table.find(game_id))
+      .filter(g::status.eq(GameStatus::Preparing))
+      .set((
+        g::node_id.eq(node_id),
+        g::node_reserved_at.eq(chrono::Utc::now()),
+      ))
+      .execute(conn)?;
+    Ok(())
+  })
+}
+
+/// Releases the capacity reserved by `assign_game` if the node didn't create the game
+pub fn release_game(conn: &DbConn, game_id: i32) -> Result<()> {
+  use game::dsl as g;
+  diesel::update(game::table.find(game_id))
+    .set(g::node_reserved_at.eq(Option::<chrono::DateTime<chrono::Utc>>::None))
+    .execute(conn)?;
+  Ok(())
+}
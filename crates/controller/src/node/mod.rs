@@ -7,6 +7,7 @@ pub use state::request::PlayerLeaveResponse;
 pub use state::NodeRegistry;
 pub use types::*;
 pub mod messages {
-  pub use crate::node::state::conn::{NodeCreateGame, NodePlayerLeave};
-  pub use crate::node::state::ListNode;
+  pub use crate::node::state::conn::{NodeCreateGame, NodePlayerLeave, NodeQueryGameStatus};
+  pub use crate::node::state::request::CreatedGameInfo;
+  pub use crate::node::state::{ListNode, ListNodeStatus, SelectNodeForGame, SetPacketCapture};
 }
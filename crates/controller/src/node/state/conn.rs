@@ -1,13 +1,16 @@
 use crate::error::*;
+use crate::game::state::anomaly::GameAnomaly;
 use crate::game::state::GameRegistry;
 use crate::game::state::{GameSlotClientStatusUpdate, GameStatusUpdate};
-use crate::game::{Game, GameStatus};
+use crate::game::{Game, GameRules, GameStatus};
+use crate::node::state::pool::FramePool;
 use crate::node::state::request::{CreatedGameInfo, NodeRequestActor, NodeRequestExt};
-use crate::node::{NodeConnConfig, PlayerLeaveResponse};
+use crate::node::{NodeConnConfig, NodeLoad, PlayerLeaveResponse};
 use crate::state::ActorMapExt;
 use backoff::backoff::Backoff;
 use backoff::ExponentialBackoff;
 use flo_net::packet::*;
+use flo_net::proto::flo_common::GameLiveStatus;
 use flo_net::proto::flo_node::*;
 use flo_net::stream::FloStream;
 use flo_state::reply::FutureReply;
@@ -15,34 +18,64 @@ use flo_state::{async_trait, Actor, Addr, Context, Handler, Message, Owner};
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoUnpack};
 use std::collections::BTreeMap;
 
+use crate::game::state::failover::{FailoverNodeGames, NodeGameEvent};
 use crate::game::state::registry::Remove;
 use crate::player::PlayerBanType;
-use flo_net::ping::{PingMsg, PingStream};
-use futures::StreamExt;
+use flo_net::keepalive::{Incoming, KeepAlive, KeepAliveConfig, KeepAliveEvent};
 use std::net::{Ipv4Addr, SocketAddrV4};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio::time::sleep;
 use tracing_futures::Instrument;
 
 const MAX_BACKOFF: Duration = Duration::from_secs(60);
-
+/// Games that haven't started are moved to other nodes once the node stays disconnected for this long
+const FAILOVER_DELAY: Duration = Duration::from_secs(30);
+/// The node reports its load every 10 seconds
+const NODE_LOAD_MAX_AGE: Duration = Duration::from_secs(30);
+const KEEPALIVE: KeepAliveConfig = KeepAliveConfig {
+  interval: Duration::from_secs(30),
+  timeout: Duration::from_secs(10),
+};
+
+/// Keeps `node_connections` of the service config connections to the node,
+/// requests are spread across them and each one reconnects on its own.
+/// The node is ready while at least one of them is connected.
 pub struct NodeConnActor {
   config: NodeConnConfig,
-  reconnect_backoff: Option<ExponentialBackoff>,
-  status: NodeConnStatus,
+  slots: Vec<ConnSlot>,
   request_actor: Option<Owner<NodeRequestActor>>,
+  frames: FramePool,
   game_reg_addr: Addr<GameRegistry>,
+  /// Incremented on every established connection
+  conn_generation: u64,
+  load: Option<(Instant, NodeLoad)>,
+}
+
+struct ConnSlot {
+  status: NodeConnStatus,
+  /// Of the established connection
+  generation: u64,
+  reconnect_backoff: Option<ExponentialBackoff>,
 }
 
 impl NodeConnActor {
   pub fn new(config: NodeConnConfig, game_reg_addr: Addr<GameRegistry>) -> Self {
+    let slots = (0..crate::config::service_config().node_connections)
+      .map(|_| ConnSlot {
+        status: NodeConnStatus::Connecting,
+        generation: 0,
+        reconnect_backoff: None,
+      })
+      .collect();
     Self {
       config,
-      status: NodeConnStatus::Connecting,
-      reconnect_backoff: None,
+      slots,
       request_actor: None,
+      frames: FramePool::default(),
       game_reg_addr,
+      conn_generation: 0,
+      load: None,
     }
   }
 
@@ -60,7 +93,9 @@ impl NodeConnActor {
 #[async_trait]
 impl Actor for NodeConnActor {
   async fn started(&mut self, ctx: &mut Context<Self>) {
-    self.handle(ctx, Connect).await
+    for slot in 0..self.slots.len() {
+      self.handle(ctx, Connect { slot }).await
+    }
   }
 }
 
@@ -82,19 +117,22 @@ impl From<flo_net::error::Error> for NodeConnectError {
 }
 
 impl NodeConnActor {
-  fn schedule_reconnect(&mut self, ctx: &mut Context<Self>) {
-    self.request_actor.take();
-
-    let delay = self
+  fn schedule_reconnect(&mut self, ctx: &mut Context<Self>, slot: usize) {
+    let delay = self.slots[slot]
       .reconnect_backoff
       .get_or_insert_with(|| Self::default_backoff())
       .next_backoff()
       .unwrap_or(MAX_BACKOFF);
-    tracing::error!(node_id = self.config.id, "reconnect: backoff: {:?}", delay);
+    tracing::error!(
+      node_id = self.config.id,
+      slot,
+      "reconnect: backoff: {:?}",
+      delay
+    );
     let addr = ctx.addr();
     ctx.spawn(async move {
       sleep(delay).await;
-      addr.send(Connect).await.ok();
+      addr.send(Connect { slot }).await.ok();
     });
   }
 
@@ -134,24 +172,29 @@ impl NodeConnActor {
     Ok(stream)
   }
 
-  async fn stream_worker(addr: Addr<Self>, mut rx: mpsc::Receiver<Frame>, mut stream: FloStream) {
-    let mut ping = PingStream::interval(Duration::from_secs(30), Duration::from_secs(10));
-    ping.start();
+  async fn stream_worker(
+    addr: Addr<Self>,
+    slot: usize,
+    generation: u64,
+    mut rx: mpsc::Receiver<Frame>,
+    mut stream: FloStream,
+  ) {
+    let mut keepalive = KeepAlive::new(KEEPALIVE);
 
     loop {
       tokio::select! {
-        Some(msg) = ping.next() => {
-          match msg {
-            PingMsg::Ping(frame) => {
+        event = keepalive.next() => {
+          match event {
+            KeepAliveEvent::Ping(frame) => {
               if let Err(err) = stream.send_frame(frame).await {
                 tracing::error!("send: {}", err);
-                addr.send(Disconnected).await.ok();
+                addr.send(Disconnected { slot, generation }).await.ok();
                 break;
               }
             },
-            PingMsg::Timeout => {
+            KeepAliveEvent::Timeout => {
               tracing::error!("ping timeout");
-              addr.send(Disconnected).await.ok();
+              addr.send(Disconnected { slot, generation }).await.ok();
               break;
             },
           }
@@ -159,17 +202,22 @@ impl NodeConnActor {
         Some(frame) = rx.recv() => {
           if let Err(err) = stream.send_frame(frame).await {
             tracing::error!("send: {}", err);
-            addr.send(Disconnected).await.ok();
+            addr.send(Disconnected { slot, generation }).await.ok();
             break;
           }
         }
         res = stream.recv_frame() => {
           match res {
             Ok(frame) => {
-              if frame.type_id == PacketTypeId::Pong {
-                ping.capture_pong(frame);
-                continue;
-              }
+              let frame = match keepalive.recv(frame) {
+                Incoming::Frame(frame) => frame,
+                Incoming::KeepAlive(reply) => {
+                  if let Some(frame) = reply {
+                    stream.send_frame(frame).await.ok();
+                  }
+                  continue;
+                }
+              };
 
               let handle_res = match addr.send(IncomingFrame(frame)).await {
                 Ok(res) => res,
@@ -179,13 +227,14 @@ impl NodeConnActor {
               };
               if let Err(err) = handle_res {
                 tracing::error!("handle frame: {}", err);
-                addr.send(Disconnected).await.ok();
+                crate::dashboard::record_error("node", format!("handle frame: {}", err));
+                addr.send(Disconnected { slot, generation }).await.ok();
                 break;
               }
             },
             Err(err) => {
               tracing::error!("recv: {}", err);
-              addr.send(Disconnected).await.ok();
+              addr.send(Disconnected { slot, generation }).await.ok();
               break;
             },
           }
@@ -195,7 +244,9 @@ impl NodeConnActor {
   }
 }
 
-struct Connect;
+struct Connect {
+  slot: usize,
+}
 
 impl Message for Connect {
   type Result = ();
@@ -203,46 +254,79 @@ impl Message for Connect {
 
 #[async_trait]
 impl Handler<Connect> for NodeConnActor {
-  async fn handle(&mut self, ctx: &mut Context<Self>, _: Connect) {
-    if self.status == NodeConnStatus::Connected {
-      tracing::warn!(node_id = self.config.id, "already connected");
+  async fn handle(&mut self, ctx: &mut Context<Self>, Connect { slot }: Connect) {
+    let node_id = self.config.id;
+    if self.slots[slot].status == NodeConnStatus::Connected {
+      tracing::warn!(node_id, slot, "already connected");
       return;
     }
 
     let (ip, port) = match parse_addr(&self.config.addr) {
       Ok(v) => v,
       Err(err) => {
-        self.status = NodeConnStatus::Error;
-        tracing::error!(node_id = self.config.id, "parse node address: {}", err);
+        self.slots[slot].status = NodeConnStatus::Error;
+        tracing::error!(node_id, "parse node address: {}", err);
         return;
       }
     };
-    let node_id = self.config.id;
     let secret = self.config.secret.clone();
-    let stream = match Self::connect(node_id, ip, port, &secret).await {
+    let addr = ctx.addr();
+    // the other connections keep working while this one connects
+    ctx.spawn(async move {
+      let result = Self::connect(node_id, ip, port, &secret).await;
+      addr.send(Connected { slot, result }).await.ok();
+    });
+  }
+}
+
+struct Connected {
+  slot: usize,
+  result: Result<FloStream, NodeConnectError>,
+}
+
+impl Message for Connected {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Connected> for NodeConnActor {
+  async fn handle(&mut self, ctx: &mut Context<Self>, Connected { slot, result }: Connected) {
+    let node_id = self.config.id;
+    let stream = match result {
       Ok(stream) => stream,
       Err(NodeConnectError::Retry(err)) => {
-        tracing::error!(node_id, "error: {}", err);
-        self.schedule_reconnect(ctx);
+        tracing::error!(node_id, slot, "error: {}", err);
+        self.schedule_reconnect(ctx, slot);
         return;
       }
       Err(NodeConnectError::Fatal(err)) => {
-        self.status = NodeConnStatus::Error;
-        tracing::error!(node_id, "fatal error: {}", err);
+        self.slots[slot].status = NodeConnStatus::Error;
+        tracing::error!(node_id, slot, "fatal error: {}", err);
         return;
       }
     };
+    self.conn_generation += 1;
+    let generation = self.conn_generation;
     let (tx, rx) = mpsc::channel(32);
     ctx.spawn(
-      Self::stream_worker(ctx.addr(), rx, stream)
-        .instrument(tracing::debug_span!("stream_worker", node_id)),
+      Self::stream_worker(ctx.addr(), slot, generation, rx, stream)
+        .instrument(tracing::debug_span!("stream_worker", node_id, slot)),
     );
-    self.request_actor = NodeRequestActor::new(tx).start().into();
-    self.reconnect_backoff.take();
+    let conn = &mut self.slots[slot];
+    conn.status = NodeConnStatus::Connected;
+    conn.generation = generation;
+    conn.reconnect_backoff.take();
+    self.frames.insert(slot, tx);
+    if self.request_actor.is_none() {
+      self.request_actor = NodeRequestActor::new(self.frames.clone()).start().into();
+    }
   }
 }
 
-struct Disconnected;
+struct Disconnected {
+  slot: usize,
+  generation: u64,
+}
 
 impl Message for Disconnected {
   type Result = ();
@@ -250,8 +334,56 @@ impl Message for Disconnected {
 
 #[async_trait]
 impl Handler<Disconnected> for NodeConnActor {
-  async fn handle(&mut self, ctx: &mut Context<Self>, _: Disconnected) {
-    self.schedule_reconnect(ctx);
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    Disconnected { slot, generation }: Disconnected,
+  ) {
+    let conn = &mut self.slots[slot];
+    if conn.status != NodeConnStatus::Connected || conn.generation != generation {
+      return;
+    }
+    conn.status = NodeConnStatus::Connecting;
+    self.frames.remove(slot);
+    self.schedule_reconnect(ctx, slot);
+    if !self.frames.is_empty() {
+      return;
+    }
+
+    // the last connection is down, pending requests are cancelled
+    self.request_actor.take();
+    self.load.take();
+    let generation = self.conn_generation;
+    let addr = ctx.addr();
+    ctx.spawn(async move {
+      sleep(FAILOVER_DELAY).await;
+      addr.send(CheckFailover { generation }).await.ok();
+    });
+  }
+}
+
+struct CheckFailover {
+  generation: u64,
+}
+
+impl Message for CheckFailover {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<CheckFailover> for NodeConnActor {
+  async fn handle(&mut self, _: &mut Context<Self>, CheckFailover { generation }: CheckFailover) {
+    // reconnected in time
+    if self.request_actor.is_some() || generation != self.conn_generation {
+      return;
+    }
+    let node_id = self.config.id;
+    tracing::warn!(node_id, "node down, failing over games");
+    self
+      .game_reg_addr
+      .notify(FailoverNodeGames { node_id })
+      .await
+      .ok();
   }
 }
 
@@ -274,6 +406,8 @@ impl Handler<IncomingFrame> for NodeConnActor {
       Response(RequestDone),
       GameSlotClientStatusUpdate(GameSlotClientStatusUpdate),
       GameStatusUpdate(Vec<GameStatusUpdate>),
+      GameAnomaly(GameAnomaly),
+      Status(NodeLoad),
     }
 
     let parsed = flo_net::try_flo_packet! {
@@ -322,6 +456,14 @@ impl Handler<IncomingFrame> for NodeConnActor {
             )
           )
         }
+        packet: PacketControllerGameStatusResponse => {
+          Parsed::Response(
+            RequestDone::new(
+              RequestId::GameStatus(packet.request_id),
+              Ok(Response::GameStatus(packet.status))
+            )
+          )
+        }
         packet: PacketClientUpdateSlotClientStatus => {
           Parsed::GameSlotClientStatusUpdate(S2ProtoUnpack::unpack(packet)?)
         }
@@ -331,6 +473,12 @@ impl Handler<IncomingFrame> for NodeConnActor {
         packet: PacketNodeGameStatusUpdateBulk => {
           Parsed::GameStatusUpdate(packet.games.into_iter().map(Into::into).collect())
         }
+        packet: PacketNodeGameAnomaly => {
+          Parsed::GameAnomaly(packet.into())
+        }
+        packet: PacketNodeStatus => {
+          Parsed::Status(NodeLoad::unpack(packet)?)
+        }
       }
     };
 
@@ -341,22 +489,46 @@ impl Handler<IncomingFrame> for NodeConnActor {
           actor.send(msg).await?;
         }
       }
-      Parsed::GameSlotClientStatusUpdate(message) => {
+      Parsed::GameSlotClientStatusUpdate(event) => {
         let addr = self.game_reg_addr.clone();
+        let node_id = self.config.id;
         ctx.spawn(async move {
-          let game_id = message.game_id;
-          if let Err(err) = addr.send_to(game_id, message).await {
+          let game_id = event.game_id;
+          if let Err(err) = addr
+            .send_to(game_id, NodeGameEvent { node_id, event })
+            .await
+          {
             tracing::warn!(game_id, "GameSlotClientStatusUpdate: {}", err);
           }
         });
       }
+      Parsed::GameAnomaly(event) => {
+        let addr = self.game_reg_addr.clone();
+        let node_id = self.config.id;
+        ctx.spawn(async move {
+          let game_id = event.game_id;
+          if let Err(err) = addr
+            .send_to(game_id, NodeGameEvent { node_id, event })
+            .await
+          {
+            tracing::warn!(game_id, "GameAnomaly: {}", err);
+          }
+        });
+      }
+      Parsed::Status(load) => {
+        self.load = Some((Instant::now(), load));
+      }
       Parsed::GameStatusUpdate(messages) => {
         let addr = self.game_reg_addr.clone();
+        let node_id = self.config.id;
         ctx.spawn(async move {
-          for message in messages {
-            let game_id = message.game_id;
-            let status = message.status;
-            if let Err(err) = addr.send_to(message.game_id, message).await {
+          for event in messages {
+            let game_id = event.game_id;
+            let status = event.status;
+            if let Err(err) = addr
+              .send_to(game_id, NodeGameEvent { node_id, event })
+              .await
+            {
               let status = format!("{:?}", status);
               tracing::warn!(
                 game_id,
@@ -384,6 +556,11 @@ impl Handler<IncomingFrame> for NodeConnActor {
 pub struct NodeCreateGame {
   pub game: Game,
   pub ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+  /// Relayed chat is filtered by the node
+  pub mute_list_map: BTreeMap<i32, Vec<i32>>,
+  pub rules: GameRules,
+  /// Retries with the same id get the player tokens of the first request
+  pub request_id: String,
 }
 
 impl Message for NodeCreateGame {
@@ -395,7 +572,13 @@ impl Handler<NodeCreateGame> for NodeConnActor {
   async fn handle(
     &mut self,
     ctx: &mut Context<Self>,
-    NodeCreateGame { game, ban_list_map }: NodeCreateGame,
+    NodeCreateGame {
+      game,
+      ban_list_map,
+      mute_list_map,
+      rules,
+      request_id,
+    }: NodeCreateGame,
   ) -> Result<FutureReply<Result<CreatedGameInfo>>> {
     let addr = self
       .request_actor
@@ -404,7 +587,12 @@ impl Handler<NodeCreateGame> for NodeConnActor {
       .ok_or_else(|| Error::NodeNotReady)?;
     let (tx, rx) = FutureReply::channel();
     ctx.spawn(async move {
-      tx.send(addr.create_game(game, ban_list_map).await).ok();
+      tx.send(
+        addr
+          .create_game(game, ban_list_map, mute_list_map, rules, request_id)
+          .await,
+      )
+      .ok();
     });
     Ok(rx)
   }
@@ -440,6 +628,92 @@ impl Handler<NodePlayerLeave> for NodeConnActor {
   }
 }
 
+pub struct NodeQueryGameStatus {
+  pub game_id: i32,
+}
+
+impl Message for NodeQueryGameStatus {
+  type Result = Result<FutureReply<Result<Option<GameLiveStatus>>>>;
+}
+
+#[async_trait]
+impl Handler<NodeQueryGameStatus> for NodeConnActor {
+  async fn handle(
+    &mut self,
+    ctx: &mut Context<Self>,
+    NodeQueryGameStatus { game_id }: NodeQueryGameStatus,
+  ) -> Result<FutureReply<Result<Option<GameLiveStatus>>>> {
+    let addr = self
+      .request_actor
+      .as_ref()
+      .map(|v| v.addr())
+      .ok_or_else(|| Error::NodeNotReady)?;
+    let (tx, rx) = FutureReply::channel();
+    ctx.spawn(async move {
+      tx.send(addr.query_game_status(game_id).await).ok();
+    });
+    Ok(rx)
+  }
+}
+
+pub struct NodeSetPacketCapture {
+  pub enabled: bool,
+}
+
+impl Message for NodeSetPacketCapture {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<NodeSetPacketCapture> for NodeConnActor {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    NodeSetPacketCapture { enabled }: NodeSetPacketCapture,
+  ) -> Result<()> {
+    if self.frames.is_empty() {
+      return Err(Error::NodeNotReady);
+    }
+    let frame = PacketControllerSetPacketCapture { enabled }.encode_as_frame()?;
+    self
+      .frames
+      .send(frame)
+      .map_err(|_| Error::NodeRequestCancelled)?;
+    Ok(())
+  }
+}
+
+pub struct GetNodeReady;
+
+impl Message for GetNodeReady {
+  type Result = bool;
+}
+
+#[async_trait]
+impl Handler<GetNodeReady> for NodeConnActor {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetNodeReady) -> bool {
+    self.request_actor.is_some()
+  }
+}
+
+/// Returns the last load report, reports older than `NODE_LOAD_MAX_AGE` are ignored
+pub struct GetNodeLoad;
+
+impl Message for GetNodeLoad {
+  type Result = Option<NodeLoad>;
+}
+
+#[async_trait]
+impl Handler<GetNodeLoad> for NodeConnActor {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetNodeLoad) -> Option<NodeLoad> {
+    self
+      .load
+      .as_ref()
+      .filter(|(t, _)| t.elapsed() < NODE_LOAD_MAX_AGE)
+      .map(|(_, load)| load.clone())
+  }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum NodeConnStatus {
   Connecting,
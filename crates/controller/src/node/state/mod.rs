@@ -1,17 +1,19 @@
 pub mod conn;
+pub mod pool;
 pub mod request;
 
 use crate::db::ExecutorRef;
 use crate::error::*;
 use crate::game::state::GameRegistry;
-use crate::node::{Node, NodeConnConfig};
+use crate::node::{Node, NodeConnConfig, NodeStatus};
 use crate::player::state::sender::PlayerRegistryHandle;
 use crate::state::{Data, GetActorEntry, Reload};
 use arc_swap::ArcSwap;
-use conn::NodeConnActor;
+use conn::{GetNodeLoad, GetNodeReady, NodeConnActor, NodeSetPacketCapture};
 use flo_state::{
   async_trait, Actor, Addr, Context, Deferred, Handler, Message, Owner, RegistryRef, Service,
 };
+use flo_types::ping::PingStats;
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
@@ -92,7 +94,6 @@ impl Handler<Reload> for NodeRegistry {
   async fn handle(&mut self, _: &mut Context<Self>, _: Reload) -> Result<()> {
     use flo_net::packet::FloPacket;
     use flo_net::proto::flo_connect::{PacketAddNode, PacketRemoveNode};
-    use s2_grpc_utils::S2ProtoPack;
 
     let nodes = self.load_snapshot().await?;
 
@@ -118,7 +119,7 @@ impl Handler<Reload> for NodeRegistry {
         );
         broadcast_frames.push(
           PacketAddNode {
-            node: node.clone().pack()?,
+            node: node.clone().pack_for_client()?,
           }
           .encode_as_frame()?,
         );
@@ -150,3 +151,163 @@ impl Handler<ListNode> for NodeRegistry {
     Vec::<_>::clone(&self.nodes_snapshot.load())
   }
 }
+
+pub struct ListNodeStatus;
+
+impl Message for ListNodeStatus {
+  type Result = Vec<NodeStatus>;
+}
+
+#[async_trait]
+impl Handler<ListNodeStatus> for NodeRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, _: ListNodeStatus) -> Vec<NodeStatus> {
+    let nodes = self.nodes_snapshot.load();
+    let mut list = Vec::with_capacity(nodes.len());
+    for node in nodes.iter() {
+      let (ready, load) = if let Some(owner) = self.map.get(&node.id) {
+        (
+          owner.send(GetNodeReady).await.unwrap_or_default(),
+          owner.send(GetNodeLoad).await.unwrap_or_default(),
+        )
+      } else {
+        (false, None)
+      };
+      list.push(NodeStatus {
+        node: node.clone().into(),
+        ready,
+        load,
+      });
+    }
+    list
+  }
+}
+
+/// Toggles frame capture on every connected node.
+/// Returns the number of nodes that received the command.
+pub struct SetPacketCapture {
+  pub enabled: bool,
+}
+
+impl Message for SetPacketCapture {
+  type Result = usize;
+}
+
+#[async_trait]
+impl Handler<SetPacketCapture> for NodeRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    SetPacketCapture { enabled }: SetPacketCapture,
+  ) -> usize {
+    let mut n = 0;
+    for (node_id, owner) in &self.map {
+      match owner.send(NodeSetPacketCapture { enabled }).await {
+        Ok(Ok(())) => n += 1,
+        Ok(Err(err)) => tracing::warn!(node_id, "set packet capture: {}", err),
+        Err(err) => tracing::warn!(node_id, "set packet capture: {}", err),
+      }
+    }
+    n
+  }
+}
+
+/// Picks a ready node that still has capacity.
+/// Prefers the region of the node with the lowest ping in `ping_map`, then the least loaded node.
+/// The load of a node is its share of `max_games` or its reported load, whichever is higher.
+/// Nodes reporting a load of 1 or more are skipped.
+pub struct SelectNodeForGame {
+  pub ping_map: BTreeMap<i32, PingStats>,
+  /// Overrides the region of the node with the lowest ping
+  pub region: Option<String>,
+  /// Used if `ping_map` has no results
+  pub fallback_region: Option<String>,
+  /// Nodes that rejected the game as full
+  pub exclude: Vec<i32>,
+}
+
+impl Message for SelectNodeForGame {
+  type Result = Result<Node>;
+}
+
+#[async_trait]
+impl Handler<SelectNodeForGame> for NodeRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    SelectNodeForGame {
+      ping_map,
+      region,
+      fallback_region,
+      exclude,
+    }: SelectNodeForGame,
+  ) -> Result<Node> {
+    let nodes = self.nodes_snapshot.load_full();
+
+    let mut candidates = Vec::with_capacity(nodes.len());
+    let mut loads = BTreeMap::new();
+    for node in nodes.iter().filter(|node| !exclude.contains(&node.id)) {
+      let owner = if let Some(owner) = self.map.get(&node.id) {
+        owner
+      } else {
+        continue;
+      };
+      if owner.send(GetNodeReady).await.unwrap_or_default() {
+        candidates.push(node);
+        if let Some(load) = owner.send(GetNodeLoad).await.unwrap_or_default() {
+          loads.insert(node.id, load.load_factor());
+        }
+      }
+    }
+
+    let ids: Vec<i32> = candidates.iter().map(|node| node.id).collect();
+    let counts = self
+      .db
+      .exec(move |conn| {
+        ids
+          .into_iter()
+          .map(|id| Ok((id, crate::node::db::count_active_games(conn, id)?)))
+          .collect::<Result<BTreeMap<i32, i64>>>()
+      })
+      .await?;
+
+    // (node, load factor)
+    let candidates: Vec<(&Node, f64)> = candidates
+      .into_iter()
+      .filter_map(|node| {
+        let count = counts.get(&node.id).cloned().unwrap_or_default();
+        let reported = loads.get(&node.id).cloned().unwrap_or_default();
+        match node.max_games {
+          Some(max) if count >= max as i64 => None,
+          // a saturated node slows down all of its games
+          _ if reported >= 1. => None,
+          Some(max) => Some((node, (count as f64 / max as f64).max(reported))),
+          None => Some((node, reported)),
+        }
+      })
+      .collect();
+
+    let preferred_region = region.or_else(|| {
+      ping_map
+        .iter()
+        .filter_map(|(node_id, stats)| Some((*node_id, stats.avg.or(stats.current)?)))
+        .min_by_key(|(_, ping)| *ping)
+        .and_then(|(node_id, _)| nodes.iter().find(|node| node.id == node_id))
+        .map(|node| node.region.clone())
+        .or(fallback_region)
+    });
+
+    preferred_region
+      .and_then(|region| least_loaded(candidates.iter().filter(|(node, _)| node.region == region)))
+      .or_else(|| least_loaded(candidates.iter()))
+      .ok_or_else(|| Error::NoNodeAvailable)
+  }
+}
+
+fn least_loaded<'a, I>(iter: I) -> Option<Node>
+where
+  I: Iterator<Item = &'a (&'a Node, f64)>,
+{
+  iter
+    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+    .map(|(node, _)| Node::clone(node))
+}
@@ -0,0 +1,92 @@
+use flo_net::packet::Frame;
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Senders of the established connections to a node, keyed by connection slot
+#[derive(Debug, Clone, Default)]
+pub struct FramePool {
+  senders: Arc<RwLock<BTreeMap<usize, mpsc::Sender<Frame>>>>,
+  next: Arc<AtomicUsize>,
+}
+
+impl FramePool {
+  pub fn insert(&self, slot: usize, tx: mpsc::Sender<Frame>) {
+    self.senders.write().insert(slot, tx);
+  }
+
+  pub fn remove(&self, slot: usize) {
+    self.senders.write().remove(&slot);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.senders.read().is_empty()
+  }
+
+  /// Round robin, connections with a full send buffer are skipped so a stalled
+  /// connection only holds the frames already queued on it.
+  /// Never waits: `Full` if every buffer is full, `Closed` if no connection is open.
+  pub fn send(&self, frame: Frame) -> Result<(), TrySendError<Frame>> {
+    let senders = self.senders.read();
+    let senders: Vec<_> = senders.values().collect();
+    if senders.is_empty() {
+      return Err(TrySendError::Closed(frame));
+    }
+    let start = self.next.fetch_add(1, Ordering::Relaxed);
+    let mut frame = frame;
+    let mut full = false;
+    for i in 0..senders.len() {
+      match senders[(start + i) % senders.len()].try_send(frame) {
+        Ok(()) => return Ok(()),
+        Err(TrySendError::Full(v)) => {
+          full = true;
+          frame = v;
+        }
+        Err(TrySendError::Closed(v)) => frame = v,
+      }
+    }
+    if full {
+      Err(TrySendError::Full(frame))
+    } else {
+      Err(TrySendError::Closed(frame))
+    }
+  }
+}
+
+#[test]
+fn test_frame_pool() {
+  use flo_net::packet::PacketTypeId;
+
+  let frame = || Frame::new_empty(PacketTypeId::Ping);
+  let pool = FramePool::default();
+  assert!(matches!(pool.send(frame()), Err(TrySendError::Closed(_))));
+
+  let (tx0, mut rx0) = mpsc::channel(1);
+  let (tx1, mut rx1) = mpsc::channel(1);
+  pool.insert(0, tx0);
+  pool.insert(1, tx1);
+
+  // the first connection stalls with one frame queued, the others go to the second one
+  pool.send(frame()).unwrap();
+  for _ in 0..3 {
+    pool.send(frame()).unwrap();
+    assert!(rx1.try_recv().is_ok());
+  }
+
+  // both stall
+  pool.send(frame()).unwrap();
+  assert!(matches!(pool.send(frame()), Err(TrySendError::Full(_))));
+  assert!(rx1.try_recv().is_ok());
+
+  assert!(rx0.try_recv().is_ok());
+  assert!(rx0.try_recv().is_err());
+
+  pool.remove(1);
+  pool.send(frame()).unwrap();
+  assert!(rx0.try_recv().is_ok());
+
+  drop(rx0);
+  assert!(matches!(pool.send(frame()), Err(TrySendError::Closed(_))));
+}
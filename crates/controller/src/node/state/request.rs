@@ -1,8 +1,10 @@
+use super::pool::FramePool;
 use crate::error::*;
-use crate::game::{Game, SlotClientStatus, SlotStatus};
+use crate::game::{Game, GameRules, SlotClientStatus, SlotStatus};
 use crate::node::PlayerToken;
 use crate::player::PlayerBanType;
 use flo_net::packet::*;
+use flo_net::proto::flo_common::GameLiveStatus;
 use flo_net::proto::flo_node::*;
 use flo_state::{async_trait, Actor, Addr, Context, Handler, Message};
 use futures::FutureExt;
@@ -11,27 +13,64 @@ use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::oneshot;
 use tokio::sync::Notify;
-use tokio::sync::{mpsc, oneshot};
 use tokio::time::sleep;
 use tracing_futures::Instrument;
 
-const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Timeout and retries of a request.
+///
+/// A retry sends the same frame with the same `RequestId`, so only idempotent requests are
+/// retried: create game carries the `request_id` idempotency key and the node replies with the
+/// tokens of the first request, a repeated player leave is accepted, and status queries are reads.
+/// A late response to an earlier attempt completes the retry.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestPolicy {
+  /// Of each attempt
+  pub timeout: Duration,
+  /// Including the first one
+  pub attempts: u32,
+  pub retry_delay: Duration,
+}
+
+// the node may have created the game, the retry gets its player tokens
+const CREATE_GAME_POLICY: RequestPolicy = RequestPolicy {
+  timeout: Duration::from_secs(5),
+  attempts: 2,
+  retry_delay: Duration::from_secs(1),
+};
+
+const PLAYER_LEAVE_POLICY: RequestPolicy = RequestPolicy {
+  timeout: Duration::from_secs(5),
+  attempts: 3,
+  retry_delay: Duration::from_secs(1),
+};
+
+const GAME_STATUS_POLICY: RequestPolicy = RequestPolicy {
+  timeout: Duration::from_secs(3),
+  attempts: 2,
+  retry_delay: Duration::from_millis(500),
+};
+
+/// Correlates game status queries with node responses
+static NEXT_GAME_STATUS_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
 
 pub struct NodeRequestActor {
-  frame_tx: mpsc::Sender<Frame>,
+  frames: FramePool,
   pending_requests: HashMap<RequestId, PendingRequest>,
 }
 
 impl Actor for NodeRequestActor {}
 
 impl NodeRequestActor {
-  pub fn new(frame_tx: mpsc::Sender<Frame>) -> Self {
+  pub fn new(frames: FramePool) -> Self {
     Self {
-      frame_tx,
+      frames,
       pending_requests: HashMap::new(),
     }
   }
@@ -54,12 +93,14 @@ impl Drop for PendingRequest {
 pub enum RequestId {
   CreateGame(i32),
   PlayerLeave(PlayerLeaveRequestId),
+  GameStatus(u64),
 }
 
 #[derive(Debug)]
 pub enum Response {
   GameCreated(CreatedGameInfo),
   PlayerLeave(PlayerLeaveResponse),
+  GameStatus(Option<GameLiveStatus>),
 }
 
 #[derive(Debug, S2ProtoUnpack)]
@@ -101,6 +142,7 @@ pub enum PlayerLeaveResponse {
 struct Request {
   id: RequestId,
   frame: Frame,
+  timeout: Duration,
 }
 
 impl Message for Request {
@@ -112,7 +154,7 @@ impl Handler<Request> for NodeRequestActor {
   async fn handle(
     &mut self,
     ctx: &mut Context<Self>,
-    Request { id, frame }: Request,
+    Request { id, frame, timeout }: Request,
   ) -> Result<PendingResponse> {
     if self.pending_requests.contains_key(&id) {
       return Err(Error::NodeRequestProcessing);
@@ -129,33 +171,26 @@ impl Handler<Request> for NodeRequestActor {
     ctx
       .spawn({
         let addr = ctx.addr();
-        let frame_tx = self.frame_tx.clone();
+        let frames = self.frames.clone();
         async move {
-          let timeout = sleep(REQUEST_TIMEOUT);
-          let send = frame_tx.send(frame);
-          tracing::debug!("request sent: {:?}", id);
-
+          let timeout = sleep(timeout);
           tokio::pin!(timeout);
-          tokio::pin!(send);
 
-          // send frame
-          let exit = tokio::select! {
-            _ = &mut timeout => {
+          // send frame, full send buffers are retried like a timeout
+          let exit = match frames.send(frame) {
+            Ok(()) => {
+              tracing::debug!("request sent: {:?}", id);
+              false
+            }
+            Err(TrySendError::Full(_)) => {
               request_callback(&addr, id, Err(Error::NodeRequestTimeout)).await;
-              tracing::debug!("action timeout: {:?}", id);
+              tracing::debug!("action buffers full: {:?}", id);
               true
             }
-            res = &mut send => {
-              match res {
-                Ok(_) => {
-                  false
-                }
-                Err(_) => {
-                  request_callback(&addr, id, Err(Error::NodeRequestCancelled)).await;
-                  tracing::debug!("action error: {:?}", id);
-                  true
-                }
-              }
+            Err(TrySendError::Closed(_)) => {
+              request_callback(&addr, id, Err(Error::NodeRequestCancelled)).await;
+              tracing::debug!("action error: {:?}", id);
+              true
             }
           };
 
@@ -175,6 +210,31 @@ impl Handler<Request> for NodeRequestActor {
   }
 }
 
+/// Sends the request and retries it on `NodeRequestTimeout`, see `RequestPolicy`
+async fn send_request(
+  addr: &Addr<NodeRequestActor>,
+  id: RequestId,
+  frame: Frame,
+  policy: RequestPolicy,
+) -> Result<Response> {
+  let mut attempt = 1;
+  loop {
+    let req = Request {
+      id,
+      frame: frame.clone(),
+      timeout: policy.timeout,
+    };
+    match addr.send(req).await??.await {
+      Err(Error::NodeRequestTimeout) if attempt < policy.attempts => {
+        tracing::warn!(attempt, "node request timeout, retrying: {:?}", id);
+        attempt += 1;
+        sleep(policy.retry_delay).await;
+      }
+      res => return res,
+    }
+  }
+}
+
 async fn request_callback(addr: &Addr<NodeRequestActor>, id: RequestId, result: Result<Response>) {
   if addr.notify(RequestDone { id, result }).await.is_err() {
     tracing::debug!("RequestDone: cancelled: request_id = {:?}", id);
@@ -231,8 +291,12 @@ pub trait NodeRequestExt {
     &self,
     game: Game,
     ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+    mute_list_map: BTreeMap<i32, Vec<i32>>,
+    rules: GameRules,
+    request_id: String,
   ) -> Result<CreatedGameInfo>;
   async fn player_force_leave(&self, game_id: i32, player_id: i32) -> Result<PlayerLeaveResponse>;
+  async fn query_game_status(&self, game_id: i32) -> Result<Option<GameLiveStatus>>;
 }
 
 #[async_trait]
@@ -241,10 +305,14 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
     &self,
     game: Game,
     mut ban_list_map: BTreeMap<i32, Vec<PlayerBanType>>,
+    mut mute_list_map: BTreeMap<i32, Vec<i32>>,
+    rules: GameRules,
+    request_id: String,
   ) -> Result<CreatedGameInfo> {
     let game_id = game.id;
 
     let req_id = RequestId::CreateGame(game_id);
+    let span = tracing::info_span!("create_game", game_id);
 
     let mut slots = Vec::with_capacity(game.slots.len());
     for (i, slot) in game.slots.iter().enumerate() {
@@ -262,6 +330,7 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
               .remove(&player.id)
               .map(|items| items.into_iter().map(|v| v as i32).collect())
               .unwrap_or_default(),
+            mute_list: mute_list_map.remove(&player.id).unwrap_or_default(),
           }),
           settings: Some(slot.settings.clone().pack()?),
           client_status: Default::default(),
@@ -279,22 +348,26 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
         }),
         slots,
         status: Default::default(),
+        rules: Some(rules.pack()?),
+        chat_filter: Some(crate::text_filter::chat_filter()),
       }),
+      trace_context: span.in_scope(flo_net::trace::current_context),
+      request_id,
     };
 
-    let req = Request {
-      id: req_id,
-      frame: pkt.encode_as_frame()?,
-    };
+    let frame = pkt.encode_as_frame()?;
 
-    let res = self.send(req).await??;
-    match res.await? {
-      Response::GameCreated(game_info) => Ok(game_info),
-      other => {
-        tracing::error!(game_id, "unexpected node response: {:?}", other);
-        Err(Error::NodeResponseUnexpected)
+    async move {
+      match send_request(self, req_id, frame, CREATE_GAME_POLICY).await? {
+        Response::GameCreated(game_info) => Ok(game_info),
+        other => {
+          tracing::error!(game_id, "unexpected node response: {:?}", other);
+          Err(Error::NodeResponseUnexpected)
+        }
       }
     }
+    .instrument(span)
+    .await
   }
 
   async fn player_force_leave(&self, game_id: i32, player_id: i32) -> Result<PlayerLeaveResponse> {
@@ -308,14 +381,28 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
 
     pkt.set_status(flo_net::proto::flo_common::SlotClientStatus::Left);
 
-    let req = Request {
-      id: req_id,
-      frame: pkt.encode_as_frame()?,
+    let frame = pkt.encode_as_frame()?;
+    match send_request(self, req_id, frame, PLAYER_LEAVE_POLICY).await? {
+      Response::PlayerLeave(res) => Ok(res),
+      other => {
+        tracing::error!(game_id, "unexpected node response: {:?}", other);
+        Err(Error::NodeResponseUnexpected)
+      }
+    }
+  }
+
+  async fn query_game_status(&self, game_id: i32) -> Result<Option<GameLiveStatus>> {
+    let request_id = NEXT_GAME_STATUS_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    let req_id = RequestId::GameStatus(request_id);
+
+    let pkt = PacketControllerGameStatusRequest {
+      request_id,
+      game_id,
     };
 
-    let res = self.send(req).await??;
-    match res.await? {
-      Response::PlayerLeave(res) => Ok(res),
+    let frame = pkt.encode_as_frame()?;
+    match send_request(self, req_id, frame, GAME_STATUS_POLICY).await? {
+      Response::GameStatus(res) => Ok(res),
       other => {
         tracing::error!(game_id, "unexpected node response: {:?}", other);
         Err(Error::NodeResponseUnexpected)
@@ -323,3 +410,47 @@ impl NodeRequestExt for Addr<NodeRequestActor> {
     }
   }
 }
+
+#[tokio::test]
+async fn test_request_retry() {
+  let (frame_tx, mut frame_rx) = tokio::sync::mpsc::channel(2);
+  let frames = FramePool::default();
+  frames.insert(0, frame_tx);
+  let actor = NodeRequestActor::new(frames).start();
+  let addr = actor.addr();
+  let id = RequestId::GameStatus(1);
+  let frame = PacketControllerGameStatusRequest {
+    request_id: 1,
+    game_id: 1,
+  }
+  .encode_as_frame()
+  .unwrap();
+  let policy = RequestPolicy {
+    timeout: Duration::from_millis(50),
+    attempts: 2,
+    retry_delay: Duration::from_millis(10),
+  };
+
+  // the first attempt times out, the answer to the retry completes the request
+  let responder = tokio::spawn({
+    let addr = addr.clone();
+    async move {
+      frame_rx.recv().await.unwrap();
+      frame_rx.recv().await.unwrap();
+      addr
+        .notify(RequestDone::new(id, Ok(Response::GameStatus(None))))
+        .await
+        .unwrap();
+      frame_rx
+    }
+  });
+  let res = send_request(&addr, id, frame.clone(), policy).await;
+  assert!(matches!(res, Ok(Response::GameStatus(None))));
+
+  // no answer
+  let mut frame_rx = responder.await.unwrap();
+  let res = send_request(&addr, id, frame, policy).await;
+  assert!(matches!(res, Err(Error::NodeRequestTimeout)));
+  assert!(frame_rx.recv().await.is_some());
+  assert!(frame_rx.recv().await.is_some());
+}
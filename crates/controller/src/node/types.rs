@@ -2,6 +2,7 @@ use chrono::{DateTime, Utc};
 use s2_grpc_utils::{S2ProtoPack, S2ProtoUnpack};
 use serde::{Deserialize, Serialize};
 
+use crate::error::Result;
 use crate::schema::node;
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Clone, S2ProtoPack)]
@@ -20,6 +21,25 @@ pub struct Node {
   pub country_id: String,
   #[s2_grpc(skip_pack)]
   pub disabled: bool,
+  #[s2_grpc(skip_pack)]
+  pub region: String,
+  /// Maximum number of concurrent games, `None` means unlimited
+  #[s2_grpc(skip_pack)]
+  pub max_games: Option<i32>,
+}
+
+impl Node {
+  /// Packs the node with a token that allows clients to use its echo service
+  pub fn pack_for_client(self) -> Result<flo_net::proto::flo_connect::Node> {
+    let token = flo_net::echo::PingToken::issue(&self.secret);
+    let mut node: flo_net::proto::flo_connect::Node = self.pack()?;
+    node.ping_token = token.as_bytes().to_vec();
+    Ok(node)
+  }
+
+  pub fn pack_list_for_client(nodes: Vec<Node>) -> Result<Vec<flo_net::proto::flo_connect::Node>> {
+    nodes.into_iter().map(Node::pack_for_client).collect()
+  }
 }
 
 pub type NodeRefColumns = (
@@ -62,6 +82,49 @@ impl From<Node> for NodeRef {
   }
 }
 
+#[derive(Debug, Serialize, Clone)]
+pub struct NodeStatus {
+  pub node: NodeRef,
+  pub ready: bool,
+  /// The last load report, if the node sent one recently
+  pub load: Option<NodeLoad>,
+}
+
+/// Load reported by the node
+#[derive(Debug, Serialize, Clone, S2ProtoUnpack)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::PacketNodeStatus))]
+pub struct NodeLoad {
+  pub load_average: f32,
+  pub memory_used_bytes: u64,
+  pub memory_total_bytes: u64,
+  pub open_games: u32,
+  pub relay_bytes_in_per_second: u64,
+  pub relay_bytes_out_per_second: u64,
+  pub max_tick_lag_ms: u32,
+  pub cpus: u32,
+}
+
+impl NodeLoad {
+  /// Games lag once a tick waits this long
+  const TICK_LAG_LIMIT_MS: u32 = 3000;
+
+  /// Usage of the most saturated resource, 1.0 means fully used
+  pub fn load_factor(&self) -> f64 {
+    let cpu = if self.cpus > 0 {
+      self.load_average as f64 / self.cpus as f64
+    } else {
+      0.
+    };
+    let memory = if self.memory_total_bytes > 0 {
+      self.memory_used_bytes as f64 / self.memory_total_bytes as f64
+    } else {
+      0.
+    };
+    let tick_lag = self.max_tick_lag_ms as f64 / Self::TICK_LAG_LIMIT_MS as f64;
+    cpu.max(memory).max(tick_lag)
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PlayerToken {
   pub player_id: i32,
@@ -113,3 +176,36 @@ impl<'a> From<&'a Node> for NodeConnConfig {
     }
   }
 }
+
+#[test]
+fn test_node_load_factor() {
+  let load = NodeLoad {
+    load_average: 1.,
+    memory_used_bytes: 1,
+    memory_total_bytes: 4,
+    open_games: 10,
+    relay_bytes_in_per_second: 0,
+    relay_bytes_out_per_second: 0,
+    max_tick_lag_ms: 0,
+    cpus: 2,
+  };
+  assert_eq!(load.load_factor(), 0.5);
+  assert_eq!(
+    NodeLoad {
+      max_tick_lag_ms: 3000,
+      ..load.clone()
+    }
+    .load_factor(),
+    1.
+  );
+  // nothing reported
+  assert_eq!(
+    NodeLoad {
+      cpus: 0,
+      memory_total_bytes: 0,
+      ..load
+    }
+    .load_factor(),
+    0.
+  );
+}
@@ -1,9 +1,12 @@
-use crate::db::DbConn;
+use crate::db::{AsyncConn, DbConn};
 use crate::error::*;
+use crate::game::GameStatus;
 use crate::player::{Player, PlayerBan, PlayerBanType, PlayerRef, PlayerSource, SourceState};
-use crate::schema::{player, player_ban, player_mute};
+use crate::schema::{game, game_used_slot, player, player_ban, player_mute};
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use s2_grpc_utils::S2ProtoEnum;
+use serde::Serialize;
 use serde_json::Value;
 use std::collections::{BTreeMap, HashMap};
 
@@ -28,6 +31,35 @@ pub fn get_ref(conn: &DbConn, id: i32) -> Result<PlayerRef> {
     .map_err(Into::into)
 }
 
+/// `get_ref` through the cache, not for reads inside a transaction
+pub fn get_ref_cached(conn: &DbConn, id: i32) -> Result<PlayerRef> {
+  crate::cache::PLAYERS.get_or_load(id, || get_ref(conn, id))
+}
+
+/// `get_ref_cached` on the async pool
+pub async fn get_ref_cached_async(conn: &AsyncConn, id: i32) -> Result<PlayerRef> {
+  if let Some(player) = crate::cache::PLAYERS.get(id) {
+    return Ok(player);
+  }
+  let stmt = conn
+    .prepare_cached("SELECT id, name, source, realm FROM player WHERE id = $1")
+    .await?;
+  let row = conn
+    .query_opt(&stmt, &[&id])
+    .await?
+    .ok_or_else(|| Error::PlayerNotFound)?;
+  let player = PlayerRef {
+    id: row.try_get(0)?,
+    name: row.try_get(1)?,
+    source: flo_net::proto::flo_connect::PlayerSource::from_i32(row.try_get(2)?)
+      .map(PlayerSource::unpack_enum)
+      .ok_or_else(|| Error::DbValueInvalid("player.source"))?,
+    realm: row.try_get(3)?,
+  };
+  crate::cache::PLAYERS.insert(id, player.clone());
+  Ok(player)
+}
+
 pub fn get_refs_by_ids(conn: &DbConn, ids: &[i32]) -> Result<Vec<PlayerRef>> {
   use player::dsl;
   player::table
@@ -90,18 +122,41 @@ pub fn upsert(conn: &DbConn, data: &UpsertPlayer) -> Result<Player> {
     return Err(Error::PlayerSourceIdInvalid);
   }
 
+  let name = flo_util::name::normalize(&data.name);
+  let data = &UpsertPlayer {
+    api_client_id: data.api_client_id,
+    name,
+    source: data.source,
+    source_id: data.source_id.clone(),
+    source_state: data.source_state.clone(),
+    realm: data.realm.clone(),
+  };
+
+  let name_folded = flo_util::name::fold(&data.name);
+
   diesel::insert_into(player::table)
-    .values(data)
+    .values((data, dsl::name_folded.eq(&name_folded)))
     .on_conflict((dsl::api_client_id, dsl::source, dsl::source_id))
     .do_update()
     .set(Update {
       name: &data.name,
+      name_folded: &name_folded,
       source_state: data.source_state.as_ref(),
       realm: data.realm.as_ref().map(AsRef::as_ref),
     })
     .get_result::<Row>(conn)
-    .map(Into::into)
-    .map_err(Into::into)
+    .map(|row| {
+      let player: Player = row.into();
+      crate::cache::PLAYERS.invalidate(player.id);
+      player
+    })
+    .map_err(|err| {
+      if crate::db::is_unique_violation(&err, "player_name_folded") {
+        Error::PlayerNameTaken
+      } else {
+        err.into()
+      }
+    })
 }
 
 pub fn add_mute(conn: &DbConn, player_id: i32, mute_player_id: i32) -> Result<()> {
@@ -137,6 +192,15 @@ pub fn remove_mute(conn: &DbConn, player_id: i32, mute_player_id: i32) -> Result
   Ok(())
 }
 
+pub fn get_mute_list(conn: &DbConn, player_id: i32) -> Result<Vec<i32>> {
+  player_mute::table
+    .select(player_mute::mute_player_id)
+    .filter(player_mute::player_id.eq(player_id))
+    .order(player_mute::mute_player_id)
+    .load(conn)
+    .map_err(Into::into)
+}
+
 pub fn get_mute_list_map(conn: &DbConn, player_ids: &[i32]) -> Result<BTreeMap<i32, Vec<i32>>> {
   use diesel::pg::expression::dsl::any;
   let pairs: Vec<(i32, i32)> = player_mute::table
@@ -271,6 +335,98 @@ pub fn check_ban_api_client_id(conn: &DbConn, api_client_id: i32, id: i32) -> Re
   Ok(())
 }
 
+/// Returns other players linked to the same external account through different api clients
+///
+/// Only battle.net account ids identify the same account across api clients, the source ids
+/// of other sources are chosen by each api client and only unique with the api client id.
+pub fn get_linked_player_ids(conn: &DbConn, player_id: i32) -> Result<Vec<i32>> {
+  use player::dsl;
+  let (source, source_id): (PlayerSource, String) = player::table
+    .find(player_id)
+    .select((dsl::source, dsl::source_id))
+    .first(conn)
+    .optional()?
+    .ok_or_else(|| Error::PlayerNotFound)?;
+  if source != PlayerSource::BNet || source_id.is_empty() {
+    return Ok(vec![]);
+  }
+  player::table
+    .filter(dsl::source.eq(source))
+    .filter(dsl::source_id.eq(source_id))
+    .filter(dsl::id.ne(player_id))
+    .select(dsl::id)
+    .load(conn)
+    .map_err(Into::into)
+}
+
+/// Returns ratings of rated players, unrated players are not included
+pub fn get_rating_map(conn: &DbConn, player_ids: &[i32]) -> Result<BTreeMap<i32, i32>> {
+  use player::dsl;
+  let pairs: Vec<(i32, Option<i32>)> = player::table
+    .filter(dsl::id.eq_any(player_ids))
+    .select((dsl::id, dsl::rating))
+    .load(conn)?;
+  Ok(
+    pairs
+      .into_iter()
+      .filter_map(|(id, rating)| rating.map(|rating| (id, rating)))
+      .collect(),
+  )
+}
+
+pub fn update_rating(conn: &DbConn, player_id: i32, rating: Option<i32>) -> Result<()> {
+  use player::dsl;
+  let n = diesel::update(player::table.find(player_id))
+    .set(dsl::rating.eq(rating))
+    .execute(conn)?;
+  if n != 1 {
+    return Err(Error::PlayerNotFound);
+  }
+  Ok(())
+}
+
+/// Players with the exact name, case-insensitive, at most `limit`
+pub fn find_refs_by_name(conn: &DbConn, name: &str, limit: i64) -> Result<Vec<PlayerRef>> {
+  use player::dsl;
+  player::table
+    .filter(dsl::name.ilike(crate::db::escape_like(name)))
+    .order(dsl::id)
+    .limit(limit)
+    .select((dsl::id, dsl::name, dsl::source, dsl::realm))
+    .load(conn)
+    .map_err(Into::into)
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerStats {
+  pub player_id: i32,
+  pub rating: Option<i32>,
+  /// Ended games the player has played
+  pub games: i64,
+  /// Of the games with action stats reported by the node
+  pub average_apm: Option<f64>,
+}
+
+pub fn get_stats(conn: &DbConn, player_id: i32) -> Result<PlayerStats> {
+  use diesel::dsl::sql;
+  use diesel::sql_types::{BigInt, Double, Nullable};
+  let rating = get_rating_map(conn, &[player_id])?.remove(&player_id);
+  let (games, average_apm) = game_used_slot::table
+    .inner_join(game::table)
+    .filter(game_used_slot::player_id.eq(player_id))
+    .filter(game::status.eq(GameStatus::Ended))
+    .select(sql::<(BigInt, Nullable<Double>)>(
+      "count(*), avg((game_used_slot.action_stats ->> 'apm')::float8)",
+    ))
+    .first::<(i64, Option<f64>)>(conn)?;
+  Ok(PlayerStats {
+    player_id,
+    rating,
+    games,
+    average_apm,
+  })
+}
+
 pub fn get_ban_list_map(
   conn: &DbConn,
   player_ids: &[i32],
@@ -327,6 +483,7 @@ struct Insert<'a> {
 #[changeset_options(treat_none_as_null = "true")]
 struct Update<'a> {
   name: &'a str,
+  name_folded: &'a str,
   source_state: Option<&'a Value>,
   realm: Option<&'a str>,
 }
@@ -342,6 +499,8 @@ pub struct Row {
   pub created_at: DateTime<Utc>,
   pub updated_at: DateTime<Utc>,
   pub api_client_id: i32,
+  pub rating: Option<i32>,
+  pub name_folded: Option<String>,
 }
 
 impl From<Row> for Player {
@@ -0,0 +1,464 @@
+//! Forwards packets to the players connected to other lobby instances over Redis pubsub.
+//!
+//! Uses the shared session store to find the instance holding the session of a player.
+//! Each instance subscribes to its own channel for the packets of its players,
+//! and to a shared channel for the game and presence topics, whose subscribers can be on
+//! any instance. The game list is kept by each instance and isn't forwarded.
+//!
+//! Game requests of a player are handled by the instance owning the game, see
+//! `SessionStore::get_game_owner`. Its replies reach the player as forwarded packets.
+//!
+//! Packets are forwarded by a single task in order, without blocking the `PlayerRegistry`.
+//! They are dropped if the task falls behind by `QUEUE_SIZE`, or if Redis is unreachable.
+
+use super::sender::PlayerFrames;
+use super::store::SessionStoreRef;
+use super::subscription::Topic;
+use super::PlayerRegistry;
+use crate::error::*;
+use flo_net::packet::{Frame, FramePayload, PacketTypeId};
+use flo_net::proto::flo_connect::SubscriptionTopic;
+use flo_state::{async_trait, Addr, Context, Handler, Message};
+use flo_util::binary::{BinDecode, BinEncode};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use prost::Message as ProstMessage;
+use redis::aio::ConnectionManager;
+use redis::Client;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+
+mod proto {
+  tonic::include_proto!("flo_lobby_bus");
+}
+
+use proto::bus_instance_message::Message as InstanceMessage;
+pub use proto::BusGameUpdate;
+use proto::{BusFrame, BusGameRequest, BusInstanceMessage, BusPlayerFrames, BusPublish};
+
+const CHANNEL_PREFIX: &str = "flo_lobby:bus";
+const QUEUE_SIZE: usize = 1024;
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+enum Outgoing {
+  Frames {
+    map: BTreeMap<i32, PlayerFrames>,
+    game_update: Option<BusGameUpdate>,
+  },
+  Publish(BusPublish),
+  GameRequest {
+    instance_id: String,
+    request: BusGameRequest,
+  },
+}
+
+/// A request of a player connected to another instance, for a game owned by this one
+pub type GameRequest = (i32, Frame);
+
+/// Handle of the forwarding task, kept by `PlayerRegistry`
+#[derive(Debug, Clone)]
+pub struct LobbyBus {
+  store: SessionStoreRef,
+  client: Client,
+  tx: Sender<Outgoing>,
+  requests_tx: Sender<GameRequest>,
+  requests_rx: Arc<Mutex<Option<Receiver<GameRequest>>>>,
+}
+
+impl LobbyBus {
+  /// `None` if the sessions aren't shared
+  pub async fn connect(store: SessionStoreRef) -> Result<Option<Self>> {
+    let url = if let Some(url) = crate::config::service_config().redis_url.clone() {
+      url
+    } else {
+      return Ok(None);
+    };
+    let client = Client::open(url.as_str())?;
+    let conn = client.get_tokio_connection_manager().await?;
+    let (tx, rx) = channel(QUEUE_SIZE);
+    let (requests_tx, requests_rx) = channel(QUEUE_SIZE);
+    tokio::spawn(forward_worker(store.clone(), conn, rx));
+    Ok(Some(Self {
+      store,
+      client,
+      tx,
+      requests_tx,
+      requests_rx: Arc::new(Mutex::new(Some(requests_rx))),
+    }))
+  }
+
+  fn instance_id(&self) -> &str {
+    self.store.instance_id()
+  }
+
+  /// The requests routed to this instance, can only be taken once
+  pub fn take_game_requests(&self) -> Option<Receiver<GameRequest>> {
+    self.requests_rx.lock().take()
+  }
+
+  /// Records this instance as the owner of a game it created
+  pub fn claim_game(&self, game_id: i32) {
+    let store = self.store.clone();
+    tokio::spawn(async move {
+      if let Err(err) = store.claim_game(game_id).await {
+        tracing::error!(game_id, "lobby bus: claim game: {}", err);
+      }
+    });
+  }
+
+  pub fn release_game(&self, game_id: i32) {
+    let store = self.store.clone();
+    tokio::spawn(async move {
+      if let Err(err) = store.release_game(game_id).await {
+        tracing::error!(game_id, "lobby bus: release game: {}", err);
+      }
+    });
+  }
+
+  /// Forwards the request to the instance owning the game.
+  /// Returns the frame back if it should be handled by this instance.
+  pub async fn route_game_request(
+    &self,
+    player_id: i32,
+    game_id: i32,
+    frame: Frame,
+  ) -> Option<Frame> {
+    let owner = match self.store.get_game_owner(game_id).await {
+      Ok(Some(owner)) if owner != self.instance_id() => owner,
+      Ok(_) => return Some(frame),
+      Err(err) => {
+        tracing::error!(game_id, "lobby bus: get game owner: {}", err);
+        return Some(frame);
+      }
+    };
+    self.push(Outgoing::GameRequest {
+      instance_id: owner,
+      request: BusGameRequest {
+        player_id,
+        frame: Some(pack_frame(frame)),
+      },
+    });
+    None
+  }
+
+  /// Forwards the frames of players not connected to this instance
+  pub fn forward(&self, map: BTreeMap<i32, PlayerFrames>, game_update: Option<BusGameUpdate>) {
+    if map.is_empty() {
+      return;
+    }
+    self.push(Outgoing::Frames { map, game_update });
+  }
+
+  /// Forwards a topic publish to the subscribers on other instances
+  pub fn publish(&self, topic: Topic, exclude: Vec<i32>, frames: PlayerFrames) {
+    if topic == Topic::GameList {
+      return;
+    }
+    let SubscriptionTopic { kind, id } = topic.pack();
+    self.push(Outgoing::Publish(BusPublish {
+      instance_id: self.instance_id().to_string(),
+      topic_kind: kind,
+      topic_id: id,
+      exclude,
+      frames: pack_frames(frames),
+    }));
+  }
+
+  fn push(&self, item: Outgoing) {
+    match self.tx.try_send(item) {
+      Ok(_) => {}
+      Err(TrySendError::Full(_)) => tracing::warn!("lobby bus queue full, packets dropped"),
+      Err(TrySendError::Closed(_)) => tracing::error!("lobby bus worker gone"),
+    }
+  }
+
+  /// Delivers the packets from other instances to `addr` until it stops
+  pub async fn subscribe(self, addr: Addr<PlayerRegistry>) {
+    loop {
+      if let Err(err) = self.receive(&addr).await {
+        tracing::error!("lobby bus subscription: {}", err);
+      }
+      if addr.send(BusPing).await.is_err() {
+        break;
+      }
+      tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+  }
+
+  async fn receive(&self, addr: &Addr<PlayerRegistry>) -> Result<()> {
+    let instance_channel = instance_channel(self.instance_id());
+    let publish_channel = publish_channel();
+    let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(&instance_channel).await?;
+    pubsub.subscribe(&publish_channel).await?;
+
+    let mut messages = pubsub.on_message();
+    while let Some(msg) = messages.next().await {
+      let item = if msg.get_channel_name() == instance_channel {
+        match BusInstanceMessage::decode(msg.get_payload_bytes()) {
+          Ok(BusInstanceMessage {
+            message: Some(InstanceMessage::PlayerFrames(frames)),
+          }) => Ok(Incoming::Frames(frames)),
+          Ok(BusInstanceMessage {
+            message: Some(InstanceMessage::GameRequest(request)),
+          }) => {
+            self.receive_game_request(request);
+            continue;
+          }
+          Ok(BusInstanceMessage { message: None }) => continue,
+          Err(err) => Err(err),
+        }
+      } else {
+        BusPublish::decode(msg.get_payload_bytes()).map(Incoming::Publish)
+      };
+      match item {
+        Ok(Incoming::Publish(ref publish)) if publish.instance_id == self.instance_id() => {}
+        Ok(item) => {
+          if addr.send(item).await.is_err() {
+            return Ok(());
+          }
+        }
+        Err(err) => tracing::error!("decode lobby bus message: {}", err),
+      }
+    }
+    Ok(())
+  }
+
+  fn receive_game_request(&self, BusGameRequest { player_id, frame }: BusGameRequest) {
+    let frame = if let Some(frame) = frame.and_then(unpack_frame) {
+      frame
+    } else {
+      return;
+    };
+    match self.requests_tx.try_send((player_id, frame)) {
+      Ok(_) => {}
+      Err(TrySendError::Full(_)) => {
+        tracing::warn!(player_id, "lobby bus request queue full, request dropped")
+      }
+      Err(TrySendError::Closed(_)) => {
+        tracing::warn!(
+          player_id,
+          "lobby bus requests are not handled, request dropped"
+        )
+      }
+    }
+  }
+}
+
+fn instance_channel(instance_id: &str) -> String {
+  format!("{}:instance:{}", CHANNEL_PREFIX, instance_id)
+}
+
+fn publish_channel() -> String {
+  format!("{}:publish", CHANNEL_PREFIX)
+}
+
+async fn forward_worker(
+  store: SessionStoreRef,
+  mut conn: ConnectionManager,
+  mut rx: Receiver<Outgoing>,
+) {
+  while let Some(item) = rx.recv().await {
+    if let Err(err) = forward_item(&store, &mut conn, item).await {
+      tracing::error!("lobby bus forward: {}", err);
+    }
+  }
+}
+
+async fn forward_item(
+  store: &SessionStoreRef,
+  conn: &mut ConnectionManager,
+  item: Outgoing,
+) -> Result<()> {
+  match item {
+    Outgoing::Frames { map, game_update } => {
+      let players: Vec<i32> = map.keys().cloned().collect();
+      let sessions = store.get_many(&players).await?;
+      for (player_id, frames) in map {
+        let record = match sessions.get(&player_id) {
+          Some(record) if record.instance_id != store.instance_id() => record,
+          _ => continue,
+        };
+        let packet = BusInstanceMessage {
+          message: Some(InstanceMessage::PlayerFrames(BusPlayerFrames {
+            player_id,
+            frames: pack_frames(frames),
+            game_update: game_update.clone(),
+          })),
+        };
+        redis::cmd("PUBLISH")
+          .arg(instance_channel(&record.instance_id))
+          .arg(packet.encode_to_vec())
+          .query_async::<_, ()>(conn)
+          .await?;
+      }
+    }
+    Outgoing::GameRequest {
+      instance_id,
+      request,
+    } => {
+      let packet = BusInstanceMessage {
+        message: Some(InstanceMessage::GameRequest(request)),
+      };
+      redis::cmd("PUBLISH")
+        .arg(instance_channel(&instance_id))
+        .arg(packet.encode_to_vec())
+        .query_async::<_, ()>(conn)
+        .await?;
+    }
+    Outgoing::Publish(publish) => {
+      redis::cmd("PUBLISH")
+        .arg(publish_channel())
+        .arg(publish.encode_to_vec())
+        .query_async::<_, ()>(conn)
+        .await?;
+    }
+  }
+  Ok(())
+}
+
+fn pack_frame(frame: Frame) -> BusFrame {
+  let mut type_id = vec![];
+  frame.type_id.encode(&mut type_id);
+  let payload = match frame.payload {
+    FramePayload::Bytes(bytes) => bytes.to_vec(),
+    // the metadata is kept in front of the payload, as on the wire
+    FramePayload::W3GS { metadata, payload } => {
+      let mut buf = vec![];
+      metadata.encode(&mut buf);
+      buf.extend_from_slice(&payload);
+      buf
+    }
+  };
+  BusFrame {
+    type_id: type_id[0] as u32,
+    payload,
+  }
+}
+
+fn pack_frames(frames: PlayerFrames) -> Vec<BusFrame> {
+  frames.into_iter().map(pack_frame).collect()
+}
+
+fn unpack_frame(frame: BusFrame) -> Option<Frame> {
+  let type_id = PacketTypeId::decode(&mut &[frame.type_id as u8][..]).ok()?;
+  Some(Frame::new_bytes(type_id, frame.payload.into()))
+}
+
+fn unpack_frames(frames: Vec<BusFrame>) -> PlayerFrames {
+  frames
+    .into_iter()
+    .filter_map(unpack_frame)
+    .collect::<Vec<_>>()
+    .into()
+}
+
+#[derive(Debug)]
+enum Incoming {
+  Frames(BusPlayerFrames),
+  Publish(BusPublish),
+}
+
+impl Message for Incoming {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Incoming> for PlayerRegistry {
+  async fn handle(&mut self, ctx: &mut Context<Self>, item: Incoming) {
+    match item {
+      Incoming::Frames(BusPlayerFrames {
+        player_id,
+        frames,
+        game_update,
+      }) => {
+        let state = if let Some(state) = self.registry.get_mut(&player_id) {
+          state
+        } else {
+          return;
+        };
+        if let Some(update) = game_update {
+          if update.left {
+            if state.game_id != Some(update.game_id) {
+              return;
+            }
+            state.game_id = None;
+          } else {
+            state.game_id = Some(update.game_id);
+          }
+          let game_id = state.game_id;
+          let store = self.store.clone();
+          ctx.spawn(async move {
+            if let Err(err) = store.set_game_id(player_id, game_id).await {
+              tracing::error!(player_id, "set session game: {}", err);
+            }
+          });
+        }
+        super::sender::send_to_player(&mut self.registry, player_id, unpack_frames(frames));
+      }
+      Incoming::Publish(BusPublish {
+        topic_kind,
+        topic_id,
+        exclude,
+        frames,
+        ..
+      }) => {
+        let topic = Topic::from_proto(&SubscriptionTopic {
+          kind: topic_kind,
+          id: topic_id,
+        });
+        let frames = unpack_frames(frames);
+        for player_id in self.subscriptions.subscribers(topic) {
+          if !exclude.contains(&player_id) {
+            super::sender::send_to_player(&mut self.registry, player_id, frames.clone());
+          }
+        }
+      }
+    }
+  }
+}
+
+/// `None` if the sessions aren't shared
+pub struct GetLobbyBus;
+
+impl Message for GetLobbyBus {
+  type Result = Option<LobbyBus>;
+}
+
+#[async_trait]
+impl Handler<GetLobbyBus> for PlayerRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetLobbyBus) -> Option<LobbyBus> {
+    self.bus.clone()
+  }
+}
+
+/// Checks that the registry is still running before subscribing again
+struct BusPing;
+
+impl Message for BusPing {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<BusPing> for PlayerRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, _: BusPing) {}
+}
+
+#[test]
+fn test_bus_frames() {
+  let frames: PlayerFrames = vec![
+    Frame::new(PacketTypeId::GameInfo, [1, 2, 3]),
+    Frame::new_empty(PacketTypeId::GameStarting),
+  ]
+  .into();
+  let frames: Vec<_> = unpack_frames(pack_frames(frames)).into_iter().collect();
+  assert_eq!(frames.len(), 2);
+  assert_eq!(frames[0].type_id, PacketTypeId::GameInfo);
+  assert!(
+    matches!(frames[0].payload, FramePayload::Bytes(ref bytes) if bytes.as_ref() == [1, 2, 3])
+  );
+  assert_eq!(frames[1].type_id, PacketTypeId::GameStarting);
+}
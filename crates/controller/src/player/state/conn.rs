@@ -1,45 +1,385 @@
 use super::PlayerRegistry;
 use crate::client::PlayerSender;
+use crate::error::*;
 use crate::player::state::PlayerState;
+use crate::policy::{SessionMode, SessionPolicy};
 use flo_state::{async_trait, Context, Handler, Message};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 
 pub struct Connect {
   pub game_id: Option<i32>,
   pub sender: PlayerSender,
+  /// Region of the closest node by GeoIP
+  pub suggested_region: Option<String>,
+  pub ip: Option<IpAddr>,
+  /// Applied if the player already has a session
+  pub policy: SessionPolicy,
 }
 
 impl Message for Connect {
-  type Result = ();
+  /// `SessionMode::Primary` if a read-only session had no session to join,
+  /// `Error::AlreadyConnected` if the policy rejected the session
+  type Result = Result<SessionMode>;
 }
 
 #[async_trait]
 impl Handler<Connect> for PlayerRegistry {
-  async fn handle(&mut self, _: &mut Context<Self>, message: Connect) {
+  async fn handle(&mut self, _: &mut Context<Self>, message: Connect) -> Result<SessionMode> {
+    self.connect(message).await
+  }
+}
+
+impl PlayerRegistry {
+  // checked and registered in the same message,
+  // two connections of the same player can't both see it offline
+  async fn connect(&mut self, message: Connect) -> Result<SessionMode> {
     let player_id = message.sender.player_id();
+    let online = self.registry.contains_key(&player_id)
+      || !self.get_remote_sessions(&[player_id]).await.is_empty();
+    if message.policy.session_mode(online)? == SessionMode::ReadOnly {
+      if let Some(state) = self.registry.get_mut(&player_id) {
+        state.read_only.push(message.sender);
+        return Ok(SessionMode::ReadOnly);
+      }
+    }
+
     let removed = self.registry.insert(
       player_id,
-      PlayerState::new(player_id, message.game_id, message.sender),
+      PlayerState::new(
+        player_id,
+        message.game_id,
+        message.sender,
+        message.suggested_region,
+        message.ip,
+      ),
     );
+    // a new session starts without subscriptions
+    self.subscriptions.remove_player(player_id);
+    self.put_session(player_id).await;
     if let Some(state) = removed {
       state.shutdown().await;
+    } else {
+      self.publish_presence(player_id, true);
+    }
+    Ok(SessionMode::Primary)
+  }
+
+  /// Only the current session takes the player offline,
+  /// replaced and read-only sessions are just dropped
+  async fn disconnect(&mut self, player_id: i32, session_id: u64) -> bool {
+    let state = match self.registry.get_mut(&player_id) {
+      Some(state) => state,
+      // the sender broke and was removed already
+      None => {
+        self.remove_session(player_id, session_id).await;
+        return false;
+      }
+    };
+    if state.sender.session_id() != session_id {
+      state
+        .read_only
+        .retain(|sender| sender.session_id() != session_id);
+      return false;
+    }
+
+    self.subscriptions.remove_player(player_id);
+    self.remove_session(player_id, session_id).await;
+    if let Some(state) = self.registry.remove(&player_id) {
+      state.shutdown().await;
+      self.publish_presence(player_id, false);
+    }
+    true
+  }
+}
+
+/// Connection IPs of online `players`
+pub struct GetPlayerIps {
+  pub players: Vec<i32>,
+}
+
+impl Message for GetPlayerIps {
+  type Result = BTreeMap<i32, IpAddr>;
+}
+
+#[async_trait]
+impl Handler<GetPlayerIps> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetPlayerIps { players }: GetPlayerIps,
+  ) -> BTreeMap<i32, IpAddr> {
+    let mut map: BTreeMap<i32, IpAddr> = players
+      .iter()
+      .filter_map(|id| {
+        let ip = self.registry.get(id)?.ip?;
+        Some((*id, ip))
+      })
+      .collect();
+    for (id, record) in self.get_remote_sessions(&players).await {
+      if let Some(ip) = record.ip {
+        map.insert(id, ip);
+      }
     }
+    map
+  }
+}
+
+pub struct GetPlayerSuggestedRegion {
+  pub player_id: i32,
+}
+
+impl Message for GetPlayerSuggestedRegion {
+  type Result = Option<String>;
+}
+
+#[async_trait]
+impl Handler<GetPlayerSuggestedRegion> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetPlayerSuggestedRegion { player_id }: GetPlayerSuggestedRegion,
+  ) -> Option<String> {
+    if let Some(state) = self.registry.get(&player_id) {
+      return state.suggested_region.clone();
+    }
+    self
+      .get_remote_sessions(&[player_id])
+      .await
+      .remove(&player_id)
+      .and_then(|record| record.suggested_region)
   }
 }
 
 pub struct Disconnect {
   pub player_id: i32,
+  /// `PlayerSender::session_id` of the closed session
+  pub session_id: u64,
 }
 
 impl Message for Disconnect {
-  type Result = ();
+  /// The player is offline now
+  type Result = bool;
 }
 
 #[async_trait]
 impl Handler<Disconnect> for PlayerRegistry {
-  async fn handle(&mut self, _: &mut Context<Self>, message: Disconnect) {
-    let player_id = message.player_id;
-    if let Some(state) = self.registry.remove(&player_id) {
-      state.shutdown().await;
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    Disconnect {
+      player_id,
+      session_id,
+    }: Disconnect,
+  ) -> bool {
+    self.disconnect(player_id, session_id).await
+  }
+}
+
+pub struct Kick {
+  pub player_id: i32,
+}
+
+impl Message for Kick {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<Kick> for PlayerRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, Kick { player_id }: Kick) -> Result<()> {
+    self.subscriptions.remove_player(player_id);
+    if let Some(mut state) = self.registry.remove(&player_id) {
+      self
+        .remove_session(player_id, state.sender.session_id())
+        .await;
+      state.sender.disconnect_kicked().await;
+      for mut sender in state.read_only {
+        sender.disconnect_kicked().await;
+      }
+      self.publish_presence(player_id, false);
+      Ok(())
+    } else {
+      Err(Error::PlayerNotFound)
     }
   }
 }
+
+/// Filters `players` down to the ones currently connected
+pub struct GetOnlinePlayers {
+  pub players: Vec<i32>,
+}
+
+impl Message for GetOnlinePlayers {
+  type Result = Vec<i32>;
+}
+
+#[async_trait]
+impl Handler<GetOnlinePlayers> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    GetOnlinePlayers { players }: GetOnlinePlayers,
+  ) -> Vec<i32> {
+    let remote = self.get_remote_sessions(&players).await;
+    players
+      .into_iter()
+      .filter(|id| self.registry.contains_key(id) || remote.contains_key(id))
+      .collect()
+  }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SenderQueueStats {
+  pub players: usize,
+  /// Frames waiting to be sent, summed over all players
+  pub queued: usize,
+  pub max_queued: usize,
+}
+
+pub struct GetSenderQueueStats;
+
+impl Message for GetSenderQueueStats {
+  type Result = SenderQueueStats;
+}
+
+#[async_trait]
+impl Handler<GetSenderQueueStats> for PlayerRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, _: GetSenderQueueStats) -> SenderQueueStats {
+    let mut stats = SenderQueueStats::default();
+    for state in self.registry.values() {
+      let queued = state.sender.queue_len();
+      stats.players += 1;
+      stats.queued += queued;
+      stats.max_queued = stats.max_queued.max(queued);
+    }
+    stats
+  }
+}
+
+#[cfg(test)]
+fn connect_message(sender: PlayerSender, policy: SessionPolicy) -> Connect {
+  Connect {
+    game_id: None,
+    sender,
+    suggested_region: None,
+    ip: None,
+    policy,
+  }
+}
+
+#[test]
+fn test_session_replace() {
+  use crate::client::PlayerSenderMessage;
+  use flo_net::proto::flo_connect::ClientDisconnectReason;
+
+  futures::executor::block_on(async {
+    let mut registry = PlayerRegistry::new();
+    let (first, mut first_receiver) = PlayerSender::new(1);
+    let (second, _second_receiver) = PlayerSender::new(1);
+    let (first_id, second_id) = (first.session_id(), second.session_id());
+
+    let mode = registry
+      .connect(connect_message(first, SessionPolicy::Replace))
+      .await
+      .unwrap();
+    assert_eq!(mode, SessionMode::Primary);
+    let mode = registry
+      .connect(connect_message(second, SessionPolicy::Replace))
+      .await
+      .unwrap();
+    assert_eq!(mode, SessionMode::Primary);
+    assert!(matches!(
+      first_receiver.recv().await,
+      Some(PlayerSenderMessage::Disconnect(
+        ClientDisconnectReason::Multi
+      ))
+    ));
+
+    // the replaced session closing doesn't take the new one down
+    assert!(!registry.disconnect(1, first_id).await);
+    assert_eq!(registry.registry[&1].sender.session_id(), second_id);
+    assert!(registry.disconnect(1, second_id).await);
+    assert!(registry.registry.is_empty());
+  });
+}
+
+#[test]
+fn test_session_read_only() {
+  use crate::client::PlayerSenderMessage;
+  use flo_net::packet::{Frame, PacketTypeId};
+  use flo_net::proto::flo_connect::ClientDisconnectReason;
+
+  futures::executor::block_on(async {
+    let mut registry = PlayerRegistry::new();
+    let (primary, mut primary_receiver) = PlayerSender::new(1);
+    let (read_only, mut read_only_receiver) = PlayerSender::new(1);
+    let primary_id = primary.session_id();
+
+    // nothing to join yet
+    let (early, _early_receiver) = PlayerSender::new(2);
+    let mode = registry
+      .connect(connect_message(early, SessionPolicy::ReadOnly))
+      .await
+      .unwrap();
+    assert_eq!(mode, SessionMode::Primary);
+
+    let mode = registry
+      .connect(connect_message(primary, SessionPolicy::ReadOnly))
+      .await
+      .unwrap();
+    assert_eq!(mode, SessionMode::Primary);
+    let mode = registry
+      .connect(connect_message(read_only, SessionPolicy::ReadOnly))
+      .await
+      .unwrap();
+    assert_eq!(mode, SessionMode::ReadOnly);
+
+    super::sender::send_to_player(
+      &mut registry.registry,
+      1,
+      Frame::new_empty(PacketTypeId::GameStarting).into(),
+    );
+    for receiver in [&mut primary_receiver, &mut read_only_receiver] {
+      assert!(matches!(
+        receiver.recv().await,
+        Some(PlayerSenderMessage::Frame(frame)) if frame.type_id == PacketTypeId::GameStarting
+      ));
+    }
+
+    assert!(registry.disconnect(1, primary_id).await);
+    assert!(matches!(
+      read_only_receiver.recv().await,
+      Some(PlayerSenderMessage::Disconnect(
+        ClientDisconnectReason::SessionEnded
+      ))
+    ));
+  });
+}
+
+#[test]
+fn test_session_reject() {
+  futures::executor::block_on(async {
+    let mut registry = PlayerRegistry::new();
+    let (first, _first_receiver) = PlayerSender::new(1);
+    let (second, _second_receiver) = PlayerSender::new(1);
+    let (first_id, second_id) = (first.session_id(), second.session_id());
+
+    registry
+      .connect(connect_message(first, SessionPolicy::Reject))
+      .await
+      .unwrap();
+    assert!(matches!(
+      registry
+        .connect(connect_message(second, SessionPolicy::Reject))
+        .await,
+      Err(Error::AlreadyConnected)
+    ));
+    assert_eq!(registry.registry[&1].sender.session_id(), first_id);
+    assert!(registry.registry[&1].read_only.is_empty());
+
+    // the rejected connection closing doesn't take the player offline
+    assert!(!registry.disconnect(1, second_id).await);
+    assert!(registry.disconnect(1, first_id).await);
+  });
+}
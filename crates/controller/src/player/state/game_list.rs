@@ -0,0 +1,277 @@
+use super::PlayerRegistry;
+use crate::error::Result;
+use crate::game::{Game, GameStatus};
+use crate::player::state::subscription::Topic;
+use flo_net::packet::{FloPacket, Frame};
+use flo_net::proto::flo_connect::{
+  GameListEntry, GameListEntryChange, PacketGameListSnapshot, PacketGameListUpdate,
+};
+use flo_state::{async_trait, Context, Handler, Message};
+use s2_grpc_utils::S2ProtoEnum;
+use std::collections::BTreeMap;
+
+/// Public games shown in the game browser
+#[derive(Debug, Default)]
+pub struct GameList {
+  seq: u64,
+  entries: BTreeMap<i32, GameListEntry>,
+  /// Games of the federated lobbies, replaced on each poll
+  remote_entries: Vec<GameListEntry>,
+}
+
+impl GameList {
+  pub fn contains(&self, game_id: i32) -> bool {
+    self.entries.contains_key(&game_id)
+  }
+
+  pub fn snapshot(&self) -> PacketGameListSnapshot {
+    PacketGameListSnapshot {
+      seq: self.seq,
+      games: self.entries.values().cloned().collect(),
+      remote_games: self.remote_entries.clone(),
+    }
+  }
+
+  /// Replaces the remote games, returns the snapshot to send if they changed.
+  /// The ids of remote games can clash with local ones so they are not sent as deltas.
+  pub fn set_remote(&mut self, entries: Vec<GameListEntry>) -> Option<PacketGameListSnapshot> {
+    if self.remote_entries == entries {
+      return None;
+    }
+    self.remote_entries = entries;
+    self.seq += 1;
+    Some(self.snapshot())
+  }
+
+  /// Adds a game or updates the fields of a listed game
+  pub fn upsert(&mut self, entry: GameListEntry) -> Option<PacketGameListUpdate> {
+    let update = match self.entries.get(&entry.id) {
+      Some(current) => {
+        let change = GameListEntryChange {
+          id: entry.id,
+          players: if current.players != entry.players {
+            Some(entry.players)
+          } else {
+            None
+          },
+          status: if current.status != entry.status {
+            Some(entry.status)
+          } else {
+            None
+          },
+        };
+        if change.players.is_none() && change.status.is_none() {
+          return None;
+        }
+        PacketGameListUpdate {
+          changed: vec![change],
+          ..Default::default()
+        }
+      }
+      None => PacketGameListUpdate {
+        added: vec![entry.clone()],
+        ..Default::default()
+      },
+    };
+    self.entries.insert(entry.id, entry);
+    Some(self.next(update))
+  }
+
+  /// Updates a listed game, games not listed are ignored
+  pub fn update(
+    &mut self,
+    game_id: i32,
+    players: Option<i32>,
+    status: Option<GameStatus>,
+  ) -> Option<PacketGameListUpdate> {
+    let mut entry = self.entries.get(&game_id)?.clone();
+    if let Some(players) = players {
+      entry.players = players;
+    }
+    if let Some(status) = status {
+      entry.status = status_to_proto(status);
+    }
+    self.upsert(entry)
+  }
+
+  pub fn remove(&mut self, game_id: i32) -> Option<PacketGameListUpdate> {
+    self.entries.remove(&game_id)?;
+    Some(self.next(PacketGameListUpdate {
+      removed: vec![game_id],
+      ..Default::default()
+    }))
+  }
+
+  fn next(&mut self, mut update: PacketGameListUpdate) -> PacketGameListUpdate {
+    self.seq += 1;
+    update.seq = self.seq;
+    update
+  }
+}
+
+pub fn entry_from_game(game: &Game) -> GameListEntry {
+  GameListEntry {
+    id: game.id,
+    name: game.name.clone(),
+    map_name: game.map.name.clone(),
+    players: game.get_player_ids().len() as i32,
+    max_players: game.max_players,
+    status: status_to_proto(game.status),
+    lobby_id: String::new(),
+  }
+}
+
+pub fn status_to_proto(status: GameStatus) -> i32 {
+  let status: flo_net::proto::flo_connect::GameStatus = status.into_proto_enum();
+  status.into()
+}
+
+#[derive(Debug)]
+pub enum GameListChange {
+  Upsert(GameListEntry),
+  Players { game_id: i32, players: i32 },
+  Status { game_id: i32, status: GameStatus },
+  Remove { game_id: i32 },
+  Remote(Vec<GameListEntry>),
+}
+
+impl PlayerRegistry {
+  pub(crate) fn send_game_list_snapshot(&mut self, player_id: i32) -> Result<()> {
+    let frame = self.game_list.snapshot().encode_as_frame()?;
+    super::sender::send_to_player(&mut self.registry, player_id, frame.into());
+    Ok(())
+  }
+}
+
+pub struct UpdateGameList(pub GameListChange);
+
+impl Message for UpdateGameList {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<UpdateGameList> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    UpdateGameList(change): UpdateGameList,
+  ) -> Result<()> {
+    let frame = match change {
+      GameListChange::Upsert(entry) => self.game_list.upsert(entry).map(encode),
+      GameListChange::Players { game_id, players } => self
+        .game_list
+        .update(game_id, Some(players), None)
+        .map(encode),
+      GameListChange::Status { game_id, status } => self
+        .game_list
+        .update(game_id, None, Some(status))
+        .map(encode),
+      GameListChange::Remove { game_id } => self.game_list.remove(game_id).map(encode),
+      GameListChange::Remote(entries) => self.game_list.set_remote(entries).map(encode),
+    };
+    if let Some(frame) = frame {
+      let frame = frame?;
+      for player_id in self.subscriptions.subscribers(Topic::GameList) {
+        super::sender::send_to_player(&mut self.registry, player_id, frame.clone().into());
+      }
+    }
+    Ok(())
+  }
+}
+
+fn encode<T: FloPacket>(packet: T) -> Result<Frame> {
+  Ok(packet.encode_as_frame()?)
+}
+
+pub struct ResyncGameList {
+  pub player_id: i32,
+}
+
+impl Message for ResyncGameList {
+  type Result = Result<()>;
+}
+
+#[async_trait]
+impl Handler<ResyncGameList> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    ResyncGameList { player_id }: ResyncGameList,
+  ) -> Result<()> {
+    if !self.registry.contains_key(&player_id) {
+      return Ok(());
+    }
+    self.send_game_list_snapshot(player_id)
+  }
+}
+
+#[cfg(test)]
+fn test_entry(id: i32, players: i32) -> GameListEntry {
+  GameListEntry {
+    id,
+    name: format!("game {}", id),
+    map_name: "map".to_string(),
+    players,
+    max_players: 12,
+    status: status_to_proto(GameStatus::Preparing),
+    lobby_id: String::new(),
+  }
+}
+
+#[test]
+fn test_game_list_delta() {
+  let mut list = GameList::default();
+
+  let update = list.upsert(test_entry(1, 1)).unwrap();
+  assert_eq!(update.seq, 1);
+  assert_eq!(update.added[0].id, 1);
+
+  assert!(list.upsert(test_entry(1, 1)).is_none());
+  assert!(list.update(2, Some(3), None).is_none());
+
+  let update = list.update(1, Some(2), None).unwrap();
+  assert_eq!(update.seq, 2);
+  assert!(update.added.is_empty());
+  assert_eq!(update.changed[0].players, Some(2));
+  assert_eq!(update.changed[0].status, None);
+
+  let update = list.update(1, None, Some(GameStatus::Running)).unwrap();
+  assert_eq!(update.seq, 3);
+  assert_eq!(update.changed[0].players, None);
+  assert_eq!(
+    update.changed[0].status,
+    Some(status_to_proto(GameStatus::Running))
+  );
+
+  list.upsert(test_entry(2, 1)).unwrap();
+  let snapshot = list.snapshot();
+  assert_eq!(snapshot.seq, 4);
+  assert_eq!(snapshot.games.len(), 2);
+  assert_eq!(snapshot.games[0].players, 2);
+
+  let update = list.remove(1).unwrap();
+  assert_eq!(update.seq, 5);
+  assert_eq!(update.removed, vec![1]);
+  assert!(list.remove(1).is_none());
+  assert_eq!(list.snapshot().games.len(), 1);
+}
+
+#[test]
+fn test_game_list_remote() {
+  let mut list = GameList::default();
+  list.upsert(test_entry(1, 1)).unwrap();
+
+  let remote = GameListEntry {
+    lobby_id: "eu".to_string(),
+    ..test_entry(1, 2)
+  };
+  let snapshot = list.set_remote(vec![remote.clone()]).unwrap();
+  assert_eq!(snapshot.seq, 2);
+  assert_eq!(snapshot.games.len(), 1);
+  assert_eq!(snapshot.remote_games, vec![remote.clone()]);
+  assert!(list.set_remote(vec![remote]).is_none());
+  // remote games are not local entries
+  let update = list.update(1, Some(3), None).unwrap();
+  assert_eq!(update.seq, 3);
+  assert_eq!(list.snapshot().remote_games[0].players, 2);
+}
@@ -1,59 +1,227 @@
+pub mod bus;
 pub mod conn;
+pub mod game_list;
 pub mod ping;
 pub mod sender;
+pub mod store;
+pub mod subscription;
 
 use crate::client::PlayerSender;
 use crate::error::Error;
 use crate::state::Data;
-use flo_state::{async_trait, Actor, RegistryRef, Service};
+use flo_state::{async_trait, Actor, Context, RegistryRef, Service};
 use flo_types::ping::PingStats;
 
+use crate::player::state::bus::{BusGameUpdate, LobbyBus};
+use crate::player::state::game_list::GameList;
 use crate::player::state::sender::PlayerFrames;
+use crate::player::state::store::{LocalSessionStore, SessionRecord, SessionStoreRef};
+use crate::player::state::subscription::Subscriptions;
 use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The store is called inside the handlers, a stalled Redis must not block the registry
+const SESSION_STORE_TIMEOUT: Duration = Duration::from_secs(1);
+const SESSION_STORE_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn with_store_timeout<T, F>(timeout: Duration, future: F) -> Result<T, Error>
+where
+  F: Future<Output = Result<T, Error>>,
+{
+  tokio::time::timeout(timeout, future)
+    .await
+    .map_err(|_| Error::Timeout(anyhow::format_err!("session store")))?
+}
 
 #[derive(Debug)]
 pub struct PlayerRegistry {
   registry: BTreeMap<i32, PlayerState>,
+  subscriptions: Subscriptions,
+  game_list: GameList,
+  store: SessionStoreRef,
+  /// Forwards packets to players on other instances, only set with the shared session store
+  bus: Option<LobbyBus>,
 }
 
 impl PlayerRegistry {
   pub fn new() -> Self {
+    Self::with_store(Arc::new(LocalSessionStore), None)
+  }
+
+  fn with_store(store: SessionStoreRef, bus: Option<LobbyBus>) -> Self {
     Self {
       registry: Default::default(),
+      subscriptions: Default::default(),
+      game_list: Default::default(),
+      store,
+      bus,
+    }
+  }
+
+  /// Forwards the frames of players who aren't connected to this instance
+  fn forward<I>(&self, frames: I, game_update: Option<BusGameUpdate>)
+  where
+    I: IntoIterator<Item = (i32, PlayerFrames)>,
+  {
+    if let Some(bus) = self.bus.as_ref() {
+      bus.forward(
+        frames
+          .into_iter()
+          .filter(|(id, _)| !self.registry.contains_key(id))
+          .collect(),
+        game_update,
+      );
+    }
+  }
+
+  /// Records the session of a player connected to this instance
+  async fn put_session(&self, player_id: i32) {
+    let state = if let Some(state) = self.registry.get(&player_id) {
+      state
+    } else {
+      return;
+    };
+    let record = SessionRecord {
+      instance_id: self.store.instance_id().to_string(),
+      session_id: state.sender.session_id(),
+      game_id: state.game_id,
+      suggested_region: state.suggested_region.clone(),
+      ip: state.ip,
+    };
+    if let Err(err) =
+      with_store_timeout(SESSION_STORE_TIMEOUT, self.store.put(player_id, &record)).await
+    {
+      tracing::error!(player_id, "put session: {}", err);
+    }
+  }
+
+  async fn remove_session(&self, player_id: i32, session_id: u64) {
+    if let Err(err) = with_store_timeout(
+      SESSION_STORE_TIMEOUT,
+      self.store.remove(player_id, session_id),
+    )
+    .await
+    {
+      tracing::error!(player_id, "remove session: {}", err);
+    }
+  }
+
+  async fn set_session_game_id(&self, player_id: i32, game_id: Option<i32>) {
+    if let Err(err) = with_store_timeout(
+      SESSION_STORE_TIMEOUT,
+      self.store.set_game_id(player_id, game_id),
+    )
+    .await
+    {
+      tracing::error!(player_id, "set session game: {}", err);
+    }
+  }
+
+  /// Sessions of the players connected to other instances
+  async fn get_remote_sessions(&self, players: &[i32]) -> BTreeMap<i32, SessionRecord> {
+    let players: Vec<i32> = players
+      .iter()
+      .cloned()
+      .filter(|id| !self.registry.contains_key(id))
+      .collect();
+    if players.is_empty() {
+      return BTreeMap::new();
+    }
+    match with_store_timeout(SESSION_STORE_TIMEOUT, self.store.get_many(&players)).await {
+      // a session of this instance that isn't in the registry is gone
+      Ok(mut map) => {
+        map.retain(|_, record| record.instance_id != self.store.instance_id());
+        map
+      }
+      Err(err) => {
+        tracing::error!("get sessions: {}", err);
+        BTreeMap::new()
+      }
     }
   }
 }
 
-impl Actor for PlayerRegistry {}
+#[async_trait]
+impl Actor for PlayerRegistry {
+  async fn started(&mut self, ctx: &mut Context<Self>) {
+    if let Some(bus) = self.bus.clone() {
+      ctx.spawn(bus.subscribe(ctx.addr()));
+    }
+  }
+}
 
 #[async_trait]
 impl Service<Data> for PlayerRegistry {
   type Error = Error;
 
   async fn create(_registry: &mut RegistryRef<Data>) -> Result<Self, Self::Error> {
-    Ok(PlayerRegistry::new())
+    // the lobby keeps serving the players connected to this instance without Redis
+    let (store, bus) =
+      match with_store_timeout(SESSION_STORE_CONNECT_TIMEOUT, connect_shared()).await {
+        Ok(shared) => shared,
+        Err(err) => {
+          tracing::error!(
+            "session store unavailable, sessions are not shared: {}",
+            err
+          );
+          (Arc::new(LocalSessionStore) as SessionStoreRef, None)
+        }
+      };
+    Ok(PlayerRegistry::with_store(store, bus))
   }
 }
 
+async fn connect_shared() -> Result<(SessionStoreRef, Option<LobbyBus>), Error> {
+  let store = store::connect().await?;
+  let bus = LobbyBus::connect(store.clone()).await?;
+  Ok((store, bus))
+}
+
 #[derive(Debug)]
 pub struct PlayerState {
   pub player_id: i32,
   pub ping_map: BTreeMap<i32, PingStats>,
   pub game_id: Option<i32>,
   pub sender: PlayerSender,
+  /// Sessions opened next to `sender` under `SessionPolicy::ReadOnly`, they receive the same frames
+  pub read_only: Vec<PlayerSender>,
+  pub suggested_region: Option<String>,
+  pub ip: Option<IpAddr>,
 }
 
 impl PlayerState {
-  fn new(player_id: i32, game_id: Option<i32>, sender: PlayerSender) -> PlayerState {
+  fn new(
+    player_id: i32,
+    game_id: Option<i32>,
+    sender: PlayerSender,
+    suggested_region: Option<String>,
+    ip: Option<IpAddr>,
+  ) -> PlayerState {
     Self {
       player_id,
       game_id,
       ping_map: Default::default(),
       sender,
+      read_only: vec![],
+      suggested_region,
+      ip,
     }
   }
 
   fn try_send_frames(&mut self, frames: PlayerFrames) -> bool {
+    if !self.read_only.is_empty() {
+      // read-only sessions that can't keep up are dropped
+      self.read_only.retain(|sender| {
+        let mut sender = sender.clone();
+        frames
+          .clone()
+          .into_iter()
+          .all(|frame| sender.try_send(frame))
+      });
+    }
     for frame in frames {
       if !self.sender.try_send(frame) {
         return false;
@@ -62,7 +230,11 @@ impl PlayerState {
     true
   }
 
+  /// Replaced or gone, the read-only sessions end with it
   async fn shutdown(mut self) {
     self.sender.disconnect_multi().await;
+    for mut sender in self.read_only {
+      sender.disconnect_session_ended().await;
+    }
   }
 }
@@ -1,12 +1,21 @@
+use super::bus::BusGameUpdate;
 use super::{PlayerRegistry, PlayerState};
 use crate::error::*;
 use crate::game::Game;
 use crate::player::session::get_session_update_packet;
+use crate::player::state::conn::{
+  GetOnlinePlayers, GetPlayerIps, GetPlayerSuggestedRegion, GetSenderQueueStats, SenderQueueStats,
+};
+use crate::player::state::game_list::{GameListChange, ResyncGameList, UpdateGameList};
+use crate::player::state::ping::GetPlayersPingSnapshot;
+use crate::player::state::subscription::{Publish, Topic};
 use flo_net::packet::{FloPacket, Frame};
 use flo_state::{async_trait, Addr, Context, Handler, Message};
+use flo_types::ping::PingStats;
 use s2_grpc_utils::S2ProtoPack;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
+use std::net::IpAddr;
 
 #[derive(Debug)]
 struct Send {
@@ -21,7 +30,11 @@ impl Message for Send {
 #[async_trait]
 impl Handler<Send> for PlayerRegistry {
   async fn handle(&mut self, _: &mut Context<Self>, Send { player_id, frames }: Send) {
-    send_to_player(&mut self.registry, player_id, frames);
+    if self.registry.contains_key(&player_id) {
+      send_to_player(&mut self.registry, player_id, frames);
+    } else {
+      self.forward(Some((player_id, frames)), None);
+    }
   }
 }
 
@@ -65,6 +78,10 @@ impl Message for Broadcast {
 #[async_trait]
 impl Handler<Broadcast> for PlayerRegistry {
   async fn handle(&mut self, _: &mut Context<Self>, Broadcast { players, frames }: Broadcast) {
+    self.forward(
+      players.iter().map(|player_id| (*player_id, frames.clone())),
+      None,
+    );
     for player_id in players {
       send_to_player(&mut self.registry, player_id, frames.clone());
     }
@@ -83,6 +100,12 @@ impl Message for BroadcastMap {
 #[async_trait]
 impl Handler<BroadcastMap> for PlayerRegistry {
   async fn handle(&mut self, _: &mut Context<Self>, BroadcastMap { map }: BroadcastMap) {
+    self.forward(
+      map
+        .iter()
+        .map(|(player_id, frames)| (*player_id, frames.clone())),
+      None,
+    );
     for (player_id, frames) in map {
       send_to_player(&mut self.registry, player_id, frames);
     }
@@ -113,15 +136,28 @@ impl Handler<PlayerReplaceGame> for PlayerRegistry {
     use flo_net::proto::flo_connect::*;
     let game_id = game.id;
 
+    let frames = vec![
+      get_session_update_packet(Some(game.id)).encode_as_frame()?,
+      PacketPlayerMuteListUpdate { mute_list }.encode_as_frame()?,
+      PacketGameInfo {
+        game: Some(game.pack()?),
+      }
+      .encode_as_frame()?,
+    ];
+
+    if self.registry.contains_key(&player_id) {
+      self.set_session_game_id(player_id, Some(game_id)).await;
+    } else {
+      self.forward(
+        Some((player_id, frames.into())),
+        Some(BusGameUpdate {
+          game_id,
+          left: false,
+        }),
+      );
+      return Ok(());
+    }
     if let Entry::Occupied(mut entry) = self.registry.entry(player_id) {
-      let frames = vec![
-        get_session_update_packet(Some(game.id)).encode_as_frame()?,
-        PacketPlayerMuteListUpdate { mute_list }.encode_as_frame()?,
-        PacketGameInfo {
-          game: Some(game.pack()?),
-        }
-        .encode_as_frame()?,
-      ];
       entry.get_mut().game_id = Some(game_id);
       if !entry.get_mut().try_send_frames(frames.into()) {
         entry.remove();
@@ -162,22 +198,36 @@ impl Handler<PlayersReplaceGame> for PlayerRegistry {
     }
     .encode_as_frame()?;
 
+    let mut remote = BTreeMap::new();
     for player_id in player_ids {
+      let frames = vec![
+        frame_session_update.clone(),
+        PacketPlayerMuteListUpdate {
+          mute_list: mute_list_map.remove(&player_id).unwrap_or_default(),
+        }
+        .encode_as_frame()?,
+        frame_game_info.clone(),
+      ];
+      if self.registry.contains_key(&player_id) {
+        self.set_session_game_id(player_id, Some(game_id)).await;
+      } else {
+        remote.insert(player_id, frames.into());
+        continue;
+      }
       if let Entry::Occupied(mut entry) = self.registry.entry(player_id) {
-        let frames = vec![
-          frame_session_update.clone(),
-          PacketPlayerMuteListUpdate {
-            mute_list: mute_list_map.remove(&player_id).unwrap_or_default(),
-          }
-          .encode_as_frame()?,
-          frame_game_info.clone(),
-        ];
         entry.get_mut().game_id = Some(game_id);
         if !entry.get_mut().try_send_frames(frames.into()) {
           entry.remove();
         }
       }
     }
+    self.forward(
+      remote,
+      Some(BusGameUpdate {
+        game_id,
+        left: false,
+      }),
+    );
 
     Ok(())
   }
@@ -199,12 +249,29 @@ impl Handler<PlayerLeaveGame> for PlayerRegistry {
     _: &mut Context<Self>,
     PlayerLeaveGame { player_id, game_id }: PlayerLeaveGame,
   ) -> Result<()> {
+    let in_game =
+      matches!(self.registry.get(&player_id), Some(state) if state.game_id == Some(game_id));
+    if in_game {
+      self.set_session_game_id(player_id, None).await;
+    } else if !self.registry.contains_key(&player_id) {
+      // the instance of the player checks the game id
+      self.forward(
+        Some((
+          player_id,
+          get_session_update_packet(None).encode_as_frame()?.into(),
+        )),
+        Some(BusGameUpdate {
+          game_id,
+          left: true,
+        }),
+      );
+      return Ok(());
+    }
     if let Entry::Occupied(mut entry) = self.registry.entry(player_id) {
       if entry.get().game_id == Some(game_id) {
         if !entry
           .get_mut()
-          .sender
-          .try_send(get_session_update_packet(None).encode_as_frame()?)
+          .try_send_frames(get_session_update_packet(None).encode_as_frame()?.into())
         {
           entry.remove();
         } else {
@@ -306,7 +373,11 @@ impl Iterator for PlayerFramesIntoIterator {
   }
 }
 
-fn send_to_player(map: &mut BTreeMap<i32, PlayerState>, player_id: i32, frames: PlayerFrames) {
+pub(super) fn send_to_player(
+  map: &mut BTreeMap<i32, PlayerState>,
+  player_id: i32,
+  frames: PlayerFrames,
+) {
   let remove = {
     let entry = map.get_mut(&player_id);
     if let Some(entry) = entry {
@@ -430,6 +501,64 @@ impl PlayerRegistryHandle {
       .await??;
     Ok(())
   }
+
+  pub async fn get_ping_map(&self, player_id: i32) -> Result<BTreeMap<i32, PingStats>> {
+    let mut snapshot = self
+      .0
+      .send(GetPlayersPingSnapshot {
+        players: vec![player_id],
+      })
+      .await?;
+    Ok(snapshot.map.remove(&player_id).unwrap_or_default())
+  }
+
+  pub async fn get_players_ping_map(
+    &self,
+    players: Vec<i32>,
+  ) -> Result<BTreeMap<i32, BTreeMap<i32, PingStats>>> {
+    Ok(self.0.send(GetPlayersPingSnapshot { players }).await?.map)
+  }
+
+  pub async fn get_sender_queue_stats(&self) -> Result<SenderQueueStats> {
+    Ok(self.0.send(GetSenderQueueStats).await?)
+  }
+
+  pub async fn get_player_ips(&self, players: Vec<i32>) -> Result<BTreeMap<i32, IpAddr>> {
+    Ok(self.0.send(GetPlayerIps { players }).await?)
+  }
+
+  pub async fn get_suggested_region(&self, player_id: i32) -> Result<Option<String>> {
+    Ok(self.0.send(GetPlayerSuggestedRegion { player_id }).await?)
+  }
+
+  pub async fn get_online_players(&self, players: Vec<i32>) -> Result<Vec<i32>> {
+    Ok(self.0.send(GetOnlinePlayers { players }).await?)
+  }
+
+  /// Sends `frames` to subscribers of `topic` that aren't in `exclude`
+  pub async fn publish<T>(&self, topic: Topic, exclude: Vec<i32>, frames: T) -> Result<()>
+  where
+    T: Into<PlayerFrames>,
+  {
+    self
+      .0
+      .send(Publish {
+        topic,
+        exclude,
+        frames: frames.into(),
+      })
+      .await?;
+    Ok(())
+  }
+
+  /// Applies `change` to the game list and sends the delta to its subscribers
+  pub async fn update_game_list(&self, change: GameListChange) -> Result<()> {
+    self.0.send(UpdateGameList(change)).await?
+  }
+
+  pub async fn resync_game_list(&self, player_id: i32) -> Result<()> {
+    self.0.send(ResyncGameList { player_id }).await?
+  }
 }
 
 impl From<Addr<PlayerRegistry>> for PlayerRegistryHandle {
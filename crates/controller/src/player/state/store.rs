@@ -0,0 +1,386 @@
+//! Sessions of the connected players, shared by the lobby instances.
+//!
+//! Each instance keeps the connections of its own players in `PlayerRegistry`,
+//! the store records which instance holds the session of a player, with the session details
+//! other instances ask for. Without `redis_url` nothing is shared and only the players
+//! connected to this instance are known.
+//!
+//! Games are owned by the instance that created them, the store records the owner so the
+//! requests of players connected to other instances can be routed to it.
+//!
+//! Instances refresh a heartbeat key while running,
+//! sessions and games of an instance that stopped refreshing it are ignored.
+
+use crate::error::*;
+use flo_state::async_trait;
+use once_cell::sync::Lazy;
+use redis::aio::ConnectionManager;
+use redis::{pipe, Client, Script};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Debug, Formatter};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const REDIS_KEY_PREFIX: &str = "flo_lobby";
+const REDIS_SESSION_TTL_SECS: u64 = 24 * 3600;
+const INSTANCE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+const INSTANCE_HEARTBEAT_TTL_SECS: u64 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRecord {
+  /// `SessionStore::instance_id` of the instance the player is connected to
+  pub instance_id: String,
+  pub session_id: u64,
+  pub game_id: Option<i32>,
+  pub suggested_region: Option<String>,
+  pub ip: Option<IpAddr>,
+}
+
+#[async_trait]
+pub trait SessionStore: Debug + Send + Sync {
+  /// Identifies this instance in the session records
+  fn instance_id(&self) -> &str;
+
+  async fn put(&self, player_id: i32, record: &SessionRecord) -> Result<()>;
+
+  /// Removes the session if it's still `session_id` of this instance
+  async fn remove(&self, player_id: i32, session_id: u64) -> Result<()>;
+
+  /// Updates the game of the session if it's held by this instance
+  async fn set_game_id(&self, player_id: i32, game_id: Option<i32>) -> Result<()>;
+
+  /// Sessions of `players` held by running instances, including this one
+  async fn get_many(&self, players: &[i32]) -> Result<BTreeMap<i32, SessionRecord>>;
+
+  /// Records this instance as the owner of the game
+  async fn claim_game(&self, game_id: i32) -> Result<()>;
+
+  /// Removes the owner of the game if it's this instance
+  async fn release_game(&self, game_id: i32) -> Result<()>;
+
+  /// The running instance owning the game, `None` if it's unknown
+  async fn get_game_owner(&self, game_id: i32) -> Result<Option<String>>;
+}
+
+pub type SessionStoreRef = Arc<dyn SessionStore>;
+
+pub async fn connect() -> Result<SessionStoreRef> {
+  if let Some(url) = crate::config::service_config().redis_url.as_ref() {
+    let conn = Client::open(url.as_str())?
+      .get_tokio_connection_manager()
+      .await?;
+    let store = RedisSessionStore {
+      instance_id: format!("{:016x}", rand::random::<u64>()),
+      conn,
+    };
+    store.heartbeat().await?;
+    tokio::spawn({
+      let store = store.clone();
+      async move {
+        let mut interval = tokio::time::interval(INSTANCE_HEARTBEAT_INTERVAL);
+        loop {
+          interval.tick().await;
+          if let Err(err) = store.heartbeat().await {
+            tracing::error!("session store heartbeat: {}", err);
+          }
+        }
+      }
+    });
+    tracing::info!(
+      "sessions are shared in redis: instance_id = {}",
+      store.instance_id
+    );
+    return Ok(Arc::new(store));
+  }
+  Ok(Arc::new(LocalSessionStore))
+}
+
+/// Nothing is shared, `PlayerRegistry` already knows every session of the instance
+#[derive(Debug)]
+pub struct LocalSessionStore;
+
+#[async_trait]
+impl SessionStore for LocalSessionStore {
+  fn instance_id(&self) -> &str {
+    "local"
+  }
+
+  async fn put(&self, _player_id: i32, _record: &SessionRecord) -> Result<()> {
+    Ok(())
+  }
+
+  async fn remove(&self, _player_id: i32, _session_id: u64) -> Result<()> {
+    Ok(())
+  }
+
+  async fn set_game_id(&self, _player_id: i32, _game_id: Option<i32>) -> Result<()> {
+    Ok(())
+  }
+
+  async fn get_many(&self, _players: &[i32]) -> Result<BTreeMap<i32, SessionRecord>> {
+    Ok(BTreeMap::new())
+  }
+
+  async fn claim_game(&self, _game_id: i32) -> Result<()> {
+    Ok(())
+  }
+
+  async fn release_game(&self, _game_id: i32) -> Result<()> {
+    Ok(())
+  }
+
+  async fn get_game_owner(&self, _game_id: i32) -> Result<Option<String>> {
+    Ok(None)
+  }
+}
+
+// KEYS: session; ARGV: instance_id, session_id
+static REMOVE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+  Script::new(
+    r#"
+if redis.call('HGET', KEYS[1], 'instance_id') == ARGV[1]
+  and redis.call('HGET', KEYS[1], 'session_id') == ARGV[2] then
+  return redis.call('DEL', KEYS[1])
+end
+return 0
+"#,
+  )
+});
+
+// KEYS: session; ARGV: instance_id, game_id
+static SET_GAME_ID_SCRIPT: Lazy<Script> = Lazy::new(|| {
+  Script::new(
+    r#"
+if redis.call('HGET', KEYS[1], 'instance_id') == ARGV[1] then
+  return redis.call('HSET', KEYS[1], 'game_id', ARGV[2])
+end
+return 0
+"#,
+  )
+});
+
+// KEYS: game; ARGV: instance_id
+static RELEASE_GAME_SCRIPT: Lazy<Script> = Lazy::new(|| {
+  Script::new(
+    r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+  return redis.call('DEL', KEYS[1])
+end
+return 0
+"#,
+  )
+});
+
+/// Sessions are hashes under `flo_lobby:session:{player_id}`, empty fields are `None`.
+/// Game owners are instance ids under `flo_lobby:game:{game_id}`.
+#[derive(Clone)]
+pub struct RedisSessionStore {
+  instance_id: String,
+  conn: ConnectionManager,
+}
+
+impl Debug for RedisSessionStore {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RedisSessionStore")
+      .field("instance_id", &self.instance_id)
+      .finish()
+  }
+}
+
+impl RedisSessionStore {
+  fn session_key(player_id: i32) -> String {
+    format!("{}:session:{}", REDIS_KEY_PREFIX, player_id)
+  }
+
+  fn game_key(game_id: i32) -> String {
+    format!("{}:game:{}", REDIS_KEY_PREFIX, game_id)
+  }
+
+  fn instance_key(instance_id: &str) -> String {
+    format!("{}:instance:{}", REDIS_KEY_PREFIX, instance_id)
+  }
+
+  async fn heartbeat(&self) -> Result<()> {
+    redis::cmd("SET")
+      .arg(Self::instance_key(&self.instance_id))
+      .arg(1)
+      .arg("EX")
+      .arg(INSTANCE_HEARTBEAT_TTL_SECS)
+      .query_async::<_, ()>(&mut self.conn.clone())
+      .await?;
+    Ok(())
+  }
+}
+
+fn parse_record(mut fields: HashMap<String, String>) -> Option<SessionRecord> {
+  let mut take = |name: &str| fields.remove(name).filter(|v| !v.is_empty());
+  Some(SessionRecord {
+    instance_id: take("instance_id")?,
+    session_id: take("session_id")?.parse().ok()?,
+    game_id: take("game_id").and_then(|v| v.parse().ok()),
+    suggested_region: take("suggested_region"),
+    ip: take("ip").and_then(|v| v.parse().ok()),
+  })
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+  fn instance_id(&self) -> &str {
+    &self.instance_id
+  }
+
+  async fn put(&self, player_id: i32, record: &SessionRecord) -> Result<()> {
+    let key = Self::session_key(player_id);
+    pipe()
+      .atomic()
+      .cmd("HSET")
+      .arg(&key)
+      .arg("instance_id")
+      .arg(&record.instance_id)
+      .arg("session_id")
+      .arg(record.session_id)
+      .arg("game_id")
+      .arg(record.game_id.map(|v| v.to_string()).unwrap_or_default())
+      .arg("suggested_region")
+      .arg(record.suggested_region.as_deref().unwrap_or_default())
+      .arg("ip")
+      .arg(record.ip.map(|v| v.to_string()).unwrap_or_default())
+      .ignore()
+      .cmd("EXPIRE")
+      .arg(&key)
+      .arg(REDIS_SESSION_TTL_SECS)
+      .ignore()
+      .query_async::<_, ()>(&mut self.conn.clone())
+      .await?;
+    Ok(())
+  }
+
+  async fn remove(&self, player_id: i32, session_id: u64) -> Result<()> {
+    REMOVE_SCRIPT
+      .key(Self::session_key(player_id))
+      .arg(&self.instance_id)
+      .arg(session_id)
+      .invoke_async::<_, ()>(&mut self.conn.clone())
+      .await?;
+    Ok(())
+  }
+
+  async fn set_game_id(&self, player_id: i32, game_id: Option<i32>) -> Result<()> {
+    SET_GAME_ID_SCRIPT
+      .key(Self::session_key(player_id))
+      .arg(&self.instance_id)
+      .arg(game_id.map(|v| v.to_string()).unwrap_or_default())
+      .invoke_async::<_, ()>(&mut self.conn.clone())
+      .await?;
+    Ok(())
+  }
+
+  async fn get_many(&self, players: &[i32]) -> Result<BTreeMap<i32, SessionRecord>> {
+    if players.is_empty() {
+      return Ok(BTreeMap::new());
+    }
+    let mut query = pipe();
+    for player_id in players {
+      query.cmd("HGETALL").arg(Self::session_key(*player_id));
+    }
+    let list: Vec<HashMap<String, String>> = query.query_async(&mut self.conn.clone()).await?;
+    let records: Vec<(i32, SessionRecord)> = players
+      .iter()
+      .cloned()
+      .zip(list)
+      .filter_map(|(player_id, fields)| Some((player_id, parse_record(fields)?)))
+      .collect();
+
+    let instances: Vec<String> = records
+      .iter()
+      .map(|(_, record)| record.instance_id.clone())
+      .collect::<BTreeSet<_>>()
+      .into_iter()
+      .collect();
+    if instances.is_empty() {
+      return Ok(BTreeMap::new());
+    }
+    let mut query = pipe();
+    for instance_id in &instances {
+      query.cmd("EXISTS").arg(Self::instance_key(instance_id));
+    }
+    let alive: Vec<bool> = query.query_async(&mut self.conn.clone()).await?;
+    let alive: BTreeSet<_> = instances
+      .into_iter()
+      .zip(alive)
+      .filter(|(_, alive)| *alive)
+      .map(|(instance_id, _)| instance_id)
+      .collect();
+
+    Ok(
+      records
+        .into_iter()
+        .filter(|(_, record)| alive.contains(&record.instance_id))
+        .collect(),
+    )
+  }
+
+  async fn claim_game(&self, game_id: i32) -> Result<()> {
+    redis::cmd("SET")
+      .arg(Self::game_key(game_id))
+      .arg(&self.instance_id)
+      .arg("EX")
+      .arg(REDIS_SESSION_TTL_SECS)
+      .query_async::<_, ()>(&mut self.conn.clone())
+      .await?;
+    Ok(())
+  }
+
+  async fn release_game(&self, game_id: i32) -> Result<()> {
+    RELEASE_GAME_SCRIPT
+      .key(Self::game_key(game_id))
+      .arg(&self.instance_id)
+      .invoke_async::<_, ()>(&mut self.conn.clone())
+      .await?;
+    Ok(())
+  }
+
+  async fn get_game_owner(&self, game_id: i32) -> Result<Option<String>> {
+    let mut conn = self.conn.clone();
+    let owner: Option<String> = redis::cmd("GET")
+      .arg(Self::game_key(game_id))
+      .query_async(&mut conn)
+      .await?;
+    let owner = if let Some(owner) = owner {
+      owner
+    } else {
+      return Ok(None);
+    };
+    let alive: bool = redis::cmd("EXISTS")
+      .arg(Self::instance_key(&owner))
+      .query_async(&mut conn)
+      .await?;
+    Ok(if alive { Some(owner) } else { None })
+  }
+}
+
+#[test]
+fn test_parse_session_record() {
+  let fields = |pairs: &[(&str, &str)]| {
+    pairs
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .collect::<HashMap<_, _>>()
+  };
+
+  let record = parse_record(fields(&[
+    ("instance_id", "a"),
+    ("session_id", "1"),
+    ("game_id", ""),
+    ("suggested_region", "eu"),
+    ("ip", "127.0.0.1"),
+  ]))
+  .unwrap();
+  assert_eq!(record.game_id, None);
+  assert_eq!(record.suggested_region.as_deref(), Some("eu"));
+  assert_eq!(record.ip, Some("127.0.0.1".parse().unwrap()));
+
+  // expired while it was read
+  assert!(parse_record(fields(&[])).is_none());
+}
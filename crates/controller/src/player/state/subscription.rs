@@ -0,0 +1,244 @@
+use super::PlayerRegistry;
+use crate::player::state::sender::PlayerFrames;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_connect::{
+  PacketPlayerPresenceUpdate, SubscriptionTopic, SubscriptionTopicKind,
+};
+use flo_state::{async_trait, Context, Handler, Message};
+use std::collections::{BTreeMap, BTreeSet};
+
+const MAX_TOPICS_PER_PLAYER: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Topic {
+  GameList,
+  Game(i32),
+  Presence(i32),
+}
+
+impl Topic {
+  pub fn from_proto(topic: &SubscriptionTopic) -> Self {
+    match topic.kind() {
+      SubscriptionTopicKind::GameList => Topic::GameList,
+      SubscriptionTopicKind::Game => Topic::Game(topic.id),
+      SubscriptionTopicKind::PlayerPresence => Topic::Presence(topic.id),
+    }
+  }
+
+  pub fn pack(&self) -> SubscriptionTopic {
+    let (kind, id) = match *self {
+      Topic::GameList => (SubscriptionTopicKind::GameList, 0),
+      Topic::Game(id) => (SubscriptionTopicKind::Game, id),
+      Topic::Presence(id) => (SubscriptionTopicKind::PlayerPresence, id),
+    };
+    SubscriptionTopic {
+      kind: kind.into(),
+      id,
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+  topics: BTreeMap<Topic, BTreeSet<i32>>,
+  players: BTreeMap<i32, BTreeSet<Topic>>,
+}
+
+impl Subscriptions {
+  /// Returns `false` if the player has `MAX_TOPICS_PER_PLAYER` other topics
+  pub fn subscribe(&mut self, player_id: i32, topic: Topic) -> bool {
+    let topics = self.players.entry(player_id).or_default();
+    if topics.len() >= MAX_TOPICS_PER_PLAYER && !topics.contains(&topic) {
+      return false;
+    }
+    topics.insert(topic);
+    self.topics.entry(topic).or_default().insert(player_id);
+    true
+  }
+
+  pub fn unsubscribe(&mut self, player_id: i32, topic: Topic) {
+    if let Some(set) = self.topics.get_mut(&topic) {
+      set.remove(&player_id);
+      if set.is_empty() {
+        self.topics.remove(&topic);
+      }
+    }
+    if let Some(set) = self.players.get_mut(&player_id) {
+      set.remove(&topic);
+      if set.is_empty() {
+        self.players.remove(&player_id);
+      }
+    }
+  }
+
+  pub fn remove_player(&mut self, player_id: i32) {
+    for topic in self.players.remove(&player_id).unwrap_or_default() {
+      if let Some(set) = self.topics.get_mut(&topic) {
+        set.remove(&player_id);
+        if set.is_empty() {
+          self.topics.remove(&topic);
+        }
+      }
+    }
+  }
+
+  pub fn subscribers(&self, topic: Topic) -> Vec<i32> {
+    self
+      .topics
+      .get(&topic)
+      .map(|set| set.iter().cloned().collect())
+      .unwrap_or_default()
+  }
+}
+
+impl PlayerRegistry {
+  // a game is visible to its players, or to everyone if listed,
+  // the presence of a player to the players who can see their game
+  fn can_subscribe(&self, player_id: i32, topic: Topic) -> bool {
+    let game_id = |player_id: i32| self.registry.get(&player_id).and_then(|p| p.game_id);
+    let visible = |id: i32| game_id(player_id) == Some(id) || self.game_list.contains(id);
+    match topic {
+      Topic::GameList => true,
+      Topic::Game(id) => visible(id),
+      Topic::Presence(id) => id == player_id || game_id(id).map(visible).unwrap_or(false),
+    }
+  }
+
+  pub(crate) fn publish_presence(&mut self, player_id: i32, online: bool) {
+    let subscribers = self.subscriptions.subscribers(Topic::Presence(player_id));
+    if subscribers.is_empty() && self.bus.is_none() {
+      return;
+    }
+    let frame = match (PacketPlayerPresenceUpdate { player_id, online }).encode_as_frame() {
+      Ok(frame) => frame,
+      Err(err) => {
+        tracing::error!(player_id, "encode presence update: {}", err);
+        return;
+      }
+    };
+    if let Some(bus) = self.bus.as_ref() {
+      bus.publish(Topic::Presence(player_id), vec![], frame.clone().into());
+    }
+    for subscriber in subscribers {
+      super::sender::send_to_player(&mut self.registry, subscriber, frame.clone().into());
+    }
+  }
+}
+
+pub struct Subscribe {
+  pub player_id: i32,
+  pub topics: Vec<Topic>,
+}
+
+impl Message for Subscribe {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Subscribe> for PlayerRegistry {
+  async fn handle(&mut self, _: &mut Context<Self>, Subscribe { player_id, topics }: Subscribe) {
+    // only online players can subscribe, `Disconnect` drops the subscriptions
+    if !self.registry.contains_key(&player_id) {
+      return;
+    }
+    for topic in topics {
+      if !self.can_subscribe(player_id, topic) {
+        tracing::debug!(player_id, "subscribe denied: {:?}", topic);
+        continue;
+      }
+      if !self.subscriptions.subscribe(player_id, topic) {
+        tracing::debug!(player_id, "subscribe denied, too many topics: {:?}", topic);
+        continue;
+      }
+      if topic == Topic::GameList {
+        if let Err(err) = self.send_game_list_snapshot(player_id) {
+          tracing::error!(player_id, "send game list snapshot: {}", err);
+        }
+      }
+    }
+  }
+}
+
+pub struct Unsubscribe {
+  pub player_id: i32,
+  pub topics: Vec<Topic>,
+}
+
+impl Message for Unsubscribe {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Unsubscribe> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    Unsubscribe { player_id, topics }: Unsubscribe,
+  ) {
+    for topic in topics {
+      self.subscriptions.unsubscribe(player_id, topic);
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct Publish {
+  pub topic: Topic,
+  /// Players that already received the frames
+  pub exclude: Vec<i32>,
+  pub frames: PlayerFrames,
+}
+
+impl Message for Publish {
+  type Result = ();
+}
+
+#[async_trait]
+impl Handler<Publish> for PlayerRegistry {
+  async fn handle(
+    &mut self,
+    _: &mut Context<Self>,
+    Publish {
+      topic,
+      exclude,
+      frames,
+    }: Publish,
+  ) {
+    for player_id in self.subscriptions.subscribers(topic) {
+      if exclude.contains(&player_id) {
+        continue;
+      }
+      super::sender::send_to_player(&mut self.registry, player_id, frames.clone());
+    }
+    if let Some(bus) = self.bus.as_ref() {
+      bus.publish(topic, exclude, frames);
+    }
+  }
+}
+
+#[test]
+fn test_subscriptions() {
+  let mut subs = Subscriptions::default();
+  subs.subscribe(1, Topic::GameList);
+  subs.subscribe(2, Topic::GameList);
+  subs.subscribe(1, Topic::Game(10));
+  assert_eq!(subs.subscribers(Topic::GameList), vec![1, 2]);
+  assert_eq!(subs.subscribers(Topic::Game(10)), vec![1]);
+
+  subs.unsubscribe(2, Topic::GameList);
+  assert_eq!(subs.subscribers(Topic::GameList), vec![1]);
+
+  subs.remove_player(1);
+  assert!(subs.subscribers(Topic::GameList).is_empty());
+  assert!(subs.subscribers(Topic::Game(10)).is_empty());
+  assert!(subs.topics.is_empty());
+  assert!(subs.players.is_empty());
+
+  for id in 0..MAX_TOPICS_PER_PLAYER {
+    assert!(subs.subscribe(1, Topic::Presence(id as i32)));
+  }
+  assert!(!subs.subscribe(1, Topic::GameList));
+  assert!(subs.subscribe(1, Topic::Presence(0)));
+  subs.unsubscribe(1, Topic::Presence(0));
+  assert!(subs.subscribe(1, Topic::GameList));
+}
@@ -0,0 +1,76 @@
+use diesel::prelude::*;
+
+use crate::db::{AsyncConn, DbConn};
+use crate::error::*;
+use crate::game::Race;
+use crate::player_preferences::PlayerPreferences;
+use crate::schema::player_preferences;
+use flo_net::proto::flo_connect;
+use s2_grpc_utils::S2ProtoEnum;
+
+/// Returns the default preferences if the player has not saved any
+pub fn get(conn: &DbConn, player_id: i32) -> Result<PlayerPreferences> {
+  use player_preferences::dsl;
+  Ok(
+    player_preferences::table
+      .find(player_id)
+      .select((dsl::race, dsl::color, dsl::node_region))
+      .first::<PlayerPreferences>(conn)
+      .optional()?
+      .unwrap_or_default(),
+  )
+}
+
+/// `get` on the async pool
+pub async fn get_async(conn: &AsyncConn, player_id: i32) -> Result<PlayerPreferences> {
+  let stmt = conn
+    .prepare_cached("SELECT race, color, node_region FROM player_preferences WHERE player_id = $1")
+    .await?;
+  let row = if let Some(row) = conn.query_opt(&stmt, &[&player_id]).await? {
+    row
+  } else {
+    return Ok(PlayerPreferences::default());
+  };
+  let race: Option<i32> = row.try_get(0)?;
+  Ok(PlayerPreferences {
+    race: race
+      .map(|v| {
+        flo_connect::Race::from_i32(v)
+          .map(Race::unpack_enum)
+          .ok_or_else(|| Error::DbValueInvalid("player_preferences.race"))
+      })
+      .transpose()?,
+    color: row.try_get(1)?,
+    node_region: row.try_get(2)?,
+  })
+}
+
+pub fn upsert(conn: &DbConn, player_id: i32, preferences: &PlayerPreferences) -> Result<()> {
+  use diesel::dsl::now;
+  use player_preferences::dsl;
+
+  #[derive(Insertable, AsChangeset)]
+  #[table_name = "player_preferences"]
+  #[changeset_options(treat_none_as_null = "true")]
+  struct Upsert<'a> {
+    player_id: i32,
+    race: Option<Race>,
+    color: Option<i32>,
+    node_region: Option<&'a str>,
+  }
+
+  let upsert = Upsert {
+    player_id,
+    race: preferences.race,
+    color: preferences.color,
+    node_region: preferences.node_region.as_deref(),
+  };
+
+  diesel::insert_into(player_preferences::table)
+    .values(&upsert)
+    .on_conflict(dsl::player_id)
+    .do_update()
+    .set((&upsert, dsl::updated_at.eq(now)))
+    .execute(conn)?;
+  Ok(())
+}
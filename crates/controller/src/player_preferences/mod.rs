@@ -0,0 +1,48 @@
+pub mod db;
+
+use crate::error::*;
+use crate::game::Race;
+use flo_net::proto::flo_connect;
+use s2_grpc_utils::S2ProtoEnum;
+use serde::Serialize;
+
+/// Defaults the lobby applies when the player joins a game
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Queryable)]
+pub struct PlayerPreferences {
+  pub race: Option<Race>,
+  pub color: Option<i32>,
+  pub node_region: Option<String>,
+}
+
+impl PlayerPreferences {
+  pub fn from_proto(value: flo_connect::PlayerPreferences) -> Result<Self> {
+    let race = value
+      .race
+      .map(|v| {
+        flo_connect::Race::from_i32(v)
+          .map(Race::unpack_enum)
+          .ok_or_else(|| Error::PlayerPreferencesInvalid)
+      })
+      .transpose()?;
+
+    if let Some(color) = value.color {
+      if color < 0 || color > 23 {
+        return Err(Error::PlayerPreferencesInvalid);
+      }
+    }
+
+    Ok(Self {
+      race,
+      color: value.color,
+      node_region: value.node_region.filter(|v| !v.is_empty()),
+    })
+  }
+
+  pub fn into_proto(self) -> flo_connect::PlayerPreferences {
+    flo_connect::PlayerPreferences {
+      race: self.race.map(|v| v.into_proto_enum() as i32),
+      color: self.color,
+      node_region: self.node_region,
+    }
+  }
+}
@@ -0,0 +1,156 @@
+use crate::error::*;
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use std::env;
+use std::str::FromStr;
+
+/// Lobby policies against smurf and shared accounts, all disabled by default
+#[derive(Debug, Default)]
+pub struct Policy {
+  /// `FLO_POLICY_MIN_ACCOUNT_AGE_DAYS`: accounts younger than this can't join public games
+  pub min_account_age_days: Option<i64>,
+  /// `FLO_POLICY_MAX_SESSIONS_PER_ACCOUNT`: concurrent sessions of players linked to the same
+  /// external account
+  pub max_sessions_per_account: Option<usize>,
+  /// `FLO_POLICY_FLAG_SHARED_IP`: record an audit event for moderators when players in the same
+  /// game connect from the same IP
+  pub flag_shared_ip: bool,
+  /// `FLO_POLICY_SESSION`: `replace`, `reject` or `read_only`, what happens when a player who
+  /// is already online connects again
+  pub session: SessionPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionPolicy {
+  /// The new session replaces the old one, which is told it logged in elsewhere
+  Replace,
+  /// The new session is rejected
+  Reject,
+  /// The new session only receives what the first one receives, and can't change anything
+  ReadOnly,
+}
+
+impl Default for SessionPolicy {
+  fn default() -> Self {
+    SessionPolicy::Replace
+  }
+}
+
+impl SessionPolicy {
+  /// `online` is whether the player already has a session, checked by the player registry
+  /// while it registers the new one
+  pub fn session_mode(self, online: bool) -> Result<SessionMode> {
+    match self {
+      _ if !online => Ok(SessionMode::Primary),
+      SessionPolicy::Replace => Ok(SessionMode::Primary),
+      SessionPolicy::Reject => Err(Error::AlreadyConnected),
+      SessionPolicy::ReadOnly => Ok(SessionMode::ReadOnly),
+    }
+  }
+}
+
+impl FromStr for SessionPolicy {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "replace" => Ok(SessionPolicy::Replace),
+      "reject" => Ok(SessionPolicy::Reject),
+      "read_only" => Ok(SessionPolicy::ReadOnly),
+      _ => Err(()),
+    }
+  }
+}
+
+/// How a new session is registered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionMode {
+  /// Replaces the current session if there is one
+  Primary,
+  /// Added next to the current session
+  ReadOnly,
+}
+
+impl Policy {
+  pub fn get() -> &'static Policy {
+    static INSTANCE: Lazy<Policy> = Lazy::new(|| Policy {
+      min_account_age_days: parse_env("FLO_POLICY_MIN_ACCOUNT_AGE_DAYS"),
+      max_sessions_per_account: parse_env("FLO_POLICY_MAX_SESSIONS_PER_ACCOUNT"),
+      flag_shared_ip: parse_env("FLO_POLICY_FLAG_SHARED_IP").unwrap_or_default(),
+      session: parse_env("FLO_POLICY_SESSION").unwrap_or_default(),
+    });
+    &INSTANCE
+  }
+
+  pub fn check_account_age(&self, created_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<()> {
+    match self.min_account_age_days {
+      Some(days) if now - created_at < Duration::days(days) => Err(Error::AccountTooNew(days)),
+      _ => Ok(()),
+    }
+  }
+
+  /// `online` is the number of other sessions of the same external account
+  pub fn check_sessions(&self, online: usize) -> Result<()> {
+    match self.max_sessions_per_account {
+      Some(max) if online >= max => Err(Error::TooManySessions),
+      _ => Ok(()),
+    }
+  }
+}
+
+fn parse_env<T: FromStr>(name: &str) -> Option<T> {
+  let value = env::var(name).ok()?;
+  match value.parse() {
+    Ok(v) => Some(v),
+    Err(_) => {
+      tracing::error!("invalid env `{}`: {}", name, value);
+      None
+    }
+  }
+}
+
+#[test]
+fn test_policy() {
+  use chrono::TimeZone;
+
+  let policy = Policy {
+    min_account_age_days: Some(7),
+    max_sessions_per_account: Some(1),
+    flag_shared_ip: false,
+    session: SessionPolicy::Reject,
+  };
+  let now = Utc.ymd(2021, 7, 20).and_hms(0, 0, 0);
+  assert!(policy
+    .check_account_age(Utc.ymd(2021, 7, 10).and_hms(0, 0, 0), now)
+    .is_ok());
+  assert!(policy
+    .check_account_age(Utc.ymd(2021, 7, 15).and_hms(0, 0, 0), now)
+    .is_err());
+  assert!(policy.check_sessions(0).is_ok());
+  assert!(policy.check_sessions(1).is_err());
+  assert_eq!(
+    policy.session.session_mode(false).unwrap(),
+    SessionMode::Primary
+  );
+  assert!(matches!(
+    policy.session.session_mode(true),
+    Err(Error::AlreadyConnected)
+  ));
+
+  let policy = Policy::default();
+  assert!(policy.check_account_age(now, now).is_ok());
+  assert!(policy.check_sessions(10).is_ok());
+  assert_eq!(
+    policy.session.session_mode(true).unwrap(),
+    SessionMode::Primary
+  );
+
+  let policy = Policy {
+    session: "read_only".parse().unwrap(),
+    ..Policy::default()
+  };
+  assert_eq!(
+    policy.session.session_mode(true).unwrap(),
+    SessionMode::ReadOnly
+  );
+}
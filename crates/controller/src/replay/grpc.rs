@@ -0,0 +1,61 @@
+use crate::config::FloGrpcInterceptor;
+use crate::error::Error;
+use crate::state::ControllerStateRef;
+use tonic::service::interceptor::InterceptedService;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+  tonic::include_proto!("flo_replay");
+}
+
+use proto::flo_replay_server::{FloReplay, FloReplayServer};
+use proto::*;
+
+pub fn server(
+  state: ControllerStateRef,
+  interceptor: FloGrpcInterceptor,
+) -> InterceptedService<FloReplayServer<FloReplayService>, FloGrpcInterceptor> {
+  FloReplayServer::with_interceptor(FloReplayService { state }, interceptor)
+}
+
+pub struct FloReplayService {
+  state: ControllerStateRef,
+}
+
+#[tonic::async_trait]
+impl FloReplay for FloReplayService {
+  async fn get_replay_url(
+    &self,
+    request: Request<GetReplayUrlRequest>,
+  ) -> Result<Response<GetReplayUrlReply>, Status> {
+    let is_admin = crate::admin::verify_admin_secret(
+      request
+        .metadata()
+        .get(crate::admin::REQUEST_META_ADMIN_SECRET)
+        .map(|v| v.as_bytes()),
+    )
+    .is_ok();
+    let GetReplayUrlRequest { game_id, player_id } = request.into_inner();
+    let replay_url = self
+      .state
+      .db
+      .exec(move |conn| {
+        let game = crate::game::db::get(conn, game_id)?;
+        if !is_admin && !crate::game::db::has_player(conn, game_id, player_id)? {
+          return Err(Error::ReplayAccessDenied);
+        }
+        let node_id = game
+          .node
+          .map(|node| node.id)
+          .ok_or_else(|| Error::GameNodeNotSelected)?;
+        let node = crate::node::db::get_node(conn, node_id)?;
+        crate::replay::get_url(game_id, game.status, &node)
+      })
+      .await
+      .map_err(Error::from)?;
+    Ok(Response::new(GetReplayUrlReply {
+      url: replay_url.url,
+      expires_at: replay_url.expires_at,
+    }))
+  }
+}
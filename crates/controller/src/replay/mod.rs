@@ -0,0 +1,119 @@
+pub(crate) mod grpc;
+
+use crate::error::*;
+use crate::game::GameStatus;
+use crate::node::Node;
+use once_cell::sync::Lazy;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::Region;
+use rusoto_s3::util::{PreSignedRequest, PreSignedRequestOption};
+use rusoto_s3::GetObjectRequest;
+use std::env;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+/// Where nodes store replays, must match the node replay storage config
+#[derive(Debug)]
+enum ReplayStorage {
+  /// Served by each node HTTP server
+  Node,
+  S3 {
+    bucket: String,
+    region: Region,
+    credentials: AwsCredentials,
+  },
+}
+
+static STORAGE: Lazy<ReplayStorage> = Lazy::new(|| {
+  let bucket = if let Ok(bucket) = env::var("FLO_REPLAY_S3_BUCKET") {
+    bucket
+  } else {
+    return ReplayStorage::Node;
+  };
+  let region_name = env::var("AWS_S3_REGION").unwrap_or_default();
+  let region = if let Ok(endpoint) = env::var("FLO_REPLAY_S3_ENDPOINT") {
+    Region::Custom {
+      name: region_name,
+      endpoint,
+    }
+  } else {
+    region_name.parse().unwrap_or_default()
+  };
+  ReplayStorage::S3 {
+    bucket,
+    region,
+    credentials: AwsCredentials::new(
+      env::var("AWS_ACCESS_KEY_ID").unwrap_or_default(),
+      env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default(),
+      None,
+      None,
+    ),
+  }
+});
+
+#[derive(Debug)]
+pub struct ReplayUrl {
+  pub url: String,
+  /// unix timestamp in seconds
+  pub expires_at: u32,
+}
+
+/// Returns a download link of the replay of an ended game hosted by `node`
+pub fn get_url(game_id: i32, status: GameStatus, node: &Node) -> Result<ReplayUrl> {
+  if status != GameStatus::Ended {
+    return Err(Error::GameNotEnded);
+  }
+
+  let expires_at =
+    flo_net::echo::unix_now().saturating_add(flo_net::replay::REPLAY_URL_TTL.as_secs() as u32);
+  let url = match *STORAGE {
+    ReplayStorage::Node => format!(
+      "http://{}{}",
+      node_http_addr(&node.ip_addr)?,
+      flo_net::replay::signed_path(&node.secret, game_id, expires_at)
+    ),
+    ReplayStorage::S3 {
+      ref bucket,
+      ref region,
+      ref credentials,
+    } => GetObjectRequest {
+      bucket: bucket.clone(),
+      key: flo_net::replay::object_key(game_id),
+      ..Default::default()
+    }
+    .get_presigned_url(
+      region,
+      credentials,
+      &PreSignedRequestOption {
+        expires_in: flo_net::replay::REPLAY_URL_TTL,
+      },
+    ),
+  };
+  Ok(ReplayUrl { url, expires_at })
+}
+
+// `ip_addr` can contain the echo port, other ports are at fixed offsets from it
+fn node_http_addr(ip_addr: &str) -> Result<SocketAddrV4> {
+  if let Ok(addr) = ip_addr.parse::<SocketAddrV4>() {
+    return Ok(SocketAddrV4::new(
+      *addr.ip(),
+      addr.port() + flo_constants::NODE_HTTP_PORT_OFFSET,
+    ));
+  }
+  ip_addr
+    .parse::<Ipv4Addr>()
+    .map(|ip| SocketAddrV4::new(ip, flo_constants::NODE_HTTP_PORT))
+    .map_err(|_| Error::InvalidNodeAddress(ip_addr.to_string()))
+}
+
+#[test]
+fn test_node_http_addr() {
+  assert_eq!(
+    node_http_addr("127.0.0.1").unwrap(),
+    "127.0.0.1:3555".parse().unwrap()
+  );
+  assert_eq!(
+    node_http_addr("127.0.0.1:4552").unwrap(),
+    "127.0.0.1:4555".parse().unwrap()
+  );
+  assert!(node_http_addr("localhost").is_err());
+}
@@ -1,3 +1,15 @@
+table! {
+    audit_event (id) {
+        id -> Int8,
+        kind -> Int4,
+        actor_kind -> Int4,
+        actor_id -> Nullable<Int4>,
+        game_id -> Nullable<Int4>,
+        payload -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     api_client (id) {
         id -> Int4,
@@ -28,6 +40,47 @@ table! {
         locked -> Bool,
         mask_player_names -> Bool,
         game_version -> Nullable<Text>,
+        traffic_stats -> Nullable<Jsonb>,
+        slots_version -> Int4,
+        name_folded -> Nullable<Text>,
+        node_reserved_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    game_create_request (api_client_id, request_id) {
+        api_client_id -> Int4,
+        request_id -> Text,
+        game_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    game_schedule (id) {
+        id -> Int4,
+        template_id -> Int4,
+        node_id -> Int4,
+        start_time -> Time,
+        open_minutes -> Int4,
+        min_players -> Int4,
+        game_id -> Nullable<Int4>,
+        game_start_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        host_player_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    game_template (id) {
+        id -> Int4,
+        player_id -> Int4,
+        name -> Text,
+        is_public -> Bool,
+        config -> Jsonb,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -48,6 +101,37 @@ table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         client_status_synced_node_conn_id -> Nullable<Int8>,
+        action_stats -> Nullable<Jsonb>,
+    }
+}
+
+table! {
+    maintenance_window (id) {
+        id -> Int4,
+        starts_at -> Timestamptz,
+        ends_at -> Timestamptz,
+        message -> Text,
+        drain_nodes -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    map_catalogue (id) {
+        id -> Int4,
+        sha1 -> Text,
+        name -> Text,
+        author -> Text,
+        description -> Text,
+        path -> Text,
+        map -> Jsonb,
+        hosted_count -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        previous_version_id -> Nullable<Int4>,
+        latest_version_id -> Nullable<Int4>,
+        sha256 -> Nullable<Text>,
+        file_info -> Nullable<Jsonb>,
     }
 }
 
@@ -59,6 +143,17 @@ table! {
     }
 }
 
+table! {
+    motd_item (id) {
+        id -> Int4,
+        kind -> Int4,
+        title -> Text,
+        body -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 table! {
     node (id) {
         id -> Int4,
@@ -70,6 +165,8 @@ table! {
         updated_at -> Timestamptz,
         country_id -> Text,
         disabled -> Bool,
+        region -> Text,
+        max_games -> Nullable<Int4>,
     }
 }
 
@@ -84,6 +181,8 @@ table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         api_client_id -> Int4,
+        rating -> Nullable<Int4>,
+        name_folded -> Nullable<Text>,
     }
 }
 
@@ -106,20 +205,73 @@ table! {
     }
 }
 
+table! {
+    player_preferences (player_id) {
+        player_id -> Int4,
+        race -> Nullable<Int4>,
+        color -> Nullable<Int4>,
+        node_region -> Nullable<Text>,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    webhook (id) {
+        id -> Int4,
+        url -> Text,
+        secret -> Text,
+        enabled -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    webhook_delivery (id) {
+        id -> Int8,
+        webhook_id -> Int4,
+        event -> Text,
+        payload -> Jsonb,
+        attempts -> Int4,
+        status_code -> Nullable<Int4>,
+        error -> Nullable<Text>,
+        delivered_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
 joinable!(game -> node (node_id));
+joinable!(game_create_request -> api_client (api_client_id));
+joinable!(game_create_request -> game (game_id));
 joinable!(game -> player (created_by));
+joinable!(game_schedule -> game (game_id));
+joinable!(game_schedule -> game_template (template_id));
+joinable!(game_schedule -> node (node_id));
+joinable!(game_template -> player (player_id));
 joinable!(game_used_slot -> game (game_id));
 joinable!(game_used_slot -> player (player_id));
 joinable!(player -> api_client (api_client_id));
 joinable!(player_ban -> player (player_id));
+joinable!(player_preferences -> player (player_id));
+joinable!(webhook_delivery -> webhook (webhook_id));
 
 allow_tables_to_appear_in_same_query!(
     api_client,
+    audit_event,
     game,
+    game_create_request,
+    game_schedule,
+    game_template,
     game_used_slot,
+    maintenance_window,
+    map_catalogue,
     map_checksum,
+    motd_item,
     node,
     player,
     player_ban,
     player_mute,
+    player_preferences,
+    webhook,
+    webhook_delivery,
 );
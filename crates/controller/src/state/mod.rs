@@ -1,6 +1,6 @@
 mod actor_map;
 
-use bs_diesel_utils::{Executor, ExecutorRef};
+use crate::db::{Executor, ExecutorRef};
 use flo_state::{Addr, Message, Registry};
 
 use std::sync::Arc;
@@ -12,6 +12,12 @@ use crate::node::NodeRegistry;
 use crate::player::state::PlayerRegistry;
 
 use crate::config::ConfigStorage;
+use crate::discord::DiscordBot;
+use crate::federation::FederationSync;
+use crate::game_schedule::GameScheduler;
+use crate::maintenance::Maintenance;
+use crate::motd::Motd;
+use crate::player::state::bus::{GetLobbyBus, LobbyBus};
 use crate::player::state::sender::PlayerRegistryHandle;
 pub use actor_map::{ActorMapExt, GetActorEntry};
 
@@ -27,14 +33,30 @@ pub struct ControllerState {
   pub games: Addr<GameRegistry>,
   pub players: Addr<PlayerRegistry>,
   pub player_packet_sender: PlayerRegistryHandle,
+  /// Routes game requests to the instance owning the game, see `LobbyBus`
+  pub bus: Option<LobbyBus>,
   pub config: Addr<ConfigStorage>,
+  pub maintenance: Addr<Maintenance>,
+  pub motd: Addr<Motd>,
+  pub scheduler: Addr<GameScheduler>,
+  pub discord: Addr<DiscordBot>,
+  pub federation: Addr<FederationSync>,
 }
 
 pub type ControllerStateRef = Arc<ControllerState>;
 
 impl ControllerState {
   pub async fn init() -> Result<Self> {
-    let db = Executor::env().into_ref();
+    crate::config::init_service_config()?;
+    // the urls can also come from the config file, so they are passed instead of `DATABASE_URL`
+    let config = crate::config::service_config();
+    let mut db = ExecutorRef::new(Executor::new(&config.database_url).into_ref());
+    if let Some(ref read_url) = config.database_read_url {
+      db = db.with_replica(Executor::new(read_url).into_ref());
+    }
+    if config.database_async_pool_size > 0 {
+      db = db.with_async_pool(&config.database_url, config.database_async_pool_size)?;
+    }
 
     #[cfg(not(debug_assertions))]
     {
@@ -45,8 +67,16 @@ impl ControllerState {
 
     let nodes = registry.resolve().await?;
     let games = registry.resolve().await?;
-    let players = registry.resolve().await?;
+    let players: Addr<PlayerRegistry> = registry.resolve().await?;
     let config = registry.resolve().await?;
+    let maintenance = registry.resolve().await?;
+    let motd = registry.resolve().await?;
+    let scheduler = registry.resolve().await?;
+    let discord = registry.resolve().await?;
+    let federation = registry.resolve().await?;
+    let bus = players.send(GetLobbyBus).await?;
+
+    crate::webhook::resume(&db);
 
     Ok(ControllerState {
       db,
@@ -55,7 +85,13 @@ impl ControllerState {
       games,
       players: players.clone(),
       player_packet_sender: PlayerRegistryHandle::from(players),
+      bus,
       config,
+      maintenance,
+      motd,
+      scheduler,
+      discord,
+      federation,
     })
   }
 
@@ -0,0 +1,58 @@
+//! Client of external filters implementing the `flo_text_filter.TextFilter` service.
+
+use flo_state::async_trait;
+use std::time::Duration;
+use tonic::transport::{Channel, Endpoint};
+
+use super::{TextFilter, TextKind, Verdict};
+use crate::error::*;
+
+pub mod proto {
+  tonic::include_proto!("flo_text_filter");
+}
+
+use proto::text_filter_client::TextFilterClient;
+
+pub struct GrpcTextFilter {
+  client: TextFilterClient<Channel>,
+}
+
+impl GrpcTextFilter {
+  /// Connects on the first check
+  pub fn new(url: &str, timeout_ms: u64) -> Result<Self> {
+    let channel = Endpoint::from_shared(url.to_string())
+      .map_err(|_| Error::TextFilterUrlInvalid(url.to_string()))?
+      .timeout(Duration::from_millis(timeout_ms))
+      .connect_lazy()?;
+    Ok(Self {
+      client: TextFilterClient::new(channel),
+    })
+  }
+}
+
+#[async_trait]
+impl TextFilter for GrpcTextFilter {
+  async fn check(&self, kind: TextKind, text: &str, player_id: Option<i32>) -> Result<Verdict> {
+    let kind = match kind {
+      TextKind::GameName => proto::TextKind::GameName,
+      TextKind::Chat => proto::TextKind::Chat,
+      TextKind::Description => proto::TextKind::Description,
+    };
+    let reply = self
+      .client
+      .clone()
+      .check_text(proto::CheckTextRequest {
+        kind: kind.into(),
+        text: text.to_string(),
+        player_id: player_id.unwrap_or_default(),
+      })
+      .await
+      .map_err(Error::TextFilter)?
+      .into_inner();
+    Ok(match reply.verdict() {
+      proto::TextVerdict::Allow => Verdict::Allow,
+      proto::TextVerdict::Mask => Verdict::Mask(reply.masked_text),
+      proto::TextVerdict::Block => Verdict::Block,
+    })
+  }
+}
@@ -0,0 +1,165 @@
+//! Checks the text set by players before it is saved or shown to others.
+//!
+//! The default filter blocks the words and patterns of `text_filter` in the service config.
+//! With `text_filter.grpc_url` set, the text that passed the word list is also checked by an
+//! external service, see `grpc`. Embedders can replace the whole filter with `set_filter`.
+//!
+//! Chat is relayed by the nodes, they mask it with the word list sent with each game, see
+//! `chat_filter`. The external filter is too slow for chat and isn't asked.
+
+pub mod grpc;
+
+use arc_swap::ArcSwapOption;
+use flo_config::ControllerConfig;
+use flo_state::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use crate::config::service_config;
+use crate::error::*;
+
+pub use flo_util::word_list::WordList;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextKind {
+  GameName,
+  Chat,
+  Description,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+  Allow,
+  /// Use this text instead
+  Mask(String),
+  Block,
+}
+
+#[async_trait]
+pub trait TextFilter: Send + Sync {
+  /// `player_id` is the player who set the text, if known
+  async fn check(&self, kind: TextKind, text: &str, player_id: Option<i32>) -> Result<Verdict>;
+}
+
+/// Replaces the filter built from the service config
+pub fn set_filter(filter: Arc<dyn TextFilter>) {
+  CUSTOM_FILTER.store(Some(Arc::new(filter)));
+}
+
+/// Runs the current filter, errors of external filters are logged and the text is allowed
+pub async fn check(kind: TextKind, text: &str, player_id: Option<i32>) -> Result<Verdict> {
+  let filter = current();
+  match filter.check(kind, text, player_id).await {
+    Ok(verdict) => Ok(verdict),
+    Err(Error::TextFilter(status)) => {
+      tracing::warn!("text filter: {}", status);
+      Ok(Verdict::Allow)
+    }
+    Err(err) => Err(err),
+  }
+}
+
+/// The text to save, or `blocked` if the text is not allowed
+pub async fn apply(
+  kind: TextKind,
+  text: String,
+  player_id: Option<i32>,
+  blocked: Error,
+) -> Result<String> {
+  if text.is_empty() {
+    return Ok(text);
+  }
+  match check(kind, &text, player_id).await? {
+    Verdict::Allow => Ok(text),
+    Verdict::Mask(masked) => Ok(masked),
+    Verdict::Block => Err(blocked),
+  }
+}
+
+/// The words and patterns of the service config, sent to the nodes hosting a game
+pub fn chat_filter() -> flo_net::proto::flo_node::ChatFilter {
+  let config = service_config();
+  flo_net::proto::flo_node::ChatFilter {
+    blocked_words: config.text_filter.blocked_words.clone(),
+    blocked_patterns: config.text_filter.blocked_patterns.clone(),
+  }
+}
+
+/// The word list of the service config, for text checked in synchronous code
+pub fn word_list() -> Arc<WordList> {
+  current_config().1
+}
+
+static CUSTOM_FILTER: Lazy<ArcSwapOption<Arc<dyn TextFilter>>> = Lazy::new(ArcSwapOption::empty);
+
+type ConfigFilter = (Arc<dyn TextFilter>, Arc<WordList>);
+
+// rebuilt when the service config is reloaded
+static CONFIG_FILTER: Lazy<Mutex<Option<(Arc<ControllerConfig>, ConfigFilter)>>> =
+  Lazy::new(|| Mutex::new(None));
+
+fn current() -> Arc<dyn TextFilter> {
+  if let Some(filter) = CUSTOM_FILTER.load_full() {
+    return (*filter).clone();
+  }
+  current_config().0
+}
+
+fn current_config() -> ConfigFilter {
+  let config = service_config();
+  let mut guard = CONFIG_FILTER.lock();
+  match guard.as_ref() {
+    Some((cached, filter)) if Arc::ptr_eq(cached, &config) => filter.clone(),
+    _ => {
+      let word_list = Arc::new(WordList::new(
+        &config.text_filter.blocked_words,
+        &config.text_filter.blocked_patterns,
+      ));
+      let filter: Arc<dyn TextFilter> = match config.text_filter.grpc_url {
+        Some(ref url) => match grpc::GrpcTextFilter::new(url, config.text_filter.grpc_timeout_ms) {
+          Ok(external) => Arc::new(Chain(word_list.clone(), external)),
+          Err(err) => {
+            tracing::error!("text filter: `{}`: {}", url, err);
+            word_list.clone()
+          }
+        },
+        None => word_list.clone(),
+      };
+      let filter = (filter, word_list);
+      guard.replace((config, filter.clone()));
+      filter
+    }
+  }
+}
+
+#[async_trait]
+impl TextFilter for WordList {
+  async fn check(&self, kind: TextKind, text: &str, _player_id: Option<i32>) -> Result<Verdict> {
+    if !self.is_blocked(text) {
+      return Ok(Verdict::Allow);
+    }
+    Ok(match kind {
+      // the room stays readable
+      TextKind::Chat => Verdict::Mask(self.mask(text)),
+      TextKind::GameName | TextKind::Description => Verdict::Block,
+    })
+  }
+}
+
+/// The external filter only sees the text the first one allowed, or masked
+struct Chain(Arc<WordList>, grpc::GrpcTextFilter);
+
+#[async_trait]
+impl TextFilter for Chain {
+  async fn check(&self, kind: TextKind, text: &str, player_id: Option<i32>) -> Result<Verdict> {
+    match self.0.check(kind, text, player_id).await? {
+      Verdict::Allow => self.1.check(kind, text, player_id).await,
+      Verdict::Mask(masked) => Ok(match self.1.check(kind, &masked, player_id).await? {
+        Verdict::Allow => Verdict::Mask(masked),
+        other => other,
+      }),
+      Verdict::Block => Ok(Verdict::Block),
+    }
+  }
+}
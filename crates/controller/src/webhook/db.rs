@@ -0,0 +1,217 @@
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::db::DbConn;
+use crate::error::*;
+use crate::schema::{webhook, webhook_delivery};
+use crate::webhook::{validate_url, Webhook, WebhookDelivery, WebhookEventKind};
+
+type WebhookColumns = (
+  webhook::id,
+  webhook::url,
+  webhook::secret,
+  webhook::enabled,
+  webhook::created_at,
+);
+
+const WEBHOOK_COLUMNS: WebhookColumns = (
+  webhook::id,
+  webhook::url,
+  webhook::secret,
+  webhook::enabled,
+  webhook::created_at,
+);
+
+pub fn list(conn: &DbConn) -> Result<Vec<Webhook>> {
+  Ok(
+    webhook::table
+      .order(webhook::id)
+      .select(WEBHOOK_COLUMNS)
+      .load(conn)?,
+  )
+}
+
+pub fn add(conn: &DbConn, url: &str, secret: &str) -> Result<Webhook> {
+  validate_url(url)?;
+  if secret.is_empty() {
+    return Err(Error::WebhookSecretEmpty);
+  }
+  Ok(
+    diesel::insert_into(webhook::table)
+      .values((webhook::url.eq(url), webhook::secret.eq(secret)))
+      .returning(WEBHOOK_COLUMNS)
+      .get_result(conn)?,
+  )
+}
+
+pub fn remove(conn: &DbConn, id: i32) -> Result<()> {
+  let n = diesel::delete(webhook::table.find(id)).execute(conn)?;
+  if n == 0 {
+    return Err(Error::WebhookNotFound);
+  }
+  Ok(())
+}
+
+#[derive(Debug)]
+pub struct DeliveryTarget {
+  pub delivery_id: i64,
+  pub url: String,
+  pub secret: String,
+}
+
+/// Logs a delivery of the event for every enabled webhook
+pub fn insert_deliveries(
+  conn: &DbConn,
+  kind: WebhookEventKind,
+  payload: Value,
+) -> Result<Vec<DeliveryTarget>> {
+  conn.transaction(|| {
+    let webhooks: Vec<(i32, String, String)> = webhook::table
+      .filter(webhook::enabled.eq(true))
+      .select((webhook::id, webhook::url, webhook::secret))
+      .load(conn)?;
+    if webhooks.is_empty() {
+      return Ok(vec![]);
+    }
+
+    let ids: Vec<i64> = diesel::insert_into(webhook_delivery::table)
+      .values(
+        webhooks
+          .iter()
+          .map(|(id, _, _)| {
+            (
+              webhook_delivery::webhook_id.eq(*id),
+              webhook_delivery::event.eq(kind.as_str()),
+              webhook_delivery::payload.eq(&payload),
+            )
+          })
+          .collect::<Vec<_>>(),
+      )
+      .returning(webhook_delivery::id)
+      .get_results(conn)?;
+
+    Ok(
+      ids
+        .into_iter()
+        .zip(webhooks)
+        .map(|(delivery_id, (_, url, secret))| DeliveryTarget {
+          delivery_id,
+          url,
+          secret,
+        })
+        .collect(),
+    )
+  })
+}
+
+/// A delivery not completed when the lobby stopped
+#[derive(Debug)]
+pub struct PendingDelivery {
+  pub target: DeliveryTarget,
+  pub event: String,
+  pub payload: Value,
+  pub attempts: i32,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Deliveries of enabled webhooks created after `since` and not delivered yet
+pub fn list_pending_deliveries(
+  conn: &DbConn,
+  since: DateTime<Utc>,
+) -> Result<Vec<PendingDelivery>> {
+  use webhook_delivery::dsl;
+  let rows: Vec<(i64, String, Value, i32, DateTime<Utc>, String, String)> = webhook_delivery::table
+    .inner_join(webhook::table)
+    .filter(
+      dsl::delivered_at
+        .is_null()
+        .and(dsl::created_at.gt(since))
+        .and(webhook::enabled.eq(true)),
+    )
+    .order(dsl::id)
+    .select((
+      dsl::id,
+      dsl::event,
+      dsl::payload,
+      dsl::attempts,
+      dsl::created_at,
+      webhook::url,
+      webhook::secret,
+    ))
+    .load(conn)?;
+  Ok(
+    rows
+      .into_iter()
+      .map(
+        |(delivery_id, event, payload, attempts, created_at, url, secret)| PendingDelivery {
+          target: DeliveryTarget {
+            delivery_id,
+            url,
+            secret,
+          },
+          event,
+          payload,
+          attempts,
+          created_at,
+        },
+      )
+      .collect(),
+  )
+}
+
+#[derive(Debug)]
+pub struct UpdateDelivery {
+  pub attempts: i32,
+  pub status_code: Option<i32>,
+  pub error: Option<String>,
+  pub delivered: bool,
+}
+
+pub fn update_delivery(conn: &DbConn, id: i64, update: UpdateDelivery) -> Result<()> {
+  use diesel::dsl::now;
+  use webhook_delivery::dsl;
+  diesel::update(webhook_delivery::table.find(id))
+    .set((
+      dsl::attempts.eq(update.attempts),
+      dsl::status_code.eq(update.status_code),
+      dsl::error.eq(update.error),
+    ))
+    .execute(conn)?;
+  if update.delivered {
+    diesel::update(webhook_delivery::table.find(id))
+      .set(dsl::delivered_at.eq(now))
+      .execute(conn)?;
+  }
+  Ok(())
+}
+
+/// Lists deliveries of a webhook newest first
+pub fn list_deliveries(
+  conn: &DbConn,
+  webhook_id: i32,
+  take: Option<i64>,
+) -> Result<Vec<WebhookDelivery>> {
+  use webhook_delivery::dsl;
+  const MAX_TAKE: i64 = 100;
+
+  let take = take.unwrap_or(MAX_TAKE).max(1).min(MAX_TAKE);
+  Ok(
+    webhook_delivery::table
+      .filter(dsl::webhook_id.eq(webhook_id))
+      .order(dsl::id.desc())
+      .limit(take)
+      .select((
+        dsl::id,
+        dsl::webhook_id,
+        dsl::event,
+        dsl::payload,
+        dsl::attempts,
+        dsl::status_code,
+        dsl::error,
+        dsl::delivered_at,
+        dsl::created_at,
+      ))
+      .load(conn)?,
+  )
+}
@@ -0,0 +1,283 @@
+//! Game lifecycle events POSTed to the URLs registered by admins.
+//!
+//! The body is a JSON object `{ "event", "created_at", "data" }`, signed with the
+//! webhook's secret: `x-flo-timestamp` is the unix time of the attempt and
+//! `x-flo-signature: sha256=<hex HMAC-SHA256 of "<timestamp>\n<body>">`. Receivers should
+//! reject old timestamps, a replayed request can't be signed again.
+//!
+//! Every delivery is logged and failed ones are retried with exponential backoff, for up
+//! to `MAX_RETRY_ELAPSED` after the event. Deliveries not completed when the lobby stopped
+//! are resumed by `resume` on startup.
+
+pub mod db;
+
+use crate::db::ExecutorRef;
+use crate::error::*;
+use backoff::backoff::Backoff;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, StatusCode, Uri};
+use hyper_tls::HttpsConnector;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::fmt::Write;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Deliveries are given up after this long
+const MAX_RETRY_ELAPSED: Duration = Duration::from_secs(6 * 3600);
+
+pub const HEADER_EVENT: &str = "x-flo-event";
+pub const HEADER_DELIVERY: &str = "x-flo-delivery";
+pub const HEADER_TIMESTAMP: &str = "x-flo-timestamp";
+pub const HEADER_SIGNATURE: &str = "x-flo-signature";
+
+static CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> =
+  Lazy::new(|| Client::builder().build(HttpsConnector::new()));
+
+#[derive(Debug, Serialize, Copy, Clone, PartialEq)]
+pub enum WebhookEventKind {
+  #[serde(rename = "game.created")]
+  GameCreated,
+  #[serde(rename = "game.started")]
+  GameStarted,
+  #[serde(rename = "game.finished")]
+  GameFinished,
+  /// Cancelled before it started, or terminated
+  #[serde(rename = "game.aborted")]
+  GameAborted,
+  #[serde(rename = "player.banned")]
+  PlayerBanned,
+}
+
+impl WebhookEventKind {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      WebhookEventKind::GameCreated => "game.created",
+      WebhookEventKind::GameStarted => "game.started",
+      WebhookEventKind::GameFinished => "game.finished",
+      WebhookEventKind::GameAborted => "game.aborted",
+      WebhookEventKind::PlayerBanned => "player.banned",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct Webhook {
+  pub id: i32,
+  pub url: String,
+  #[serde(skip)]
+  pub secret: String,
+  pub enabled: bool,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Queryable)]
+pub struct WebhookDelivery {
+  pub id: i64,
+  pub webhook_id: i32,
+  pub event: String,
+  pub payload: Value,
+  pub attempts: i32,
+  pub status_code: Option<i32>,
+  pub error: Option<String>,
+  pub delivered_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Only http and https URLs with a host are accepted
+pub fn validate_url(url: &str) -> Result<()> {
+  let uri: Uri = url.parse().map_err(|_| Error::WebhookUrlInvalid)?;
+  match (uri.scheme_str(), uri.host()) {
+    (Some("http"), Some(_)) | (Some("https"), Some(_)) => Ok(()),
+    _ => Err(Error::WebhookUrlInvalid),
+  }
+}
+
+/// Sends the event to all enabled webhooks.
+/// Deliveries happen in the background, failures are logged and never affect the caller.
+pub fn emit(db: &ExecutorRef, kind: WebhookEventKind, data: Value) {
+  let db = db.clone();
+  tokio::spawn(async move {
+    let payload = json!({
+      "event": kind,
+      "created_at": Utc::now(),
+      "data": data,
+    });
+    let body = payload.to_string();
+    let deliveries = match db
+      .exec(move |conn| db::insert_deliveries(conn, kind, payload))
+      .await
+    {
+      Ok(deliveries) => deliveries,
+      Err(err) => {
+        tracing::error!("webhook: insert deliveries {:?}: {}", kind, err);
+        return;
+      }
+    };
+    for delivery in deliveries {
+      tokio::spawn(deliver(
+        db.clone(),
+        kind.as_str().to_string(),
+        delivery,
+        body.clone(),
+        0,
+        MAX_RETRY_ELAPSED,
+      ));
+    }
+  });
+}
+
+/// Resumes the deliveries not completed when the lobby stopped, called on startup
+pub fn resume(db: &ExecutorRef) {
+  let db = db.clone();
+  tokio::spawn(async move {
+    let since = Utc::now() - chrono::Duration::from_std(MAX_RETRY_ELAPSED).unwrap();
+    let deliveries = match db
+      .exec(move |conn| db::list_pending_deliveries(conn, since))
+      .await
+    {
+      Ok(deliveries) => deliveries,
+      Err(err) => {
+        tracing::error!("webhook: list pending deliveries: {}", err);
+        return;
+      }
+    };
+    if !deliveries.is_empty() {
+      tracing::info!("webhook: resuming {} deliveries", deliveries.len());
+    }
+    for delivery in deliveries {
+      let elapsed = (Utc::now() - delivery.created_at)
+        .to_std()
+        .unwrap_or_default();
+      tokio::spawn(deliver(
+        db.clone(),
+        delivery.event,
+        delivery.target,
+        delivery.payload.to_string(),
+        delivery.attempts,
+        MAX_RETRY_ELAPSED.saturating_sub(elapsed),
+      ));
+    }
+  });
+}
+
+/// Retries until delivered or `max_elapsed` has passed, `attempts` were made before
+async fn deliver(
+  db: ExecutorRef,
+  event: String,
+  target: db::DeliveryTarget,
+  body: String,
+  mut attempts: i32,
+  max_elapsed: Duration,
+) {
+  let mut backoff = backoff::ExponentialBackoff {
+    max_interval: MAX_RETRY_INTERVAL,
+    max_elapsed_time: Some(max_elapsed),
+    ..Default::default()
+  };
+  loop {
+    attempts += 1;
+    let (status_code, error) = match post(&event, &target, &body).await {
+      Ok(status) if status.is_success() => (Some(status.as_u16() as i32), None),
+      Ok(status) => (
+        Some(status.as_u16() as i32),
+        Some(format!("status {}", status)),
+      ),
+      Err(err) => (None, Some(err.to_string())),
+    };
+    let retry_after = if error.is_some() {
+      backoff.next_backoff()
+    } else {
+      None
+    };
+
+    if let Some(ref error) = error {
+      tracing::warn!(
+        delivery_id = target.delivery_id,
+        attempts,
+        "webhook: deliver {}: {}",
+        event,
+        error
+      );
+    }
+    let delivery_id = target.delivery_id;
+    let update = db::UpdateDelivery {
+      attempts,
+      status_code,
+      delivered: error.is_none(),
+      error,
+    };
+    if let Err(err) = db
+      .exec(move |conn| db::update_delivery(conn, delivery_id, update))
+      .await
+    {
+      tracing::error!(delivery_id, "webhook: update delivery: {}", err);
+    }
+
+    match retry_after {
+      Some(duration) => tokio::time::sleep(duration).await,
+      None => break,
+    }
+  }
+}
+
+async fn post(event: &str, target: &db::DeliveryTarget, body: &str) -> Result<StatusCode> {
+  let timestamp = Utc::now().timestamp();
+  let req = hyper::Request::builder()
+    .method(Method::POST)
+    .uri(target.url.as_str())
+    .header(hyper::header::CONTENT_TYPE, "application/json")
+    .header(HEADER_EVENT, event)
+    .header(HEADER_DELIVERY, target.delivery_id.to_string())
+    .header(HEADER_TIMESTAMP, timestamp.to_string())
+    .header(
+      HEADER_SIGNATURE,
+      sign(&target.secret, &signing_message(timestamp, body)),
+    )
+    .body(Body::from(body.to_string()))
+    .map_err(|_| Error::WebhookUrlInvalid)?;
+  let res = tokio::time::timeout(REQUEST_TIMEOUT, CLIENT.request(req))
+    .await
+    .map_err(|_| Error::Timeout(anyhow::format_err!("webhook request timeout")))??;
+  Ok(res.status())
+}
+
+fn signing_message(timestamp: i64, body: &str) -> Vec<u8> {
+  format!("{}\n{}", timestamp, body).into_bytes()
+}
+
+pub(crate) fn sign(secret: &str, body: &[u8]) -> String {
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+  mac.update(body);
+  let mut s = String::from("sha256=");
+  for b in mac.finalize().into_bytes().iter() {
+    write!(s, "{:02x}", b).ok();
+  }
+  s
+}
+
+#[test]
+fn test_webhook() {
+  // RFC 4231 test case 2
+  assert_eq!(
+    sign("Jefe", b"what do ya want for nothing?"),
+    "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+  );
+  assert_eq!(signing_message(1627776000, "{}"), b"1627776000\n{}");
+  assert_eq!(
+    serde_json::to_value(WebhookEventKind::GameAborted).unwrap(),
+    WebhookEventKind::GameAborted.as_str()
+  );
+
+  assert!(validate_url("https://example.com/flo/hook").is_ok());
+  assert!(validate_url("http://127.0.0.1:8080").is_ok());
+  assert!(validate_url("ftp://example.com").is_err());
+  assert!(validate_url("/flo/hook").is_err());
+  assert!(validate_url("not a url").is_err());
+}
@@ -16,8 +16,10 @@ pub enum Error {
   ReplayInvalidGameInfoRecord,
   #[error("string contains null byte")]
   NullByteInString,
+  #[error("limit exceeded: {0}")]
+  LimitExceeded(flo_util::binary::BinDecodeError),
   #[error("bin decode: {0}")]
-  BinDecode(#[from] flo_util::binary::BinDecodeError),
+  BinDecode(flo_util::binary::BinDecodeError),
   #[error("w3gs: {0}")]
   W3GS(#[from] flo_w3gs::error::Error),
   #[error("platform: {0}")]
@@ -34,4 +36,14 @@ pub enum Error {
   Io(#[from] std::io::Error),
 }
 
+impl From<flo_util::binary::BinDecodeError> for Error {
+  fn from(err: flo_util::binary::BinDecodeError) -> Self {
+    if err.is_limit_exceeded() {
+      Error::LimitExceeded(err)
+    } else {
+      Error::BinDecode(err)
+    }
+  }
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -12,6 +12,10 @@ use crate::error::*;
 use crate::proto;
 use flo_w3gs::protocol::constants::GameSettingFlags;
 
+/// Game info records larger than this are rejected without being parsed
+const MAX_GAME_INFO_LEN: usize = 16 * 1024;
+const MAX_PLAYERS: u8 = 24;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct GameInfo {
   pub(crate) message_id: i32,
@@ -33,7 +37,7 @@ impl GameInfo {
     map_sha1: [u8; 20],
     map_checksum: u32,
   ) -> Result<Self> {
-    let name = CString::new(name).map_err(|_| Error::NullByteInString)?;
+    let name = flo_util::name::to_c_string(name, flo_util::name::MAX_GAME_NAME_LEN);
     Ok(GameInfo {
       message_id: 0,
       game_id: id.to_string(),
@@ -166,9 +170,8 @@ impl GameInfo {
   }
 
   pub fn decode_bytes(bytes: &[u8]) -> Result<Self> {
-    use prost::Message;
     use std::collections::HashMap;
-    let message: proto::GameInfo = Message::decode(bytes)?;
+    let message = decode_message(bytes)?;
     let entries: HashMap<&str, &str> = message
       .entries
       .iter()
@@ -178,8 +181,7 @@ impl GameInfo {
       .get(&"game_data")
       .cloned()
       .ok_or_else(|| Error::InvalidGameInfo("no `game_data` entry"))?;
-    let data_bytes = base64::decode(data_b64)?;
-    let game_data = GameData::decode(&mut data_bytes.as_ref())?;
+    let game_data = GameData::decode_base64(data_b64)?;
     let game_id = entries
       .get(&"game_id")
       .cloned()
@@ -214,6 +216,13 @@ impl GameInfo {
       .ok_or_else(|| Error::InvalidGameInfo("no `players_max` entry"))?
       .parse()
       .map_err(|_| Error::InvalidGameInfo("invalid `players_max`"))?;
+    for (name, value) in &[("players_num", players_num), ("players_max", players_max)] {
+      if *value > MAX_PLAYERS {
+        return Err(Error::LimitExceeded(
+          BinDecodeError::limit_exceeded(MAX_PLAYERS as usize).context(name),
+        ));
+      }
+    }
     Ok(Self {
       message_id: message.message_id,
       game_id: game_id.to_string(),
@@ -229,6 +238,28 @@ impl GameInfo {
   pub fn set_port(&mut self, port: u16) {
     self.data.port = port;
   }
+
+  /// The entries of an encoded record as they are, to inspect records that fail to decode
+  pub fn decode_entries(bytes: &[u8]) -> Result<Vec<(String, String)>> {
+    let message = decode_message(bytes)?;
+    Ok(
+      message
+        .entries
+        .into_iter()
+        .map(|e| (e.key, e.value))
+        .collect(),
+    )
+  }
+}
+
+fn decode_message(bytes: &[u8]) -> Result<proto::GameInfo> {
+  use prost::Message;
+  if bytes.len() > MAX_GAME_INFO_LEN {
+    return Err(Error::LimitExceeded(
+      BinDecodeError::limit_exceeded(MAX_GAME_INFO_LEN).context("GameInfo"),
+    ));
+  }
+  Ok(Message::decode(bytes)?)
 }
 
 #[derive(Debug, BinEncode, BinDecode, PartialEq, Clone)]
@@ -237,12 +268,38 @@ pub struct GameData {
   #[bin(eq = 0)]
   _unknown_byte: u8,
   pub settings: GameSettings,
+  #[bin(max = 24)]
   pub slots_total: u32,
   #[bin(bitflags(u32))]
   pub flags: GameFlags,
   pub port: u16,
 }
 
+impl GameData {
+  /// Decodes the base64 `game_data` entry of a game info record
+  pub fn decode_base64(data: &str) -> Result<Self> {
+    let bytes = base64::decode(data.trim())?;
+    Ok(GameData::decode(&mut bytes.as_slice())?)
+  }
+
+  /// Reforged clients check both the game flags and the game settings
+  pub fn classic_graphics_only(&self) -> bool {
+    self.flags.contains(GameFlags::CLASSIC_GRAPHICS)
+      || self
+        .settings
+        .game_setting_flags
+        .contains(GameSettingFlags::CLASSIC_GRAPHICS)
+  }
+
+  pub fn set_classic_graphics_only(&mut self, value: bool) {
+    self.flags.set(GameFlags::CLASSIC_GRAPHICS, value);
+    self
+      .settings
+      .game_setting_flags
+      .set(GameSettingFlags::CLASSIC_GRAPHICS, value);
+  }
+}
+
 #[test]
 fn test_decode_protobuf_gameinfo() {
   use super::proto;
@@ -261,6 +318,22 @@ fn test_decode_gameinfo_check() {
   println!("{:#?}", v);
 }
 
+#[test]
+fn test_decode_gameinfo_entries() {
+  for bytes in &[
+    include_bytes!("../../../../deps/wc3-samples/lan/gameinfo_melee.bin") as &[u8],
+    include_bytes!("../../../../deps/wc3-samples/lan/gameinfo_check.bin") as &[u8],
+  ] {
+    let entries = GameInfo::decode_entries(bytes).unwrap();
+    let game_data = entries
+      .iter()
+      .find(|(key, _)| key == "game_data")
+      .map(|(_, value)| value)
+      .unwrap();
+    GameData::decode_base64(game_data).unwrap();
+  }
+}
+
 #[test]
 fn test_encode_gameinfo() {
   let bytes = include_bytes!("../../../../deps/wc3-samples/lan/gameinfo_melee.bin") as &[u8];
@@ -289,3 +362,32 @@ fn test_decode_gamedata_2() {
   let data = GameData::decode(&mut bytes.as_slice()).unwrap();
   println!("{:#?}", data);
 }
+
+#[test]
+fn test_decode_gamedata_base64() {
+  let data = "YidiJ2InYgAAAQNJBwEBoQHxSQFXMYt5TZthcXMvKTMprWNvb3V5Y2G7eS93M20BMScxMQEByeVvKddX/4+NjWFvjTkDbz8b+wMLHcMAAgAAAAnAQgCk7g==";
+  let decoded = GameData::decode_base64(data).unwrap();
+  // pasted with surrounding whitespace
+  assert_eq!(
+    GameData::decode_base64(&format!(" {}\n", data)).unwrap(),
+    decoded
+  );
+}
+
+#[test]
+fn test_classic_graphics_only() {
+  let mut info = GameInfo::new(1, "game", "maps\\(2)EchoIsles.w3x", [0; 20], 0).unwrap();
+  assert!(!info.data.classic_graphics_only());
+  info.data.set_classic_graphics_only(true);
+  let bytes = info.data.encode_to_bytes();
+  let decoded = GameData::decode(&mut bytes.as_slice()).unwrap();
+  assert!(decoded
+    .flags
+    .contains(GameFlags::CLASSIC_GRAPHICS | GameFlags::OBS_FULL));
+  assert!(decoded
+    .settings
+    .game_setting_flags
+    .contains(GameSettingFlags::CLASSIC_GRAPHICS));
+  info.data.set_classic_graphics_only(false);
+  assert!(!info.data.classic_graphics_only());
+}
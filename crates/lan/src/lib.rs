@@ -6,6 +6,6 @@ mod proto {
 
 pub mod error;
 
-pub use self::game_info::GameInfo;
+pub use self::game_info::{GameData, GameInfo};
 pub use self::mdns::publisher::MdnsPublisher;
 pub use self::mdns::search::{search_lan_games, LanGame};
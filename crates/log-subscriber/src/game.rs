@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+/// Files are reopened in append mode once evicted
+const MAX_OPEN_FILES: usize = 64;
+
+/// Writes every event recorded inside a span with a `game_id` field,
+/// or carrying the field itself, to `<dir>/game-<game_id>.log`.
+pub struct GameLogLayer {
+  dir: PathBuf,
+  files: Mutex<HashMap<i32, File>>,
+}
+
+impl GameLogLayer {
+  pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+    Self {
+      dir: dir.into(),
+      files: Mutex::new(HashMap::new()),
+    }
+  }
+
+  fn write_line(&self, game_id: i32, line: &str) {
+    let mut files = match self.files.lock() {
+      Ok(files) => files,
+      Err(_) => return,
+    };
+    if !files.contains_key(&game_id) && files.len() >= MAX_OPEN_FILES {
+      files.clear();
+    }
+    let file = match files.get_mut(&game_id) {
+      Some(file) => file,
+      None => {
+        // logging here would recurse into this layer
+        if std::fs::create_dir_all(&self.dir).is_err() {
+          return;
+        }
+        let file = match OpenOptions::new()
+          .create(true)
+          .append(true)
+          .open(self.dir.join(format!("game-{}.log", game_id)))
+        {
+          Ok(file) => file,
+          Err(_) => return,
+        };
+        files.entry(game_id).or_insert(file)
+      }
+    };
+    file.write_all(line.as_bytes()).ok();
+  }
+}
+
+/// The `game_id` and formatted fields of a span
+struct SpanFields {
+  game_id: Option<i32>,
+  fields: String,
+}
+
+impl<S> Layer<S> for GameLogLayer
+where
+  S: Subscriber + for<'a> LookupSpan<'a>,
+{
+  fn new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+    let mut visitor = FieldsVisitor::default();
+    attrs.record(&mut visitor);
+    if let Some(span) = ctx.span(id) {
+      span.extensions_mut().insert(SpanFields {
+        game_id: visitor.game_id,
+        fields: visitor.fields,
+      });
+    }
+  }
+
+  fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+    let span = match ctx.span(id) {
+      Some(span) => span,
+      None => return,
+    };
+    let mut extensions = span.extensions_mut();
+    if let Some(fields) = extensions.get_mut::<SpanFields>() {
+      let mut visitor = FieldsVisitor {
+        game_id: fields.game_id,
+        fields: std::mem::take(&mut fields.fields),
+      };
+      values.record(&mut visitor);
+      fields.game_id = visitor.game_id;
+      fields.fields = visitor.fields;
+    }
+  }
+
+  fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+    let mut visitor = FieldsVisitor::default();
+    event.record(&mut visitor);
+
+    let leaf = if event.is_contextual() {
+      ctx.lookup_current()
+    } else {
+      event.parent().and_then(|id| ctx.span(id))
+    };
+
+    let mut game_id = visitor.game_id;
+    let mut spans = vec![];
+    let mut next = leaf;
+    while let Some(span) = next {
+      if let Some(fields) = span.extensions().get::<SpanFields>() {
+        game_id = game_id.or(fields.game_id);
+        spans.push(format_span(&span, &fields.fields));
+      }
+      next = span.parent();
+    }
+
+    let game_id = match game_id {
+      Some(id) => id,
+      None => return,
+    };
+
+    let meta = event.metadata();
+    let mut line = String::new();
+    SystemTime.format_time(&mut line).ok();
+    write!(line, " {:>5} ", meta.level()).ok();
+    for span in spans.iter().rev() {
+      write!(line, "{}:", span).ok();
+    }
+    if !spans.is_empty() {
+      line.push(' ');
+    }
+    write!(line, "{}:{}", meta.target(), visitor.fields).ok();
+    line.push('\n');
+
+    self.write_line(game_id, &line);
+  }
+}
+
+fn format_span<S>(span: &SpanRef<'_, S>, fields: &str) -> String
+where
+  S: for<'a> LookupSpan<'a>,
+{
+  if fields.is_empty() {
+    span.name().to_string()
+  } else {
+    format!("{}{{{}}}", span.name(), fields.trim_start())
+  }
+}
+
+#[derive(Default)]
+struct FieldsVisitor {
+  game_id: Option<i32>,
+  fields: String,
+}
+
+impl Visit for FieldsVisitor {
+  fn record_i64(&mut self, field: &Field, value: i64) {
+    if field.name() == "game_id" {
+      self.game_id = Some(value as i32);
+    }
+    self.record_debug(field, &value)
+  }
+
+  fn record_u64(&mut self, field: &Field, value: u64) {
+    if field.name() == "game_id" {
+      self.game_id = Some(value as i32);
+    }
+    self.record_debug(field, &value)
+  }
+
+  fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+    if field.name() == "message" {
+      write!(self.fields, " {:?}", value).ok();
+    } else {
+      write!(self.fields, " {}={:?}", field.name(), value).ok();
+    }
+  }
+}
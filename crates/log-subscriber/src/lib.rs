@@ -1,19 +1,108 @@
 use std::sync::Once;
 pub use tracing::{debug, error, info, instrument, span, warn, Level};
 pub use tracing_futures::Instrument;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+
+mod game;
+pub use game::GameLogLayer;
+
+/// Set to `json` to emit one JSON object per line, with the fields of the current spans
+pub const LOG_FORMAT_ENV: &str = "FLO_LOG_FORMAT";
+/// A directory to also write the logs of each game into, see `GameLogLayer`
+pub const LOG_GAME_DIR_ENV: &str = "FLO_LOG_GAME_DIR";
+/// `host:port` of a Jaeger agent to export spans to, requires the `jaeger` feature
+pub const JAEGER_AGENT_ENV: &str = "FLO_JAEGER_AGENT";
 
 static INIT: Once = Once::new();
 
 pub fn init() {
   INIT.call_once(|| {
-    #[cfg(debug_assertions)]
-    tracing_subscriber::fmt::init();
+    let json = std::env::var(LOG_FORMAT_ENV)
+      .map(|v| v.eq_ignore_ascii_case("json"))
+      .unwrap_or(false);
+    let game_log = std::env::var_os(LOG_GAME_DIR_ENV).map(GameLogLayer::new);
+
+    // logged once the subscriber is installed
+    let (jaeger, jaeger_err) = match jaeger::layer() {
+      Ok(layer) => (layer, None),
+      Err(err) => (None, Some(err)),
+    };
+
+    // everything at info and above if RUST_LOG is unset
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry()
+      .with(filter)
+      .with(game_log)
+      .with(jaeger);
 
-    #[cfg(not(debug_assertions))]
-    tracing_subscriber::fmt::fmt().with_ansi(false).init();
+    if json {
+      registry
+        .with(
+          fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true),
+        )
+        .init();
+    } else {
+      registry
+        .with(fmt::layer().with_ansi(cfg!(debug_assertions)))
+        .init();
+    }
+
+    if let Some(err) = jaeger_err {
+      tracing::error!("jaeger: {}", err);
+    }
   });
 }
 
+#[cfg(feature = "jaeger")]
+mod jaeger {
+  use opentelemetry::sdk::propagation::TraceContextPropagator;
+  use tracing_opentelemetry::OpenTelemetryLayer;
+  use tracing_subscriber::registry::LookupSpan;
+
+  pub type Error = opentelemetry::trace::TraceError;
+
+  /// The service name is `OTEL_SERVICE_NAME`, or the executable name
+  pub fn layer<S>(
+  ) -> Result<Option<OpenTelemetryLayer<S, opentelemetry::sdk::trace::Tracer>>, Error>
+  where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+  {
+    let agent = match std::env::var(super::JAEGER_AGENT_ENV) {
+      Ok(agent) => agent,
+      Err(_) => return Ok(None),
+    };
+    let service_name = match std::env::var("OTEL_SERVICE_NAME").ok().or_else(|| {
+      std::env::current_exe()
+        .ok()
+        .and_then(|path| path.file_stem().map(|v| v.to_string_lossy().to_string()))
+    }) {
+      Some(name) => name,
+      None => return Ok(None),
+    };
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let tracer = opentelemetry_jaeger::new_pipeline()
+      .with_agent_endpoint(agent)
+      .with_service_name(service_name)
+      .install_batch(opentelemetry::runtime::Tokio)?;
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+  }
+}
+
+#[cfg(not(feature = "jaeger"))]
+mod jaeger {
+  pub type Error = std::convert::Infallible;
+
+  pub fn layer() -> Result<Option<tracing_subscriber::layer::Identity>, Error> {
+    Ok(None)
+  }
+}
+
 pub fn init_env_override(env: &str) {
   std::env::set_var("RUST_LOG", env);
   init();
@@ -1,6 +1,11 @@
 fn main() {
   let mut prost_build = prost_build::Config::new();
   prost_build.type_attribute(".", "#[derive(Serialize, Deserialize)]");
+  // added after clients started sending the message
+  prost_build.field_attribute(
+    ".flo_connect.PacketGameStartRequest.ready_check",
+    "#[serde(default)]",
+  );
   prost_build
     .compile_protos(
       &[
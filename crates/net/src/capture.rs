@@ -0,0 +1,103 @@
+//! Opt-in frame capture.
+//!
+//! When enabled, every frame sent or received through a `FloStream` is logged under the
+//! `flo_capture` tracing target with its direction, type, length and a hexdump of the payload.
+//! The payloads of the packets carrying secrets or tokens are not dumped, see `is_redacted`.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pretty_hex::PrettyHex;
+
+use crate::packet::{Frame, FramePayload, PacketTypeId};
+
+/// Tracing target of captured frames
+pub const TRACING_TARGET: &str = "flo_capture";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+  ENABLED.store(enabled, Ordering::Relaxed);
+  tracing::info!("packet capture enabled: {}", enabled);
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+  ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+  Send,
+  Recv,
+}
+
+pub(crate) fn capture_frame(peer: Option<SocketAddr>, direction: Direction, frame: &Frame) {
+  let peer = peer
+    .map(|addr| addr.to_string())
+    .unwrap_or_else(|| "-".to_string());
+  match frame.payload {
+    FramePayload::Bytes(ref bytes) if is_redacted(frame.type_id) => {
+      tracing::debug!(
+        target: TRACING_TARGET,
+        "{} {:?} {:?} len = {} (redacted)",
+        peer,
+        direction,
+        frame.type_id,
+        bytes.len()
+      );
+    }
+    FramePayload::Bytes(ref bytes) => {
+      tracing::debug!(
+        target: TRACING_TARGET,
+        "{} {:?} {:?} len = {}\n{:?}",
+        peer,
+        direction,
+        frame.type_id,
+        bytes.len(),
+        bytes.as_ref().hex_dump()
+      );
+    }
+    FramePayload::W3GS {
+      ref metadata,
+      ref payload,
+    } => {
+      tracing::debug!(
+        target: TRACING_TARGET,
+        "{} {:?} {:?}({:?}) sid = {} len = {}\n{:?}",
+        peer,
+        direction,
+        frame.type_id,
+        metadata.type_id(),
+        metadata.sid(),
+        payload.len(),
+        payload.as_ref().hex_dump()
+      );
+    }
+  }
+}
+
+/// Packets with a connect secret, a session token or a node token.
+/// The ping tokens of the node lists only tag echo datagrams and are kept.
+fn is_redacted(type_id: PacketTypeId) -> bool {
+  matches!(
+    type_id,
+    PacketTypeId::ConnectController
+      | PacketTypeId::GamePlayerToken
+      | PacketTypeId::GameInvite
+      | PacketTypeId::GameInviteAcceptRequest
+      | PacketTypeId::GameRemoteJoin
+      | PacketTypeId::ControllerConnect
+      | PacketTypeId::ControllerCreateGameAccept
+      | PacketTypeId::ClientConnect
+      | PacketTypeId::ObserverConnect
+  )
+}
+
+#[test]
+fn test_is_redacted() {
+  assert!(is_redacted(PacketTypeId::ConnectController));
+  assert!(is_redacted(PacketTypeId::ClientConnect));
+  assert!(!is_redacted(PacketTypeId::GameInfo));
+  assert!(!is_redacted(PacketTypeId::W3GS));
+}
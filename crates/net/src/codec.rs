@@ -1,8 +1,10 @@
 use bytes::{Buf, Bytes, BytesMut};
+use std::net::SocketAddr;
 use tokio_util::codec::{Decoder, Encoder};
 
 use flo_util::binary::BinDecode;
 
+use crate::capture::{self, Direction};
 use crate::constants::MAX_PAYLOAD_LEN;
 use crate::error::Error;
 use crate::packet::{Frame, FramePayload, Header, PacketTypeId};
@@ -11,12 +13,29 @@ use crate::w3gs::W3GSMetadata;
 #[derive(Debug)]
 pub struct FloFrameCodec {
   decode_state: DecoderState,
+  peer: Option<SocketAddr>,
 }
 
 impl FloFrameCodec {
   pub fn new() -> Self {
     Self {
       decode_state: DecoderState::DecodingHeader,
+      peer: None,
+    }
+  }
+
+  /// `peer` is only used to label captured frames
+  pub fn with_peer(peer: Option<SocketAddr>) -> Self {
+    Self {
+      peer,
+      ..Self::new()
+    }
+  }
+
+  #[inline]
+  fn capture(&self, direction: Direction, frame: &Frame) {
+    if capture::is_enabled() {
+      capture::capture_frame(self.peer, direction, frame);
     }
   }
 }
@@ -38,10 +57,9 @@ impl Decoder for FloFrameCodec {
 
           if src.remaining() >= payload_len {
             // payload received
-            Ok(Some(Self::frame(
-              header.type_id,
-              src.split_to(payload_len).freeze(),
-            )?))
+            let frame = Self::frame(header.type_id, src.split_to(payload_len).freeze())?;
+            self.capture(Direction::Recv, &frame);
+            Ok(Some(frame))
           } else {
             // wait payload
             src.reserve(payload_len);
@@ -65,6 +83,7 @@ impl Decoder for FloFrameCodec {
           let payload = src.split_to(payload_len);
           let frame = Self::frame(header.type_id, payload.freeze())?;
           self.decode_state = DecoderState::DecodingHeader;
+          self.capture(Direction::Recv, &frame);
           Ok(Some(frame))
         } else {
           Ok(None)
@@ -88,6 +107,7 @@ impl Encoder<Frame> for FloFrameCodec {
 
   #[inline]
   fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+    self.capture(Direction::Send, &item);
     item.encode(dst);
     Ok(())
   }
@@ -3,6 +3,7 @@ pub use crate::proto::flo_connect::*;
 packet_type!(ConnectController, PacketClientConnect);
 packet_type!(ConnectControllerAccept, PacketClientConnectAccept);
 packet_type!(ConnectControllerReject, PacketClientConnectReject);
+packet_type!(ClientVersionAdvertisement, PacketClientVersionAdvertisement);
 packet_type!(LobbyDisconnect, PacketClientDisconnect);
 packet_type!(GameInfo, PacketGameInfo);
 packet_type!(GamePlayerEnter, PacketGamePlayerEnter);
@@ -25,6 +26,18 @@ packet_type!(GamePlayerToken, PacketGamePlayerToken);
 packet_type!(GameStartRequest, PacketGameStartRequest);
 packet_type!(GameStarting, PacketGameStarting);
 packet_type!(GameStartReject, PacketGameStartReject);
+packet_type!(GameStartQueued, PacketGameStartQueued);
+packet_type!(ReadyCheck, PacketReadyCheck);
+packet_type!(ReadyCheckResponse, PacketReadyCheckResponse);
+packet_type!(ReadyCheckReject, PacketReadyCheckReject);
+packet_type!(GameWaitlistJoinRequest, PacketGameWaitlistJoinRequest);
+packet_type!(GameWaitlistLeaveRequest, PacketGameWaitlistLeaveRequest);
+packet_type!(GameWaitlistUpdate, PacketGameWaitlistUpdate);
+packet_type!(GameWaitlistOffer, PacketGameWaitlistOffer);
+packet_type!(GameWaitlistOfferResponse, PacketGameWaitlistOfferResponse);
+packet_type!(GameRemoteJoinRequest, PacketGameRemoteJoinRequest);
+packet_type!(GameRemoteJoin, PacketGameRemoteJoin);
+packet_type!(GameRemoteJoinReject, PacketGameRemoteJoinReject);
 packet_type!(
   GameStartPlayerClientInfoRequest,
   PacketGameStartPlayerClientInfoRequest
@@ -35,3 +48,26 @@ packet_type!(RemoveNode, PacketRemoveNode);
 packet_type!(PlayerMuteListUpdate, PacketPlayerMuteListUpdate);
 packet_type!(PlayerMuteAddRequest, PacketPlayerMuteAddRequest);
 packet_type!(PlayerMuteRemoveRequest, PacketPlayerMuteRemoveRequest);
+packet_type!(PlayerMuteListRequest, PacketPlayerMuteListRequest);
+packet_type!(ServerNotice, PacketServerNotice);
+packet_type!(Motd, PacketMotd);
+packet_type!(GameBalanceTeamsRequest, PacketGameBalanceTeamsRequest);
+packet_type!(GameTransferHostRequest, PacketGameTransferHostRequest);
+packet_type!(GameHostUpdate, PacketGameHostUpdate);
+packet_type!(GameInviteRequest, PacketGameInviteRequest);
+packet_type!(GameInvite, PacketGameInvite);
+packet_type!(GameInviteAcceptRequest, PacketGameInviteAcceptRequest);
+packet_type!(
+  PlayerPreferencesUpdateRequest,
+  PacketPlayerPreferencesUpdateRequest
+);
+packet_type!(PlayerPreferences, PacketPlayerPreferences);
+packet_type!(GameScheduled, PacketGameScheduled);
+packet_type!(GameStatusRequest, PacketGameStatusRequest);
+packet_type!(GameStatusResponse, PacketGameStatusResponse);
+packet_type!(SubscribeRequest, PacketSubscribeRequest);
+packet_type!(UnsubscribeRequest, PacketUnsubscribeRequest);
+packet_type!(GameListSnapshot, PacketGameListSnapshot);
+packet_type!(GameListUpdate, PacketGameListUpdate);
+packet_type!(GameListResyncRequest, PacketGameListResyncRequest);
+packet_type!(PlayerPresenceUpdate, PacketPlayerPresenceUpdate);
@@ -0,0 +1,251 @@
+//! Routes frames to handlers registered by packet type.
+//!
+//! Replaces `try_flo_packet!` matches for streams accepting many packet types, each handler
+//! can be registered and tested on its own. The context `C` is cloned into every call.
+//!
+//! The handler metrics are registered with the default prometheus registry,
+//! labeled by the name of the dispatcher and the packet type.
+
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use prometheus::{
+  register_counter_vec, register_int_counter_vec, Counter, CounterVec, IntCounter, IntCounterVec,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::packet::{FloPacket, Frame, PacketTypeId};
+
+static HANDLER_CALLS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec!(
+    "flo_packet_handler_calls_total",
+    "Frames dispatched to packet handlers",
+    &["dispatcher", "packet"]
+  )
+  .unwrap()
+});
+static HANDLER_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec!(
+    "flo_packet_handler_errors_total",
+    "Packet handler calls that failed, including decode errors",
+    &["dispatcher", "packet"]
+  )
+  .unwrap()
+});
+static HANDLER_BUSY_SECONDS: Lazy<CounterVec> = Lazy::new(|| {
+  register_counter_vec!(
+    "flo_packet_handler_busy_seconds_total",
+    "Time spent in packet handlers",
+    &["dispatcher", "packet"]
+  )
+  .unwrap()
+});
+static UNKNOWN_PACKETS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec!(
+    "flo_packet_handler_unknown_total",
+    "Frames dispatched without a registered handler",
+    &["dispatcher"]
+  )
+  .unwrap()
+});
+
+type BoxHandler<C, E> = Box<dyn Fn(C, Frame) -> BoxFuture<'static, Result<(), E>> + Send + Sync>;
+
+/// An async fn, or closure, taking the context and the decoded packet
+pub trait PacketHandler<C, P, E>: Send + Sync + 'static {
+  type Future: Future<Output = Result<(), E>> + Send + 'static;
+
+  fn call(&self, ctx: C, packet: P) -> Self::Future;
+}
+
+impl<C, P, E, F, Fut> PacketHandler<C, P, E> for F
+where
+  F: Fn(C, P) -> Fut + Send + Sync + 'static,
+  Fut: Future<Output = Result<(), E>> + Send + 'static,
+{
+  type Future = Fut;
+
+  fn call(&self, ctx: C, packet: P) -> Fut {
+    self(ctx, packet)
+  }
+}
+
+pub struct Dispatcher<C, E> {
+  name: &'static str,
+  handlers: HashMap<PacketTypeId, Entry<C, E>>,
+  unknown: Option<BoxHandler<C, E>>,
+  unknown_count: IntCounter,
+}
+
+struct Entry<C, E> {
+  handler: BoxHandler<C, E>,
+  metrics: HandlerMetrics,
+}
+
+impl<C, E> Dispatcher<C, E>
+where
+  C: Send + 'static,
+  E: From<Error> + Send + 'static,
+{
+  /// `name` labels the metrics, dispatchers with the same name share them
+  pub fn new(name: &'static str) -> Self {
+    Self {
+      name,
+      handlers: HashMap::new(),
+      unknown: None,
+      unknown_count: UNKNOWN_PACKETS.with_label_values(&[name]),
+    }
+  }
+
+  /// Replaces the handler of the same packet type, if any
+  pub fn on<P>(mut self, handler: impl PacketHandler<C, P, E>) -> Self
+  where
+    P: FloPacket + Default + Send + 'static,
+  {
+    let handler: BoxHandler<C, E> = Box::new(move |ctx, frame| match frame.decode::<P>() {
+      Ok(packet) => Box::pin(handler.call(ctx, packet)),
+      Err(err) => Box::pin(futures::future::ready(Err(err.into()))),
+    });
+    self.handlers.insert(
+      P::TYPE_ID,
+      Entry {
+        handler,
+        metrics: HandlerMetrics::new(self.name, P::TYPE_ID),
+      },
+    );
+    self
+  }
+
+  /// Called with frames no handler is registered for.
+  /// Without it, dispatching them returns `Error::UnexpectedPacketTypeId`.
+  pub fn on_unknown<F, Fut>(mut self, f: F) -> Self
+  where
+    F: Fn(C, Frame) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), E>> + Send + 'static,
+  {
+    self.unknown = Some(Box::new(move |ctx, frame| Box::pin(f(ctx, frame))));
+    self
+  }
+
+  pub fn handles(&self, type_id: PacketTypeId) -> bool {
+    self.handlers.contains_key(&type_id)
+  }
+
+  pub async fn dispatch(&self, ctx: C, frame: Frame) -> Result<(), E> {
+    let entry = match self.handlers.get(&frame.type_id) {
+      Some(entry) => entry,
+      None => {
+        self.unknown_count.inc();
+        return match self.unknown {
+          Some(ref f) => f(ctx, frame).await,
+          None => Err(Error::unexpected_packet_type_id(frame.type_id).into()),
+        };
+      }
+    };
+    let t = Instant::now();
+    let res = (entry.handler)(ctx, frame).await;
+    entry.metrics.record(t.elapsed(), res.is_ok());
+    res
+  }
+
+  /// Of every registered handler
+  pub fn metrics(&self) -> impl Iterator<Item = (PacketTypeId, &HandlerMetrics)> {
+    self
+      .handlers
+      .iter()
+      .map(|(type_id, entry)| (*type_id, &entry.metrics))
+  }
+
+  /// Frames dispatched without a registered handler
+  pub fn unknown_count(&self) -> u64 {
+    self.unknown_count.get()
+  }
+}
+
+/// Decode errors count as handler errors
+#[derive(Debug)]
+pub struct HandlerMetrics {
+  calls: IntCounter,
+  errors: IntCounter,
+  busy_seconds: Counter,
+}
+
+impl HandlerMetrics {
+  fn new(dispatcher: &str, type_id: PacketTypeId) -> Self {
+    let packet = format!("{:?}", type_id);
+    let labels = [dispatcher, packet.as_str()];
+    Self {
+      calls: HANDLER_CALLS.with_label_values(&labels),
+      errors: HANDLER_ERRORS.with_label_values(&labels),
+      busy_seconds: HANDLER_BUSY_SECONDS.with_label_values(&labels),
+    }
+  }
+
+  fn record(&self, elapsed: Duration, ok: bool) {
+    self.calls.inc();
+    if !ok {
+      self.errors.inc();
+    }
+    self.busy_seconds.inc_by(elapsed.as_secs_f64());
+  }
+
+  pub fn calls(&self) -> u64 {
+    self.calls.get()
+  }
+
+  pub fn errors(&self) -> u64 {
+    self.errors.get()
+  }
+
+  /// Total time spent in the handler
+  pub fn busy_time(&self) -> Duration {
+    Duration::from_secs_f64(self.busy_seconds.get())
+  }
+}
+
+#[tokio::test]
+async fn test_dispatcher() {
+  use crate::proto::flo_node::{
+    PacketControllerSetPacketCapture, PacketControllerUpdateSlotStatus,
+  };
+  use std::sync::atomic::{AtomicU64, Ordering};
+  use std::sync::Arc;
+
+  let enabled = Arc::new(AtomicU64::new(0));
+  let dispatcher = Dispatcher::<_, Error>::new("test").on::<PacketControllerSetPacketCapture>(
+    |enabled: Arc<AtomicU64>, packet: PacketControllerSetPacketCapture| async move {
+      enabled.store(packet.enabled as u64, Ordering::Relaxed);
+      Ok(())
+    },
+  );
+  let frame = PacketControllerSetPacketCapture { enabled: true }
+    .encode_as_frame()
+    .unwrap();
+  dispatcher.dispatch(enabled.clone(), frame).await.unwrap();
+  assert_eq!(enabled.load(Ordering::Relaxed), 1);
+
+  let unknown = PacketControllerUpdateSlotStatus::default()
+    .encode_as_frame()
+    .unwrap();
+  assert!(matches!(
+    dispatcher.dispatch(enabled.clone(), unknown.clone()).await,
+    Err(Error::UnexpectedPacketTypeId { .. })
+  ));
+  let dispatcher = dispatcher.on_unknown(|_, _| async { Ok(()) });
+  dispatcher.dispatch(enabled.clone(), unknown).await.unwrap();
+  assert_eq!(dispatcher.unknown_count(), 2);
+
+  let bad = Frame::new(PacketControllerSetPacketCapture::TYPE_ID, [0xFF]);
+  assert!(dispatcher.dispatch(enabled, bad).await.is_err());
+  let (type_id, metrics) = dispatcher.metrics().next().unwrap();
+  assert_eq!(type_id, PacketControllerSetPacketCapture::TYPE_ID);
+  assert_eq!((metrics.calls(), metrics.errors()), (2, 1));
+
+  let exported = prometheus::gather()
+    .into_iter()
+    .find(|family| family.get_name() == "flo_packet_handler_calls_total")
+    .unwrap();
+  assert_eq!(exported.get_metric()[0].get_counter().get_value(), 2.0);
+}
@@ -0,0 +1,143 @@
+//! Datagram format of the node UDP echo service.
+//!
+//! Each datagram starts with a ping token issued by the controller, followed by the client payload.
+//! Nodes only echo the payload back if the token was signed with their secret and has not expired.
+//! Datagrams of the legacy format, a bare 4 or 8 byte payload sent by clients without ping
+//! tokens, are only echoed back as is if the node allows them.
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAC_LEN: usize = 8;
+pub const PING_TOKEN_LEN: usize = 4 + MAC_LEN;
+pub const ECHO_PAYLOAD_LEN: usize = 4;
+pub const ECHO_DATAGRAM_LEN: usize = PING_TOKEN_LEN + ECHO_PAYLOAD_LEN;
+pub const PING_TOKEN_TTL: Duration = Duration::from_secs(24 * 3600);
+pub const LEGACY_ECHO_DATAGRAM_LENS: &[usize] = &[4, 8];
+
+/// `expires_at` (unix timestamp, little endian) followed by a truncated HMAC-SHA256 of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PingToken([u8; PING_TOKEN_LEN]);
+
+impl PingToken {
+  /// Creates a token valid for `PING_TOKEN_TTL`
+  pub fn issue(secret: &str) -> Self {
+    let expires_at = unix_now().saturating_add(PING_TOKEN_TTL.as_secs() as u32);
+    Self::new(secret, expires_at)
+  }
+
+  pub fn new(secret: &str, expires_at: u32) -> Self {
+    let expires_at = expires_at.to_le_bytes();
+    let mut bytes = [0_u8; PING_TOKEN_LEN];
+    bytes[..4].copy_from_slice(&expires_at);
+    bytes[4..].copy_from_slice(&sign(secret, &expires_at));
+    Self(bytes)
+  }
+
+  pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+    bytes.try_into().ok().map(Self)
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.0
+  }
+
+  pub fn expires_at(&self) -> u32 {
+    u32::from_le_bytes([self.0[0], self.0[1], self.0[2], self.0[3]])
+  }
+
+  pub fn verify(&self, secret: &str, now: u32) -> bool {
+    if self.expires_at() < now {
+      return false;
+    }
+    let mac = sign(secret, &self.0[..4]);
+    // constant time comparison
+    mac
+      .iter()
+      .zip(&self.0[4..])
+      .fold(0, |acc, (a, b)| acc | (a ^ b))
+      == 0
+  }
+}
+
+/// Builds an echo datagram
+pub fn encode_datagram(
+  token: &PingToken,
+  payload: [u8; ECHO_PAYLOAD_LEN],
+) -> [u8; ECHO_DATAGRAM_LEN] {
+  let mut buf = [0_u8; ECHO_DATAGRAM_LEN];
+  buf[..PING_TOKEN_LEN].copy_from_slice(token.as_bytes());
+  buf[PING_TOKEN_LEN..].copy_from_slice(&payload);
+  buf
+}
+
+/// Returns the payload to echo back if the datagram carries a valid token,
+/// or the whole datagram if it has the legacy format and `allow_legacy` is set
+pub fn verify_datagram<'a>(
+  secret: &str,
+  datagram: &'a [u8],
+  now: u32,
+  allow_legacy: bool,
+) -> Option<&'a [u8]> {
+  if LEGACY_ECHO_DATAGRAM_LENS.contains(&datagram.len()) {
+    return Some(datagram).filter(|_| allow_legacy);
+  }
+  if datagram.len() != ECHO_DATAGRAM_LEN {
+    return None;
+  }
+  let token = PingToken::from_slice(&datagram[..PING_TOKEN_LEN])?;
+  if token.verify(secret, now) {
+    Some(&datagram[PING_TOKEN_LEN..])
+  } else {
+    None
+  }
+}
+
+pub fn unix_now() -> u32 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() as u32)
+    .unwrap_or_default()
+}
+
+fn sign(secret: &str, data: &[u8]) -> [u8; MAC_LEN] {
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+  mac.update(data);
+  let mut bytes = [0_u8; MAC_LEN];
+  bytes.copy_from_slice(&mac.finalize().into_bytes()[..MAC_LEN]);
+  bytes
+}
+
+#[test]
+fn test_ping_token() {
+  let token = PingToken::new("secret", 1000);
+  assert!(token.verify("secret", 1000));
+  assert!(!token.verify("secret", 1001));
+  assert!(!token.verify("other", 1000));
+
+  let datagram = encode_datagram(&token, [1, 2, 3, 4]);
+  assert_eq!(
+    verify_datagram("secret", &datagram, 999, false),
+    Some(&[1_u8, 2, 3, 4] as &[u8])
+  );
+  assert_eq!(verify_datagram("secret", &datagram[..3], 999, true), None);
+  assert_eq!(verify_datagram("secret", &datagram[..4], 999, false), None);
+  assert_eq!(
+    verify_datagram("secret", &datagram[..4], 999, true),
+    Some(&datagram[..4])
+  );
+  assert_eq!(
+    verify_datagram("secret", &datagram[..8], 999, true),
+    Some(&datagram[..8])
+  );
+  let mut long = datagram.to_vec();
+  long.push(0);
+  assert_eq!(verify_datagram("secret", &long, 999, true), None);
+
+  let mut tampered = datagram;
+  tampered[0] = tampered[0].wrapping_add(1);
+  assert_eq!(verify_datagram("secret", &tampered, 999, false), None);
+}
@@ -0,0 +1,118 @@
+//! Connection keepalive: sends pings, answers the peer's pings and reports the peer dead
+//! once a pong doesn't arrive in time.
+
+use crate::packet::{Frame, PacketTypeId};
+use crate::ping::{PingMsg, PingStream};
+use futures::StreamExt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAliveConfig {
+  pub interval: Duration,
+  pub timeout: Duration,
+}
+
+impl KeepAliveConfig {
+  pub fn new(interval: Duration, timeout: Duration) -> Self {
+    Self { interval, timeout }
+  }
+}
+
+pub struct KeepAlive {
+  ping: PingStream,
+  rtt: Option<u32>,
+}
+
+impl KeepAlive {
+  pub fn new(config: KeepAliveConfig) -> Self {
+    let mut ping = PingStream::interval(config.interval, config.timeout);
+    ping.start();
+    Self { ping, rtt: None }
+  }
+
+  /// Round trip time in milliseconds of the last pong passed to `recv`, if not taken yet
+  pub fn take_rtt(&mut self) -> Option<u32> {
+    self.rtt.take()
+  }
+
+  /// Waits for the next ping to send or the pong timeout, cancel safe
+  pub async fn next(&mut self) -> KeepAliveEvent {
+    match self.ping.next().await {
+      Some(PingMsg::Ping(frame)) => KeepAliveEvent::Ping(frame),
+      Some(PingMsg::Timeout) | None => KeepAliveEvent::Timeout,
+    }
+  }
+
+  /// Handles a frame received from the peer
+  pub fn recv(&mut self, frame: Frame) -> Incoming {
+    match frame.type_id {
+      PacketTypeId::Ping => Incoming::KeepAlive(Some(pong(frame))),
+      PacketTypeId::Pong => {
+        if let Some(rtt) = self.ping.capture_pong(frame) {
+          self.rtt = Some(rtt);
+        }
+        Incoming::KeepAlive(None)
+      }
+      _ => Incoming::Frame(frame),
+    }
+  }
+}
+
+pub enum KeepAliveEvent {
+  /// Send this frame to the peer
+  Ping(Frame),
+  /// No pong received in time
+  Timeout,
+}
+
+pub enum Incoming {
+  /// A keepalive frame, the reply should be sent to the peer
+  KeepAlive(Option<Frame>),
+  Frame(Frame),
+}
+
+/// The reply to a ping frame, for connections where only the peer sends pings
+pub fn pong(mut frame: Frame) -> Frame {
+  frame.type_id = PacketTypeId::Pong;
+  frame
+}
+
+#[cfg(test)]
+fn test_config() -> KeepAliveConfig {
+  KeepAliveConfig::new(Duration::from_secs(1), Duration::from_millis(500))
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_keepalive_timeout() {
+  let mut keepalive = KeepAlive::new(test_config());
+  assert!(matches!(keepalive.next().await, KeepAliveEvent::Ping(_)));
+  assert!(matches!(keepalive.next().await, KeepAliveEvent::Timeout));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_keepalive_pong() {
+  let mut keepalive = KeepAlive::new(test_config());
+
+  for _ in 0..3 {
+    let frame = match keepalive.next().await {
+      KeepAliveEvent::Ping(frame) => frame,
+      KeepAliveEvent::Timeout => panic!("timeout after pong"),
+    };
+    assert!(matches!(
+      keepalive.recv(pong(frame)),
+      Incoming::KeepAlive(None)
+    ));
+    assert!(keepalive.take_rtt().is_some());
+    assert!(keepalive.take_rtt().is_none());
+  }
+
+  let ping = Frame::new(PacketTypeId::Ping, [0, 0, 0, 1]);
+  match keepalive.recv(ping) {
+    Incoming::KeepAlive(Some(reply)) => assert_eq!(reply.type_id, PacketTypeId::Pong),
+    _ => panic!("ping not answered"),
+  }
+  assert!(matches!(
+    keepalive.recv(Frame::new_empty(PacketTypeId::ObserverDataEnd)),
+    Incoming::Frame(_)
+  ));
+}
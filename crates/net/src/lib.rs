@@ -6,11 +6,18 @@ pub mod error;
 #[macro_use]
 pub mod packet;
 
+pub mod capture;
 pub mod constants;
+pub mod dispatch;
+pub mod echo;
+pub mod keepalive;
 pub mod listener;
 pub mod ping;
+pub mod replay;
+pub mod signed_url;
 pub mod stream;
 pub mod time;
+pub mod trace;
 pub mod w3gs;
 
 pub mod proto {
@@ -16,6 +16,15 @@ packet_type!(ControllerCreateGame, PacketControllerCreateGame);
 packet_type!(ControllerCreateGameAccept, PacketControllerCreateGameAccept);
 packet_type!(ControllerCreateGameReject, PacketControllerCreateGameReject);
 packet_type!(ControllerQueryGameStatus, PacketControllerQueryGameStatus);
+packet_type!(ControllerSetPacketCapture, PacketControllerSetPacketCapture);
+packet_type!(
+  ControllerGameStatusRequest,
+  PacketControllerGameStatusRequest
+);
+packet_type!(
+  ControllerGameStatusResponse,
+  PacketControllerGameStatusResponse
+);
 packet_type!(ClientConnect, PacketClientConnect);
 packet_type!(ClientConnectAccept, PacketClientConnectAccept);
 packet_type!(ClientConnectReject, PacketClientConnectReject);
@@ -33,3 +42,5 @@ packet_type!(
 );
 packet_type!(NodeGameStatusUpdate, PacketNodeGameStatusUpdate);
 packet_type!(NodeGameStatusUpdateBulk, PacketNodeGameStatusUpdateBulk);
+packet_type!(NodeGameAnomaly, PacketNodeGameAnomaly);
+packet_type!(NodeStatus, PacketNodeStatus);
@@ -236,6 +236,74 @@ pub enum PacketTypeId {
   PlayerMuteAddRequest,
   #[bin(value = 0x1F)]
   PlayerMuteRemoveRequest,
+  #[bin(value = 0x20)]
+  ServerNotice,
+  #[bin(value = 0x21)]
+  GameBalanceTeamsRequest,
+  #[bin(value = 0x22)]
+  GameTransferHostRequest,
+  #[bin(value = 0x23)]
+  GameHostUpdate,
+  #[bin(value = 0x24)]
+  GameInviteRequest,
+  #[bin(value = 0x25)]
+  GameInvite,
+  #[bin(value = 0x26)]
+  GameInviteAcceptRequest,
+  #[bin(value = 0x27)]
+  PlayerPreferencesUpdateRequest,
+  #[bin(value = 0x28)]
+  PlayerPreferences,
+  #[bin(value = 0x29)]
+  GameScheduled,
+  #[bin(value = 0x2A)]
+  GameStatusRequest,
+  #[bin(value = 0x2B)]
+  GameStatusResponse,
+  #[bin(value = 0x2C)]
+  SubscribeRequest,
+  #[bin(value = 0x2D)]
+  UnsubscribeRequest,
+  #[bin(value = 0x2E)]
+  GameListUpdate,
+  #[bin(value = 0x2F)]
+  PlayerPresenceUpdate,
+
+  // Client <-> Lobby, continued
+  #[bin(value = 0x70)]
+  GameListSnapshot,
+  #[bin(value = 0x71)]
+  GameListResyncRequest,
+  #[bin(value = 0x72)]
+  GameStartQueued,
+  #[bin(value = 0x73)]
+  ReadyCheck,
+  #[bin(value = 0x74)]
+  ReadyCheckResponse,
+  #[bin(value = 0x75)]
+  ReadyCheckReject,
+  #[bin(value = 0x76)]
+  ClientVersionAdvertisement,
+  #[bin(value = 0x77)]
+  GameWaitlistJoinRequest,
+  #[bin(value = 0x78)]
+  GameWaitlistLeaveRequest,
+  #[bin(value = 0x79)]
+  GameWaitlistUpdate,
+  #[bin(value = 0x7A)]
+  GameWaitlistOffer,
+  #[bin(value = 0x7B)]
+  GameWaitlistOfferResponse,
+  #[bin(value = 0x7C)]
+  GameRemoteJoinRequest,
+  #[bin(value = 0x7D)]
+  GameRemoteJoin,
+  #[bin(value = 0x7E)]
+  GameRemoteJoinReject,
+  #[bin(value = 0x7F)]
+  PlayerMuteListRequest,
+  #[bin(value = 0x80)]
+  Motd,
 
   // Lobby <-> Node
   #[bin(value = 0x30)]
@@ -258,6 +326,12 @@ pub enum PacketTypeId {
   ControllerUpdateSlotStatusReject,
   #[bin(value = 0x39)]
   ControllerQueryGameStatus,
+  #[bin(value = 0x3A)]
+  ControllerSetPacketCapture,
+  #[bin(value = 0x3B)]
+  ControllerGameStatusRequest,
+  #[bin(value = 0x3C)]
+  ControllerGameStatusResponse,
 
   // Client <-> Node
   #[bin(value = 0x40)]
@@ -282,6 +356,10 @@ pub enum PacketTypeId {
   NodeGameStatusUpdate,
   #[bin(value = 0x51)]
   NodeGameStatusUpdateBulk,
+  #[bin(value = 0x52)]
+  NodeGameAnomaly,
+  #[bin(value = 0x53)]
+  NodeStatus,
 
   // Client <-> Observer
   #[bin(value = 0x60)]
@@ -0,0 +1,42 @@
+//! Storage naming and download links of game replays archived by nodes.
+//!
+//! Nodes storing replays in a local directory serve them over HTTP,
+//! the controller links to them with a signature made with the node secret.
+
+use std::time::Duration;
+
+pub const REPLAY_URL_TTL: Duration = Duration::from_secs(3600);
+pub const REPLAY_HTTP_PATH_PREFIX: &str = "/replays/";
+
+/// Object key or file name of the replay of a game
+pub fn object_key(game_id: i32) -> String {
+  format!("{}.flo", game_id)
+}
+
+/// Path and query of a replay served by the node HTTP server
+pub fn signed_path(secret: &str, game_id: i32, expires_at: u32) -> String {
+  crate::signed_url::signed_path(
+    secret.as_bytes(),
+    REPLAY_HTTP_PATH_PREFIX,
+    &game_id.to_string(),
+    expires_at,
+  )
+}
+
+/// Returns the game id if the path and query were signed with `secret` and have not expired
+pub fn verify_path(secret: &str, path: &str, query: &str, now: u32) -> Option<i32> {
+  crate::signed_url::verify_path(secret.as_bytes(), REPLAY_HTTP_PATH_PREFIX, path, query, now)?
+    .parse()
+    .ok()
+}
+
+#[test]
+fn test_replay_signed_path() {
+  let path = signed_path("secret", 42, 1000);
+  let (path, query) = path.split_at(path.find('?').unwrap());
+  let query = &query[1..];
+  assert_eq!(verify_path("secret", path, query, 1000), Some(42));
+  assert_eq!(verify_path("secret", path, query, 1001), None);
+  assert_eq!(verify_path("other", path, query, 1000), None);
+  assert_eq!(verify_path("secret", "/replays/43", query, 1000), None);
+}
@@ -0,0 +1,85 @@
+//! Short-lived download links: `<prefix><resource>?expires=<unix time>&sig=<hex>`,
+//! signed with HMAC-SHA256 of the resource and the expiry time.
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::fmt::Write;
+
+/// Path and query of `resource`, valid until `expires_at`
+pub fn signed_path(secret: &[u8], prefix: &str, resource: &str, expires_at: u32) -> String {
+  let bytes = mac(secret, resource, expires_at).finalize().into_bytes();
+  let mut sig = String::with_capacity(bytes.len() * 2);
+  for b in bytes.iter() {
+    write!(sig, "{:02x}", b).ok();
+  }
+  format!("{}{}?expires={}&sig={}", prefix, resource, expires_at, sig)
+}
+
+/// Returns the resource if the path and query were signed with `secret` and have not expired
+pub fn verify_path<'a>(
+  secret: &[u8],
+  prefix: &str,
+  path: &'a str,
+  query: &str,
+  now: u32,
+) -> Option<&'a str> {
+  let resource = path.strip_prefix(prefix)?;
+  let mut expires_at: Option<u32> = None;
+  let mut sig = None;
+  for pair in query.split('&') {
+    let mut parts = pair.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+      (Some("expires"), Some(v)) => expires_at = v.parse().ok(),
+      (Some("sig"), Some(v)) => sig = Some(v),
+      _ => {}
+    }
+  }
+  let expires_at = expires_at?;
+  if expires_at < now {
+    return None;
+  }
+  let sig = decode_hex(sig?)?;
+  // constant time
+  mac(secret, resource, expires_at).verify(&sig).ok()?;
+  Some(resource)
+}
+
+fn mac(secret: &[u8], resource: &str, expires_at: u32) -> Hmac<Sha256> {
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+  mac.update(resource.as_bytes());
+  mac.update(&expires_at.to_le_bytes());
+  mac
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+  if value.len() % 2 != 0 {
+    return None;
+  }
+  (0..value.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(value.get(i..(i + 2))?, 16).ok())
+    .collect()
+}
+
+#[test]
+fn test_signed_path() {
+  let path = signed_path(b"secret", "/files/", "abc", 1000);
+  let (path, query) = path.split_at(path.find('?').unwrap());
+  let query = &query[1..];
+  assert_eq!(
+    verify_path(b"secret", "/files/", path, query, 1000),
+    Some("abc")
+  );
+  assert_eq!(verify_path(b"secret", "/files/", path, query, 1001), None);
+  assert_eq!(verify_path(b"other", "/files/", path, query, 1000), None);
+  assert_eq!(verify_path(b"secret", "/other/", path, query, 1000), None);
+  assert_eq!(
+    verify_path(b"secret", "/files/", "/files/abd", query, 1000),
+    None
+  );
+  let tampered = query.replace("expires=1000", "expires=2000");
+  assert_eq!(
+    verify_path(b"secret", "/files/", path, &tampered, 1000),
+    None
+  );
+}
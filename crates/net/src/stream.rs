@@ -7,9 +7,10 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::{TcpStream, ToSocketAddrs};
 use tokio::time::timeout;
-use tokio_util::codec::Framed;
+use tokio_util::codec::{Framed, FramedRead, FramedWrite};
 
 use crate::codec::FloFrameCodec;
 use crate::error::*;
@@ -33,7 +34,8 @@ impl FloStream {
     //TODO: not supported by current tokio
     //socket.set_keepalive(None).ok();
 
-    let transport = Framed::new(socket, FloFrameCodec::new());
+    let codec = FloFrameCodec::with_peer(socket.peer_addr().ok());
+    let transport = Framed::new(socket, codec);
     Ok(FloStream {
       transport,
       timeout: DEFAULT_TIMEOUT,
@@ -46,7 +48,8 @@ impl FloStream {
     // not supported by tokio atm
     //socket.set_keepalive(Some(Duration::from_secs(30)))?;
 
-    let transport = Framed::new(socket, FloFrameCodec::new());
+    let codec = FloFrameCodec::with_peer(socket.peer_addr().ok());
+    let transport = Framed::new(socket, codec);
     Ok(FloStream {
       transport,
       timeout: DEFAULT_TIMEOUT,
@@ -54,8 +57,9 @@ impl FloStream {
   }
 
   pub fn new(socket: TcpStream) -> Self {
+    let codec = FloFrameCodec::with_peer(socket.peer_addr().ok());
     FloStream {
-      transport: Framed::new(socket, FloFrameCodec::new()),
+      transport: Framed::new(socket, codec),
       timeout: DEFAULT_TIMEOUT,
     }
   }
@@ -160,6 +164,30 @@ impl FloStream {
     Ok(())
   }
 
+  /// Splits into halves that can be used concurrently, e.g. from different tasks
+  pub fn split(self) -> (FloStreamReader, FloStreamWriter) {
+    let timeout = self.timeout;
+    let parts = self.transport.into_parts();
+    let peer = parts.io.peer_addr().ok();
+    let (read, write) = parts.io.into_split();
+
+    let mut reader = FramedRead::new(read, parts.codec);
+    *reader.read_buffer_mut() = parts.read_buf;
+    let mut writer = FramedWrite::new(write, FloFrameCodec::with_peer(peer));
+    *writer.write_buffer_mut() = parts.write_buf;
+
+    (
+      FloStreamReader {
+        timeout,
+        transport: reader,
+      },
+      FloStreamWriter {
+        timeout,
+        transport: writer,
+      },
+    )
+  }
+
   pub async fn downgrade_to_binary_stream(self) -> Result<(Bytes, TcpStream)> {
     let parts = self.transport.into_parts();
     let mut stream = parts.io;
@@ -178,9 +206,173 @@ impl Stream for FloStream {
   }
 }
 
+/// The receiving half of a `FloStream`
+#[derive(Debug)]
+pub struct FloStreamReader {
+  pub timeout: Duration,
+  transport: FramedRead<OwnedReadHalf, FloFrameCodec>,
+}
+
+impl FloStreamReader {
+  #[inline]
+  pub fn peer_addr(&self) -> Result<SocketAddr> {
+    self.transport.get_ref().peer_addr().map_err(Into::into)
+  }
+
+  #[inline]
+  pub async fn recv<T>(&mut self) -> Result<T>
+  where
+    T: FloPacket + Default,
+  {
+    let frame = self.recv_frame().await?;
+    Ok(frame.decode()?)
+  }
+
+  #[inline]
+  pub async fn recv_timeout<T>(&mut self, duration: Duration) -> Result<T>
+  where
+    T: FloPacket + Default,
+  {
+    let frame = timeout(duration, self.recv_frame())
+      .await
+      .map_err(|_elapsed| Error::StreamTimeout)??;
+    Ok(frame.decode()?)
+  }
+
+  #[inline]
+  pub async fn recv_frame(&mut self) -> Result<Frame> {
+    let frame = self
+      .transport
+      .try_next()
+      .await?
+      .ok_or_else(|| Error::StreamClosed)?;
+    Ok(frame)
+  }
+
+  #[inline]
+  pub async fn recv_frame_timeout(&mut self) -> Result<Frame> {
+    let frame = timeout(self.timeout, self.transport.try_next())
+      .await
+      .map_err(|_elapsed| Error::StreamTimeout)??
+      .ok_or_else(|| Error::StreamClosed)?;
+    Ok(frame)
+  }
+}
+
+impl Stream for FloStreamReader {
+  type Item = Result<Frame>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    Pin::new(&mut self.transport).poll_next(cx)
+  }
+}
+
+/// The sending half of a `FloStream`
+#[derive(Debug)]
+pub struct FloStreamWriter {
+  pub timeout: Duration,
+  transport: FramedWrite<OwnedWriteHalf, FloFrameCodec>,
+}
+
+impl FloStreamWriter {
+  pub async fn send_frame_timeout(&mut self, frame: Frame) -> Result<()> {
+    timeout(self.timeout, self.transport.send(frame))
+      .await
+      .map_err(|_elapsed| Error::StreamTimeout)??;
+    Ok(())
+  }
+
+  #[inline]
+  pub async fn send_frame(&mut self, frame: Frame) -> Result<()> {
+    self.transport.send(frame).await?;
+    Ok(())
+  }
+
+  #[inline]
+  pub async fn send_frames<I>(&mut self, iter: I) -> Result<()>
+  where
+    I: IntoIterator<Item = Frame>,
+  {
+    let mut stream = tokio_stream::iter(iter.into_iter().map(Ok));
+    timeout(self.timeout, self.transport.send_all(&mut stream))
+      .await
+      .map_err(|_elapsed| Error::StreamTimeout)??;
+    Ok(())
+  }
+
+  #[inline]
+  pub async fn send<T>(&mut self, packet: T) -> Result<()>
+  where
+    T: FloPacket,
+  {
+    self.send_frame_timeout(packet.encode_as_frame()?).await?;
+    Ok(())
+  }
+
+  pub async fn flush(&mut self) -> Result<()> {
+    poll_fn(|ctx| Pin::new(&mut self.transport).poll_flush(ctx)).await?;
+    self.transport.get_mut().flush().await?;
+    Ok(())
+  }
+
+  /// Closes the write side so the peer receives EOF, the reader keeps working
+  pub async fn shutdown(&mut self) -> Result<()> {
+    poll_fn(|ctx| Pin::new(&mut self.transport).poll_close(ctx)).await?;
+    self.transport.get_mut().shutdown().await?;
+    Ok(())
+  }
+}
+
 #[test]
 fn test_lookup() {
   use std::net::ToSocketAddrs;
   let mut addrs_iter = "wc3.tools:443".to_socket_addrs().unwrap();
   dbg!(addrs_iter.next());
 }
+
+#[tokio::test]
+async fn test_split() {
+  use crate::packet::{FramePayload, PacketTypeId};
+  use tokio::net::TcpListener;
+
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+
+  // echoes every frame back until EOF
+  let server = tokio::spawn(async move {
+    let (socket, _) = listener.accept().await.unwrap();
+    let (mut reader, mut writer) = FloStream::new(socket).split();
+    let mut n = 0;
+    while let Ok(frame) = reader.recv_frame().await {
+      writer.send_frame(frame).await.unwrap();
+      n += 1;
+    }
+    n
+  });
+
+  let (mut reader, mut writer) = FloStream::connect(addr).await.unwrap().split();
+  let send = tokio::spawn(async move {
+    for i in 0..100_u32 {
+      writer
+        .send_frame(Frame::new(PacketTypeId::Ping, i.to_be_bytes()))
+        .await
+        .unwrap();
+    }
+    writer.shutdown().await.unwrap();
+  });
+
+  for i in 0..100_u32 {
+    let frame = reader.recv_frame().await.unwrap();
+    assert_eq!(frame.type_id, PacketTypeId::Ping);
+    match frame.payload {
+      FramePayload::Bytes(bytes) => assert_eq!(bytes.as_ref(), &i.to_be_bytes()),
+      FramePayload::W3GS { .. } => panic!("unexpected w3gs payload"),
+    }
+  }
+  send.await.unwrap();
+  assert_eq!(server.await.unwrap(), 100);
+  assert!(matches!(
+    reader.recv_frame().await,
+    Err(Error::StreamClosed)
+  ));
+}
@@ -0,0 +1,52 @@
+//! Propagates the current span to another service through the `TraceContext` packet fields.
+//! Both are no-ops unless the subscriber exports spans, see `flo-log-subscriber`.
+
+use crate::proto::flo_common::TraceContext;
+use opentelemetry::propagation::{Extractor, Injector};
+use std::collections::HashMap;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The context of the current span, `None` if it isn't exported
+pub fn current_context() -> Option<TraceContext> {
+  let cx = Span::current().context();
+  let mut fields = HashMap::new();
+  opentelemetry::global::get_text_map_propagator(|propagator| {
+    propagator.inject_context(&cx, &mut FieldsInjector(&mut fields))
+  });
+  if fields.is_empty() {
+    None
+  } else {
+    Some(TraceContext { fields })
+  }
+}
+
+/// Makes `span` a child of the remote span in `context`
+pub fn set_parent(span: &Span, context: Option<&TraceContext>) {
+  if let Some(context) = context {
+    let cx = opentelemetry::global::get_text_map_propagator(|propagator| {
+      propagator.extract(&FieldsExtractor(&context.fields))
+    });
+    span.set_parent(cx);
+  }
+}
+
+struct FieldsInjector<'a>(&'a mut HashMap<String, String>);
+
+impl<'a> Injector for FieldsInjector<'a> {
+  fn set(&mut self, key: &str, value: String) {
+    self.0.insert(key.to_string(), value);
+  }
+}
+
+struct FieldsExtractor<'a>(&'a HashMap<String, String>);
+
+impl<'a> Extractor for FieldsExtractor<'a> {
+  fn get(&self, key: &str) -> Option<&str> {
+    self.0.get(key).map(|v| v.as_str())
+  }
+
+  fn keys(&self) -> Vec<&str> {
+    self.0.keys().map(|k| k.as_str()).collect()
+  }
+}
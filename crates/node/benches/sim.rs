@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use flo_node::sim::{Script, Simulation};
+
+const TICKS: usize = 10000;
+
+fn bench_tick_loop(c: &mut Criterion) {
+  let mut group = c.benchmark_group("tick_loop");
+  group.throughput(Throughput::Elements(TICKS as u64));
+  for &(players, action_len) in &[(2, 16), (8, 16), (8, 256)] {
+    group.bench_with_input(
+      BenchmarkId::new(format!("{}p", players), action_len),
+      &(players, action_len),
+      |b, &(players, action_len)| {
+        b.iter(|| {
+          let scripts = (0..players)
+            .map(|_| {
+              Script::new()
+                .latency(100)
+                .repeat_action(2, vec![0xff; action_len])
+                .boxed()
+            })
+            .collect();
+          let mut sim = Simulation::new(Default::default(), 30, scripts);
+          sim.run(TICKS).unwrap()
+        })
+      },
+    );
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_tick_loop);
+criterion_main!(benches);
@@ -1,16 +1,17 @@
 use futures::stream::StreamExt;
 
-use flo_constants::NODE_CLIENT_PORT;
 use flo_net::listener::FloListener;
+use flo_net::proto::flo_common::{ErrorCode, TraceContext};
 use flo_net::proto::flo_node::*;
 use flo_net::stream::FloStream;
 
 use crate::error::*;
 use crate::state::{GlobalState, GlobalStateRef, PlayerToken};
 use flo_w3gs::constants::LeaveReason;
+use tracing_futures::Instrument;
 
 pub async fn serve_client(state: GlobalStateRef) -> Result<()> {
-  let mut listener = FloListener::bind_v4(NODE_CLIENT_PORT).await?;
+  let mut listener = FloListener::bind_v4(crate::config::get()?.client_port).await?;
 
   while let Some(incoming) = listener.incoming().next().await {
     if let Ok(mut stream) = incoming {
@@ -27,6 +28,7 @@ pub async fn serve_client(state: GlobalStateRef) -> Result<()> {
               .send(PacketClientConnectReject {
                 reason: reason.into(),
                 message: format!("{}", err),
+                error_code: err.code().into(),
               })
               .await
               .ok();
@@ -34,55 +36,13 @@ pub async fn serve_client(state: GlobalStateRef) -> Result<()> {
           }
         };
 
-        tracing::debug!(
+        let span = tracing::info_span!(
+          "client",
           game_id = claim.game_id,
-          player_id = claim.player_id,
-          "connected"
+          player_id = claim.player_id
         );
-
-        let session = match state.get_game(claim.game_id) {
-          Some(session) => session,
-          None => {
-            stream
-              .send(PacketClientConnectReject {
-                reason: ClientConnectRejectReason::Unknown.into(),
-                message: format!("Game session was not found."),
-              })
-              .await
-              .ok();
-            return;
-          }
-        };
-
-        if claim.shutdown_retry {
-          if let Err(err) = session
-            .retry_shutdown(claim.player_id, claim.leave_reason, &mut stream)
-            .await
-          {
-            tracing::error!(
-              game_id = claim.game_id,
-              player_id = claim.player_id,
-              "retry_shutdown: {}",
-              err
-            );
-            reject(&mut stream, err).await.ok();
-          }
-        } else {
-          if let Err((stream, err)) = session
-            .register_player_stream(claim.player_id, stream)
-            .await
-          {
-            tracing::error!(
-              game_id = claim.game_id,
-              player_id = claim.player_id,
-              "register player stream: {}",
-              err
-            );
-            if let Some(mut stream) = stream {
-              reject(&mut stream, err).await.ok();
-            }
-          }
-        }
+        flo_net::trace::set_parent(&span, claim.trace_context.as_ref());
+        serve_player(&state, stream, claim).instrument(span).await;
       });
     }
   }
@@ -90,15 +50,58 @@ pub async fn serve_client(state: GlobalStateRef) -> Result<()> {
   Ok(())
 }
 
+async fn serve_player(state: &GlobalState, mut stream: FloStream, claim: Claim) {
+  tracing::debug!("connected");
+
+  let session = match state.get_game(claim.game_id) {
+    Some(session) => session,
+    None => {
+      stream
+        .send(PacketClientConnectReject {
+          reason: ClientConnectRejectReason::Unknown.into(),
+          message: format!("Game session was not found."),
+          error_code: ErrorCode::GameNotFound.into(),
+        })
+        .await
+        .ok();
+      return;
+    }
+  };
+
+  let context = ErrorContext::game(claim.game_id).with_player(claim.player_id);
+  if claim.shutdown_retry {
+    if let Err(err) = session
+      .retry_shutdown(claim.player_id, claim.leave_reason, &mut stream)
+      .await
+    {
+      let err = err.with_context(context);
+      tracing::error!("retry_shutdown: {}", err);
+      reject(&mut stream, err).await.ok();
+    }
+  } else {
+    if let Err((stream, err)) = session
+      .register_player_stream(claim.player_id, stream)
+      .await
+    {
+      let err = err.with_context(context);
+      tracing::error!("register player stream: {}", err);
+      if let Some(mut stream) = stream {
+        reject(&mut stream, err).await.ok();
+      }
+    }
+  }
+}
+
 async fn reject(stream: &mut FloStream, err: Error) -> Result<()> {
   stream
     .send(PacketClientConnectReject {
-      reason: match err {
+      reason: match err.root() {
         Error::PlayerConnectionExists => ClientConnectRejectReason::Multi,
         _ => ClientConnectRejectReason::Unknown,
       }
       .into(),
       message: format!("Register: {}", err),
+      error_code: err.code().into(),
     })
     .await?;
   Ok(())
@@ -118,6 +121,7 @@ async fn handshake(state: &GlobalState, stream: &mut FloStream) -> Result<Claim>
 
   let pending = state
     .get_pending_player(&token)
+    .await?
     .ok_or_else(|| Error::InvalidToken)?;
 
   Ok(Claim {
@@ -125,6 +129,7 @@ async fn handshake(state: &GlobalState, stream: &mut FloStream) -> Result<Claim>
     player_id: pending.player_id,
     shutdown_retry: connect.retry_shutdown,
     leave_reason: connect.leave_reason.map(LeaveReason::from),
+    trace_context: connect.trace_context,
   })
 }
 
@@ -134,4 +139,5 @@ pub struct Claim {
   player_id: i32,
   shutdown_retry: bool,
   leave_reason: Option<LeaveReason>,
+  trace_context: Option<TraceContext>,
 }
@@ -0,0 +1,22 @@
+use crate::error::Result;
+use flo_config::service::{ConfigReceiver, ServiceConfig};
+use flo_config::NodeConfig;
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+static CONFIG: OnceCell<ConfigReceiver<NodeConfig>> = OnceCell::new();
+
+/// Loads the config and reloads it on SIGHUP or when the file changes
+pub fn init() -> Result<()> {
+  CONFIG.set(flo_config::service::watch()?).ok();
+  Ok(())
+}
+
+/// The current config, loaded once without reloading if `init` wasn't called
+pub fn get() -> Result<Arc<NodeConfig>> {
+  let rx = CONFIG
+    .get_or_try_init(|| -> Result<_> { Ok(watch::channel(Arc::new(NodeConfig::load()?)).1) })?;
+  let config = rx.borrow().clone();
+  Ok(config)
+}
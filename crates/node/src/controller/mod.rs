@@ -1,16 +1,19 @@
 use futures::lock::Mutex;
 use futures::stream::StreamExt;
-use parking_lot::RwLock;
+use once_cell::sync::Lazy;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::watch;
 use tracing_futures::Instrument;
 
-use flo_constants::NODE_CONTROLLER_PORT;
+use flo_net::dispatch::Dispatcher;
 use flo_net::listener::FloListener;
 use flo_net::packet::Frame;
 use flo_net::proto::flo_node::*;
 use flo_net::stream::FloStream;
-use flo_net::try_flo_packet;
 use flo_task::{SpawnScope, SpawnScopeHandle};
 
 use crate::error::*;
@@ -25,19 +28,45 @@ pub struct ControllerServer {
 #[derive(Debug)]
 struct State {
   g_state: GlobalStateRef,
-  current: RwLock<Option<ControllerConn>>,
+  /// Oldest first, the lobby can keep several connections
+  conns: parking_lot::Mutex<VecDeque<ControllerConn>>,
+  next_conn_id: AtomicU64,
+  /// Frames not sent in reply to a lobby frame, sent on the oldest connection only,
+  /// the lobby handles each connection on its own and would reorder them
   frame_tx: Sender<Frame>,
   frame_rx: Mutex<Receiver<Frame>>,
+  /// Id of the oldest connection
+  events_conn_tx: watch::Sender<Option<u64>>,
+  events_conn_rx: watch::Receiver<Option<u64>>,
+}
+
+impl State {
+  fn update_events_conn(&self, conns: &VecDeque<ControllerConn>) {
+    let id = conns.front().map(|conn| conn.id);
+    if *self.events_conn_rx.borrow() != id {
+      self.events_conn_tx.send(id).ok();
+    }
+  }
+
+  fn remove_conn(&self, id: u64) {
+    let mut conns = self.conns.lock();
+    conns.retain(|conn| conn.id != id);
+    self.update_events_conn(&conns);
+  }
 }
 
 impl ControllerServer {
   pub fn new(g_state: GlobalStateRef) -> ControllerServer {
     let (frame_tx, frame_rx) = channel(crate::constants::CONTROLLER_SENDER_BUF_SIZE);
+    let (events_conn_tx, events_conn_rx) = watch::channel(None);
     let state = Arc::new(State {
       g_state,
-      current: RwLock::new(None),
+      conns: parking_lot::Mutex::new(VecDeque::new()),
+      next_conn_id: AtomicU64::new(0),
       frame_tx,
       frame_rx: Mutex::new(frame_rx),
+      events_conn_tx,
+      events_conn_rx,
     });
     Self { state }
   }
@@ -49,12 +78,19 @@ impl ControllerServer {
   }
 
   pub async fn serve(&mut self) -> Result<()> {
-    let mut listener = FloListener::bind_v4(NODE_CONTROLLER_PORT).await?;
+    let mut listener = FloListener::bind_v4(crate::config::get()?.controller_port).await?;
 
     while let Some(incoming) = listener.incoming().next().await {
       if let Ok(stream) = incoming {
         if let Ok(conn) = self.handshake(stream).await {
-          self.state.current.write().replace(conn);
+          let max = crate::config::get()?.max_controller_connections;
+          let mut conns = self.state.conns.lock();
+          conns.push_back(conn);
+          // dropping a connection closes it
+          while conns.len() > max {
+            conns.pop_front();
+          }
+          self.state.update_events_conn(&conns);
         }
       }
     }
@@ -68,7 +104,7 @@ impl ControllerServer {
 
     let connect: PacketControllerConnect = stream.recv_timeout(RECV_TIMEOUT).await?;
 
-    if connect.secret != crate::env::Env::get().secret_key {
+    if connect.secret != crate::config::get()?.secret_key {
       stream
         .send(PacketControllerConnectReject {
           reason: ControllerConnectRejectReason::InvalidSecretKey.into(),
@@ -109,29 +145,43 @@ impl ControllerServerHandle {
       .await
       .map_err(|err| err.0)
   }
+
+  /// Sends a frame to the controller, or drops it if the send buf is full
+  pub fn try_send(&self, frame: Frame) -> Result<(), Frame> {
+    self
+      .state
+      .frame_tx
+      .try_send(frame)
+      .map_err(|err| match err {
+        TrySendError::Full(frame) | TrySendError::Closed(frame) => frame,
+      })
+  }
 }
 
 #[derive(Debug)]
 struct ControllerConn {
+  id: u64,
   _scope: SpawnScope,
 }
 
 impl ControllerConn {
   fn new(state: Arc<State>, stream: FloStream) -> Self {
     let scope = SpawnScope::new();
+    let id = state.next_conn_id.fetch_add(1, Ordering::Relaxed);
 
     tokio::spawn({
       let scope = scope.handle();
       async move {
-        if let Err(e) = handle_stream(state, stream, scope).await {
+        if let Err(e) = handle_stream(state.clone(), stream, scope, id).await {
           tracing::debug!("handle_stream: {}", e);
         }
+        state.remove_conn(id);
         tracing::debug!("exiting")
       }
       .instrument(tracing::debug_span!("worker"))
     });
 
-    ControllerConn { _scope: scope }
+    ControllerConn { id, _scope: scope }
   }
 }
 
@@ -139,8 +189,12 @@ async fn handle_stream(
   state: Arc<State>,
   mut stream: FloStream,
   mut scope: SpawnScopeHandle,
+  id: u64,
 ) -> Result<()> {
-  let mut rx = state.frame_rx.lock().await;
+  // replies go out on the connection of the request, pongs included
+  let (reply_tx, mut reply_rx) = channel(crate::constants::CONTROLLER_SENDER_BUF_SIZE);
+  let mut events_conn = state.events_conn_rx.clone();
+  let mut send_events = *events_conn.borrow() == Some(id);
   loop {
     tokio::select! {
       _ = scope.left() => {
@@ -149,13 +203,20 @@ async fn handle_stream(
       frame = stream.recv_frame() => {
         let frame = frame?;
         let state = state.clone();
+        let reply_tx = reply_tx.clone();
         tokio::spawn(async move {
-          if let Err(e) = handle_frame(&state, frame).await {
+          if let Err(e) = handle_frame(&state, &reply_tx, frame).await {
             tracing::error!("handle_frame: {}", e);
           }
         }.instrument(tracing::debug_span!("handle_frame_worker")));
       }
-      next = rx.recv() => {
+      Some(frame) = reply_rx.recv() => {
+        stream.send_frame_timeout(frame).await?;
+      }
+      Ok(()) = events_conn.changed() => {
+        send_events = *events_conn.borrow() == Some(id);
+      }
+      next = next_frame(&state), if send_events => {
         if let Some(frame) = next {
           stream.send_frame_timeout(frame).await?;
         } else {
@@ -167,25 +228,63 @@ async fn handle_stream(
   Ok(())
 }
 
-async fn handle_frame(state: &Arc<State>, mut frame: Frame) -> Result<()> {
-  let tx = &state.frame_tx;
+// the receiver is only locked while waiting, so a stalled connection holds at most one frame
+async fn next_frame(state: &State) -> Option<Frame> {
+  state.frame_rx.lock().await.recv().await
+}
+
+async fn handle_frame(state: &Arc<State>, tx: &Sender<Frame>, frame: Frame) -> Result<()> {
   if frame.type_id == PingStream::PING_TYPE_ID {
-    frame.type_id = PingStream::PONG_TYPE_ID;
-    tx.send(frame).await.ok();
+    tx.send(flo_net::keepalive::pong(frame)).await.ok();
     return Ok(());
   }
 
-  try_flo_packet! {
-    frame => {
-      pkt: PacketControllerCreateGame => {
-        let frame = state.g_state.handle_controller_create_game(ControllerServerHandle::new(state.clone()), pkt)?;
+  DISPATCHER
+    .dispatch((state.clone(), tx.clone()), frame)
+    .await
+}
+
+/// The state and the sender of the connection the frame came from
+type FrameContext = (Arc<State>, Sender<Frame>);
+
+static DISPATCHER: Lazy<Dispatcher<FrameContext, Error>> = Lazy::new(|| {
+  Dispatcher::new("controller")
+    .on(
+      |(state, tx): FrameContext, pkt: PacketControllerCreateGame| async move {
+        let frame = state
+          .g_state
+          .handle_controller_create_game(ControllerServerHandle::new(state.clone()), pkt)
+          .await?;
         flo_log::result_ok!("create game", tx.send(frame).await);
-      }
-      pkt: PacketControllerUpdateSlotStatus => {
-        let frame = state.g_state.handle_controller_update_slot_client_status(pkt).await?;
+        Ok(())
+      },
+    )
+    .on(
+      |(state, tx): FrameContext, pkt: PacketControllerUpdateSlotStatus| async move {
+        let frame = state
+          .g_state
+          .handle_controller_update_slot_client_status(pkt)
+          .await?;
         flo_log::result_ok!("update slot status", tx.send(frame).await);
-      }
-    }
-  }
-  Ok(())
-}
+        Ok(())
+      },
+    )
+    .on(
+      |(state, tx): FrameContext, pkt: PacketControllerGameStatusRequest| async move {
+        let frame = state.g_state.handle_controller_game_status(pkt).await?;
+        flo_log::result_ok!("game status", tx.send(frame).await);
+        Ok(())
+      },
+    )
+    .on(
+      |_: FrameContext, pkt: PacketControllerSetPacketCapture| async move {
+        flo_net::capture::set_enabled(pkt.enabled);
+        Ok(())
+      },
+    )
+    // sent by newer controllers
+    .on_unknown(|_, frame: Frame| async move {
+      tracing::warn!("unknown controller frame: {:?}", frame.type_id);
+      Ok(())
+    })
+});
@@ -1,22 +1,28 @@
 use crate::error::Result;
+use flo_net::echo::{unix_now, verify_datagram, ECHO_DATAGRAM_LEN};
 use std::net::{Ipv4Addr, SocketAddrV4};
 use tokio::net::UdpSocket;
-const ALLOWED_ECHO_DATAGRAM_LEN: &[usize] = &[4, 8];
-const MAX_RECV_BUF: usize = 8;
 
 use flo_constants::NODE_ECHO_PORT;
 
 pub async fn serve_echo() -> Result<()> {
   let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, NODE_ECHO_PORT)).await?;
+  let secret = crate::config::get()?.secret_key.clone();
 
-  let mut recv_buf = [0_u8; MAX_RECV_BUF];
+  // one byte more to tell longer datagrams apart, they are cut to the buffer size
+  let mut recv_buf = [0_u8; ECHO_DATAGRAM_LEN + 1];
 
   loop {
     if let Some((size, peer)) = socket.recv_from(&mut recv_buf).await.ok() {
-      if !ALLOWED_ECHO_DATAGRAM_LEN.contains(&size) {
+      if size > ECHO_DATAGRAM_LEN {
         continue;
       }
-      socket.send_to(&recv_buf[..size], &peer).await.ok();
+      let allow_legacy = crate::config::get()?.echo_legacy_datagrams;
+      // only the payload is echoed back, the token is not needed by the client
+      // legacy datagrams have no token and are echoed back whole if allowed
+      if let Some(payload) = verify_datagram(&secret, &recv_buf[..size], unix_now(), allow_legacy) {
+        socket.send_to(payload, &peer).await.ok();
+      }
     }
   }
 }
@@ -1,15 +1,34 @@
 use once_cell::sync::Lazy;
 use std::env;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub struct Env {
-  pub secret_key: String,
+  /// Stores replays in a local directory, served by the HTTP server
+  pub replay_dir: Option<PathBuf>,
+  /// Uploads replays to a S3 bucket
+  pub replay_s3_bucket: Option<String>,
+  pub replay_s3_endpoint: Option<String>,
+  /// Saves the hosted games to a file, lobby games are restored from it on startup
+  pub state_snapshot_path: Option<PathBuf>,
+  /// Records a W3GS trace of each game in this directory, for debugging
+  pub w3gs_trace_dir: Option<PathBuf>,
+  /// Stores the player tokens in Redis instead of memory
+  pub redis_url: Option<String>,
+  /// Prefixes the Redis keys of this node, required with `redis_url`
+  pub node_id: Option<String>,
 }
 
 impl Env {
   pub fn get() -> &'static Env {
     static INSTANCE: Lazy<Env> = Lazy::new(|| Env {
-      secret_key: env::var("FLO_NODE_SECRET").unwrap_or_default(),
+      replay_dir: env::var("FLO_NODE_REPLAY_DIR").ok().map(PathBuf::from),
+      replay_s3_bucket: env::var("FLO_NODE_REPLAY_S3_BUCKET").ok(),
+      replay_s3_endpoint: env::var("FLO_NODE_REPLAY_S3_ENDPOINT").ok(),
+      state_snapshot_path: env::var("FLO_NODE_STATE_SNAPSHOT").ok().map(PathBuf::from),
+      w3gs_trace_dir: env::var("FLO_NODE_W3GS_TRACE_DIR").ok().map(PathBuf::from),
+      redis_url: env::var("FLO_NODE_REDIS_URL").ok(),
+      node_id: env::var("FLO_NODE_ID").ok(),
     });
     &INSTANCE
   }
@@ -1,12 +1,19 @@
 use crate::game::{AckError, SlotClientStatus};
+use flo_net::proto::flo_common::ErrorCode;
 use thiserror::Error;
 
+pub use flo_util::error::ErrorContext;
+
 #[derive(Error, Debug)]
 pub enum Error {
+  #[error("config: {0}")]
+  Config(#[from] flo_config::error::Error),
   #[error("cancelled")]
   Cancelled,
   #[error("game exists")]
   GameExists,
+  #[error("node full")]
+  NodeFull,
   #[error("game desync: {0:?}")]
   GameDesync(#[from] AckError),
   #[error("game has no player")]
@@ -33,6 +40,10 @@ pub enum Error {
   InvalidClientStatusTransition(SlotClientStatus, SlotClientStatus),
   #[error("observer put record: {0}")]
   ObsPutRecord(#[from] rusoto_core::RusotoError<rusoto_kinesis::PutRecordError>),
+  #[error("invalid replay storage config: {0}")]
+  InvalidReplayStorage(&'static str),
+  #[error("replay upload: {0}")]
+  ReplayUpload(#[from] rusoto_core::RusotoError<rusoto_s3::PutObjectError>),
   #[error("tokio io: {0}")]
   Tokio(#[from] tokio::io::Error),
   #[error("operation timeout")]
@@ -45,6 +56,82 @@ pub enum Error {
   Proto(#[from] s2_grpc_utils::result::Error),
   #[error("http: {0}")]
   Http(#[from] hyper::Error),
+  #[error("FLO_NODE_ID is required with FLO_NODE_REDIS_URL")]
+  RedisNodeIdMissing,
+  #[error("redis: {0}")]
+  Redis(#[from] redis::RedisError),
+  #[error("{source} ({context})")]
+  WithContext {
+    source: Box<Error>,
+    context: ErrorContext,
+  },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+  /// Adds the game or player id to the error, see `ErrorContext`
+  pub fn with_context(self, context: ErrorContext) -> Self {
+    match self {
+      Error::WithContext {
+        source,
+        context: inner,
+      } => Error::WithContext {
+        source,
+        context: inner.merge(context),
+      },
+      source if context.is_empty() => source,
+      source => Error::WithContext {
+        source: Box::new(source),
+        context,
+      },
+    }
+  }
+
+  /// The error without context, match on this instead of the error itself
+  pub fn root(&self) -> &Error {
+    match self {
+      Error::WithContext { source, .. } => source,
+      err => err,
+    }
+  }
+
+  /// The stable code sent to clients next to the message.
+  /// No wildcard arm so new variants have to pick one.
+  pub fn code(&self) -> ErrorCode {
+    match self {
+      Error::WithContext { source, .. } => source.code(),
+      Error::InvalidToken => ErrorCode::InvalidToken,
+      Error::InvalidSecret => ErrorCode::PermissionDenied,
+      Error::PlayerConnectionExists => ErrorCode::AlreadyConnected,
+      Error::PlayerNotFoundInGame | Error::PlayerAlreadyLeft => ErrorCode::PlayerNotInGame,
+      Error::PlayerBusy(_) => ErrorCode::PlayerAlreadyInGame,
+      Error::NodeFull => ErrorCode::NodeFull,
+      Error::Timeout(_) => ErrorCode::Timeout,
+      Error::Cancelled | Error::PlayerChannelBroken => ErrorCode::Unavailable,
+      Error::W3GS(_) | Error::GameDesync(_) => ErrorCode::GameDataInvalid,
+      Error::GameExists
+      | Error::NoPlayer
+      | Error::InvalidSlotId
+      | Error::InvalidPlayerSlotClientStatus(_)
+      | Error::InvalidClientStatusTransition(..) => ErrorCode::InvalidRequest,
+      Error::Config(_)
+      | Error::ObsPutRecord(_)
+      | Error::InvalidReplayStorage(_)
+      | Error::ReplayUpload(_)
+      | Error::Tokio(_)
+      | Error::Net(_)
+      | Error::Proto(_)
+      | Error::Http(_)
+      | Error::RedisNodeIdMissing
+      | Error::Redis(_) => ErrorCode::Internal,
+    }
+  }
+}
+
+#[test]
+fn test_error_code() {
+  let err = Error::PlayerConnectionExists.with_context(ErrorContext::game(1).with_player(2));
+  assert_eq!(err.code(), ErrorCode::AlreadyConnected);
+  assert_eq!(Error::PlayerBusy(2).code(), ErrorCode::PlayerAlreadyInGame);
+}
@@ -0,0 +1,256 @@
+//! Decodes the actions relayed by the dispatcher off the relay path, to count the action stats
+//! and flag player behaviors that are impossible for humans.
+//! Samples are dropped if the task falls behind, they are counted in the stats of the player.
+//! The queued samples are drained with `AnomalyMessage::Flush` before the stats are packed.
+//! Unit visibility is only known to game clients, so selections of units
+//! hidden by the fog of war can't be checked on the node.
+
+use super::stats::ActionStatsState;
+use crate::game::{GameEvent, GameEventSender};
+use bytes::Bytes;
+use flo_net::proto::flo_node::GameAnomalyKind;
+use flo_util::binary::BinDecode;
+use flo_w3gs::actions::Action;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use tracing_futures::Instrument;
+
+const ACTION_RATE_WINDOW_MS: u32 = 1000;
+/// Sustained for a whole window, far above the peaks of professional players
+const MAX_ACTIONS_PER_WINDOW: usize = 40;
+/// Hotkey group selections each followed by a command, within a single action packet
+const MAX_GROUP_COMMANDS_PER_PACKET: usize = 2;
+/// Recent action packets of a player attached to reports
+const EVIDENCE_PACKETS: usize = 8;
+/// Each kind of anomaly is reported at most once per player within this game time
+const REPORT_COOLDOWN_MS: u32 = 60_000;
+const SAMPLE_BUF_SIZE: usize = 64;
+
+#[derive(Debug)]
+pub enum AnomalyMessage {
+  Sample(ActionSample),
+  /// Resolved once the samples queued before it are recorded
+  Flush(oneshot::Sender<()>),
+}
+
+#[derive(Debug)]
+pub struct ActionSample {
+  pub player_id: i32,
+  pub tick: u32,
+  pub time_ms: u32,
+  pub data: Bytes,
+}
+
+#[derive(Debug)]
+pub struct GameAnomaly {
+  pub player_id: i32,
+  pub kind: GameAnomalyKind,
+  pub tick: u32,
+  pub detail: String,
+  pub evidence: Vec<Bytes>,
+}
+
+/// Starts the analysis task of a game, samples are dropped if it falls behind
+pub fn spawn(
+  game_id: i32,
+  stats: Arc<Mutex<ActionStatsState>>,
+  out_tx: GameEventSender,
+  ct: CancellationToken,
+) -> Sender<AnomalyMessage> {
+  let (tx, mut rx) = channel(SAMPLE_BUF_SIZE);
+  tokio::spawn(
+    async move {
+      let mut detector = AnomalyDetector::default();
+      loop {
+        tokio::select! {
+          _ = ct.cancelled() => break,
+          next = rx.recv() => {
+            let sample = match next {
+              Some(AnomalyMessage::Sample(sample)) => sample,
+              Some(AnomalyMessage::Flush(tx)) => {
+                tx.send(()).ok();
+                continue;
+              }
+              None => break,
+            };
+            let actions = if let Some(actions) = decode_actions(&sample.data) {
+              actions
+            } else {
+              tracing::debug!(player_id = sample.player_id, "sample dropped: invalid actions");
+              continue;
+            };
+            stats.lock().record(sample.player_id, sample.time_ms, &actions);
+            for anomaly in detector.feed(&sample, &actions) {
+              tracing::warn!(
+                game_id,
+                player_id = anomaly.player_id,
+                "anomaly detected: {:?}: {}",
+                anomaly.kind,
+                anomaly.detail
+              );
+              if out_tx.send(GameEvent::Anomaly(anomaly)).await.is_err() {
+                return;
+              }
+            }
+          }
+        }
+      }
+    }
+    .instrument(tracing::debug_span!("anomaly", game_id)),
+  );
+  tx
+}
+
+/// Decodes the actions of a packet along with their bytes.
+/// Returns `None` if any of them can't be decoded, unknown actions can't be skipped.
+pub fn decode_actions(data: &Bytes) -> Option<Vec<(Action, Bytes)>> {
+  let mut actions = vec![];
+  let mut buf = data.as_ref();
+  while !buf.is_empty() {
+    let offset = data.len() - buf.len();
+    let action = Action::decode(&mut buf).ok()?;
+    actions.push((action, data.slice(offset..(data.len() - buf.len()))));
+  }
+  Some(actions)
+}
+
+#[derive(Debug, Default)]
+struct PlayerActivity {
+  // game time of each action in the current window
+  action_times: VecDeque<u32>,
+  recent: VecDeque<Bytes>,
+  last_report_times: BTreeMap<i32, u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct AnomalyDetector {
+  players: BTreeMap<i32, PlayerActivity>,
+}
+
+impl AnomalyDetector {
+  pub fn feed(&mut self, sample: &ActionSample, actions: &[(Action, Bytes)]) -> Vec<GameAnomaly> {
+    let player = self.players.entry(sample.player_id).or_default();
+    player.recent.push_back(sample.data.clone());
+    if player.recent.len() > EVIDENCE_PACKETS {
+      player.recent.pop_front();
+    }
+
+    let mut group_commands = 0;
+    let mut group_selected = false;
+    for (action, _) in actions {
+      match action {
+        Action::SelectGroupHotkey(_) => group_selected = true,
+        Action::UnitBuildingAbility(_)
+        | Action::UnitBuildingAbilityTargeted(_)
+        | Action::UnitBuildingAbilityTargetedId(_)
+        | Action::ItemGivenDropped(_)
+        | Action::UnitBuildingAbility2Targets2Items(_) => {
+          if group_selected {
+            group_commands += 1;
+            group_selected = false;
+          }
+        }
+        _ => {}
+      }
+    }
+
+    for _ in 0..actions.len() {
+      player.action_times.push_back(sample.time_ms);
+    }
+    while let Some(time) = player.action_times.front() {
+      if sample.time_ms.saturating_sub(*time) >= ACTION_RATE_WINDOW_MS {
+        player.action_times.pop_front();
+      } else {
+        break;
+      }
+    }
+
+    let mut found = vec![];
+    if player.action_times.len() > MAX_ACTIONS_PER_WINDOW {
+      found.push((
+        GameAnomalyKind::ActionRate,
+        format!(
+          "{} actions in {}ms",
+          player.action_times.len(),
+          ACTION_RATE_WINDOW_MS
+        ),
+      ));
+    }
+    if group_commands > MAX_GROUP_COMMANDS_PER_PACKET {
+      found.push((
+        GameAnomalyKind::MultiGroupCommand,
+        format!("{} hotkey groups commanded at once", group_commands),
+      ));
+    }
+
+    let mut anomalies = vec![];
+    for (kind, detail) in found {
+      let last = player.last_report_times.get(&(kind as i32));
+      if matches!(last, Some(time) if sample.time_ms.saturating_sub(*time) < REPORT_COOLDOWN_MS) {
+        continue;
+      }
+      player.last_report_times.insert(kind as i32, sample.time_ms);
+      anomalies.push(GameAnomaly {
+        player_id: sample.player_id,
+        kind,
+        tick: sample.tick,
+        detail,
+        evidence: player.recent.iter().cloned().collect(),
+      });
+    }
+    anomalies
+  }
+}
+
+#[test]
+fn test_anomaly_action_rate() {
+  let mut detector = AnomalyDetector::default();
+  // 10 x EscPressed
+  let data = Bytes::from_static(&[0x61; 10]);
+  let actions = decode_actions(&data).unwrap();
+  let mut feed = |tick: u32| {
+    let sample = ActionSample {
+      player_id: 1,
+      tick,
+      time_ms: tick * 100,
+      data: data.clone(),
+    };
+    detector.feed(&sample, &actions)
+  };
+  for i in 0..4 {
+    assert!(feed(i).is_empty());
+  }
+  let anomalies = feed(4);
+  assert_eq!(anomalies.len(), 1);
+  assert_eq!(anomalies[0].kind, GameAnomalyKind::ActionRate);
+  assert_eq!(anomalies[0].evidence.len(), 5);
+
+  // cooldown
+  assert!(feed(5).is_empty());
+}
+
+#[test]
+fn test_anomaly_multi_group_command() {
+  let mut detector = AnomalyDetector::default();
+  let select_group: &[u8] = &[0x18, 0x01, 0x00];
+  // ability_flag, item_id, 2 x unknown
+  let command: &[u8] = &[0x10, 0, 0, 0x03, 0x00, 0x0D, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+  let mut data = vec![];
+  for _ in 0..3 {
+    data.extend_from_slice(select_group);
+    data.extend_from_slice(command);
+  }
+  let sample = ActionSample {
+    player_id: 1,
+    tick: 0,
+    time_ms: 0,
+    data: Bytes::from(data),
+  };
+  let anomalies = detector.feed(&sample, &decode_actions(&sample.data).unwrap());
+  assert_eq!(anomalies.len(), 1);
+  assert_eq!(anomalies[0].kind, GameAnomalyKind::MultiGroupCommand);
+}
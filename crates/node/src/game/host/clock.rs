@@ -4,10 +4,30 @@ use std::pin::Pin;
 use std::time::{Duration, Instant};
 use tokio::time::{sleep, Sleep};
 
+use crate::constants::GAME_CLOCK_MAX_PAUSE;
 use flo_w3gs::protocol::action::PlayerAction;
 use futures::task::{Context, Poll};
 use std::task::Waker;
 
+/// Source of the game ticks, real time in the dispatcher and virtual time in the simulation
+pub trait GameClock {
+  fn add_action(&mut self, action: PlayerAction);
+  fn replace_actions(&mut self, actions: Vec<PlayerAction>);
+  fn set_step(&mut self, value: u16);
+  fn step(&self) -> u16;
+  /// Stops ticking until resumed, `ClockEvent::PauseTimeout` is emitted
+  /// if the clock stays paused for `GAME_CLOCK_MAX_PAUSE`
+  fn pause(&mut self);
+  fn is_paused(&self) -> bool;
+  fn resume(&mut self);
+}
+
+#[derive(Debug)]
+pub enum ClockEvent {
+  Tick(Tick),
+  PauseTimeout,
+}
+
 #[derive(Debug)]
 pub struct ActionTickStream {
   paused: bool,
@@ -36,40 +56,45 @@ impl ActionTickStream {
       resume_waker: None,
     }
   }
+}
 
-  pub fn set_step(&mut self, value: u16) {
-    self.step = std::cmp::min(Self::MAX_STEP, std::cmp::max(Self::MIN_STEP, value));
-    self.step_duration = Duration::from_millis(value as u64);
-    self
-      .delay
-      .as_mut()
-      .reset((Instant::now() + self.step_duration).into());
+impl GameClock for ActionTickStream {
+  fn add_action(&mut self, action: PlayerAction) {
+    self.actions.push(action)
   }
 
-  pub fn step(&self) -> u16 {
-    self.step
+  fn replace_actions(&mut self, actions: Vec<PlayerAction>) {
+    self.actions = actions;
   }
 
-  pub fn add_action(&mut self, action: PlayerAction) {
-    self.actions.push(action)
+  fn set_step(&mut self, value: u16) {
+    self.step = std::cmp::min(Self::MAX_STEP, std::cmp::max(Self::MIN_STEP, value));
+    self.step_duration = Duration::from_millis(value as u64);
+    if !self.paused {
+      self
+        .delay
+        .as_mut()
+        .reset((Instant::now() + self.step_duration).into());
+    }
   }
 
-  pub fn replace_actions(&mut self, actions: Vec<PlayerAction>) {
-    self.actions = actions;
+  fn step(&self) -> u16 {
+    self.step
   }
 
-  pub fn pause(&mut self) {
+  fn pause(&mut self) {
     self.paused = true;
-    self.delay.as_mut().reset(Instant::now().into());
+    self
+      .delay
+      .as_mut()
+      .reset((Instant::now() + GAME_CLOCK_MAX_PAUSE).into());
   }
 
-  pub fn is_paused(&self) -> bool {
-    {
-      self.paused
-    }
+  fn is_paused(&self) -> bool {
+    self.paused
   }
 
-  pub fn resume(&mut self) {
+  fn resume(&mut self) {
     self.paused = false;
     self
       .delay
@@ -87,14 +112,20 @@ pub struct Tick {
 }
 
 impl Stream for ActionTickStream {
-  type Item = Tick;
+  type Item = ClockEvent;
 
   fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
     if self.paused {
       if self.resume_waker.as_ref().map(|w| w.will_wake(cx.waker())) != Some(true) {
         self.resume_waker.replace(cx.waker().clone());
       }
-      return Poll::Pending;
+      // the delay is the pause timeout while paused
+      futures::ready!(Pin::new(&mut self.delay).poll(cx));
+      self
+        .delay
+        .as_mut()
+        .reset((Instant::now() + GAME_CLOCK_MAX_PAUSE).into());
+      return Poll::Ready(Some(ClockEvent::PauseTimeout));
     }
 
     // Wait for the delay to be done
@@ -114,6 +145,6 @@ impl Stream for ActionTickStream {
       actions,
       actions_bytes_len,
     };
-    Poll::Ready(Some(tick))
+    Poll::Ready(Some(ClockEvent::Tick(tick)))
   }
 }
@@ -1,30 +1,41 @@
+use super::anomaly::{self, ActionSample, AnomalyMessage};
 use super::broadcast;
-use super::clock::ActionTickStream;
+use super::clock::{ActionTickStream, ClockEvent, GameClock};
 use super::delay::{DelayedFrame, DelayedFrameStream};
 use super::player::{PlayerDispatchInfo, PlayerSendError};
+use super::rules::GameRulesState;
+use super::stats::ActionStatsState;
 use super::sync::SyncMap;
+use super::trace::GameTrace;
+use super::traffic::{GameTraffic, PlayerTraffic};
 use crate::error::*;
 use crate::game::host::clock::Tick;
 use crate::game::host::stream::{PlayerStream, PlayerStreamCmd, PlayerStreamHandle};
 use crate::game::host::sync::{ClockResult, PlayerDesync};
 use crate::game::{
-  AckError, GameEvent, GameEventSender, PlayerBanType, PlayerSlot, SlotClientStatus,
+  AckError, GameEvent, GameEventSender, GameRules, PlayerBanType, PlayerSlot, SlotClientStatus,
   SlotClientStatusUpdateSource,
 };
 use crate::observer::ObserverPublisherHandle;
+use flo_net::keepalive::{Incoming, KeepAlive, KeepAliveConfig, KeepAliveEvent};
 use flo_net::packet::{Frame, PacketTypeId};
-use flo_net::ping::{PingMsg, PingStream};
+use flo_net::proto::flo_common::GameLiveStatus;
+use flo_net::proto::flo_node::{GameTrafficStats, PlayerActionStats};
 use flo_net::w3gs::{W3GSFrameExt, W3GSMetadata, W3GSPacket, W3GSPacketTypeId};
 use flo_observer::record::{RTTStats, RTTStatsItem};
+use flo_util::binary::IntoCStringLossy;
 use flo_util::chat::{parse_chat_command, ChatCommand};
+use flo_util::word_list::WordList;
 use flo_w3gs::action::{IncomingAction, IncomingAction2, OutgoingKeepAlive};
 use flo_w3gs::protocol::action::{OutgoingAction, PlayerAction, TimeSlot};
-use flo_w3gs::protocol::chat::ChatToHost;
+use flo_w3gs::protocol::chat::{ChatFromHost, ChatMessage, ChatToHost};
 use flo_w3gs::protocol::constants::LeaveReason;
 use flo_w3gs::protocol::lag::{LagPlayer, StartLag, StopLag};
 use flo_w3gs::protocol::leave::LeaveReq;
 use flo_w3gs::protocol::leave::{LeaveAck, PlayerLeft};
+use flo_w3gs::protocol::map::{MapPartError, MapPartOK};
 use flo_w3gs::protocol::packet::*;
+use flo_w3gs::protocol::peer::{ClientInfo, PeerSet, PingFromOthers, PongToOthers};
 use futures::stream::StreamExt;
 use parking_lot::Mutex;
 use s2_grpc_utils::S2ProtoEnum;
@@ -34,11 +45,15 @@ use std::time::{Duration, Instant};
 use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::{oneshot, watch, Notify};
-use tokio::time::{interval_at, sleep, MissedTickBehavior};
+use tokio::time::{interval_at, MissedTickBehavior};
 use tokio_util::sync::CancellationToken;
 use tracing_futures::Instrument;
 
+#[cfg(feature = "sim")]
+pub mod sim;
+
 const DISPATCH_ACTIONS_MTU: usize = 1350 - 8;
+const ACTION_STATS_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum Cmd {
@@ -88,6 +103,9 @@ pub struct Dispatcher {
   ct: CancellationToken,
   cmd_tx: Sender<Cmd>,
   start_notify: Arc<Notify>,
+  shared: Arc<Mutex<Shared>>,
+  traffic: Arc<GameTraffic>,
+  anomaly_tx: Sender<AnomalyMessage>,
 }
 
 impl Drop for Dispatcher {
@@ -100,6 +118,8 @@ impl Dispatcher {
   pub fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    rules: GameRules,
+    chat_filter: WordList,
     obs: ObserverPublisherHandle,
     out_tx: GameEventSender,
   ) -> Self {
@@ -112,9 +132,12 @@ impl Dispatcher {
     let state = State::new(
       game_id,
       slots,
+      rules,
+      chat_filter,
       obs.clone(),
       status_rx,
       action_tx.clone(),
+      out_tx.clone(),
       ct.clone(),
     );
 
@@ -127,6 +150,10 @@ impl Dispatcher {
       start_messages.push(format!("Some players in this game have been muted: {}", chat_banned_player_names.join(", ")));
     }
 
+    let shared = state.shared.clone();
+    let traffic = state.traffic.clone();
+    let anomaly_tx = state.anomaly_tx.clone();
+
     tokio::spawn(
       Self::tick(
         game_id,
@@ -135,6 +162,7 @@ impl Dispatcher {
         start_notify.clone(),
         status_tx,
         action_rx,
+        cmd_tx.clone(),
         ct.clone(),
       )
       .instrument(tracing::debug_span!("tick", game_id)),
@@ -150,6 +178,9 @@ impl Dispatcher {
       game_id,
       cmd_tx,
       start_notify,
+      shared,
+      traffic,
+      anomaly_tx,
     }
   }
 
@@ -158,6 +189,33 @@ impl Dispatcher {
     self.start_notify.notify_one();
   }
 
+  pub fn live_status(&self) -> GameLiveStatus {
+    self.shared.lock().live_status()
+  }
+
+  pub fn action_stats(&self) -> Vec<PlayerActionStats> {
+    self.shared.lock().action_stats()
+  }
+
+  /// Waits for the queued action samples to be recorded, so `action_stats` counts them
+  pub async fn flush_action_stats(&self) {
+    let (tx, rx) = oneshot::channel();
+    let flush = async {
+      self.anomaly_tx.send(AnomalyMessage::Flush(tx)).await.ok();
+      rx.await.ok();
+    };
+    if tokio::time::timeout(ACTION_STATS_FLUSH_TIMEOUT, flush)
+      .await
+      .is_err()
+    {
+      tracing::warn!(game_id = self.game_id, "flush action stats: timeout");
+    }
+  }
+
+  pub fn traffic_stats(&self) -> GameTrafficStats {
+    self.traffic.pack()
+  }
+
   pub async fn register_player_stream(&self, stream: PlayerStream) -> Result<PlayerStreamHandle> {
     let (tx, rx) = oneshot::channel();
     self
@@ -231,6 +289,7 @@ impl Dispatcher {
     start_notify: Arc<Notify>,
     status_tx: watch::Sender<DispatchStatus>,
     mut rx: Receiver<ActionMsg>,
+    cmd_tx: Sender<Cmd>,
     ct: CancellationToken,
   ) {
     let started = {
@@ -241,6 +300,14 @@ impl Dispatcher {
     };
 
     if started {
+      let step_ms = match crate::config::get() {
+        Ok(config) => config.game_step_ms,
+        Err(err) => {
+          tracing::error!("start game: {}", err);
+          ct.cancel();
+          return;
+        }
+      };
       shared.lock().set_started();
       status_tx.send(DispatchStatus::Running).ok();
 
@@ -251,32 +318,37 @@ impl Dispatcher {
         }
       }
 
-      let mut tick_stream = ActionTickStream::new(*crate::constants::GAME_DEFAULT_STEP_MS);
-      let pause_timeout = sleep(Duration::from_secs(0));
-      tokio::pin!(pause_timeout);
+      let mut driver = TickDriver {
+        game_id,
+        clock: ActionTickStream::new(step_ms),
+        status_tx,
+      };
 
       {
         let ct = ct.clone();
         let shared = shared.clone();
-        tokio::spawn(async move {
-          let base_time = tokio::time::Instant::from_std(Instant::now());
-          let mut stream = interval_at(
-            base_time + crate::constants::RTT_STATS_REPORT_DELAY,
-            crate::constants::RTT_STATS_REPORT_INTERVAL,
-          );
-          stream.set_missed_tick_behavior(MissedTickBehavior::Skip);
-          loop {
-            tokio::select! {
-              _ = ct.cancelled() => {
-                break;
-              }
-              now = stream.tick() => {
-                let time = now.saturating_duration_since(base_time).as_millis();
-                shared.lock().push_rtt_stats(time as _);
+        tokio::spawn(
+          async move {
+            let base_time = tokio::time::Instant::from_std(Instant::now());
+            let mut stream = interval_at(
+              base_time + crate::constants::RTT_STATS_REPORT_DELAY,
+              crate::constants::RTT_STATS_REPORT_INTERVAL,
+            );
+            stream.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            loop {
+              tokio::select! {
+                _ = ct.cancelled() => {
+                  break;
+                }
+                now = stream.tick() => {
+                  let time = now.saturating_duration_since(base_time).as_millis();
+                  shared.lock().push_rtt_stats(time as _);
+                }
               }
             }
           }
-        });
+          .in_current_span(),
+        );
       }
 
       loop {
@@ -285,74 +357,98 @@ impl Dispatcher {
             break;
           }
           Some(msg) = rx.recv() => {
-            match msg {
-              ActionMsg::PlayerAction(action) => {
-                tick_stream.add_action(action);
-              }
-              ActionMsg::SetStep(step) => {
-                tick_stream.set_step(step);
-                shared
-                  .lock()
-                  .broadcast_message(format!("Game step has been set to {}ms.", tick_stream.step()));
-              },
-              ActionMsg::CheckStopLag => {
-                if tick_stream.is_paused() {
-                  match shared.lock().check_stop_lag() {
-                    Ok(true) => {
-                      tick_stream.resume();
-                      status_tx.send(DispatchStatus::Running).ok();
-                      tracing::info!(
-                        game_id,
-                        "resume clock: all lagging player resumed"
-                      );
-                    },
-                    Err(err) => {
-                      tracing::error!("check_stop_lag: {}", err);
-                    },
-                    _ => {}
-                  }
+            driver.handle_msg(&mut shared.lock(), msg);
+          }
+          Some(event) = driver.clock.next() => {
+            let res = driver.handle_event(&mut shared.lock(), event);
+            match res {
+              Ok(afk_player_ids) => {
+                for player_id in afk_player_ids {
+                  cmd_tx.send(Cmd::RemovePlayer {
+                    player_id,
+                    leave_reason: Some(LeaveReason::LeaveDisconnect),
+                  }).await.ok();
                 }
               },
-              ActionMsg::ResumeClock => {
-                tracing::info!(
-                  game_id,
-                  "resume clock"
-                );
-                tick_stream.resume();
-                status_tx.send(DispatchStatus::Running).ok();
-              }
-            }
-          }
-          Some(tick) = tick_stream.next() => {
-            match shared.lock().dispatch_action_tick(tick) {
-              Ok(DispatchResult::Continue) => {},
-              Ok(DispatchResult::Lag(tick)) => {
-                tick_stream.replace_actions(tick.actions);
-                pause_timeout.as_mut().reset((Instant::now() + crate::constants::GAME_CLOCK_MAX_PAUSE).into());
-                tick_stream.pause();
-                status_tx.send(DispatchStatus::Paused).ok();
-              }
               Err(err) => {
                 tracing::error!(
                   game_id,
-                  "dispatch action tick: {}", err
+                  "dispatch clock event: {}", err
                 );
                 break;
               }
             }
           }
-          _ = &mut pause_timeout, if tick_stream.is_paused() => {
-            if let Err(err) = shared.lock().drop_all_lag_players() {
-              tracing::error!(
-                game_id,
-                "drop all lag players: {}", err
+        }
+      }
+    }
+  }
+}
+
+/// Applies the game clock to `Shared`, driven by real time in `Dispatcher::tick`
+/// and by virtual time in the simulation
+struct TickDriver<C> {
+  game_id: i32,
+  clock: C,
+  status_tx: watch::Sender<DispatchStatus>,
+}
+
+impl<C: GameClock> TickDriver<C> {
+  fn handle_msg(&mut self, shared: &mut Shared, msg: ActionMsg) {
+    match msg {
+      ActionMsg::PlayerAction(action) => {
+        self.clock.add_action(action);
+      }
+      ActionMsg::SetStep(step) => {
+        self.clock.set_step(step);
+        shared.broadcast_message(format!(
+          "Game step has been set to {}ms.",
+          self.clock.step()
+        ));
+      }
+      ActionMsg::CheckStopLag => {
+        if self.clock.is_paused() {
+          match shared.check_stop_lag() {
+            Ok(true) => {
+              self.clock.resume();
+              self.status_tx.send(DispatchStatus::Running).ok();
+              tracing::info!(
+                game_id = self.game_id,
+                "resume clock: all lagging player resumed"
               );
-              break;
             }
-            tick_stream.resume();
+            Err(err) => {
+              tracing::error!("check_stop_lag: {}", err);
+            }
+            _ => {}
           }
         }
       }
+      ActionMsg::ResumeClock => {
+        tracing::info!(game_id = self.game_id, "resume clock");
+        self.clock.resume();
+        self.status_tx.send(DispatchStatus::Running).ok();
+      }
+    }
+  }
+
+  /// Returns the ids of the AFK players to remove
+  fn handle_event(&mut self, shared: &mut Shared, event: ClockEvent) -> Result<Vec<i32>> {
+    match event {
+      ClockEvent::Tick(tick) => match shared.dispatch_action_tick(tick)? {
+        DispatchResult::Continue => Ok(shared.take_afk_players()),
+        DispatchResult::Lag(tick) => {
+          self.clock.replace_actions(tick.actions);
+          self.clock.pause();
+          self.status_tx.send(DispatchStatus::Paused).ok();
+          Ok(vec![])
+        }
+      },
+      ClockEvent::PauseTimeout => {
+        shared.drop_all_lag_players()?;
+        self.clock.resume();
+        Ok(vec![])
+      }
     }
   }
 }
@@ -374,22 +470,32 @@ struct State {
   game_player_id_lookup: BTreeMap<u8, i32>,
   _player_name_lookup: BTreeMap<i32, String>,
   chat_banned_player_ids: Vec<i32>,
+  // player id -> players whose chat is not relayed to the player
+  mute_list_map: BTreeMap<i32, BTreeSet<i32>>,
+  chat_filter: WordList,
   left_players: BTreeSet<i32>,
+  anomaly_tx: Sender<AnomalyMessage>,
+  traffic: Arc<GameTraffic>,
 }
 
 impl State {
   fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    rules: GameRules,
+    chat_filter: WordList,
     obs: ObserverPublisherHandle,
     status_rx: watch::Receiver<DispatchStatus>,
     _action_tx: Sender<ActionMsg>,
+    out_tx: GameEventSender,
     ct: CancellationToken,
   ) -> Self {
+    let shared = Shared::new(game_id, slots, rules, obs);
+    let anomaly_tx = anomaly::spawn(game_id, shared.stats.clone(), out_tx, ct.clone());
     State {
       game_id,
       ct,
-      shared: Arc::new(Mutex::new(Shared::new(game_id, slots, obs))),
+      shared: Arc::new(Mutex::new(shared)),
       status_rx,
       game_player_id_lookup: slots
         .into_iter()
@@ -409,7 +515,22 @@ impl State {
           }
         })
         .collect(),
+      mute_list_map: slots
+        .into_iter()
+        .filter(|slot| !slot.player.mute_list.is_empty())
+        .map(|slot| {
+          (
+            slot.player.player_id,
+            slot.player.mute_list.iter().cloned().collect(),
+          )
+        })
+        .collect(),
+      chat_filter,
       left_players: BTreeSet::new(),
+      anomaly_tx,
+      traffic: Arc::new(GameTraffic::new(
+        slots.iter().map(|slot| slot.player.player_id),
+      )),
     }
   }
 
@@ -516,6 +637,7 @@ impl State {
       peer_cmd_rx,
       peer_tx.clone(),
       delay,
+      self.traffic.player(player_id),
     );
     tokio::spawn(
       async move {
@@ -675,16 +797,49 @@ impl State {
         );
         return Ok(());
       }
+      player.trace_received(&packet);
       player.slot_player_id()
     };
 
     match packet.type_id() {
       PacketTypeId::OutgoingAction => {
         let payload: OutgoingAction = packet.decode_payload()?;
+        let data = {
+          let mut shared = self.shared.lock();
+          let tick = shared.sync.tick();
+          match shared.rules.check_action(player_id, tick, payload.data) {
+            Ok(data) => {
+              let time_ms = shared.sync.time();
+              let sent = self
+                .anomaly_tx
+                .try_send(AnomalyMessage::Sample(ActionSample {
+                  player_id,
+                  tick,
+                  time_ms,
+                  data: data.clone(),
+                }))
+                .is_ok();
+              if !sent {
+                shared.stats.lock().record_dropped(player_id);
+              }
+              data
+            }
+            Err(msg) => {
+              tracing::info!(
+                game_id = self.game_id,
+                player_id,
+                "action rejected: {}",
+                msg
+              );
+              shared.private_message(player_id, msg);
+              return Ok(());
+            }
+          }
+        };
         action_tx
           .send(ActionMsg::PlayerAction(PlayerAction {
             player_id: slot_player_id,
-            data: payload.data,
+            data,
           }))
           .await
           .map_err(|_| Error::Cancelled)?;
@@ -727,6 +882,12 @@ impl State {
           }
         }
       }
+      PacketTypeId::PingFromOthers => log_ignored::<PingFromOthers>(player_id, &packet),
+      PacketTypeId::PongToOthers => log_ignored::<PongToOthers>(player_id, &packet),
+      PacketTypeId::ClientInfo => log_ignored::<ClientInfo>(player_id, &packet),
+      PacketTypeId::PeerSet => log_ignored::<PeerSet>(player_id, &packet),
+      PacketTypeId::MapPartOK => log_ignored::<MapPartOK>(player_id, &packet),
+      PacketTypeId::MapPartError => log_ignored::<MapPartError>(player_id, &packet),
       id => {
         tracing::warn!("unexpected w3gs packet id = {:?}", id);
       }
@@ -804,7 +965,7 @@ impl State {
   ) -> Result<()> {
     use flo_w3gs::protocol::constants::PacketTypeId;
 
-    let chat: ChatToHost = packet.decode_simple()?;
+    let mut chat: ChatToHost = packet.decode_simple()?;
     if let Some(cmd) = chat.chat_message().and_then(parse_chat_command) {
       if self.handle_command(action_tx, player_id, cmd).await? {
         return Ok(());
@@ -815,19 +976,27 @@ impl State {
       return Ok(());
     }
 
-    packet.header.type_id = PacketTypeId::ChatFromHost;
+    let to_players = chat.to_players.clone();
+    if mask_chat_message(&self.chat_filter, &mut chat.message) {
+      packet = Packet::simple(ChatFromHost(chat))?;
+    } else {
+      packet.header.type_id = PacketTypeId::ChatFromHost;
+    }
     {
       let mut guard = self.shared.lock();
       guard.obs.push_w3gs(self.game_id, packet.clone());
       guard.broadcast(
         packet,
         broadcast::AllowList(
-          &chat
-            .to_players
+          &to_players
             .into_iter()
             .filter_map(|id| {
               if let Some(id) = self.game_player_id_lookup.get(&id).cloned() {
-                if id != player_id {
+                let muted = matches!(
+                  self.mute_list_map.get(&id),
+                  Some(muted) if muted.contains(&player_id)
+                );
+                if id != player_id && !muted {
                   Some(id)
                 } else {
                   None
@@ -1034,12 +1203,32 @@ struct Shared {
   sync: SyncMap,
   lagging_player_ids: BTreeSet<i32>,
   drop_votes: BTreeSet<i32>,
+  lag_events: u32,
+  dropped_player_ids: Vec<i32>,
+  rules: GameRulesState,
+  // updated by the anomaly task
+  stats: Arc<Mutex<ActionStatsState>>,
   obs: ObserverPublisherHandle,
 }
 
 impl Shared {
-  fn new(game_id: i32, slots: &[PlayerSlot], obs: ObserverPublisherHandle) -> Self {
+  fn new(
+    game_id: i32,
+    slots: &[PlayerSlot],
+    rules: GameRules,
+    obs: ObserverPublisherHandle,
+  ) -> Self {
     let sync = SyncMap::new(slots.iter().map(|s| s.player.player_id).collect());
+    // observers never send actions
+    let player_ids: Vec<i32> = slots
+      .iter()
+      .filter(|s| s.settings.team != 24)
+      .map(|s| s.player.player_id)
+      .collect();
+    let rules = GameRulesState::new(rules, player_ids.clone())
+      .with_player_slots(slots.iter().map(|s| (s.player.player_id, s.id)));
+    let stats = Arc::new(Mutex::new(ActionStatsState::new(player_ids)));
+    let trace = GameTrace::create(game_id);
     let mut slot_id_lookup = BTreeMap::new();
     Self {
       game_id,
@@ -1047,7 +1236,7 @@ impl Shared {
       map: slots
         .into_iter()
         .map(|slot| {
-          let p = PlayerDispatchInfo::new(slot);
+          let p = PlayerDispatchInfo::new(slot, trace.clone());
           slot_id_lookup.insert(slot.player.player_id, p.slot_player_id());
           (slot.player.player_id, p)
         })
@@ -1056,6 +1245,10 @@ impl Shared {
       sync,
       lagging_player_ids: BTreeSet::new(),
       drop_votes: BTreeSet::new(),
+      lag_events: 0,
+      dropped_player_ids: vec![],
+      rules,
+      stats,
       obs,
     }
   }
@@ -1064,6 +1257,26 @@ impl Shared {
     self.started = true;
   }
 
+  fn live_status(&self) -> GameLiveStatus {
+    GameLiveStatus {
+      ticks: self.sync.tick(),
+      time_ms: self.sync.time(),
+      connected_player_ids: self
+        .map
+        .iter()
+        .filter(|(_, info)| info.stream_id().is_some())
+        .map(|(player_id, _)| *player_id)
+        .collect(),
+      lagging_player_ids: self.lagging_player_ids.iter().cloned().collect(),
+      lag_events: self.lag_events,
+      dropped_player_ids: self.dropped_player_ids.clone(),
+    }
+  }
+
+  fn action_stats(&self) -> Vec<PlayerActionStats> {
+    self.stats.lock().pack(self.sync.time())
+  }
+
   fn get_player(&mut self, player_id: i32) -> Option<&mut PlayerDispatchInfo> {
     self.map.get_mut(&player_id)
   }
@@ -1077,6 +1290,7 @@ impl Shared {
         return Ok(DispatchResult::Lag(tick));
       }
     }
+    crate::metrics::record_tick_lag_ms(self.sync.pending_time());
 
     if tick.actions_bytes_len > DISPATCH_ACTIONS_MTU {
       tracing::debug!(
@@ -1137,6 +1351,17 @@ impl Shared {
     Ok(DispatchResult::Continue)
   }
 
+  fn take_afk_players(&mut self) -> Vec<i32> {
+    let player_ids = self.rules.take_afk_players(self.sync.tick());
+    for player_id in &player_ids {
+      if let Some(name) = self.map.get(player_id).map(|v| v.player_name().to_string()) {
+        tracing::info!(game_id = self.game_id, player_id, "kick afk player");
+        self.broadcast_message(format!("{} has been removed for inactivity.", name));
+      }
+    }
+    player_ids
+  }
+
   fn push_rtt_stats(&mut self, time: u32) {
     let items = self.map.iter_mut().map(|(id, info)| {
       let stats = info.take_rtt();
@@ -1162,6 +1387,7 @@ impl Shared {
     );
     if let Some(items) = self.refresh_lag_packet()? {
       self.drop_votes.clear();
+      self.lag_events += 1;
       let mut send_errors = vec![];
       for (recv_player_id, info) in &mut self.map {
         if !items.iter().any(|(v, _, _)| v == recv_player_id) {
@@ -1323,6 +1549,8 @@ impl Shared {
     };
 
     tracing::info!(game_id = self.game_id, player_id, "remove player");
+    self.rules.remove_player(player_id);
+    self.stats.lock().remove_player(player_id, self.sync.time());
 
     for p in self.map.values_mut() {
       p.remove_lag_slot(player.slot_player_id());
@@ -1450,6 +1678,7 @@ impl Shared {
       );
       self.remove_player_and_broadcast(*drop_player_id, None)?;
     }
+    self.dropped_player_ids.extend(drop_player_ids);
     self.lagging_player_ids.clear();
     Ok(())
   }
@@ -1562,6 +1791,7 @@ struct PeerWorker {
   delay: DelayedFrameStream,
   delay_send_buf: Vec<Frame>,
   shutdown: bool,
+  traffic: Arc<PlayerTraffic>,
 }
 
 impl PeerWorker {
@@ -1573,6 +1803,7 @@ impl PeerWorker {
     in_rx: Receiver<PlayerStreamCmd>,
     out_tx: Sender<PeerMsg>,
     delay: Option<Duration>,
+    traffic: Arc<PlayerTraffic>,
   ) -> Self {
     Self {
       game_id,
@@ -1584,6 +1815,7 @@ impl PeerWorker {
       delay: DelayedFrameStream::new(delay),
       delay_send_buf: Vec::new(),
       shutdown: false,
+      traffic,
     }
   }
 
@@ -1592,18 +1824,18 @@ impl PeerWorker {
     let stream_ct = self.stream.token();
 
     if let Some(frames) = resend_frames {
+      self.traffic.record_out(&frames);
       self.stream.get_mut().send_frames(frames).await?;
     }
 
     let mut delay_buf = VecDeque::new();
-    let mut ping = PingStream::interval(
-      crate::constants::GAME_PING_INTERVAL,
-      crate::constants::GAME_PING_TIMEOUT,
-    );
+    let config = crate::config::get()?;
+    let mut keepalive = KeepAlive::new(KeepAliveConfig::new(
+      config.game_ping_interval(),
+      config.game_ping_timeout(),
+    ));
     let mut last_status = *self.status_rx.borrow();
 
-    ping.start();
-
     loop {
       tokio::select! {
         _ = self.ct.cancelled() => {
@@ -1636,20 +1868,26 @@ impl PeerWorker {
         next = self.stream.get_mut().recv_frame() => {
           match next {
             Ok(frame) => {
-              match frame.type_id {
-                PingStream::PONG_TYPE_ID => {
-                  if ping.started() {
-                    if let Some(rtt) = ping.capture_pong(frame) {
-                      if self.dispatcher_tx.send(PeerMsg::Pong {
-                        player_id,
-                        rtt
-                      }).await.is_err() {
-                        break;
-                      }
+              self.traffic.record_in(&frame);
+              let frame = match keepalive.recv(frame) {
+                Incoming::Frame(frame) => frame,
+                Incoming::KeepAlive(reply) => {
+                  if let Some(frame) = reply {
+                    self.traffic.record_out(Some(&frame));
+                    self.stream.get_mut().send_frame(frame).await?;
+                  }
+                  if let Some(rtt) = keepalive.take_rtt() {
+                    if self.dispatcher_tx.send(PeerMsg::Pong {
+                      player_id,
+                      rtt
+                    }).await.is_err() {
+                      break;
                     }
                   }
                   continue;
-                },
+                }
+              };
+              match frame.type_id {
                 PacketTypeId::ClientShutdown => {
                   self.shutdown(player_id, None).await;
                   break;
@@ -1694,6 +1932,7 @@ impl PeerWorker {
                 self.delay.insert(DelayedFrame::Out(frame));
                 continue;
               }
+              self.traffic.record_out(Some(&frame));
               self.stream.get_mut().send_frame(frame).await?;
             }
             PlayerStreamCmd::SetDelay(delay) => {
@@ -1731,12 +1970,13 @@ impl PeerWorker {
             }
           }
         }
-        Some(next) = ping.next(), if ping.started() => {
+        next = keepalive.next() => {
           match next {
-            PingMsg::Ping(frame) => {
+            KeepAliveEvent::Ping(frame) => {
+              self.traffic.record_out(Some(&frame));
               self.stream.get_mut().send_frame(frame).await?;
             },
-            PingMsg::Timeout => {
+            KeepAliveEvent::Timeout => {
               tracing::info!(
                 game_id = self.game_id,
                 player_id,
@@ -1781,6 +2021,7 @@ impl PeerWorker {
       }
     }
     let frame = Frame::new_empty(PacketTypeId::ClientShutdownAck);
+    self.traffic.record_out(Some(&frame));
     if let Err(err) = self.stream.get_mut().send_frame(frame).await {
       tracing::error!(
         game_id = self.game_id,
@@ -1839,6 +2080,7 @@ impl PeerWorker {
       }
     }
     if out_buf_write {
+      self.traffic.record_out(&self.delay_send_buf);
       self
         .stream
         .get_mut()
@@ -1871,3 +2113,44 @@ enum ClosePlayerStreamResult {
   ClosedLagging,
   Skipped,
 }
+
+// the layouts of these packets are not verified against the game, a decode error is only logged
+fn log_ignored<T>(player_id: i32, packet: &Packet)
+where
+  T: PacketPayload + flo_util::binary::BinDecode + std::fmt::Debug,
+{
+  match packet.decode_simple::<T>() {
+    Ok(payload) => tracing::debug!(player_id, "ignored: {:?}", payload),
+    Err(err) => tracing::warn!(player_id, "ignored {:?}, decode: {}", packet.type_id(), err),
+  }
+}
+
+// relayed with the blocked words replaced, returns false if the message is unchanged
+fn mask_chat_message(chat_filter: &WordList, message: &mut ChatMessage) -> bool {
+  let text = match message {
+    ChatMessage::Chat(text) | ChatMessage::Scoped { message: text, .. } => text,
+    _ => return false,
+  };
+  let value = text.to_string_lossy();
+  if !chat_filter.is_blocked(&value) {
+    return false;
+  }
+  *text = chat_filter.mask(&value).into_c_string_lossy();
+  true
+}
+
+#[test]
+fn test_mask_chat_message() {
+  use flo_w3gs::protocol::chat::MessageScope;
+
+  let chat_filter = WordList::new(&["noob".to_string()], &[]);
+  let mut chat = ChatToHost::in_game(MessageScope::All, 1, &[2, 3], "gg noob");
+  assert!(mask_chat_message(&chat_filter, &mut chat.message));
+  assert_eq!(chat.chat_message(), Some(&b"gg ****"[..]));
+
+  let mut chat = ChatToHost::lobby(1, &[2], "gl hf");
+  assert!(!mask_chat_message(&chat_filter, &mut chat.message));
+
+  let mut message = ChatMessage::TeamChange(1);
+  assert!(!mask_chat_message(&chat_filter, &mut message));
+}
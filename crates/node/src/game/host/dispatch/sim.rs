@@ -0,0 +1,580 @@
+//! Deterministic simulation of the game tick loop.
+//!
+//! Runs the `TickDriver` of `Dispatcher::tick` on a virtual clock advanced one step at a time,
+//! with scripted players, so lag handling, desync detection and action batching can be tested
+//! and benchmarked without sockets or timers.
+
+use super::{AckAction, ActionMsg, DispatchStatus, Shared, TickDriver};
+use crate::error::*;
+use crate::game::host::anomaly::decode_actions;
+use crate::game::host::clock::{ActionTickStream, ClockEvent, GameClock, Tick};
+use crate::game::host::stream::{PlayerStreamCmd, PlayerStreamHandle};
+use crate::game::{Computer, GamePlayer, GameSlotSettings, PlayerSlot, Race, SlotClientStatus};
+use crate::observer::ObserverPublisherHandle;
+use bytes::Bytes;
+use flo_net::w3gs::{W3GSFrameExt, W3GSPacketTypeId};
+use flo_w3gs::protocol::action::PlayerAction;
+use flo_w3gs::protocol::constants::LeaveReason;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::sync::watch;
+
+pub use crate::game::GameRules;
+
+const GAME_ID: i32 = 0;
+
+/// A player driven by the simulation
+pub trait SimPlayer {
+  /// Actions sent before `tick` is dispatched
+  fn actions(&mut self, tick: u32) -> Vec<Bytes>;
+
+  /// Checksum of `tick`, dispatched at `dispatched_ms` of virtual time,
+  /// or `None` if the player hasn't processed it at `time_ms`
+  fn ack(&mut self, tick: u32, dispatched_ms: u32, time_ms: u32) -> Option<u32>;
+
+  /// Whether the player leaves before `tick` is dispatched
+  fn leave(&mut self, _tick: u32) -> bool {
+    false
+  }
+}
+
+/// Scripted player, answers every tick with the same checksum as the others unless told otherwise
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+  actions: BTreeMap<u32, Vec<Bytes>>,
+  repeat: Option<(u32, Bytes)>,
+  latency_ms: u32,
+  stalls: Vec<Range<u32>>,
+  desync_tick: Option<u32>,
+  leave_tick: Option<u32>,
+}
+
+impl Script {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sends `data` before `tick`
+  pub fn action<T: Into<Bytes>>(mut self, tick: u32, data: T) -> Self {
+    self.actions.entry(tick).or_default().push(data.into());
+    self
+  }
+
+  /// Sends `data` every `interval` ticks
+  pub fn repeat_action<T: Into<Bytes>>(mut self, interval: u32, data: T) -> Self {
+    self.repeat = Some((std::cmp::max(1, interval), data.into()));
+    self
+  }
+
+  /// Answers ticks `latency_ms` after they are dispatched
+  pub fn latency(mut self, latency_ms: u32) -> Self {
+    self.latency_ms = latency_ms;
+    self
+  }
+
+  /// Stops answering ticks during `range` of virtual time
+  pub fn stall(mut self, range: Range<u32>) -> Self {
+    self.stalls.push(range);
+    self
+  }
+
+  /// Answers `tick` with a different checksum
+  pub fn desync_at(mut self, tick: u32) -> Self {
+    self.desync_tick = Some(tick);
+    self
+  }
+
+  pub fn leave_at(mut self, tick: u32) -> Self {
+    self.leave_tick = Some(tick);
+    self
+  }
+
+  pub fn boxed(self) -> Box<dyn SimPlayer> {
+    Box::new(self)
+  }
+}
+
+impl SimPlayer for Script {
+  fn actions(&mut self, tick: u32) -> Vec<Bytes> {
+    let mut actions = self.actions.remove(&tick).unwrap_or_default();
+    if let Some((interval, ref data)) = self.repeat {
+      if tick % interval == 0 {
+        actions.push(data.clone());
+      }
+    }
+    actions
+  }
+
+  fn ack(&mut self, tick: u32, dispatched_ms: u32, time_ms: u32) -> Option<u32> {
+    if self.stalls.iter().any(|range| range.contains(&time_ms)) {
+      return None;
+    }
+    if dispatched_ms + self.latency_ms > time_ms {
+      return None;
+    }
+    if self.desync_tick == Some(tick) {
+      Some(!tick)
+    } else {
+      Some(tick)
+    }
+  }
+
+  fn leave(&mut self, tick: u32) -> bool {
+    self.leave_tick == Some(tick)
+  }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SimReport {
+  pub ticks: u32,
+  /// Game time
+  pub time_ms: u32,
+  /// Virtual time spent with the clock paused
+  pub paused_ms: u32,
+  pub lag_events: u32,
+  pub dropped_player_ids: Vec<i32>,
+  pub rejected_actions: usize,
+  pub players: BTreeMap<i32, SimPlayerReport>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SimPlayerReport {
+  pub acked_ticks: u32,
+  pub removed_at_tick: Option<u32>,
+  pub incoming_actions: usize,
+  /// Fragments of over-sized ticks
+  pub incoming_actions2: usize,
+  pub start_lags: usize,
+  pub stop_lags: usize,
+  pub player_lefts: usize,
+}
+
+struct SimPlayerState {
+  player: Box<dyn SimPlayer>,
+  slot_player_id: u8,
+  rx: Receiver<PlayerStreamCmd>,
+  next_tick: u32,
+}
+
+/// Game clock advanced by the simulation
+#[derive(Debug)]
+struct VirtualClock {
+  step: u16,
+  time_ms: u32,
+  paused_at: Option<u32>,
+  actions: Vec<PlayerAction>,
+}
+
+impl VirtualClock {
+  fn new(step: u16) -> Self {
+    let mut clock = VirtualClock {
+      step: 0,
+      time_ms: 0,
+      paused_at: None,
+      actions: vec![],
+    };
+    clock.set_step(step);
+    clock
+  }
+
+  fn advance(&mut self) {
+    self.time_ms += self.step as u32;
+  }
+
+  /// Returns `None` while paused
+  fn next_event(&mut self) -> Option<ClockEvent> {
+    if let Some(paused_at) = self.paused_at {
+      if self.time_ms - paused_at < crate::constants::GAME_CLOCK_MAX_PAUSE.as_millis() as u32 {
+        return None;
+      }
+      self.paused_at = Some(self.time_ms);
+      return Some(ClockEvent::PauseTimeout);
+    }
+    let actions = std::mem::replace(&mut self.actions, vec![]);
+    let actions_bytes_len = actions.iter().map(|a| a.byte_len()).sum();
+    Some(ClockEvent::Tick(Tick {
+      time_increment_ms: self.step,
+      actions,
+      actions_bytes_len,
+    }))
+  }
+}
+
+impl GameClock for VirtualClock {
+  fn add_action(&mut self, action: PlayerAction) {
+    self.actions.push(action)
+  }
+
+  fn replace_actions(&mut self, actions: Vec<PlayerAction>) {
+    self.actions = actions;
+  }
+
+  fn set_step(&mut self, value: u16) {
+    self.step = std::cmp::min(
+      ActionTickStream::MAX_STEP,
+      std::cmp::max(ActionTickStream::MIN_STEP, value),
+    );
+  }
+
+  fn step(&self) -> u16 {
+    self.step
+  }
+
+  fn pause(&mut self) {
+    self.paused_at = Some(self.time_ms);
+  }
+
+  fn is_paused(&self) -> bool {
+    self.paused_at.is_some()
+  }
+
+  fn resume(&mut self) {
+    self.paused_at = None;
+  }
+}
+
+/// The tick loop of a game, with players `1..=n` in slot order
+pub struct Simulation {
+  shared: Shared,
+  driver: TickDriver<VirtualClock>,
+  // tick -> virtual time of dispatch, for ticks not answered by every player
+  dispatched: BTreeMap<u32, u32>,
+  players: BTreeMap<i32, SimPlayerState>,
+  report: SimReport,
+}
+
+impl Simulation {
+  pub fn new(rules: GameRules, step_ms: u16, players: Vec<Box<dyn SimPlayer>>) -> Self {
+    let slots: Vec<_> = (0..players.len())
+      .map(|idx| PlayerSlot {
+        id: idx as u32,
+        settings: GameSlotSettings {
+          team: 0,
+          color: idx as i32,
+          computer: Computer::Easy,
+          handicap: 100,
+          race: Race::Human,
+        },
+        player: GamePlayer {
+          player_id: idx as i32 + 1,
+          name: format!("Player {}", idx + 1),
+          ban_list: vec![],
+          mute_list: vec![],
+        },
+        client_status: SlotClientStatus::Loaded,
+        sender: None,
+      })
+      .collect();
+
+    let mut shared = Shared::new(GAME_ID, &slots, rules, ObserverPublisherHandle::disabled());
+    shared.set_started();
+
+    let players = slots
+      .iter()
+      .zip(players)
+      .map(|(slot, player)| {
+        let player_id = slot.player.player_id;
+        let (tx, rx) = channel(crate::constants::PEER_CHANNEL_SIZE);
+        let info = shared.get_player(player_id).expect("player registered");
+        info.register_sender(PlayerStreamHandle::simulated(player_id as u64, tx));
+        let state = SimPlayerState {
+          player,
+          slot_player_id: info.slot_player_id(),
+          rx,
+          next_tick: 1,
+        };
+        (player_id, state)
+      })
+      .collect();
+
+    let (status_tx, _) = watch::channel(DispatchStatus::Running);
+    Self {
+      shared,
+      driver: TickDriver {
+        game_id: GAME_ID,
+        clock: VirtualClock::new(step_ms),
+        status_tx,
+      },
+      dispatched: BTreeMap::new(),
+      players,
+      report: SimReport::default(),
+    }
+  }
+
+  /// Virtual time
+  pub fn time_ms(&self) -> u32 {
+    self.driver.clock.time_ms
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.driver.clock.is_paused()
+  }
+
+  pub fn run(&mut self, steps: usize) -> Result<SimReport> {
+    for _ in 0..steps {
+      self.step()?;
+    }
+    Ok(self.report())
+  }
+
+  /// Advances the virtual clock by one step
+  pub fn step(&mut self) -> Result<()> {
+    self.driver.clock.advance();
+    let tick = self.shared.sync.tick() + 1;
+
+    let leave_player_ids: Vec<_> = self
+      .players
+      .iter_mut()
+      .filter_map(|(player_id, state)| state.player.leave(tick).then(|| *player_id))
+      .collect();
+    for player_id in leave_player_ids {
+      self
+        .shared
+        .remove_player_and_broadcast(player_id, Some(LeaveReason::LeaveLost))?;
+    }
+
+    self.collect_actions(tick);
+    self.ack()?;
+    self.clock()?;
+    self.receive();
+    Ok(())
+  }
+
+  pub fn report(&self) -> SimReport {
+    let status = self.shared.live_status();
+    SimReport {
+      ticks: status.ticks,
+      time_ms: status.time_ms,
+      lag_events: status.lag_events,
+      dropped_player_ids: status.dropped_player_ids,
+      ..self.report.clone()
+    }
+  }
+
+  fn collect_actions(&mut self, tick: u32) {
+    for (player_id, state) in &mut self.players {
+      for data in state.player.actions(tick) {
+        let tick = self.shared.sync.tick();
+        match self.shared.rules.check_action(*player_id, tick, data) {
+          Ok(data) => {
+            let time_ms = self.shared.sync.time();
+            // checked by the rules
+            let actions = decode_actions(&data).unwrap_or_default();
+            self
+              .shared
+              .stats
+              .lock()
+              .record(*player_id, time_ms, &actions);
+            self.driver.handle_msg(
+              &mut self.shared,
+              ActionMsg::PlayerAction(PlayerAction {
+                player_id: state.slot_player_id,
+                data,
+              }),
+            );
+          }
+          Err(msg) => {
+            tracing::debug!(player_id, "action rejected: {}", msg);
+            self.report.rejected_actions += 1;
+          }
+        }
+      }
+    }
+  }
+
+  fn ack(&mut self) -> Result<()> {
+    let time_ms = self.time_ms();
+    let mut check_stop_lag = false;
+    for (player_id, state) in &mut self.players {
+      while let Some(&dispatched_ms) = self.dispatched.get(&state.next_tick) {
+        // removed by a desync
+        if !self.shared.map.contains_key(player_id) {
+          break;
+        }
+        let checksum = match state.player.ack(state.next_tick, dispatched_ms, time_ms) {
+          Some(checksum) => checksum,
+          None => break,
+        };
+        state.next_tick += 1;
+        self
+          .report
+          .players
+          .entry(*player_id)
+          .or_default()
+          .acked_ticks += 1;
+        if let AckAction::CheckStopLag = self.shared.ack(*player_id, checksum)? {
+          check_stop_lag = true;
+        }
+      }
+    }
+
+    if check_stop_lag {
+      self
+        .driver
+        .handle_msg(&mut self.shared, ActionMsg::CheckStopLag);
+    }
+
+    let answered = self.players.values().map(|state| state.next_tick).min();
+    if let Some(tick) = answered {
+      self.dispatched = self.dispatched.split_off(&tick);
+    }
+    Ok(())
+  }
+
+  fn clock(&mut self) -> Result<()> {
+    let event = if let Some(event) = self.driver.clock.next_event() {
+      event
+    } else {
+      self.report.paused_ms += self.driver.clock.step() as u32;
+      return Ok(());
+    };
+    let tick = self.shared.sync.tick();
+    let afk_player_ids = self.driver.handle_event(&mut self.shared, event)?;
+    if self.shared.sync.tick() != tick {
+      self
+        .dispatched
+        .insert(self.shared.sync.tick(), self.time_ms());
+    }
+    for player_id in afk_player_ids {
+      self
+        .shared
+        .remove_player_and_broadcast(player_id, Some(LeaveReason::LeaveDisconnect))?;
+    }
+    Ok(())
+  }
+
+  // players receive everything sent in the same step
+  fn receive(&mut self) {
+    for (player_id, state) in &mut self.players {
+      let report = self.report.players.entry(*player_id).or_default();
+      while let Ok(cmd) = state.rx.try_recv() {
+        let frame = match cmd {
+          PlayerStreamCmd::Send(frame) => frame,
+          _ => continue,
+        };
+        let type_id = match frame.try_into_w3gs() {
+          Ok((meta, _)) => meta.type_id(),
+          Err(_) => continue,
+        };
+        match type_id {
+          W3GSPacketTypeId::IncomingAction => report.incoming_actions += 1,
+          W3GSPacketTypeId::IncomingAction2 => report.incoming_actions2 += 1,
+          W3GSPacketTypeId::StartLag => report.start_lags += 1,
+          W3GSPacketTypeId::StopLag => report.stop_lags += 1,
+          W3GSPacketTypeId::PlayerLeft => report.player_lefts += 1,
+          _ => {}
+        }
+      }
+      match self.shared.map.get_mut(player_id) {
+        Some(info) => info.ack_all_sent(),
+        None => {
+          report.removed_at_tick = Some(self.shared.sync.tick());
+        }
+      }
+    }
+
+    let shared = &self.shared;
+    self
+      .players
+      .retain(|player_id, _| shared.map.contains_key(player_id));
+  }
+}
+
+#[cfg(test)]
+fn players(scripts: Vec<Script>) -> Vec<Box<dyn SimPlayer>> {
+  scripts.into_iter().map(Script::boxed).collect()
+}
+
+#[test]
+fn test_sim_no_lag() {
+  let mut sim = Simulation::new(
+    GameRules::default(),
+    30,
+    players(vec![
+      Script::new()
+        .latency(100)
+        .repeat_action(10, vec![0x16, 0x01, 0x00, 0x00]),
+      Script::new().latency(200),
+    ]),
+  );
+  let report = sim.run(1000).unwrap();
+  assert_eq!(report.ticks, 1000);
+  assert_eq!(report.time_ms, 30 * 1000);
+  assert_eq!(report.lag_events, 0);
+  assert_eq!(report.players[&1].incoming_actions, 1000);
+  assert_eq!(report.players[&2].incoming_actions, 1000);
+  assert!(report.players[&2].acked_ticks < report.players[&1].acked_ticks);
+}
+
+#[test]
+fn test_sim_lag_resume() {
+  let mut sim = Simulation::new(
+    GameRules::default(),
+    30,
+    players(vec![
+      Script::new().latency(100),
+      Script::new().latency(100).stall(3000..9000),
+    ]),
+  );
+  let report = sim.run(1000).unwrap();
+  assert_eq!(report.lag_events, 1);
+  assert!(report.paused_ms > 0);
+  assert!(report.dropped_player_ids.is_empty());
+  assert_eq!(report.players[&1].start_lags, 1);
+  assert_eq!(report.players[&1].stop_lags, 1);
+  assert_eq!(report.players[&2].start_lags, 0);
+  assert!(!sim.is_paused());
+}
+
+#[test]
+fn test_sim_drop_after_max_pause() {
+  let mut sim = Simulation::new(
+    GameRules::default(),
+    30,
+    players(vec![
+      Script::new().latency(100),
+      Script::new().latency(100).stall(3000..120_000),
+    ]),
+  );
+  let report = sim.run(3000).unwrap();
+  assert_eq!(report.lag_events, 1);
+  assert_eq!(report.dropped_player_ids, vec![2]);
+  assert!(report.players[&2].removed_at_tick.is_some());
+  assert_eq!(report.players[&1].player_lefts, 1);
+}
+
+#[test]
+fn test_sim_desync() {
+  let mut sim = Simulation::new(
+    GameRules::default(),
+    30,
+    players(vec![
+      Script::new(),
+      Script::new(),
+      Script::new().desync_at(100),
+    ]),
+  );
+  let report = sim.run(200).unwrap();
+  assert_eq!(report.players[&1].removed_at_tick, None);
+  assert_eq!(report.players[&2].removed_at_tick, None);
+  assert!(report.players[&3].removed_at_tick.unwrap() > 100);
+  assert_eq!(report.ticks, 200);
+}
+
+#[test]
+fn test_sim_fragment_actions() {
+  let action = vec![0x16; 400];
+  let mut sim = Simulation::new(
+    GameRules::default(),
+    30,
+    players(
+      (0..4)
+        .map(|_| Script::new().repeat_action(1, action.clone()))
+        .collect(),
+    ),
+  );
+  let report = sim.run(100).unwrap();
+  assert_eq!(report.players[&1].incoming_actions, 100);
+  assert_eq!(report.players[&1].incoming_actions2, 100);
+}
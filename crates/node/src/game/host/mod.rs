@@ -1,22 +1,33 @@
 use s2_grpc_utils::S2ProtoEnum;
 
+pub use anomaly::GameAnomaly;
+#[cfg(feature = "sim")]
+pub use dispatch::sim;
 use dispatch::Dispatcher;
 use flo_net::packet::*;
+use flo_net::proto::flo_common::GameLiveStatus;
+use flo_net::proto::flo_node::{GameTrafficStats, PlayerActionStats};
 pub use sync::AckError;
 
 use crate::error::*;
 use crate::game::host::stream::{PlayerStream, PlayerStreamHandle};
-use crate::game::{GameEventSender, NodeGameStatusSnapshot, PlayerSlot};
+use crate::game::{GameEventSender, GameRules, NodeGameStatusSnapshot, PlayerSlot};
 use crate::observer::ObserverPublisherHandle;
+use flo_util::word_list::WordList;
 use flo_w3gs::constants::LeaveReason;
 
+mod anomaly;
 mod broadcast;
 mod clock;
 mod delay;
 mod dispatch;
 mod player;
+mod rules;
+mod stats;
 pub mod stream;
 mod sync;
+mod trace;
+mod traffic;
 
 #[derive(Debug)]
 pub struct GameHost {
@@ -28,10 +39,12 @@ impl GameHost {
   pub fn new(
     game_id: i32,
     slots: &[PlayerSlot],
+    rules: GameRules,
+    chat_filter: WordList,
     obs: ObserverPublisherHandle,
     event_sender: GameEventSender,
   ) -> Self {
-    let dispatcher = Dispatcher::new(game_id, slots, obs, event_sender);
+    let dispatcher = Dispatcher::new(game_id, slots, rules, chat_filter, obs, event_sender);
     Self {
       game_id,
       dispatcher,
@@ -42,6 +55,22 @@ impl GameHost {
     self.dispatcher.start();
   }
 
+  pub fn live_status(&self) -> GameLiveStatus {
+    self.dispatcher.live_status()
+  }
+
+  pub fn action_stats(&self) -> Vec<PlayerActionStats> {
+    self.dispatcher.action_stats()
+  }
+
+  pub async fn flush_action_stats(&self) {
+    self.dispatcher.flush_action_stats().await
+  }
+
+  pub fn traffic_stats(&self) -> GameTrafficStats {
+    self.dispatcher.traffic_stats()
+  }
+
   pub async fn register_player_stream(
     &mut self,
     mut stream: PlayerStream,
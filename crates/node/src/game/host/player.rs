@@ -1,15 +1,18 @@
 use crate::error::Result;
 use crate::game::host::stream::PlayerStreamHandle;
+use crate::game::host::trace::GameTrace;
 use crate::game::{PlayerBanType, PlayerSlot};
 use flo_net::packet::Frame;
 use flo_net::w3gs::{W3GSAckQueue, W3GSFrameExt, W3GSMetadata, W3GSPacket};
 use flo_w3gs::protocol::chat::ChatFromHost;
+use flo_w3gs::trace::Direction;
 use std::collections::BTreeSet;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::error::TrySendError;
 
 #[derive(Debug)]
 pub struct PlayerDispatchInfo {
+  player_id: i32,
   player_name: String,
   last_stream_id: Option<u64>,
   tx: Option<PlayerStreamHandle>,
@@ -23,11 +26,13 @@ pub struct PlayerDispatchInfo {
   last_disconnect: Option<Instant>,
   rtt_stats: PlayerRTTStats,
   last_rtt_stats: Option<PlayerRTTStats>,
+  trace: Option<GameTrace>,
 }
 
 impl PlayerDispatchInfo {
-  pub fn new(slot: &PlayerSlot) -> Self {
+  pub fn new(slot: &PlayerSlot, trace: Option<GameTrace>) -> Self {
     Self {
+      player_id: slot.player.player_id,
       player_name: slot.player.name.clone(),
       last_stream_id: None,
       tx: None,
@@ -41,6 +46,7 @@ impl PlayerDispatchInfo {
       last_disconnect: None,
       rtt_stats: PlayerRTTStats::default(),
       last_rtt_stats: None,
+      trace,
     }
   }
 
@@ -66,6 +72,13 @@ impl PlayerDispatchInfo {
     true
   }
 
+  /// Called once for each packet received, resends are not recorded
+  pub fn trace_received(&self, pkt: &W3GSPacket) {
+    if let Some(ref trace) = self.trace {
+      trace.record(Direction::Incoming, self.player_id, pkt);
+    }
+  }
+
   pub fn register_sender(&mut self, tx: PlayerStreamHandle) {
     self.last_stream_id.replace(tx.stream_id());
     self.tx.replace(tx);
@@ -109,6 +122,9 @@ impl PlayerDispatchInfo {
   }
 
   pub fn enqueue_w3gs(&mut self, pkt: W3GSPacket) -> W3GSMetadata {
+    if let Some(ref trace) = self.trace {
+      trace.record(Direction::Outgoing, self.player_id, &pkt);
+    }
     let sid = self.w3gs_ack_q.gen_next_send_sid();
     let ack_sid = self.w3gs_ack_q.take_ack_received();
     let meta = W3GSMetadata::new(pkt.type_id(), sid, ack_sid.clone());
@@ -116,6 +132,19 @@ impl PlayerDispatchInfo {
     meta
   }
 
+  /// Acknowledges every queued packet, as if the player received them
+  #[cfg(feature = "sim")]
+  pub fn ack_all_sent(&mut self) {
+    let last_sid = self
+      .w3gs_ack_q
+      .pending_ack_queue()
+      .back()
+      .map(|(meta, _)| meta.sid());
+    if let Some(sid) = last_sid {
+      self.w3gs_ack_q.ack_sent(sid);
+    }
+  }
+
   pub fn send_private_message(&mut self, msg: &str) {
     if self.stream_id().is_some() {
       let payload = ChatFromHost::private_to_self(self.slot_player_id, format!("[FLO] {}", msg));
@@ -0,0 +1,260 @@
+use crate::game::GameRules;
+use bytes::{Bytes, BytesMut};
+use flo_util::binary::BinDecode;
+use flo_w3gs::actions::Action;
+use std::collections::BTreeMap;
+
+/// `ChangeAllyOptions` flag bit of shared unit control
+const ALLY_FLAG_SHARED_CONTROL: u32 = 0x40;
+
+#[derive(Debug)]
+pub struct GameRulesState {
+  rules: GameRules,
+  paused: bool,
+  pause_counts: BTreeMap<i32, u32>,
+  // players subject to the AFK check
+  last_action_ticks: BTreeMap<i32, u32>,
+  player_slots: BTreeMap<i32, u32>,
+}
+
+impl GameRulesState {
+  pub fn new<I>(rules: GameRules, player_ids: I) -> Self
+  where
+    I: IntoIterator<Item = i32>,
+  {
+    Self {
+      rules,
+      paused: false,
+      pause_counts: BTreeMap::new(),
+      last_action_ticks: player_ids.into_iter().map(|id| (id, 0)).collect(),
+      player_slots: BTreeMap::new(),
+    }
+  }
+
+  /// Slot indexes of the players, required to check the shared control pairs
+  pub fn with_player_slots<I>(mut self, slots: I) -> Self
+  where
+    I: IntoIterator<Item = (i32, u32)>,
+  {
+    self.player_slots = slots.into_iter().collect();
+    self
+  }
+
+  fn is_shared_control_allowed(&self, player_id: i32, target_slot_id: u32) -> bool {
+    if self.rules.disable_shared_control {
+      return false;
+    }
+    if self.rules.shared_control_pairs.is_empty() {
+      return true;
+    }
+    let slot_id = if let Some(slot_id) = self.player_slots.get(&player_id) {
+      *slot_id
+    } else {
+      return false;
+    };
+    self.rules.shared_control_pairs.iter().any(|pair| {
+      (pair.slot_id == slot_id && pair.with_slot_id == target_slot_id)
+        || (pair.slot_id == target_slot_id && pair.with_slot_id == slot_id)
+    })
+  }
+
+  /// Checks the actions of a player against the rules.
+  /// Returns the action data to forward, or the message explaining why it was rejected.
+  pub fn check_action(&mut self, player_id: i32, tick: u32, data: Bytes) -> Result<Bytes, String> {
+    if let Some(last) = self.last_action_ticks.get_mut(&player_id) {
+      *last = tick;
+    }
+
+    let mut patched: Option<BytesMut> = None;
+    let mut pauses = 0;
+    let mut resumed = false;
+    let mut buf = data.as_ref();
+    while !buf.is_empty() {
+      let offset = data.len() - buf.len();
+      // unknown actions can't be skipped, so the packet can't be checked
+      let action = match Action::decode(&mut buf) {
+        Ok(action) => action,
+        Err(err) => {
+          tracing::debug!(player_id, "decode action: {}", err);
+          return Err("Unrecognized action, the actions were not sent.".to_string());
+        }
+      };
+      match action {
+        Action::PauseGame => {
+          if self.rules.disable_pause {
+            return Err("Pausing is disabled in this game.".to_string());
+          }
+          pauses += 1;
+        }
+        Action::ResumeGame => {
+          resumed = true;
+        }
+        Action::SaveGame(_) => {
+          if self.rules.disable_save {
+            return Err("Saving is disabled in this game.".to_string());
+          }
+        }
+        Action::ChangeAllyOptions(ref options)
+          if options.flags & ALLY_FLAG_SHARED_CONTROL != 0
+            && !self.is_shared_control_allowed(player_id, options.player_slot_number as u32) =>
+        {
+          let bytes = patched.get_or_insert_with(|| BytesMut::from(data.as_ref()));
+          // type_id: u8, player_slot_number: u8
+          let flags_offset = offset + 2;
+          bytes[flags_offset..(flags_offset + 4)]
+            .copy_from_slice(&(options.flags & !ALLY_FLAG_SHARED_CONTROL).to_le_bytes());
+        }
+        _ => {}
+      }
+    }
+
+    if pauses > 0 {
+      if self.rules.max_pause_count > 0 {
+        let count = self.pause_counts.entry(player_id).or_default();
+        if *count + pauses > self.rules.max_pause_count {
+          return Err(format!(
+            "You can only pause {} time(s) in this game.",
+            self.rules.max_pause_count
+          ));
+        }
+        *count += pauses;
+      }
+      self.paused = true;
+    }
+
+    if resumed {
+      self.paused = false;
+      // time spent paused does not count
+      for last in self.last_action_ticks.values_mut() {
+        *last = tick;
+      }
+    }
+
+    Ok(patched.map(BytesMut::freeze).unwrap_or(data))
+  }
+
+  /// Returns players that have been inactive for longer than `afk_kick_ticks`,
+  /// each player is only returned once
+  pub fn take_afk_players(&mut self, tick: u32) -> Vec<i32> {
+    let afk_kick_ticks = self.rules.afk_kick_ticks;
+    if afk_kick_ticks == 0 || self.paused {
+      return vec![];
+    }
+    let ids: Vec<_> = self
+      .last_action_ticks
+      .iter()
+      .filter(|(_, last)| tick.saturating_sub(**last) >= afk_kick_ticks)
+      .map(|(id, _)| *id)
+      .collect();
+    for id in &ids {
+      self.last_action_ticks.remove(id);
+    }
+    ids
+  }
+
+  pub fn remove_player(&mut self, player_id: i32) {
+    self.last_action_ticks.remove(&player_id);
+  }
+}
+
+#[test]
+fn test_game_rules_pause() {
+  let mut state = GameRulesState::new(
+    GameRules {
+      max_pause_count: 1,
+      ..Default::default()
+    },
+    vec![1],
+  );
+  let pause = Bytes::from_static(&[0x01]);
+  assert_eq!(state.check_action(1, 0, pause.clone()), Ok(pause.clone()));
+  assert!(state.check_action(1, 1, pause.clone()).is_err());
+
+  let mut state = GameRulesState::new(
+    GameRules {
+      disable_pause: true,
+      ..Default::default()
+    },
+    vec![1],
+  );
+  assert!(state.check_action(1, 0, pause).is_err());
+}
+
+#[test]
+fn test_game_rules_shared_control() {
+  let mut state = GameRulesState::new(
+    GameRules {
+      disable_shared_control: true,
+      ..Default::default()
+    },
+    vec![1],
+  );
+  let data = Bytes::from_static(&[0x50, 0x02, 0x7F, 0x00, 0x00, 0x00]);
+  assert_eq!(
+    state.check_action(1, 0, data),
+    Ok(Bytes::from_static(&[0x50, 0x02, 0x3F, 0x00, 0x00, 0x00]))
+  );
+}
+
+#[test]
+fn test_game_rules_shared_control_pairs() {
+  use crate::game::SharedControlPair;
+
+  let mut state = GameRulesState::new(
+    GameRules {
+      shared_control_pairs: vec![SharedControlPair {
+        slot_id: 0,
+        with_slot_id: 1,
+      }],
+      ..Default::default()
+    },
+    vec![1, 2, 3],
+  )
+  .with_player_slots(vec![(1, 0), (2, 1), (3, 2)]);
+  let share_with_slot = |slot: u8| Bytes::from(vec![0x50, slot, 0x7F, 0x00, 0x00, 0x00]);
+  assert_eq!(
+    state.check_action(1, 0, share_with_slot(1)),
+    Ok(share_with_slot(1))
+  );
+  assert_eq!(
+    state.check_action(2, 0, share_with_slot(0)),
+    Ok(share_with_slot(0))
+  );
+  assert_eq!(
+    state.check_action(3, 0, share_with_slot(0)),
+    Ok(Bytes::from_static(&[0x50, 0x00, 0x3F, 0x00, 0x00, 0x00]))
+  );
+}
+
+#[test]
+fn test_game_rules_afk() {
+  let mut state = GameRulesState::new(
+    GameRules {
+      afk_kick_ticks: 10,
+      ..Default::default()
+    },
+    vec![1, 2],
+  );
+  state
+    .check_action(1, 5, Bytes::from_static(&[0x01]))
+    .unwrap();
+  assert_eq!(state.take_afk_players(20), Vec::<i32>::new());
+  state
+    .check_action(1, 20, Bytes::from_static(&[0x02]))
+    .unwrap();
+  state
+    .check_action(2, 25, Bytes::from_static(&[0x02]))
+    .unwrap();
+  assert_eq!(state.take_afk_players(30), vec![1]);
+  assert_eq!(state.take_afk_players(35), vec![2]);
+  assert_eq!(state.take_afk_players(50), Vec::<i32>::new());
+}
+
+#[test]
+fn test_game_rules_unknown_action() {
+  let mut state = GameRulesState::new(GameRules::default(), vec![1]);
+  // PauseGame followed by an unknown type id
+  assert!(state
+    .check_action(1, 0, Bytes::from_static(&[0x01, 0xFF]))
+    .is_err());
+}
@@ -0,0 +1,209 @@
+use bytes::Bytes;
+use flo_net::proto::flo_node::PlayerActionStats;
+use flo_w3gs::actions::Action;
+use std::collections::{BTreeMap, VecDeque};
+
+const APM_WINDOW_MS: u32 = 60_000;
+/// An action identical to the previous one within this time doesn't count towards EPM
+const EFFECTIVE_REPEAT_MS: u32 = 250;
+
+#[derive(Debug)]
+pub struct ActionStatsState {
+  players: BTreeMap<i32, PlayerActionCounter>,
+}
+
+impl ActionStatsState {
+  pub fn new<I>(player_ids: I) -> Self
+  where
+    I: IntoIterator<Item = i32>,
+  {
+    Self {
+      players: player_ids
+        .into_iter()
+        .map(|id| (id, PlayerActionCounter::default()))
+        .collect(),
+    }
+  }
+
+  /// Counts the decoded actions of a packet
+  pub fn record(&mut self, player_id: i32, time_ms: u32, actions: &[(Action, Bytes)]) {
+    let counter = if let Some(v) = self.players.get_mut(&player_id) {
+      v
+    } else {
+      return;
+    };
+    if counter.left_at.is_some() {
+      return;
+    }
+
+    for (action, bytes) in actions {
+      let category = if let Some(v) = ActionCategory::of(action) {
+        v
+      } else {
+        continue;
+      };
+      counter.add(category, time_ms, bytes.clone());
+    }
+  }
+
+  /// Counts a sample of the player that was dropped before being recorded
+  pub fn record_dropped(&mut self, player_id: i32) {
+    if let Some(counter) = self.players.get_mut(&player_id) {
+      counter.dropped_samples += 1;
+    }
+  }
+
+  /// Stops counting the time of a player who left
+  pub fn remove_player(&mut self, player_id: i32, time_ms: u32) {
+    if let Some(counter) = self.players.get_mut(&player_id) {
+      counter.left_at.get_or_insert(time_ms);
+    }
+  }
+
+  pub fn pack(&self, time_ms: u32) -> Vec<PlayerActionStats> {
+    self
+      .players
+      .iter()
+      .map(|(player_id, counter)| counter.pack(*player_id, time_ms))
+      .collect()
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ActionCategory {
+  Selection,
+  Hotkey,
+  Ability,
+  Item,
+  Other,
+}
+
+impl ActionCategory {
+  /// Returns `None` for actions not issued by the player directly
+  fn of(action: &Action) -> Option<Self> {
+    let category = match *action {
+      Action::ChangeSelection(_)
+      | Action::SelectSubgroup114b(_)
+      | Action::PreSubselection
+      | Action::SelectGroundItem(_) => Self::Selection,
+      Action::AssignGroupHotkey(_) | Action::SelectGroupHotkey(_) => Self::Hotkey,
+      Action::UnitBuildingAbility(_)
+      | Action::UnitBuildingAbilityTargeted(_)
+      | Action::UnitBuildingAbilityTargetedId(_)
+      | Action::UnitBuildingAbility2Targets2Items(_) => Self::Ability,
+      Action::ItemGivenDropped(_) => Self::Item,
+      Action::CancelHeroRevival(_)
+      | Action::RemoveUnitFromBuildingQueue(_)
+      | Action::TransferResources(_)
+      | Action::EnterChooseHeroSkillSubmenu
+      | Action::EnterChooseBuildingSubmenu
+      | Action::MinimapSignal(_)
+      | Action::EscPressed => Self::Other,
+      _ => return None,
+    };
+    Some(category)
+  }
+}
+
+#[derive(Debug, Default)]
+struct PlayerActionCounter {
+  actions: u32,
+  effective_actions: u32,
+  selection_actions: u32,
+  hotkey_actions: u32,
+  ability_actions: u32,
+  item_actions: u32,
+  other_actions: u32,
+  // game time of each action in the current window
+  window: VecDeque<u32>,
+  peak_apm: u32,
+  last_action: Option<(u32, Bytes)>,
+  left_at: Option<u32>,
+  dropped_samples: u32,
+}
+
+impl PlayerActionCounter {
+  fn add(&mut self, category: ActionCategory, time_ms: u32, bytes: Bytes) {
+    self.actions += 1;
+    match category {
+      ActionCategory::Selection => self.selection_actions += 1,
+      ActionCategory::Hotkey => self.hotkey_actions += 1,
+      ActionCategory::Ability => self.ability_actions += 1,
+      ActionCategory::Item => self.item_actions += 1,
+      ActionCategory::Other => self.other_actions += 1,
+    }
+
+    let repeated = matches!(
+      self.last_action,
+      Some((time, ref last)) if time_ms.saturating_sub(time) < EFFECTIVE_REPEAT_MS && *last == bytes
+    );
+    if !repeated {
+      self.effective_actions += 1;
+    }
+    self.last_action = Some((time_ms, bytes));
+
+    self.window.push_back(time_ms);
+    while let Some(time) = self.window.front() {
+      if time_ms.saturating_sub(*time) >= APM_WINDOW_MS {
+        self.window.pop_front();
+      } else {
+        break;
+      }
+    }
+    self.peak_apm = std::cmp::max(self.peak_apm, self.window.len() as u32);
+  }
+
+  fn pack(&self, player_id: i32, time_ms: u32) -> PlayerActionStats {
+    let time_ms = self.left_at.unwrap_or(time_ms);
+    let per_minute = |count: u32| {
+      if time_ms == 0 {
+        0
+      } else {
+        (count as u64 * APM_WINDOW_MS as u64 / time_ms as u64) as u32
+      }
+    };
+    PlayerActionStats {
+      player_id,
+      apm: per_minute(self.actions),
+      epm: per_minute(self.effective_actions),
+      peak_apm: self.peak_apm,
+      selection_actions: self.selection_actions,
+      hotkey_actions: self.hotkey_actions,
+      ability_actions: self.ability_actions,
+      item_actions: self.item_actions,
+      other_actions: self.other_actions,
+      time_ms,
+      dropped_samples: self.dropped_samples,
+    }
+  }
+}
+
+#[test]
+fn test_action_stats() {
+  use super::anomaly::decode_actions;
+  let mut state = ActionStatsState::new(vec![1, 2]);
+  let select_group = Bytes::from_static(&[0x18, 0x01, 0x00]);
+  let esc = Bytes::from_static(&[0x61]);
+  // MMDMessage is not counted
+  let mmd = Bytes::from_static(&[0x6B, b'a', 0, b'b', 0, b'c', 0, 0, 0, 0, 0]);
+
+  state.record(1, 0, &decode_actions(&select_group).unwrap());
+  state.record(1, 100, &decode_actions(&select_group).unwrap());
+  state.record(1, 1000, &decode_actions(&esc).unwrap());
+  state.record(1, 1000, &decode_actions(&mmd).unwrap());
+  state.record(2, 0, &decode_actions(&esc).unwrap());
+  state.remove_player(2, 30_000);
+  state.record(2, 31_000, &decode_actions(&esc).unwrap());
+  state.record_dropped(1);
+
+  let stats = state.pack(60_000);
+  assert_eq!(stats[0].player_id, 1);
+  assert_eq!(stats[0].apm, 3);
+  assert_eq!(stats[0].epm, 2);
+  assert_eq!(stats[0].peak_apm, 3);
+  assert_eq!(stats[0].hotkey_actions, 2);
+  assert_eq!(stats[0].other_actions, 1);
+  assert_eq!(stats[0].dropped_samples, 1);
+  assert_eq!(stats[1].apm, 2);
+  assert_eq!(stats[1].time_ms, 30_000);
+}
@@ -74,6 +74,16 @@ impl PlayerStreamHandle {
     }
   }
 
+  /// A handle without a connection, its commands are consumed by the game simulation
+  #[cfg(feature = "sim")]
+  pub fn simulated(stream_id: u64, tx: Sender<PlayerStreamCmd>) -> Self {
+    Self {
+      stream_id,
+      tx,
+      ct: CancellationToken::new(),
+    }
+  }
+
   pub fn stream_id(&self) -> u64 {
     self.stream_id
   }
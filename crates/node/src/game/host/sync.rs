@@ -106,6 +106,16 @@ impl SyncMap {
       .map(|v| self.tick.saturating_sub(v.tick))
   }
 
+  /// Game time passed since the oldest tick not acked by every player
+  pub fn pending_time(&self) -> u32 {
+    self
+      .pending_tick
+      .values()
+      .next()
+      .map(|id| self.time.saturating_sub(self.pending_slab[*id].time))
+      .unwrap_or_default()
+  }
+
   pub fn ack(&mut self, player_id: i32, checksum: u32) -> Result<AckResult, AckError> {
     let state = self
       .players
@@ -0,0 +1,64 @@
+//! Records the W3GS packets of each game if `FLO_NODE_W3GS_TRACE_DIR` is set,
+//! to attach to protocol bug reports. `flo-cli trace print <path>` prints a trace.
+
+use crate::env::Env;
+use flo_net::w3gs::W3GSPacket;
+use flo_w3gs::trace::{Direction, TraceWriter};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Shared by the players of a game, the file is flushed when the last clone is dropped
+#[derive(Clone)]
+pub struct GameTrace {
+  game_id: i32,
+  path: Arc<PathBuf>,
+  // `None` after a write error
+  writer: Arc<Mutex<Option<TraceWriter<BufWriter<File>>>>>,
+}
+
+impl std::fmt::Debug for GameTrace {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("GameTrace")
+      .field("path", &self.path)
+      .finish()
+  }
+}
+
+impl GameTrace {
+  pub fn create(game_id: i32) -> Option<Self> {
+    let dir = Env::get().w3gs_trace_dir.as_ref()?;
+    let secs = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or_default();
+    // a restored game gets another file
+    let path = dir.join(format!("{}-{}.w3gstrace", game_id, secs));
+    let writer = match File::create(&path).and_then(|file| TraceWriter::new(BufWriter::new(file))) {
+      Ok(writer) => writer,
+      Err(err) => {
+        tracing::error!(game_id, "create w3gs trace `{}`: {}", path.display(), err);
+        return None;
+      }
+    };
+    tracing::info!(game_id, "recording w3gs trace: {}", path.display());
+    Some(Self {
+      game_id,
+      path: Arc::new(path),
+      writer: Arc::new(Mutex::new(Some(writer))),
+    })
+  }
+
+  pub fn record(&self, direction: Direction, player_id: i32, packet: &W3GSPacket) {
+    let mut guard = self.writer.lock();
+    if let Some(writer) = guard.as_mut() {
+      if let Err(err) = writer.write(direction, player_id, packet) {
+        tracing::error!(game_id = self.game_id, "write w3gs trace: {}", err);
+        guard.take();
+      }
+    }
+  }
+}
@@ -0,0 +1,105 @@
+use flo_net::packet::Frame;
+use flo_net::proto::flo_node::{GameTrafficStats, PlayerTrafficStats};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// type id + payload length
+const FRAME_HEADER_SIZE: u64 = 3;
+
+/// Bytes relayed to and from the players of a game
+#[derive(Debug)]
+pub struct GameTraffic {
+  players: BTreeMap<i32, Arc<PlayerTraffic>>,
+}
+
+impl GameTraffic {
+  pub fn new<I>(player_ids: I) -> Self
+  where
+    I: IntoIterator<Item = i32>,
+  {
+    Self {
+      players: player_ids
+        .into_iter()
+        .map(|id| (id, Arc::new(PlayerTraffic::default())))
+        .collect(),
+    }
+  }
+
+  /// The counters of a player, shared with the player's peer worker
+  pub fn player(&self, player_id: i32) -> Arc<PlayerTraffic> {
+    self
+      .players
+      .get(&player_id)
+      .cloned()
+      .unwrap_or_else(|| Arc::new(PlayerTraffic::default()))
+  }
+
+  pub fn pack(&self) -> GameTrafficStats {
+    let players: Vec<_> = self
+      .players
+      .iter()
+      .map(|(player_id, traffic)| PlayerTrafficStats {
+        player_id: *player_id,
+        bytes_in: traffic.bytes_in.load(Ordering::Relaxed),
+        bytes_out: traffic.bytes_out.load(Ordering::Relaxed),
+      })
+      .collect();
+    GameTrafficStats {
+      bytes_in: players.iter().map(|p| p.bytes_in).sum(),
+      bytes_out: players.iter().map(|p| p.bytes_out).sum(),
+      players,
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct PlayerTraffic {
+  bytes_in: AtomicU64,
+  bytes_out: AtomicU64,
+}
+
+impl PlayerTraffic {
+  pub fn record_in(&self, frame: &Frame) {
+    let size = frame_size(frame);
+    self.bytes_in.fetch_add(size, Ordering::Relaxed);
+    crate::metrics::RELAY_BYTES_IN.inc_by(size);
+  }
+
+  pub fn record_out<'a, I>(&self, frames: I)
+  where
+    I: IntoIterator<Item = &'a Frame>,
+  {
+    let size = frames.into_iter().map(frame_size).sum();
+    self.bytes_out.fetch_add(size, Ordering::Relaxed);
+    crate::metrics::RELAY_BYTES_OUT.inc_by(size);
+  }
+}
+
+fn frame_size(frame: &Frame) -> u64 {
+  FRAME_HEADER_SIZE + frame.payload.len() as u64
+}
+
+#[test]
+fn test_game_traffic() {
+  use flo_net::packet::PacketTypeId;
+
+  let traffic = GameTraffic::new(vec![1, 2]);
+  let frame = Frame::new(PacketTypeId::Ping, [0, 0, 0, 1]);
+
+  let p1 = traffic.player(1);
+  p1.record_in(&frame);
+  p1.record_out(&[frame.clone(), frame.clone()]);
+  traffic.player(2).record_out(Some(&frame));
+  // unknown players are not reported
+  traffic.player(3).record_in(&frame);
+
+  let stats = traffic.pack();
+  assert_eq!(stats.bytes_in, 7);
+  assert_eq!(stats.bytes_out, 21);
+  assert_eq!(stats.players.len(), 2);
+  assert_eq!(stats.players[0].bytes_in, 7);
+  assert_eq!(stats.players[0].bytes_out, 14);
+  assert_eq!(stats.players[1].bytes_in, 0);
+  assert_eq!(stats.players[1].bytes_out, 7);
+}
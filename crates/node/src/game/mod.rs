@@ -1,22 +1,31 @@
 use std::collections::BTreeMap;
-use std::sync::Arc;
+use std::pin::Pin;
+use std::time::Instant;
 
-use futures::lock::Mutex;
 use futures::FutureExt;
 use s2_grpc_utils::{S2ProtoEnum, S2ProtoUnpack};
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::oneshot;
+use tokio::time::Sleep;
 use tracing_futures::Instrument;
 
 use flo_event::*;
 use flo_net::packet::{FloPacket, Frame, PacketTypeId};
+use flo_net::proto::flo_common::GameLiveStatus;
 use flo_net::proto::flo_node as proto;
 use flo_net::stream::FloStream;
 use flo_task::SpawnScope;
+use flo_types::lifecycle::{GameLifecycle, GamePhase};
 pub use flo_types::node::*;
+use flo_util::word_list::WordList;
+#[cfg(feature = "sim")]
+pub use host::sim;
 use host::stream::PlayerStreamHandle;
 pub use host::AckError;
+use host::GameAnomaly;
 use host::GameHost;
 
+use crate::constants::{GAME_COMMAND_BUF_SIZE, GAME_COMMAND_SLOW_WAIT, GAME_LOAD_TIMEOUT};
 use crate::controller::ControllerServerHandle;
 use crate::error::*;
 use crate::observer::ObserverPublisherHandle;
@@ -30,6 +39,7 @@ mod host;
 pub enum GameEvent {
   GameStatusChange(NodeGameStatus),
   PlayerStatusChange(i32, SlotClientStatus, SlotClientStatusUpdateSource),
+  Anomaly(GameAnomaly),
 }
 
 pub type GameEventSender = Sender<GameEvent>;
@@ -42,7 +52,7 @@ impl FloEvent for GameEvent {
 pub struct GameSession {
   _scope: SpawnScope,
   _game_id: i32,
-  state: Arc<Mutex<State>>,
+  tx: Sender<QueuedCommand>,
 }
 
 impl GameSession {
@@ -55,17 +65,32 @@ impl GameSession {
     let scope = SpawnScope::new();
     let game_id = game.id;
     let (tx, mut rx) = GameEvent::channel(32);
+    let (cmd_tx, mut cmd_rx) = channel(GAME_COMMAND_BUF_SIZE);
+    let snapshot_game = game.clone();
     let slots: Vec<_> = Vec::<GameSlot>::unpack(game.slots)?
       .into_iter()
       .filter_map(PlayerSlot::from_game_slot)
       .collect();
+    let rules = Option::<GameRules>::unpack(game.rules)?.unwrap_or_default();
+    let chat_filter = game
+      .chat_filter
+      .map(|filter| WordList::new(&filter.blocked_words, &filter.blocked_patterns))
+      .unwrap_or_default();
+
+    let mut lifecycle = GameLifecycle::new(GamePhase::Created);
+    lifecycle.on_transition(|transition| {
+      crate::metrics::GAME_TRANSITIONS
+        .with_label_values(&[transition.to.as_str()])
+        .inc()
+    });
 
     let mut scope_handle = scope.handle();
-    let state = Arc::new(Mutex::new(State {
+    let mut state = State {
       game_id,
+      game: snapshot_game,
       g_event_sender,
-      host: GameHost::new(game_id, &slots, obs.clone(), tx.clone()),
-      status: NodeGameStatus::Created,
+      host: GameHost::new(game_id, &slots, rules, chat_filter, obs.clone(), tx.clone()),
+      lifecycle,
       player_slots: slots
         .into_iter()
         .map(|slot| (slot.player.player_id, slot))
@@ -73,63 +98,263 @@ impl GameSession {
       tx,
       ctrl,
       obs,
-    }));
-
-    let sess = Self {
-      _scope: scope,
-      _game_id: game_id,
-      state,
     };
 
-    tokio::spawn({
-      let handle = sess.handle();
+    // the task owns the state, events and commands of the game are handled one at a time
+    tokio::spawn(
       async move {
+        let mut load_timeout: Option<Pin<Box<Sleep>>> = None;
         loop {
           tokio::select! {
             _ = scope_handle.left() => {
               break;
             }
+            _ = async { load_timeout.as_mut().unwrap().await }, if load_timeout.is_some() => {
+              load_timeout.take();
+              if let Err(err) = state.drop_unloaded_players().instrument(tracing::info_span!("game", game_id)).await {
+                tracing::error!("drop unloaded players: {}", err);
+              }
+            }
             next = rx.recv() => {
               let event = match next {
                 Some(event) => event,
                 None => break,
               };
-              if let Err(err) = Self::handle_event(&handle, event).instrument(tracing::info_span!("game", id = game_id)).await {
+              match event {
+                GameEvent::GameStatusChange(NodeGameStatus::Loading) => {
+                  load_timeout = Some(Box::pin(tokio::time::sleep(GAME_LOAD_TIMEOUT)));
+                }
+                GameEvent::GameStatusChange(_) => {
+                  load_timeout.take();
+                }
+                _ => {}
+              }
+              if let Err(err) = state.handle_event(event).instrument(tracing::info_span!("game", game_id)).await {
                 tracing::error!("handle events: {}", err);
               }
             }
+            Some(queued) = cmd_rx.recv() => {
+              let QueuedCommand { command, queued_at } = queued;
+              let wait = queued_at.elapsed();
+              crate::metrics::GAME_COMMANDS_QUEUED.dec();
+              crate::metrics::GAME_COMMAND_WAIT.observe(wait.as_secs_f64());
+              if wait > GAME_COMMAND_SLOW_WAIT {
+                tracing::warn!("command waited {:?}", wait);
+              }
+              state.handle_command(command).instrument(tracing::info_span!("game", game_id)).await;
+            }
           }
         }
+        // queued requests are cancelled
+        cmd_rx.close();
+        while cmd_rx.recv().await.is_some() {
+          crate::metrics::GAME_COMMANDS_QUEUED.dec();
+        }
         tracing::debug!("exiting");
       }
-        .instrument(tracing::debug_span!("event_worker", game_id))
-    });
+      .instrument(tracing::debug_span!("event_worker", game_id)),
+    );
 
-    Ok(sess)
+    Ok(Self {
+      _scope: scope,
+      _game_id: game_id,
+      tx: cmd_tx,
+    })
   }
 
   pub fn handle(&self) -> GameSessionHandle {
-    GameSessionHandle(self.state.clone())
+    GameSessionHandle(self.tx.clone())
+  }
+}
+
+enum GameCommand {
+  RegisterPlayerStream {
+    player_id: i32,
+    stream: FloStream,
+    reply: oneshot::Sender<Result<(), (Option<FloStream>, Error)>>,
+  },
+  LiveStatus {
+    reply: oneshot::Sender<GameLiveStatus>,
+  },
+  Snapshot {
+    reply: oneshot::Sender<(proto::Game, GameLiveStatus)>,
+  },
+  ReportStatus {
+    reply: oneshot::Sender<Result<()>>,
+  },
+  NotifyPlayerShutdown {
+    player_id: i32,
+    leave_reason: Option<LeaveReason>,
+    reply: oneshot::Sender<Result<()>>,
+  },
+  UpdatePlayerClientStatus {
+    source: SlotClientStatusUpdateSource,
+    player_id: i32,
+    status: SlotClientStatus,
+    reply: oneshot::Sender<Result<()>>,
+  },
+}
+
+struct QueuedCommand {
+  command: GameCommand,
+  queued_at: Instant,
+}
+
+/// Sends commands to the task of a game.
+/// Requests fail with `Error::Cancelled` once the game session is dropped.
+#[derive(Debug, Clone)]
+pub struct GameSessionHandle(Sender<QueuedCommand>);
+
+impl GameSessionHandle {
+  async fn request<T, F>(&self, f: F) -> Result<T>
+  where
+    F: FnOnce(oneshot::Sender<T>) -> GameCommand,
+  {
+    let (reply, rx) = oneshot::channel();
+    let queued = QueuedCommand {
+      command: f(reply),
+      queued_at: Instant::now(),
+    };
+    crate::metrics::GAME_COMMANDS_QUEUED.inc();
+    if self.0.send(queued).await.is_err() {
+      crate::metrics::GAME_COMMANDS_QUEUED.dec();
+      return Err(Error::Cancelled);
+    }
+    rx.await.map_err(|_| Error::Cancelled)
+  }
+
+  pub async fn register_player_stream(
+    &self,
+    player_id: i32,
+    stream: FloStream,
+  ) -> Result<(), (Option<FloStream>, Error)> {
+    self
+      .request(|reply| GameCommand::RegisterPlayerStream {
+        player_id,
+        stream,
+        reply,
+      })
+      .await
+      .map_err(|err| (None, err))?
+  }
+
+  pub async fn live_status(&self) -> Result<GameLiveStatus> {
+    self
+      .request(|reply| GameCommand::LiveStatus { reply })
+      .await
+  }
+
+  /// The game with the current game and slot client status
+  pub async fn snapshot(&self) -> Result<(proto::Game, GameLiveStatus)> {
+    self.request(|reply| GameCommand::Snapshot { reply }).await
+  }
+
+  /// Reports the full game status to the controller
+  pub async fn report_status(&self) -> Result<()> {
+    self
+      .request(|reply| GameCommand::ReportStatus { reply })
+      .await?
+  }
+
+  pub async fn retry_shutdown(
+    &self,
+    player_id: i32,
+    leave_reason: Option<LeaveReason>,
+    stream: &mut FloStream,
+  ) -> Result<(), Error> {
+    self
+      .request(|reply| GameCommand::NotifyPlayerShutdown {
+        player_id,
+        leave_reason,
+        reply,
+      })
+      .await??;
+    stream
+      .send_frame(Frame::new_empty(PacketTypeId::ClientShutdownAck))
+      .await?;
+    stream.flush().await?;
+    Ok(())
+  }
+
+  pub async fn update_player_client_status(
+    &self,
+    source: SlotClientStatusUpdateSource,
+    player_id: i32,
+    status: SlotClientStatus,
+  ) -> Result<()> {
+    self
+      .request(|reply| GameCommand::UpdatePlayerClientStatus {
+        source,
+        player_id,
+        status,
+        reply,
+      })
+      .await?
+  }
+}
+
+impl State {
+  async fn handle_command(&mut self, command: GameCommand) {
+    match command {
+      GameCommand::RegisterPlayerStream {
+        player_id,
+        stream,
+        reply,
+      } => {
+        let res = self.register_player_stream(player_id, stream).await;
+        reply.send(res).ok();
+      }
+      GameCommand::LiveStatus { reply } => {
+        reply.send(self.live_status()).ok();
+      }
+      GameCommand::Snapshot { reply } => {
+        reply.send(self.snapshot()).ok();
+      }
+      GameCommand::ReportStatus { reply } => {
+        let res = self.broadcast_status_update(StatusUpdate::Full).await;
+        reply.send(res).ok();
+      }
+      GameCommand::NotifyPlayerShutdown {
+        player_id,
+        leave_reason,
+        reply,
+      } => {
+        let res = self
+          .host
+          .notify_player_shutdown(player_id, leave_reason)
+          .await;
+        reply.send(res).ok();
+      }
+      GameCommand::UpdatePlayerClientStatus {
+        source,
+        player_id,
+        status,
+        reply,
+      } => {
+        let res = self
+          .update_player_client_status(source, player_id, status)
+          .await;
+        reply.send(res).ok();
+      }
+    }
   }
 
-  async fn handle_event(handle: &GameSessionHandle, event: GameEvent) -> Result<()> {
+  async fn handle_event(&mut self, event: GameEvent) -> Result<()> {
     match event {
       GameEvent::PlayerStatusChange(player_id, status, source) => {
-        handle
+        self
           .update_player_client_status(source, player_id, status)
           .await?;
       }
       GameEvent::GameStatusChange(status) => {
-        let mut guard = handle.0.lock().await;
-        let game_id = guard.game_id;
-        guard.status = status;
-        guard.broadcast_status_update(StatusUpdate::Full).await?;
+        let game_id = self.game_id;
+        self.broadcast_status_update(StatusUpdate::Full).await?;
         match status {
           NodeGameStatus::Running => {
-            guard.host.start();
+            self.host.start();
           }
           NodeGameStatus::Ended => {
-            guard
+            self
               .g_event_sender
               .send(GlobalEvent::GameEnded(game_id))
               .await
@@ -138,25 +363,31 @@ impl GameSession {
           _ => {}
         }
       }
+      GameEvent::Anomaly(anomaly) => {
+        let frame = proto::PacketNodeGameAnomaly {
+          game_id: self.game_id,
+          player_id: anomaly.player_id,
+          kind: anomaly.kind.into(),
+          tick: anomaly.tick,
+          detail: anomaly.detail,
+          evidence: anomaly.evidence.into_iter().map(|v| v.to_vec()).collect(),
+        }
+        .encode_as_frame()?;
+        self.ctrl.send(frame).await.ok();
+      }
     }
     Ok(())
   }
-}
-
-#[derive(Debug, Clone)]
-pub struct GameSessionHandle(Arc<Mutex<State>>);
 
-impl GameSessionHandle {
-  pub async fn register_player_stream(
-    &self,
+  async fn register_player_stream(
+    &mut self,
     player_id: i32,
     stream: FloStream,
   ) -> Result<(), (Option<FloStream>, Error)> {
     use host::stream::PlayerStream;
 
-    let mut guard = self.0.lock().await;
     {
-      let slot = if let Some(v) = guard.player_slots.get_mut(&player_id) {
+      let slot = if let Some(v) = self.player_slots.get_mut(&player_id) {
         v
       } else {
         return Err((stream.into(), Error::PlayerNotFoundInGame));
@@ -177,45 +408,83 @@ impl GameSessionHandle {
     };
 
     let stream = PlayerStream::new(player_id, stream);
-    let snapshot = guard.get_status_snapshot();
-    let sender = guard
+    let snapshot = self.get_status_snapshot();
+    let sender = self
       .host
       .register_player_stream(stream, snapshot)
       .await
       .map_err(|err| (None, err))?;
-    guard
+    self
       .player_slots
       .get_mut(&player_id)
       .map(|slot| slot.sender.replace(sender));
     Ok(())
   }
 
-  pub async fn retry_shutdown(
-    &self,
-    player_id: i32,
-    leave_reason: Option<LeaveReason>,
-    stream: &mut FloStream,
-  ) -> Result<(), Error> {
-    let mut guard = self.0.lock().await;
-    guard
-      .host
-      .notify_player_shutdown(player_id, leave_reason)
-      .await?;
-    stream
-      .send_frame(Frame::new_empty(PacketTypeId::ClientShutdownAck))
-      .await?;
-    stream.flush().await?;
+  fn live_status(&self) -> GameLiveStatus {
+    let mut status = self.host.live_status();
+    if self.lifecycle.phase() == GamePhase::Loading {
+      for slot in self.player_slots.values() {
+        match slot.client_status {
+          SlotClientStatus::Joined | SlotClientStatus::Loading => {
+            status.loading_player_ids.push(slot.player.player_id)
+          }
+          SlotClientStatus::Loaded => status.loaded_player_ids.push(slot.player.player_id),
+          _ => {}
+        }
+      }
+    }
+    status
+  }
+
+  /// Drops the players whose client never finished loading,
+  /// the game starts once the remaining players have loaded
+  async fn drop_unloaded_players(&mut self) -> Result<()> {
+    if self.lifecycle.phase() != GamePhase::Loading {
+      return Ok(());
+    }
+    let player_ids: Vec<i32> = self
+      .player_slots
+      .values()
+      .filter(|slot| {
+        slot.client_status == SlotClientStatus::Joined
+          || slot.client_status == SlotClientStatus::Loading
+      })
+      .map(|slot| slot.player.player_id)
+      .collect();
+    for player_id in player_ids {
+      tracing::warn!(player_id, "load timeout");
+      self
+        .host
+        .notify_player_shutdown(player_id, Some(LeaveReason::LeaveDisconnect))
+        .await?;
+    }
     Ok(())
   }
 
-  pub async fn update_player_client_status(
-    &self,
+  /// The game with the current game and slot client status
+  fn snapshot(&self) -> (proto::Game, GameLiveStatus) {
+    let mut game = self.game.clone();
+    game.set_status(self.status().into_proto_enum());
+    for slot in &mut game.slots {
+      if let Some(status) = slot
+        .player
+        .as_ref()
+        .and_then(|p| self.player_slots.get(&p.player_id))
+        .map(|slot| slot.client_status)
+      {
+        slot.set_client_status(status.into_proto_enum());
+      }
+    }
+    (game, self.host.live_status())
+  }
+
+  async fn update_player_client_status(
+    &mut self,
     source: SlotClientStatusUpdateSource,
     player_id: i32,
     next_status: SlotClientStatus,
   ) -> Result<()> {
-    let mut guard = self.0.lock().await;
-
     tracing::info!(
       player_id,
       "update player client status: {:?} => {:?}",
@@ -223,18 +492,21 @@ impl GameSessionHandle {
       next_status
     );
 
-    let game_id = guard.game_id;
-    let game_status = guard.status;
+    let game_id = self.game_id;
+    let game_status = self.status();
 
     let send_all = if source == SlotClientStatusUpdateSource::Node
       && next_status == SlotClientStatus::Connected
     {
-      Some(guard.get_status_update_frame(game_id, StatusUpdate::Full)?)
+      if game_status == NodeGameStatus::Ended {
+        self.host.flush_action_stats().await;
+      }
+      Some(self.get_status_update_frame(game_id, StatusUpdate::Full)?)
     } else {
       None
     };
 
-    let slot = guard
+    let slot = self
       .player_slots
       .get_mut(&player_id)
       .ok_or_else(|| Error::PlayerNotFoundInGame)?;
@@ -300,29 +572,29 @@ impl GameSessionHandle {
 
     match next_status {
       SlotClientStatus::Left => {
-        if !guard.check_game_end().await {
-          if guard.status == NodeGameStatus::Loading {
-            guard.check_game_all_loaded().await;
+        if !self.check_game_end().await {
+          if self.lifecycle.phase() == GamePhase::Loading {
+            self.check_game_all_loaded().await;
           }
         }
       }
       SlotClientStatus::Disconnected => {
-        if !guard.check_game_end().await {
-          if guard.status == NodeGameStatus::Loading {
-            guard.check_game_all_loaded().await;
+        if !self.check_game_end().await {
+          if self.lifecycle.phase() == GamePhase::Loading {
+            self.check_game_all_loaded().await;
           }
         }
       }
       SlotClientStatus::Pending => {}
       SlotClientStatus::Connected => {
-        if guard.status == NodeGameStatus::Created {
-          guard.status = NodeGameStatus::Waiting;
+        if self.lifecycle.phase() == GamePhase::Created {
+          self.transition(GamePhase::Lobby);
         }
       }
       SlotClientStatus::Joined => {
-        if guard.status == NodeGameStatus::Waiting {
+        if self.lifecycle.phase() == GamePhase::Lobby {
           // everyone has joined, start the game
-          guard.check_game_all_joined().await;
+          self.check_game_all_joined().await;
         } else {
           // someone leave the lan game after the game started
           tracing::debug!(player_id, "rejoin");
@@ -330,27 +602,27 @@ impl GameSessionHandle {
       }
       SlotClientStatus::Loading => {}
       SlotClientStatus::Loaded => {
-        if guard.status == NodeGameStatus::Loading {
-          guard.check_game_all_loaded().await;
+        if self.lifecycle.phase() == GamePhase::Loading {
+          self.check_game_all_loaded().await;
         }
       }
     }
 
     // game status changed
-    if guard.status != game_status {
-      let next_status = guard.status;
-      guard
+    if self.status() != game_status {
+      let next_status = self.status();
+      self
         .tx
         .send(GameEvent::GameStatusChange(next_status))
         .await
         .map_err(|_| Error::Cancelled)?;
     }
 
-    let update = if guard.status != game_status {
+    let update = if self.status() != game_status {
       StatusUpdate::Slot {
         player_id,
         status: next_status,
-        game_status: Some(guard.status),
+        game_status: Some(self.status()),
       }
     } else {
       StatusUpdate::Slot {
@@ -360,7 +632,7 @@ impl GameSessionHandle {
       }
     };
 
-    guard.broadcast_status_update(update).await?;
+    self.broadcast_status_update(update).await?;
 
     Ok(())
   }
@@ -386,9 +658,11 @@ pub enum SlotClientStatusUpdateSource {
 #[derive(Debug)]
 struct State {
   game_id: i32,
+  /// The game as created by the controller
+  game: proto::Game,
   g_event_sender: GlobalEventSender,
   host: GameHost,
-  status: NodeGameStatus,
+  lifecycle: GameLifecycle,
   player_slots: BTreeMap<i32, PlayerSlot>,
   ctrl: ControllerServerHandle,
   tx: GameEventSender,
@@ -417,6 +691,10 @@ impl State {
             ..Default::default()
           };
           pkt.set_status(game_status.into_proto_enum());
+          if game_status == NodeGameStatus::Ended {
+            pkt.player_stats = self.host.action_stats();
+            pkt.traffic = Some(self.host.traffic_stats());
+          }
           pkt
             .insert_updated_player_game_client_status_map(player_id, slot_status.into_proto_enum());
           pkt.encode_as_frame()?
@@ -443,7 +721,11 @@ impl State {
           game_id: self.game_id,
           ..Default::default()
         };
-        pkt.set_status(self.status.into_proto_enum());
+        pkt.set_status(self.status().into_proto_enum());
+        if self.status() == NodeGameStatus::Ended {
+          pkt.player_stats = self.host.action_stats();
+          pkt.traffic = Some(self.host.traffic_stats());
+        }
         for slot in self.player_slots.values() {
           pkt.insert_updated_player_game_client_status_map(
             slot.player.player_id,
@@ -458,6 +740,18 @@ impl State {
 
   async fn broadcast_status_update(&mut self, update: StatusUpdate) -> Result<()> {
     let game_id = self.game_id;
+    // the frame carries the action stats of ended games
+    let ended = self.status() == NodeGameStatus::Ended
+      || matches!(
+        update,
+        StatusUpdate::Slot {
+          game_status: Some(NodeGameStatus::Ended),
+          ..
+        }
+      );
+    if ended {
+      self.host.flush_action_stats().await;
+    }
     let frame = self.get_status_update_frame(game_id, update)?;
 
     let ctrl = self.ctrl.clone();
@@ -516,13 +810,29 @@ impl PlayerSlot {
 }
 
 impl State {
+  fn status(&self) -> NodeGameStatus {
+    self.lifecycle.phase().into()
+  }
+
+  // invalid transitions are logged, the game keeps its phase
+  fn transition(&mut self, phase: GamePhase) {
+    if let Err(err) = self.lifecycle.transition(phase) {
+      tracing::error!(game_id = self.game_id, "{}", err);
+    }
+  }
+
   async fn check_game_end(&mut self) -> bool {
     if self.player_slots.values().all(|slot| {
       (slot.client_status == SlotClientStatus::Left
         || slot.client_status == SlotClientStatus::Disconnected)
         || slot.settings.team == 24
     }) {
-      self.status = NodeGameStatus::Ended;
+      // a game nobody started is aborted
+      let phase = match self.lifecycle.phase() {
+        GamePhase::Loading | GamePhase::Running | GamePhase::Ended => GamePhase::Ended,
+        _ => GamePhase::Aborted,
+      };
+      self.transition(phase);
       tracing::debug!("all player left, end game");
       self.obs.push_game_end(self.game_id);
       self
@@ -543,12 +853,12 @@ impl State {
       .all(|slot| slot.client_status == SlotClientStatus::Joined)
     {
       tracing::debug!("all joined");
-      self.status = NodeGameStatus::Loading;
+      self.transition(GamePhase::Loading);
     }
   }
 
   async fn check_game_all_loaded(&mut self) {
-    if self.status == NodeGameStatus::Loading
+    if self.lifecycle.phase() == GamePhase::Loading
       && self.player_slots.values().all(|slot| {
         [
           SlotClientStatus::Loaded,
@@ -558,7 +868,7 @@ impl State {
         .contains(&slot.client_status)
       })
     {
-      self.status = NodeGameStatus::Running;
+      self.transition(GamePhase::Running);
       tracing::debug!("all loaded");
     }
   }
@@ -620,13 +930,32 @@ pub struct GamePlayer {
   pub player_id: i32,
   pub name: String,
   pub ban_list: Vec<PlayerBanType>,
+  pub mute_list: Vec<i32>,
+}
+
+#[derive(Debug, Default, Clone, S2ProtoUnpack)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::GameRules))]
+pub struct GameRules {
+  pub disable_pause: bool,
+  pub disable_save: bool,
+  pub max_pause_count: u32,
+  pub afk_kick_ticks: u32,
+  pub disable_shared_control: bool,
+  pub shared_control_pairs: Vec<SharedControlPair>,
+}
+
+#[derive(Debug, Clone, Copy, S2ProtoUnpack)]
+#[s2_grpc(message_type(flo_net::proto::flo_node::SharedControlPair))]
+pub struct SharedControlPair {
+  pub slot_id: u32,
+  pub with_slot_id: u32,
 }
 
 impl<'a> From<&'a State> for NodeGameStatusSnapshot {
   fn from(state: &'a State) -> Self {
     NodeGameStatusSnapshot {
       game_id: state.game_id,
-      game_status: state.status,
+      game_status: state.status(),
       player_game_client_status_map: state
         .player_slots
         .values()
@@ -1,18 +1,24 @@
 mod client;
+mod config;
 mod controller;
 mod echo;
 mod env;
 mod game;
 mod metrics;
 mod state;
+mod status;
 mod version;
 
 mod constants;
 pub mod error;
 mod observer;
+mod replay;
 
 use error::Result;
 
+#[cfg(feature = "sim")]
+pub use game::sim;
+
 use flo_event::*;
 
 use self::client::serve_client;
@@ -20,10 +26,14 @@ use self::echo::serve_echo;
 use self::metrics::serve_metrics;
 use crate::state::GlobalState;
 use state::event::{handle_global_events, FloNodeEventContext, GlobalEvent};
+use state::snapshot::serve_snapshots;
+use status::serve_status;
 
 pub async fn serve() -> Result<()> {
+  config::init()?;
+
   let (event_sender, event_receiver) = GlobalEvent::channel(30);
-  let state = GlobalState::new(event_sender).into_ref();
+  let state = GlobalState::new(event_sender, state::store::connect().await?).into_ref();
   let mut ctrl = controller::ControllerServer::new(state.clone());
   let ctrl_handle = ctrl.handle();
 
@@ -32,6 +42,8 @@ pub async fn serve() -> Result<()> {
     serve_client(state.clone()),
     serve_metrics(),
     serve_echo(),
+    serve_snapshots(state.clone(), ctrl_handle.clone()),
+    serve_status(ctrl_handle.clone()),
     handle_global_events(
       FloNodeEventContext {
         state,
@@ -1,8 +1,14 @@
 use once_cell::sync::Lazy;
-use prometheus::{register_int_gauge, Encoder, IntGauge, TextEncoder};
+use prometheus::{
+  register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge, Encoder,
+  Histogram, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
 
 use crate::error::*;
-use hyper::header::CONTENT_TYPE;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use tokio_util::io::ReaderStream;
 
 pub static GAME_SESSIONS: Lazy<IntGauge> =
   Lazy::new(|| register_int_gauge!("flonode_game_sessions", "Number of game sessions").unwrap());
@@ -21,6 +27,87 @@ pub static PLAYER_TOKENS: Lazy<IntGauge> = Lazy::new(|| {
   .unwrap()
 });
 
+pub static GAME_TRANSITIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register_int_counter_vec!(
+    "flonode_game_transitions_total",
+    "Game phase transitions, by the phase entered",
+    &["phase"]
+  )
+  .unwrap()
+});
+
+pub static GAME_COMMANDS_QUEUED: Lazy<IntGauge> = Lazy::new(|| {
+  register_int_gauge!(
+    "flonode_game_commands_queued",
+    "Number of commands waiting for their game task"
+  )
+  .unwrap()
+});
+pub static GAME_COMMAND_WAIT: Lazy<Histogram> = Lazy::new(|| {
+  register_histogram!(
+    "flonode_game_command_wait_seconds",
+    "Time commands waited for their game task"
+  )
+  .unwrap()
+});
+
+pub static RELAY_BYTES_IN: Lazy<IntCounter> = Lazy::new(|| {
+  register_int_counter!(
+    "flonode_relay_bytes_in_total",
+    "Bytes received from players of game sessions"
+  )
+  .unwrap()
+});
+pub static RELAY_BYTES_OUT: Lazy<IntCounter> = Lazy::new(|| {
+  register_int_counter!(
+    "flonode_relay_bytes_out_total",
+    "Bytes sent to players of game sessions"
+  )
+  .unwrap()
+});
+pub static RELAY_BYTES_IN_RATE: Lazy<IntGauge> = Lazy::new(|| {
+  register_int_gauge!(
+    "flonode_relay_bytes_in_per_second",
+    "Bytes per second received from players, averaged over the last sample interval"
+  )
+  .unwrap()
+});
+pub static RELAY_BYTES_OUT_RATE: Lazy<IntGauge> = Lazy::new(|| {
+  register_int_gauge!(
+    "flonode_relay_bytes_out_per_second",
+    "Bytes per second sent to players, averaged over the last sample interval"
+  )
+  .unwrap()
+});
+
+// the longest tick ack wait of all games, reset by `take_max_tick_lag_ms`
+static MAX_TICK_LAG_MS: AtomicU32 = AtomicU32::new(0);
+
+pub fn record_tick_lag_ms(value: u32) {
+  MAX_TICK_LAG_MS.fetch_max(value, Ordering::Relaxed);
+}
+
+/// Returns the longest tick lag recorded since the last call
+pub fn take_max_tick_lag_ms() -> u32 {
+  MAX_TICK_LAG_MS.swap(0, Ordering::Relaxed)
+}
+
+const RELAY_RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Updates the relay rate gauges from the byte counters
+async fn sample_relay_rates() {
+  let secs = RELAY_RATE_SAMPLE_INTERVAL.as_secs();
+  let mut interval = tokio::time::interval(RELAY_RATE_SAMPLE_INTERVAL);
+  let mut last = (RELAY_BYTES_IN.get(), RELAY_BYTES_OUT.get());
+  loop {
+    interval.tick().await;
+    let current = (RELAY_BYTES_IN.get(), RELAY_BYTES_OUT.get());
+    RELAY_BYTES_IN_RATE.set((current.0.saturating_sub(last.0) / secs) as i64);
+    RELAY_BYTES_OUT_RATE.set((current.1.saturating_sub(last.1) / secs) as i64);
+    last = current;
+  }
+}
+
 pub async fn serve_metrics() -> Result<()> {
   use hyper::service::{make_service_fn, service_fn};
   use hyper::{Body, Request, Response, Server};
@@ -36,6 +123,14 @@ pub async fn serve_metrics() -> Result<()> {
       return Ok(response);
     }
 
+    if req
+      .uri()
+      .path()
+      .starts_with(flo_net::replay::REPLAY_HTTP_PATH_PREFIX)
+    {
+      return Ok(serve_replay(&req).await);
+    }
+
     let encoder = TextEncoder::new();
 
     let metric_families = prometheus::gather();
@@ -51,11 +146,59 @@ pub async fn serve_metrics() -> Result<()> {
     Ok(response)
   }
 
+  async fn serve_replay(req: &Request<Body>) -> Response<Body> {
+    let status = |code: u16| {
+      Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+    };
+    let config = match crate::config::get() {
+      Ok(config) => config,
+      Err(err) => {
+        tracing::error!("serve replay: {}", err);
+        return status(500);
+      }
+    };
+    let game_id = match flo_net::replay::verify_path(
+      &config.secret_key,
+      req.uri().path(),
+      req.uri().query().unwrap_or_default(),
+      flo_net::echo::unix_now(),
+    ) {
+      Some(id) => id,
+      None => return status(403),
+    };
+    let file = match crate::replay::open(game_id).await {
+      Ok(Some(file)) => file,
+      Ok(None) => return status(404),
+      Err(err) => {
+        tracing::error!(game_id, "serve replay: {}", err);
+        return status(500);
+      }
+    };
+    let len = match file.metadata().await {
+      Ok(metadata) => metadata.len(),
+      Err(err) => {
+        tracing::error!(game_id, "serve replay: {}", err);
+        return status(500);
+      }
+    };
+    Response::builder()
+      .status(200)
+      .header(CONTENT_TYPE, "application/gzip")
+      .header(CONTENT_LENGTH, len)
+      .body(Body::wrap_stream(ReaderStream::new(file)))
+      .unwrap()
+  }
+
   let addr = SocketAddr::from(SocketAddrV4::new(
     Ipv4Addr::UNSPECIFIED,
     flo_constants::NODE_HTTP_PORT,
   ));
 
+  tokio::spawn(sample_relay_rates());
+
   let server = Server::bind(&addr).serve(make_service_fn(|_| async {
     Ok::<_, hyper::Error>(service_fn(serve_req))
   }));
@@ -54,6 +54,16 @@ pub struct ObserverPublisherHandle {
 }
 
 impl ObserverPublisherHandle {
+  /// A handle that discards every record
+  #[cfg(feature = "sim")]
+  pub fn disabled() -> Self {
+    let (tx, _) = channel(1);
+    ObserverPublisherHandle {
+      broken: Cell::new(true),
+      tx,
+    }
+  }
+
   pub fn push_w3gs(&self, game_id: i32, packet: Packet) {
     self.push_record(GameRecord::new_w3gs(game_id, packet))
   }
@@ -134,6 +144,9 @@ impl BufferMap {
     let mut g = self.map.lock();
     if let Some(buf) = g.get_mut(&game_id) {
       buf.should_remove = true;
+      if let Some(replay) = buf.replay.take() {
+        crate::replay::save(game_id, replay.freeze());
+      }
     }
   }
 
@@ -345,6 +358,8 @@ struct GameBuffer {
   split_chunks: VecDeque<Bytes>,
   last_update: Instant,
   should_remove: bool,
+  // all records of the game, kept until the game ends if replays are stored
+  replay: Option<BytesMut>,
 }
 
 impl GameBuffer {
@@ -355,6 +370,11 @@ impl GameBuffer {
       split_chunks: VecDeque::new(),
       last_update: Instant::now(),
       should_remove: false,
+      replay: if crate::replay::enabled() {
+        Some(BytesMut::new())
+      } else {
+        None
+      },
     }
   }
 
@@ -376,6 +396,9 @@ impl GameBuffer {
     self.data.put_u32(self.seq_id);
     record.encode(&mut self.data);
     self.seq_id = self.seq_id.saturating_add(1);
+    if let Some(replay) = self.replay.as_mut() {
+      record.data.encode(replay);
+    }
 
     if self.data.len() > crate::constants::OBS_MAX_CHUNK_SIZE {
       tracing::warn!(
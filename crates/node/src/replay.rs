@@ -0,0 +1,189 @@
+//! Stores the observer records of each game as its replay when the game ends.
+//!
+//! Replays use the archive format of `flo-observer-fs`, so they can be read by the observer tools.
+
+use crate::env::Env;
+use crate::error::*;
+use backoff::backoff::Backoff;
+use bytes::Bytes;
+use flo_state::async_trait;
+use once_cell::sync::Lazy;
+use rusoto_core::credential::StaticProvider;
+use rusoto_core::request::HttpClient;
+use rusoto_core::Region;
+use rusoto_s3::{S3Client, S3};
+use std::path::PathBuf;
+use tokio::fs::File;
+
+#[async_trait]
+pub trait ReplayStorage: Send + Sync {
+  async fn put(&self, game_id: i32, data: Bytes) -> Result<()>;
+
+  /// Replays served by the node HTTP server, `None` if the storage isn't local
+  async fn open(&self, _game_id: i32) -> Result<Option<File>> {
+    Ok(None)
+  }
+}
+
+static STORAGE: Lazy<Option<Box<dyn ReplayStorage>>> = Lazy::new(|| match from_env() {
+  Ok(storage) => storage,
+  Err(err) => {
+    tracing::error!("replay storage disabled: {}", err);
+    None
+  }
+});
+
+fn from_env() -> Result<Option<Box<dyn ReplayStorage>>> {
+  let env = Env::get();
+  if let Some(root) = env.replay_dir.clone() {
+    return Ok(Some(Box::new(LocalDirStorage { root })));
+  }
+  if let Some(bucket) = env.replay_s3_bucket.clone() {
+    return Ok(Some(Box::new(S3Storage::new(bucket)?)));
+  }
+  Ok(None)
+}
+
+pub fn enabled() -> bool {
+  STORAGE.is_some()
+}
+
+/// Archives and uploads the records of a game in background
+pub fn save(game_id: i32, records: Bytes) {
+  let storage = if let Some(storage) = STORAGE.as_ref() {
+    storage
+  } else {
+    return;
+  };
+
+  tokio::spawn(async move {
+    let data = match tokio::task::spawn_blocking(move || build_archive(game_id, &records)).await {
+      Ok(Ok(data)) => data,
+      Ok(Err(err)) => {
+        tracing::error!(game_id, "build replay: {}", err);
+        return;
+      }
+      Err(_) => return,
+    };
+
+    let mut backoff = backoff::ExponentialBackoff::default();
+    loop {
+      match storage.put(game_id, data.clone()).await {
+        Ok(_) => {
+          tracing::info!(game_id, "replay saved: {} bytes", data.len());
+          break;
+        }
+        Err(err) => {
+          if let Some(duration) = backoff.next_backoff() {
+            tracing::warn!(game_id, "save replay: {}", err);
+            tokio::time::sleep(duration).await;
+          } else {
+            tracing::error!(game_id, "save replay: {}", err);
+            break;
+          }
+        }
+      }
+    }
+  });
+}
+
+pub async fn open(game_id: i32) -> Result<Option<File>> {
+  if let Some(storage) = STORAGE.as_ref() {
+    storage.open(game_id).await
+  } else {
+    Ok(None)
+  }
+}
+
+fn build_archive(game_id: i32, records: &[u8]) -> Result<Bytes> {
+  use flate2::write::GzEncoder;
+  use flate2::Compression;
+  use std::io::Write;
+
+  let mut encoder = GzEncoder::new(vec![], Compression::default());
+  encoder.write_all(&flo_observer_fs::FileHeader::new(game_id).bytes())?;
+  encoder.write_all(records)?;
+  Ok(Bytes::from(encoder.finish()?))
+}
+
+pub struct LocalDirStorage {
+  root: PathBuf,
+}
+
+#[async_trait]
+impl ReplayStorage for LocalDirStorage {
+  async fn put(&self, game_id: i32, data: Bytes) -> Result<()> {
+    tokio::fs::create_dir_all(&self.root).await?;
+    let path = self.root.join(flo_net::replay::object_key(game_id));
+    let temp_path = path.with_extension("tmp");
+    tokio::fs::write(&temp_path, &data).await?;
+    tokio::fs::rename(temp_path, path).await?;
+    Ok(())
+  }
+
+  async fn open(&self, game_id: i32) -> Result<Option<File>> {
+    let path = self.root.join(flo_net::replay::object_key(game_id));
+    match File::open(path).await {
+      Ok(file) => Ok(Some(file)),
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(err.into()),
+    }
+  }
+}
+
+/// Amazon S3 or any S3 compatible storage if `FLO_NODE_REPLAY_S3_ENDPOINT` is set
+pub struct S3Storage {
+  bucket: String,
+  client: S3Client,
+}
+
+impl S3Storage {
+  fn new(bucket: String) -> Result<Self> {
+    let env = Env::get();
+    let provider = StaticProvider::new(
+      std::env::var("AWS_ACCESS_KEY_ID")
+        .map_err(|_| Error::InvalidReplayStorage("missing env AWS_ACCESS_KEY_ID"))?,
+      std::env::var("AWS_SECRET_ACCESS_KEY")
+        .map_err(|_| Error::InvalidReplayStorage("missing env AWS_SECRET_ACCESS_KEY"))?,
+      None,
+      None,
+    );
+    let region_name = std::env::var("AWS_S3_REGION")
+      .map_err(|_| Error::InvalidReplayStorage("missing env AWS_S3_REGION"))?;
+    let region = if let Some(endpoint) = env.replay_s3_endpoint.clone() {
+      Region::Custom {
+        name: region_name,
+        endpoint,
+      }
+    } else {
+      region_name
+        .parse()
+        .map_err(|_| Error::InvalidReplayStorage("invalid env AWS_S3_REGION"))?
+    };
+    let client =
+      HttpClient::new().map_err(|_| Error::InvalidReplayStorage("create http client"))?;
+    Ok(Self {
+      bucket,
+      client: S3Client::new_with(client, provider, region),
+    })
+  }
+}
+
+#[async_trait]
+impl ReplayStorage for S3Storage {
+  async fn put(&self, game_id: i32, data: Bytes) -> Result<()> {
+    use futures::stream;
+    use rusoto_core::ByteStream;
+    use rusoto_s3::PutObjectRequest;
+
+    let len = data.len();
+    let req = PutObjectRequest {
+      key: flo_net::replay::object_key(game_id),
+      body: Some(ByteStream::new_with_size(stream::iter(Some(Ok(data))), len)),
+      bucket: self.bucket.clone(),
+      ..Default::default()
+    };
+    self.client.put_object(req).await?;
+    Ok(())
+  }
+}
@@ -33,7 +33,7 @@ pub async fn handle_global_events(
     match event {
       GlobalEvent::GameEnded(game_id) => {
         tracing::debug!(game_id, "game ended: {}", game_id);
-        ctx.state.end_game(game_id);
+        ctx.state.end_game(game_id).await;
       }
     }
   }
@@ -1,19 +1,21 @@
 pub use event::{handle_global_events, GlobalEvent, GlobalEventSender};
 pub mod event;
+pub mod snapshot;
+pub mod store;
 mod types;
 
 pub use types::*;
 
 use dashmap::DashMap;
-use parking_lot::RwLock;
 use s2_grpc_utils::S2ProtoEnum;
-use std::collections::HashMap;
 use std::sync::Arc;
+use tracing_futures::Instrument;
 
 use flo_net::packet::{FloPacket, Frame, OptionalFieldExt};
 use flo_net::proto::flo_node::{
   ControllerCreateGameRejectReason, Game, PacketControllerCreateGame,
   PacketControllerCreateGameAccept, PacketControllerCreateGameReject,
+  PacketControllerGameStatusRequest, PacketControllerGameStatusResponse,
   PacketControllerUpdateSlotStatus, PacketControllerUpdateSlotStatusAccept,
   PacketControllerUpdateSlotStatusReject,
 };
@@ -23,11 +25,12 @@ use crate::error::*;
 use crate::game::{GameSession, GameSessionHandle, SlotClientStatusUpdateSource};
 use crate::metrics;
 use crate::observer::{ObserverPublisher, ObserverPublisherHandle};
+use store::{GamePlayerTokens, PlayerTokenStore};
 
 #[derive(Debug)]
 pub struct GlobalState {
   event_sender: GlobalEventSender,
-  players: PlayerRegistry,
+  players: Box<dyn PlayerTokenStore>,
   games: GameRegistry,
   obs: ObserverPublisher,
 }
@@ -35,10 +38,10 @@ pub struct GlobalState {
 pub type GlobalStateRef = Arc<GlobalState>;
 
 impl GlobalState {
-  pub fn new(event_sender: GlobalEventSender) -> Self {
+  pub fn new(event_sender: GlobalEventSender, players: Box<dyn PlayerTokenStore>) -> Self {
     GlobalState {
       event_sender,
-      players: PlayerRegistry::new(),
+      players,
       games: GameRegistry::new(),
       obs: ObserverPublisher::new(),
     }
@@ -48,26 +51,42 @@ impl GlobalState {
     Arc::new(self)
   }
 
-  pub fn get_pending_player(&self, token: &PlayerToken) -> Option<RegisteredPlayer> {
-    self.players.get_by_token(token)
+  pub async fn get_pending_player(&self, token: &PlayerToken) -> Result<Option<RegisteredPlayer>> {
+    self.players.get_by_token(token).await
   }
 
   pub fn get_game(&self, id: i32) -> Option<GameSessionHandle> {
     self.games.get(id)
   }
 
-  pub fn end_game(&self, id: i32) {
-    self.players.remove_game(id);
+  pub async fn end_game(&self, id: i32) {
+    if let Err(err) = self.players.remove_game(id).await {
+      tracing::error!(game_id = id, "remove player tokens: {}", err);
+    }
     self.games.remove(id);
   }
 
-  pub fn handle_controller_create_game(
+  pub async fn handle_controller_create_game(
     &self,
     ctrl: ControllerServerHandle,
     packet: PacketControllerCreateGame,
   ) -> Result<Frame> {
     let game = packet.game.extract()?;
 
+    let span = tracing::info_span!("create_game", game_id = game.id);
+    flo_net::trace::set_parent(&span, packet.trace_context.as_ref());
+    self
+      .create_game(ctrl, game, packet.request_id)
+      .instrument(span)
+      .await
+  }
+
+  async fn create_game(
+    &self,
+    ctrl: ControllerServerHandle,
+    game: Game,
+    request_id: String,
+  ) -> Result<Frame> {
     let game_id = game.id;
     let player_ids: Vec<i32> = game
       .slots
@@ -76,7 +95,23 @@ impl GlobalState {
       .collect();
 
     if player_ids.is_empty() {
-      return Err(Error::NoPlayer);
+      return Err(Error::NoPlayer.with_context(ErrorContext::game(game_id)));
+    }
+
+    let exists = self.games.get(game_id).is_some();
+    if !request_id.is_empty() && exists {
+      if let Some(tokens) = self.players.get_game_tokens(game_id).await? {
+        if tokens.request_id == request_id {
+          tracing::info!("duplicated request: {}", request_id);
+          return Ok(
+            PacketControllerCreateGameAccept {
+              game_id,
+              player_tokens: tokens.tokens,
+            }
+            .encode_as_frame()?,
+          );
+        }
+      }
     }
 
     let pending: Vec<(PlayerToken, RegisteredPlayer)> = {
@@ -104,10 +139,12 @@ impl GlobalState {
       ctrl,
       self.obs.handle(),
       self.event_sender.clone().into(),
+      crate::config::get()?.max_games,
     ) {
       let reason = match err {
         Error::GameExists => ControllerCreateGameRejectReason::GameExists,
-        err => return Err(err),
+        Error::NodeFull => ControllerCreateGameRejectReason::NodeFull,
+        err => return Err(err.with_context(ErrorContext::game(game_id))),
       };
       return Ok(
         PacketControllerCreateGameReject {
@@ -126,10 +163,24 @@ impl GlobalState {
       })
       .collect();
 
-    let stale_pending_players = self.players.register(GamePlayerTokens {
-      game_id,
-      pairs: pending,
-    });
+    let stale_pending_players = match self
+      .players
+      .register(GamePlayerTokens {
+        game_id,
+        request_id,
+        pairs: pending,
+      })
+      .await
+    {
+      Ok(players) => players,
+      Err(err) => {
+        // players can't connect without the tokens
+        if !exists {
+          self.games.remove(game_id);
+        }
+        return Err(err.with_context(ErrorContext::game(game_id)));
+      }
+    };
     if !stale_pending_players.is_empty() {
       for player in stale_pending_players {
         tracing::warn!(
@@ -149,6 +200,24 @@ impl GlobalState {
     )
   }
 
+  pub async fn handle_controller_game_status(
+    &self,
+    packet: PacketControllerGameStatusRequest,
+  ) -> Result<Frame> {
+    let status = match self.games.get(packet.game_id) {
+      Some(game) => game.live_status().await.ok(),
+      None => None,
+    };
+    Ok(
+      PacketControllerGameStatusResponse {
+        request_id: packet.request_id,
+        game_id: packet.game_id,
+        status,
+      }
+      .encode_as_frame()?,
+    )
+  }
+
   pub async fn handle_controller_update_slot_client_status(
     &self,
     packet: PacketControllerUpdateSlotStatus,
@@ -229,94 +298,6 @@ impl GlobalState {
   }
 }
 
-#[derive(Debug)]
-struct PlayerRegistry {
-  state: RwLock<PlayerTokenRegistryState>,
-}
-
-#[derive(Debug, Default)]
-struct PlayerTokenRegistryState {
-  map: HashMap<PlayerToken, RegisteredPlayer>,
-  player_token: HashMap<i32, PlayerToken>,
-  // game_id => [(player_id, tokens)]
-  game_tokens: HashMap<i32, Vec<(i32, PlayerToken)>>,
-}
-
-impl PlayerRegistry {
-  fn new() -> Self {
-    PlayerRegistry {
-      state: RwLock::new(PlayerTokenRegistryState::default()),
-    }
-  }
-
-  // for controller
-  fn register(
-    &self,
-    GamePlayerTokens { game_id, pairs }: GamePlayerTokens,
-  ) -> Vec<RegisteredPlayer> {
-    let mut state = self.state.write();
-    let mut stale_players = vec![];
-
-    state.game_tokens.insert(game_id, {
-      pairs
-        .iter()
-        .map(|(token, player)| (player.player_id, token.clone()))
-        .collect()
-    });
-
-    for (token, player) in pairs {
-      let player_id = player.player_id;
-      let stale_player = if let Some(old) = state.player_token.insert(player_id, token.clone()) {
-        state.map.remove(&old)
-      } else {
-        tracing::debug!("player token inc: {}: {:?}", player_id, token);
-        metrics::PLAYER_TOKENS.inc();
-        None
-      };
-      state.map.insert(token.clone(), player);
-      if let Some(stale_player) = stale_player {
-        stale_players.push(stale_player)
-      }
-    }
-
-    stale_players
-  }
-
-  fn remove_game(&self, game_id: i32) {
-    let mut state = self.state.write();
-    // remove game_id => tokens
-    if let Some(tokens) = state.game_tokens.remove(&game_id) {
-      for (player_id, token) in tokens {
-        use std::collections::hash_map::Entry;
-        // remove token => player
-        if state.map.remove(&token).is_some() {
-          tracing::debug!("player token dec: {}: {:?}", player_id, token);
-          metrics::PLAYER_TOKENS.dec();
-        }
-        // remote player_id => token
-        match state.player_token.entry(player_id) {
-          Entry::Occupied(entry) => {
-            if entry.get() == &token {
-              entry.remove();
-            }
-          }
-          Entry::Vacant(_) => {}
-        }
-      }
-    }
-  }
-
-  pub fn get_by_token(&self, token: &PlayerToken) -> Option<RegisteredPlayer> {
-    self.state.read().map.get(&token).cloned()
-  }
-}
-
-#[derive(Debug)]
-struct GamePlayerTokens {
-  game_id: i32,
-  pairs: Vec<(PlayerToken, RegisteredPlayer)>,
-}
-
 #[derive(Debug)]
 struct GameRegistry {
   map: DashMap<i32, GameSession>,
@@ -336,10 +317,19 @@ impl GameRegistry {
     ctrl: ControllerServerHandle,
     obs: ObserverPublisherHandle,
     g_event_sender: GlobalEventSender,
+    max_games: Option<usize>,
   ) -> Result<()> {
     use dashmap::mapref::entry::Entry;
     let game_id = game.id;
 
+    // checked before locking the entry, `len` locks every shard
+    if let Some(max_games) = max_games {
+      if !self.map.contains_key(&game_id) && self.map.len() >= max_games {
+        tracing::warn!(game_id, "node full: max_games = {}", max_games);
+        return Err(Error::NodeFull);
+      }
+    }
+
     match self.map.entry(game_id) {
       Entry::Vacant(entry) => {
         entry.insert(GameSession::new(game, ctrl, obs, g_event_sender)?);
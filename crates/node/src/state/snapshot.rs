@@ -0,0 +1,259 @@
+//! Crash recovery of the hosted games.
+//!
+//! The games and player tokens are saved to `FLO_NODE_STATE_SNAPSHOT` periodically.
+//! On startup, games still in the lobby phase are restored with the same player tokens,
+//! so the clients reconnecting to the node can resume. Games that were already loading
+//! or running can't be resumed and are reported to the controller as ended.
+
+use super::store::GamePlayerTokens;
+use super::{GlobalState, GlobalStateRef, PlayerToken, RegisteredPlayer};
+use crate::controller::ControllerServerHandle;
+use crate::env::Env;
+use crate::error::*;
+use flo_net::packet::{FloPacket, Message};
+use flo_net::proto::flo_common::SlotClientStatus;
+use flo_net::proto::flo_node::{
+  Game, GameSnapshot, NodeGameStatus, NodeStateSnapshot, PacketNodeGameStatusUpdate,
+};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Restores the games saved by the last snapshot, then saves snapshots periodically
+pub async fn serve_snapshots(state: GlobalStateRef, ctrl: ControllerServerHandle) -> Result<()> {
+  let path = if let Some(path) = Env::get().state_snapshot_path.as_ref() {
+    path
+  } else {
+    return Ok(());
+  };
+
+  // runs alongside the controller connection, the status reports are buffered until it connects
+  if let Err(err) = restore_snapshot(path, &state, ctrl).await {
+    // kept for inspection, the next snapshot would replace it
+    let bad_path = path.with_extension("bad");
+    tracing::error!("restore state snapshot: {}, moved to {:?}", err, bad_path);
+    if let Err(err) = tokio::fs::rename(path, &bad_path).await {
+      tracing::error!("move state snapshot: {}", err);
+    }
+  }
+
+  let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+  loop {
+    interval.tick().await;
+    let snapshot = state.snapshot().await;
+    if let Err(err) = write_snapshot(path, &snapshot).await {
+      tracing::error!("write state snapshot: {}", err);
+    }
+  }
+}
+
+async fn restore_snapshot(
+  path: &Path,
+  state: &GlobalState,
+  ctrl: ControllerServerHandle,
+) -> Result<()> {
+  let bytes = match tokio::fs::read(path).await {
+    Ok(bytes) => bytes,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(err) => return Err(err.into()),
+  };
+  let snapshot = NodeStateSnapshot::decode(&bytes[..]).map_err(flo_net::error::Error::from)?;
+
+  tracing::info!(
+    saved_at = snapshot.saved_at,
+    "restoring {} games",
+    snapshot.games.len()
+  );
+
+  for game in snapshot.games {
+    let game_id = game.game.as_ref().map(|g| g.id).unwrap_or_default();
+    if let Err(err) = state.restore_game(ctrl.clone(), game).await {
+      tracing::error!(game_id, "restore game: {}", err);
+    }
+  }
+  Ok(())
+}
+
+// written to a temporary file first, the previous snapshot stays intact if the node crashes while writing
+async fn write_snapshot(path: &Path, snapshot: &NodeStateSnapshot) -> Result<()> {
+  let tmp_path = path.with_extension("tmp");
+  let mut file = tokio::fs::File::create(&tmp_path).await?;
+  file.write_all(&snapshot.encode_to_vec()).await?;
+  file.sync_all().await?;
+  tokio::fs::rename(&tmp_path, path).await?;
+  Ok(())
+}
+
+impl GlobalState {
+  async fn snapshot(&self) -> NodeStateSnapshot {
+    let handles: Vec<_> = self
+      .games
+      .map
+      .iter()
+      .map(|r| (*r.key(), r.value().handle()))
+      .collect();
+
+    let mut games = Vec::with_capacity(handles.len());
+    for (game_id, handle) in handles {
+      // ended since the handles were collected
+      let (game, live_status) = match handle.snapshot().await {
+        Ok(v) => v,
+        Err(_) => continue,
+      };
+      let player_tokens = match self.players.get_game_tokens(game_id).await {
+        Ok(tokens) => tokens.map(|tokens| tokens.tokens).unwrap_or_default(),
+        Err(err) => {
+          tracing::error!(game_id, "get player tokens: {}", err);
+          continue;
+        }
+      };
+      games.push(GameSnapshot {
+        game: Some(game),
+        player_tokens,
+        live_status: Some(live_status),
+      });
+    }
+
+    NodeStateSnapshot {
+      saved_at: flo_net::echo::unix_now(),
+      games,
+    }
+  }
+
+  async fn restore_game(&self, ctrl: ControllerServerHandle, snapshot: GameSnapshot) -> Result<()> {
+    let mut game = if let Some(game) = snapshot.game {
+      game
+    } else {
+      return Ok(());
+    };
+    let game_id = game.id;
+
+    if !is_restorable(&game) {
+      tracing::warn!(game_id, "game lost: {:?}", game.status());
+      let mut pkt = PacketNodeGameStatusUpdate {
+        game_id,
+        ..Default::default()
+      };
+      pkt.set_status(NodeGameStatus::Ended);
+      for player_id in game
+        .slots
+        .iter()
+        .filter_map(|s| s.player.as_ref())
+        .map(|p| p.player_id)
+      {
+        pkt.insert_updated_player_game_client_status_map(player_id, SlotClientStatus::Disconnected);
+      }
+      ctrl.send(pkt.encode_as_frame()?).await.ok();
+      return Ok(());
+    }
+
+    reset_lobby_game(&mut game);
+
+    let pairs: Vec<_> = snapshot
+      .player_tokens
+      .into_iter()
+      .filter_map(|t| {
+        Some((
+          PlayerToken::from_vec(t.token)?,
+          RegisteredPlayer {
+            player_id: t.player_id,
+            game_id,
+          },
+        ))
+      })
+      .collect();
+
+    self.games.register(
+      game,
+      ctrl,
+      self.obs.handle(),
+      self.event_sender.clone().into(),
+      None,
+    )?;
+    self
+      .players
+      .register(GamePlayerTokens {
+        game_id,
+        request_id: String::new(),
+        pairs,
+      })
+      .await?;
+
+    if let Some(handle) = self.games.get(game_id) {
+      handle.report_status().await?;
+    }
+
+    tracing::info!(game_id, "game restored");
+    Ok(())
+  }
+}
+
+fn is_restorable(game: &Game) -> bool {
+  match game.status() {
+    NodeGameStatus::Created | NodeGameStatus::Waiting => true,
+    NodeGameStatus::Loading | NodeGameStatus::Running | NodeGameStatus::Ended => false,
+  }
+}
+
+// no player is connected after a restart, the players who left stay left
+fn reset_lobby_game(game: &mut Game) {
+  game.set_status(NodeGameStatus::Created);
+  for slot in &mut game.slots {
+    if slot.player.is_some() && slot.client_status() != SlotClientStatus::Left {
+      slot.set_client_status(SlotClientStatus::Pending);
+    }
+  }
+}
+
+#[test]
+fn test_restore_lobby_game() {
+  use flo_net::proto::flo_node::{GamePlayer, GameSlot};
+
+  let slot = |player_id: i32, status: SlotClientStatus| {
+    let mut slot = GameSlot {
+      player: Some(GamePlayer {
+        player_id,
+        ..Default::default()
+      }),
+      ..Default::default()
+    };
+    slot.set_client_status(status);
+    slot
+  };
+
+  let mut game = Game {
+    id: 1,
+    slots: vec![
+      slot(1, SlotClientStatus::Joined),
+      slot(2, SlotClientStatus::Left),
+      GameSlot::default(),
+    ],
+    ..Default::default()
+  };
+  game.set_status(NodeGameStatus::Waiting);
+
+  let bytes = NodeStateSnapshot {
+    saved_at: 1,
+    games: vec![GameSnapshot {
+      game: Some(game),
+      ..Default::default()
+    }],
+  }
+  .encode_to_vec();
+  let mut game = NodeStateSnapshot::decode(&bytes[..]).unwrap().games[0]
+    .game
+    .take()
+    .unwrap();
+
+  assert!(is_restorable(&game));
+  reset_lobby_game(&mut game);
+  assert_eq!(game.status(), NodeGameStatus::Created);
+  assert_eq!(game.slots[0].client_status(), SlotClientStatus::Pending);
+  assert_eq!(game.slots[1].client_status(), SlotClientStatus::Left);
+  assert_eq!(game.slots[2].client_status(), SlotClientStatus::Pending);
+
+  game.set_status(NodeGameStatus::Running);
+  assert!(!is_restorable(&game));
+}
@@ -0,0 +1,415 @@
+//! Storage of the player tokens registered by the controller.
+//!
+//! Tokens are kept in memory unless `FLO_NODE_REDIS_URL` is set. With Redis, a restarted node
+//! still knows the tokens of its restored games. The keys are prefixed with `FLO_NODE_ID`,
+//! so nodes sharing the server don't replace each other's tokens.
+//! Redis entries expire after `REDIS_TOKEN_TTL_SECS` in case the node that created them
+//! never ends the game.
+
+use super::{PlayerToken, RegisteredPlayer};
+use crate::env::Env;
+use crate::error::*;
+use crate::metrics;
+use flo_net::proto::flo_node::PlayerToken as PlayerTokenPacket;
+use flo_state::async_trait;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use redis::aio::ConnectionManager;
+use redis::{pipe, AsyncCommands, Client, Script};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+
+const REDIS_KEY_PREFIX: &str = "flo_node";
+const REDIS_TOKEN_TTL_SECS: u64 = 24 * 3600;
+
+#[derive(Debug)]
+pub struct GamePlayerTokens {
+  pub game_id: i32,
+  pub request_id: String,
+  pub pairs: Vec<(PlayerToken, RegisteredPlayer)>,
+}
+
+#[derive(Debug)]
+pub struct GameTokens {
+  /// Empty if the game wasn't created by a request with an id
+  pub request_id: String,
+  pub tokens: Vec<PlayerTokenPacket>,
+}
+
+#[async_trait]
+pub trait PlayerTokenStore: Debug + Send + Sync {
+  /// Replaces the tokens of the game, returns the players whose tokens of other games were replaced
+  async fn register(&self, tokens: GamePlayerTokens) -> Result<Vec<RegisteredPlayer>>;
+
+  async fn remove_game(&self, game_id: i32) -> Result<()>;
+
+  async fn get_game_tokens(&self, game_id: i32) -> Result<Option<GameTokens>>;
+
+  async fn get_by_token(&self, token: &PlayerToken) -> Result<Option<RegisteredPlayer>>;
+}
+
+pub async fn connect() -> Result<Box<dyn PlayerTokenStore>> {
+  if let Some(url) = Env::get().redis_url.as_ref() {
+    let node_id = Env::get()
+      .node_id
+      .as_ref()
+      .ok_or(Error::RedisNodeIdMissing)?;
+    let conn = Client::open(url.as_str())?
+      .get_tokio_connection_manager()
+      .await?;
+    tracing::info!(
+      node_id = node_id.as_str(),
+      "player tokens are stored in redis"
+    );
+    let store = RedisTokenStore {
+      prefix: format!("{}:{}", REDIS_KEY_PREFIX, node_id),
+      conn,
+    };
+    // tokens of the restored games are still there
+    metrics::PLAYER_TOKENS.set(store.count_tokens().await?);
+    return Ok(Box::new(store));
+  }
+  Ok(Box::new(MemoryTokenStore::new()))
+}
+
+#[derive(Debug)]
+pub struct MemoryTokenStore {
+  state: RwLock<MemoryTokenStoreState>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryTokenStoreState {
+  map: HashMap<PlayerToken, RegisteredPlayer>,
+  player_token: HashMap<i32, PlayerToken>,
+  // game_id => [(player_id, tokens)]
+  game_tokens: HashMap<i32, Vec<(i32, PlayerToken)>>,
+  // game_id => request id of the registered tokens
+  game_request_ids: HashMap<i32, String>,
+}
+
+impl MemoryTokenStore {
+  pub fn new() -> Self {
+    MemoryTokenStore {
+      state: RwLock::new(MemoryTokenStoreState::default()),
+    }
+  }
+}
+
+#[async_trait]
+impl PlayerTokenStore for MemoryTokenStore {
+  async fn register(
+    &self,
+    GamePlayerTokens {
+      game_id,
+      request_id,
+      pairs,
+    }: GamePlayerTokens,
+  ) -> Result<Vec<RegisteredPlayer>> {
+    let mut state = self.state.write();
+    let mut stale_players = vec![];
+
+    // tokens of an earlier request are never used
+    state.remove_game(game_id);
+    if !request_id.is_empty() {
+      state.game_request_ids.insert(game_id, request_id);
+    }
+    state.game_tokens.insert(game_id, {
+      pairs
+        .iter()
+        .map(|(token, player)| (player.player_id, token.clone()))
+        .collect()
+    });
+
+    for (token, player) in pairs {
+      let player_id = player.player_id;
+      let stale_player = if let Some(old) = state.player_token.insert(player_id, token.clone()) {
+        state.map.remove(&old)
+      } else {
+        tracing::debug!("player token inc: {}: {:?}", player_id, token);
+        metrics::PLAYER_TOKENS.inc();
+        None
+      };
+      state.map.insert(token.clone(), player);
+      if let Some(stale_player) = stale_player {
+        stale_players.push(stale_player)
+      }
+    }
+
+    Ok(stale_players)
+  }
+
+  async fn remove_game(&self, game_id: i32) -> Result<()> {
+    self.state.write().remove_game(game_id);
+    Ok(())
+  }
+
+  async fn get_game_tokens(&self, game_id: i32) -> Result<Option<GameTokens>> {
+    let state = self.state.read();
+    Ok(state.game_tokens.get(&game_id).map(|tokens| {
+      GameTokens {
+        request_id: state
+          .game_request_ids
+          .get(&game_id)
+          .cloned()
+          .unwrap_or_default(),
+        tokens: tokens
+          .iter()
+          .map(|(player_id, token)| PlayerTokenPacket {
+            player_id: *player_id,
+            token: token.to_vec(),
+          })
+          .collect(),
+      }
+    }))
+  }
+
+  async fn get_by_token(&self, token: &PlayerToken) -> Result<Option<RegisteredPlayer>> {
+    Ok(self.state.read().map.get(&token).cloned())
+  }
+}
+
+impl MemoryTokenStoreState {
+  fn remove_game(&mut self, game_id: i32) {
+    self.game_request_ids.remove(&game_id);
+    // remove game_id => tokens
+    if let Some(tokens) = self.game_tokens.remove(&game_id) {
+      for (player_id, token) in tokens {
+        use std::collections::hash_map::Entry;
+        // remove token => player
+        if self.map.remove(&token).is_some() {
+          tracing::debug!("player token dec: {}: {:?}", player_id, token);
+          metrics::PLAYER_TOKENS.dec();
+        }
+        // remote player_id => token
+        match self.player_token.entry(player_id) {
+          Entry::Occupied(entry) => {
+            if entry.get() == &token {
+              entry.remove();
+            }
+          }
+          Entry::Vacant(_) => {}
+        }
+      }
+    }
+  }
+}
+
+// ARGV: prefix, game_id
+// `removed` counts the deleted tokens for `metrics::PLAYER_TOKENS`
+const REDIS_REMOVE_GAME: &str = r#"
+local prefix, game_id = ARGV[1], ARGV[2]
+local game_key = prefix .. ':game_tokens:' .. game_id
+local request_key = prefix .. ':game_request:' .. game_id
+local removed = 0
+local old = redis.call('HGETALL', game_key)
+for i = 1, #old, 2 do
+  local player_key = prefix .. ':player_token:' .. old[i]
+  removed = removed + redis.call('DEL', prefix .. ':token:' .. old[i + 1])
+  if redis.call('GET', player_key) == old[i + 1] then
+    redis.call('DEL', player_key)
+  end
+end
+redis.call('DEL', game_key, request_key)
+"#;
+
+// ARGV: prefix, game_id, request_id, ttl, then player_id and token of each player
+// returns the change of the token count and the stale players
+const REDIS_REGISTER: &str = r#"
+local ttl = ARGV[4]
+if ARGV[3] ~= '' then
+  redis.call('SET', request_key, ARGV[3], 'EX', ttl)
+end
+local stale = {}
+for i = 5, #ARGV, 2 do
+  local player_id, token = ARGV[i], ARGV[i + 1]
+  local player_key = prefix .. ':player_token:' .. player_id
+  local old_token = redis.call('GET', player_key)
+  if old_token then
+    local old_player = redis.call('GET', prefix .. ':token:' .. old_token)
+    if old_player then
+      removed = removed + redis.call('DEL', prefix .. ':token:' .. old_token)
+      table.insert(stale, old_player)
+    end
+  end
+  redis.call('SET', player_key, token, 'EX', ttl)
+  redis.call('SET', prefix .. ':token:' .. token, player_id .. ':' .. game_id, 'EX', ttl)
+  redis.call('HSET', game_key, player_id, token)
+end
+redis.call('EXPIRE', game_key, ttl)
+return {(#ARGV - 4) / 2 - removed, stale}
+"#;
+
+// both remove the previous tokens of the game first
+static REMOVE_GAME_SCRIPT: Lazy<Script> =
+  Lazy::new(|| Script::new(&format!("{}return removed", REDIS_REMOVE_GAME)));
+static REGISTER_SCRIPT: Lazy<Script> =
+  Lazy::new(|| Script::new(&format!("{}{}", REDIS_REMOVE_GAME, REDIS_REGISTER)));
+
+/// Players are stored as `player_id:game_id` under the hex encoded tokens
+#[derive(Clone)]
+pub struct RedisTokenStore {
+  /// `REDIS_KEY_PREFIX` salted with the node id
+  prefix: String,
+  conn: ConnectionManager,
+}
+
+impl Debug for RedisTokenStore {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RedisTokenStore").finish()
+  }
+}
+
+impl RedisTokenStore {
+  fn token_key(&self, token: &str) -> String {
+    format!("{}:token:{}", self.prefix, token)
+  }
+
+  async fn count_tokens(&self) -> Result<i64> {
+    let mut conn = self.conn.clone();
+    let mut iter = conn.scan_match::<_, String>(self.token_key("*")).await?;
+    let mut count = 0;
+    while iter.next_item().await.is_some() {
+      count += 1;
+    }
+    Ok(count)
+  }
+
+  fn add_tokens(delta: i64) {
+    if delta > 0 {
+      metrics::PLAYER_TOKENS.add(delta);
+    } else if delta < 0 {
+      metrics::PLAYER_TOKENS.sub(-delta);
+    }
+  }
+
+  fn parse_player(value: &str) -> Option<RegisteredPlayer> {
+    let mut parts = value.splitn(2, ':');
+    Some(RegisteredPlayer {
+      player_id: parts.next()?.parse().ok()?,
+      game_id: parts.next()?.parse().ok()?,
+    })
+  }
+}
+
+#[async_trait]
+impl PlayerTokenStore for RedisTokenStore {
+  async fn register(
+    &self,
+    GamePlayerTokens {
+      game_id,
+      request_id,
+      pairs,
+    }: GamePlayerTokens,
+  ) -> Result<Vec<RegisteredPlayer>> {
+    let mut invocation = REGISTER_SCRIPT.prepare_invoke();
+    invocation
+      .arg(&self.prefix)
+      .arg(game_id)
+      .arg(request_id)
+      .arg(REDIS_TOKEN_TTL_SECS);
+    for (token, player) in &pairs {
+      invocation.arg(player.player_id).arg(token.to_hex());
+    }
+    let (delta, stale): (i64, Vec<String>) =
+      invocation.invoke_async(&mut self.conn.clone()).await?;
+    Self::add_tokens(delta);
+    Ok(
+      stale
+        .iter()
+        .filter_map(|value| Self::parse_player(value))
+        .collect(),
+    )
+  }
+
+  async fn remove_game(&self, game_id: i32) -> Result<()> {
+    let removed: i64 = REMOVE_GAME_SCRIPT
+      .arg(&self.prefix)
+      .arg(game_id)
+      .invoke_async(&mut self.conn.clone())
+      .await?;
+    Self::add_tokens(-removed);
+    Ok(())
+  }
+
+  async fn get_game_tokens(&self, game_id: i32) -> Result<Option<GameTokens>> {
+    let (tokens, request_id): (HashMap<i32, String>, Option<String>) = pipe()
+      .cmd("HGETALL")
+      .arg(format!("{}:game_tokens:{}", self.prefix, game_id))
+      .cmd("GET")
+      .arg(format!("{}:game_request:{}", self.prefix, game_id))
+      .query_async(&mut self.conn.clone())
+      .await?;
+    if tokens.is_empty() {
+      return Ok(None);
+    }
+    Ok(Some(GameTokens {
+      request_id: request_id.unwrap_or_default(),
+      tokens: tokens
+        .into_iter()
+        .filter_map(|(player_id, token)| {
+          Some(PlayerTokenPacket {
+            player_id,
+            token: PlayerToken::from_hex(&token)?.to_vec(),
+          })
+        })
+        .collect(),
+    }))
+  }
+
+  async fn get_by_token(&self, token: &PlayerToken) -> Result<Option<RegisteredPlayer>> {
+    let value: Option<String> = redis::cmd("GET")
+      .arg(self.token_key(&token.to_hex()))
+      .query_async(&mut self.conn.clone())
+      .await?;
+    Ok(value.as_deref().and_then(Self::parse_player))
+  }
+}
+
+#[test]
+fn test_memory_token_store() {
+  futures::executor::block_on(async {
+    let store = MemoryTokenStore::new();
+    let token = PlayerToken::new_uuid();
+    let register = |game_id: i32, token: &PlayerToken| GamePlayerTokens {
+      game_id,
+      request_id: "request".to_string(),
+      pairs: vec![(
+        token.clone(),
+        RegisteredPlayer {
+          player_id: 1,
+          game_id,
+        },
+      )],
+    };
+
+    assert!(store
+      .register(register(1, &token))
+      .await
+      .unwrap()
+      .is_empty());
+    let tokens = store.get_game_tokens(1).await.unwrap().unwrap();
+    assert_eq!(tokens.request_id, "request");
+    assert_eq!(tokens.tokens[0].token, token.to_vec());
+
+    // the player joined another game
+    let stale = store
+      .register(register(2, &PlayerToken::new_uuid()))
+      .await
+      .unwrap();
+    assert_eq!(stale[0].game_id, 1);
+    assert!(store.get_by_token(&token).await.unwrap().is_none());
+
+    store.remove_game(2).await.unwrap();
+    assert!(store.get_game_tokens(2).await.unwrap().is_none());
+  });
+}
+
+#[test]
+fn test_redis_parse_player() {
+  let player = RedisTokenStore::parse_player("12:34").unwrap();
+  assert_eq!((player.player_id, player.game_id), (12, 34));
+  assert!(RedisTokenStore::parse_player("12").is_none());
+
+  let token = PlayerToken::new_uuid();
+  assert_eq!(PlayerToken::from_hex(&token.to_hex()), Some(token));
+}
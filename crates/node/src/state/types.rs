@@ -21,6 +21,21 @@ impl PlayerToken {
   pub fn to_vec(&self) -> Vec<u8> {
     self.0.to_vec()
   }
+
+  pub fn to_hex(&self) -> String {
+    self.0.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  pub fn from_hex(value: &str) -> Option<Self> {
+    if value.len() != 32 || !value.is_ascii() {
+      return None;
+    }
+    let mut token = PlayerToken([0; 16]);
+    for (i, byte) in token.0.iter_mut().enumerate() {
+      *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(token)
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,125 @@
+//! Load reports sent to the controller, used for node selection.
+
+use crate::controller::ControllerServerHandle;
+use crate::error::*;
+use crate::metrics;
+use flo_net::packet::FloPacket;
+use flo_net::proto::flo_node::PacketNodeStatus;
+
+pub async fn serve_status(ctrl: ControllerServerHandle) -> Result<()> {
+  let mut interval = tokio::time::interval(crate::constants::NODE_STATUS_INTERVAL);
+  loop {
+    interval.tick().await;
+    let frame = get_status().await.encode_as_frame()?;
+    // a stale report is useless once the controller reconnects
+    if ctrl.try_send(frame).is_err() {
+      tracing::debug!("node status discarded");
+    }
+  }
+}
+
+async fn get_status() -> PacketNodeStatus {
+  let SystemLoad {
+    load_average,
+    memory_used_bytes,
+    memory_total_bytes,
+    cpus,
+  } = read_system_load().await;
+  PacketNodeStatus {
+    load_average,
+    memory_used_bytes,
+    memory_total_bytes,
+    open_games: metrics::GAME_SESSIONS.get() as u32,
+    relay_bytes_in_per_second: metrics::RELAY_BYTES_IN_RATE.get() as u64,
+    relay_bytes_out_per_second: metrics::RELAY_BYTES_OUT_RATE.get() as u64,
+    max_tick_lag_ms: metrics::take_max_tick_lag_ms(),
+    cpus,
+  }
+}
+
+/// Zero values are treated as unknown by the controller
+#[derive(Default)]
+struct SystemLoad {
+  load_average: f32,
+  memory_used_bytes: u64,
+  memory_total_bytes: u64,
+  cpus: u32,
+}
+
+#[cfg(target_os = "linux")]
+async fn read_system_load() -> SystemLoad {
+  let load_average = tokio::fs::read_to_string("/proc/loadavg")
+    .await
+    .ok()
+    .and_then(|v| parse_load_average(&v))
+    .unwrap_or_default();
+  let (memory_used_bytes, memory_total_bytes) = tokio::fs::read_to_string("/proc/meminfo")
+    .await
+    .ok()
+    .and_then(|v| parse_meminfo(&v))
+    .unwrap_or_default();
+  let cpus = tokio::fs::read_to_string("/proc/cpuinfo")
+    .await
+    .map(|v| count_cpus(&v))
+    .unwrap_or_default();
+  SystemLoad {
+    load_average,
+    memory_used_bytes,
+    memory_total_bytes,
+    cpus,
+  }
+}
+
+/// Only the tick lag and the game metrics are reported
+#[cfg(not(target_os = "linux"))]
+async fn read_system_load() -> SystemLoad {
+  SystemLoad::default()
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_load_average(loadavg: &str) -> Option<f32> {
+  loadavg.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn count_cpus(cpuinfo: &str) -> u32 {
+  cpuinfo
+    .lines()
+    .filter(|line| line.starts_with("processor"))
+    .count() as u32
+}
+
+/// Returns (used, total) in bytes
+#[cfg(any(target_os = "linux", test))]
+fn parse_meminfo(meminfo: &str) -> Option<(u64, u64)> {
+  let get = |key: &str| -> Option<u64> {
+    let line = meminfo.lines().find(|line| line.starts_with(key))?;
+    let kb: u64 = line[key.len()..]
+      .trim_start_matches(':')
+      .split_whitespace()
+      .next()?
+      .parse()
+      .ok()?;
+    Some(kb * 1024)
+  };
+  let total = get("MemTotal")?;
+  let available = get("MemAvailable")?;
+  Some((total.saturating_sub(available), total))
+}
+
+#[test]
+fn test_parse_node_status() {
+  assert_eq!(
+    parse_load_average("0.52 0.58 0.59 1/467 12345\n"),
+    Some(0.52)
+  );
+  assert_eq!(parse_load_average(""), None);
+  assert_eq!(
+    count_cpus("processor\t: 0\ncpu MHz\t: 1\n\nprocessor\t: 1\n"),
+    2
+  );
+
+  let meminfo = "MemTotal:        2048 kB\nMemFree:          512 kB\nMemAvailable:    1024 kB\n";
+  assert_eq!(parse_meminfo(meminfo), Some((1024 * 1024, 2048 * 1024)));
+  assert_eq!(parse_meminfo("MemTotal: 2048 kB\n"), None);
+}
@@ -2,16 +2,18 @@ use crate::broadcast::BroadcastReceiver;
 use crate::error::{Error, Result};
 use crate::game::stream::{GameStreamDataSnapshot, GameStreamEvent};
 use flo_net::{
+  keepalive::{Incoming, KeepAlive, KeepAliveConfig, KeepAliveEvent},
   packet::{Frame, PacketTypeId},
-  ping::{PingMsg, PingStream},
   stream::FloStream,
 };
 use std::time::Duration;
 use tokio_stream::StreamExt;
 use super::send_queue::{GameStreamSendQueue, NoDelaySendQueue, DelaySendQueue};
 
-const PING_INTERVAL: Duration = Duration::from_secs(10);
-const PING_TIMEOUT: Duration = Duration::from_secs(10);
+const KEEPALIVE: KeepAliveConfig = KeepAliveConfig {
+  interval: Duration::from_secs(10),
+  timeout: Duration::from_secs(10),
+};
 
 pub struct GameStreamServer {
   game_id: i32,
@@ -52,8 +54,7 @@ impl GameStreamServer {
       }
     }
 
-    let mut ping = PingStream::interval(PING_INTERVAL, PING_TIMEOUT);
-    ping.start();
+    let mut keepalive = KeepAlive::new(KEEPALIVE);
     loop {
       tokio::select! {
         r = self.rx.recv() => {
@@ -91,8 +92,8 @@ impl GameStreamServer {
         r = transport.recv_frame() => {
           match r {
             Ok(frame) => {
-              if frame.type_id == PacketTypeId::Pong {
-                ping.capture_pong(frame);
+              if let Incoming::KeepAlive(Some(reply)) = keepalive.recv(frame) {
+                transport.send_frame(reply).await?;
               }
             },
             Err(err) => {
@@ -104,12 +105,12 @@ impl GameStreamServer {
             }
           }
         }
-        Some(next) = ping.next() => {
-          match next {
-            PingMsg::Ping(frame) => {
+        event = keepalive.next() => {
+          match event {
+            KeepAliveEvent::Ping(frame) => {
               transport.send_frame(frame).await?;
             },
-            PingMsg::Timeout => {
+            KeepAliveEvent::Timeout => {
               tracing::error!(game_id, "ping timeout");
               break;
             },
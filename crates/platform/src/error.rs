@@ -14,6 +14,12 @@ pub enum Error {
   #[error("unable to get running Warcraft III path ({0})")]
   GetRunningWar3Path(u32),
 
+  #[error("detecting running Warcraft III is not supported on this platform")]
+  RunningWar3DetectionUnsupported,
+
+  #[error("io: {0}")]
+  Io(#[from] std::io::Error),
+
   #[error("config: {0}")]
   Config(#[from] flo_config::error::Error),
 
@@ -18,6 +18,31 @@ pub struct ClientPlatformInfo {
   pub executable_path: PathBuf,
 }
 
+#[derive(Debug, Clone)]
+pub struct RunningWar3 {
+  pub executable_path: PathBuf,
+  pub version: String,
+}
+
+/// Returns `None` if Warcraft III is not running
+#[cfg(any(windows, target_os = "macos"))]
+pub fn get_running_war3() -> Result<Option<RunningWar3>> {
+  let executable_path = match war3::get_running_war3_executable_path()? {
+    Some(path) => path,
+    None => return Ok(None),
+  };
+  let version = war3::get_war3_version(&executable_path)?;
+  Ok(Some(RunningWar3 {
+    executable_path,
+    version,
+  }))
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_running_war3() -> Result<Option<RunningWar3>> {
+  Err(Error::RunningWar3DetectionUnsupported)
+}
+
 impl ClientPlatformInfo {
   #[cfg(windows)]
   pub fn with_config(config: &ClientConfig) -> Result<Self> {
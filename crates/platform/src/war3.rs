@@ -60,7 +60,9 @@ pub use self::windows::*;
 mod macos {
   use crate::error::Result;
   use serde::Deserialize;
-  use std::path::Path;
+  use std::path::{Path, PathBuf};
+
+  const EXECUTABLE_SUFFIX: &str = "Warcraft III.app/Contents/MacOS/Warcraft III";
 
   pub fn get_war3_version(path: &Path) -> Result<String> {
     #[derive(Deserialize)]
@@ -74,6 +76,34 @@ mod macos {
     Ok(value.cf_bundle_version)
   }
 
+  pub fn get_running_war3_executable_path() -> Result<Option<PathBuf>> {
+    let output = std::process::Command::new("ps")
+      .args(&["-axo", "comm="])
+      .output()?;
+    Ok(find_war3_app_path(&String::from_utf8_lossy(&output.stdout)))
+  }
+
+  /// Finds the app bundle path from the process list, the version lives in its Info.plist
+  fn find_war3_app_path(processes: &str) -> Option<PathBuf> {
+    processes.lines().map(str::trim).find_map(|line| {
+      let prefix_len = line.strip_suffix(EXECUTABLE_SUFFIX)?.len();
+      let app_path = &line[..prefix_len + "Warcraft III.app".len()];
+      Some(PathBuf::from(app_path))
+    })
+  }
+
+  #[test]
+  fn test_find_war3_app_path() {
+    let processes = "/sbin/launchd\n/Applications/Warcraft III/_retail_/x86_64/Warcraft III.app/Contents/MacOS/Warcraft III\n";
+    assert_eq!(
+      find_war3_app_path(processes),
+      Some(PathBuf::from(
+        "/Applications/Warcraft III/_retail_/x86_64/Warcraft III.app"
+      ))
+    );
+    assert_eq!(find_war3_app_path("/sbin/launchd\n"), None);
+  }
+
   #[test]
   fn get_mac_war3_version() {
     use crate::path::detect_installation_path;
@@ -0,0 +1,134 @@
+//! Databases used by the lab, either provided by `FLO_TESTLAB_DATABASE_URL` or a docker container.
+//!
+//! The lab truncates the tables on start, so the dedicated env variable is used instead of
+//! `DATABASE_URL` to avoid pointing it to a real database by accident.
+
+use crate::error::*;
+use bs_diesel_utils::ExecutorRef;
+use diesel::{Connection, PgConnection, RunQueryDsl};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+embed_migrations!("../../migrations");
+
+const DOCKER_IMAGE: &str = "postgres:12";
+const DOCKER_PASSWORD: &str = "flo";
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Id of the api client created by `reset`
+pub(crate) const API_CLIENT_ID: i32 = 1;
+
+pub enum TestDatabase {
+  Url(String),
+  Docker { container_id: String, url: String },
+}
+
+impl TestDatabase {
+  pub fn from_env() -> Option<Self> {
+    std::env::var("FLO_TESTLAB_DATABASE_URL")
+      .ok()
+      .map(TestDatabase::Url)
+  }
+
+  /// Starts a postgres container that is removed when this value is dropped
+  pub fn docker() -> Result<Self> {
+    let output = Command::new("docker")
+      .args(&[
+        "run",
+        "-d",
+        "--rm",
+        "-e",
+        &format!("POSTGRES_PASSWORD={}", DOCKER_PASSWORD),
+        "-p",
+        "127.0.0.1::5432",
+        DOCKER_IMAGE,
+      ])
+      .output()?;
+    if !output.status.success() {
+      return Err(Error::Database(
+        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+      ));
+    }
+    let container_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let output = Command::new("docker")
+      .args(&["port", &container_id, "5432"])
+      .output()?;
+    let port = String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .next()
+      .and_then(|addr| addr.rsplit(':').next())
+      .and_then(|port| port.trim().parse::<u16>().ok());
+    let db = TestDatabase::Docker {
+      url: format!(
+        "postgres://postgres:{}@127.0.0.1:{}/postgres",
+        DOCKER_PASSWORD,
+        port.unwrap_or_default()
+      ),
+      container_id,
+    };
+    if port.is_none() {
+      return Err(Error::Database("unable to get container port".to_string()));
+    }
+    Ok(db)
+  }
+
+  /// `FLO_TESTLAB_DATABASE_URL` if set, otherwise a docker container
+  pub fn from_env_or_docker() -> Result<Self> {
+    match Self::from_env() {
+      Some(db) => Ok(db),
+      None => Self::docker(),
+    }
+  }
+
+  pub fn url(&self) -> &str {
+    match *self {
+      TestDatabase::Url(ref url) => url,
+      TestDatabase::Docker { ref url, .. } => url,
+    }
+  }
+
+  pub(crate) async fn wait_ready(&self) -> Result<()> {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+      match PgConnection::establish(self.url()) {
+        Ok(_) => return Ok(()),
+        Err(err) => {
+          if Instant::now() > deadline {
+            return Err(err.into());
+          }
+          tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+      }
+    }
+  }
+}
+
+impl Drop for TestDatabase {
+  fn drop(&mut self) {
+    if let TestDatabase::Docker {
+      ref container_id, ..
+    } = *self
+    {
+      Command::new("docker")
+        .args(&["rm", "-f", container_id])
+        .output()
+        .ok();
+    }
+  }
+}
+
+/// Runs the migrations and removes everything left by the previous run
+pub(crate) async fn reset(db: &ExecutorRef) -> Result<()> {
+  db.exec(|conn| -> flo_controller::error::Result<()> {
+    embedded_migrations::run(conn)?;
+    diesel::sql_query("truncate table api_client, node restart identity cascade").execute(conn)?;
+    diesel::sql_query(
+      "insert into api_client (name, secret_key) values ('flo-testlab', 'flo-testlab')",
+    )
+    .execute(conn)?;
+    Ok(())
+  })
+  .await?;
+  Ok(())
+}
@@ -0,0 +1,37 @@
+use flo_net::proto::flo_connect::PacketGameStartReject;
+use flo_net::proto::flo_node::ClientConnectRejectReason;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+  #[error("database: {0}")]
+  Database(String),
+  #[error("timeout: {0}")]
+  Timeout(&'static str),
+  #[error("lobby connection rejected: {0:?}")]
+  LobbyConnectionRejected(flo_net::proto::flo_connect::ClientConnectRejectReason),
+  #[error("game start rejected: {0:?}")]
+  GameStartRejected(PacketGameStartReject),
+  #[error("node connection rejected: {1} ({0:?})")]
+  NodeConnectionRejected(ClientConnectRejectReason, String),
+  #[error("player token not received: {0}")]
+  PlayerTokenNotReceived(i32),
+  #[error("controller: {0}")]
+  Controller(#[from] flo_controller::error::Error),
+  #[error("net: {0}")]
+  Net(#[from] flo_net::error::Error),
+  #[error("w3gs: {0}")]
+  W3GS(#[from] flo_w3gs::error::Error),
+  #[error("packet conversion: {0}")]
+  PacketConversion(#[from] s2_grpc_utils::result::Error),
+  #[error("actor: {0}")]
+  Actor(#[from] flo_state::error::Error),
+  #[error("db connection: {0}")]
+  DbConnection(#[from] diesel::ConnectionError),
+  #[error("task: {0}")]
+  TaskJoin(#[from] tokio::task::JoinError),
+  #[error("io: {0}")]
+  Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,359 @@
+use crate::db::{TestDatabase, API_CLIENT_ID};
+use crate::error::*;
+use crate::lobby::LobbyClient;
+use crate::node::{NodeClient, Step};
+use bs_diesel_utils::Executor;
+use flo_controller::game::db::{CreateGameOptions, CreateGameParams};
+use flo_controller::game::messages::{CreateGame, PlayerJoin, SelectNode};
+use flo_controller::map::{Map, MapForce, MapPlayer, MapSha1};
+use flo_controller::node::db::AddNode;
+use flo_controller::node::messages::ListNodeStatus;
+use flo_controller::player::db::UpsertPlayer;
+use flo_controller::player::PlayerSource;
+use flo_controller::{ActorMapExt, ControllerState, ControllerStateRef};
+use flo_types::node::SlotClientStatus;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+/// Reported by every fake player when a game starts
+pub const WAR3_VERSION: &str = "1.32.10.18067";
+pub const TEST_MAP_SHA1: [u8; 20] = [0; 20];
+
+const JWT_SECRET_BASE64: &str = "ZmxvLXRlc3RsYWI=";
+const NODE_SECRET: &str = "flo-testlab";
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The lobby and a node running in the current runtime, stopped when dropped
+pub struct TestLab {
+  state: ControllerStateRef,
+  node_id: i32,
+  tasks: Vec<JoinHandle<()>>,
+  _db: TestDatabase,
+}
+
+impl TestLab {
+  /// Starts with `TestDatabase::from_env_or_docker`
+  pub async fn start() -> Result<Self> {
+    Self::with_database(TestDatabase::from_env_or_docker()?).await
+  }
+
+  pub async fn with_database(db: TestDatabase) -> Result<Self> {
+    db.wait_ready().await?;
+
+    std::env::set_var("DATABASE_URL", db.url());
+    if std::env::var("JWT_SECRET_BASE64").is_err() {
+      std::env::set_var("JWT_SECRET_BASE64", JWT_SECRET_BASE64);
+    }
+    std::env::set_var("FLO_NODE_SECRET", NODE_SECRET);
+
+    let executor = Executor::env().into_ref();
+    crate::db::reset(&executor).await?;
+    let node = executor
+      .exec(|conn| {
+        flo_controller::node::db::add_node(
+          conn,
+          &AddNode {
+            name: "testlab".to_string(),
+            location: "local".to_string(),
+            secret: NODE_SECRET.to_string(),
+            ip_addr: "127.0.0.1".to_string(),
+            country_id: "US".to_string(),
+            region: "local".to_string(),
+            max_games: None,
+          },
+        )
+      })
+      .await?;
+
+    let mut tasks = vec![];
+    tasks.push(tokio::spawn(async {
+      if let Err(err) = flo_node::serve().await {
+        tracing::error!("node: {}", err);
+      }
+    }));
+
+    let state = ControllerState::init().await?.into_ref();
+    tasks.push(tokio::spawn({
+      let state = state.clone();
+      async move {
+        if let Err(err) = flo_controller::serve_socket(state).await {
+          tracing::error!("lobby socket: {}", err);
+        }
+      }
+    }));
+
+    let lab = TestLab {
+      state,
+      node_id: node.id,
+      tasks,
+      _db: db,
+    };
+    lab.wait_ready().await?;
+    Ok(lab)
+  }
+
+  pub fn state(&self) -> &ControllerStateRef {
+    &self.state
+  }
+
+  pub fn node_id(&self) -> i32 {
+    self.node_id
+  }
+
+  async fn wait_ready(&self) -> Result<()> {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+      let socket_ready = TcpStream::connect(("127.0.0.1", flo_constants::CONTROLLER_SOCKET_PORT))
+        .await
+        .is_ok();
+      let node_ready = self
+        .state
+        .nodes
+        .send(ListNodeStatus)
+        .await?
+        .iter()
+        .any(|status| status.node.id == self.node_id && status.ready);
+      if socket_ready && node_ready {
+        return Ok(());
+      }
+      if Instant::now() > deadline {
+        return Err(Error::Timeout("lab ready"));
+      }
+      tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+  }
+
+  pub async fn create_player(&self, name: &str) -> Result<i32> {
+    let data = UpsertPlayer {
+      api_client_id: API_CLIENT_ID,
+      name: name.to_string(),
+      source: PlayerSource::Test,
+      source_id: name.to_string(),
+      source_state: None,
+      realm: None,
+    };
+    let player = self
+      .state
+      .db
+      .exec(move |conn| flo_controller::player::db::upsert(conn, &data))
+      .await?;
+    Ok(player.id)
+  }
+
+  /// Creates a game on the lab node with the test map, hosted by the first player
+  pub async fn create_game(&self, player_ids: &[i32]) -> Result<i32> {
+    let host_player_id = player_ids[0];
+    let message = CreateGame {
+      params: CreateGameParams {
+        player_id: host_player_id,
+        name: format!("testlab-{}", host_player_id),
+        map: test_map(player_ids.len()),
+        is_private: false,
+        is_live: false,
+      },
+      options: CreateGameOptions::default(),
+    }
+    .filter_name()
+    .await?;
+    let game = self.state.games.send(message).await??;
+
+    for &player_id in &player_ids[1..] {
+      self
+        .state
+        .games
+        .send_to(
+          game.id,
+          PlayerJoin {
+            player_id,
+            password: None,
+            by_token: false,
+          },
+        )
+        .await?;
+    }
+
+    self
+      .state
+      .games
+      .send_to(
+        game.id,
+        SelectNode {
+          node_id: Some(self.node_id),
+          player_id: host_player_id,
+        },
+      )
+      .await?;
+
+    Ok(game.id)
+  }
+
+  /// Starts the game from the lobby and connects every player to the node.
+  /// `lobby_clients` are in the order of `create_game`, the first one is the host.
+  pub async fn start_game(
+    &self,
+    game_id: i32,
+    lobby_clients: &mut [LobbyClient],
+  ) -> Result<Vec<NodeClient>> {
+    lobby_clients[0].request_game_start(game_id).await?;
+    for client in lobby_clients.iter_mut().skip(1) {
+      client.accept_ready_check().await?;
+    }
+    for client in lobby_clients.iter_mut() {
+      client.ack_game_start(WAR3_VERSION, &TEST_MAP_SHA1).await?;
+    }
+
+    let mut node_clients = Vec::with_capacity(lobby_clients.len());
+    for client in lobby_clients.iter_mut() {
+      let token = client.recv_player_token().await?;
+      if token.game_id != game_id {
+        return Err(Error::PlayerTokenNotReceived(client.player_id()));
+      }
+      node_clients.push(NodeClient::connect(client.player_id(), token.player_token).await?);
+    }
+
+    // the game loads once everyone has joined
+    for client in node_clients.iter_mut() {
+      client.update_status(SlotClientStatus::Joined).await?;
+    }
+    for client in node_clients.iter_mut() {
+      client.update_status(SlotClientStatus::Loading).await?;
+      client.update_status(SlotClientStatus::Loaded).await?;
+    }
+
+    Ok(node_clients)
+  }
+
+  /// Plays a game from creation to the end, with one player per script
+  pub async fn play(&self, scripts: Vec<Vec<Step>>) -> Result<GameRun> {
+    let mut player_ids = Vec::with_capacity(scripts.len());
+    for idx in 0..scripts.len() {
+      player_ids.push(self.create_player(&format!("player{}", idx + 1)).await?);
+    }
+
+    let game_id = self.create_game(&player_ids).await?;
+
+    let mut lobby_clients = Vec::with_capacity(player_ids.len());
+    for &player_id in &player_ids {
+      lobby_clients.push(LobbyClient::connect(player_id).await?);
+    }
+
+    let node_clients = self.start_game(game_id, &mut lobby_clients).await?;
+
+    // players run concurrently, the game doesn't advance if someone stops answering
+    let handles: Vec<_> = node_clients
+      .into_iter()
+      .zip(scripts)
+      .map(|(mut client, steps)| {
+        tokio::spawn(async move {
+          client.run(&steps).await?;
+          Ok::<_, Error>(client)
+        })
+      })
+      .collect();
+
+    let mut node_clients = Vec::with_capacity(handles.len());
+    for handle in handles {
+      node_clients.push(handle.await??);
+    }
+
+    Ok(GameRun {
+      game_id,
+      player_ids,
+      lobby_clients,
+      node_clients,
+    })
+  }
+}
+
+impl Drop for TestLab {
+  fn drop(&mut self) {
+    for task in &self.tasks {
+      task.abort();
+    }
+  }
+}
+
+pub struct GameRun {
+  pub game_id: i32,
+  pub player_ids: Vec<i32>,
+  pub lobby_clients: Vec<LobbyClient>,
+  pub node_clients: Vec<NodeClient>,
+}
+
+/// A map with one force, the file doesn't exist since fake players never load it
+pub fn test_map(players: usize) -> Map {
+  Map {
+    sha1: MapSha1(TEST_MAP_SHA1),
+    checksum: 0,
+    name: "testlab".to_string(),
+    description: String::new(),
+    author: String::new(),
+    path: "maps\\testlab.w3x".to_string(),
+    width: 64,
+    height: 64,
+    players: (0..players)
+      .map(|idx| MapPlayer {
+        name: format!("Player {}", idx + 1),
+        r#type: 1,
+        race: 0,
+        flags: 0,
+      })
+      .collect(),
+    forces: vec![MapForce {
+      name: "Force 1".to_string(),
+      flags: 0,
+      player_set: u32::MAX,
+    }],
+  }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn test_game_lifecycle() {
+  use bytes::Bytes;
+  use flo_w3gs::protocol::constants::LeaveReason;
+
+  flo_log_subscriber::init_env_override("flo_testlab=debug,flo_controller=info,flo_node=info");
+
+  let lab = TestLab::start().await.unwrap();
+  let run = lab
+    .play(vec![
+      vec![
+        Step::Ticks(10),
+        Step::Action(Bytes::from_static(&[0x16, 0x01, 0x01, 0x00])),
+        Step::Ticks(10),
+        Step::Leave(LeaveReason::LeaveLost),
+      ],
+      vec![Step::Ticks(30), Step::Leave(LeaveReason::LeaveWon)],
+    ])
+    .await
+    .unwrap();
+
+  assert_eq!(run.node_clients[0].ticks(), 20);
+  assert_eq!(run.node_clients[1].ticks(), 30);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore]
+async fn test_session_replace() {
+  use flo_net::proto::flo_connect::{
+    ClientDisconnectReason, PacketClientDisconnect, PacketListNodes, PacketListNodesRequest,
+  };
+
+  flo_log_subscriber::init_env_override("flo_testlab=debug,flo_controller=info");
+
+  let lab = TestLab::start().await.unwrap();
+  let player_id = lab.create_player("player").await.unwrap();
+
+  let mut first = LobbyClient::connect(player_id).await.unwrap();
+  let mut second = LobbyClient::connect(player_id).await.unwrap();
+
+  let disconnect = first.recv::<PacketClientDisconnect>().await.unwrap();
+  assert_eq!(disconnect.reason(), ClientDisconnectReason::Multi);
+
+  // the replacing session stays registered
+  second.send(PacketListNodesRequest {}).await.unwrap();
+  let nodes = second.recv::<PacketListNodes>().await.unwrap();
+  assert_eq!(nodes.nodes.len(), 1);
+}
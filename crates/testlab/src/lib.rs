@@ -0,0 +1,16 @@
+//! Runs the lobby, a node and fake players in one process to drive game lifecycles end-to-end.
+//!
+//! Servers listen on the default ports and some configuration is read once per process,
+//! so tests using the lab have to run one at a time (`--test-threads=1`).
+
+#[macro_use]
+extern crate diesel_migrations;
+
+mod db;
+pub mod error;
+mod lab;
+pub mod lobby;
+pub mod node;
+
+pub use db::TestDatabase;
+pub use lab::{test_map, GameRun, TestLab, TEST_MAP_SHA1, WAR3_VERSION};
@@ -0,0 +1,136 @@
+//! A fake player connected to the lobby socket, in place of the flo client.
+
+use crate::error::*;
+use flo_net::packet::{FloPacket, Frame, PacketTypeId};
+use flo_net::proto::flo_connect::{
+  PacketClientConnect, PacketClientConnectAccept, PacketClientConnectReject, PacketGamePlayerToken,
+  PacketGameStartPlayerClientInfoRequest, PacketGameStartReject, PacketGameStartRequest,
+  PacketGameStarting, PacketReadyCheck, PacketReadyCheckResponse,
+};
+use flo_net::stream::FloStream;
+use std::time::Duration;
+use tokio::time::timeout;
+
+pub const RECV_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct LobbyClient {
+  player_id: i32,
+  stream: FloStream,
+}
+
+impl LobbyClient {
+  pub async fn connect(player_id: i32) -> Result<Self> {
+    let token = flo_controller::player::token::create_player_token(player_id)?;
+    Self::connect_to(
+      &format!("127.0.0.1:{}", flo_constants::CONTROLLER_SOCKET_PORT),
+      player_id,
+      token,
+    )
+    .await
+  }
+
+  /// Connects to a lobby socket elsewhere, with a token issued by that controller
+  pub async fn connect_to(addr: &str, player_id: i32, token: String) -> Result<Self> {
+    let mut stream = FloStream::connect_no_delay(addr).await?;
+
+    stream
+      .send(PacketClientConnect {
+        connect_version: Some(flo_constants::MIN_FLO_VERSION.into()),
+        token,
+      })
+      .await?;
+
+    let frame = stream.recv_frame().await?;
+    flo_net::try_flo_packet! {
+      frame => {
+        _p: PacketClientConnectAccept => {}
+        p: PacketClientConnectReject => {
+          return Err(Error::LobbyConnectionRejected(p.reason()))
+        }
+      }
+    };
+
+    Ok(Self { player_id, stream })
+  }
+
+  pub fn player_id(&self) -> i32 {
+    self.player_id
+  }
+
+  pub async fn send<T: FloPacket>(&mut self, packet: T) -> Result<()> {
+    self.stream.send(packet).await?;
+    Ok(())
+  }
+
+  /// Receives the next frame, answering pings from the lobby
+  pub async fn recv_frame(&mut self) -> Result<Frame> {
+    loop {
+      let mut frame = timeout(RECV_TIMEOUT, self.stream.recv_frame())
+        .await
+        .map_err(|_| Error::Timeout("lobby frame"))??;
+      if frame.type_id == PacketTypeId::Ping {
+        frame.type_id = PacketTypeId::Pong;
+        self.stream.send_frame(frame).await?;
+        continue;
+      }
+      return Ok(frame);
+    }
+  }
+
+  /// Skips frames until a `T` arrives
+  pub async fn recv<T: FloPacket + Default>(&mut self) -> Result<T> {
+    loop {
+      let frame = self.recv_frame().await?;
+      if frame.type_id == T::TYPE_ID {
+        return Ok(frame.decode()?);
+      }
+      tracing::debug!(player_id = self.player_id, "skip: {:?}", frame.type_id);
+    }
+  }
+
+  pub async fn request_game_start(&mut self, game_id: i32) -> Result<()> {
+    self
+      .send(PacketGameStartRequest {
+        game_id,
+        ready_check: true,
+      })
+      .await
+  }
+
+  /// Accepts the ready check, sent to every player but the host
+  pub async fn accept_ready_check(&mut self) -> Result<()> {
+    let check: PacketReadyCheck = self.recv().await?;
+    self
+      .send(PacketReadyCheckResponse {
+        game_id: check.game_id,
+        ready: true,
+      })
+      .await
+  }
+
+  /// Answers the version check of a starting game
+  pub async fn ack_game_start(&mut self, war3_version: &str, map_sha1: &[u8]) -> Result<()> {
+    let starting: PacketGameStarting = self.recv().await?;
+    self
+      .send(PacketGameStartPlayerClientInfoRequest {
+        game_id: starting.game_id,
+        war3_version: war3_version.to_string(),
+        map_sha1: map_sha1.to_vec(),
+        ..Default::default()
+      })
+      .await
+  }
+
+  /// Waits for the node token, sent once every player has answered the version check
+  pub async fn recv_player_token(&mut self) -> Result<PacketGamePlayerToken> {
+    loop {
+      let frame = self.recv_frame().await?;
+      if frame.type_id == PacketGamePlayerToken::TYPE_ID {
+        return Ok(frame.decode()?);
+      }
+      if frame.type_id == PacketGameStartReject::TYPE_ID {
+        return Err(Error::GameStartRejected(frame.decode()?));
+      }
+    }
+  }
+}
@@ -0,0 +1,203 @@
+//! A fake game client connected to the node, in place of the LAN proxy and the game.
+
+use crate::error::*;
+use crate::lobby::RECV_TIMEOUT;
+use bytes::Bytes;
+use flo_net::packet::{FloPacket, Frame, PacketTypeId};
+use flo_net::proto::flo_node::{
+  PacketClientConnect, PacketClientConnectAccept, PacketClientConnectReject,
+  PacketClientUpdateSlotClientStatusRequest, PacketNodeGameStatusUpdate,
+};
+use flo_net::stream::FloStream;
+use flo_net::w3gs::{W3GSAckQueue, W3GSFrameExt, W3GSMetadata, W3GSPacket, W3GSPacketTypeId};
+use flo_types::node::{NodeGameStatus, SlotClientStatus};
+use flo_w3gs::protocol::action::{OutgoingAction, OutgoingKeepAlive};
+use flo_w3gs::protocol::constants::LeaveReason;
+use flo_w3gs::protocol::leave::LeaveReq;
+use s2_grpc_utils::S2ProtoEnum;
+use tokio::time::timeout;
+
+/// A step of a scripted player
+#[derive(Debug, Clone)]
+pub enum Step {
+  /// Sends an action with the next keep alive
+  Action(Bytes),
+  /// Answers this many game ticks
+  Ticks(usize),
+  Leave(LeaveReason),
+}
+
+pub struct NodeClient {
+  player_id: i32,
+  stream: FloStream,
+  ack_q: W3GSAckQueue,
+  game_status: NodeGameStatus,
+  ticks: usize,
+}
+
+impl NodeClient {
+  pub async fn connect(player_id: i32, token: Vec<u8>) -> Result<Self> {
+    Self::connect_to("127.0.0.1", player_id, token).await
+  }
+
+  /// Connects to the client port of the node at `ip_addr`
+  pub async fn connect_to(ip_addr: &str, player_id: i32, token: Vec<u8>) -> Result<Self> {
+    let mut stream =
+      FloStream::connect_no_delay(format!("{}:{}", ip_addr, flo_constants::NODE_CLIENT_PORT))
+        .await?;
+
+    stream
+      .send(PacketClientConnect {
+        version: Some(flo_constants::MIN_FLO_VERSION.into()),
+        token,
+        ..Default::default()
+      })
+      .await?;
+
+    let frame = stream.recv_frame().await?;
+    let game_status = flo_net::try_flo_packet! {
+      frame => {
+        p: PacketClientConnectAccept => {
+          NodeGameStatus::unpack_enum(p.game_status())
+        }
+        p: PacketClientConnectReject => {
+          return Err(Error::NodeConnectionRejected(p.reason(), p.message))
+        }
+      }
+    };
+
+    Ok(Self {
+      player_id,
+      stream,
+      ack_q: W3GSAckQueue::new(),
+      game_status,
+      ticks: 0,
+    })
+  }
+
+  pub fn player_id(&self) -> i32 {
+    self.player_id
+  }
+
+  pub fn game_status(&self) -> NodeGameStatus {
+    self.game_status
+  }
+
+  /// Number of game ticks received
+  pub fn ticks(&self) -> usize {
+    self.ticks
+  }
+
+  pub async fn update_status(&mut self, status: SlotClientStatus) -> Result<()> {
+    let mut pkt = PacketClientUpdateSlotClientStatusRequest::default();
+    pkt.set_status(status.into_proto_enum());
+    self.stream.send(pkt).await?;
+    Ok(())
+  }
+
+  pub async fn send_w3gs(&mut self, pkt: W3GSPacket) -> Result<()> {
+    let sid = self.ack_q.gen_next_send_sid();
+    let meta = W3GSMetadata::new(pkt.type_id(), sid, self.ack_q.take_ack_received());
+    self.ack_q.push_send(meta.clone(), pkt.clone());
+    self.stream.send_frame(Frame::from_w3gs(meta, pkt)).await?;
+    Ok(())
+  }
+
+  /// Receives the next W3GS packet.
+  /// Node frames are handled in between: pings are answered and game status updates are tracked.
+  pub async fn recv_w3gs(&mut self) -> Result<W3GSPacket> {
+    loop {
+      let mut frame = timeout(RECV_TIMEOUT, self.stream.recv_frame())
+        .await
+        .map_err(|_| Error::Timeout("node frame"))??;
+      match frame.type_id {
+        PacketTypeId::Ping => {
+          frame.type_id = PacketTypeId::Pong;
+          self.stream.send_frame(frame).await?;
+        }
+        PacketTypeId::W3GS => {
+          let (meta, pkt) = frame.try_into_w3gs()?;
+          if !self.ack_q.ack_received(meta.sid()) {
+            continue;
+          }
+          if let Some(ack_sid) = meta.ack_sid() {
+            self.ack_q.ack_sent(ack_sid);
+          }
+          return Ok(pkt);
+        }
+        PacketNodeGameStatusUpdate::TYPE_ID => {
+          let pkt: PacketNodeGameStatusUpdate = frame.decode()?;
+          self.game_status = NodeGameStatus::unpack_enum(pkt.status());
+        }
+        other => {
+          tracing::debug!(player_id = self.player_id, "skip: {:?}", other);
+        }
+      }
+    }
+  }
+
+  /// Reports the LAN join and map loading, the game runs once every player did
+  pub async fn join_and_load(&mut self) -> Result<()> {
+    self.update_status(SlotClientStatus::Joined).await?;
+    self.update_status(SlotClientStatus::Loading).await?;
+    self.update_status(SlotClientStatus::Loaded).await?;
+    Ok(())
+  }
+
+  /// Waits for the next game tick and answers it with a keep alive, sending `action` first
+  pub async fn tick(&mut self, action: Option<&[u8]>) -> Result<()> {
+    loop {
+      let pkt = self.recv_w3gs().await?;
+      match pkt.type_id() {
+        W3GSPacketTypeId::IncomingAction | W3GSPacketTypeId::IncomingAction2 => break,
+        _ => {}
+      }
+    }
+    self.ticks += 1;
+
+    if let Some(data) = action {
+      self
+        .send_w3gs(W3GSPacket::with_payload(OutgoingAction::new(data))?)
+        .await?;
+    }
+    self
+      .send_w3gs(W3GSPacket::simple(OutgoingKeepAlive {
+        unknown: 1,
+        checksum: 0,
+      })?)
+      .await
+  }
+
+  /// Sends a leave request and waits for the node to acknowledge it
+  pub async fn leave(&mut self, reason: LeaveReason) -> Result<()> {
+    self
+      .send_w3gs(W3GSPacket::simple(LeaveReq::new(reason))?)
+      .await?;
+    loop {
+      let pkt = self.recv_w3gs().await?;
+      if pkt.type_id() == W3GSPacketTypeId::LeaveAck {
+        return Ok(());
+      }
+    }
+  }
+
+  pub async fn run(&mut self, steps: &[Step]) -> Result<()> {
+    let mut pending_action: Option<&[u8]> = None;
+    for step in steps {
+      match *step {
+        Step::Action(ref data) => {
+          pending_action = Some(data.as_ref());
+        }
+        Step::Ticks(n) => {
+          for _ in 0..n {
+            self.tick(pending_action.take()).await?;
+          }
+        }
+        Step::Leave(reason) => {
+          self.leave(reason).await?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
@@ -9,6 +9,7 @@ pub enum DisconnectReason {
   Unknown = 0,
   Multi = 1,
   Maintenance = 2,
+  Kicked = 3,
 }
 
 #[derive(Debug, S2ProtoUnpack, Serialize, Clone)]
@@ -55,6 +56,7 @@ pub enum RejectReason {
   Unknown = 0,
   ClientVersionTooOld = 1,
   InvalidToken = 2,
+  TooManySessions = 3,
 }
 
 #[derive(Debug, S2ProtoUnpack, Serialize)]
@@ -70,6 +72,8 @@ pub struct GameInfo {
   pub is_live: bool,
   pub random_seed: i32,
   pub created_by: Option<PlayerInfo>,
+  pub entry_key: u32,
+  pub slots_version: i32,
 }
 
 #[derive(Debug, S2ProtoEnum, PartialEq, Copy, Clone, Serialize)]
@@ -274,6 +278,24 @@ pub struct MapDetail {
   pub num_players: usize,
   pub players: Vec<MapPlayerOwned>,
   pub forces: Vec<MapForceOwned>,
+  /// Players can't leave the force the map puts them in
+  pub fixed_teams: bool,
+  /// The map info couldn't be read, the players and forces are placeholders built from
+  /// the player count of the map header
+  pub protected: bool,
+  /// Cheat packs and disallowed natives found in the map script, shown to the host
+  pub script_findings: Vec<MapScriptFinding>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MapScriptFinding {
+  /// `cheat_pack`, `banned_native` or `file_native`
+  pub kind: &'static str,
+  /// The signature or the native
+  pub name: &'static str,
+  pub line: usize,
+  /// Allowed, e.g. the file natives of save/load maps
+  pub warning: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -1,4 +1,5 @@
 pub mod game;
+pub mod lifecycle;
 pub mod node;
 pub mod ping;
 pub mod observer;
\ No newline at end of file
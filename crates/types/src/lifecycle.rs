@@ -0,0 +1,236 @@
+//! Phases of a game, shared by the lobby and the node.
+//!
+//! `Created -> Lobby -> Loading -> Running -> Ended`, a game that did not end can be `Aborted`.
+//! Loading games go back to the lobby when their node is lost, and end early if every player
+//! leaves. The lobby stores the phase as its `GameStatus`, the node reports it as
+//! `NodeGameStatus`.
+
+use crate::node::NodeGameStatus;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Serialize, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamePhase {
+  /// Created on the node, no player connected yet
+  Created,
+  /// Players are joining
+  Lobby,
+  Loading,
+  Running,
+  Ended,
+  /// Cancelled, or left by every player before it started
+  Aborted,
+}
+
+impl GamePhase {
+  pub const ALL: [GamePhase; 6] = [
+    GamePhase::Created,
+    GamePhase::Lobby,
+    GamePhase::Loading,
+    GamePhase::Running,
+    GamePhase::Ended,
+    GamePhase::Aborted,
+  ];
+
+  pub fn is_terminal(self) -> bool {
+    matches!(self, GamePhase::Ended | GamePhase::Aborted)
+  }
+
+  /// Staying in the same phase is not a transition
+  pub fn can_transition_to(self, next: GamePhase) -> bool {
+    use GamePhase::*;
+    match (self, next) {
+      (Created, Lobby) | (Lobby, Loading) | (Loading, Running) | (Running, Ended) => true,
+      (Loading, Lobby) | (Loading, Ended) => true,
+      (from, Aborted) => !from.is_terminal(),
+      _ => false,
+    }
+  }
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      GamePhase::Created => "created",
+      GamePhase::Lobby => "lobby",
+      GamePhase::Loading => "loading",
+      GamePhase::Running => "running",
+      GamePhase::Ended => "ended",
+      GamePhase::Aborted => "aborted",
+    }
+  }
+}
+
+impl From<NodeGameStatus> for GamePhase {
+  fn from(status: NodeGameStatus) -> Self {
+    match status {
+      NodeGameStatus::Created => GamePhase::Created,
+      NodeGameStatus::Waiting => GamePhase::Lobby,
+      NodeGameStatus::Loading => GamePhase::Loading,
+      NodeGameStatus::Running => GamePhase::Running,
+      NodeGameStatus::Ended => GamePhase::Ended,
+    }
+  }
+}
+
+impl From<GamePhase> for NodeGameStatus {
+  fn from(phase: GamePhase) -> Self {
+    match phase {
+      GamePhase::Created => NodeGameStatus::Created,
+      GamePhase::Lobby => NodeGameStatus::Waiting,
+      GamePhase::Loading => NodeGameStatus::Loading,
+      GamePhase::Running => NodeGameStatus::Running,
+      GamePhase::Ended | GamePhase::Aborted => NodeGameStatus::Ended,
+    }
+  }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GameTransition {
+  pub from: GamePhase,
+  pub to: GamePhase,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InvalidTransition(pub GameTransition);
+
+impl fmt::Display for InvalidTransition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "invalid game transition: {} -> {}",
+      self.0.from.as_str(),
+      self.0.to.as_str()
+    )
+  }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+type TransitionHook = Box<dyn Fn(GameTransition) + Send + Sync>;
+
+/// The phase of one game, changed only through valid transitions.
+/// Hooks are called after every transition, in the order they were added.
+pub struct GameLifecycle {
+  phase: GamePhase,
+  hooks: Vec<TransitionHook>,
+}
+
+impl GameLifecycle {
+  pub fn new(phase: GamePhase) -> Self {
+    Self {
+      phase,
+      hooks: vec![],
+    }
+  }
+
+  pub fn phase(&self) -> GamePhase {
+    self.phase
+  }
+
+  pub fn on_transition<F>(&mut self, hook: F)
+  where
+    F: Fn(GameTransition) + Send + Sync + 'static,
+  {
+    self.hooks.push(Box::new(hook));
+  }
+
+  /// Fails if `transition` would, without changing the phase
+  pub fn check(&self, to: GamePhase) -> Result<(), InvalidTransition> {
+    if to == self.phase || self.phase.can_transition_to(to) {
+      Ok(())
+    } else {
+      Err(InvalidTransition(GameTransition {
+        from: self.phase,
+        to,
+      }))
+    }
+  }
+
+  /// Returns `false` if the game is already in the phase
+  pub fn transition(&mut self, to: GamePhase) -> Result<bool, InvalidTransition> {
+    self.check(to)?;
+    if to == self.phase {
+      return Ok(false);
+    }
+    let transition = GameTransition {
+      from: self.phase,
+      to,
+    };
+    self.phase = to;
+    for hook in &self.hooks {
+      hook(transition);
+    }
+    Ok(true)
+  }
+}
+
+impl fmt::Debug for GameLifecycle {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("GameLifecycle")
+      .field("phase", &self.phase)
+      .finish()
+  }
+}
+
+#[test]
+fn test_transitions() {
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+
+  let valid = [
+    (GamePhase::Created, GamePhase::Lobby),
+    (GamePhase::Lobby, GamePhase::Loading),
+    (GamePhase::Loading, GamePhase::Lobby),
+    (GamePhase::Loading, GamePhase::Running),
+    (GamePhase::Loading, GamePhase::Ended),
+    (GamePhase::Running, GamePhase::Ended),
+  ];
+  // every pair: a rejected transition keeps the phase and calls no hook
+  for &from in GamePhase::ALL.iter() {
+    for &to in GamePhase::ALL.iter() {
+      let calls = Arc::new(AtomicUsize::new(0));
+      let mut lifecycle = GameLifecycle::new(from);
+      lifecycle.on_transition({
+        let calls = calls.clone();
+        move |t| {
+          assert_eq!(t, GameTransition { from, to });
+          calls.fetch_add(1, Ordering::SeqCst);
+        }
+      });
+      let expected =
+        valid.contains(&(from, to)) || (to == GamePhase::Aborted && !from.is_terminal());
+      assert_eq!(
+        from.can_transition_to(to),
+        expected,
+        "{:?} -> {:?}",
+        from,
+        to
+      );
+      assert_eq!(lifecycle.check(to).is_ok(), expected || from == to);
+      match lifecycle.transition(to) {
+        Ok(changed) => {
+          assert!(expected || from == to, "{:?} -> {:?}", from, to);
+          assert_eq!(changed, from != to);
+          assert_eq!(lifecycle.phase(), to);
+        }
+        Err(err) => {
+          assert!(!expected && from != to, "{:?} -> {:?}", from, to);
+          assert_eq!(err, InvalidTransition(GameTransition { from, to }));
+          assert_eq!(lifecycle.phase(), from);
+        }
+      }
+      assert_eq!(calls.load(Ordering::SeqCst), expected as usize);
+    }
+  }
+
+  // terminal phases are never left
+  for &from in GamePhase::ALL.iter().filter(|phase| phase.is_terminal()) {
+    assert!(GamePhase::ALL.iter().all(|&to| !from.can_transition_to(to)));
+  }
+
+  for &phase in GamePhase::ALL
+    .iter()
+    .filter(|&&phase| phase != GamePhase::Aborted)
+  {
+    assert_eq!(GamePhase::from(NodeGameStatus::from(phase)), phase);
+  }
+}
@@ -54,7 +54,7 @@ where
   #[inline]
   fn check_size(&mut self, size: usize) -> Result<(), BinDecodeError> {
     if self.remaining() < size {
-      return Err(BinDecodeError::incomplete());
+      return Err(BinDecodeError::incomplete_needed(size - self.remaining()));
     }
     Ok(())
   }
@@ -125,9 +125,14 @@ where
   where
     TItem: BinDecode,
   {
-    self.check_size(TItem::MIN_SIZE * len)?;
-
-    let mut items = Vec::with_capacity(len);
+    let min_size = TItem::MIN_SIZE
+      .checked_mul(len)
+      .ok_or_else(BinDecodeError::incomplete)?;
+    self.check_size(min_size)?;
+
+    // `len` comes from untrusted input, don't let it drive the allocation size
+    // when items can be zero sized
+    let mut items = Vec::with_capacity(std::cmp::min(len, self.remaining()));
     for _ in 0..len {
       items.push(TItem::decode(self)?)
     }
@@ -5,12 +5,22 @@ pub use std::mem::size_of;
 
 mod ext;
 mod net;
+mod partial;
 
 pub use self::ext::*;
 pub use self::net::*;
+pub use self::partial::*;
+
+/// Upper bound of a decoded `CString`, longer strings are rejected with
+/// `BinDecodeError::LimitExceeded` instead of buffering untrusted input
+pub const MAX_CSTRING_LEN: usize = 4096;
 
 pub trait BinEncode {
   fn encode<T: BufMut>(&self, buf: &mut T);
+  /// Fails if a length doesn't fit its `len_prefix`, `encode` cuts such values at the prefix maximum
+  fn check_limits(&self) -> Result<(), BinDecodeError> {
+    Ok(())
+  }
   fn encode_to_bytes(&self) -> BytesMut {
     let mut bytes = BytesMut::new();
     self.encode(&mut bytes);
@@ -93,12 +103,18 @@ impl BinDecode for CString {
     match get_cstring_slice(slice)? {
       // cstring found in current slice
       Some(s) => {
+        if s.len() - 1 > MAX_CSTRING_LEN {
+          return Err(BinDecodeError::limit_exceeded(MAX_CSTRING_LEN).context("CString"));
+        }
         let out = s[..(s.len() - 1)].to_vec();
         let len = s.len();
         buf.advance(len);
         Ok(CString::new(out).map_err(BinDecodeError::failure)?)
       }
       None => {
+        if slice.len() > MAX_CSTRING_LEN {
+          return Err(BinDecodeError::limit_exceeded(MAX_CSTRING_LEN).context("CString"));
+        }
         let mut out = slice.to_vec();
         let len = slice.len();
         buf.advance(len);
@@ -108,7 +124,13 @@ impl BinDecode for CString {
           }
 
           let slice = buf.chunk();
-          if let Some(s) = get_cstring_slice(slice)? {
+          let found = get_cstring_slice(slice)?;
+          let chunk_len = found.map(|s| s.len() - 1).unwrap_or(slice.len());
+          if out.len() + chunk_len > MAX_CSTRING_LEN {
+            return Err(BinDecodeError::limit_exceeded(MAX_CSTRING_LEN).context("CString"));
+          }
+
+          if let Some(s) = found {
             out.extend(&s[..(s.len() - 1)]);
             let len = s.len();
             buf.advance(len);
@@ -210,6 +232,30 @@ fn test_ext_decode_cstring() {
   assert_eq!(buf.remaining(), 1);
 }
 
+#[test]
+fn test_ext_decode_cstring_limit() {
+  let mut bytes = vec![b'x'; MAX_CSTRING_LEN];
+  bytes.push(0);
+  assert_eq!(
+    CString::decode(&mut bytes.as_slice())
+      .unwrap()
+      .as_bytes()
+      .len(),
+    MAX_CSTRING_LEN
+  );
+
+  let mut bytes = vec![b'x'; MAX_CSTRING_LEN + 1];
+  bytes.push(0);
+  assert!(CString::decode(&mut bytes.as_slice())
+    .unwrap_err()
+    .is_limit_exceeded());
+
+  // non-continuous buffer
+  let half = vec![b'x'; MAX_CSTRING_LEN / 2 + 1];
+  let mut buf = half.as_slice().chain(half.as_slice()).chain(&b"\0"[..]);
+  assert!(CString::decode(&mut buf).unwrap_err().is_limit_exceeded());
+}
+
 #[test]
 fn test_derive_decode_fixed_size() {
   use flo_codegen::BinDecode;
@@ -504,6 +550,162 @@ fn test_derive_encode_slice() {
   assert_eq!(bytes, buf);
 }
 
+#[test]
+fn test_derive_len_prefix() {
+  use flo_codegen::{BinDecode, BinEncode};
+  #[derive(Debug, BinDecode, BinEncode, PartialEq)]
+  #[bin(mod_path = "crate::binary")]
+  struct T {
+    #[bin(len_prefix = "u8")]
+    _1: Vec<u16>,
+    #[bin(len_prefix = "u16")]
+    _2: String,
+    _3: u8,
+  }
+
+  assert_eq!(T::MIN_SIZE, 1 + 2 + 1);
+  assert!(!T::FIXED_SIZE);
+
+  let mut buf = BytesMut::new();
+  buf.put_u8(2);
+  buf.put_u16_le(1);
+  buf.put_u16_le(2);
+  buf.put_u16_le(3);
+  buf.put_slice(b"flo");
+  buf.put_u8(4);
+
+  let value = T {
+    _1: vec![1, 2],
+    _2: "flo".to_string(),
+    _3: 4,
+  };
+
+  let mut bytes: Vec<u8> = vec![];
+  value.encode(&mut bytes);
+  assert_eq!(bytes, buf);
+
+  assert_eq!(T::decode(&mut buf).unwrap(), value);
+  assert!(!buf.has_remaining());
+
+  let value = T {
+    _1: vec![0; 256],
+    _2: String::new(),
+    _3: 0,
+  };
+  assert!(value.check_limits().unwrap_err().is_limit_exceeded());
+  let mut bytes: Vec<u8> = vec![];
+  value.encode(&mut bytes);
+  assert_eq!(bytes.len(), 1 + 255 * 2 + 2 + 1);
+  assert_eq!(bytes[0], 255);
+}
+
+#[test]
+fn test_derive_decode_max() {
+  use flo_codegen::BinDecode;
+  #[derive(Debug, BinDecode, PartialEq)]
+  #[bin(mod_path = "crate::binary")]
+  struct T {
+    #[bin(max = 2)]
+    _len: u8,
+    #[bin(repeat = "_len")]
+    _items: Vec<u8>,
+    #[bin(len_prefix = "u8", max = 2)]
+    _prefixed: Vec<u8>,
+  }
+
+  let mut buf = &[2_u8, 1, 2, 1, 3][..];
+  assert_eq!(
+    T::decode(&mut buf).unwrap(),
+    T {
+      _len: 2,
+      _items: vec![1, 2],
+      _prefixed: vec![3],
+    }
+  );
+
+  let mut buf = &[3_u8, 1, 2, 3, 0][..];
+  assert!(T::decode(&mut buf).unwrap_err().is_limit_exceeded());
+
+  let mut buf = &[0_u8, 3, 1, 2, 3][..];
+  assert!(T::decode(&mut buf).unwrap_err().is_limit_exceeded());
+}
+
+#[test]
+fn test_derive_tagged_enum() {
+  use flo_codegen::{BinDecode, BinEncode};
+  #[derive(Debug, BinDecode, BinEncode, PartialEq)]
+  #[bin(mod_path = "crate::binary")]
+  #[bin(enum_repr(u8))]
+  enum V {
+    #[bin(value = 1)]
+    A,
+    #[bin(value = 2)]
+    B(u16),
+    #[bin(value = 3)]
+    C { x: u8, s: CString },
+  }
+
+  let values = vec![
+    V::A,
+    V::B(2),
+    V::C {
+      x: 3,
+      s: CString::new("c").unwrap(),
+    },
+  ];
+
+  let mut bytes: Vec<u8> = vec![];
+  for v in &values {
+    v.encode(&mut bytes);
+  }
+  assert_eq!(bytes, vec![1, 2, 2, 0, 3, 3, b'c', 0]);
+
+  let mut buf = bytes.as_slice();
+  for v in values {
+    assert_eq!(V::decode(&mut buf).unwrap(), v);
+  }
+
+  assert!(V::decode(&mut &[4_u8][..]).is_err());
+  assert!(V::decode(&mut &[2_u8, 0][..]).unwrap_err().is_incomplete());
+}
+
+#[test]
+fn test_derive_if() {
+  use flo_codegen::{BinDecode, BinEncode};
+  #[derive(Debug, BinDecode, BinEncode, PartialEq)]
+  #[bin(mod_path = "crate::binary")]
+  struct T {
+    flags: u8,
+    #[bin(if = "flags & 0x4 != 0")]
+    value: Option<u32>,
+    end: u8,
+  }
+
+  for (value, bytes) in vec![
+    (
+      T {
+        flags: 0x4,
+        value: Some(1),
+        end: 2,
+      },
+      vec![4_u8, 1, 0, 0, 0, 2],
+    ),
+    (
+      T {
+        flags: 0x1,
+        value: None,
+        end: 2,
+      },
+      vec![1_u8, 2],
+    ),
+  ] {
+    let mut encoded: Vec<u8> = vec![];
+    value.encode(&mut encoded);
+    assert_eq!(encoded, bytes);
+    assert_eq!(T::decode(&mut bytes.as_slice()).unwrap(), value);
+  }
+}
+
 #[test]
 fn test_derive_enum_encode() {
   use flo_codegen::BinEncode;
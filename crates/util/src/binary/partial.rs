@@ -0,0 +1,77 @@
+use super::{BinDecode, BinDecodeError, Buf, BytesMut};
+
+/// Result of `BinDecodePartial::decode_partial`
+#[derive(Debug, PartialEq)]
+pub enum Partial<T> {
+  Complete(T),
+  /// At least this many more bytes are required, the buffer is left untouched
+  Incomplete(usize),
+}
+
+/// Decodes from a partially-filled read buffer.
+///
+/// Decoding runs against a borrowed view of the buffer, so nothing is copied
+/// or consumed until a value is complete, and the caller can resume after
+/// reading at least the reported number of bytes.
+pub trait BinDecodePartial: BinDecode {
+  fn decode_partial(buf: &mut BytesMut) -> Result<Partial<Self>, BinDecodeError>;
+}
+
+impl<T> BinDecodePartial for T
+where
+  T: BinDecode,
+{
+  fn decode_partial(buf: &mut BytesMut) -> Result<Partial<Self>, BinDecodeError> {
+    if buf.len() < T::MIN_SIZE {
+      return Ok(Partial::Incomplete(T::MIN_SIZE - buf.len()));
+    }
+
+    let mut view = &buf[..];
+    match T::decode(&mut view) {
+      Ok(value) => {
+        let consumed = buf.len() - view.len();
+        buf.advance(consumed);
+        Ok(Partial::Complete(value))
+      }
+      Err(err) if err.is_incomplete() => Ok(Partial::Incomplete(err.needed().unwrap_or(1))),
+      Err(err) => Err(err),
+    }
+  }
+}
+
+#[test]
+fn test_decode_partial() {
+  use super::{BufMut, CString};
+  use flo_codegen::BinDecode;
+
+  #[derive(Debug, BinDecode, PartialEq)]
+  #[bin(mod_path = "crate::binary")]
+  struct T {
+    id: u32,
+    name: CString,
+    value: u16,
+  }
+
+  let mut buf = BytesMut::new();
+  assert_eq!(T::decode_partial(&mut buf).unwrap(), Partial::Incomplete(7));
+
+  buf.put_u32_le(1);
+  buf.put_slice(b"fl");
+  assert_eq!(T::decode_partial(&mut buf).unwrap(), Partial::Incomplete(1));
+  assert_eq!(buf.len(), 6);
+
+  buf.put_slice(b"o\0");
+  assert_eq!(T::decode_partial(&mut buf).unwrap(), Partial::Incomplete(2));
+
+  buf.put_u16_le(3);
+  buf.put_u8(0xFF);
+  assert_eq!(
+    T::decode_partial(&mut buf).unwrap(),
+    Partial::Complete(T {
+      id: 1,
+      name: CString::new("flo").unwrap(),
+      value: 3,
+    })
+  );
+  assert_eq!(&buf[..], &[0xFF]);
+}
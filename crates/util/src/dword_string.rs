@@ -1,7 +1,7 @@
 use crate::{BinDecode, BinEncode};
 use std::fmt;
 
-#[derive(Clone, Copy, BinEncode, BinDecode)]
+#[derive(Clone, Copy, PartialEq, Eq, BinEncode, BinDecode)]
 #[bin(mod_path = "crate::binary")]
 pub struct DwordString {
   bytes: [u8; 4],
@@ -8,21 +8,118 @@ pub enum Error {
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Identifiers of the game, player and node an error happened with.
+/// Later values don't replace the ones already set, the innermost context is the most specific.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ErrorContext {
+  pub game_id: Option<i32>,
+  pub player_id: Option<i32>,
+  pub node_id: Option<i32>,
+}
+
+impl ErrorContext {
+  pub fn game(game_id: i32) -> Self {
+    Self::default().with_game(game_id)
+  }
+
+  pub fn player(player_id: i32) -> Self {
+    Self::default().with_player(player_id)
+  }
+
+  pub fn node(node_id: i32) -> Self {
+    Self::default().with_node(node_id)
+  }
+
+  pub fn with_game(mut self, game_id: i32) -> Self {
+    self.game_id.get_or_insert(game_id);
+    self
+  }
+
+  pub fn with_player(mut self, player_id: i32) -> Self {
+    self.player_id.get_or_insert(player_id);
+    self
+  }
+
+  pub fn with_node(mut self, node_id: i32) -> Self {
+    self.node_id.get_or_insert(node_id);
+    self
+  }
+
+  pub fn merge(self, other: ErrorContext) -> Self {
+    Self {
+      game_id: self.game_id.or(other.game_id),
+      player_id: self.player_id.or(other.player_id),
+      node_id: self.node_id.or(other.node_id),
+    }
+  }
+
+  /// Without the node id, for messages sent to players
+  pub fn client_safe(self) -> Self {
+    Self {
+      node_id: None,
+      ..self
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.game_id.is_none() && self.player_id.is_none() && self.node_id.is_none()
+  }
+}
+
+impl std::fmt::Display for ErrorContext {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let fields = [
+      ("game_id", self.game_id),
+      ("player_id", self.player_id),
+      ("node_id", self.node_id),
+    ];
+    let mut first = true;
+    for (name, value) in fields.iter() {
+      if let Some(value) = value {
+        if !first {
+          f.write_str(", ")?;
+        }
+        write!(f, "{} = {}", name, value)?;
+        first = false;
+      }
+    }
+    Ok(())
+  }
+}
+
 #[derive(Error, Debug)]
 pub enum BinDecodeError {
   #[error("{context}not enough data")]
-  Incomplete { context: BinDecodeErrorContext },
+  Incomplete {
+    /// Lower bound of the number of missing bytes, if known
+    needed: Option<usize>,
+    context: BinDecodeErrorContext,
+  },
   #[error("{context}{message}")]
   Failure {
     message: String,
     context: BinDecodeErrorContext,
   },
+  #[error("{context}value exceeds limit of {limit}")]
+  LimitExceeded {
+    limit: usize,
+    context: BinDecodeErrorContext,
+  },
 }
 
 impl BinDecodeError {
   #[inline]
   pub fn incomplete() -> Self {
     BinDecodeError::Incomplete {
+      needed: None,
+      context: BinDecodeErrorContext::new(),
+    }
+  }
+
+  #[inline]
+  pub fn incomplete_needed(needed: usize) -> Self {
+    BinDecodeError::Incomplete {
+      needed: Some(needed),
       context: BinDecodeErrorContext::new(),
     }
   }
@@ -38,11 +135,22 @@ impl BinDecodeError {
     }
   }
 
+  #[inline]
+  pub fn limit_exceeded(limit: usize) -> Self {
+    BinDecodeError::LimitExceeded {
+      limit,
+      context: BinDecodeErrorContext::new(),
+    }
+  }
+
   pub fn context<T: std::fmt::Display>(self, ctx: T) -> Self {
     match self {
-      BinDecodeError::Incomplete { mut context } => {
+      BinDecodeError::Incomplete {
+        needed,
+        mut context,
+      } => {
         context.insert(ctx);
-        BinDecodeError::Incomplete { context }
+        BinDecodeError::Incomplete { needed, context }
       }
       BinDecodeError::Failure {
         message,
@@ -51,6 +159,10 @@ impl BinDecodeError {
         context.insert(ctx);
         BinDecodeError::Failure { message, context }
       }
+      BinDecodeError::LimitExceeded { limit, mut context } => {
+        context.insert(ctx);
+        BinDecodeError::LimitExceeded { limit, context }
+      }
     }
   }
 
@@ -61,6 +173,23 @@ impl BinDecodeError {
       _ => false,
     }
   }
+
+  /// Number of missing bytes reported by an `Incomplete` error
+  #[inline]
+  pub fn needed(&self) -> Option<usize> {
+    match *self {
+      BinDecodeError::Incomplete { needed, .. } => needed,
+      _ => None,
+    }
+  }
+
+  #[inline]
+  pub fn is_limit_exceeded(&self) -> bool {
+    match *self {
+      BinDecodeError::LimitExceeded { .. } => true,
+      _ => false,
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -89,3 +218,18 @@ impl std::fmt::Display for BinDecodeErrorContext {
     Ok(())
   }
 }
+
+#[test]
+fn test_error_context() {
+  let context = ErrorContext::game(1).with_player(2).with_game(3);
+  assert_eq!(context.game_id, Some(1));
+  assert_eq!(context.to_string(), "game_id = 1, player_id = 2");
+
+  let merged = context.merge(ErrorContext::node(4).with_player(5));
+  assert_eq!(
+    merged.to_string(),
+    "game_id = 1, player_id = 2, node_id = 4"
+  );
+  assert_eq!(merged.client_safe(), context);
+  assert!(ErrorContext::default().is_empty());
+}
@@ -2,8 +2,10 @@ pub mod binary;
 pub mod chat;
 pub mod dword_string;
 pub mod error;
+pub mod name;
 pub mod stat_string;
 pub mod uptime;
+pub mod word_list;
 
 pub use flo_codegen::*;
 
@@ -0,0 +1,133 @@
+//! Player and game names.
+//!
+//! Names come from the lobby and from the game in any Unicode form, and are sent to the game
+//! as C strings. `normalize` gives the form that is saved and sent, `fold` the form that is
+//! compared when names have to be unique.
+
+use std::ffi::CString;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// In bytes, longer game names are cut by the game
+pub const MAX_GAME_NAME_LEN: usize = 31;
+
+/// NFC, without null bytes and other control characters, trimmed
+pub fn normalize(name: &str) -> String {
+  let name: String = name.nfc().filter(|c| !c.is_control()).collect();
+  name.trim().to_string()
+}
+
+/// The normalized name as a C string, at most `max_len` bytes long without the null byte
+pub fn to_c_string(name: &str, max_len: usize) -> CString {
+  let name = normalize(name);
+  let name = truncate(&name, max_len);
+  CString::new(name).expect("null bytes removed")
+}
+
+/// Number of glyphs as displayed, a glyph can take several chars and bytes
+pub fn glyph_len(name: &str) -> usize {
+  name.graphemes(true).count()
+}
+
+/// The longest prefix of at most `max_len` bytes that doesn't cut a glyph
+pub fn truncate(name: &str, max_len: usize) -> &str {
+  if name.len() <= max_len {
+    return name;
+  }
+  let end = name
+    .grapheme_indices(true)
+    .map(|(idx, glyph)| idx + glyph.len())
+    .take_while(|end| *end <= max_len)
+    .last()
+    .unwrap_or(0);
+  &name[..end]
+}
+
+/// The normalized name truncated so that `suffix` fits in `max_len` bytes after it
+pub fn truncate_with_suffix(name: &str, suffix: &str, max_len: usize) -> String {
+  let name = normalize(name);
+  let name = truncate(&name, max_len.saturating_sub(suffix.len())).trim_end();
+  format!("{}{}", name, suffix)
+}
+
+/// Names that look the same fold to the same value: compatibility forms, case, accents
+/// and the Latin lookalikes from other scripts are folded, e.g. `Ｔｈｒａｌｌ`, `thrall`
+/// and `Тhrall` (with a Cyrillic `Т`).
+pub fn fold(name: &str) -> String {
+  normalize(name)
+    .nfkd()
+    .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+    .flat_map(char::to_lowercase)
+    .map(fold_char)
+    .collect()
+}
+
+// lowercase lookalikes, after NFKD
+fn fold_char(c: char) -> char {
+  match c {
+    // Cyrillic
+    'а' => 'a',
+    'в' => 'b',
+    'е' | 'ё' => 'e',
+    'к' => 'k',
+    'м' => 'm',
+    'н' => 'h',
+    'о' => 'o',
+    'р' => 'p',
+    'с' => 'c',
+    'т' => 't',
+    'у' => 'y',
+    'х' => 'x',
+    'і' => 'i',
+    'ј' => 'j',
+    'ѕ' => 's',
+    // Greek
+    'α' => 'a',
+    'β' => 'b',
+    'ε' => 'e',
+    'ι' => 'i',
+    'κ' => 'k',
+    'ν' => 'v',
+    'ο' => 'o',
+    'ρ' => 'p',
+    'τ' => 't',
+    'υ' => 'u',
+    'χ' => 'x',
+    // digits and letters mixed up in names
+    '0' => 'o',
+    '1' | 'l' => 'i',
+    _ => c,
+  }
+}
+
+#[test]
+fn test_normalize() {
+  // `e` followed by a combining acute accent
+  assert_eq!(normalize(" Rene\u{301}\0\n"), "René");
+  assert_eq!(to_c_string("Rene\u{301}\0", 4).as_bytes(), "Ren".as_bytes());
+  assert_eq!(to_c_string("René", 5).as_bytes(), "René".as_bytes());
+}
+
+#[test]
+fn test_glyphs() {
+  let name = "Ре\u{301}нт";
+  assert_eq!(glyph_len(name), 4);
+  // the accent is not separated from its letter
+  assert_eq!(truncate(name, 4), "Р");
+  assert_eq!(truncate(name, 6), "Ре\u{301}");
+  assert_eq!(truncate("abc", 10), "abc");
+  assert_eq!(truncate_with_suffix("Ре\u{301}нт", "-1", 8), "Ре\u{301}-1");
+  assert_eq!(truncate_with_suffix("abc", "-1", 10), "abc-1");
+}
+
+#[test]
+fn test_fold() {
+  let is_confusable = |a: &str, b: &str| fold(a) == fold(b);
+  assert!(is_confusable("Thrall", "Ｔｈｒａｌｌ"));
+  assert!(is_confusable("Thrall", "\u{422}hrall"));
+  assert!(is_confusable("Grom", "GR0M"));
+  assert!(is_confusable("Illidan", "1llidan"));
+  assert!(is_confusable("Jaina", "Jaína"));
+  assert!(!is_confusable("Thrall", "Thral"));
+}
@@ -0,0 +1,118 @@
+//! Blocked words of the text set by players, shared by the controller filter and the
+//! chat relayed by the nodes.
+
+use regex::{Regex, RegexBuilder};
+
+/// Always blocked, in addition to the configured words
+const BLOCKED_WORDS: &[&str] = &["fuck", "shit", "cunt", "bitch", "asshole"];
+
+/// Blocks whole words, case insensitive, and patterns matching anywhere in the text
+#[derive(Debug)]
+pub struct WordList {
+  words: Vec<String>,
+  patterns: Vec<Regex>,
+}
+
+impl Default for WordList {
+  fn default() -> Self {
+    Self::new(&[], &[])
+  }
+}
+
+impl WordList {
+  /// Invalid patterns are logged and ignored
+  pub fn new(blocked_words: &[String], blocked_patterns: &[String]) -> Self {
+    let words = BLOCKED_WORDS
+      .iter()
+      .map(|word| word.to_string())
+      .chain(blocked_words.iter().map(|word| word.to_lowercase()))
+      .collect();
+    let patterns = blocked_patterns
+      .iter()
+      .filter_map(
+        |pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+          Ok(regex) => Some(regex),
+          Err(err) => {
+            tracing::error!("text filter: invalid pattern `{}`: {}", pattern, err);
+            None
+          }
+        },
+      )
+      .collect();
+    Self { words, patterns }
+  }
+
+  pub fn is_blocked(&self, text: &str) -> bool {
+    words(text).any(|(_, word)| self.is_blocked_word(word))
+      || self.patterns.iter().any(|regex| regex.is_match(text))
+  }
+
+  /// Blocked words and pattern matches replaced by as many `*`
+  pub fn mask(&self, text: &str) -> String {
+    let mut masked = text.to_string();
+    let words: Vec<_> = words(text).collect();
+    // from the end, the masks can be shorter than the words
+    for (start, word) in words.into_iter().rev() {
+      if self.is_blocked_word(word) {
+        masked.replace_range(
+          start..(start + word.len()),
+          &"*".repeat(word.chars().count()),
+        );
+      }
+    }
+    for regex in &self.patterns {
+      masked = regex
+        .replace_all(&masked, |caps: &regex::Captures| {
+          "*".repeat(caps[0].chars().count())
+        })
+        .into_owned();
+    }
+    masked
+  }
+
+  fn is_blocked_word(&self, word: &str) -> bool {
+    let word = word.to_lowercase();
+    self.words.iter().any(|blocked| *blocked == word)
+  }
+}
+
+// byte offsets, words are runs of alphanumeric chars
+fn words<'a>(text: &'a str) -> impl Iterator<Item = (usize, &'a str)> + 'a {
+  let mut start = None;
+  text
+    .char_indices()
+    .chain(std::iter::once((text.len(), ' ')))
+    .filter_map(move |(idx, c)| match (c.is_alphanumeric(), start) {
+      (true, None) => {
+        start = Some(idx);
+        None
+      }
+      (false, Some(from)) => {
+        start = None;
+        Some((from, &text[from..idx]))
+      }
+      _ => None,
+    })
+}
+
+#[test]
+fn test_word_list() {
+  let list = WordList::new(
+    &["Noob".to_string()],
+    &[r"discord\.gg/\w+".to_string(), "(".to_string()],
+  );
+  assert_eq!(list.patterns.len(), 1);
+
+  assert!(!list.is_blocked("gl hf"));
+  assert!(!list.is_blocked("shiitake"));
+  assert!(list.is_blocked("SHIT happens"));
+  assert!(list.is_blocked("you noob!"));
+  assert!(list.is_blocked("join DISCORD.gg/abc"));
+
+  assert_eq!(list.mask("Shit, noob"), "****, ****");
+  assert_eq!(
+    list.mask("see discord.gg/abc now"),
+    "see ************** now"
+  );
+  assert_eq!(list.mask("gl hf"), "gl hf");
+}
@@ -0,0 +1,74 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use flo_util::binary::*;
+use flo_w3gs::protocol::action::{IncomingAction, OutgoingAction, PlayerAction, TimeSlot};
+use flo_w3gs::protocol::packet::Packet;
+
+fn time_slot(players: u8, action_len: usize) -> TimeSlot {
+  TimeSlot {
+    time_increment_ms: 100,
+    actions: (1..=players)
+      .map(|player_id| PlayerAction {
+        player_id,
+        data: Bytes::from(vec![player_id; action_len]),
+      })
+      .collect(),
+  }
+}
+
+fn encoded(packet: &Packet) -> BytesMut {
+  let mut buf = BytesMut::new();
+  packet.encode(&mut buf);
+  buf
+}
+
+fn decode(buf: &mut BytesMut) -> Packet {
+  let header = Packet::decode_header(buf).unwrap();
+  Packet::decode(header, buf).unwrap()
+}
+
+fn bench_outgoing_action(c: &mut Criterion) {
+  let payload = (0..64).collect::<Vec<u8>>();
+  let packet = Packet::with_payload(OutgoingAction::new(&payload)).unwrap();
+  let bytes = encoded(&packet);
+
+  let mut group = c.benchmark_group("outgoing_action");
+  group.throughput(Throughput::Bytes(bytes.len() as u64));
+  group.bench_function("encode", |b| {
+    b.iter(|| Packet::with_payload(OutgoingAction::new(black_box(&payload))).unwrap())
+  });
+  group.bench_function("decode", |b| {
+    b.iter(|| {
+      let mut buf = bytes.clone();
+      let packet = decode(&mut buf);
+      packet.decode_payload::<OutgoingAction>().unwrap()
+    })
+  });
+  group.finish();
+}
+
+fn bench_incoming_action(c: &mut Criterion) {
+  let mut group = c.benchmark_group("incoming_action");
+  for players in [2_u8, 12, 24].iter().cloned() {
+    let packet = Packet::with_payload(IncomingAction(time_slot(players, 32))).unwrap();
+    let bytes = encoded(&packet);
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function(format!("encode/{}", players), |b| {
+      b.iter_batched(
+        || IncomingAction(time_slot(players, 32)),
+        |payload| Packet::with_payload(payload).unwrap(),
+        criterion::BatchSize::SmallInput,
+      )
+    });
+    group.bench_function(format!("decode/{}", players), |b| {
+      b.iter(|| {
+        let mut buf = bytes.clone();
+        let packet = decode(&mut buf);
+        packet.decode_payload::<IncomingAction>().unwrap()
+      })
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, bench_outgoing_action, bench_incoming_action);
+criterion_main!(benches);
@@ -12,6 +12,8 @@ pub enum Error {
   PayloadSizeOverflow,
   #[error("invalid packet length: {0}")]
   InvalidPacketLength(u16),
+  #[error("payload too large: {0}")]
+  PayloadTooLarge(usize),
   #[error("invalid payload length: {0}")]
   InvalidPayloadLength(usize),
   #[error("invalid state: no header")]
@@ -29,10 +31,24 @@ pub enum Error {
   },
   #[error("invalid checksum")]
   InvalidChecksum,
+  #[error("limit exceeded: {0}")]
+  LimitExceeded(flo_util::binary::BinDecodeError),
   #[error("bin decode: {0}")]
-  BinDecode(#[from] flo_util::binary::BinDecodeError),
+  BinDecode(flo_util::binary::BinDecodeError),
   #[error("protobuf decode: {0}")]
   ProtoBufDecode(#[from] prost::DecodeError),
+  #[error("invalid trace: {0}")]
+  InvalidTrace(&'static str),
+}
+
+impl From<flo_util::binary::BinDecodeError> for Error {
+  fn from(err: flo_util::binary::BinDecodeError) -> Self {
+    if err.is_limit_exceeded() {
+      Error::LimitExceeded(err)
+    } else {
+      Error::BinDecode(err)
+    }
+  }
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,6 +1,7 @@
 pub mod error;
 pub mod net;
 pub mod protocol;
+pub mod trace;
 
 pub use protocol::*;
 pub mod actions;
@@ -4,17 +4,40 @@ use tokio_util::codec::{Decoder, Encoder};
 use crate::error::Error;
 use crate::protocol::packet::{Header, Packet};
 
+/// Largest payload a W3GS header can describe
+pub const MAX_PAYLOAD_LEN: usize = (std::u16::MAX - 4) as usize;
+
+/// Frames a byte stream into W3GS packets, payloads are split out of
+/// the read buffer without copying
 #[derive(Debug)]
 pub struct W3GSCodec {
   decode_state: DecoderState,
+  max_payload_len: usize,
 }
 
 impl W3GSCodec {
   pub fn new() -> Self {
+    Self::with_max_payload_len(MAX_PAYLOAD_LEN)
+  }
+
+  /// Packets with a larger payload are rejected with `Error::PayloadTooLarge`
+  /// in both directions
+  pub fn with_max_payload_len(max_payload_len: usize) -> Self {
     Self {
       decode_state: DecoderState::DecodingHeader,
+      max_payload_len: std::cmp::min(max_payload_len, MAX_PAYLOAD_LEN),
     }
   }
+
+  pub fn max_payload_len(&self) -> usize {
+    self.max_payload_len
+  }
+}
+
+impl Default for W3GSCodec {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Decoder for W3GSCodec {
@@ -24,16 +47,20 @@ impl Decoder for W3GSCodec {
   fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
     match self.decode_state {
       DecoderState::DecodingHeader => {
-        if src.remaining() >= Header::MIN_SIZE {
-          let header = Header::decode(src)?;
+        if let Partial::Complete(header) = Header::decode_partial(src)? {
           let payload_len = header.get_payload_len()?;
+
+          if payload_len > self.max_payload_len {
+            return Err(Error::PayloadTooLarge(payload_len));
+          }
+
           if src.remaining() >= payload_len {
             // payload received
             let packet = Packet::decode(header, src)?;
             Ok(Some(packet))
           } else {
             // wait payload
-            src.reserve(payload_len);
+            src.reserve(payload_len - src.remaining());
             self.decode_state = DecoderState::DecodingPayload {
               header: Some(header),
               payload_len,
@@ -62,6 +89,19 @@ impl Decoder for W3GSCodec {
       }
     }
   }
+
+  fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+    match self.decode(buf)? {
+      Some(packet) => Ok(Some(packet)),
+      None => {
+        if buf.is_empty() && matches!(self.decode_state, DecoderState::DecodingHeader) {
+          Ok(None)
+        } else {
+          Err(Error::StreamClosed)
+        }
+      }
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -77,7 +117,96 @@ impl Encoder<Packet> for W3GSCodec {
   type Error = Error;
 
   fn encode(&mut self, item: Packet, dst: &mut BytesMut) -> Result<(), Self::Error> {
+    if item.payload_len() > self.max_payload_len {
+      return Err(Error::PayloadTooLarge(item.payload_len()));
+    }
     item.encode(dst);
     Ok(())
   }
 }
+
+#[cfg(test)]
+fn test_packets() -> Vec<Packet> {
+  use crate::protocol::game::PlayerLoaded;
+  use crate::protocol::leave::LeaveAck;
+
+  vec![
+    Packet::simple(PlayerLoaded { player_id: 1 }).unwrap(),
+    Packet::simple(LeaveAck).unwrap(),
+    Packet::simple(PlayerLoaded { player_id: 2 }).unwrap(),
+  ]
+}
+
+#[test]
+fn test_decode_partial() {
+  let mut bytes = BytesMut::new();
+  for packet in test_packets() {
+    packet.encode(&mut bytes);
+  }
+
+  let mut codec = W3GSCodec::new();
+  let mut src = BytesMut::new();
+  let mut decoded = vec![];
+  for byte in bytes {
+    src.put_u8(byte);
+    if let Some(packet) = codec.decode(&mut src).unwrap() {
+      decoded.push(packet);
+    }
+  }
+
+  assert!(src.is_empty());
+  assert_eq!(decoded.len(), 3);
+  for (decoded, expected) in decoded.iter().zip(test_packets()) {
+    assert_eq!(decoded.type_id(), expected.type_id());
+    assert_eq!(decoded.payload, expected.payload);
+  }
+}
+
+#[test]
+fn test_decode_many() {
+  let mut src = BytesMut::new();
+  for packet in test_packets() {
+    packet.encode(&mut src);
+  }
+
+  let mut codec = W3GSCodec::new();
+  let mut count = 0;
+  while codec.decode(&mut src).unwrap().is_some() {
+    count += 1;
+  }
+  assert_eq!(count, 3);
+  assert!(codec.decode_eof(&mut src).unwrap().is_none());
+}
+
+#[test]
+fn test_decode_eof_partial() {
+  let mut src = BytesMut::new();
+  test_packets()[0].encode(&mut src);
+  src.truncate(src.len() - 1);
+
+  let mut codec = W3GSCodec::new();
+  assert!(matches!(
+    codec.decode_eof(&mut src),
+    Err(Error::StreamClosed)
+  ));
+}
+
+#[test]
+fn test_max_payload_len() {
+  use crate::protocol::chat::ChatToHost;
+
+  let packet = Packet::simple(ChatToHost::lobby(1, &[2], "hello")).unwrap();
+  let mut codec = W3GSCodec::with_max_payload_len(4);
+
+  let mut src = BytesMut::new();
+  packet.encode(&mut src);
+  assert!(matches!(
+    codec.decode(&mut src),
+    Err(Error::PayloadTooLarge(_))
+  ));
+
+  assert!(matches!(
+    codec.encode(packet, &mut BytesMut::new()),
+    Err(Error::PayloadTooLarge(_))
+  ));
+}
@@ -4,15 +4,15 @@ use futures::{ready, StreamExt};
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::net::{TcpListener, TcpSocket, TcpStream, ToSocketAddrs};
 use tokio_stream::Stream;
 use tokio_util::codec::Framed;
 
 use crate::error::*;
 use crate::protocol::packet::{Packet, PacketPayload, PacketPayloadDecode};
 
-mod codec;
-use self::codec::W3GSCodec;
+pub mod codec;
+pub use self::codec::W3GSCodec;
 
 #[derive(Debug)]
 pub struct W3GSListener {
@@ -22,7 +22,17 @@ pub struct W3GSListener {
 
 impl W3GSListener {
   pub async fn bind() -> Result<Self, Error> {
-    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+    Self::bind_port(0).await
+  }
+
+  /// Binds `port`, or a random port if `port` is 0
+  pub async fn bind_port(port: u16) -> Result<Self, Error> {
+    let socket = TcpSocket::new_v4()?;
+    // on Windows SO_REUSEADDR allows binding a port that is still listening
+    #[cfg(not(windows))]
+    socket.set_reuseaddr(true)?;
+    socket.bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port).into())?;
+    let listener = socket.listen(1024)?;
     let local_addr = listener.local_addr()?;
     Ok(W3GSListener {
       listener,
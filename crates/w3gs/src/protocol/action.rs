@@ -29,6 +29,10 @@ impl PacketPayloadEncode for OutgoingAction {
     buf.put_u32_le(self.crc32);
     buf.put(self.data.clone());
   }
+
+  fn encode_len(&self) -> Option<usize> {
+    Some(size_of::<u32>() + self.data.len())
+  }
 }
 
 impl PacketPayloadDecode for OutgoingAction {
@@ -83,6 +87,10 @@ impl PacketPayloadEncode for IncomingAction {
   fn encode(&self, buf: &mut BytesMut) {
     self.0.encode(buf)
   }
+
+  fn encode_len(&self) -> Option<usize> {
+    self.0.encode_len()
+  }
 }
 
 impl PacketPayloadDecode for IncomingAction {
@@ -102,6 +110,10 @@ impl PacketPayloadEncode for IncomingAction2 {
   fn encode(&self, buf: &mut BytesMut) {
     self.0.encode(buf)
   }
+
+  fn encode_len(&self) -> Option<usize> {
+    self.0.encode_len()
+  }
 }
 
 impl PacketPayloadDecode for IncomingAction2 {
@@ -225,6 +237,15 @@ impl PacketPayloadEncode for TimeSlot {
       buf.unsplit(actions_buf);
     }
   }
+
+  fn encode_len(&self) -> Option<usize> {
+    if self.actions.is_empty() {
+      Some(size_of::<u16>())
+    } else {
+      let actions_len: usize = self.actions.iter().map(PlayerAction::byte_len).sum();
+      Some(size_of::<u16>() * 2 + actions_len)
+    }
+  }
 }
 
 impl PacketPayloadDecode for TimeSlot {
@@ -261,6 +282,7 @@ impl PacketPayloadDecode for TimeSlot {
       return Err(Error::InvalidChecksum);
     }
 
+    // action data are sliced out of `buf` without copying
     let mut actions = vec![];
     while buf.has_remaining() {
       actions.push(PlayerAction::decode(buf)?)
@@ -287,7 +309,7 @@ impl PlayerAction {
   fn encode(&self, buf: &mut BytesMut) {
     buf.put_u8(self.player_id);
     buf.put_u16_le(self.data.len() as u16);
-    buf.put(self.data.as_ref());
+    buf.put_slice(self.data.as_ref());
   }
 
   fn decode(buf: &mut Bytes) -> Result<Self> {
@@ -448,3 +470,30 @@ fn test_incoming_action2_split_chunk_30() {
     30_usize
   )
 }
+
+#[test]
+fn test_time_slot_decode_zero_copy() {
+  let time_slot = TimeSlot {
+    time_increment_ms: 100,
+    actions: vec![
+      PlayerAction {
+        player_id: 1,
+        data: Bytes::from((0..100).collect::<Vec<u8>>()),
+      },
+      PlayerAction {
+        player_id: 2,
+        data: Bytes::from((0..50).collect::<Vec<u8>>()),
+      },
+    ],
+  };
+
+  let bytes = time_slot.encode_to_bytes();
+  assert_eq!(time_slot.encode_len(), Some(bytes.len()));
+
+  let range = bytes.as_ptr_range();
+  let decoded = TimeSlot::decode(&mut bytes.clone()).unwrap();
+  assert_eq!(decoded, time_slot);
+  for action in &decoded.actions {
+    assert!(range.contains(&action.data.as_ptr()));
+  }
+}
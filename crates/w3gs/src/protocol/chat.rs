@@ -1,11 +1,12 @@
 use flo_util::binary::*;
 use flo_util::{BinDecode, BinEncode};
 
-use crate::protocol::constants::{MessageType, PacketTypeId};
+use crate::protocol::constants::PacketTypeId;
 use crate::protocol::packet::PacketPayload;
 
 #[derive(Debug, BinDecode, BinEncode, PartialEq)]
 pub struct ChatToHost {
+  #[bin(max = 24)]
   pub to_players_len: u8,
   #[bin(repeat = "to_players_len")]
   pub to_players: Vec<u8>,
@@ -55,76 +56,26 @@ impl PacketPayload for ChatToHost {
   const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::ChatToHost;
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, BinDecode, BinEncode)]
+#[bin(enum_repr(u8))]
 pub enum ChatMessage {
+  #[bin(value = 0x10)]
   Chat(CString),
+  #[bin(value = 0x11)]
   TeamChange(u8),
+  #[bin(value = 0x12)]
   ColorChange(u8),
+  #[bin(value = 0x13)]
   RaceChange(u8),
+  #[bin(value = 0x14)]
   HandicapChange(u8),
+  #[bin(value = 0x20)]
   Scoped {
     scope: MessageScope,
     message: CString,
   },
 }
 
-impl BinDecode for ChatMessage {
-  const MIN_SIZE: usize = 2;
-  const FIXED_SIZE: bool = false;
-
-  fn decode<T: Buf>(buf: &mut T) -> Result<Self, BinDecodeError> {
-    buf.check_size(Self::MIN_SIZE)?;
-    match MessageType::decode(buf)? {
-      MessageType::Chat => Ok(ChatMessage::Chat(CString::decode(buf)?)),
-      MessageType::TeamChange => Ok(ChatMessage::TeamChange(buf.get_u8())),
-      MessageType::ColorChange => Ok(ChatMessage::ColorChange(buf.get_u8())),
-      MessageType::RaceChange => Ok(ChatMessage::RaceChange(buf.get_u8())),
-      MessageType::HandicapChange => Ok(ChatMessage::HandicapChange(buf.get_u8())),
-      MessageType::Scoped => {
-        let scope = MessageScope::decode(buf)?;
-        let message = CString::decode(buf)?;
-        Ok(ChatMessage::Scoped { scope, message })
-      }
-      MessageType::UnknownValue(v) => Err(BinDecodeError::failure(format!(
-        "invalid message type: {}",
-        v
-      ))),
-    }
-  }
-}
-
-impl BinEncode for ChatMessage {
-  fn encode<T: BufMut>(&self, buf: &mut T) {
-    match *self {
-      ChatMessage::Chat(ref msg) => {
-        MessageType::Chat.encode(buf);
-        msg.encode(buf);
-      }
-      ChatMessage::TeamChange(v) => {
-        MessageType::TeamChange.encode(buf);
-        buf.put_u8(v)
-      }
-      ChatMessage::ColorChange(v) => {
-        MessageType::ColorChange.encode(buf);
-        buf.put_u8(v)
-      }
-      ChatMessage::RaceChange(v) => {
-        MessageType::RaceChange.encode(buf);
-        buf.put_u8(v)
-      }
-      ChatMessage::HandicapChange(v) => {
-        MessageType::HandicapChange.encode(buf);
-        buf.put_u8(v)
-      }
-      ChatMessage::Scoped { scope, ref message } => {
-        MessageType::Scoped.encode(buf);
-        scope.encode(buf);
-        message.encode(buf);
-      }
-    }
-  }
-}
-
 impl ChatMessage {
   pub fn encode_len(&self) -> usize {
     1 + match *self {
@@ -224,3 +175,20 @@ impl ChatFromOthers {
 impl PacketPayload for ChatFromOthers {
   const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::ChatFromOthers;
 }
+
+#[test]
+fn test_chat_to_host() {
+  crate::packet::test_simple_payload_bytes(
+    &[1, 2, 1, 0x10, b'h', b'i', 0],
+    &ChatToHost::lobby(1, &[2], "hi"),
+  );
+  crate::packet::test_simple_payload_bytes(
+    &[1, 2, 1, 0x11, 3],
+    &ChatToHost {
+      to_players_len: 1,
+      to_players: vec![2],
+      from_player: 1,
+      message: ChatMessage::TeamChange(3),
+    },
+  );
+}
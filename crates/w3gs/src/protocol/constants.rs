@@ -247,6 +247,9 @@ bitflags! {
       const OBS_ON_DEFEAT = 0x200000;
       const OBS_NONE      = 0x400000;
       const OBS_MASK      = 0x700000;
+
+      // Reforged: only clients running classic graphics can join
+      const CLASSIC_GRAPHICS = 0x800000;
   }
 }
 
@@ -276,6 +279,9 @@ bitflags! {
     const SHARED_CONTROL = 0x01000000;
     const RANDOM_HERO    = 0x02000000;
     const RANDOM_RACE    = 0x04000000;
+
+    // Reforged, mirrors `GameFlags::CLASSIC_GRAPHICS`
+    const CLASSIC_GRAPHICS = 0x08000000;
   }
 }
 
@@ -0,0 +1,189 @@
+//! LAN game discovery, exchanged over UDP port 6112
+
+use flo_util::binary::*;
+use flo_util::dword_string::DwordString;
+use flo_util::{BinDecode, BinEncode};
+
+use crate::protocol::constants::PacketTypeId;
+use crate::protocol::game::GameSettings;
+use crate::protocol::packet::PacketPayload;
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct SearchGame {
+  pub product: DwordString,
+  pub version: u32,
+  #[bin(eq = 0)]
+  _unknown_1: u32,
+}
+
+impl SearchGame {
+  pub fn new(product: DwordString, version: u32) -> Self {
+    Self {
+      product,
+      version,
+      _unknown_1: 0,
+    }
+  }
+}
+
+impl PacketPayload for SearchGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::SearchGame;
+}
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct GameInfo {
+  pub product: DwordString,
+  pub version: u32,
+  pub host_counter: u32,
+  pub entry_key: u32,
+  pub game_name: CString,
+  pub password: CString,
+  pub game_settings: GameSettings,
+  pub slots_total: u32,
+  pub game_flags: u32,
+  #[bin(eq = 1)]
+  _unknown_1: u32,
+  pub slots_available: u32,
+  pub uptime_secs: u32,
+  pub port: u16,
+}
+
+impl GameInfo {
+  pub fn new(
+    product: DwordString,
+    version: u32,
+    host_counter: u32,
+    entry_key: u32,
+    game_name: CString,
+    game_settings: GameSettings,
+    slots_total: u32,
+    game_flags: u32,
+    slots_available: u32,
+    uptime_secs: u32,
+    port: u16,
+  ) -> Self {
+    Self {
+      product,
+      version,
+      host_counter,
+      entry_key,
+      game_name,
+      password: CString::default(),
+      game_settings,
+      slots_total,
+      game_flags,
+      _unknown_1: 1,
+      slots_available,
+      uptime_secs,
+      port,
+    }
+  }
+}
+
+impl PacketPayload for GameInfo {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::GameInfo;
+}
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct CreateGame {
+  pub product: DwordString,
+  pub version: u32,
+  pub host_counter: u32,
+}
+
+impl PacketPayload for CreateGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::CreateGame;
+}
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct RefreshGame {
+  pub host_counter: u32,
+  pub players_in_game: u32,
+  pub slots_total: u32,
+}
+
+impl PacketPayload for RefreshGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::RefreshGame;
+}
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct DecreateGame {
+  pub host_counter: u32,
+}
+
+impl PacketPayload for DecreateGame {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::DecreateGame;
+}
+
+#[test]
+fn test_search_game() {
+  crate::packet::test_simple_payload_bytes(
+    &[80, 88, 51, 87, 26, 0, 0, 0, 0, 0, 0, 0],
+    &SearchGame::new(DwordString::new(b"W3XP"), 26),
+  )
+}
+
+#[test]
+fn test_game_info() {
+  use crate::protocol::constants::GameSettingFlags;
+  use crate::protocol::game::GameSettingsMap;
+  use crate::protocol::packet::Packet;
+
+  let info = || {
+    GameInfo::new(
+      DwordString::new(b"W3XP"),
+      26,
+      1,
+      0x12345678,
+      CString::new("flo").unwrap(),
+      GameSettings::new(
+        GameSettingFlags::default(),
+        GameSettingsMap {
+          path: "Maps\\(2)bootybay.w3m".to_string(),
+          width: 84,
+          height: 84,
+          sha1: [1; 20],
+          checksum: 0xDEADBEEF,
+        },
+      ),
+      24,
+      0x09,
+      12,
+      60,
+      6112,
+    )
+  };
+
+  let packet = Packet::simple(info()).unwrap();
+  let decoded: GameInfo = packet.decode_simple().unwrap();
+  assert_eq!(decoded, info());
+}
+
+#[test]
+fn test_create_game() {
+  crate::packet::test_simple_payload_bytes(
+    &[80, 88, 51, 87, 26, 0, 0, 0, 1, 0, 0, 0],
+    &CreateGame {
+      product: DwordString::new(b"W3XP"),
+      version: 26,
+      host_counter: 1,
+    },
+  )
+}
+
+#[test]
+fn test_refresh_game() {
+  crate::packet::test_simple_payload_bytes(
+    &[1, 0, 0, 0, 2, 0, 0, 0, 12, 0, 0, 0],
+    &RefreshGame {
+      host_counter: 1,
+      players_in_game: 2,
+      slots_total: 12,
+    },
+  )
+}
+
+#[test]
+fn test_decreate_game() {
+  crate::packet::test_simple_payload_bytes(&[1, 0, 0, 0], &DecreateGame { host_counter: 1 })
+}
@@ -92,6 +92,10 @@ impl BinDecode for GameSettings {
     let cstr = CString::decode(buf)?;
     let data = flo_util::stat_string::decode(cstr.as_bytes());
 
+    if data.len() < min_len {
+      return Err(BinDecodeError::incomplete().context("GameSettings"));
+    }
+
     let mut buf = &data[..];
 
     let game_setting_flags = buf.get_u32_le();
@@ -166,6 +170,16 @@ impl PacketPayload for PlayerLoaded {
   const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::PlayerLoaded;
 }
 
+/// Sent by the host when all players have left and the game is about to close
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct GameOver {
+  pub player_id: u8,
+}
+
+impl PacketPayload for GameOver {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::GameOver;
+}
+
 #[test]
 fn test_count_down_start() {
   crate::packet::test_simple_payload_type("count_down_start.bin", &CountDownStart)
@@ -185,3 +199,8 @@ fn test_game_loaded_self() {
 fn test_player_loaded() {
   crate::packet::test_simple_payload_type("player_loaded.bin", &PlayerLoaded { player_id: 2 })
 }
+
+#[test]
+fn test_game_over() {
+  crate::packet::test_simple_payload_bytes(&[1], &GameOver { player_id: 1 })
+}
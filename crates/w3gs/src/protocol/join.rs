@@ -14,8 +14,7 @@ pub struct ReqJoin {
   pub listen_port: u16,
   pub join_counter: u32,
   pub player_name: CString,
-  _num_unknown_2: u8,
-  #[bin(repeat = "_num_unknown_2")]
+  #[bin(len_prefix = "u8")]
   _unknown_2: Vec<u8>,
   pub internal_addr: SockAddr,
 }
@@ -32,7 +31,6 @@ impl ReqJoin {
       listen_port: 0,
       join_counter: 1,
       player_name: player_name.into_c_string_lossy(),
-      _num_unknown_2: 2,
       _unknown_2: vec![0, 0],
       internal_addr: SockAddr::new_null(),
     }
@@ -84,7 +82,6 @@ fn test_req_join() {
       listen_port: 16000,
       join_counter: 1,
       player_name: CString::new("1111").unwrap(),
-      _num_unknown_2: 2,
       _unknown_2: vec![0, 0],
       internal_addr: SockAddr::new_ipv4([192, 168, 1, 6], 32830),
     },
@@ -5,17 +5,13 @@ use crate::protocol::packet::PacketPayload;
 
 #[derive(Debug, BinDecode, BinEncode, PartialEq)]
 pub struct StartLag {
-  _num_of_players: u8,
-  #[bin(repeat = "_num_of_players")]
+  #[bin(len_prefix = "u8", max = 24)]
   players: Vec<LagPlayer>,
 }
 
 impl StartLag {
   pub fn new(players: Vec<LagPlayer>) -> Self {
-    StartLag {
-      _num_of_players: players.len() as u8,
-      players,
-    }
+    StartLag { players }
   }
 
   pub fn players(&self) -> &[LagPlayer] {
@@ -39,3 +35,16 @@ pub struct StopLag(pub LagPlayer);
 impl PacketPayload for StopLag {
   const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::StopLag;
 }
+
+/// Sent by a client voting to drop the lagging players
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct DropReq;
+
+impl PacketPayload for DropReq {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::DropReq;
+}
+
+#[test]
+fn test_drop_req() {
+  crate::packet::test_simple_payload_bytes(&[], &DropReq)
+}
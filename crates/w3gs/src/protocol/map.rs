@@ -1,9 +1,10 @@
 use flo_util::binary::*;
 use flo_util::{BinDecode, BinEncode};
 
+use crate::error::*;
 use crate::protocol::constants::PacketTypeId;
 use crate::protocol::game::GameSettings;
-use crate::protocol::packet::PacketPayload;
+use crate::protocol::packet::{PacketPayload, PacketPayloadDecode, PacketPayloadEncode};
 
 #[derive(Debug, BinDecode, BinEncode, PartialEq)]
 pub struct MapCheck {
@@ -55,6 +56,145 @@ impl PacketPayload for MapSize {
   const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::MapSize;
 }
 
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct StartDownload {
+  #[bin(eq = 0x01)]
+  _unknown_1: u32,
+  pub player_id: u8,
+}
+
+impl StartDownload {
+  pub fn new(player_id: u8) -> Self {
+    Self {
+      _unknown_1: 1,
+      player_id,
+    }
+  }
+}
+
+impl PacketPayload for StartDownload {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::StartDownload;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MapPart {
+  pub to_player_id: u8,
+  pub from_player_id: u8,
+  pub chunk_position: u32,
+  pub crc32: u32,
+  pub data: Bytes,
+}
+
+impl MapPart {
+  pub fn new(to_player_id: u8, from_player_id: u8, chunk_position: u32, bytes: &[u8]) -> Self {
+    let mut crc32 = crc32fast::Hasher::new();
+    crc32.update(bytes);
+    MapPart {
+      to_player_id,
+      from_player_id,
+      chunk_position,
+      crc32: crc32.finalize(),
+      data: Bytes::copy_from_slice(bytes),
+    }
+  }
+}
+
+impl PacketPayloadEncode for MapPart {
+  fn encode(&self, buf: &mut BytesMut) {
+    buf.reserve(2 + size_of::<u32>() * 3 + self.data.len());
+    buf.put_u8(self.to_player_id);
+    buf.put_u8(self.from_player_id);
+    buf.put_u32_le(1);
+    buf.put_u32_le(self.chunk_position);
+    buf.put_u32_le(self.crc32);
+    buf.put(self.data.clone());
+  }
+
+  fn encode_len(&self) -> Option<usize> {
+    Some(2 + size_of::<u32>() * 3 + self.data.len())
+  }
+}
+
+impl PacketPayloadDecode for MapPart {
+  fn decode(buf: &mut Bytes) -> Result<Self> {
+    if buf.remaining() < 2 + size_of::<u32>() * 3 {
+      return Err(Error::InvalidPayloadLength(buf.remaining()));
+    }
+
+    let to_player_id = u8::decode(buf)?;
+    let from_player_id = u8::decode(buf)?;
+    let unknown = u32::decode(buf)?;
+    if unknown != 1 {
+      return Err(
+        BinDecodeError::failure(format!("unexpected value: {}", unknown))
+          .context("MapPart")
+          .into(),
+      );
+    }
+    let chunk_position = u32::decode(buf)?;
+    let checksum = u32::decode(buf)?;
+    let data = buf.split_to(buf.remaining());
+
+    let mut crc32 = crc32fast::Hasher::new();
+    crc32.update(data.as_ref());
+    let crc32 = crc32.finalize();
+
+    if checksum != crc32 {
+      return Err(Error::InvalidChecksum);
+    }
+
+    Ok(Self {
+      to_player_id,
+      from_player_id,
+      chunk_position,
+      crc32,
+      data,
+    })
+  }
+}
+
+impl PacketPayload for MapPart {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::MapPart;
+}
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct MapPartOK {
+  pub from_player_id: u8,
+  pub to_player_id: u8,
+  #[bin(eq = 0x01)]
+  _unknown_1: u32,
+  pub chunk_position_next: u32,
+}
+
+impl MapPartOK {
+  pub fn new(from_player_id: u8, to_player_id: u8, chunk_position_next: u32) -> Self {
+    Self {
+      from_player_id,
+      to_player_id,
+      _unknown_1: 1,
+      chunk_position_next,
+    }
+  }
+}
+
+impl PacketPayload for MapPartOK {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::MapPartOK;
+}
+
+/// Sent by the downloading client when a chunk failed the checksum check
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct MapPartError {
+  pub from_player_id: u8,
+  pub to_player_id: u8,
+  #[bin(eq = 0x01)]
+  _unknown_1: u32,
+  pub chunk_position: u32,
+}
+
+impl PacketPayload for MapPartError {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::MapPartError;
+}
+
 #[test]
 fn test_map_check() {
   crate::packet::test_simple_payload_type(
@@ -83,3 +223,39 @@ fn test_map_size() {
     },
   )
 }
+
+#[test]
+fn test_start_download() {
+  crate::packet::test_simple_payload_bytes(&[1, 0, 0, 0, 2], &StartDownload::new(2))
+}
+
+#[test]
+fn test_map_part() {
+  crate::packet::test_payload_bytes(
+    &[
+      2, 1, 1, 0, 0, 0, 0, 5, 0, 0, 226, 54, 94, 167, 102, 108, 111,
+    ],
+    &MapPart::new(2, 1, 1280, b"flo"),
+  )
+}
+
+#[test]
+fn test_map_part_ok() {
+  crate::packet::test_simple_payload_bytes(
+    &[1, 2, 1, 0, 0, 0, 0, 5, 0, 0],
+    &MapPartOK::new(1, 2, 1280),
+  )
+}
+
+#[test]
+fn test_map_part_error() {
+  crate::packet::test_simple_payload_bytes(
+    &[1, 2, 1, 0, 0, 0, 0, 5, 0, 0],
+    &MapPartError {
+      from_player_id: 1,
+      to_player_id: 2,
+      _unknown_1: 1,
+      chunk_position: 1280,
+    },
+  )
+}
@@ -2,12 +2,14 @@ pub mod action;
 pub mod chat;
 pub mod constants;
 pub mod desync;
+pub mod discovery;
 pub mod game;
 pub mod join;
 pub mod lag;
 pub mod leave;
 pub mod map;
 pub mod packet;
+pub mod peer;
 pub mod ping;
 pub mod player;
 pub mod slot;
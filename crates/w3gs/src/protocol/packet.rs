@@ -96,6 +96,7 @@ impl Packet {
   where
     T: PacketPayload + BinEncode + std::fmt::Debug,
   {
+    payload.check_limits()?;
     Self::with_payload(SimplePayload(payload))
   }
 
@@ -217,6 +218,7 @@ impl Header {
 #[derive(Debug, BinDecode, BinEncode, PartialEq)]
 pub struct ProtoBufPayload {
   pub type_id: ProtoBufMessageTypeId,
+  #[bin(max = 65531)]
   pub len: u32,
   #[bin(repeat = "len")]
   pub data: Vec<u8>,
@@ -382,6 +384,46 @@ pub(crate) fn test_protobuf_payload_type<
   assert_eq!(new.encode_to_bytes(), packet.payload);
 }
 
+/// Same as `test_simple_payload_type` but takes the payload bytes directly,
+/// for packet types we don't have captured samples of
+#[cfg(test)]
+pub(crate) fn test_simple_payload_bytes<T>(payload: &[u8], expecting: &T)
+where
+  T: PacketPayload + BinEncode + BinDecode + std::cmp::PartialEq + std::fmt::Debug,
+{
+  let packet = Packet {
+    header: Header::new(T::PACKET_TYPE_ID, (payload.len() + 4) as u16),
+    payload: Bytes::copy_from_slice(payload),
+  };
+
+  let decoded: T = packet.decode_simple().unwrap();
+  assert_eq!(&decoded, expecting);
+
+  let encoded = Packet::simple(decoded).unwrap();
+  assert_eq!(encoded.header.len, packet.header.len);
+  assert_eq!(encoded.payload, packet.payload);
+}
+
+/// Same as `test_payload_type` but takes the payload bytes directly
+#[cfg(test)]
+pub(crate) fn test_payload_bytes<T>(payload: &[u8], expecting: &T)
+where
+  T: PacketPayload
+    + PacketPayloadEncode
+    + PacketPayloadDecode
+    + std::cmp::PartialEq
+    + std::fmt::Debug,
+{
+  let packet = Packet {
+    header: Header::new(T::PACKET_TYPE_ID, (payload.len() + 4) as u16),
+    payload: Bytes::copy_from_slice(payload),
+  };
+
+  let decoded: T = packet.decode_payload().unwrap();
+  assert_eq!(&decoded, expecting);
+  assert_eq!(decoded.encode_to_bytes(), packet.payload);
+}
+
 #[test]
 fn test_packet() {
   use flo_util::binary::*;
@@ -0,0 +1,102 @@
+//! Peer-to-peer packets exchanged directly between clients.
+//!
+//! Flo clients only talk to the node, so these are decoded to be logged and dropped
+//! instead of being forwarded. The fields of unknown meaning are kept private, they are
+//! only there to decode the full payload.
+//!
+//! Tournament packets are out of scope: their layout isn't known without captures of them,
+//! they stay `PacketTypeId::UnknownValue`.
+
+use flo_util::{BinDecode, BinEncode};
+
+use crate::protocol::constants::PacketTypeId;
+use crate::protocol::packet::PacketPayload;
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct PingFromOthers {
+  pub signature: u32,
+  pub peer_connection_flags: u32,
+  _unknown_1: u32,
+}
+
+impl PacketPayload for PingFromOthers {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::PingFromOthers;
+}
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct PongToOthers {
+  pub signature: u32,
+}
+
+impl PacketPayload for PongToOthers {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::PongToOthers;
+}
+
+/// Layout is not documented anywhere, the field names are best guesses
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct ClientInfo {
+  pub peer_key: u32,
+  _unknown_1: u32,
+  pub player_id: u8,
+  _unknown_2: u8,
+  pub peer_connection_flags: u32,
+}
+
+impl PacketPayload for ClientInfo {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::ClientInfo;
+}
+
+#[derive(Debug, BinDecode, BinEncode, PartialEq)]
+pub struct PeerSet {
+  pub peer_connection_flags: u16,
+}
+
+impl PacketPayload for PeerSet {
+  const PACKET_TYPE_ID: PacketTypeId = PacketTypeId::PeerSet;
+}
+
+#[test]
+fn test_ping_from_others() {
+  crate::packet::test_simple_payload_bytes(
+    &[0x78, 0x56, 0x34, 0x12, 3, 0, 0, 0, 0, 0, 0, 0],
+    &PingFromOthers {
+      signature: 0x12345678,
+      peer_connection_flags: 3,
+      _unknown_1: 0,
+    },
+  )
+}
+
+#[test]
+fn test_pong_to_others() {
+  crate::packet::test_simple_payload_bytes(
+    &[0x78, 0x56, 0x34, 0x12],
+    &PongToOthers {
+      signature: 0x12345678,
+    },
+  )
+}
+
+#[test]
+fn test_client_info() {
+  crate::packet::test_simple_payload_bytes(
+    &[0x78, 0x56, 0x34, 0x12, 0, 0, 0, 0, 2, 0, 3, 0, 0, 0],
+    &ClientInfo {
+      peer_key: 0x12345678,
+      _unknown_1: 0,
+      player_id: 2,
+      _unknown_2: 0,
+      peer_connection_flags: 3,
+    },
+  )
+}
+
+#[test]
+fn test_peer_set() {
+  crate::packet::test_simple_payload_bytes(
+    &[3, 0],
+    &PeerSet {
+      peer_connection_flags: 3,
+    },
+  )
+}
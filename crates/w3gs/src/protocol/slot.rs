@@ -9,6 +9,7 @@ use crate::protocol::packet::PacketPayload;
 pub struct SlotInfo {
   // 7 + sizeof(SlotData) x num_slots
   pub(crate) _length_of_slot_data: u16,
+  #[bin(max = 24)]
   pub(crate) _num_slots: u8,
   #[bin(repeat = "_num_slots")]
   pub(crate) slots: Vec<SlotData>,
@@ -272,3 +273,10 @@ fn test_slot_info_3() {
     },
   );
 }
+
+#[test]
+fn test_slot_info_too_many_slots() {
+  let mut bytes = &[0_u8, 0, 25, 0, 0, 0, 0, 0, 0, 0][..];
+  let err = SlotInfo::decode(&mut bytes).unwrap_err();
+  assert!(err.is_limit_exceeded());
+}
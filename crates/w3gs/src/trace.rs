@@ -0,0 +1,264 @@
+//! Trace files of the W3GS packets exchanged with the players of a game.
+//!
+//! A trace is a `FLOT` magic and a version byte, followed by one record per packet:
+//! `elapsed_ms: u32`, `direction: u8`, `player_id: i32` (all little endian) and the packet
+//! as sent on the wire, header included.
+
+use std::fmt::Debug;
+use std::io::{self, Read, Write};
+use std::time::Instant;
+
+use flo_util::binary::*;
+
+use crate::error::{Error, Result};
+use crate::protocol::action::{IncomingAction, IncomingAction2, OutgoingAction, OutgoingKeepAlive};
+use crate::protocol::chat::{ChatFromHost, ChatFromOthers, ChatToHost};
+use crate::protocol::constants::PacketTypeId;
+use crate::protocol::desync::Desync;
+use crate::protocol::discovery::{CreateGame, DecreateGame, GameInfo, RefreshGame, SearchGame};
+use crate::protocol::game::{CountDownEnd, CountDownStart, GameLoadedSelf, GameOver, PlayerLoaded};
+use crate::protocol::join::{RejectJoin, ReqJoin, SlotInfoJoin};
+use crate::protocol::lag::{DropReq, StartLag, StopLag};
+use crate::protocol::leave::{LeaveAck, LeaveReq, PlayerKicked, PlayerLeft};
+use crate::protocol::map::{MapCheck, MapPart, MapPartError, MapPartOK, MapSize, StartDownload};
+use crate::protocol::packet::{Header, Packet, ProtoBufPayload};
+use crate::protocol::peer::{ClientInfo, PeerSet, PingFromOthers, PongToOthers};
+use crate::protocol::ping::{PingFromHost, PongToHost};
+use crate::protocol::player::PlayerInfo;
+use crate::protocol::slot::SlotInfo;
+
+const MAGIC: &[u8; 4] = b"FLOT";
+const VERSION: u8 = 1;
+const RECORD_HEADER_LEN: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+  /// Sent by the player to the host
+  Incoming,
+  /// Sent by the host to the player
+  Outgoing,
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceRecord {
+  /// Since the trace was started
+  pub elapsed_ms: u32,
+  pub direction: Direction,
+  pub player_id: i32,
+  pub packet: Packet,
+}
+
+pub struct TraceWriter<W> {
+  inner: W,
+  started_at: Instant,
+  buf: BytesMut,
+}
+
+impl<W: Write> TraceWriter<W> {
+  pub fn new(mut inner: W) -> io::Result<Self> {
+    inner.write_all(MAGIC)?;
+    inner.write_all(&[VERSION])?;
+    Ok(Self {
+      inner,
+      started_at: Instant::now(),
+      buf: BytesMut::new(),
+    })
+  }
+
+  pub fn write(&mut self, direction: Direction, player_id: i32, packet: &Packet) -> io::Result<()> {
+    let elapsed_ms = self.started_at.elapsed().as_millis() as u32;
+    self.write_record(&TraceRecord {
+      elapsed_ms,
+      direction,
+      player_id,
+      packet: packet.clone(),
+    })
+  }
+
+  pub fn write_record(&mut self, record: &TraceRecord) -> io::Result<()> {
+    self.buf.clear();
+    self.buf.put_u32_le(record.elapsed_ms);
+    self.buf.put_u8(match record.direction {
+      Direction::Incoming => 0,
+      Direction::Outgoing => 1,
+    });
+    self.buf.put_i32_le(record.player_id);
+    record.packet.encode(&mut self.buf);
+    self.inner.write_all(&self.buf)
+  }
+
+  pub fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+pub struct TraceReader<R> {
+  inner: R,
+}
+
+impl<R: Read> TraceReader<R> {
+  pub fn new(mut inner: R) -> Result<Self> {
+    let mut header = [0; 5];
+    inner.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+      return Err(Error::InvalidTrace("not a trace file"));
+    }
+    if header[4] != VERSION {
+      return Err(Error::InvalidTrace("unsupported version"));
+    }
+    Ok(Self { inner })
+  }
+
+  /// Returns `None` at the end of the trace
+  pub fn next_record(&mut self) -> Result<Option<TraceRecord>> {
+    let mut record_header = [0; RECORD_HEADER_LEN];
+    match self.inner.read_exact(&mut record_header) {
+      Ok(()) => {}
+      Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+      Err(err) => return Err(err.into()),
+    }
+    let mut buf = &record_header[..];
+    let elapsed_ms = buf.get_u32_le();
+    let direction = match buf.get_u8() {
+      0 => Direction::Incoming,
+      1 => Direction::Outgoing,
+      _ => return Err(Error::InvalidTrace("invalid direction")),
+    };
+    let player_id = buf.get_i32_le();
+
+    let mut packet_header = [0; 4];
+    self.inner.read_exact(&mut packet_header)?;
+    let header = Header::decode(&mut &packet_header[..])?;
+    let mut payload = vec![0; header.get_payload_len()?];
+    self.inner.read_exact(&mut payload)?;
+
+    Ok(Some(TraceRecord {
+      elapsed_ms,
+      direction,
+      player_id,
+      packet: Packet {
+        header,
+        payload: payload.into(),
+      },
+    }))
+  }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+  type Item = Result<TraceRecord>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_record().transpose()
+  }
+}
+
+/// Decodes the payload with the type of the packet, `None` for types without a payload type
+pub fn decode_payload(packet: &Packet) -> Result<Option<Box<dyn Debug>>> {
+  fn simple<T>(packet: &Packet) -> Result<Option<Box<dyn Debug>>>
+  where
+    T: crate::protocol::packet::PacketPayload + BinDecode + Debug + 'static,
+  {
+    Ok(Some(Box::new(packet.decode_simple::<T>()?)))
+  }
+
+  fn payload<T>(packet: &Packet) -> Result<Option<Box<dyn Debug>>>
+  where
+    T: crate::protocol::packet::PacketPayload
+      + crate::protocol::packet::PacketPayloadDecode
+      + Debug
+      + 'static,
+  {
+    Ok(Some(Box::new(packet.decode_payload::<T>()?)))
+  }
+
+  match packet.type_id() {
+    PacketTypeId::PingFromHost => simple::<PingFromHost>(packet),
+    PacketTypeId::SlotInfoJoin => simple::<SlotInfoJoin>(packet),
+    PacketTypeId::RejectJoin => simple::<RejectJoin>(packet),
+    PacketTypeId::PlayerInfo => simple::<PlayerInfo>(packet),
+    PacketTypeId::PlayerLeft => simple::<PlayerLeft>(packet),
+    PacketTypeId::PlayerLoaded => simple::<PlayerLoaded>(packet),
+    PacketTypeId::SlotInfo => simple::<SlotInfo>(packet),
+    PacketTypeId::CountDownStart => simple::<CountDownStart>(packet),
+    PacketTypeId::CountDownEnd => simple::<CountDownEnd>(packet),
+    PacketTypeId::IncomingAction => payload::<IncomingAction>(packet),
+    PacketTypeId::Desync => simple::<Desync>(packet),
+    PacketTypeId::ChatFromHost => simple::<ChatFromHost>(packet),
+    PacketTypeId::StartLag => simple::<StartLag>(packet),
+    PacketTypeId::StopLag => simple::<StopLag>(packet),
+    PacketTypeId::GameOver => simple::<GameOver>(packet),
+    PacketTypeId::PlayerKicked => simple::<PlayerKicked>(packet),
+    PacketTypeId::LeaveAck => simple::<LeaveAck>(packet),
+    PacketTypeId::ReqJoin => simple::<ReqJoin>(packet),
+    PacketTypeId::LeaveReq => simple::<LeaveReq>(packet),
+    PacketTypeId::GameLoadedSelf => simple::<GameLoadedSelf>(packet),
+    PacketTypeId::OutgoingAction => payload::<OutgoingAction>(packet),
+    PacketTypeId::OutgoingKeepAlive => simple::<OutgoingKeepAlive>(packet),
+    PacketTypeId::ChatToHost => simple::<ChatToHost>(packet),
+    PacketTypeId::DropReq => simple::<DropReq>(packet),
+    PacketTypeId::SearchGame => simple::<SearchGame>(packet),
+    PacketTypeId::GameInfo => simple::<GameInfo>(packet),
+    PacketTypeId::CreateGame => simple::<CreateGame>(packet),
+    PacketTypeId::RefreshGame => simple::<RefreshGame>(packet),
+    PacketTypeId::DecreateGame => simple::<DecreateGame>(packet),
+    PacketTypeId::ChatFromOthers => simple::<ChatFromOthers>(packet),
+    PacketTypeId::PingFromOthers => simple::<PingFromOthers>(packet),
+    PacketTypeId::PongToOthers => simple::<PongToOthers>(packet),
+    PacketTypeId::ClientInfo => simple::<ClientInfo>(packet),
+    PacketTypeId::PeerSet => simple::<PeerSet>(packet),
+    PacketTypeId::MapCheck => simple::<MapCheck>(packet),
+    PacketTypeId::StartDownload => simple::<StartDownload>(packet),
+    PacketTypeId::MapSize => simple::<MapSize>(packet),
+    PacketTypeId::MapPart => payload::<MapPart>(packet),
+    PacketTypeId::MapPartOK => simple::<MapPartOK>(packet),
+    PacketTypeId::MapPartError => simple::<MapPartError>(packet),
+    PacketTypeId::PongToHost => simple::<PongToHost>(packet),
+    PacketTypeId::IncomingAction2 => payload::<IncomingAction2>(packet),
+    PacketTypeId::ProtoBuf => simple::<ProtoBufPayload>(packet),
+    _ => Ok(None),
+  }
+}
+
+#[test]
+fn test_trace() {
+  let packets = vec![
+    (
+      Direction::Outgoing,
+      1,
+      Packet::simple(PingFromHost::with_payload(7)).unwrap(),
+    ),
+    (Direction::Incoming, 2, Packet::simple(LeaveAck).unwrap()),
+    (
+      Direction::Outgoing,
+      2,
+      Packet::simple(PlayerLoaded { player_id: 3 }).unwrap(),
+    ),
+  ];
+
+  let mut writer = TraceWriter::new(vec![]).unwrap();
+  for (direction, player_id, packet) in &packets {
+    writer.write(*direction, *player_id, packet).unwrap();
+  }
+  let data = writer.inner;
+
+  let records: Vec<_> = TraceReader::new(&data[..])
+    .unwrap()
+    .collect::<Result<_>>()
+    .unwrap();
+  assert_eq!(records.len(), packets.len());
+  for (record, (direction, player_id, packet)) in records.iter().zip(&packets) {
+    assert_eq!(record.direction, *direction);
+    assert_eq!(record.player_id, *player_id);
+    assert_eq!(record.packet.type_id(), packet.type_id());
+    assert_eq!(record.packet.payload, packet.payload);
+    assert!(decode_payload(&record.packet).unwrap().is_some());
+  }
+
+  // truncated in the middle of a record
+  let mut reader = TraceReader::new(&data[..data.len() - 1]).unwrap();
+  assert!(reader.next_record().unwrap().is_some());
+  assert!(reader.next_record().unwrap().is_some());
+  assert!(reader.next_record().is_err());
+
+  assert!(TraceReader::new(&b"W3GS\x01"[..]).is_err());
+}
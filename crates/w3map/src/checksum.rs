@@ -46,7 +46,7 @@ impl MapChecksum {
       let mut xoro = XoroHasher::new();
 
       let files: &[&[&str]] = &[
-        &["war3map.j", "scripts\\war3map.j", "war3map.lua"],
+        crate::SCRIPT_PATHS,
         &["war3map.w3e"],
         &["war3map.wpm"],
         &["war3map.doo"],
@@ -13,5 +13,21 @@ bitflags! {
     const CUSTOM_UPGRADES = 0x0200;
     const WATER_WAVES_ON_CLIFF_SHORES = 0x0800;
     const WATER_WAVES_ON_SLOPE_SHORES = 0x1000;
+    const TERRAIN_FOG = 0x2000;
+    const REQUIRES_EXPANSION = 0x4000;
+    const ITEM_CLASSIFICATION = 0x8000;
+    const WATER_TINTING = 0x10000;
+    const ACCURATE_RANDOM = 0x20000;
+    const CUSTOM_ABILITY_SKINS = 0x40000;
+  }
+}
+
+bitflags! {
+  pub struct ForceFlags: u32 {
+    const ALLIED = 0x01;
+    const ALLIED_VICTORY = 0x02;
+    const SHARE_VISION = 0x04;
+    const SHARE_UNIT_CONTROL = 0x10;
+    const SHARE_ADVANCED_UNIT_CONTROL = 0x20;
   }
 }
@@ -5,6 +5,8 @@ use thiserror::Error;
 pub enum Error {
   #[error("map script not found")]
   MapScriptNotFound,
+  #[error("archive header not found")]
+  ArchiveHeaderNotFound,
   #[error("storage file not found: {0}")]
   StorageFileNotFound(String),
   #[cfg(feature = "w3storage")]
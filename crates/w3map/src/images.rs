@@ -0,0 +1,94 @@
+use flo_blp::BLPImage;
+use flo_util::binary::BinDecode;
+use image::{ImageBuffer, ImageFormat, Rgba};
+
+use crate::error::Result;
+use crate::{Archive, W3Map};
+
+pub type RgbaImage = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+const PREVIEW_PATH: &str = "war3mapPreview.tga";
+const MINIMAP_TGA_PATH: &str = "war3mapMap.tga";
+
+/// The images of a map shown on map pages.
+/// An image is `None` if the map doesn't have it or it can't be decoded.
+#[derive(Debug, Default)]
+pub struct MapImages {
+  /// The custom loading screen, if it is an image file rather than a model
+  pub loading_screen: Option<RgbaImage>,
+  /// The preview shown in the game lobby
+  pub preview: Option<RgbaImage>,
+  pub minimap: Option<RgbaImage>,
+  /// The minimap with the gold mine, neutral building and start location icons
+  pub minimap_with_icons: Option<RgbaImage>,
+}
+
+impl W3Map {
+  pub(crate) fn load_images(&self, archive: &mut Archive) -> Result<MapImages> {
+    let loading_screen = match self
+      .info
+      .ls_path
+      .as_ref()
+      .and_then(|path| self.trigger_strings.get(path))
+    {
+      Some(path) if !path.is_empty() => read_image(archive, &path)?,
+      _ => None,
+    };
+    let preview = read_image(archive, PREVIEW_PATH)?;
+    let minimap = match self.image {
+      Some(ref image) => Some(image.buffer().clone()),
+      None => read_image(archive, MINIMAP_TGA_PATH)?,
+    };
+    let minimap_with_icons = minimap.clone().map(|mut image| {
+      for icon in self.minimap_icons.iter() {
+        icon.draw_into(&mut image);
+      }
+      image
+    });
+    Ok(MapImages {
+      loading_screen,
+      preview,
+      minimap,
+      minimap_with_icons,
+    })
+  }
+}
+
+/// Returns `None` if the file doesn't exist or isn't a BLP or TGA image
+fn read_image(archive: &mut Archive, path: &str) -> Result<Option<RgbaImage>> {
+  let ext = match path.rsplit_once('.') {
+    Some((_, ext)) => ext.to_ascii_lowercase(),
+    None => return Ok(None),
+  };
+  if ext != "blp" && ext != "tga" {
+    return Ok(None);
+  }
+  let bytes = match archive.read_file_all_opt(path)? {
+    Some(bytes) => bytes,
+    None => return Ok(None),
+  };
+  let image = if ext == "blp" {
+    BLPImage::decode(&mut bytes.as_slice())
+      .ok()
+      .map(|image| image.buffer().clone())
+  } else {
+    image::load_from_memory_with_format(&bytes, ImageFormat::Tga)
+      .ok()
+      .map(|image| image.into_rgba8())
+  };
+  Ok(image)
+}
+
+#[test]
+fn test_open_with_images() {
+  let (_map, images) =
+    W3Map::open_with_images(flo_util::sample_path!("map", "(2)ConcealedHill.w3x")).unwrap();
+  assert!(images.minimap.is_some());
+  assert_eq!(
+    images.minimap.as_ref().map(|image| image.dimensions()),
+    images
+      .minimap_with_icons
+      .as_ref()
+      .map(|image| image.dimensions())
+  );
+}
@@ -39,6 +39,7 @@ pub struct MapInfo {
   pub ls_text: TriggerStringRef,
   pub ls_title: TriggerStringRef,
   pub ls_sub_title: TriggerStringRef,
+  /// Game data set, 0 for the default one of the game version
   pub data_set: u32,
   #[bin(condition = "version >= MapFormatVersion::TFT")]
   pub ps_path: Option<TriggerStringRef>,
@@ -49,10 +50,12 @@ pub struct MapInfo {
   pub env: Option<GameEnv>,
   #[bin(condition = "version >= MapFormatVersion::TFT131")]
   pub code_format: Option<u32>,
+  /// Bit 0 for SD, bit 1 for HD
   #[bin(condition = "version >= MapFormatVersion::Reforged")]
-  pub _unknown_reforged_1: Option<u32>,
+  pub supported_modes: Option<u32>,
+  /// 0 for Reign of Chaos, 1 for The Frozen Throne
   #[bin(condition = "version >= MapFormatVersion::Reforged")]
-  pub _unknown_reforged_2: Option<u32>,
+  pub game_data_version: Option<u32>,
   pub num_players: u32,
   #[bin(condition = "version < MapFormatVersion::Reforged")]
   #[bin(repeat = "num_players")]
@@ -116,13 +119,15 @@ pub struct ReforgedPlayer {
   pub start_pos_y: f32,
   pub ally_prio_low: u32,
   pub ally_prio_high: u32,
-  pub _unknown_1: u32,
-  pub _unknown_2: u32,
+  pub enemy_prio_low: u32,
+  pub enemy_prio_high: u32,
 }
 
 #[derive(Debug, Clone, BinDecode)]
 pub struct Force {
+  /// See `ForceFlags`
   pub flags: u32,
+  /// Bit `n` is set if the player with id `n` is in the force
   pub player_set: u32,
   pub name: TriggerStringRef,
 }
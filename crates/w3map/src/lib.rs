@@ -9,14 +9,20 @@ pub mod error;
 
 mod checksum;
 mod constants;
+mod images;
 mod info;
 mod minimap;
+mod protected;
+mod script_analysis;
 mod trigger_string;
 
 pub use self::checksum::MapChecksum;
 pub use self::constants::*;
+pub use self::images::*;
 pub use self::info::*;
 pub use self::minimap::*;
+pub use self::protected::*;
+pub use self::script_analysis::*;
 pub use self::trigger_string::*;
 
 pub use flo_blp::BLPImage;
@@ -25,6 +31,9 @@ use flo_w3storage::W3Storage;
 
 use self::error::{Error, Result};
 
+/// Archive paths of the map script, the first one found is used
+pub(crate) const SCRIPT_PATHS: &[&str] = &["war3map.j", "scripts\\war3map.j", "war3map.lua"];
+
 #[derive(Debug)]
 pub struct W3Map {
   suggested_players: String,
@@ -37,18 +46,33 @@ pub struct W3Map {
 
 impl W3Map {
   pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-    Self::load_info(Self::open_archive_file(path)?)
+    Self::load_info(&mut Self::open_archive_file(path)?)
   }
 
   pub fn open_with_checksum<P: AsRef<Path>>(path: P) -> Result<(Self, MapChecksum)> {
     let mut archive = Self::open_archive_file(path)?;
     let checksum = MapChecksum::compute(&mut archive)?;
-    let map = Self::load_info(archive)?;
+    let map = Self::load_info(&mut archive)?;
     Ok((map, checksum))
   }
 
   pub fn open_memory(bytes: &[u8]) -> Result<Self> {
-    Self::load_info(Self::open_archive_memory(bytes)?)
+    Self::load_info(&mut Self::open_archive_memory(bytes)?)
+  }
+
+  /// Also decodes the loading screen, preview and minimap images
+  pub fn open_with_images<P: AsRef<Path>>(path: P) -> Result<(Self, MapImages)> {
+    let mut archive = Self::open_archive_file(path)?;
+    let map = Self::load_info(&mut archive)?;
+    let images = map.load_images(&mut archive)?;
+    Ok((map, images))
+  }
+
+  pub fn open_memory_with_images(bytes: &[u8]) -> Result<(Self, MapImages)> {
+    let mut archive = Self::open_archive_memory(bytes)?;
+    let map = Self::load_info(&mut archive)?;
+    let images = map.load_images(&mut archive)?;
+    Ok((map, images))
   }
 
   #[cfg(feature = "w3storage")]
@@ -77,7 +101,7 @@ impl W3Map {
       Data::Bytes(ref bytes) => Self::open_archive_memory(bytes),
     }?;
     let checksum = MapChecksum::compute(&mut archive)?;
-    let map = Self::load_info(archive)?;
+    let map = Self::load_info(&mut archive)?;
     Ok((map, checksum))
   }
 
@@ -95,6 +119,33 @@ impl W3Map {
     Ok(checksum)
   }
 
+  /// Returns the archive path of the script and its content
+  pub fn read_script<P: AsRef<Path>>(path: P) -> Result<(&'static str, Vec<u8>)> {
+    Self::read_archive_script(&mut Self::open_archive_file(path)?)
+  }
+
+  #[cfg(feature = "w3storage")]
+  pub fn read_storage_script(storage: &W3Storage, path: &str) -> Result<(&'static str, Vec<u8>)> {
+    use flo_w3storage::Data;
+    let file = storage
+      .resolve_file(path)?
+      .ok_or_else(|| Error::StorageFileNotFound(path.to_string()))?;
+    let mut archive = match *file.data() {
+      Data::Path(ref path) => Self::open_archive_file(path),
+      Data::Bytes(ref bytes) => Self::open_archive_memory(bytes),
+    }?;
+    Self::read_archive_script(&mut archive)
+  }
+
+  fn read_archive_script(archive: &mut Archive) -> Result<(&'static str, Vec<u8>)> {
+    for script_path in SCRIPT_PATHS {
+      if let Some(bytes) = archive.read_file_all_opt(script_path)? {
+        return Ok((*script_path, bytes));
+      }
+    }
+    Err(Error::MapScriptNotFound)
+  }
+
   pub fn render_preview_jpeg(&self) -> Vec<u8> {
     let mut bg = if let Some(ref image) = self.image {
       image.buffer().clone()
@@ -173,6 +224,7 @@ impl W3Map {
         players
           .iter()
           .map(|p| MapPlayer {
+            id: p.id,
             name: self.trigger_strings.get(&p.name).unwrap_or_default(),
             r#type: p.type_,
             race: p.race,
@@ -185,6 +237,7 @@ impl W3Map {
           players
             .iter()
             .map(|p| MapPlayer {
+              id: p.id,
               name: self.trigger_strings.get(&p.name).unwrap_or_default(),
               r#type: p.type_,
               race: p.race,
@@ -216,6 +269,43 @@ impl W3Map {
   pub fn flags(&self) -> MapFlags {
     MapFlags::from_bits_truncate(self.info.flags)
   }
+
+  pub fn format_version(&self) -> MapFormatVersion {
+    self.info.version
+  }
+
+  pub fn is_melee(&self) -> bool {
+    self.flags().contains(MapFlags::MELEE)
+  }
+
+  /// Players can't leave the force the map puts them in
+  pub fn has_fixed_teams(&self) -> bool {
+    self
+      .flags()
+      .contains(MapFlags::CUSTOM_FORCES | MapFlags::FIXED_PLAYER_SETTINGS)
+  }
+
+  pub fn game_data_set(&self) -> u32 {
+    self.info.data_set
+  }
+
+  /// Only set in Reforged maps, 0 for Reign of Chaos, 1 for The Frozen Throne
+  pub fn game_data_version(&self) -> Option<u32> {
+    self.info.game_data_version
+  }
+
+  /// Index of the force of the player with this id
+  pub fn get_player_force(&self, player_id: u32) -> Option<usize> {
+    self
+      .info
+      .forces
+      .iter()
+      .position(|force| force_contains(force.player_set, player_id))
+  }
+}
+
+fn force_contains(player_set: u32, player_id: u32) -> bool {
+  player_id < 32 && player_set & (1 << player_id) != 0
 }
 
 pub(crate) fn open_archive<P: AsRef<Path>>(path: P) -> Result<stormlib::Archive> {
@@ -239,24 +329,38 @@ impl W3Map {
   fn open_archive_memory(bytes: &[u8]) -> Result<Archive> {
     Ok(Archive::Memory(MemoryArchive {
       bytes,
-      inner: ceres_mpq::Archive::open(Cursor::new(bytes))?,
+      inner: ceres_mpq::Archive::open(Cursor::new(Cow::Borrowed(bytes)))?,
     }))
   }
 
-  fn load_info(mut archive: Archive) -> Result<Self> {
-    let trigger_strings = match archive.read_file_all_opt("war3map.wts") {
-      Ok(Some(bytes)) => {
-        TriggerStringMap::decode(&mut bytes.as_slice()).map_err(Error::ReadTriggerStrings)?
-      }
-      Ok(None) => TriggerStringMap::empty(),
-      Err(err) => {
-        if Archive::is_err_file_not_found(&err) {
-          TriggerStringMap::empty()
-        } else {
-          return Err(err);
-        }
+  fn load_info(archive: &mut Archive) -> Result<Self> {
+    Self::load_info_with(archive, false)
+  }
+
+  /// Skips the optional files that can't be decoded instead of failing
+  fn load_info_lenient(archive: &mut Archive) -> Result<Self> {
+    Self::load_info_with(archive, true)
+  }
+
+  fn load_info_with(archive: &mut Archive, lenient: bool) -> Result<Self> {
+    fn optional<T>(lenient: bool, value: Result<Option<T>>) -> Result<Option<T>> {
+      match value {
+        Err(_) if lenient => Ok(None),
+        value => value,
       }
-    };
+    }
+
+    let trigger_strings = optional(
+      lenient,
+      archive.read_file_all_opt("war3map.wts").and_then(|bytes| {
+        bytes
+          .map(|bytes| {
+            TriggerStringMap::decode(&mut bytes.as_slice()).map_err(Error::ReadTriggerStrings)
+          })
+          .transpose()
+      }),
+    )?
+    .unwrap_or_else(TriggerStringMap::empty);
 
     let info: MapInfo = {
       let bytes = archive
@@ -265,6 +369,27 @@ impl W3Map {
       BinDecode::decode(&mut bytes.as_slice()).map_err(Error::ReadInfo)?
     };
 
+    let image = optional(
+      lenient,
+      archive
+        .read_file_all_opt("war3mapMap.blp")
+        .and_then(|bytes| {
+          bytes
+            .map(|bytes| BLPImage::decode(&mut bytes.as_slice()).map_err(Error::ReadImage))
+            .transpose()
+        }),
+    )?;
+
+    let minimap_icons = optional(
+      lenient,
+      archive.read_file_all_opt("war3map.mmp").and_then(|bytes| {
+        bytes
+          .map(|bytes| BinDecode::decode(&mut bytes.as_slice()).map_err(Error::ReadMinimapIcons))
+          .transpose()
+      }),
+    )?
+    .unwrap_or_default();
+
     Ok(W3Map {
       suggested_players: trigger_strings
         .get(&info.suggested_players)
@@ -272,20 +397,8 @@ impl W3Map {
         .unwrap_or_else(|| "".to_string()),
       file_size: archive.get_size()?,
       info,
-      image: {
-        archive
-          .read_file_all_opt("war3mapMap.blp")?
-          .map(|bytes| BLPImage::decode(&mut bytes.as_slice()).map_err(Error::ReadImage))
-          .transpose()?
-      },
-      minimap_icons: {
-        let bytes = archive.read_file_all_opt("war3map.mmp")?;
-        if let Some(bytes) = bytes {
-          BinDecode::decode(&mut bytes.as_slice()).map_err(Error::ReadMinimapIcons)?
-        } else {
-          Default::default()
-        }
-      },
+      image,
+      minimap_icons,
       trigger_strings,
     })
   }
@@ -298,7 +411,8 @@ struct FileArchive {
 
 struct MemoryArchive<'a> {
   bytes: &'a [u8],
-  inner: ceres_mpq::Archive<Cursor<&'a [u8]>>,
+  /// Reads a repaired copy of `bytes` if the file table of the map is damaged
+  inner: ceres_mpq::Archive<Cursor<Cow<'a, [u8]>>>,
 }
 
 pub(crate) enum Archive<'a> {
@@ -346,6 +460,7 @@ impl<'a> Archive<'a> {
 
 #[derive(Debug)]
 pub struct MapPlayer<'a> {
+  pub id: u32,
   pub name: Cow<'a, str>,
   pub r#type: u32,
   pub race: u32,
@@ -359,6 +474,16 @@ pub struct MapForce<'a> {
   pub player_set: u32,
 }
 
+impl<'a> MapForce<'a> {
+  pub fn force_flags(&self) -> ForceFlags {
+    ForceFlags::from_bits_truncate(self.flags)
+  }
+
+  pub fn contains_player(&self, player_id: u32) -> bool {
+    force_contains(self.player_set, player_id)
+  }
+}
+
 #[test]
 fn test_open_map() {
   for name in &[
@@ -419,6 +544,21 @@ fn test_open_storage_with_checksum() {
   )
 }
 
+#[test]
+fn test_map_forces() {
+  let map = W3Map::open(flo_util::sample_path!("map", "(2)ConcealedHill.w3x")).unwrap();
+  assert!(map.is_melee());
+  assert!(!map.has_fixed_teams());
+  let forces = map.get_forces();
+  for player in map.get_players() {
+    assert_eq!(map.get_player_force(player.id), Some(0));
+    assert!(forces[0].contains_player(player.id));
+  }
+  assert!(!force_contains(0b10, 0));
+  assert!(force_contains(0b10, 1));
+  assert!(!force_contains(u32::MAX, 32));
+}
+
 #[test]
 fn test_open_map_special() {
   let map = W3Map::open(flo_util::sample_path!(
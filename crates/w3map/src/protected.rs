@@ -0,0 +1,750 @@
+//! Protected maps have their archive or map info damaged on purpose to keep editors from
+//! opening them. The game only loads files by name, so such a map can still be hosted as
+//! long as its checksum can be computed, with the metadata of the map file header.
+//!
+//! A damaged file table is rebuilt from its valid entries, and the map files it has no
+//! name for are guessed from the content of the other files. Guessed object data files
+//! would change the checksum, so only files with a signature are guessed.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use flo_util::binary::BinDecode;
+use lazy_static::lazy_static;
+
+use crate::error::{Error, Result};
+use crate::{Archive, MapChecksum, MapFlags, MapFormatVersion, MapInfo, MemoryArchive, W3Map};
+
+const HEADER_MAGIC: &[u8; 4] = b"HM3W";
+const HEADER_LEN: usize = 512;
+const MPQ_MAGIC: &[u8; 4] = b"MPQ\x1a";
+
+/// The `HM3W` header in front of the archive, read by the game to list LAN games
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapHeader {
+  pub name: String,
+  pub flags: MapFlags,
+  pub num_players: u32,
+}
+
+impl MapHeader {
+  pub fn decode(bytes: &[u8]) -> Option<Self> {
+    if bytes.len() < 8 || &bytes[..4] != HEADER_MAGIC {
+      return None;
+    }
+    let bytes = &bytes[8..bytes.len().min(HEADER_LEN)];
+    let name_len = bytes.iter().position(|b| *b == 0)?;
+    let name = String::from_utf8_lossy(&bytes[..name_len]).into_owned();
+    let rest = &bytes[(name_len + 1)..];
+    if rest.len() < 8 {
+      return None;
+    }
+    let flags = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+    let num_players = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]);
+    Some(Self {
+      name,
+      flags: MapFlags::from_bits_truncate(flags),
+      num_players,
+    })
+  }
+}
+
+/// A map whose metadata can't be read, with what could be recovered
+#[derive(Debug)]
+pub struct MapProtected {
+  /// `None` if the header is missing too
+  pub header: Option<MapHeader>,
+  /// Why the map info couldn't be read
+  pub error: Error,
+}
+
+impl MapProtected {
+  pub fn name(&self) -> &str {
+    self.header.as_ref().map(|h| h.name.as_str()).unwrap_or("")
+  }
+
+  pub fn num_players(&self) -> usize {
+    self.header.as_ref().map(|h| h.num_players).unwrap_or(0) as usize
+  }
+}
+
+#[derive(Debug)]
+pub enum OpenedMap {
+  Full(W3Map),
+  Protected(MapProtected),
+}
+
+impl W3Map {
+  /// Like `open_with_checksum`, but returns the recovered metadata of protected maps.
+  /// Fails only if the archive can't be opened or the checksum can't be computed.
+  pub fn open_with_checksum_recover<P: AsRef<Path>>(path: P) -> Result<(OpenedMap, MapChecksum)> {
+    let header = {
+      let mut bytes = Vec::with_capacity(HEADER_LEN);
+      std::fs::File::open(path.as_ref())?
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut bytes)?;
+      MapHeader::decode(&bytes)
+    };
+    let opened =
+      Self::open_archive_file(path.as_ref()).and_then(|archive| Self::recover(archive, header));
+    match opened {
+      Ok((OpenedMap::Full(map), checksum)) => Ok((OpenedMap::Full(map), checksum)),
+      // stormlib can't repair the file table
+      opened => {
+        let bytes = std::fs::read(path)?;
+        match Self::open_memory_with_checksum_recover(&bytes) {
+          Ok(recovered @ (OpenedMap::Full(_), _)) => Ok(recovered),
+          Ok(recovered) => opened.or(Ok(recovered)),
+          Err(_) => opened,
+        }
+      }
+    }
+  }
+
+  pub fn open_memory_with_checksum_recover(bytes: &[u8]) -> Result<(OpenedMap, MapChecksum)> {
+    let archive = open_archive_memory_scan(bytes)?;
+    Self::recover(archive, MapHeader::decode(bytes))
+  }
+
+  #[cfg(feature = "w3storage")]
+  pub fn open_storage_with_checksum_recover(
+    storage: &flo_w3storage::W3Storage,
+    path: &str,
+  ) -> Result<(OpenedMap, MapChecksum)> {
+    use flo_w3storage::Data;
+    let file = storage
+      .resolve_file(path)?
+      .ok_or_else(|| Error::StorageFileNotFound(path.to_string()))?;
+    match *file.data() {
+      Data::Path(ref path) => Self::open_with_checksum_recover(path),
+      Data::Bytes(ref bytes) => Self::open_memory_with_checksum_recover(bytes),
+    }
+  }
+
+  fn recover(mut archive: Archive, header: Option<MapHeader>) -> Result<(OpenedMap, MapChecksum)> {
+    let checksum = MapChecksum::compute(&mut archive)?;
+    let map = match Self::load_info(&mut archive) {
+      Ok(map) => OpenedMap::Full(map),
+      // the trigger strings, minimap and icons are not needed by the game
+      Err(_) => match Self::load_info_lenient(&mut archive) {
+        Ok(map) => OpenedMap::Full(map),
+        Err(error) => OpenedMap::Protected(MapProtected { header, error }),
+      },
+    };
+    Ok((map, checksum))
+  }
+}
+
+/// Opens the first archive that has the map files, protectors put fake archive headers in
+/// front of the real one. Headers are aligned to 512 bytes.
+/// If no archive can be read as is, the file table is rebuilt from its valid entries.
+fn open_archive_memory_scan(bytes: &[u8]) -> Result<Archive> {
+  let offsets: Vec<usize> = (0..bytes.len())
+    .step_by(HEADER_LEN)
+    .filter(|offset| bytes[*offset..].starts_with(MPQ_MAGIC))
+    .collect();
+  let mut first_err = None;
+  let mut incomplete = None;
+  for offset in offsets.iter().cloned() {
+    match ceres_mpq::Archive::open(Cursor::new(Cow::Borrowed(&bytes[offset..]))) {
+      Ok(inner) => {
+        let mut archive = Archive::Memory(MemoryArchive { bytes, inner });
+        if has_map_files(&mut archive) {
+          return Ok(archive);
+        }
+        incomplete.get_or_insert(archive);
+      }
+      Err(err) => {
+        first_err.get_or_insert(err);
+      }
+    }
+  }
+
+  for offset in offsets.iter().cloned() {
+    let repaired = if let Some(repaired) = repair_archive(&bytes[offset..]) {
+      repaired
+    } else {
+      continue;
+    };
+    if let Ok(inner) = ceres_mpq::Archive::open(Cursor::new(Cow::Owned(repaired))) {
+      let mut archive = Archive::Memory(MemoryArchive { bytes, inner });
+      if has_map_files(&mut archive) {
+        return Ok(archive);
+      }
+    }
+  }
+
+  if let Some(archive) = incomplete {
+    return Ok(archive);
+  }
+  Err(match first_err {
+    Some(err) => err.into(),
+    None => Error::ArchiveHeaderNotFound,
+  })
+}
+
+/// The map info and the script, the game can't load a map without them
+fn has_map_files(archive: &mut Archive) -> bool {
+  fn has_file(archive: &mut Archive, path: &str) -> bool {
+    matches!(archive.read_file_all_opt(path), Ok(Some(_)))
+  }
+  has_file(archive, "war3map.w3i")
+    && crate::SCRIPT_PATHS
+      .iter()
+      .any(|path| has_file(archive, path))
+}
+
+const MPQ_HEADER_V0_LEN: usize = 32;
+const MPQ_TABLE_ENTRY_WORDS: usize = 4;
+/// Larger tables are fake, maps have far fewer files
+const MAX_TABLE_ENTRIES: usize = 1 << 20;
+const BLOCK_EXISTS: u32 = 0x80000000;
+const BLOCK_ENCRYPTED: u32 = 0x00010000;
+const BLOCK_FIX_KEY: u32 = 0x00020000;
+const HASH_ENTRY_EMPTY: u32 = 0xFFFFFFFF;
+const HASH_ENTRY_DELETED: u32 = 0xFFFFFFFE;
+/// Files read to guess the names of the missing map files
+const MAX_GUESS_CANDIDATES: usize = 1024;
+/// Map files other than the script guessed from their content
+const GUESSED_FILES: &[&str] = &[
+  "war3map.w3i",
+  "war3map.wts",
+  "war3map.w3e",
+  "war3map.wpm",
+  "war3map.doo",
+];
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// Returns a copy of the archive with a file table rebuilt from the valid entries of the
+/// damaged one, and the missing map files named after their content.
+/// File data is left in place so encrypted files can still be decrypted.
+fn repair_archive(archive: &[u8]) -> Option<Vec<u8>> {
+  let mut tables = FileTables::read(archive)?;
+  let missing = tables.missing_files();
+  if missing.is_empty() {
+    return tables.write(archive);
+  }
+
+  // the key of an encrypted file is derived from its name without the directory,
+  // so they are read once under each missing name
+  let mut probe = tables.clone();
+  let mut probe_names: Vec<(String, Option<&'static str>, u32)> = vec![];
+  for index in tables
+    .guess_candidates()
+    .into_iter()
+    .take(MAX_GUESS_CANDIDATES)
+  {
+    if tables.blocks[index as usize].flags & BLOCK_ENCRYPTED == 0 {
+      probe_names.push((format!("(recovered)\\{}", index), None, index));
+    } else {
+      for name in &missing {
+        probe_names.push((
+          format!("(recovered)\\{}\\{}", index, name),
+          Some(*name),
+          index,
+        ));
+      }
+    }
+  }
+  probe_names.retain(|(name, _, index)| probe.insert(name, *index));
+  let mut probe = ceres_mpq::Archive::open(Cursor::new(probe.write(archive)?)).ok()?;
+
+  let mut guessed: Vec<(&'static str, u32)> = vec![];
+  for (name, expected, index) in probe_names {
+    // ceres_mpq trusts the sector offsets, garbage ones decrypted with a wrong name overflow
+    if !tables.has_valid_sectors(archive, index, expected.unwrap_or("")) {
+      continue;
+    }
+    let bytes = if let Ok(bytes) = probe.read_file(&name) {
+      bytes
+    } else {
+      continue;
+    };
+    let guess = match guess_file_name(&bytes) {
+      Some(guess) if expected.map(|name| name == guess).unwrap_or(true) => guess,
+      _ => continue,
+    };
+    if missing.contains(&guess) && !guessed.iter().any(|(name, _)| *name == guess) {
+      guessed.push((guess, index));
+    }
+  }
+  for (name, index) in guessed {
+    tables.insert(name, index);
+  }
+  tables.write(archive)
+}
+
+/// Files read to load the map info and compute the checksum, with their signature
+fn guess_file_name(bytes: &[u8]) -> Option<&'static str> {
+  let text = if bytes.starts_with(UTF8_BOM) {
+    &bytes[UTF8_BOM.len()..]
+  } else {
+    bytes
+  };
+  let contains = |needle: &[u8]| text.windows(needle.len()).any(|w| w == needle);
+  let name = if bytes.starts_with(b"W3E!") {
+    "war3map.w3e"
+  } else if bytes.starts_with(b"MP3W") {
+    "war3map.wpm"
+  } else if bytes.starts_with(b"W3do") {
+    "war3map.doo"
+  } else if text.starts_with(b"STRING ") {
+    "war3map.wts"
+  } else if contains(b"function main takes nothing returns nothing") {
+    "war3map.j"
+  } else if contains(b"function main()") && contains(b"function config()") {
+    "war3map.lua"
+  } else {
+    match MapInfo::decode(&mut &bytes[..]) {
+      Ok(MapInfo {
+        version: MapFormatVersion::UnknownValue(_),
+        ..
+      })
+      | Err(_) => return None,
+      Ok(_) => "war3map.w3i",
+    }
+  };
+  Some(name)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HashEntry {
+  name_a: u32,
+  name_b: u32,
+  locale_platform: u32,
+  block_index: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BlockEntry {
+  offset: u32,
+  compressed_size: u32,
+  size: u32,
+  flags: u32,
+}
+
+#[derive(Debug, Clone)]
+struct FileTables {
+  sector_size_shift: u16,
+  hash: Vec<HashEntry>,
+  blocks: Vec<BlockEntry>,
+}
+
+impl FileTables {
+  /// Entries past the end of the archive are dropped, and so are the blocks outside of it
+  /// and the hash entries pointing to them. The hash table keeps its size so the files
+  /// are still found at their slots.
+  fn read(archive: &[u8]) -> Option<Self> {
+    if archive.len() < MPQ_HEADER_V0_LEN {
+      return None;
+    }
+    let u32_at = |pos: usize| {
+      u32::from_le_bytes([
+        archive[pos],
+        archive[pos + 1],
+        archive[pos + 2],
+        archive[pos + 3],
+      ])
+    };
+    let sector_size_shift = u16::from_le_bytes([archive[14], archive[15]]);
+    let hash_table_offset = u32_at(16) as usize;
+    let block_table_offset = u32_at(20) as usize;
+    let hash_table_len = u32_at(24) as usize;
+    let block_table_len = u32_at(28) as usize;
+    if hash_table_len == 0
+      || hash_table_len > MAX_TABLE_ENTRIES
+      || !hash_table_len.is_power_of_two()
+    {
+      return None;
+    }
+
+    let hash_words = read_table(
+      archive,
+      hash_table_offset,
+      hash_table_len,
+      hash_string("(hash table)", HASH_FILE_KEY),
+    );
+    let block_words = read_table(
+      archive,
+      block_table_offset,
+      block_table_len.min(MAX_TABLE_ENTRIES),
+      hash_string("(block table)", HASH_FILE_KEY),
+    );
+
+    let blocks: Vec<BlockEntry> = block_words
+      .chunks_exact(MPQ_TABLE_ENTRY_WORDS)
+      .map(|w| {
+        let block = BlockEntry {
+          offset: w[0],
+          compressed_size: w[1],
+          size: w[2],
+          flags: w[3],
+        };
+        let end = block.offset as u64 + block.compressed_size as u64;
+        if block.flags & BLOCK_EXISTS == 0 || end > archive.len() as u64 {
+          BlockEntry {
+            offset: 0,
+            compressed_size: 0,
+            size: 0,
+            flags: 0,
+          }
+        } else {
+          block
+        }
+      })
+      .collect();
+
+    let mut hash: Vec<HashEntry> = hash_words
+      .chunks_exact(MPQ_TABLE_ENTRY_WORDS)
+      .map(|w| HashEntry {
+        name_a: w[0],
+        name_b: w[1],
+        locale_platform: w[2],
+        block_index: w[3],
+      })
+      .collect();
+    // the part of the table past the end of the archive
+    hash.resize(
+      hash_table_len,
+      HashEntry {
+        name_a: HASH_ENTRY_EMPTY,
+        name_b: HASH_ENTRY_EMPTY,
+        locale_platform: HASH_ENTRY_EMPTY,
+        block_index: HASH_ENTRY_EMPTY,
+      },
+    );
+    for entry in &mut hash {
+      if entry.block_index == HASH_ENTRY_EMPTY || entry.block_index == HASH_ENTRY_DELETED {
+        continue;
+      }
+      let valid = blocks
+        .get(entry.block_index as usize)
+        .map(|block| block.flags & BLOCK_EXISTS != 0)
+        .unwrap_or(false);
+      // ceres_mpq doesn't skip deleted entries, the names are cleared so they never match
+      if !valid {
+        *entry = HashEntry {
+          name_a: HASH_ENTRY_EMPTY,
+          name_b: HASH_ENTRY_EMPTY,
+          locale_platform: HASH_ENTRY_EMPTY,
+          block_index: HASH_ENTRY_DELETED,
+        };
+      }
+    }
+
+    if !blocks.iter().any(|block| block.flags & BLOCK_EXISTS != 0) {
+      return None;
+    }
+
+    Some(Self {
+      sector_size_shift,
+      hash,
+      blocks,
+    })
+  }
+
+  fn find(&self, name: &str) -> bool {
+    self.lookup(name).is_some()
+  }
+
+  /// Returns the block index of the file
+  fn lookup(&self, name: &str) -> Option<u32> {
+    let (name_a, name_b) = (
+      hash_string(name, HASH_NAME_A),
+      hash_string(name, HASH_NAME_B),
+    );
+    let mask = self.hash.len() - 1;
+    let start = hash_string(name, HASH_TABLE_OFFSET) as usize & mask;
+    for i in 0..self.hash.len() {
+      let entry = &self.hash[(start + i) & mask];
+      if entry.block_index == HASH_ENTRY_EMPTY {
+        break;
+      }
+      if entry.block_index != HASH_ENTRY_DELETED && entry.name_a == name_a && entry.name_b == name_b
+      {
+        return Some(entry.block_index);
+      }
+    }
+    None
+  }
+
+  /// Adds a name for a block, an empty slot is kept to end the lookups of missing files
+  fn insert(&mut self, name: &str, block_index: u32) -> bool {
+    let empty = self
+      .hash
+      .iter()
+      .filter(|entry| entry.block_index == HASH_ENTRY_EMPTY)
+      .count();
+    let mask = self.hash.len() - 1;
+    let start = hash_string(name, HASH_TABLE_OFFSET) as usize & mask;
+    for i in 0..self.hash.len() {
+      let entry = &mut self.hash[(start + i) & mask];
+      let usable = match entry.block_index {
+        HASH_ENTRY_DELETED => true,
+        HASH_ENTRY_EMPTY => empty > 1,
+        _ => false,
+      };
+      if usable {
+        *entry = HashEntry {
+          name_a: hash_string(name, HASH_NAME_A),
+          name_b: hash_string(name, HASH_NAME_B),
+          locale_platform: 0,
+          block_index,
+        };
+        return true;
+      }
+    }
+    false
+  }
+
+  /// Map files the table has no name for
+  fn missing_files(&self) -> Vec<&'static str> {
+    let mut missing = vec![];
+    if !crate::SCRIPT_PATHS.iter().any(|path| self.find(path)) {
+      missing.extend_from_slice(&["war3map.j", "war3map.lua"]);
+    }
+    for path in GUESSED_FILES {
+      if !self.find(path) {
+        missing.push(*path);
+      }
+    }
+    missing
+  }
+
+  /// Existing blocks other than the ones of the map files found by name.
+  /// Protectors also rename the map files, so blocks with a name are candidates too.
+  fn guess_candidates(&self) -> Vec<u32> {
+    let found: Vec<u32> = crate::SCRIPT_PATHS
+      .iter()
+      .chain(GUESSED_FILES)
+      .filter_map(|path| self.lookup(path))
+      .collect();
+    self
+      .blocks
+      .iter()
+      .enumerate()
+      .filter(|(index, block)| block.flags & BLOCK_EXISTS != 0 && !found.contains(&(*index as u32)))
+      .map(|(index, _)| index as u32)
+      .collect()
+  }
+
+  /// Checks the sector offsets of a file, decrypted with the key of `name` if it is encrypted
+  fn has_valid_sectors(&self, archive: &[u8], block_index: u32, name: &str) -> bool {
+    let block = &self.blocks[block_index as usize];
+    let sector_size = 512_u64 << self.sector_size_shift.min(16);
+    let sectors = ((block.size as u64 + sector_size - 1) / sector_size) as usize;
+    let mut words = read_words(&archive[block.offset as usize..], sectors + 1);
+    if words.len() != sectors + 1 {
+      return false;
+    }
+    if block.flags & BLOCK_ENCRYPTED != 0 {
+      let mut key = hash_string(name, HASH_FILE_KEY);
+      if block.flags & BLOCK_FIX_KEY != 0 {
+        key = key.wrapping_add(block.offset) ^ block.size;
+      }
+      decrypt(&mut words, key.wrapping_sub(1));
+    }
+    words[0] as usize == words.len() * 4
+      && words.windows(2).all(|w| w[0] <= w[1])
+      && words[sectors] <= block.compressed_size
+  }
+
+  /// Copies the archive with the tables appended and a version 0 header pointing to them
+  fn write(&self, archive: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = archive.to_vec();
+    let hash_table_offset = bytes.len();
+    let hash_words: Vec<u32> = self
+      .hash
+      .iter()
+      .flat_map(|e| vec![e.name_a, e.name_b, e.locale_platform, e.block_index])
+      .collect();
+    write_table(
+      &mut bytes,
+      hash_words,
+      hash_string("(hash table)", HASH_FILE_KEY),
+    );
+    let block_table_offset = bytes.len();
+    let block_words: Vec<u32> = self
+      .blocks
+      .iter()
+      .flat_map(|e| vec![e.offset, e.compressed_size, e.size, e.flags])
+      .collect();
+    write_table(
+      &mut bytes,
+      block_words,
+      hash_string("(block table)", HASH_FILE_KEY),
+    );
+
+    let words = [
+      MPQ_HEADER_V0_LEN as u32,
+      u32::try_from(bytes.len()).ok()?,
+      // format version 0, sector size shift
+      (self.sector_size_shift as u32) << 16,
+      u32::try_from(hash_table_offset).ok()?,
+      u32::try_from(block_table_offset).ok()?,
+      self.hash.len() as u32,
+      self.blocks.len() as u32,
+    ];
+    bytes[..4].copy_from_slice(MPQ_MAGIC);
+    for (i, word) in words.iter().enumerate() {
+      bytes[(4 + i * 4)..(8 + i * 4)].copy_from_slice(&word.to_le_bytes());
+    }
+    Some(bytes)
+  }
+}
+
+/// Reads the entries that are inside of the archive
+fn read_table(archive: &[u8], offset: usize, len: usize, key: u32) -> Vec<u32> {
+  let available = archive.len().saturating_sub(offset) / (MPQ_TABLE_ENTRY_WORDS * 4);
+  let mut words = read_words(
+    &archive[offset.min(archive.len())..],
+    len.min(available) * MPQ_TABLE_ENTRY_WORDS,
+  );
+  decrypt(&mut words, key);
+  words
+}
+
+fn read_words(bytes: &[u8], len: usize) -> Vec<u32> {
+  bytes
+    .chunks_exact(4)
+    .take(len)
+    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    .collect()
+}
+
+fn write_table(bytes: &mut Vec<u8>, mut words: Vec<u32>, key: u32) {
+  encrypt(&mut words, key);
+  for word in words {
+    bytes.extend_from_slice(&word.to_le_bytes());
+  }
+}
+
+const HASH_TABLE_OFFSET: u32 = 0;
+const HASH_NAME_A: u32 = 1;
+const HASH_NAME_B: u32 = 2;
+const HASH_FILE_KEY: u32 = 3;
+
+lazy_static! {
+  static ref CRYPT_TABLE: [u32; 0x500] = {
+    let mut table = [0; 0x500];
+    let mut seed: u32 = 0x00100001;
+    for i in 0..0x100 {
+      for j in 0..5 {
+        seed = (seed * 125 + 3) % 0x2AAAAB;
+        let high = (seed & 0xFFFF) << 0x10;
+        seed = (seed * 125 + 3) % 0x2AAAAB;
+        let low = seed & 0xFFFF;
+        table[i + j * 0x100] = high | low;
+      }
+    }
+    table
+  };
+}
+
+/// Case-insensitive, `/` and `\` are the same separator
+fn hash_string(value: &str, hash_type: u32) -> u32 {
+  let mut seed1: u32 = 0x7FED7FED;
+  let mut seed2: u32 = 0xEEEEEEEE;
+  for b in value.bytes() {
+    let b = match b {
+      b'/' => b'\\',
+      b => b.to_ascii_uppercase(),
+    } as u32;
+    seed1 = CRYPT_TABLE[(hash_type * 0x100 + b) as usize] ^ seed1.wrapping_add(seed2);
+    seed2 = b
+      .wrapping_add(seed1)
+      .wrapping_add(seed2)
+      .wrapping_add(seed2 << 5)
+      .wrapping_add(3);
+  }
+  seed1
+}
+
+fn decrypt(words: &mut [u32], mut key: u32) {
+  let mut seed: u32 = 0xEEEEEEEE;
+  for word in words {
+    seed = seed.wrapping_add(CRYPT_TABLE[0x400 + (key & 0xFF) as usize]);
+    let value = *word ^ key.wrapping_add(seed);
+    key = ((!key << 0x15).wrapping_add(0x11111111)) | (key >> 0x0B);
+    seed = value
+      .wrapping_add(seed)
+      .wrapping_add(seed << 5)
+      .wrapping_add(3);
+    *word = value;
+  }
+}
+
+fn encrypt(words: &mut [u32], mut key: u32) {
+  let mut seed: u32 = 0xEEEEEEEE;
+  for word in words {
+    seed = seed.wrapping_add(CRYPT_TABLE[0x400 + (key & 0xFF) as usize]);
+    let value = *word;
+    *word = value ^ key.wrapping_add(seed);
+    key = ((!key << 0x15).wrapping_add(0x11111111)) | (key >> 0x0B);
+    seed = value
+      .wrapping_add(seed)
+      .wrapping_add(seed << 5)
+      .wrapping_add(3);
+  }
+}
+
+#[test]
+fn test_decode_map_header() {
+  let mut bytes = vec![0; HEADER_LEN];
+  bytes[..4].copy_from_slice(HEADER_MAGIC);
+  let name = b"(2)Protected\0";
+  bytes[8..(8 + name.len())].copy_from_slice(name);
+  let rest = 8 + name.len();
+  bytes[rest..(rest + 4)].copy_from_slice(&MapFlags::MELEE.bits().to_le_bytes());
+  bytes[(rest + 4)..(rest + 8)].copy_from_slice(&2_u32.to_le_bytes());
+  assert_eq!(
+    MapHeader::decode(&bytes),
+    Some(MapHeader {
+      name: "(2)Protected".to_string(),
+      flags: MapFlags::MELEE,
+      num_players: 2,
+    })
+  );
+
+  assert_eq!(MapHeader::decode(&bytes[..20]), None);
+  assert_eq!(MapHeader::decode(b"MPQ\x1a\0\0\0\0"), None);
+}
+
+#[test]
+fn test_open_memory_with_checksum_recover() {
+  let bytes = std::fs::read(flo_util::sample_path!("map", "(2)ConcealedHill.w3x")).unwrap();
+  let (map, _) = W3Map::open_memory_with_checksum_recover(&bytes).unwrap();
+  assert!(matches!(map, OpenedMap::Full(_)));
+  assert!(W3Map::open_memory_with_checksum_recover(&bytes[..HEADER_LEN]).is_err());
+}
+
+#[test]
+fn test_repair_archive() {
+  let bytes = std::fs::read(flo_util::sample_path!("map", "(2)ConcealedHill.w3x")).unwrap();
+  let archive = &bytes[HEADER_LEN..];
+  let mut tables = FileTables::read(archive).unwrap();
+  assert!(tables.missing_files().is_empty());
+
+  // protectors remove the names of the map files
+  let (name_a, name_b) = (
+    hash_string("war3map.w3i", HASH_NAME_A),
+    hash_string("war3map.w3i", HASH_NAME_B),
+  );
+  for entry in &mut tables.hash {
+    if entry.name_a == name_a && entry.name_b == name_b {
+      entry.name_a = 0;
+      entry.name_b = 0;
+    }
+  }
+  let mut damaged = bytes[..HEADER_LEN].to_vec();
+  damaged.extend(tables.write(archive).unwrap());
+  // fake block count
+  damaged[(HEADER_LEN + 28)..(HEADER_LEN + 32)].copy_from_slice(&0x7FFFFFFF_u32.to_le_bytes());
+
+  let repaired = repair_archive(&damaged[HEADER_LEN..]).unwrap();
+  assert!(FileTables::read(&repaired).unwrap().find("war3map.w3i"));
+  let (map, _) = W3Map::open_memory_with_checksum_recover(&damaged).unwrap();
+  assert!(matches!(map, OpenedMap::Full(_)));
+}
@@ -0,0 +1,218 @@
+//! Checks the map script for cheat packs and natives that are not allowed in ranked games.
+//!
+//! Cheat packs are injected into the script of popular maps and unlock commands for the
+//! player who knows the activation phrase. The report lists what was found, it is up to
+//! the lobby to warn the host or to refuse the map.
+
+use std::path::Path;
+
+use crate::error::Result;
+use crate::W3Map;
+#[cfg(feature = "w3storage")]
+use flo_w3storage::W3Storage;
+
+/// Case insensitive strings left by known cheat packs, in code or in string literals
+const CHEAT_PACK_SIGNATURES: &[&str] = &[
+  "cheat pack",
+  "cheatpack",
+  "cheats enabled",
+  "cheats activated",
+  "fai cheat",
+  "darkness cheat",
+];
+
+/// Natives that turn on cheat codes
+const BANNED_NATIVES: &[&str] = &["Cheat"];
+
+/// Natives that run and write files on the disk of the players.
+/// Save/load maps use them to keep the codes of the players, so they are only warnings.
+const FILE_NATIVES: &[&str] = &["Preloader", "PreloadGenEnd"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptLanguage {
+  Jass,
+  Lua,
+}
+
+impl ScriptLanguage {
+  pub fn from_path(path: &str) -> Self {
+    if path.to_ascii_lowercase().ends_with(".lua") {
+      ScriptLanguage::Lua
+    } else {
+      ScriptLanguage::Jass
+    }
+  }
+
+  fn line_comment(&self) -> &'static str {
+    match *self {
+      ScriptLanguage::Jass => "//",
+      ScriptLanguage::Lua => "--",
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptFindingKind {
+  CheatPack { signature: &'static str },
+  BannedNative { name: &'static str },
+  FileNative { name: &'static str },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptFinding {
+  pub kind: ScriptFindingKind,
+  /// 1-based
+  pub line: usize,
+}
+
+impl ScriptFinding {
+  pub fn is_warning(&self) -> bool {
+    matches!(self.kind, ScriptFindingKind::FileNative { .. })
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct ScriptReport {
+  pub findings: Vec<ScriptFinding>,
+}
+
+impl ScriptReport {
+  /// Warnings don't count
+  pub fn is_clean(&self) -> bool {
+    self.findings.iter().all(|f| f.is_warning())
+  }
+
+  pub fn has_cheat_pack(&self) -> bool {
+    self
+      .findings
+      .iter()
+      .any(|f| matches!(f.kind, ScriptFindingKind::CheatPack { .. }))
+  }
+}
+
+/// Reports each signature and native once, at the first line it appears
+pub fn analyze_script(source: &str, language: ScriptLanguage) -> ScriptReport {
+  let mut report = ScriptReport::default();
+  let mut found_signatures = vec![false; CHEAT_PACK_SIGNATURES.len()];
+  let mut found_natives = vec![false; BANNED_NATIVES.len() + FILE_NATIVES.len()];
+
+  for (idx, line) in source.lines().enumerate() {
+    let lowercase = line.to_ascii_lowercase();
+    for (signature, found) in CHEAT_PACK_SIGNATURES
+      .iter()
+      .zip(found_signatures.iter_mut())
+    {
+      if !*found && lowercase.contains(signature) {
+        *found = true;
+        report.findings.push(ScriptFinding {
+          kind: ScriptFindingKind::CheatPack {
+            signature: *signature,
+          },
+          line: idx + 1,
+        });
+      }
+    }
+
+    let code = match line.find(language.line_comment()) {
+      Some(pos) => &line[..pos],
+      None => line,
+    };
+    for (name, found) in BANNED_NATIVES
+      .iter()
+      .chain(FILE_NATIVES)
+      .zip(found_natives.iter_mut())
+    {
+      if !*found && calls(code, name) {
+        *found = true;
+        let kind = if BANNED_NATIVES.contains(name) {
+          ScriptFindingKind::BannedNative { name: *name }
+        } else {
+          ScriptFindingKind::FileNative { name: *name }
+        };
+        report.findings.push(ScriptFinding {
+          kind,
+          line: idx + 1,
+        });
+      }
+    }
+  }
+
+  report
+}
+
+/// `name` as a whole identifier followed by an argument list
+fn calls(code: &str, name: &str) -> bool {
+  let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+  code.match_indices(name).any(|(pos, _)| {
+    let before = code[..pos].chars().next_back();
+    let after = code[(pos + name.len())..].trim_start();
+    !matches!(before, Some(c) if is_ident(c)) && after.starts_with('(')
+  })
+}
+
+impl W3Map {
+  /// Reads the script of the map file and analyzes it
+  pub fn analyze_script<P: AsRef<Path>>(path: P) -> Result<ScriptReport> {
+    let (script_path, bytes) = Self::read_script(path)?;
+    Ok(analyze_script(
+      &String::from_utf8_lossy(&bytes),
+      ScriptLanguage::from_path(script_path),
+    ))
+  }
+
+  /// Reads the script of a map in the game storage and analyzes it
+  #[cfg(feature = "w3storage")]
+  pub fn analyze_storage_script(storage: &W3Storage, path: &str) -> Result<ScriptReport> {
+    let (script_path, bytes) = Self::read_storage_script(storage, path)?;
+    Ok(analyze_script(
+      &String::from_utf8_lossy(&bytes),
+      ScriptLanguage::from_path(script_path),
+    ))
+  }
+}
+
+#[test]
+fn test_analyze_script() {
+  let script = r#"
+function main takes nothing returns nothing
+  // call Cheat("warpten") in a comment
+  call DisplayTextToPlayer(p, 0, 0, "Cheat Pack loaded")
+  call Cheat ("iseedeadpeople")
+  call MyCheat("x")
+  call Cheat("greedisgood")
+endfunction
+"#;
+  let report = analyze_script(script, ScriptLanguage::Jass);
+  assert_eq!(
+    report.findings,
+    vec![
+      ScriptFinding {
+        kind: ScriptFindingKind::CheatPack {
+          signature: "cheat pack"
+        },
+        line: 4,
+      },
+      ScriptFinding {
+        kind: ScriptFindingKind::BannedNative { name: "Cheat" },
+        line: 5,
+      },
+    ]
+  );
+  assert!(report.has_cheat_pack());
+
+  let report = analyze_script("-- Cheat(\"x\")\nprint(1)", ScriptLanguage::Lua);
+  assert!(report.is_clean());
+
+  // save/load codes
+  let report = analyze_script(
+    "call PreloadGenClear()\ncall PreloadGenEnd(\"save\\\\code.pld\")\ncall Preloader(\"save\\\\code.pld\")",
+    ScriptLanguage::Jass,
+  );
+  assert_eq!(report.findings.len(), 2);
+  assert!(report.findings.iter().all(|f| f.is_warning()));
+  assert!(report.is_clean());
+  assert_eq!(
+    ScriptLanguage::from_path("war3map.lua"),
+    ScriptLanguage::Lua
+  );
+}
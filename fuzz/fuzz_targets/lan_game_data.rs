@@ -0,0 +1,9 @@
+#![no_main]
+use flo_lan::GameData;
+use flo_util::binary::BinDecode;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  let mut buf = data;
+  GameData::decode(&mut buf).ok();
+});
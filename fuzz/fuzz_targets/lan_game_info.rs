@@ -0,0 +1,7 @@
+#![no_main]
+use flo_lan::GameInfo;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  GameInfo::decode_bytes(data).ok();
+});
@@ -0,0 +1,47 @@
+#![no_main]
+use flo_util::binary::*;
+use flo_w3gs::action::{IncomingAction, OutgoingAction};
+use flo_w3gs::chat::ChatToHost;
+use flo_w3gs::constants::PacketTypeId;
+use flo_w3gs::join::ReqJoin;
+use flo_w3gs::net::W3GSCodec;
+use flo_w3gs::packet::{Packet, ProtoBufPayload};
+use flo_w3gs::slot::SlotInfo;
+use libfuzzer_sys::fuzz_target;
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+  let mut codec = W3GSCodec::new();
+  let mut buf = BytesMut::from(data);
+  while let Ok(Some(packet)) = codec.decode(&mut buf) {
+    decode_payload(&packet);
+  }
+});
+
+fn decode_payload(packet: &Packet) {
+  match packet.type_id() {
+    PacketTypeId::ReqJoin => {
+      packet.decode_simple::<ReqJoin>().ok();
+    }
+    PacketTypeId::SlotInfo => {
+      packet.decode_simple::<SlotInfo>().ok();
+    }
+    PacketTypeId::ChatToHost => {
+      packet.decode_simple::<ChatToHost>().ok();
+    }
+    PacketTypeId::OutgoingAction => {
+      packet.decode_payload::<OutgoingAction>().ok();
+    }
+    PacketTypeId::IncomingAction => {
+      if let Ok(action) = packet.decode_payload::<IncomingAction>() {
+        for player_action in &action.0.actions {
+          for _ in player_action.actions() {}
+        }
+      }
+    }
+    PacketTypeId::ProtoBuf => {
+      packet.decode_simple::<ProtoBufPayload>().ok();
+    }
+    _ => {}
+  }
+}
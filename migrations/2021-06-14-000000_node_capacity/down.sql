@@ -0,0 +1,3 @@
+alter table node
+    drop column region,
+    drop column max_games;
\ No newline at end of file
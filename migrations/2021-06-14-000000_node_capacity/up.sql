@@ -0,0 +1,3 @@
+alter table node
+    add column region text default '' not null,
+    add column max_games integer;
\ No newline at end of file
@@ -0,0 +1 @@
+drop table audit_event;
\ No newline at end of file
@@ -0,0 +1,12 @@
+create table audit_event (
+    id bigserial not null primary key,
+    kind integer not null,
+    actor_kind integer not null,
+    actor_id integer,
+    game_id integer,
+    payload jsonb not null default '{}',
+    created_at timestamp with time zone default now() not null
+);
+
+create index audit_event_game_id on audit_event(game_id);
+create index audit_event_actor on audit_event(actor_kind, actor_id);
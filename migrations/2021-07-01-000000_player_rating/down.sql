@@ -0,0 +1,2 @@
+alter table player
+    drop column rating;
\ No newline at end of file
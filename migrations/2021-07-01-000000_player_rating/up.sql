@@ -0,0 +1,2 @@
+alter table player
+    add column rating integer;
\ No newline at end of file
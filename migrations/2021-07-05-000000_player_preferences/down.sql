@@ -0,0 +1 @@
+drop table player_preferences;
\ No newline at end of file
@@ -0,0 +1,7 @@
+create table player_preferences (
+    player_id integer not null primary key references player(id),
+    race integer,
+    color integer,
+    node_region text,
+    updated_at timestamp with time zone default now() not null
+);
@@ -0,0 +1 @@
+drop table game_template;
\ No newline at end of file
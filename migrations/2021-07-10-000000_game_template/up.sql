@@ -0,0 +1,11 @@
+create table game_template (
+    id serial not null primary key,
+    player_id integer not null references player(id),
+    name text not null,
+    is_public boolean default false not null,
+    config jsonb not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
+
+create index game_template_player_id on game_template(player_id);
@@ -0,0 +1,14 @@
+create table game_schedule (
+    id serial not null primary key,
+    template_id integer not null references game_template(id) on delete cascade,
+    node_id integer not null references node(id),
+    -- utc
+    start_time time not null,
+    open_minutes integer default 30 not null,
+    min_players integer default 2 not null,
+    -- the game created for the last occurrence
+    game_id integer references game(id),
+    game_start_at timestamp with time zone,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
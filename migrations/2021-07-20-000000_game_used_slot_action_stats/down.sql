@@ -0,0 +1,2 @@
+alter table game_used_slot
+    drop column action_stats;
\ No newline at end of file
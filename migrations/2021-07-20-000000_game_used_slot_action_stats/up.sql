@@ -0,0 +1,2 @@
+alter table game_used_slot
+    add column action_stats jsonb;
\ No newline at end of file
@@ -0,0 +1,6 @@
+drop index node_region;
+drop index game_used_slot_game_id_occupied;
+drop index game_name_trgm;
+drop index game_map_name_trgm;
+drop index game_public_map_name;
+drop index game_public_created_at;
@@ -0,0 +1,8 @@
+create extension if not exists pg_trgm;
+
+create index game_public_created_at on game(created_at, id) where is_private = false;
+create index game_public_map_name on game(map_name, id) where is_private = false;
+create index game_map_name_trgm on game using gin (map_name gin_trgm_ops);
+create index game_name_trgm on game using gin (name gin_trgm_ops);
+create index game_used_slot_game_id_occupied on game_used_slot(game_id) where player_id is not null;
+create index node_region on node(region);
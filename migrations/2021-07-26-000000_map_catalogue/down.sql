@@ -0,0 +1 @@
+drop table map_catalogue;
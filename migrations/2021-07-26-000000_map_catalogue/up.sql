@@ -0,0 +1,18 @@
+create extension if not exists pg_trgm;
+
+create table map_catalogue (
+    id serial not null primary key,
+    sha1 text not null unique,
+    name text not null,
+    author text not null,
+    description text not null,
+    path text not null,
+    map jsonb not null,
+    hosted_count integer default 1 not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
+
+create index map_catalogue_name_trgm on map_catalogue using gin (name gin_trgm_ops);
+create index map_catalogue_author_trgm on map_catalogue using gin (author gin_trgm_ops);
+create index map_catalogue_description_trgm on map_catalogue using gin (description gin_trgm_ops);
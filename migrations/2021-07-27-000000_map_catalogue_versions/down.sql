@@ -0,0 +1,5 @@
+drop index map_catalogue_name_author;
+
+alter table map_catalogue
+    drop column previous_version_id,
+    drop column latest_version_id;
@@ -0,0 +1,5 @@
+alter table map_catalogue
+    add column previous_version_id integer references map_catalogue(id),
+    add column latest_version_id integer references map_catalogue(id);
+
+create index map_catalogue_name_author on map_catalogue(name, author);
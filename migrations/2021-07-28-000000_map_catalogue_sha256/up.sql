@@ -0,0 +1,2 @@
+alter table map_catalogue
+    add column sha256 text unique;
@@ -0,0 +1,2 @@
+alter table game
+    drop column traffic_stats;
\ No newline at end of file
@@ -0,0 +1,2 @@
+alter table game
+    add column traffic_stats jsonb;
\ No newline at end of file
@@ -0,0 +1,24 @@
+create table webhook (
+    id serial not null primary key,
+    url text not null,
+    -- HMAC-SHA256 key of the `x-flo-signature` header
+    secret text not null,
+    enabled boolean default true not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
+
+create table webhook_delivery (
+    id bigserial not null primary key,
+    webhook_id integer not null references webhook(id) on delete cascade,
+    event text not null,
+    payload jsonb not null,
+    attempts integer default 0 not null,
+    -- of the last attempt
+    status_code integer,
+    error text,
+    delivered_at timestamp with time zone,
+    created_at timestamp with time zone default now() not null
+);
+
+create index webhook_delivery_webhook_id on webhook_delivery (webhook_id, id desc);
@@ -0,0 +1 @@
+drop table game_create_request;
@@ -0,0 +1,8 @@
+-- request ids sent by api clients with create game requests, retries return the same game
+create table game_create_request (
+    api_client_id integer not null references api_client(id) on delete cascade,
+    request_id text not null,
+    game_id integer not null references game(id) on delete cascade,
+    created_at timestamp with time zone default now() not null,
+    primary key (api_client_id, request_id)
+);
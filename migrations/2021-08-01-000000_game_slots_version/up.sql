@@ -0,0 +1,3 @@
+-- bumped by every slot layout change, updates compare-and-swap against it
+-- starts at 1, clients send 0 to skip the check
+alter table game add column slots_version integer default 1 not null;
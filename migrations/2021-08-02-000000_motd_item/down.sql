@@ -0,0 +1 @@
+drop table motd_item;
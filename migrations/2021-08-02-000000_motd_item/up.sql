@@ -0,0 +1,10 @@
+create table motd_item (
+    id serial not null primary key,
+    -- 0 = announcement, 1 = patch notes, 2 = event
+    kind integer default 0 not null,
+    title text not null,
+    -- markdown-ish source, parsed into segments when sent to clients
+    body text not null,
+    created_at timestamp with time zone default now() not null,
+    updated_at timestamp with time zone default now() not null
+);
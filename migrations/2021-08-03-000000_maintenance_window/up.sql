@@ -0,0 +1,9 @@
+-- at most one row, the currently scheduled window
+create table maintenance_window (
+    id integer not null primary key default 1 check (id = 1),
+    starts_at timestamp with time zone not null,
+    ends_at timestamp with time zone not null,
+    message text not null,
+    drain_nodes boolean not null,
+    created_at timestamp with time zone default now() not null
+);
@@ -0,0 +1,6 @@
+drop index player_name_folded;
+drop index game_name_folded;
+alter table player
+    drop column name_folded;
+alter table game
+    drop column name_folded;
@@ -0,0 +1,9 @@
+-- `flo_util::name::fold` of the name, compared when names have to be unique,
+-- rows created before are set when they are updated
+alter table game
+    add column name_folded text;
+alter table player
+    add column name_folded text;
+
+create index game_name_folded on game (name_folded);
+create unique index player_name_folded on player (api_client_id, source, coalesce(realm, ''), name_folded);
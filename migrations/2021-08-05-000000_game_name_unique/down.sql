@@ -0,0 +1,2 @@
+drop index game_name_folded_active;
+create index game_name_folded on game (name_folded);
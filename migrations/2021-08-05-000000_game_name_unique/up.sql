@@ -0,0 +1,3 @@
+drop index game_name_folded;
+-- active games, see `GameStatus::active_variants`
+create unique index game_name_folded_active on game (name_folded) where status in (0, 1, 2, 4);
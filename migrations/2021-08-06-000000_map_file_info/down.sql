@@ -0,0 +1,2 @@
+alter table map_catalogue
+    drop column file_info;
@@ -0,0 +1,3 @@
+-- `MapFileInfo` registered with the file, trusted over `map` sent by the game clients
+alter table map_catalogue
+    add column file_info jsonb;
@@ -0,0 +1,2 @@
+alter table game_schedule
+    drop column host_player_id;
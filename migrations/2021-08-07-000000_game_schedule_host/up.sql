@@ -0,0 +1,3 @@
+-- hosts the scheduled games, the template owner if null
+alter table game_schedule
+    add column host_player_id integer references player(id) on delete set null;
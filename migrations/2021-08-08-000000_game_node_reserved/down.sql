@@ -0,0 +1,2 @@
+alter table game
+    drop column node_reserved_at;
@@ -0,0 +1,3 @@
+-- set while the game is being created on its node, counted against the node capacity
+alter table game
+    add column node_reserved_at timestamp with time zone;